@@ -4,53 +4,45 @@
 rayon = "1.10.0"
 strsim = "0.11.1"
 ---
-use std::{io::Write, process::Command, sync::atomic::AtomicU64};
+//! Differential fuzzer. Compiles rustlantis-style programs both natively and with the backend, and compares their output.
+//!
+//! Usage:
+//! - `fuzz.rs GENERATOR START [END]` generates and tests the cases `START..END`, in `/tmp/fuzz`.
+//! - `fuzz.rs reduce FILE [OUT]` shrinks a mismatching case(by default into `FILE.min.rs`), and then bisects the
+//!   optimization fuel, to check if the mismatch is caused by the optimizer.
+//! - `fuzz.rs bisect FILE` only bisects the optimization fuel.
+use std::{io::Write, ops::Range, process::Command, sync::atomic::AtomicU64};
 static LINES: AtomicU64 = AtomicU64::new(0);
-fn run_test(test_id: u64, is_release: bool) -> Option<f64> {
-    match std::panic::catch_unwind(|| run_test_impl(test_id, is_release)) {
+/// The result of comparing the native and .NET builds of a test case.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    /// Both builds printed the same thing.
+    Same,
+    /// The outputs differ, by `similarity`. If `backend_failed` is set, the backend could not build the program.
+    Differs { similarity: f64, backend_failed: bool },
+    /// The native build did not compile or run, so the case can't be compared.
+    Invalid,
+}
+fn run_test(stem: &str, is_release: bool, fuel: Option<u32>, verbose: bool) -> Outcome {
+    match std::panic::catch_unwind(|| run_test_impl(stem, is_release, fuel, verbose)) {
         Ok(inner) => inner,
-        Err(_) => Some(1.0),
+        Err(_) => Outcome::Differs {
+            similarity: 1.0,
+            backend_failed: false,
+        },
     }
 }
-fn run_test_impl(test_id: u64, is_release: bool) -> Option<f64> {
+/// Builds and runs `{stem}.rs`. If `fuel` is set, the backend only gets this much optimization fuel.
+fn run_test_impl(stem: &str, is_release: bool, fuel: Option<u32>, verbose: bool) -> Outcome {
     let rustc_opt_flag = if is_release { "-O" } else { "-g" };
     let test_dir = "/tmp/fuzz/";
-    let rust_src = format!("/tmp/fuzz/fuzz{test_id}.rs");
-    let dotnet_exe = format!("/tmp/fuzz/fuzz{test_id}.exe");
-    let dotnet_wrapper = format!("/tmp/fuzz/fuzz{test_id}");
-    let native_exec = format!("/tmp/fuzz/fuzz{test_id}.elf");
+    let rust_src = format!("{stem}.rs");
+    let dotnet_exe = format!("{stem}.exe");
+    let native_exec = format!("{stem}.elf");
     // Ensures the test directory is present
     std::fs::create_dir_all(test_dir).expect("Could not setup the test env");
-    // Builds the backend if neceasry
-    rustc_codegen_clr::compile_test::RUSTC_BUILD_STATUS
-        .as_ref()
-        .expect("Could not build rustc!");
-    // Compiles the test project
+    // Compiles the project with native rust first, since there is nothing to compare against if this fails.
     let mut cmd = std::process::Command::new("rustc");
-    //.env("RUST_TARGET_PATH","../../")
-    let rustc_args = rustc_codegen_clr::compile_test::rustc_args();
-    cmd.current_dir(test_dir)
-        .arg("-O")
-        .args(rustc_args.iter())
-        .args([&rust_src, "-o", &dotnet_exe]);
-
-    let out = cmd.output().expect("failed to execute process");
-    // If stderr is not empty, then something went wrong, so print the stdout and stderr for debuging.
-    if !out.stderr.is_empty() {
-        let stdout =
-            String::from_utf8(out.stdout).expect("rustc error contained non-UTF8 characters.");
-        let stderr =
-            String::from_utf8(out.stderr).expect("rustc error contained non-UTF8 characters.");
-        eprintln!("stdout:\n{stdout}\nstderr:\n{stderr}");
-    }
-
-    //super::peverify(exec_path, test_dir);
-
-    let dotnet_out =
-        rustc_codegen_clr::compile_test::test_dotnet_executable(&dotnet_wrapper, test_dir);
-    // Compiles the project with native rust
-    let mut cmd = std::process::Command::new("rustc");
-    //.env("RUST_TARGET_PATH","../../")
     cmd.current_dir(test_dir).args([
         rustc_opt_flag,
         &rust_src,
@@ -60,31 +52,68 @@ fn run_test_impl(test_id: u64, is_release: bool) -> Option<f64> {
         "2021",
     ]);
     let out = cmd.output().expect("failed to execute process");
-    // If stderr is not empty, then something went wrong, so print the stdout and stderr for debuging.
-    if !out.stderr.is_empty() {
-        let stdout =
-            String::from_utf8(out.stdout).expect("rustc error contained non-UTF8 characters.");
-        let stderr =
-            String::from_utf8(out.stderr).expect("rustc error contained non-UTF8 characters.");
-
-        eprintln!("stdout:\n{stdout}\nstderr:\n{stderr}");
+    if !out.status.success() {
+        if verbose {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            eprintln!("Native build failed:\n{stderr}");
+        }
+        return Outcome::Invalid;
     }
     let rust_out = std::process::Command::new(&native_exec)
         .current_dir(test_dir)
         .output()
         .expect("failed to execute process");
+    if !rust_out.status.success() {
+        return Outcome::Invalid;
+    }
     let rust_out =
         String::from_utf8(rust_out.stdout).expect("rust error contained non-UTF8 characters.");
-
+    // Builds the backend if neceasry
+    rustc_codegen_clr::compile_test::RUSTC_BUILD_STATUS
+        .as_ref()
+        .expect("Could not build rustc!");
+    // Compiles the test project
+    let mut cmd = std::process::Command::new("rustc");
+    //.env("RUST_TARGET_PATH","../../")
+    let rustc_args = rustc_codegen_clr::compile_test::rustc_args();
+    cmd.current_dir(test_dir)
+        .arg("-O")
+        .args(rustc_args.iter())
+        .args([&rust_src, "-o", &dotnet_exe]);
+    if let Some(fuel) = fuel {
+        // Read both by the codegen, and the linker.
+        cmd.env("OPT_FUEL", fuel.to_string());
+    }
+    let out = cmd.output().expect("failed to execute process");
+    if !out.status.success() {
+        if verbose {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            eprintln!("stdout:\n{stdout}\nstderr:\n{stderr}");
+        }
+        return Outcome::Differs {
+            similarity: 0.0,
+            backend_failed: true,
+        };
+    }
+    let dotnet_out = rustc_codegen_clr::compile_test::test_dotnet_executable(stem, test_dir);
     if rust_out == dotnet_out {
-        //std::fs::remove_file(rust_src).unwrap();
         std::fs::remove_file(dotnet_exe).unwrap();
         std::fs::remove_file(native_exec).unwrap();
-        //std::fs::remove_file(dotnet_wrapper).unwrap();
-        None
+        Outcome::Same
     } else {
-        Some(strsim::jaro(&rust_out, &dotnet_out))
+        Outcome::Differs {
+            similarity: strsim::jaro(&rust_out, &dotnet_out),
+            backend_failed: false,
+        }
+    }
+}
+/// Removes the files built from `{stem}.rs`, leaving only the source.
+fn cleanup(stem: &str) {
+    for ext in ["exe", "elf", "il", "runtimeconfig.json", "exe.mdb"] {
+        let _ = std::fs::remove_file(format!("{stem}.{ext}"));
     }
+    let _ = std::fs::remove_file(stem);
 }
 fn gen_file(test_id: u64, generator: &str) {
     let rust_src = format!("/tmp/fuzz/fuzz{test_id}.rs");
@@ -106,22 +135,272 @@ fn gen_file(test_id: u64, generator: &str) {
 }
 fn test(test_id: u64, generator: &str) -> Option<(u64, f64)> {
     gen_file(test_id, generator);
-    let res = run_test(test_id, false)
-        .or(run_test(test_id, true))
-        .map(|sim| (test_id, 1.0 - sim));
+    let stem = format!("/tmp/fuzz/fuzz{test_id}");
+    let res = [false, true]
+        .into_iter()
+        .find_map(|is_release| match run_test(&stem, is_release, None, true) {
+            Outcome::Differs { similarity, .. } => Some((test_id, 1.0 - similarity)),
+            Outcome::Same | Outcome::Invalid => None,
+        });
     if res.is_none() {
-        std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.rs")).unwrap();
+        std::fs::remove_file(format!("{stem}.rs")).unwrap();
     }
-    std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.il")).unwrap();
-    std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.runtimeconfig.json")).unwrap();
+    // The executables of failing cases are kept, for inspection.
+    let _ = std::fs::remove_file(format!("{stem}.il"));
+    let _ = std::fs::remove_file(format!("{stem}.runtimeconfig.json"));
     // Try removing the .mdb, if present.
-    let _ = std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.exe.mdb"));
+    let _ = std::fs::remove_file(format!("{stem}.exe.mdb"));
     res
 }
+/// A test case being reduced. Only mismatches of the same kind as the original one are considered interesting, so that
+/// the reducer does not turn a miscompilation into an unrelated build failure.
+struct Reducer {
+    stem: String,
+    is_release: bool,
+    backend_failed: bool,
+    tests: u32,
+}
+impl Reducer {
+    /// Checks if `src` still has the mismatch, when built with `fuel`.
+    fn interesting(&mut self, src: &str, fuel: Option<u32>) -> bool {
+        self.tests += 1;
+        std::fs::write(format!("{}.rs", self.stem), src).unwrap();
+        let outcome = run_test(&self.stem, self.is_release, fuel, false);
+        cleanup(&self.stem);
+        matches!(outcome, Outcome::Differs { backend_failed, .. } if backend_failed == self.backend_failed)
+    }
+    /// Removes as many of `units` from `lines` as it can, while keeping the case interesting. Works like `ddmin`: tries
+    /// removing big chunks of units first, and then halves the chunk size. Returns true if anything was removed.
+    fn reduce_units(&mut self, lines: &mut Vec<String>, units: &[Range<usize>]) -> bool {
+        let mut removed = vec![false; lines.len()];
+        let mut units: Vec<_> = units.to_vec();
+        let mut progress = false;
+        let mut chunk = units.len();
+        while chunk > 0 {
+            let mut start = 0;
+            while start < units.len() {
+                let end = (start + chunk).min(units.len());
+                let mut candidate = removed.clone();
+                units[start..end]
+                    .iter()
+                    .flat_map(Clone::clone)
+                    .for_each(|line| candidate[line] = true);
+                if self.interesting(&join_lines(lines, &candidate), None) {
+                    removed = candidate;
+                    units.drain(start..end);
+                    progress = true;
+                    eprintln!(
+                        "Removed {} units, {} lines remain. Run {} tests.",
+                        end - start,
+                        removed.iter().filter(|removed| !**removed).count(),
+                        self.tests
+                    );
+                } else {
+                    start = end;
+                }
+            }
+            chunk /= 2;
+        }
+        let mut removed = removed.into_iter();
+        lines.retain(|_| !removed.next().unwrap());
+        progress
+    }
+    /// Shrinks `src` by deleting functions and other items, then basic blocks, and then single statements, until no
+    /// more can be removed.
+    fn reduce(&mut self, src: &str) -> String {
+        let mut lines: Vec<String> = src.lines().map(str::to_owned).collect();
+        loop {
+            let mut progress = false;
+            for level in [Level::Items, Level::Blocks, Level::Statements] {
+                let units = units(&lines, level);
+                progress |= self.reduce_units(&mut lines, &units);
+            }
+            if !progress {
+                return join_lines(&lines, &vec![false; lines.len()]);
+            }
+        }
+    }
+    /// Finds the smallest amount of optimization fuel the mismatch happens with, the same way `optsect` does. If it
+    /// happens without any fuel, then the bug is in the codegen, and not in the optimizer.
+    fn bisect_fuel(&mut self, src: &str) {
+        if self.interesting(src, Some(0)) {
+            println!("The mismatch happens with OPT_FUEL=0, so it is not caused by the optimizer.");
+            return;
+        }
+        // Find any amount of fuel the mismatch happens with.
+        let mut good = 0;
+        let mut bad = 1;
+        while !self.interesting(src, Some(bad)) {
+            good = bad;
+            bad = match bad.checked_mul(4) {
+                Some(bad) if bad <= 1 << 26 => bad,
+                _ => {
+                    println!("Could not reproduce the mismatch with OPT_FUEL set, so it can't be bisected.");
+                    return;
+                }
+            };
+        }
+        while good < bad - 1 {
+            let mid = good + (bad - good) / 2;
+            eprintln!("Testing fuel range {good} {bad}, curr {mid}");
+            if self.interesting(src, Some(mid)) {
+                bad = mid;
+            } else {
+                good = mid;
+            }
+        }
+        println!(
+            "The mismatch first happens with OPT_FUEL={bad}, so it is most likely an optimizer bug. Compare the builds with OPT_FUEL={good} and OPT_FUEL={bad}."
+        );
+    }
+}
+/// Granularity of the units removed by the reducer.
+#[derive(Clone, Copy)]
+enum Level {
+    /// Top level items, like functions, along with their attributes.
+    Items,
+    /// Nested brace delimited regions, like basic blocks.
+    Blocks,
+    /// Single lines inside of a region.
+    Statements,
+}
+/// Returns the change in brace depth caused by `line`, ignoring braces in string and char literals, and in comments.
+fn brace_delta(line: &str) -> isize {
+    let chars: Vec<char> = line.chars().collect();
+    let mut delta = 0;
+    let mut in_str = false;
+    let mut idx = 0;
+    while idx < chars.len() {
+        match chars[idx] {
+            '\\' if in_str => idx += 1,
+            '"' => in_str = !in_str,
+            '/' if !in_str && chars.get(idx + 1) == Some(&'/') => break,
+            // Char literals, like `'{'` or `'\''`. Lifetimes are never followed by a closing quote.
+            '\'' if !in_str => match chars[idx + 1..] {
+                ['\\', _, '\'', ..] => idx += 3,
+                [_, '\'', ..] => idx += 2,
+                _ => (),
+            },
+            '{' if !in_str => delta += 1,
+            '}' if !in_str => delta -= 1,
+            _ => (),
+        }
+        idx += 1;
+    }
+    delta
+}
+/// Splits `lines` into units which can be removed by the reducer.
+fn units(lines: &[String], level: Level) -> Vec<Range<usize>> {
+    let mut units = Vec::new();
+    // Start lines and depths of the currently open regions.
+    let mut open: Vec<(usize, isize)> = Vec::new();
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        let delta = brace_delta(line);
+        if delta > 0 {
+            open.push((idx, depth));
+        }
+        // A stray closing brace must not shift the depth of everything after it.
+        depth = (depth + delta).max(0);
+        if delta < 0 {
+            while let Some(&(start, start_depth)) = open.last() {
+                if start_depth < depth {
+                    break;
+                }
+                open.pop();
+                let start = match (level, start_depth) {
+                    // Attributes(like `#[custom_mir(...)]`) must be removed together with their item.
+                    (Level::Items, 0) => {
+                        let attrs = lines[..start]
+                            .iter()
+                            .rev()
+                            .take_while(|line| line.trim_start().starts_with("#["))
+                            .count();
+                        start - attrs
+                    }
+                    (Level::Blocks, 1..) => start,
+                    _ => continue,
+                };
+                units.push(start..idx + 1);
+            }
+        }
+        if matches!(level, Level::Statements) && delta == 0 && depth > 0 && !line.trim().is_empty()
+        {
+            units.push(idx..idx + 1);
+        }
+    }
+    units
+}
+fn join_lines(lines: &[String], removed: &[bool]) -> String {
+    let mut src = String::new();
+    for (line, _) in lines.iter().zip(removed).filter(|(_, removed)| !**removed) {
+        src.push_str(line);
+        src.push('\n');
+    }
+    src
+}
+/// Reduces the test case at `path`, and bisects the optimization fuel. If `out` is set, the reduced case is saved there.
+fn reduce(path: &str, out: Option<&str>) {
+    std::fs::create_dir_all("/tmp/fuzz").unwrap();
+    let src = std::fs::read_to_string(path).expect("Could not read the test case");
+    let stem = format!("/tmp/fuzz/reduce{}", std::process::id());
+    // Find out how the case fails, so that the reducer can preserve that.
+    let Some((is_release, backend_failed)) = [false, true].into_iter().find_map(|is_release| {
+        std::fs::write(format!("{stem}.rs"), &src).unwrap();
+        let outcome = run_test(&stem, is_release, None, true);
+        cleanup(&stem);
+        match outcome {
+            Outcome::Differs { backend_failed, .. } => Some((is_release, backend_failed)),
+            Outcome::Same | Outcome::Invalid => None,
+        }
+    }) else {
+        println!("{path} has no mismatch, so there is nothing to reduce.");
+        return;
+    };
+    let mut reducer = Reducer {
+        stem: stem.clone(),
+        is_release,
+        backend_failed,
+        tests: 0,
+    };
+    let src = match out {
+        Some(out) => {
+            let reduced = reducer.reduce(&src);
+            std::fs::write(out, &reduced).expect("Could not save the reduced test case");
+            println!(
+                "Reduced {path} from {} to {} lines, in {} tests. Saved as {out}.",
+                src.lines().count(),
+                reduced.lines().count(),
+                reducer.tests
+            );
+            reduced
+        }
+        None => src,
+    };
+    if !backend_failed {
+        reducer.bisect_fuel(&src);
+    }
+    let _ = std::fs::remove_file(format!("{stem}.rs"));
+}
 fn main() {
     use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
     let generator = std::env::args().nth(1).unwrap();
+    match generator.as_str() {
+        "reduce" => {
+            let path = std::env::args().nth(2).expect("no test case path");
+            let out = std::env::args().nth(3).unwrap_or_else(|| {
+                let stem = path.strip_suffix(".rs").unwrap_or(&path);
+                format!("{stem}.min.rs")
+            });
+            return reduce(&path, Some(&out));
+        }
+        "bisect" => {
+            let path = std::env::args().nth(2).expect("no test case path");
+            return reduce(&path, None);
+        }
+        _ => (),
+    }
     let search_start = str::parse::<u64>(&std::env::args().nth(2).unwrap()).unwrap();
     let search_end = std::env::args()
         .nth(3)
@@ -140,3 +419,54 @@ fn main() {
         "Created {test_cases} test cases, totaling {LINES:?} LOC, found faliures:{faliures:?}"
     );
 }
+#[test]
+fn brace_delta_literals() {
+    assert_eq!(brace_delta("fn main() {"), 1);
+    assert_eq!(brace_delta("} else {"), 0);
+    assert_eq!(brace_delta("}}"), -2);
+    assert_eq!(brace_delta(r#"println!("{}", "}");"#), 0);
+    assert_eq!(brace_delta(r#"let s = "\"{";"#), 0);
+    assert_eq!(brace_delta("let c = '{';"), 0);
+    assert_eq!(brace_delta(r"let c = '\'';{"), 1);
+    assert_eq!(brace_delta(r#"let c = '"'; {"#), 1);
+    assert_eq!(brace_delta("fn f<'a>(x: &'a u8) {"), 1);
+    assert_eq!(brace_delta("} // {"), -1);
+}
+#[cfg(test)]
+fn lines(src: &str) -> Vec<String> {
+    src.lines().map(str::to_owned).collect()
+}
+#[test]
+fn units_nested() {
+    let src = lines(
+        r#"#[custom_mir(dialect = "runtime")]
+fn f(a: u8) -> u8 {
+    mir! {
+        {
+            RET = a;
+            Goto(bb1)
+        }
+        bb1 = {
+            Return()
+        }
+    }
+}
+fn main() {
+    println!("{}", "}");
+}"#,
+    );
+    assert_eq!(units(&src, Level::Items), [0..12, 12..15]);
+    assert_eq!(units(&src, Level::Blocks), [3..7, 7..10, 2..11]);
+    assert_eq!(units(&src, Level::Statements), [4..5, 5..6, 8..9, 13..14]);
+}
+#[test]
+fn units_unbalanced() {
+    // An unclosed region is never a unit.
+    let src = lines("fn f() {\n    a;\nfn g() {\n}");
+    assert_eq!(units(&src, Level::Items), []);
+    assert_eq!(units(&src, Level::Blocks), [2..4]);
+    // A stray closing brace does not hide the items after it.
+    let src = lines("}\nfn g() {\n    a;\n}\n}\nfn h() {\n}");
+    assert_eq!(units(&src, Level::Items), [1..4, 5..7]);
+    assert_eq!(units(&src, Level::Statements), [2..3]);
+}
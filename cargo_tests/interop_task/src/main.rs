@@ -0,0 +1,54 @@
+#![feature(unsized_const_params)]
+use mycorrhiza::system::threading::tasks::{GenericTask, Task, ValueTask};
+use mycorrhiza::task::{block_on, spawn, spawn_with_result, GenericTaskFuture, TaskFuture};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+/// Polls the inner future again right away, up to `polls` times, while it is pending.
+struct Spin<F> {
+    inner: F,
+    polls: u32,
+}
+impl<F: Future + Unpin> Future for Spin<F> {
+    type Output = F::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let res = Pin::new(&mut self.inner).poll(cx);
+        if res.is_pending() && self.polls > 0 {
+            self.polls -= 1;
+            cx.waker().wake_by_ref();
+        }
+        res
+    }
+}
+fn delay(ms: i32) -> Task {
+    Task::static1::<"Delay", i32, Task>(ms)
+}
+fn main() {
+    // A Rust future exposed as a `Task<int>`, whose result is read by `Task<T>.Result`.
+    let task: GenericTask<i32> = spawn_with_result(async { 6 * 7 });
+    assert_eq!(task.result(), 42);
+    // The same task awaited from Rust code.
+    let task = spawn_with_result(async { 1_u64 << 40 });
+    assert_eq!(block_on(GenericTaskFuture::from(task)), 1 << 40);
+    // A spawned future awaiting a task which is still running.
+    let task = spawn_with_result(async {
+        TaskFuture::from(delay(20)).await;
+        7_i32
+    });
+    assert_eq!(block_on(GenericTaskFuture::from(task)), 7);
+    // A task polled many times before it completes only registers a single continuation.
+    block_on(Spin {
+        inner: TaskFuture::from(delay(20)),
+        polls: 1000,
+    });
+    // A task with no result.
+    let task = spawn(async {
+        TaskFuture::from(delay(5)).await;
+    });
+    block_on(TaskFuture::from(task));
+    assert!(task.is_completed());
+    // A value task.
+    block_on(TaskFuture::from(ValueTask::from_task(delay(5))));
+}
@@ -0,0 +1,72 @@
+#![feature(macro_attr, unsized_const_params)]
+#![allow(non_snake_case, dead_code)]
+use mycorrhiza::{
+    dotnet_typedef,
+    intrinsics::{RustcCLRInteropManagedClass, RustcCLRInteropManagedStruct},
+    system::MString,
+};
+type RustObj = RustcCLRInteropManagedClass<"", "RustObj">;
+type RustObj2 = RustcCLRInteropManagedClass<"", "RustObj2">;
+type RustStruct = RustcCLRInteropManagedStruct<"", "RustStruct", 4>;
+fn rust_obj_ctor(this: RustObj) {}
+fn rust_obj_to_string(this: RustObj) -> MString {
+    "RustObj".into()
+}
+#[dotnet_typedef(inherits [System::Runtime] System::Object)]
+struct RustObj {
+    a: f32,
+    #[ctor(rust_obj_ctor)]
+    new: fn(RustObj),
+    #[fnimpl(virtual rust_obj_to_string)]
+    ToString: fn(RustObj) -> MString,
+}
+mod rust_obj2 {
+    use super::{MString, RustObj2};
+    pub fn ctor(this: RustObj2, a: f32) {}
+    pub fn to_string(this: RustObj2) -> MString {
+        "RustObj2".into()
+    }
+    pub fn dispose(this: RustObj2) {}
+    pub fn get_a(this: RustObj2) -> f32 {
+        2.5
+    }
+    pub fn create() -> i32 {
+        7
+    }
+}
+#[dotnet_typedef(inherits [System::Runtime] System::Object, implements [System::Runtime] System::IDisposable)]
+struct RustObj2 {
+    a: f32,
+    #[ctor(rust_obj2::ctor)]
+    new: fn(RustObj2, f32),
+    #[fnimpl(virtual rust_obj2::to_string)]
+    ToString: fn(RustObj2) -> MString,
+    #[fnimpl(virtual rust_obj2::dispose)]
+    Dispose: fn(RustObj2),
+    #[fnimpl(rust_obj2::get_a)]
+    GetA: fn(RustObj2) -> f32,
+    #[fnimpl(static rust_obj2::create)]
+    Create: fn() -> i32,
+}
+fn rust_struct_answer(this: RustStruct) -> i32 {
+    42
+}
+#[dotnet_typedef(valuetype)]
+struct RustStruct {
+    a: i32,
+    #[fnimpl(rust_struct_answer)]
+    Answer: fn(RustStruct) -> i32,
+}
+fn main() {
+    // Methods of a class defined in Rust forward to Rust functions.
+    let obj = RustObj2::ctor1(1.0_f32);
+    assert_eq!(obj.instance0::<"GetA", f32>(), 2.5);
+    assert_eq!(RustObj2::static0::<"Create", i32>(), 7);
+    let obj = RustObj::ctor0();
+    assert!(obj
+        .virt0::<"ToString", MString>()
+        .equality("RustObj".into()));
+    // So do methods of a valuetype, which get a copy of `this`.
+    let value: RustStruct = unsafe { core::mem::zeroed() };
+    assert_eq!(value.instance0::<"Answer", i32>(), 42);
+}
@@ -6,8 +6,8 @@ use std::{
 };
 
 use cilly::v2::{
-    asm::Assembly, cillyir_exporter::CillyIRExpoter, il_exporter::ILExporter, Access, CILIter,
-    MethodImpl, MethodRefIdx,
+    asm::Assembly, cillyir_exporter::CillyIRExpoter, il_exporter::ILExporter, text, Access,
+    CILIter, MethodImpl, MethodRefIdx,
 };
 use fxhash::FxHashSet;
 
@@ -151,6 +151,32 @@ fn main() {
                 println!("Preparing to export the assembly");
                 asm.export(path, CillyIRExpoter::default())
             }
+            "totext" => {
+                let path = body;
+                let path = path.trim().trim_matches('\'').trim();
+                std::fs::write(path, text::print(&asm)).unwrap();
+                println!("Saved the assembly as text");
+            }
+            "opentext" => {
+                let path = body;
+                let path = path.trim().trim_matches('\'').trim();
+                let src =
+                    std::fs::read_to_string(path).expect("ERROR:Could not open the assembly file!");
+                let loaded_asm = match text::parse(&src) {
+                    Ok(loaded_asm) => loaded_asm,
+                    Err(err) => {
+                        eprintln!("Could not parse {path:?}: {err}");
+                        continue;
+                    }
+                };
+                if asm.class_defs().is_empty() {
+                    asm = loaded_asm;
+                } else {
+                    println!("Linking an assembly");
+                    asm = asm.clone().link(loaded_asm);
+                }
+                println!("Loaded assembly");
+            }
             "mmakemissing" => {
                 let id = parse_id(body, &asm);
                 let Some(id) = asm.method_ref_to_def(id) else {
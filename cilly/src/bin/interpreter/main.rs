@@ -1,630 +1,1103 @@
-#![feature(iterator_try_collect)]
-#![allow(dead_code)]
-fn main() {}
-/*
-use std::io::Write;
+//! Executes a linked v2 [`Assembly`] directly, without a .NET runtime.
+//!
+//! All the locals, arguments and static fields live in a byte-addressed heap, so that their address can be taken.
+//! Managed objects(strings, arrays and exceptions) live in a separate object table, and are referenced by their ID.
+use std::rc::Rc;
+
 use cilly::{
-    asm::Assembly,
-    call_site::CallSite,
-    cil_node::CILNode,
-    cil_root::{CILRoot, SFI},
-    method::Method,
-    static_field_desc::StaticFieldDescriptor,
-    AsmStringContainer, FnSig, Type,
+    v2::{
+        asm::{CCTOR, ENTRYPOINT},
+        cilnode::ExtendKind,
+        cilroot::{BranchCond, CmpKind},
+        method::LocalDef,
+        Assembly, BasicBlock, BinOp, CILNode, CILRoot, ClassRefIdx, Const, Float, MethodDefIdx,
+        MethodImpl, MethodRefIdx, NodeIdx, StaticFieldIdx, StringIdx, Type,
+    },
+    IString,
 };
+use fxhash::FxHashMap;
+use value::{Object, Value};
+mod shims;
 mod value;
-use fxhash::{FxBuildHasher, FxHashMap};
-use value::Value;
+type AllocID = u32;
+type ObjID = u32;
+/// Function pointers are represented as pointers to allocations with IDs above this one.
+const FN_PTR_BASE: AllocID = 1 << 31;
 #[derive(Debug)]
-enum Exception {
-    MethodNotFound(CallSite),
-    LocalOutOfRange { loc: usize, lcount: usize },
-    ArgOutOfRange { arg: usize, lcount: usize },
-    AllocOffsetOutOfRange,
+pub enum Exception {
+    /// A managed exception, thrown by the interpreted program.
+    Throw(Value),
+    /// The program exited with this code.
+    Exit(i32),
+    MethodNotFound(String),
+    InvalidPointer(AllocID, u32),
+    NullReference,
+    InvalidOperands(String),
+    DivideByZero,
+    BlockNotFound(u32),
+    Unsupported(String),
 }
-type AllocID = u32;
-struct InterpreterState<'asm> {
+/// The layout of a value type.
+struct Layout {
+    size: u32,
+    align: u32,
+    field_offsets: FxHashMap<StringIdx, u32>,
+}
+/// The state of a single method invocation.
+#[derive(Default)]
+struct Frame {
+    args: Vec<(AllocID, Type)>,
+    locals: Vec<(AllocID, Type)>,
+    /// The exception currently being handled.
+    exception: Option<Value>,
+    /// Memory allocated using `localloc`, freed when this frame is popped.
+    allocs: Vec<AllocID>,
+}
+/// Where should the execution continue after a root was executed.
+enum Control {
+    Next,
+    Return(Value),
+    Branch(u32, u32),
+    Leave(u32),
+}
+/// The position of the currently executed block: either a main block, or a block in the handler of a main block.
+#[derive(Clone, Copy)]
+enum BlockPos {
+    Main(usize),
+    Handler(usize, usize),
+}
+pub struct InterpreterState<'asm> {
     asm: &'asm Assembly,
-    call_stack: Vec<(&'asm CallSite, usize, usize, cilly::cil_root::SFI)>,
-    locals: Vec<Box<[Value]>>,
-    mem: FxHashMap<AllocID, Box<[u8]>>,
+    args: Vec<String>,
+    mem: FxHashMap<AllocID, Vec<u8>>,
     last_alloc: AllocID,
-    fields: FxHashMap<StaticFieldDescriptor, Value>,
-    methods: FxHashMap<AllocID, CallSite>,
-    inv_methods: FxHashMap<CallSite, AllocID>,
-    last_alloc_method: AllocID,
+    objects: Vec<Object>,
+    strings: FxHashMap<StringIdx, ObjID>,
+    statics: FxHashMap<StaticFieldIdx, AllocID>,
+    fn_ptrs: Vec<MethodRefIdx>,
+    fn_ptr_ids: FxHashMap<MethodRefIdx, AllocID>,
+    layouts: FxHashMap<ClassRefIdx, Rc<Layout>>,
+    block_maps: FxHashMap<MethodDefIdx, Rc<FxHashMap<u32, usize>>>,
+    errno: Option<AllocID>,
 }
-
-fn eval_node<'asm>(
-    node: &'asm CILNode,
-    state: &mut InterpreterState<'asm>,
-    args: &mut Box<[Value]>,
-    string_map: &AsmStringContainer,
-) -> Result<Value, Exception> {
-    assert_eq!(state.locals.len(), state.call_stack.len());
-    match node {
-        CILNode::LdcU64(val) => Ok(Value::U64(*val)),
-        CILNode::LdcI64(val) => Ok(Value::I64(*val)),
-        CILNode::LdcU32(val) => Ok(Value::U32(*val)),
-        CILNode::LdcI32(val) => Ok(Value::I32(*val)),
-        //CILNode::LdcF64(val) => Ok(Value::F64(*val.into())),
-        //CILNode::LdcF32(val) => Ok(Value::F32(*val)),
-        CILNode::LdFalse => Ok(Value::Bool(false)),
-        CILNode::LdTrue => Ok(Value::Bool(true)),
-        CILNode::SizeOf(tpe) => match tpe.as_ref() {
-            Type::Int(Int::USize) | Type::Int(Int::ISize) => Ok(Value::I32(8)),
-            _ => todo!("Can't yet calc sizeof {tpe:?}"),
-        },
-        CILNode::TransmutePtr { val, new_ptr: _ } => match eval_node(val, state, args)? {
-            Value::Ptr(alloc_id, offset) => Ok(Value::Ptr(alloc_id, offset)),
-            _ => todo!("Can't transmute {val:?} to pointer."),
-        },
-        CILNode::Call(call_op_args)
-        | CILNode::CallVirt(call_op_args)
-        | CILNode::NewObj(call_op_args) => {
-            let mut call_args: Box<[_]> = call_op_args
-                .args
-                .iter()
-                .map(|arg| eval_node(arg, state, args).map(|arg| arg.pass_as_arg(state)))
-                .try_collect()?;
-            assert_eq!(state.locals.len(), state.call_stack.len());
-            match state.run(&call_op_args.site, &mut call_args) {
-                Ok(val) => Ok(val),
-                Err(Exception::MethodNotFound(_)) => {
-                    state.try_call_extern(&call_op_args.site, &mut call_args)
-                }
-                Err(err) => Err(err),
-            }
-        }
-
-        CILNode::Eq(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I8(b), Value::I8(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U8(b), Value::U8(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::I16(b), Value::I16(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U16(b), Value::U16(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::I32(b), Value::I32(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U32(b), Value::U32(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a == *b)),
-
-                (Value::Bool(b), Value::I32(val)) => Ok(Value::Bool(*b as u8 as i32 == *val)),
-                (Value::Bool(b), Value::U32(val)) => Ok(Value::Bool(*b as u8 as u32 == *val)),
-                (Value::U16(b), Value::U32(val)) => Ok(Value::Bool(*b as u32 == *val)),
-
-                _ => todo!("Can't yet cmp {a:?} and {b:?}"),
-            }
+impl<'asm> InterpreterState<'asm> {
+    fn new(asm: &'asm Assembly, args: Vec<String>) -> Self {
+        Self {
+            asm,
+            args,
+            mem: FxHashMap::default(),
+            last_alloc: 1,
+            objects: vec![],
+            strings: FxHashMap::default(),
+            statics: FxHashMap::default(),
+            fn_ptrs: vec![],
+            fn_ptr_ids: FxHashMap::default(),
+            layouts: FxHashMap::default(),
+            block_maps: FxHashMap::default(),
+            errno: None,
         }
-        CILNode::Lt(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-
-            match (&a, &b) {
-                (Value::I32(a), Value::I32(b)) => Ok(Value::Bool(*a < *b)),
-                (Value::F32(a), Value::F32(b)) => Ok(Value::Bool(*a < *b)),
-                _ => todo!("Can't yet lt {a:?} and {b:?}"),
-            }
+    }
+    pub fn alloc(&mut self, size: usize) -> AllocID {
+        let new_alloc = self.last_alloc;
+        self.last_alloc += 1;
+        assert!(self.last_alloc < FN_PTR_BASE, "Out of allocation IDs");
+        self.mem.insert(new_alloc, vec![0; size]);
+        new_alloc
+    }
+    pub fn free(&mut self, ptr: &Value) -> Result<(), Exception> {
+        match ptr.as_ptr() {
+            Some((0, 0)) => Ok(()),
+            Some((alloc, 0)) => self
+                .mem
+                .remove(&alloc)
+                .map(|_| ())
+                .ok_or(Exception::InvalidPointer(alloc, 0)),
+            Some((alloc, offset)) => Err(Exception::InvalidPointer(alloc, offset)),
+            None => Err(Exception::InvalidOperands(format!("Can't free {ptr:?}"))),
         }
-        CILNode::Shr(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I16(a), Value::U32(b)) => Ok(Value::I32((*a as i32) << b)),
-                _ => todo!("Can't yet shift right {a:?} by {b:?}"),
+    }
+    pub fn realloc(&mut self, ptr: &Value, size: usize) -> Result<Value, Exception> {
+        match ptr.as_ptr() {
+            Some((0, 0)) => Ok(Value::Ptr(self.alloc(size), 0)),
+            Some((alloc, 0)) => {
+                self.mem
+                    .get_mut(&alloc)
+                    .ok_or(Exception::InvalidPointer(alloc, 0))?
+                    .resize(size, 0);
+                Ok(Value::Ptr(alloc, 0))
             }
+            Some((alloc, offset)) => Err(Exception::InvalidPointer(alloc, offset)),
+            None => Err(Exception::InvalidOperands(format!("Can't realloc {ptr:?}"))),
         }
-        CILNode::RemUn(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::U32(a), Value::U32(b)) => Ok(Value::U32(a % b)),
-                _ => todo!("Can't yet rem unsigned {a:?} by {b:?}"),
-            }
+    }
+    /// Returns `len` bytes of memory at `ptr`.
+    pub fn mem(&self, ptr: &Value, len: usize) -> Result<&[u8], Exception> {
+        let (alloc, offset) = ptr
+            .as_ptr()
+            .ok_or_else(|| Exception::InvalidOperands(format!("{ptr:?} is not a pointer")))?;
+        if len == 0 {
+            return Ok(&[]);
         }
-        CILNode::Add(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I8(a), Value::I8(b)) => Ok(Value::I8(a.wrapping_add(*b))),
-                (Value::U8(a), Value::U8(b)) => Ok(Value::U8(a.wrapping_add(*b))),
-                (Value::U16(a), Value::U16(b)) => Ok(Value::U16(a.wrapping_add(*b))),
-                (Value::I16(a), Value::I16(b)) => Ok(Value::I16(a.wrapping_add(*b))),
-                (Value::U32(a), Value::U32(b)) => Ok(Value::U32(a.wrapping_add(*b))),
-                (Value::I32(a), Value::I32(b)) => Ok(Value::I32(a.wrapping_add(*b))),
-                (Value::U64(a), Value::U64(b)) => Ok(Value::U64(a.wrapping_add(*b))),
-                (Value::I64(a), Value::I64(b)) => Ok(Value::I64(a.wrapping_add(*b))),
-                (Value::I32(a), Value::USize(b)) => {
-                    Ok(Value::USize((*a as usize).wrapping_add(*b)))
-                }
-                (Value::Ptr(alloc, offset), Value::USize(b)) => Ok(Value::Ptr(
-                    *alloc,
-                    offset
-                        .checked_add(*b as u32)
-                        .ok_or(Exception::AllocOffsetOutOfRange)?,
-                )),
-                (Value::Ptr(alloc, offset), Value::U32(b)) => Ok(Value::Ptr(
-                    *alloc,
-                    offset
-                        .checked_add(*b)
-                        .ok_or(Exception::AllocOffsetOutOfRange)?,
-                )),
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet add {a:?} and {b:?}"),
-            }
+        self.mem
+            .get(&alloc)
+            .and_then(|mem| mem.get(offset as usize..offset as usize + len))
+            .ok_or(Exception::InvalidPointer(alloc, offset))
+    }
+    pub fn mem_mut(&mut self, ptr: &Value, len: usize) -> Result<&mut [u8], Exception> {
+        let (alloc, offset) = ptr
+            .as_ptr()
+            .ok_or_else(|| Exception::InvalidOperands(format!("{ptr:?} is not a pointer")))?;
+        if len == 0 {
+            return Ok(&mut []);
         }
-        CILNode::LDElelemRef { arr, idx } => {
-            let a = eval_node(arr, state, args)?;
-            let b = eval_node(idx, state, args)?;
-            match (&a, &b) {
-                (Value::StringArray(arr), Value::I32(idx)) => {
-                    Ok(Value::String(arr[*idx as usize].clone()))
+        self.mem
+            .get_mut(&alloc)
+            .and_then(|mem| mem.get_mut(offset as usize..offset as usize + len))
+            .ok_or(Exception::InvalidPointer(alloc, offset))
+    }
+    /// Reads a null-terminated string at `ptr`.
+    pub fn c_str(&self, ptr: &Value) -> Result<Vec<u8>, Exception> {
+        let (alloc, offset) = ptr
+            .as_ptr()
+            .ok_or_else(|| Exception::InvalidOperands(format!("{ptr:?} is not a pointer")))?;
+        let mem = self
+            .mem
+            .get(&alloc)
+            .and_then(|mem| mem.get(offset as usize..))
+            .ok_or(Exception::InvalidPointer(alloc, offset))?;
+        let len = mem
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(Exception::InvalidPointer(alloc, offset))?;
+        Ok(mem[..len].to_vec())
+    }
+    /// Reads a value of type `tpe` from the memory at `ptr`.
+    pub fn read(&mut self, tpe: &Type, ptr: &Value) -> Result<Value, Exception> {
+        let (size, _) = self.size_align(tpe)?;
+        self.decode(tpe, self.mem(ptr, size as usize)?)
+    }
+    /// Writes `val`, of type `tpe`, to the memory at `ptr`.
+    pub fn write(&mut self, tpe: &Type, ptr: &Value, val: &Value) -> Result<(), Exception> {
+        let (size, _) = self.size_align(tpe)?;
+        val.write(self.mem_mut(ptr, size as usize)?);
+        Ok(())
+    }
+    fn decode(&self, tpe: &Type, bytes: &[u8]) -> Result<Value, Exception> {
+        let mut raw = [0; 16];
+        let len = bytes.len().min(16);
+        raw[..len].copy_from_slice(&bytes[..len]);
+        let bits = u128::from_le_bytes(raw);
+        Ok(match tpe {
+            Type::Int(int) => Value::from_int(*int, bits),
+            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => Value::from_address(bits as u64),
+            Type::Bool => Value::Bool(bits & 0xFF != 0),
+            Type::PlatformChar => Value::U16(bits as u16),
+            Type::Float(Float::F16) => Value::U16(bits as u16),
+            Type::Float(Float::F32) => Value::F32(f32::from_bits(bits as u32)),
+            Type::Float(Float::F64) => Value::F64(f64::from_bits(bits as u64)),
+            Type::Float(Float::F128) => Value::U128(bits),
+            Type::ClassRef(cref) if self.asm.class_ref(*cref).is_valuetype() => {
+                match self.asm[self.asm.class_ref(*cref).name()].as_ref() {
+                    "System.UInt128" => Value::U128(bits),
+                    "System.Int128" => Value::I128(bits as i128),
+                    "System.Half" => Value::U16(bits as u16),
+                    _ => Value::ValueType(bytes.into()),
                 }
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet index into {a:?} wtih {b:?}"),
             }
-        }
-        CILNode::Mul(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I32(a), Value::I32(b)) => Ok(Value::I32(a.wrapping_mul(*b))),
-                (Value::I64(a), Value::I64(b)) => Ok(Value::I64(a.wrapping_mul(*b))),
-                (Value::USize(a), Value::USize(b)) => Ok(Value::USize(a.wrapping_mul(*b))),
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet mul {a:?} and {b:?}"),
+            Type::ClassRef(_)
+            | Type::PlatformString
+            | Type::PlatformObject
+            | Type::PlatformArray { .. } => Value::Obj(bits as ObjID),
+            Type::Void => Value::Undef,
+            Type::PlatformGeneric(..) => {
+                return Err(Exception::Unsupported(format!("Can't decode a {tpe:?}")))
             }
-        }
-
-        CILNode::ConvI8(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I8(val)),
-                Value::U8(val) => Ok(Value::I8(val as i8)),
-                Value::I16(val) => Ok(Value::I8(val as i8)),
-                Value::U16(val) => Ok(Value::I8(val as i8)),
-                Value::I32(val) => Ok(Value::I8(val as i8)),
-                Value::U32(val) => Ok(Value::I8(val as i8)),
-                _ => todo!("Can't convert a value of type {value:?} to i8"),
+        })
+    }
+    /// Returns the size and alignment of a type.
+    pub fn size_align(&mut self, tpe: &Type) -> Result<(u32, u32), Exception> {
+        Ok(match tpe {
+            Type::Int(int) => {
+                let size = int.size().map_or(8, u32::from);
+                (size, size.min(8))
             }
-        }
-        CILNode::MRefToRawPtr(ptr) => eval_node(ptr, state, args),
-        CILNode::ConvU8(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U8(val as u8)),
-                Value::U8(val) => Ok(Value::U8(val)),
-                Value::I16(val) => Ok(Value::U8(val as u8)),
-                Value::U16(val) => Ok(Value::U8(val as u8)),
-                Value::I32(val) => Ok(Value::U8(val as u8)),
-                Value::U32(val) => Ok(Value::U8(val as u8)),
-                Value::I64(val) => Ok(Value::U8(val as u8)),
-                Value::U64(val) => Ok(Value::U8(val as u8)),
-                _ => todo!("Can't convert a value of type {value:?} to u8"),
+            Type::Ptr(_)
+            | Type::Ref(_)
+            | Type::FnPtr(_)
+            | Type::PlatformString
+            | Type::PlatformObject
+            | Type::PlatformArray { .. } => (8, 8),
+            Type::Bool => (1, 1),
+            Type::PlatformChar | Type::Float(Float::F16) => (2, 2),
+            Type::Float(Float::F32) => (4, 4),
+            Type::Float(Float::F64) => (8, 8),
+            Type::Float(Float::F128) => (16, 16),
+            Type::Void => (0, 1),
+            Type::ClassRef(cref) => {
+                if self.asm.class_ref(*cref).is_valuetype() {
+                    let layout = self.layout(*cref)?;
+                    (layout.size, layout.align)
+                } else {
+                    (8, 8)
+                }
             }
-        }
-        CILNode::ConvI16(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I16(val as i16)),
-                Value::U8(val) => Ok(Value::I16(val as i16)),
-                Value::I16(val) => Ok(Value::I16(val)),
-                Value::U16(val) => Ok(Value::I16(val as i16)),
-                Value::I32(val) => Ok(Value::I16(val as i16)),
-                Value::U32(val) => Ok(Value::I16(val as i16)),
-                _ => todo!("Can't convert a value of type {value:?} to i16"),
+            Type::PlatformGeneric(..) => {
+                return Err(Exception::Unsupported(format!(
+                    "Can't get the size of {tpe:?}"
+                )))
             }
+        })
+    }
+    fn layout(&mut self, cref: ClassRefIdx) -> Result<Rc<Layout>, Exception> {
+        if let Some(layout) = self.layouts.get(&cref) {
+            return Ok(layout.clone());
         }
-        CILNode::Neg(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(-val)),
-                _ => todo!("Can't neg {value:?}"),
+        let asm = self.asm;
+        let name = &asm[asm.class_ref(cref).name()];
+        let layout = match asm.class_ref_to_def(cref) {
+            Some(def) => {
+                let def = &asm[def];
+                let mut field_offsets = FxHashMap::default();
+                let (mut size, mut align): (u32, u32) = (0, 1);
+                for (tpe, field_name, offset) in def.fields() {
+                    let (field_size, field_align) = self.size_align(tpe)?;
+                    let offset = offset.unwrap_or_else(|| size.next_multiple_of(field_align));
+                    field_offsets.insert(*field_name, offset);
+                    size = size.max(offset + field_size);
+                    align = align.max(field_align);
+                }
+                let size = match def.explict_size() {
+                    Some(size) => size.get(),
+                    // Like in .NET, empty structs have a size of 1.
+                    None => size.next_multiple_of(align).max(1),
+                };
+                Layout {
+                    size,
+                    align,
+                    field_offsets,
+                }
             }
-        }
-        CILNode::Not(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(!val)),
-                Value::I8(val) => Ok(Value::I8(!val)),
-                _ => todo!("Can't not {value:?}"),
+            None => {
+                let (size, align) = match name {
+                    "System.UInt128" | "System.Int128" => (16, 16),
+                    "System.Half" => (2, 2),
+                    _ => {
+                        return Err(Exception::Unsupported(format!(
+                            "Can't get the layout of {name}"
+                        )))
+                    }
+                };
+                Layout {
+                    size,
+                    align,
+                    field_offsets: FxHashMap::default(),
+                }
             }
+        };
+        let layout = Rc::new(layout);
+        self.layouts.insert(cref, layout.clone());
+        Ok(layout)
+    }
+    fn field_offset(&mut self, owner: ClassRefIdx, name: StringIdx) -> Result<u32, Exception> {
+        self.layout(owner)?
+            .field_offsets
+            .get(&name)
+            .copied()
+            .ok_or_else(|| Exception::Unsupported(format!("Field {} not found", &self.asm[name])))
+    }
+    pub fn alloc_obj(&mut self, obj: Object) -> Value {
+        self.objects.push(obj);
+        Value::Obj(ObjID::try_from(self.objects.len()).unwrap())
+    }
+    pub fn obj(&self, val: &Value) -> Result<&Object, Exception> {
+        match val {
+            Value::Obj(0) => Err(Exception::NullReference),
+            Value::Obj(obj) => Ok(&self.objects[*obj as usize - 1]),
+            _ => Err(Exception::InvalidOperands(format!(
+                "{val:?} is not an object"
+            ))),
         }
-        CILNode::ConvU16(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U16(val as u16)),
-                Value::U8(val) => Ok(Value::U16(val as u16)),
-                Value::I16(val) => Ok(Value::U16(val as u16)),
-                Value::U16(val) => Ok(Value::U16(val)),
-                Value::I32(val) => Ok(Value::U16(val as u16)),
-                Value::U32(val) => Ok(Value::U16(val as u16)),
-                _ => todo!("Can't convert a value of type {value:?} to u16"),
-            }
+    }
+    pub fn string(&self, val: &Value) -> Result<IString, Exception> {
+        match self.obj(val)? {
+            Object::String(string) | Object::Exception(string) => Ok(string.clone()),
+            Object::Array(_) => Err(Exception::InvalidOperands(format!(
+                "{val:?} is not a string"
+            ))),
         }
-        CILNode::ConvI32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I32(val as i32)),
-                Value::U8(val) => Ok(Value::I32(val as i32)),
-                Value::I16(val) => Ok(Value::I32(val as i32)),
-                Value::U16(val) => Ok(Value::I32(val as i32)),
-                Value::I32(val) => Ok(Value::I32(val)),
-                Value::U32(val) => Ok(Value::I32(val as i32)),
-                Value::USize(val) => Ok(Value::I32(val as i32)),
-                Value::I64(val) => Ok(Value::I32(val as i32)),
-                Value::U64(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't convert a value of type {value:?} to i32"),
+    }
+    /// Creates a managed exception with the message `msg`.
+    pub fn throw(&mut self, msg: impl Into<IString>) -> Exception {
+        Exception::Throw(self.alloc_obj(Object::Exception(msg.into())))
+    }
+    /// The address of `errno`.
+    pub fn errno(&mut self) -> Value {
+        let errno = match self.errno {
+            Some(errno) => errno,
+            None => {
+                let errno = self.alloc(4);
+                self.errno = Some(errno);
+                errno
             }
+        };
+        Value::Ptr(errno, 0)
+    }
+    fn static_field(&mut self, field: StaticFieldIdx) -> Result<Value, Exception> {
+        if let Some(alloc) = self.statics.get(&field) {
+            return Ok(Value::Ptr(*alloc, 0));
         }
-        CILNode::ConvU32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U32(val as u32)),
-                Value::U8(val) => Ok(Value::U32(val as u32)),
-                Value::I16(val) => Ok(Value::U32(val as u32)),
-                Value::U16(val) => Ok(Value::U32(val as u32)),
-                Value::I32(val) => Ok(Value::U32(val as u32)),
-                Value::U32(val) => Ok(Value::U32(val)),
-                _ => todo!("Can't convert a value of type {value:?} to u32"),
-            }
+        let (size, _) = self.size_align(&self.asm[field].tpe())?;
+        let alloc = self.alloc(size as usize);
+        self.statics.insert(field, alloc);
+        Ok(Value::Ptr(alloc, 0))
+    }
+    fn fn_ptr(&mut self, mref: MethodRefIdx) -> Value {
+        let id = *self.fn_ptr_ids.entry(mref).or_insert_with(|| {
+            self.fn_ptrs.push(mref);
+            FN_PTR_BASE + AllocID::try_from(self.fn_ptrs.len() - 1).unwrap()
+        });
+        Value::Ptr(id, 0)
+    }
+    fn fn_ptr_target(&self, ptr: &Value) -> Result<MethodRefIdx, Exception> {
+        match ptr.as_ptr() {
+            Some((id, 0)) if id >= FN_PTR_BASE => self
+                .fn_ptrs
+                .get((id - FN_PTR_BASE) as usize)
+                .copied()
+                .ok_or(Exception::InvalidPointer(id, 0)),
+            Some((id, offset)) => Err(Exception::InvalidPointer(id, offset)),
+            None => Err(Exception::InvalidOperands(format!(
+                "{ptr:?} is not a function pointer"
+            ))),
         }
-
-        CILNode::ConvF32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I32(val) => Ok(Value::F32(val as f32)),
-                _ => todo!("Can't convert a value of type {value:?} to f32"),
+    }
+    /// Calls the method `mref`, with arguments `args`.
+    pub fn call(&mut self, mref: MethodRefIdx, args: Vec<Value>) -> Result<Value, Exception> {
+        let asm = self.asm;
+        let Some(def_idx) = asm.method_ref_to_def(mref) else {
+            return shims::call_builtin(self, mref, args);
+        };
+        let def = &asm[def_idx];
+        match def.resolved_implementation(asm) {
+            MethodImpl::MethodBody { blocks, locals } => {
+                self.run_body(def_idx, blocks, locals, args)
             }
-        }
-        CILNode::ZeroExtendToU64(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::U64(val) => Ok(Value::U64(val)),
-                _ => todo!("Can't convert a value of type {value:?} to u64"),
+            MethodImpl::Extern { .. } => {
+                shims::call_libc(self, &asm[def.name()], &asm[def.sig()], args)
             }
+            MethodImpl::Missing => Err(self.throw(format!("missing methiod {}", &asm[def.name()]))),
+            MethodImpl::AliasFor(_) => panic!("resolved_implementation returned `AliasFor`"),
         }
-        CILNode::SignExtendToI64(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(val)),
-                _ => todo!("Can't convert a value of type {value:?} to u64"),
-            }
+    }
+    fn block_map(&mut self, def: MethodDefIdx, blocks: &[BasicBlock]) -> Rc<FxHashMap<u32, usize>> {
+        self.block_maps
+            .entry(def)
+            .or_insert_with(|| {
+                Rc::new(
+                    blocks
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, block)| (block.block_id(), idx))
+                        .collect(),
+                )
+            })
+            .clone()
+    }
+    fn run_body(
+        &mut self,
+        def: MethodDefIdx,
+        blocks: &'asm [BasicBlock],
+        locals: &'asm [LocalDef],
+        args: Vec<Value>,
+    ) -> Result<Value, Exception> {
+        let asm = self.asm;
+        let sig = &asm[asm[def].sig()];
+        let mut frame = Frame::default();
+        for (tpe, val) in sig.inputs().iter().zip(args) {
+            let (size, _) = self.size_align(tpe)?;
+            let alloc = self.alloc(size as usize);
+            self.write(tpe, &Value::Ptr(alloc, 0), &val)?;
+            frame.args.push((alloc, *tpe));
         }
-        CILNode::SignExtendToISize(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::U64(val) => Ok(Value::ISize(val as i64 as isize)),
-                Value::I64(val) => Ok(Value::ISize(val as isize)),
-                _ => todo!("Can't convert a value of type {value:?} to isize"),
-            }
+        for (_, tpe) in locals {
+            let tpe = asm[*tpe];
+            let (size, _) = self.size_align(&tpe)?;
+            frame.locals.push((self.alloc(size as usize), tpe));
         }
-        CILNode::ZeroExtendToUSize(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I32(val) => Ok(Value::USize(val as u32 as usize)),
-                Value::U32(val) => Ok(Value::USize(val as usize)),
-                _ => todo!("Can't convert a value of type {value:?} to usize"),
-            }
+        let res = self.run_blocks(def, blocks, &mut frame);
+        for (alloc, _) in frame.args.iter().chain(frame.locals.iter()) {
+            self.mem.remove(alloc);
         }
-        CILNode::LDLen { arr } => {
-            let arr = eval_node(arr, state, args)?;
-            match arr {
-                Value::StringArray(arr) => Ok(Value::USize(arr.len())),
-                _ => todo!("Can't get the length of a {arr:?}"),
-            }
+        for alloc in &frame.allocs {
+            self.mem.remove(alloc);
         }
-        CILNode::LDFtn(site) => Ok(Value::Ptr(state.get_fn_ptr_alloc(site), 0)),
-        CILNode::LDArg(arg) => args
-            .get(*arg as usize)
-            .cloned()
-            .ok_or(Exception::ArgOutOfRange {
-                arg: *arg as usize,
-                lcount: args.len(),
-            }),
-        CILNode::LDStaticField(field_desc) => Ok(state
-            .fields
-            .get(field_desc)
-            .cloned()
-            .unwrap_or(Value::Undef)),
-        CILNode::LDLoc(loc) => state
-            .locals
-            .last()
-            .unwrap()
-            .get(*loc as usize)
-            .cloned()
-            .ok_or(Exception::LocalOutOfRange {
-                loc: *loc as usize,
-                lcount: state.locals.len(),
-            }),
-        _ => todo!("Can't handle node:{node:?}"),
-    }
-}
-impl<'asm> InterpreterState<'asm> {
-    pub fn get_fn_ptr_alloc(&mut self, site: &CallSite) -> AllocID {
-        *self.inv_methods.entry(site.clone()).or_insert_with(|| {
-            let new_method = self.last_alloc_method;
-            self.methods.insert(new_method, site.clone());
-            new_method
-        })
-    }
-    pub fn alloc(&mut self, size: usize) -> AllocID {
-        let new_alloc = self.last_alloc;
-        self.last_alloc += 1;
-        self.mem
-            .insert(new_alloc, (0..size).map(|_| 0_u8).collect());
-        new_alloc
+        res
     }
-    pub fn try_call_extern(
+    fn run_blocks(
         &mut self,
-        call: &'asm CallSite,
-        args: &mut Box<[Value]>,
-        string_map: &AsmStringContainer,
+        def: MethodDefIdx,
+        blocks: &'asm [BasicBlock],
+        frame: &mut Frame,
     ) -> Result<Value, Exception> {
-        assert_eq!(self.locals.len(), self.call_stack.len());
-        let res = match (call.class(), call.name(), call.signature()) {
-            (Some(_), "IsNaN", _) => match args[0] {
-                Value::F32(f) => Ok(Value::Bool(f.is_nan())),
-                Value::F64(f) => Ok(Value::Bool(f.is_nan())),
-                _ => panic!("Can't check if {:?} is nan.", args[0]),
-            },
-            (Some(_), "AlignedAlloc" | "AllocHGlobal", _) => {
-                Ok(Value::Ptr(self.alloc(args[0].as_usize().unwrap()), 0))
-            }
-            (Some(_), "GetCommandLineArgs", _) => Ok(Value::StringArray(
-                std::env::args().map(|arg| arg.into()).collect(),
-            )),
-
-            (Some(_), "get_Length", _) => {
-                let arg = &args[0];
-                match arg {
-                    Value::StringArray(arr) => Ok(Value::I32(arr.len() as u32 as i32)),
-                    _ => todo!("Can't get length of {arg:?}"),
+        let asm = self.asm;
+        let block_map = self.block_map(def, blocks);
+        let main_block = |id: u32| {
+            block_map
+                .get(&id)
+                .map(|idx| BlockPos::Main(*idx))
+                .ok_or(Exception::BlockNotFound(id))
+        };
+        let mut pos = BlockPos::Main(0);
+        let mut root = 0;
+        loop {
+            let block = match pos {
+                BlockPos::Main(idx) => &blocks[idx],
+                BlockPos::Handler(idx, hidx) => &blocks[idx].handler().unwrap()[hidx],
+            };
+            let Some(root_idx) = block.roots().get(root) else {
+                // Fall trough into the next block.
+                pos = match pos {
+                    BlockPos::Main(idx) if idx + 1 < blocks.len() => BlockPos::Main(idx + 1),
+                    BlockPos::Handler(idx, hidx)
+                        if hidx + 1 < blocks[idx].handler().unwrap().len() =>
+                    {
+                        BlockPos::Handler(idx, hidx + 1)
+                    }
+                    _ => return Err(Exception::BlockNotFound(block.block_id() + 1)),
+                };
+                root = 0;
+                continue;
+            };
+            match self.exec_root(asm.get_root(*root_idx), frame) {
+                Ok(Control::Next) => root += 1,
+                Ok(Control::Return(val)) => return Ok(val),
+                Ok(Control::Leave(target)) => {
+                    pos = main_block(target)?;
+                    root = 0;
                 }
-            }
-            (Some(tpe), ".ctor", _) => match tpe.name_path(string_map) {
-                "System.UInt128" => {
-                    let (lower, upper) = (
-                        *args[0].as_u64().unwrap() as u128,
-                        *args[1].as_u64().unwrap() as u128,
-                    );
-                    //Ok(Value::U128(lower<<64))
-
-                    Ok(Value::U128(lower << 64 | upper))
+                Ok(Control::Branch(target, sub_target)) => {
+                    pos = match pos {
+                        _ if sub_target == 0 => main_block(target)?,
+                        // Outside of a handler, a branch with a sub target leaves the protected region.
+                        BlockPos::Main(_) => main_block(sub_target)?,
+                        BlockPos::Handler(idx, _) => {
+                            match blocks[idx]
+                                .handler()
+                                .unwrap()
+                                .iter()
+                                .position(|hblock| hblock.block_id() == sub_target)
+                            {
+                                Some(hidx) => BlockPos::Handler(idx, hidx),
+                                None => main_block(sub_target)?,
+                            }
+                        }
+                    };
+                    root = 0;
                 }
-                _ => todo!("Can't newobj {tpe:?}"),
-            },
-            (Some(_), "StringToCoTaskMemUTF8", _) => {
-                let string = args[0].as_string().unwrap().clone();
-                let alloc = self.alloc(string.len() + 1);
-                let alloc = Value::Ptr(alloc, 0);
-                self.get_memory_at_mut(alloc.clone())[..string.len()]
-                    .copy_from_slice(string.as_bytes());
-                Ok(alloc)
+                Err(Exception::Throw(exception)) => match pos {
+                    BlockPos::Main(idx) if blocks[idx].handler().is_some() => {
+                        frame.exception = Some(exception);
+                        pos = BlockPos::Handler(idx, 0);
+                        root = 0;
+                    }
+                    _ => return Err(Exception::Throw(exception)),
+                },
+                Err(err) => return Err(err),
             }
-
-            (Some(_class), _name, _sig) => todo!("Can't yet call extern {call:?}"),
-            _ => Err(Exception::MethodNotFound(call.clone())),
-        };
-
-        res
-    }
-    pub fn run_cctor(&mut self) -> Result<Value, Exception> {
-        match self.asm.cctor() {
-            Some(_) => self.run(
-                Box::<CallSite>::leak(Box::new(CallSite::builtin(
-                    ".cctor".into(),
-                    FnSig::new(&[], Type::Void),
-                    true,
-                ))),
-                &mut vec![].into(),
-            ),
-            None => Ok(Value::Undef),
-        }
-    }
-    pub fn run_entypoint(&mut self) -> Result<Value, Exception> {
-        let entry = self.asm.methods().find(|method| method.is_entrypoint());
-        match entry {
-            Some(entry) => self.run(
-                Box::<CallSite>::leak(Box::new(entry.call_site())),
-                &mut vec![Value::StringArray(
-                    std::env::args().map(|arg| arg.into()).collect(),
-                )]
-                .into(),
-            ),
-            None => Ok(Value::Undef),
         }
     }
-    pub fn method(&self, site: &'asm CallSite) -> Result<&'asm Method, Exception> {
-        self.asm
-            .functions()
-            .get(site)
-            .ok_or(Exception::MethodNotFound(site.clone()))
+    fn eval_args(&mut self, args: &[NodeIdx], frame: &mut Frame) -> Result<Vec<Value>, Exception> {
+        args.iter().map(|arg| self.eval(*arg, frame)).collect()
     }
-    pub fn run(
-        &mut self,
-        call: &'asm CallSite,
-        args: &mut Box<[Value]>,
-    ) -> Result<Value, Exception> {
-        assert_eq!(self.locals.len(), self.call_stack.len());
-        assert!(args.len() >= self.method(call)?.sig().inputs().len());
-        let locals = self.method(call)?.locals();
-        let locals = locals
-            .iter()
-            .map(|(_name, tpe)| Value::default_for_type(tpe, self))
-            .collect();
-        self.locals.push(locals);
-        let sfi: SFI = Box::new((0..1, 0..1, "".into()));
-        self.call_stack.push((call, 0, 0, sfi));
-        while let Some(method) = self.call_stack.last_mut() {
-            let (method, block, tree, curr_sfi) = method;
-            let method = self
-                .asm
-                .functions()
-                .get(method)
-                .ok_or::<Exception>(Exception::MethodNotFound(method.clone()))?;
-            let block_ref = &method.blocks()[*block];
-            let tree_ref = &block_ref.trees()[*tree];
-            match tree_ref.root() {
-                CILRoot::GoTo { target, sub_target } => {
-                    assert_eq!(*sub_target, 0);
-                    let (_method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                    *block = *target as usize;
-                    *tree = 0;
-                    continue;
-                }
-                CILRoot::BTrue {
-                    target,
-                    sub_target,
-                    cond,
-                } => {
-                    assert_eq!(*sub_target, 0);
-                    let cond = eval_node(cond, self, args)?.as_bool().unwrap();
-                    if cond {
-                        let (_method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                        *block = *target as usize;
-                        *tree = 0;
-                        continue;
+    fn eval(&mut self, node: NodeIdx, frame: &mut Frame) -> Result<Value, Exception> {
+        let asm = self.asm;
+        match &asm[node] {
+            CILNode::Const(cst) => Ok(match cst.as_ref() {
+                Const::I8(val) => Value::I8(*val),
+                Const::I16(val) => Value::I16(*val),
+                Const::I32(val) => Value::I32(*val),
+                Const::I64(val) => Value::I64(*val),
+                Const::ISize(val) => Value::ISize(*val as isize),
+                Const::U8(val) => Value::U8(*val),
+                Const::U16(val) => Value::U16(*val),
+                Const::U32(val) => Value::U32(*val),
+                Const::U64(val) => Value::U64(*val),
+                Const::USize(val) => Value::USize(*val as usize),
+                Const::PlatformString(string) => match self.strings.get(string) {
+                    Some(obj) => Value::Obj(*obj),
+                    None => {
+                        let obj = self.alloc_obj(Object::String(asm[*string].into()));
+                        let Value::Obj(id) = obj else { unreachable!() };
+                        self.strings.insert(*string, id);
+                        obj
                     }
+                },
+                Const::Bool(val) => Value::Bool(*val),
+                Const::F32(val) => Value::F32(**val),
+                Const::F64(val) => Value::F64(**val),
+                Const::Null(_) => Value::Obj(0),
+            }),
+            CILNode::BinOp(lhs, rhs, op) => {
+                let lhs = self.eval(*lhs, frame)?;
+                let rhs = self.eval(*rhs, frame)?;
+                match lhs.binop(&rhs, *op) {
+                    Err(Exception::DivideByZero) => Err(self.throw("Attempted to divide by zero.")),
+                    res => res,
                 }
-                CILRoot::BFalse {
-                    target,
-                    sub_target,
-                    cond,
-                } => {
-                    assert_eq!(*sub_target, 0);
-                    let cond = eval_node(cond, self, args)?.as_bool().unwrap();
-                    if !cond {
-                        let (_method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                        *block = *target as usize;
-                        *tree = 0;
-                        continue;
+            }
+            CILNode::UnOp(val, op) => self.eval(*val, frame)?.unop(op.clone()),
+            CILNode::LdLoc(loc) => {
+                let (alloc, tpe) = frame.locals[*loc as usize];
+                self.read(&tpe, &Value::Ptr(alloc, 0))
+            }
+            CILNode::LdLocA(loc) => Ok(Value::Ptr(frame.locals[*loc as usize].0, 0)),
+            CILNode::LdArg(arg) => {
+                let (alloc, tpe) = frame.args[*arg as usize];
+                self.read(&tpe, &Value::Ptr(alloc, 0))
+            }
+            CILNode::LdArgA(arg) => Ok(Value::Ptr(frame.args[*arg as usize].0, 0)),
+            CILNode::Call(call) => {
+                let args = self.eval_args(&call.1, frame)?;
+                self.call(call.0, args)
+            }
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => self
+                .eval(*input, frame)?
+                .int_cast(*target, *extend == ExtendKind::SignExtend),
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => {
+                let val = self.eval(*input, frame)?.to_float(*is_signed)?;
+                match target {
+                    Float::F32 => Ok(Value::F32(val as f32)),
+                    Float::F64 => Ok(Value::F64(val)),
+                    Float::F16 | Float::F128 => {
+                        Err(Exception::Unsupported(format!("Can't cast to {target:?}")))
                     }
                 }
-                CILRoot::Nop => (),
-                CILRoot::STLoc { local, tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(tree, self, args)?;
-                    let mut cloned = self.locals.last().unwrap()[*local as usize].clone();
-                    cloned.set(val, self);
-                    self.locals.last_mut().unwrap()[*local as usize] = cloned;
-                }
-                CILRoot::STArg { arg, tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(tree, self, args)?;
-                    args[*arg as usize].set(val, self);
-                }
-                CILRoot::SetField {
-                    addr,
-                    value,
-                    desc: _,
-                } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let _val = eval_node(value, self, args)?;
-                    let _addr = eval_node(addr, self, args)?;
-                    todo!()
-                }
-                CILRoot::SetStaticField { descr, value } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(value, self, args)?;
-                    self.fields.insert(*descr.clone(), val);
-                }
-                CILRoot::Pop { tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    eval_node(tree, self, args)?;
-                }
-                CILRoot::STIndISize(addr, val) => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let addr = eval_node(addr, self, args)?;
-                    let val = eval_node(val, self, args)?;
-                    self.get_memory_at_mut(addr)[..8]
-                        .copy_from_slice(&val.as_usize().unwrap().to_le_bytes()[..])
-                }
-                CILRoot::STIndI8(addr, val) => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let addr = eval_node(addr, self, args)?;
-                    let val = eval_node(val, self, args)?;
-                    self.get_memory_at_mut(addr)[0] = val.as_i8().unwrap() as u8;
-                }
-                CILRoot::VoidRet => {
-                    self.locals.pop();
-                    self.call_stack.pop();
-                    return Ok(Value::Undef);
+            }
+            CILNode::RefToPtr(val) => self.eval(*val, frame),
+            CILNode::PtrCast(val, _) => {
+                let val = self.eval(*val, frame)?;
+                match val.as_ptr() {
+                    Some((alloc, offset)) => Ok(Value::Ptr(alloc, offset)),
+                    None => Ok(val),
                 }
-                CILRoot::Ret { tree } => {
-                    let tree = eval_node(tree, self, args)?;
-                    self.locals.pop();
-                    self.call_stack.pop();
-                    return Ok(tree);
+            }
+            CILNode::LdFieldAdress { addr, field } => {
+                let addr = self.eval(*addr, frame)?;
+                let field = asm[*field];
+                let offset = self.field_offset(field.owner(), field.name())?;
+                let (alloc, base) = addr.as_ptr().ok_or(Exception::NullReference)?;
+                Ok(Value::Ptr(alloc, base + offset))
+            }
+            CILNode::LdField { addr, field } => {
+                let addr = self.eval(*addr, frame)?;
+                let field = asm[*field];
+                let offset = self.field_offset(field.owner(), field.name())?;
+                match addr {
+                    Value::ValueType(bytes) => {
+                        let (size, _) = self.size_align(&field.tpe())?;
+                        let (start, end) = (offset as usize, (offset + size) as usize);
+                        self.decode(&field.tpe(), &bytes[start..end])
+                    }
+                    _ => {
+                        let (alloc, base) = addr.as_ptr().ok_or(Exception::NullReference)?;
+                        self.read(&field.tpe(), &Value::Ptr(alloc, base + offset))
+                    }
                 }
-                CILRoot::SourceFileInfo(sfi) => {
-                    println!("{}:{:?}:{:?}", sfi.2, sfi.0, sfi.1);
-                    *curr_sfi = sfi.clone();
+            }
+            CILNode::LdInd { addr, tpe, .. } => {
+                let addr = self.eval(*addr, frame)?;
+                self.read(&asm[*tpe], &addr)
+            }
+            CILNode::SizeOf(tpe) => {
+                let (size, _) = self.size_align(&asm[*tpe])?;
+                Ok(Value::I32(size as i32))
+            }
+            CILNode::GetException => frame
+                .exception
+                .clone()
+                .ok_or_else(|| Exception::Unsupported("No exception to get".into())),
+            // Objects are not typed, so the cast always succeeds.
+            CILNode::IsInst(val, _) | CILNode::CheckedCast(val, _) => self.eval(*val, frame),
+            CILNode::CallI(calli) => {
+                let (fn_ptr, _, args) = calli.as_ref();
+                let args = self.eval_args(args, frame)?;
+                let fn_ptr = self.eval(*fn_ptr, frame)?;
+                let mref = self.fn_ptr_target(&fn_ptr)?;
+                self.call(mref, args)
+            }
+            CILNode::LocAlloc { size } => {
+                let size = self.eval(*size, frame)?.as_usize();
+                let alloc = self.alloc(size);
+                frame.allocs.push(alloc);
+                Ok(Value::Ptr(alloc, 0))
+            }
+            // All allocations are aligned to 2^32, so no extra work is needed.
+            CILNode::LocAllocAlgined { tpe, .. } => {
+                let (size, _) = self.size_align(&asm[*tpe])?;
+                let alloc = self.alloc(size as usize);
+                frame.allocs.push(alloc);
+                Ok(Value::Ptr(alloc, 0))
+            }
+            CILNode::LdStaticField(field) => {
+                let addr = self.static_field(*field)?;
+                self.read(&asm[*field].tpe(), &addr)
+            }
+            CILNode::LdStaticFieldAdress(field) => self.static_field(*field),
+            CILNode::LdFtn(mref) => Ok(self.fn_ptr(*mref)),
+            CILNode::LdLen(arr) => {
+                let arr = self.eval(*arr, frame)?;
+                match self.obj(&arr)? {
+                    Object::Array(elems) => Ok(Value::USize(elems.len())),
+                    obj => Err(Exception::InvalidOperands(format!(
+                        "{obj:?} is not an array"
+                    ))),
                 }
-                CILRoot::Call {
-                    args: call_args,
-                    site,
+            }
+            CILNode::LdElelemRef { array, index } => {
+                let array = self.eval(*array, frame)?;
+                let index = self.eval(*index, frame)?.as_usize();
+                match self.obj(&array)? {
+                    Object::Array(elems) => elems
+                        .get(index)
+                        .cloned()
+                        .ok_or_else(|| self.throw("Index was outside the bounds of the array.")),
+                    obj => Err(Exception::InvalidOperands(format!(
+                        "{obj:?} is not an array"
+                    ))),
                 }
-                | CILRoot::CallVirt {
-                    args: call_args,
-                    site,
-                } => {
-                    let mut call_args: Box<[_]> = call_args
-                        .iter()
-                        .map(|arg| eval_node(arg, self, args).map(|arg| arg.pass_as_arg(self)))
-                        .try_collect()?;
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    match self.run(site, &mut call_args) {
-                        Ok(val) => Ok(val),
-                        Err(Exception::MethodNotFound(_)) => {
-                            self.try_call_extern(site, &mut call_args, self.asm.string_map())
-                        }
-                        Err(err) => Err(err),
-                    }?;
+            }
+            CILNode::LdTypeToken(_) | CILNode::UnboxAny { .. } => Err(Exception::Unsupported(
+                format!("Can't yet evaluate {:?}", asm[node]),
+            )),
+        }
+    }
+    fn cond(&mut self, cond: &BranchCond, frame: &mut Frame) -> Result<bool, Exception> {
+        let (lhs, rhs, op, negate) = match cond {
+            BranchCond::True(cond) => return Ok(self.eval(*cond, frame)?.is_true()),
+            BranchCond::False(cond) => return Ok(!self.eval(*cond, frame)?.is_true()),
+            BranchCond::Eq(lhs, rhs) => (lhs, rhs, BinOp::Eq, false),
+            BranchCond::Ne(lhs, rhs) => (lhs, rhs, BinOp::Eq, true),
+            BranchCond::Lt(lhs, rhs, kind) => match kind {
+                CmpKind::Ordered | CmpKind::Signed => (lhs, rhs, BinOp::Lt, false),
+                CmpKind::Unordered | CmpKind::Unsigned => (lhs, rhs, BinOp::LtUn, false),
+            },
+            BranchCond::Gt(lhs, rhs, kind) => match kind {
+                CmpKind::Ordered | CmpKind::Signed => (lhs, rhs, BinOp::Gt, false),
+                CmpKind::Unordered | CmpKind::Unsigned => (lhs, rhs, BinOp::GtUn, false),
+            },
+            // a <= b is !(a > b), with the "orderdness" of floats flipped.
+            BranchCond::Le(lhs, rhs, kind) => match kind {
+                CmpKind::Unordered | CmpKind::Signed => (lhs, rhs, BinOp::Gt, true),
+                CmpKind::Ordered | CmpKind::Unsigned => (lhs, rhs, BinOp::GtUn, true),
+            },
+            BranchCond::Ge(lhs, rhs, kind) => match kind {
+                CmpKind::Unordered | CmpKind::Signed => (lhs, rhs, BinOp::Lt, true),
+                CmpKind::Ordered | CmpKind::Unsigned => (lhs, rhs, BinOp::LtUn, true),
+            },
+        };
+        let lhs = self.eval(*lhs, frame)?;
+        let rhs = self.eval(*rhs, frame)?;
+        Ok(lhs.binop(&rhs, op)?.is_true() != negate)
+    }
+    fn exec_root(&mut self, root: &'asm CILRoot, frame: &mut Frame) -> Result<Control, Exception> {
+        let asm = self.asm;
+        match root {
+            CILRoot::StLoc(loc, val) => {
+                let val = self.eval(*val, frame)?;
+                let (alloc, tpe) = frame.locals[*loc as usize];
+                self.write(&tpe, &Value::Ptr(alloc, 0), &val)?;
+            }
+            CILRoot::StArg(arg, val) => {
+                let val = self.eval(*val, frame)?;
+                let (alloc, tpe) = frame.args[*arg as usize];
+                self.write(&tpe, &Value::Ptr(alloc, 0), &val)?;
+            }
+            CILRoot::Ret(val) => return Ok(Control::Return(self.eval(*val, frame)?)),
+            CILRoot::VoidRet => return Ok(Control::Return(Value::Undef)),
+            CILRoot::Pop(val) => {
+                self.eval(*val, frame)?;
+            }
+            CILRoot::Throw(val) => return Err(Exception::Throw(self.eval(*val, frame)?)),
+            CILRoot::ReThrow => {
+                return Err(Exception::Throw(frame.exception.clone().ok_or_else(
+                    || Exception::Unsupported("No exception to rethrow".into()),
+                )?))
+            }
+            CILRoot::Break | CILRoot::Nop | CILRoot::SourceFileInfo { .. } => (),
+            CILRoot::Branch(branch) => {
+                let (target, sub_target, cond) = branch.as_ref();
+                let taken = match cond {
+                    Some(cond) => self.cond(cond, frame)?,
+                    None => true,
+                };
+                if taken {
+                    return Ok(Control::Branch(*target, *sub_target));
                 }
-                _ => todo!("unhandled root {root:?}", root = tree_ref.root()),
             }
-            let (_, _, tree, _) = self.call_stack.last_mut().unwrap();
-            *tree += 1;
+            CILRoot::Switch(switch) => {
+                let (value, _, targets) = switch.as_ref();
+                let (target, sub_target) = targets.target_of(self.eval(*value, frame)?.as_u64());
+                return Ok(Control::Branch(target, sub_target));
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => return Ok(Control::Leave(*target)),
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = info.as_ref();
+                let addr = self.eval(*addr, frame)?;
+                let val = self.eval(*val, frame)?;
+                let field = asm[*field];
+                let offset = self.field_offset(field.owner(), field.name())?;
+                let (alloc, base) = addr.as_ptr().ok_or(Exception::NullReference)?;
+                self.write(&field.tpe(), &Value::Ptr(alloc, base + offset), &val)?;
+            }
+            CILRoot::Call(call) => {
+                let args = self.eval_args(&call.1, frame)?;
+                self.call(call.0, args)?;
+            }
+            CILRoot::CallI(calli) => {
+                let (fn_ptr, _, args) = calli.as_ref();
+                let args = self.eval_args(args, frame)?;
+                let fn_ptr = self.eval(*fn_ptr, frame)?;
+                let mref = self.fn_ptr_target(&fn_ptr)?;
+                self.call(mref, args)?;
+            }
+            CILRoot::TailCall(call) => {
+                let args = self.eval_args(&call.1, frame)?;
+                return Ok(Control::Return(self.call(call.0, args)?));
+            }
+            CILRoot::TailCallI(calli) => {
+                let (fn_ptr, _, args) = calli.as_ref();
+                let args = self.eval_args(args, frame)?;
+                let fn_ptr = self.eval(*fn_ptr, frame)?;
+                let mref = self.fn_ptr_target(&fn_ptr)?;
+                return Ok(Control::Return(self.call(mref, args)?));
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, _) = info.as_ref();
+                let addr = self.eval(*addr, frame)?;
+                let val = self.eval(*val, frame)?;
+                self.write(tpe, &addr, &val)?;
+            }
+            CILRoot::InitBlk(blk) => {
+                let (dst, val, count) = blk.as_ref();
+                let dst = self.eval(*dst, frame)?;
+                let val = self.eval(*val, frame)?.as_u64() as u8;
+                let count = self.eval(*count, frame)?.as_usize();
+                self.mem_mut(&dst, count)?.fill(val);
+            }
+            CILRoot::CpBlk(blk) => {
+                let (dst, src, len) = blk.as_ref();
+                let dst = self.eval(*dst, frame)?;
+                let src = self.eval(*src, frame)?;
+                let len = self.eval(*len, frame)?.as_usize();
+                let src = self.mem(&src, len)?.to_vec();
+                self.mem_mut(&dst, len)?.copy_from_slice(&src);
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                let src = self.eval(*src, frame)?;
+                let dst = self.eval(*dst, frame)?;
+                let (size, _) = self.size_align(&asm[*tpe])?;
+                let src = self.mem(&src, size as usize)?.to_vec();
+                self.mem_mut(&dst, size as usize)?.copy_from_slice(&src);
+            }
+            CILRoot::SetStaticField { field, val } => {
+                let val = self.eval(*val, frame)?;
+                let addr = self.static_field(*field)?;
+                self.write(&asm[*field].tpe(), &addr, &val)?;
+            }
+            CILRoot::Unreachable(msg) => return Err(self.throw(asm[*msg].as_ref())),
         }
-        todo!()
+        Ok(Control::Next)
     }
-    fn new(asm: &'asm Assembly) -> Self {
-        Self {
-            asm,
-            call_stack: vec![],
-            locals: vec![],
-            mem: FxHashMap::with_hasher(FxBuildHasher::default()),
-            last_alloc: 1,
-            fields: FxHashMap::with_hasher(FxBuildHasher::default()),
-            methods: FxHashMap::with_hasher(FxBuildHasher::default()),
-            inv_methods: FxHashMap::with_hasher(FxBuildHasher::default()),
-            last_alloc_method: 1 << 31,
+    /// Finds all methods with the name `name`.
+    fn methods_named(&self, name: &str) -> Vec<MethodDefIdx> {
+        let asm = self.asm;
+        asm.class_defs()
+            .values()
+            .flat_map(|class| class.methods().iter())
+            .filter(|method| &asm[asm[**method].name()] == name)
+            .copied()
+            .collect()
+    }
+    /// Runs the static constructors and the entrypoint of the program, and returns its exit code.
+    fn run_program(&mut self) -> i32 {
+        let mut res = Ok(Value::Undef);
+        for cctor in self.methods_named(CCTOR) {
+            res = self.call(cctor.0, vec![]);
+            if res.is_err() {
+                break;
+            }
+        }
+        if res.is_ok() {
+            res = match self.methods_named(ENTRYPOINT).first() {
+                Some(entrypoint) => {
+                    let args = if self.asm[self.asm[*entrypoint].sig()].inputs().is_empty() {
+                        vec![]
+                    } else {
+                        let args = self.command_line_args();
+                        vec![args]
+                    };
+                    self.call(entrypoint.0, args)
+                }
+                None => {
+                    eprintln!("The assembly has no entrypoint.");
+                    return 1;
+                }
+            };
+        }
+        match res {
+            Ok(_) => 0,
+            Err(Exception::Exit(code)) => code,
+            Err(Exception::Throw(exception)) => {
+                let msg = self.string(&exception).unwrap_or_else(|_| "".into());
+                eprintln!("Unhandled exception. System.Exception: {msg}");
+                // Same as the exit code of .NET on an unhandled exception
+                134
+            }
+            Err(err) => {
+                eprintln!("Interpreter error: {err:?}");
+                1
+            }
         }
     }
-
-    fn get_memory_at_mut(&mut self, addr: Value) -> &mut [u8] {
-        let (id, offset) = addr.as_ptr().unwrap();
-        &mut self.mem.get_mut(&id).unwrap()[offset as usize..]
+    /// Returns the command line arguments, as a managed array of strings.
+    pub fn command_line_args(&mut self) -> Value {
+        let args: Vec<_> = self
+            .args
+            .clone()
+            .into_iter()
+            .map(|arg| self.alloc_obj(Object::String(arg.into())))
+            .collect();
+        self.alloc_obj(Object::Array(args.into()))
     }
 }
 fn load_asm(mut file: impl std::io::Read) -> Assembly {
     let mut asm_bytes = Vec::with_capacity(0x100);
     file.read_to_end(&mut asm_bytes)
         .expect("ERROR: Could not load the assembly file!");
-
     postcard::from_bytes(&asm_bytes).expect("ERROR:Could not decode the assembly file!")
 }
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let path = &args[1];
-    let asm = load_asm(std::fs::File::open(path).unwrap());
-    std::fs::File::create("cgraph.dot")
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(path) = args.first() else {
+        eprintln!("Usage: interpreter ASSEMBLY.cilly2 [ARGS...]");
+        std::process::exit(1);
+    };
+    let asm =
+        load_asm(std::fs::File::open(path).expect("ERROR: Could not open the assembly file!"));
+    // Calls are interpreted recursively, so deeply recursive programs need a big stack.
+    let code = std::thread::Builder::new()
+        .stack_size(1 << 30)
+        .spawn(move || InterpreterState::new(&asm, args).run_program())
         .unwrap()
-        .write_all(asm.call_graph().as_bytes())
+        .join()
         .unwrap();
-    let mut interpreter = InterpreterState::new(&asm);
-    interpreter.run_cctor().unwrap();
-    interpreter.run_entypoint().unwrap();
+    std::process::exit(code);
+}
+#[cfg(test)]
+fn test_method(
+    asm: &mut Assembly,
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    blocks: Vec<BasicBlock>,
+    locals: Vec<LocalDef>,
+) -> MethodRefIdx {
+    let main_module = asm.main_module();
+    let name = asm.alloc_string(name);
+    let sig = asm.sig(inputs, output);
+    asm.new_method(cilly::v2::MethodDef::new(
+        cilly::v2::Access::Extern,
+        main_module,
+        name,
+        sig,
+        cilly::v2::cilnode::MethodKind::Static,
+        MethodImpl::MethodBody { blocks, locals },
+        vec![None; inputs.len()],
+    ))
+    .0
+}
+#[test]
+fn arithmetic() {
+    let mut asm = Assembly::default();
+    let i32_tpe = Type::Int(cilly::v2::Int::I32);
+    // (arg0 * arg1) - 7
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let arg1 = asm.alloc_node(CILNode::LdArg(1));
+    let mul = asm.alloc_node(CILNode::BinOp(arg0, arg1, BinOp::Mul));
+    let seven = asm.alloc_node(Const::I32(7));
+    let sub = asm.alloc_node(CILNode::BinOp(mul, seven, BinOp::Sub));
+    let ret = asm.alloc_root(CILRoot::Ret(sub));
+    let method = test_method(
+        &mut asm,
+        "arithmetic",
+        &[i32_tpe, i32_tpe],
+        i32_tpe,
+        vec![BasicBlock::new(vec![ret], 0, None)],
+        vec![],
+    );
+    let mut state = InterpreterState::new(&asm, vec![]);
+    let res = state.call(method, vec![Value::I32(6), Value::I32(-3)]);
+    assert_eq!(res.unwrap(), Value::I32(-25));
+    let res = state.call(method, vec![Value::I32(i32::MAX), Value::I32(2)]);
+    assert_eq!(res.unwrap(), Value::I32(-9));
+}
+#[test]
+fn branches() {
+    let mut asm = Assembly::default();
+    let u32_tpe = Type::Int(cilly::v2::Int::U32);
+    // sum = 0; while arg0 != 0 { sum += arg0; arg0 -= 1; } return sum;
+    let zero = asm.alloc_node(Const::U32(0));
+    let one = asm.alloc_node(Const::U32(1));
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let sum = asm.alloc_node(CILNode::LdLoc(0));
+    let init = asm.alloc_root(CILRoot::StLoc(0, zero));
+    let exit = asm.alloc_root(CILRoot::Branch(Box::new((
+        2,
+        0,
+        Some(BranchCond::Eq(arg0, zero)),
+    ))));
+    let add = asm.alloc_node(CILNode::BinOp(sum, arg0, BinOp::Add));
+    let add = asm.alloc_root(CILRoot::StLoc(0, add));
+    let dec = asm.alloc_node(CILNode::BinOp(arg0, one, BinOp::Sub));
+    let dec = asm.alloc_root(CILRoot::StArg(0, dec));
+    let back = asm.alloc_root(CILRoot::Branch(Box::new((1, 0, None))));
+    let ret = asm.alloc_root(CILRoot::Ret(sum));
+    let u32_idx = asm.alloc_type(u32_tpe);
+    let method = test_method(
+        &mut asm,
+        "sum_to",
+        &[u32_tpe],
+        u32_tpe,
+        vec![
+            BasicBlock::new(vec![init], 0, None),
+            BasicBlock::new(vec![exit, add, dec, back], 1, None),
+            BasicBlock::new(vec![ret], 2, None),
+        ],
+        vec![(None, u32_idx)],
+    );
+    let mut state = InterpreterState::new(&asm, vec![]);
+    assert_eq!(
+        state.call(method, vec![Value::U32(0)]).unwrap(),
+        Value::U32(0)
+    );
+    assert_eq!(
+        state.call(method, vec![Value::U32(100)]).unwrap(),
+        Value::U32(5050)
+    );
+}
+#[test]
+fn calls() {
+    let mut asm = Assembly::default();
+    let u64_tpe = Type::Int(cilly::v2::Int::U64);
+    // factorial(arg0) = if arg0 <= 1 { 1 } else { arg0 * factorial(arg0 - 1) }
+    let main_module = asm.main_module();
+    let name = asm.alloc_string("factorial");
+    let sig = asm.sig([u64_tpe], u64_tpe);
+    let factorial = asm.alloc_methodref(cilly::v2::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        cilly::v2::cilnode::MethodKind::Static,
+        [].into(),
+    ));
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let one = asm.alloc_node(Const::U64(1));
+    let base = asm.alloc_root(CILRoot::Branch(Box::new((
+        1,
+        0,
+        Some(BranchCond::Le(arg0, one, CmpKind::Unsigned)),
+    ))));
+    let dec = asm.alloc_node(CILNode::BinOp(arg0, one, BinOp::Sub));
+    let rec = asm.alloc_node(CILNode::Call(Box::new((factorial, [dec].into()))));
+    let mul = asm.alloc_node(CILNode::BinOp(arg0, rec, BinOp::Mul));
+    let ret_mul = asm.alloc_root(CILRoot::Ret(mul));
+    let ret_one = asm.alloc_root(CILRoot::Ret(one));
+    let method = test_method(
+        &mut asm,
+        "factorial",
+        &[u64_tpe],
+        u64_tpe,
+        vec![
+            BasicBlock::new(vec![base, ret_mul], 0, None),
+            BasicBlock::new(vec![ret_one], 1, None),
+        ],
+        vec![],
+    );
+    assert_eq!(method, factorial);
+    let mut state = InterpreterState::new(&asm, vec![]);
+    assert_eq!(
+        state.call(method, vec![Value::U64(20)]).unwrap(),
+        Value::U64(2_432_902_008_176_640_000)
+    );
+    // Calls to missing methods are reported, instead of crashing the interpreter.
+    let name = asm.alloc_string("missing");
+    let missing = asm.alloc_methodref(cilly::v2::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        cilly::v2::cilnode::MethodKind::Static,
+        [].into(),
+    ));
+    let mut state = InterpreterState::new(&asm, vec![]);
+    assert!(state.call(missing, vec![Value::U64(1)]).is_err());
+}
+#[test]
+fn exceptions() {
+    let mut asm = Assembly::default();
+    let i32_tpe = Type::Int(cilly::v2::Int::I32);
+    let msg = asm.alloc_string("oops");
+    let throw = asm.alloc_root(CILRoot::Unreachable(msg));
+    let thrower = test_method(
+        &mut asm,
+        "thrower",
+        &[],
+        Type::Void,
+        vec![BasicBlock::new(vec![throw], 0, None)],
+        vec![],
+    );
+    // try { thrower(); return 0; } catch { return 1; }
+    let call = asm.alloc_root(CILRoot::Call(Box::new((thrower, [].into()))));
+    let leave = asm.alloc_root(CILRoot::ExitSpecialRegion {
+        target: 1,
+        source: 0,
+    });
+    let catch = asm.alloc_root(CILRoot::ExitSpecialRegion {
+        target: 2,
+        source: 0,
+    });
+    let zero = asm.alloc_node(Const::I32(0));
+    let one = asm.alloc_node(Const::I32(1));
+    let ret_zero = asm.alloc_root(CILRoot::Ret(zero));
+    let ret_one = asm.alloc_root(CILRoot::Ret(one));
+    let catcher = test_method(
+        &mut asm,
+        "catcher",
+        &[],
+        i32_tpe,
+        vec![
+            BasicBlock::new(
+                vec![call, leave],
+                0,
+                Some(vec![BasicBlock::new(vec![catch], 3, None)]),
+            ),
+            BasicBlock::new(vec![ret_zero], 1, None),
+            BasicBlock::new(vec![ret_one], 2, None),
+        ],
+        vec![],
+    );
+    let mut state = InterpreterState::new(&asm, vec![]);
+    assert_eq!(state.call(catcher, vec![]).unwrap(), Value::I32(1));
+    // Without a handler, the exception propagates out of the call.
+    let Err(Exception::Throw(exception)) = state.call(thrower, vec![]) else {
+        panic!("Expected an exception");
+    };
+    assert_eq!(state.string(&exception).unwrap().as_ref(), "oops");
 }
-*/
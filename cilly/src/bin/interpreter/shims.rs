@@ -0,0 +1,425 @@
+//! Implementations of methods defined outside of the interpreted assembly: .NET builtins and the `LIBC_FNS` externs.
+use std::io::Write;
+
+use cilly::v2::{BinOp, FnSig, Int, MethodRefIdx, Type};
+
+use crate::{value::Object, Exception, InterpreterState, Value};
+
+/// Creates a value of type `tpe` from an integer.
+fn int_value(tpe: &Type, bits: u128) -> Value {
+    match tpe {
+        Type::Int(int) => Value::from_int(*int, bits),
+        Type::Ptr(_) | Type::FnPtr(_) => Value::from_address(bits as u64),
+        Type::Bool => Value::Bool(bits != 0),
+        Type::Void => Value::Undef,
+        _ => Value::U64(bits as u64),
+    }
+}
+fn float_value(tpe: &Type, val: f64) -> Value {
+    match tpe {
+        Type::Float(cilly::v2::Float::F32) => Value::F32(val as f32),
+        _ => Value::F64(val),
+    }
+}
+fn as_f64(val: &Value) -> f64 {
+    match val {
+        Value::F32(val) => f64::from(*val),
+        Value::F64(val) => *val,
+        _ => panic!("{val:?} is not a float"),
+    }
+}
+/// Maps the name of a .NET math function to its implementation.
+fn math_fn(name: &str, args: &[f64]) -> Option<f64> {
+    Some(match (name, args) {
+        ("Sqrt" | "sqrt" | "sqrtf", [a]) => a.sqrt(),
+        ("Abs" | "fabs" | "fabsf", [a]) => a.abs(),
+        ("Floor" | "floor" | "floorf", [a]) => a.floor(),
+        ("Ceiling" | "ceil" | "ceilf", [a]) => a.ceil(),
+        ("Truncate" | "trunc" | "truncf", [a]) => a.trunc(),
+        ("round" | "roundf", [a]) => a.round(),
+        ("Round" | "rint" | "rintf" | "nearbyint" | "nearbyintf", [a]) => a.round_ties_even(),
+        ("Exp" | "exp" | "expf", [a]) => a.exp(),
+        ("exp2" | "exp2f", [a]) => a.exp2(),
+        ("Log" | "log" | "logf", [a]) => a.ln(),
+        ("Log2" | "log2" | "log2f", [a]) => a.log2(),
+        ("Log10" | "log10" | "log10f", [a]) => a.log10(),
+        ("Sin" | "sin" | "sinf", [a]) => a.sin(),
+        ("Cos" | "cos" | "cosf", [a]) => a.cos(),
+        ("Tan" | "tan" | "tanf", [a]) => a.tan(),
+        ("Pow" | "pow" | "powf", [a, b]) => a.powf(*b),
+        ("Min" | "fmin" | "fminf", [a, b]) => a.min(*b),
+        ("Max" | "fmax" | "fmaxf", [a, b]) => a.max(*b),
+        ("CopySign" | "copysign" | "copysignf", [a, b]) => a.copysign(*b),
+        ("fmod" | "fmodf", [a, b]) => a % b,
+        ("FusedMultiplyAdd" | "fma" | "fmaf", [a, b, c]) => a.mul_add(*b, *c),
+        _ => return None,
+    })
+}
+/// Maps the name of a .NET operator to the corresponding [`BinOp`].
+fn operator(name: &str, is_signed: bool) -> Option<BinOp> {
+    Some(match name {
+        "op_Addition" => BinOp::Add,
+        "op_Subtraction" => BinOp::Sub,
+        "op_Multiply" => BinOp::Mul,
+        "op_Division" if is_signed => BinOp::Div,
+        "op_Division" => BinOp::DivUn,
+        "op_Modulus" if is_signed => BinOp::Rem,
+        "op_Modulus" => BinOp::RemUn,
+        "op_BitwiseAnd" => BinOp::And,
+        "op_BitwiseOr" => BinOp::Or,
+        "op_ExclusiveOr" => BinOp::XOr,
+        "op_LeftShift" => BinOp::Shl,
+        "op_RightShift" if is_signed => BinOp::Shr,
+        "op_RightShift" | "op_UnsignedRightShift" => BinOp::ShrUn,
+        "op_Equality" => BinOp::Eq,
+        "op_LessThan" if is_signed => BinOp::Lt,
+        "op_LessThan" => BinOp::LtUn,
+        "op_GreaterThan" if is_signed => BinOp::Gt,
+        "op_GreaterThan" => BinOp::GtUn,
+        _ => return None,
+    })
+}
+fn write_out(fd: u64, bytes: &[u8]) -> bool {
+    match fd {
+        1 => std::io::stdout().write_all(bytes).is_ok(),
+        2 => std::io::stderr().write_all(bytes).is_ok(),
+        _ => false,
+    }
+}
+/// Calls a method, defined in some .NET assembly.
+pub fn call_builtin(
+    state: &mut InterpreterState,
+    mref: MethodRefIdx,
+    args: Vec<Value>,
+) -> Result<Value, Exception> {
+    let asm = state.asm;
+    let mref = asm.get_mref(mref);
+    let class: &str = &asm[asm.class_ref(mref.class()).name()];
+    let name: &str = &asm[mref.name()];
+    let sig = &asm[mref.sig()];
+    let output = sig.output();
+    match (class, name, &args[..]) {
+        ("System.Console", "WriteLine" | "Write", _) => {
+            let mut text = match args.first() {
+                Some(val @ Value::Obj(_)) => state.string(val)?.to_string(),
+                Some(Value::F32(val)) => val.to_string(),
+                Some(Value::F64(val)) => val.to_string(),
+                Some(Value::U16(val)) if sig.inputs()[0] == Type::PlatformChar => {
+                    char::from_u32(u32::from(*val)).unwrap_or('?').to_string()
+                }
+                Some(val) => match val.as_int() {
+                    Some((_, int)) if int.is_signed() => {
+                        (val.int_cast(Int::I128, true)?.as_int().unwrap().0 as i128).to_string()
+                    }
+                    Some(_) => val.as_u64().to_string(),
+                    None => format!("{val:?}"),
+                },
+                None => String::new(),
+            };
+            if name == "WriteLine" {
+                text.push('\n');
+            }
+            write_out(1, text.as_bytes());
+            Ok(Value::Undef)
+        }
+        ("System.Environment", "GetCommandLineArgs", []) => Ok(state.command_line_args()),
+        ("System.Environment", "Exit", [code]) => Err(Exception::Exit(code.as_u64() as i32)),
+        ("System.Environment", "FailFast", [msg]) => {
+            let msg = state.string(msg)?;
+            write_out(2, format!("{msg}\n").as_bytes());
+            Err(Exception::Exit(134))
+        }
+        ("System.Object", ".ctor", _) => Ok(Value::Undef),
+        ("System.Exception", ".ctor", [msg]) => {
+            let msg = state.string(msg)?;
+            Ok(state.alloc_obj(Object::Exception(msg)))
+        }
+        ("System.Exception", "get_Message" | "ToString", [exception]) => {
+            let msg = state.string(exception)?;
+            Ok(state.alloc_obj(Object::String(msg)))
+        }
+        ("System.String", "Concat", _) => {
+            let mut res = String::new();
+            for arg in &args {
+                res.push_str(&state.string(arg)?);
+            }
+            Ok(state.alloc_obj(Object::String(res.into())))
+        }
+        ("System.String", "op_Equality", [a, b]) => {
+            Ok(Value::Bool(state.string(a)? == state.string(b)?))
+        }
+        ("System.String", "get_Length", [string]) => Ok(Value::I32(
+            state.string(string)?.encode_utf16().count() as i32,
+        )),
+        ("System.Runtime.InteropServices.Marshal", "AllocHGlobal" | "AllocCoTaskMem", [size])
+        | ("System.Runtime.InteropServices.NativeMemory", "Alloc" | "AlignedAlloc", [size, ..]) => {
+            let alloc = state.alloc(size.as_usize());
+            Ok(int_value(
+                output,
+                u128::from(crate::value::address(alloc, 0)),
+            ))
+        }
+        ("System.Runtime.InteropServices.Marshal", "ReAllocHGlobal", [ptr, size])
+        | (
+            "System.Runtime.InteropServices.NativeMemory",
+            "Realloc" | "AlignedRealloc",
+            [ptr, size, ..],
+        ) => {
+            let ptr = state.realloc(ptr, size.as_usize())?;
+            Ok(int_value(output, u128::from(ptr.as_u64())))
+        }
+        ("System.Runtime.InteropServices.Marshal", "FreeHGlobal" | "FreeCoTaskMem", [ptr])
+        | ("System.Runtime.InteropServices.NativeMemory", "Free" | "AlignedFree", [ptr]) => {
+            state.free(ptr)?;
+            Ok(Value::Undef)
+        }
+        ("System.Runtime.InteropServices.Marshal", "StringToCoTaskMemUTF8", [string]) => {
+            let string = state.string(string)?;
+            let alloc = state.alloc(string.len() + 1);
+            let ptr = Value::Ptr(alloc, 0);
+            state
+                .mem_mut(&ptr, string.len())?
+                .copy_from_slice(string.as_bytes());
+            Ok(int_value(output, u128::from(ptr.as_u64())))
+        }
+        ("System.Runtime.InteropServices.Marshal", "PtrToStringUTF8", [ptr, rest @ ..]) => {
+            let bytes = match rest {
+                [len] => state.mem(ptr, len.as_usize())?.to_vec(),
+                _ => state.c_str(ptr)?,
+            };
+            let string = String::from_utf8_lossy(&bytes).to_string();
+            Ok(state.alloc_obj(Object::String(string.into())))
+        }
+        (
+            "System.Runtime.InteropServices.Marshal",
+            "GetLastPInvokeError" | "GetLastWin32Error",
+            [],
+        ) => {
+            let errno = state.errno();
+            state.read(&Type::Int(Int::I32), &errno)
+        }
+        ("System.Runtime.InteropServices.Marshal", "SetLastPInvokeError", [val]) => {
+            let errno = state.errno();
+            state.write(&Type::Int(Int::I32), &errno, val)?;
+            Ok(Value::Undef)
+        }
+        ("System.Threading.Interlocked" | "System.Threading.Thread", "MemoryBarrier", [])
+        | ("System.Threading.Thread", "Yield" | "Sleep", _) => Ok(Value::Undef),
+        ("System.Threading.Interlocked", _, [addr, rest @ ..]) => {
+            // Only one thread is ever running, so the operations don't need to be atomic.
+            let Type::Ref(tpe) = sig.inputs()[0] else {
+                return Err(Exception::Unsupported(format!(
+                    "Interlocked::{name} with signature {sig:?}"
+                )));
+            };
+            let tpe = asm[tpe];
+            let old = state.read(&tpe, addr)?;
+            let (new, res) = match (name, rest) {
+                ("CompareExchange", [val, comparand]) => {
+                    if old.binop(comparand, BinOp::Eq)?.is_true() {
+                        (val.clone(), old.clone())
+                    } else {
+                        (old.clone(), old.clone())
+                    }
+                }
+                ("Exchange", [val]) => (val.clone(), old.clone()),
+                ("Add", [val]) => {
+                    let new = old.binop(val, BinOp::Add)?;
+                    (new.clone(), new)
+                }
+                ("Increment" | "Decrement", []) => {
+                    let op = if name == "Increment" {
+                        BinOp::Add
+                    } else {
+                        BinOp::Sub
+                    };
+                    let one = Value::from_int(old.as_int().map_or(Int::I32, |(_, int)| int), 1);
+                    let new = old.binop(&one, op)?;
+                    (new.clone(), new)
+                }
+                ("Or", [val]) => (old.binop(val, BinOp::Or)?, old.clone()),
+                ("And", [val]) => (old.binop(val, BinOp::And)?, old.clone()),
+                _ => {
+                    return Err(Exception::MethodNotFound(format!(
+                        "System.Threading.Interlocked::{name}"
+                    )))
+                }
+            };
+            state.write(&tpe, addr, &new)?;
+            Ok(res)
+        }
+        ("System.Math" | "System.MathF", _, _)
+            if args
+                .iter()
+                .all(|arg| matches!(arg, Value::F32(_) | Value::F64(_))) =>
+        {
+            let floats: Vec<f64> = args.iter().map(as_f64).collect();
+            math_fn(name, &floats)
+                .map(|res| float_value(output, res))
+                .ok_or_else(|| Exception::MethodNotFound(format!("{class}::{name}")))
+        }
+        ("System.Double" | "System.Single", _, [val]) => {
+            let val = as_f64(val);
+            match name {
+                "IsNaN" => Ok(Value::Bool(val.is_nan())),
+                "IsInfinity" => Ok(Value::Bool(val.is_infinite())),
+                "IsFinite" => Ok(Value::Bool(val.is_finite())),
+                "IsNegative" => Ok(Value::Bool(val.is_sign_negative())),
+                _ => Err(Exception::MethodNotFound(format!("{class}::{name}"))),
+            }
+        }
+        ("System.UInt128" | "System.Int128", ".ctor", [upper, lower]) => {
+            let bits = (u128::from(upper.as_u64()) << 64) | u128::from(lower.as_u64());
+            Ok(if class == "System.Int128" {
+                Value::I128(bits as i128)
+            } else {
+                Value::U128(bits)
+            })
+        }
+        (
+            "System.UInt128" | "System.Int128",
+            "op_Explicit" | "op_Implicit" | "op_CheckedExplicit",
+            [val],
+        ) => {
+            let is_signed = matches!(
+                val,
+                Value::I8(_)
+                    | Value::I16(_)
+                    | Value::I32(_)
+                    | Value::I64(_)
+                    | Value::I128(_)
+                    | Value::ISize(_)
+            );
+            match output {
+                Type::Int(int) => val.int_cast(*int, is_signed),
+                Type::Float(_) => Ok(float_value(output, val.to_float(is_signed)?)),
+                Type::ClassRef(cref) => {
+                    let target: &str = &asm[asm.class_ref(*cref).name()];
+                    let bits = match val {
+                        Value::F32(_) | Value::F64(_) => {
+                            if target == "System.Int128" {
+                                as_f64(val) as i128 as u128
+                            } else {
+                                as_f64(val) as u128
+                            }
+                        }
+                        _ => val.int_cast(Int::U128, is_signed)?.as_int().unwrap().0,
+                    };
+                    Ok(if target == "System.Int128" {
+                        Value::I128(bits as i128)
+                    } else {
+                        Value::U128(bits)
+                    })
+                }
+                _ => Err(Exception::Unsupported(format!(
+                    "{class}::{name} to {output:?}"
+                ))),
+            }
+        }
+        ("System.UInt128" | "System.Int128", "op_UnaryNegation", [val]) => {
+            val.unop(cilly::v2::cilnode::UnOp::Neg)
+        }
+        ("System.UInt128" | "System.Int128", "op_OnesComplement", [val]) => {
+            val.unop(cilly::v2::cilnode::UnOp::Not)
+        }
+        ("System.UInt128" | "System.Int128", _, [lhs, rhs]) => {
+            let Some(op) = operator(name, class == "System.Int128") else {
+                return Err(Exception::MethodNotFound(format!("{class}::{name}")));
+            };
+            // Shift amounts are always `int`s
+            let rhs = if matches!(op, BinOp::Shl | BinOp::Shr | BinOp::ShrUn) {
+                rhs.int_cast(Int::U128, false)?
+            } else {
+                rhs.clone()
+            };
+            match lhs.binop(&rhs, op) {
+                Err(Exception::DivideByZero) => Err(state.throw("Attempted to divide by zero.")),
+                res => res,
+            }
+        }
+        _ => Err(Exception::MethodNotFound(format!("{class}::{name}"))),
+    }
+}
+/// Calls a function from libc or libm.
+pub fn call_libc(
+    state: &mut InterpreterState,
+    name: &str,
+    sig: &FnSig,
+    args: Vec<Value>,
+) -> Result<Value, Exception> {
+    let output = sig.output();
+    match (name, &args[..]) {
+        ("malloc", [size]) => {
+            let alloc = state.alloc(size.as_usize());
+            Ok(int_value(
+                output,
+                u128::from(crate::value::address(alloc, 0)),
+            ))
+        }
+        ("calloc", [count, size]) => {
+            let alloc = state.alloc(count.as_usize() * size.as_usize());
+            Ok(int_value(
+                output,
+                u128::from(crate::value::address(alloc, 0)),
+            ))
+        }
+        ("realloc", [ptr, size]) => {
+            let ptr = state.realloc(ptr, size.as_usize())?;
+            Ok(int_value(output, u128::from(ptr.as_u64())))
+        }
+        ("free", [ptr]) => {
+            state.free(ptr)?;
+            Ok(Value::Undef)
+        }
+        ("memcpy" | "memmove", [dst, src, len]) => {
+            let src = state.mem(src, len.as_usize())?.to_vec();
+            state.mem_mut(dst, len.as_usize())?.copy_from_slice(&src);
+            Ok(dst.clone())
+        }
+        ("memset", [dst, val, len]) => {
+            state.mem_mut(dst, len.as_usize())?.fill(val.as_u64() as u8);
+            Ok(dst.clone())
+        }
+        ("memcmp" | "bcmp", [a, b, len]) => {
+            let a = state.mem(a, len.as_usize())?;
+            let b = state.mem(b, len.as_usize())?;
+            let res = a
+                .iter()
+                .zip(b)
+                .find(|(a, b)| a != b)
+                .map_or(0, |(a, b)| i32::from(*a) - i32::from(*b));
+            Ok(int_value(output, res as u128))
+        }
+        ("strlen", [string]) => Ok(int_value(output, state.c_str(string)?.len() as u128)),
+        ("write", [fd, buf, len]) => {
+            let bytes = state.mem(buf, len.as_usize())?.to_vec();
+            if write_out(fd.as_u64(), &bytes) {
+                Ok(int_value(output, bytes.len() as u128))
+            } else {
+                Ok(int_value(output, -1_i128 as u128))
+            }
+        }
+        ("puts", [string]) => {
+            let mut bytes = state.c_str(string)?;
+            bytes.push(b'\n');
+            write_out(1, &bytes);
+            Ok(int_value(output, 0))
+        }
+        ("__errno_location", []) => Ok(state.errno()),
+        ("getenv", [_]) => Ok(int_value(output, 0)),
+        ("getpid", []) => Ok(int_value(output, u128::from(std::process::id()))),
+        ("isatty", [_]) => Ok(int_value(output, 0)),
+        ("abort", []) => Err(Exception::Exit(134)),
+        ("exit" | "_exit", [code]) => Err(Exception::Exit(code.as_u64() as i32)),
+        _ if args
+            .iter()
+            .all(|arg| matches!(arg, Value::F32(_) | Value::F64(_))) =>
+        {
+            let floats: Vec<f64> = args.iter().map(as_f64).collect();
+            math_fn(name, &floats)
+                .map(|res| float_value(output, res))
+                .ok_or_else(|| Exception::MethodNotFound(name.to_owned()))
+        }
+        _ => Err(Exception::MethodNotFound(name.to_owned())),
+    }
+}
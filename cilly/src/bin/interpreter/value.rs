@@ -1,13 +1,14 @@
-use cilly::{IString, Type};
+use cilly::{
+    v2::{cilnode::UnOp, BinOp, Int},
+    IString,
+};
 
-use crate::{AllocID, InterpreterState};
+use crate::{AllocID, Exception, ObjID};
 
 #[derive(Clone, Debug, PartialEq)]
 #[allow(clippy::enum_variant_names)]
 pub enum Value {
     Undef,
-    StringArray(Box<[IString]>),
-    String(IString),
     USize(usize),
     ISize(isize),
     I8(i8),
@@ -20,130 +21,271 @@ pub enum Value {
     U64(u64),
     I128(i128),
     U128(u128),
+    /// A pointer to `offset` bytes into the allocation `AllocID`.
     Ptr(AllocID, u32),
     Bool(bool),
     F32(f32),
     F64(f64),
-    ValueType(AllocID),
+    /// A reference to a managed object. `ObjID` 0 is the null reference.
+    Obj(ObjID),
+    /// The bytes of a value of a struct type.
+    ValueType(Box<[u8]>),
+}
+/// A managed object, living outside of the byte-addressed heap.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Object {
+    String(IString),
+    Array(Box<[Value]>),
+    Exception(IString),
+}
+/// Returns the width of `int`, in bits.
+pub fn int_bits(int: Int) -> u32 {
+    int.size().map_or(usize::BITS, |size| u32::from(size) * 8)
+}
+fn mask(bits: u128, int: Int) -> u128 {
+    match int_bits(int) {
+        128 => bits,
+        width => bits & ((1 << width) - 1),
+    }
+}
+fn sign_extend(bits: u128, int: Int) -> i128 {
+    let shift = 128 - int_bits(int);
+    ((bits << shift) as i128) >> shift
 }
-
 impl Value {
-    pub fn default_for_type(tpe: &Type, state: &mut InterpreterState) -> Value {
-        match tpe {
-            Type::ClassRef(tpe) => {
-                let size = state.asm.sizeof_tpedef(tpe.as_ref());
-                Value::ValueType(state.alloc(size.get() as usize))
-            }
-            _ => Value::Undef,
+    /// Creates a pointer value from its numeric address.
+    pub fn from_address(addr: u64) -> Self {
+        Self::Ptr((addr >> 32) as AllocID, addr as u32)
+    }
+    /// Creates an integer of type `int`, truncating `bits` to fit.
+    pub fn from_int(int: Int, bits: u128) -> Self {
+        match int {
+            Int::U8 => Self::U8(bits as u8),
+            Int::U16 => Self::U16(bits as u16),
+            Int::U32 => Self::U32(bits as u32),
+            Int::U64 => Self::U64(bits as u64),
+            Int::U128 => Self::U128(bits),
+            Int::USize => Self::USize(bits as usize),
+            Int::I8 => Self::I8(bits as i8),
+            Int::I16 => Self::I16(bits as i16),
+            Int::I32 => Self::I32(bits as i32),
+            Int::I64 => Self::I64(bits as i64),
+            Int::I128 => Self::I128(bits as i128),
+            Int::ISize => Self::ISize(bits as isize),
         }
     }
-    pub fn set(&mut self, other: Self, state: &mut InterpreterState) {
-        let _ = state;
+    /// Returns the raw bits and the type of an integer-like value. Pointers are treated as `usize`s, and bools as `u8`s.
+    pub fn as_int(&self) -> Option<(u128, Int)> {
+        Some(match self {
+            Self::USize(val) => (*val as u128, Int::USize),
+            Self::ISize(val) => (*val as u128, Int::ISize),
+            Self::I8(val) => (*val as u128, Int::I8),
+            Self::U8(val) => (u128::from(*val), Int::U8),
+            Self::I16(val) => (*val as u128, Int::I16),
+            Self::U16(val) => (u128::from(*val), Int::U16),
+            Self::I32(val) => (*val as u128, Int::I32),
+            Self::U32(val) => (u128::from(*val), Int::U32),
+            Self::I64(val) => (*val as u128, Int::I64),
+            Self::U64(val) => (u128::from(*val), Int::U64),
+            Self::I128(val) => (*val as u128, Int::I128),
+            Self::U128(val) => (*val, Int::U128),
+            Self::Ptr(alloc, offset) => (u128::from(address(*alloc, *offset)), Int::USize),
+            Self::Bool(val) => (u128::from(*val), Int::U8),
+            _ => return None,
+        })
+    }
+    /// The value, zero-extended to an u64. Panics if the value is not integer-like.
+    pub fn as_u64(&self) -> u64 {
+        let (bits, int) = self
+            .as_int()
+            .unwrap_or_else(|| panic!("{self:?} is not an integer"));
+        mask(bits, int) as u64
+    }
+    pub fn as_usize(&self) -> usize {
+        self.as_u64() as usize
+    }
+    /// Converts an integer-like value into a pointer.
+    pub fn as_ptr(&self) -> Option<(AllocID, u32)> {
         match self {
-            Value::Undef
-            | Value::StringArray(_)
-            | Value::String(_)
-            | Value::USize(_)
-            | Value::ISize(_)
-            | Value::I8(_)
-            | Value::U8(_)
-            | Value::I16(_)
-            | Value::U16(_)
-            | Value::I32(_)
-            | Value::U32(_)
-            | Value::I64(_)
-            | Value::U64(_)
-            | Value::I128(_)
-            | Value::U128(_)
-            | Value::Ptr(_, _)
-            | Value::Bool(_)
-            | Value::F32(_)
-            | Value::F64(_) => *self = other,
-            Value::ValueType(_alloc) => todo!("Can't yet assign a value of type ValueType"),
+            Self::Ptr(alloc, offset) => Some((*alloc, *offset)),
+            _ => {
+                let (bits, int) = self.as_int()?;
+                match Self::from_address(mask(bits, int) as u64) {
+                    Self::Ptr(alloc, offset) => Some((alloc, offset)),
+                    _ => unreachable!(),
+                }
+            }
         }
     }
-    pub fn pass_as_arg(self, state: &mut InterpreterState) -> Self {
-        let _ = state;
+    /// Checks if this value is "true", according to the rules of `brtrue`.
+    pub fn is_true(&self) -> bool {
         match self {
-            Value::Undef
-            | Value::StringArray(_)
-            | Value::String(_)
-            | Value::USize(_)
-            | Value::ISize(_)
-            | Value::I8(_)
-            | Value::U8(_)
-            | Value::I16(_)
-            | Value::U16(_)
-            | Value::I32(_)
-            | Value::U32(_)
-            | Value::I64(_)
-            | Value::U64(_)
-            | Value::I128(_)
-            | Value::U128(_)
-            | Value::Ptr(_, _)
-            | Value::Bool(_)
-            | Value::F32(_)
-            | Value::F64(_) => self.clone(),
-            Value::ValueType(_alloc) => todo!("Can't yet pass a valuetype as an arg"),
+            Self::Bool(val) => *val,
+            Self::Obj(obj) => *obj != 0,
+            _ => self.as_u64() != 0,
         }
     }
-    pub fn as_usize(&self) -> Option<usize> {
-        if let Self::USize(v) = self {
-            Some(*v)
-        } else if let Self::ISize(v) = self {
-            Some(*v as usize)
-        } else if let Self::I32(v) = self {
-            Some(*v as isize as usize)
-        } else if let Self::U32(v) = self {
-            Some(*v as usize)
-        } else if let Self::Ptr(alloc, offset) = self {
-            Some((((*alloc as u64) << (32)) + *offset as u64) as usize)
-        } else {
-            None
-        }
+    /// Encodes this value into `bytes`.
+    pub fn write(&self, bytes: &mut [u8]) {
+        let encoded = match self {
+            Self::Undef => return,
+            Self::F32(val) => val.to_le_bytes().to_vec(),
+            Self::F64(val) => val.to_le_bytes().to_vec(),
+            Self::Obj(obj) => u64::from(*obj).to_le_bytes().to_vec(),
+            Self::ValueType(val) => val.to_vec(),
+            _ => {
+                let (bits, _) = self.as_int().unwrap();
+                bits.to_le_bytes().to_vec()
+            }
+        };
+        let len = bytes.len().min(encoded.len());
+        bytes[..len].copy_from_slice(&encoded[..len]);
     }
-    pub fn as_ptr(&self) -> Option<(AllocID, u32)> {
-        if let Self::Ptr(id, offset) = self {
-            Some((*id, *offset))
-        } else {
-            None
+    pub fn binop(&self, rhs: &Self, op: BinOp) -> Result<Self, Exception> {
+        match (self, rhs) {
+            (Self::F32(a), Self::F32(b)) => float_binop(f64::from(*a), f64::from(*b), op, true),
+            (Self::F64(a), Self::F64(b)) => float_binop(*a, *b, op, false),
+            (Self::Bool(a), Self::Bool(b)) => match op {
+                BinOp::And => Ok(Self::Bool(a & b)),
+                BinOp::Or => Ok(Self::Bool(a | b)),
+                BinOp::XOr => Ok(Self::Bool(a ^ b)),
+                BinOp::Eq => Ok(Self::Bool(a == b)),
+                _ => self.int_binop(rhs, op),
+            },
+            (Self::Obj(a), Self::Obj(b)) if op == BinOp::Eq => Ok(Self::Bool(a == b)),
+            _ => self.int_binop(rhs, op),
         }
     }
-
-    pub fn as_string(&self) -> Option<&IString> {
-        if let Self::String(v) = self {
-            Some(v)
-        } else {
-            None
+    fn int_binop(&self, rhs: &Self, op: BinOp) -> Result<Self, Exception> {
+        let (Some((a, a_int)), Some((b, b_int))) = (self.as_int(), rhs.as_int()) else {
+            return Err(Exception::InvalidOperands(format!(
+                "{self:?} {op:?} {rhs:?}"
+            )));
+        };
+        // Mixing an int32 and a native int gives a native int.
+        let int = match (a_int, b_int) {
+            (Int::USize | Int::ISize, _) => a_int,
+            (_, Int::USize | Int::ISize) if a_int.size() != Some(16) => b_int,
+            _ => a_int,
+        };
+        let (ua, ub) = (mask(a, int), mask(b, int));
+        let (sa, sb) = (sign_extend(a, int), sign_extend(b, int));
+        let shift = (b as u32) & (int_bits(int) - 1);
+        let bits = match op {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Sub => a.wrapping_sub(b),
+            BinOp::Mul => a.wrapping_mul(b),
+            BinOp::Div => sa.checked_div(sb).ok_or(Exception::DivideByZero)? as u128,
+            BinOp::Rem => sa.checked_rem(sb).ok_or(Exception::DivideByZero)? as u128,
+            BinOp::DivUn => ua.checked_div(ub).ok_or(Exception::DivideByZero)?,
+            BinOp::RemUn => ua.checked_rem(ub).ok_or(Exception::DivideByZero)?,
+            BinOp::Or => a | b,
+            BinOp::XOr => a ^ b,
+            BinOp::And => a & b,
+            BinOp::Shl => a << shift,
+            BinOp::Shr => (sa >> shift) as u128,
+            BinOp::ShrUn => ua >> shift,
+            BinOp::Eq => return Ok(Self::Bool(ua == ub)),
+            BinOp::Lt => return Ok(Self::Bool(sa < sb)),
+            BinOp::Gt => return Ok(Self::Bool(sa > sb)),
+            BinOp::LtUn => return Ok(Self::Bool(ua < ub)),
+            BinOp::GtUn => return Ok(Self::Bool(ua > ub)),
+        };
+        // Pointer arithmetic keeps the provenance of the pointer.
+        match (self, rhs, op) {
+            (Self::Ptr(..), Self::Ptr(..), BinOp::Sub) => {
+                Ok(Self::from_int(Int::ISize, mask(bits, int)))
+            }
+            (Self::Ptr(..), _, _) | (_, Self::Ptr(..), BinOp::Add) => {
+                Ok(Self::from_address(mask(bits, int) as u64))
+            }
+            _ => Ok(Self::from_int(int, bits)),
         }
     }
-
-    pub fn as_bool(&self) -> Option<bool> {
-        if let Self::Bool(v) = self {
-            Some(*v)
-        } else {
-            None
+    pub fn unop(&self, op: UnOp) -> Result<Self, Exception> {
+        match (self, &op) {
+            (Self::F32(val), UnOp::Neg) => Ok(Self::F32(-val)),
+            (Self::F64(val), UnOp::Neg) => Ok(Self::F64(-val)),
+            (Self::Bool(val), UnOp::Not) => Ok(Self::Bool(!val)),
+            _ => {
+                let Some((bits, int)) = self.as_int() else {
+                    return Err(Exception::InvalidOperands(format!("{op:?} {self:?}")));
+                };
+                match op {
+                    UnOp::Not => Ok(Self::from_int(int, !bits)),
+                    UnOp::Neg => Ok(Self::from_int(int, bits.wrapping_neg())),
+                }
+            }
         }
     }
-
-    pub fn as_u64(&self) -> Option<&u64> {
-        if let Self::U64(v) = self {
-            Some(v)
-        } else {
-            None
+    /// Converts this value into an integer of type `target`, either zero or sign extending it.
+    pub fn int_cast(&self, target: Int, sign_extend_input: bool) -> Result<Self, Exception> {
+        let bits = match self {
+            Self::F32(val) if sign_extend_input => *val as i128 as u128,
+            Self::F32(val) => *val as u128,
+            Self::F64(val) if sign_extend_input => *val as i128 as u128,
+            Self::F64(val) => *val as u128,
+            _ => {
+                let Some((bits, int)) = self.as_int() else {
+                    return Err(Exception::InvalidOperands(format!(
+                        "Can't cast {self:?} to {target:?}"
+                    )));
+                };
+                if sign_extend_input {
+                    sign_extend(bits, int) as u128
+                } else {
+                    mask(bits, int)
+                }
+            }
+        };
+        Ok(Self::from_int(target, bits))
+    }
+    /// Converts this value into a float. Returns a f64, which needs to be rounded to a f32 by the caller if needed.
+    pub fn to_float(&self, is_signed: bool) -> Result<f64, Exception> {
+        match self {
+            Self::F32(val) => Ok(f64::from(*val)),
+            Self::F64(val) => Ok(*val),
+            _ => {
+                let Some((bits, int)) = self.as_int() else {
+                    return Err(Exception::InvalidOperands(format!(
+                        "Can't cast {self:?} to a float"
+                    )));
+                };
+                if is_signed {
+                    Ok(sign_extend(bits, int) as f64)
+                } else {
+                    Ok(mask(bits, int) as f64)
+                }
+            }
         }
     }
-
-    pub fn as_i8(&self) -> Option<i8> {
-        if let Self::I8(v) = self {
-            Some(*v)
-        } else if let Self::U8(v) = self {
-            Some(*v as i8)
-        } else if let Self::U32(v) = self {
-            assert!(*v < 256);
-            Some(*v as u8 as i8)
-        } else {
-            None
+}
+/// The numeric address of a pointer.
+pub fn address(alloc: AllocID, offset: u32) -> u64 {
+    (u64::from(alloc) << 32) | u64::from(offset)
+}
+fn float_binop(a: f64, b: f64, op: BinOp, is_f32: bool) -> Result<Value, Exception> {
+    let res = match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div | BinOp::DivUn => a / b,
+        BinOp::Rem | BinOp::RemUn => a % b,
+        BinOp::Eq => return Ok(Value::Bool(a == b)),
+        BinOp::Lt => return Ok(Value::Bool(a < b)),
+        BinOp::Gt => return Ok(Value::Bool(a > b)),
+        // Unordered comparisons are true if either value is NaN
+        BinOp::LtUn => return Ok(Value::Bool(a < b || a.is_nan() || b.is_nan())),
+        BinOp::GtUn => return Ok(Value::Bool(a > b || a.is_nan() || b.is_nan())),
+        _ => {
+            return Err(Exception::InvalidOperands(format!(
+                "Can't preform {op:?} on floats"
+            )))
         }
+    };
+    if is_f32 {
+        Ok(Value::F32(res as f32))
+    } else {
+        Ok(Value::F64(res))
     }
 }
@@ -62,6 +62,6 @@ fn main(){{
     }}
     let args:Vec<String> = std::env::args().collect();
     let args = &args[1..];
-    std::process::Command::new("{jumpstart_cmd}").arg(dll_path).args(args).status().expect("Could not start the .NET runtime.");
+    std::process::Command::new("{jumpstart_cmd}").args(&[{jumpstart_args}] as &[&str]).arg(dll_path).args(args).status().expect("Could not start the .NET runtime.");
 }}
 
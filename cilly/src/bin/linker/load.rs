@@ -1,7 +1,12 @@
 use ar::Archive;
 
 use cilly::IString;
-use std::io::Read;
+use sha2::{Digest as _, Sha256};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 pub struct LinkableFile {
     name: IString,
     file: Box<[u8]>,
@@ -20,10 +25,163 @@ impl LinkableFile {
         &self.file
     }
 }
+/// A SHA-256 digest.
+type Digest = [u8; 32];
+/// A serialized assembly, which has not been decoded yet.
+struct AssemblyFile {
+    name: String,
+    bytes: Box<[u8]>,
+    hash: Digest,
+}
+impl AssemblyFile {
+    fn new(name: String, bytes: Box<[u8]>) -> Self {
+        let hash = Sha256::digest(&bytes).into();
+        Self { name, bytes, hash }
+    }
+    fn decode(&self) -> cilly::v2::Assembly {
+        postcard::from_bytes(&self.bytes)
+            .unwrap_or_else(|_| panic!("ERROR:Could not decode the assembly file {}!", self.name))
+    }
+}
+/// Caches the partially linked assemblies on disk, keyed by a SHA-256 digest of the files they were built from.
+/// Once the cache grows past `max_size` bytes, the least recently used entries are removed.
+struct LinkCache<'a> {
+    dir: Option<&'a Path>,
+    /// Mixed into every key, so that entries written by a different linker build are never used.
+    seed: Digest,
+    max_size: u64,
+}
+impl<'a> LinkCache<'a> {
+    fn new(dir: Option<&'a Path>, max_size: u64) -> Self {
+        if let Some(dir) = dir {
+            std::fs::create_dir_all(dir).expect("ERROR: Could not create the link cache");
+        }
+        let exe = std::env::current_exe()
+            .and_then(std::fs::metadata)
+            .ok()
+            .map(|meta| (meta.len(), meta.modified().ok()));
+        let seed = format!("{} {exe:?}", env!("CARGO_PKG_VERSION"));
+        Self {
+            dir,
+            seed: Sha256::digest(seed).into(),
+            max_size,
+        }
+    }
+    /// Returns the key of a linked range of files.
+    fn key(&self, files: &[AssemblyFile]) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed);
+        for file in files {
+            hasher.update(file.hash);
+        }
+        hasher.finalize().into()
+    }
+    fn path(&self, key: &Digest) -> Option<PathBuf> {
+        let name: String = key.iter().map(|byte| format!("{byte:02x}")).collect();
+        self.dir.map(|dir| dir.join(name).with_extension("cilly"))
+    }
+    fn get(&self, key: &Digest) -> Option<cilly::v2::Assembly> {
+        let path = self.path(key)?;
+        let bytes = std::fs::read(&path).ok()?;
+        // A corrupted entry is just a cache miss, and gets overwritten once the range is linked again.
+        let asm = postcard::from_bytes(&bytes).ok()?;
+        // Marks the entry as recently used, so that it is evicted last.
+        let _ = std::fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(asm)
+    }
+    fn put(&self, key: &Digest, asm: &cilly::v2::Assembly) {
+        let Some(path) = self.path(key) else {
+            return;
+        };
+        // The entry is renamed into place, so that concurrent linkers never see a partially written file.
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let written = std::fs::File::create(&tmp)
+            .and_then(|mut file| asm.save_tmp(&mut file))
+            .and_then(|()| std::fs::rename(&tmp, &path));
+        if let Err(err) = written {
+            eprintln!("WARNING: Could not cache a linked assembly: {err}");
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+    /// Removes the least recently used entries, until the cache fits within its size limit.
+    fn evict(&self) {
+        let Some(entries) = self.dir.and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "cilly" {
+                    return None;
+                }
+                let meta = std::fs::metadata(&path).ok()?;
+                Some((meta.modified().ok()?, meta.len(), path))
+            })
+            .collect();
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_unstable();
+        for (_, len, path) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if std::fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
+/// Links `files` together, in order, linking both halves of the range recursively on up to `threads` threads, and then
+/// merging them. The order matters: the static initializers of the files are merged in it, and so are diverging
+/// definitions of the same method. The range is split before the file with the greatest hash, so the splits form a
+/// treap over the files. Since where a range is split only depends on the files in it, adding, removing or changing
+/// one file only requires re-linking the ranges containing it, and the rest can be loaded from the cache.
+fn link_tree(files: &[AssemblyFile], cache: &LinkCache, threads: usize) -> cilly::v2::Assembly {
+    match files {
+        [] => return cilly::v2::Assembly::default(),
+        [file] => return file.decode(),
+        _ => (),
+    }
+    let key = cache.key(files);
+    if let Some(asm) = cache.get(&key) {
+        return asm;
+    }
+    // The first file can't be a split point, since that would leave the left half empty. Ties between identical
+    // files go to the first one.
+    let split = files[1..]
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, file)| file.hash)
+        .map(|(idx, _)| idx + 1)
+        .expect("ERROR: A range of files to link was empty");
+    let (left, right) = files.split_at(split);
+    let (left, right) = if threads > 1 {
+        std::thread::scope(|scope| {
+            let left = scope.spawn(|| link_tree(left, cache, threads / 2));
+            let right = link_tree(right, cache, threads - threads / 2);
+            (left.join().expect("ERROR: Linking failed"), right)
+        })
+    } else {
+        (link_tree(left, cache, 1), link_tree(right, cache, 1))
+    };
+    let asm = left.link(right);
+    cache.put(&key, &asm);
+    asm
+}
+/// Links all the assembly `files` together, in the order they were given, caching the partial results in `cache`.
+fn link_files(files: &[AssemblyFile], cache: &LinkCache) -> cilly::v2::Assembly {
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let asm = link_tree(files, cache, threads);
+    cache.evict();
+    asm
+}
 fn load_ar(
     r: &mut impl std::io::Read,
-) -> std::io::Result<(cilly::v2::Assembly, Vec<LinkableFile>)> {
-    let mut final_assembly = cilly::v2::Assembly::default();
+    files: &mut Vec<AssemblyFile>,
+) -> std::io::Result<Vec<LinkableFile>> {
     let mut archive = Archive::new(r);
     let mut linkables = Vec::new();
     // Iterate over all entries in the archive:
@@ -40,9 +198,7 @@ fn load_ar(
             entry
                 .read_to_end(&mut asm_bytes)
                 .expect("ERROR: Could not load the assembly file!");
-            let assembly = postcard::from_bytes(&asm_bytes)
-                .unwrap_or_else(|_| panic!("ERROR:Could not decode the assembly file {name}!"));
-            final_assembly = final_assembly.link(assembly);
+            files.push(AssemblyFile::new(name, asm_bytes.into()));
         } else if ext.contains("o") {
             let mut file_bytes = Vec::with_capacity(0x100);
             entry
@@ -53,34 +209,196 @@ fn load_ar(
             eprintln!("shr:{name}");
         }
     }
-    Ok((final_assembly, linkables))
+    Ok(linkables)
 }
+/// Loads and links all the assemblies in `raw_files` and `archives`. If `cache` is set, partially linked assemblies
+/// are stored in that directory, which is kept under `cache_size` bytes, and reused by later links.
 pub fn load_assemblies(
     raw_files: &[&String],
     archives: &[String],
+    cache: Option<&Path>,
+    cache_size: u64,
 ) -> (cilly::v2::Assembly, Vec<LinkableFile>) {
     println!("Preparing to load assmeblies");
-    let mut final_assembly = cilly::v2::Assembly::default();
+    let mut files = Vec::new();
     let mut linkables = Vec::new();
     for asm_path in raw_files {
-        let mut asm_file =
-            std::fs::File::open(asm_path).expect("ERROR:Could not open the assembly file!");
-        let mut asm_bytes = Vec::with_capacity(0x10000);
-        asm_file
-            .read_to_end(&mut asm_bytes)
-            .expect("ERROR: Could not load the assembly file!");
-        let asm: cilly::v2::Assembly =
-            postcard::from_bytes(&asm_bytes).expect("ERROR:Could not decode the assembly file!");
-
-        final_assembly = final_assembly.link(asm);
+        let asm_bytes = std::fs::read(asm_path).expect("ERROR: Could not load the assembly file!");
+        files.push(AssemblyFile::new(asm_path.to_string(), asm_bytes.into()));
     }
     for asm_path in archives {
         let mut asm_file =
             std::fs::File::open(asm_path).expect("ERROR: Could not open the assembly file!");
-        let (asm, linkable) = load_ar(&mut asm_file).expect("Could not open archive");
-        final_assembly = final_assembly.link(asm);
+        let linkable = load_ar(&mut asm_file, &mut files).expect("Could not open archive");
         linkables.extend(linkable);
     }
+    let final_assembly = link_files(&files, &LinkCache::new(cache, cache_size));
     println!("Loaded assmeblies");
     (final_assembly, linkables)
 }
+#[cfg(test)]
+/// Returns assembly files defining a single class each.
+fn test_files(classes: &[&str]) -> Vec<AssemblyFile> {
+    use cilly::v2::{Access, ClassDef};
+    classes
+        .iter()
+        .map(|class| {
+            let mut asm = cilly::v2::Assembly::default();
+            let name = asm.alloc_string(*class);
+            asm.class_def(ClassDef::new(
+                name,
+                false,
+                0,
+                None,
+                vec![],
+                vec![],
+                Access::Public,
+                None,
+            ));
+            AssemblyFile::new((*class).into(), postcard::to_stdvec(&asm).unwrap().into())
+        })
+        .collect()
+}
+#[cfg(test)]
+fn class_names(asm: &cilly::v2::Assembly) -> Vec<String> {
+    let mut names: Vec<_> = asm
+        .class_defs()
+        .values()
+        .map(|def| asm[def.name()].to_string())
+        .collect();
+    names.sort();
+    names
+}
+#[cfg(test)]
+/// Returns an empty directory for a test cache.
+fn test_cache_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("link_cache_{test}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+#[cfg(test)]
+fn cache_entries(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("cilly".as_ref()))
+        .count()
+}
+#[test]
+fn cache_hit() {
+    let dir = test_cache_dir("hit");
+    let cache = LinkCache::new(Some(&dir), u64::MAX);
+    let classes = ["A", "B", "C", "D"];
+    let asm = link_files(&test_files(&classes), &cache);
+    assert_eq!(class_names(&asm), classes);
+    // Replace the entry of the whole range, to check that linking the same files again loads it.
+    let mut cached = cilly::v2::Assembly::default();
+    cached.main_module();
+    cache.put(&cache.key(&test_files(&classes)), &cached);
+    let asm = link_files(&test_files(&classes), &cache);
+    assert_eq!(class_names(&asm), ["MainModule"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn cache_miss() {
+    let dir = test_cache_dir("miss");
+    let cache = LinkCache::new(Some(&dir), u64::MAX);
+    let classes = ["A", "B", "C", "D", "E", "F", "G", "H"];
+    link_files(&test_files(&classes), &cache);
+    let merges = cache_entries(&dir);
+    assert_eq!(merges, classes.len() - 1);
+    // A changed input is a cache miss.
+    let changed = ["A", "B", "C", "D", "E", "F", "G", "X"];
+    let asm = link_files(&test_files(&changed), &cache);
+    assert_eq!(class_names(&asm), changed);
+    // Adding an input only re-links the ranges containing it, instead of shifting every range.
+    let before = cache_entries(&dir);
+    let added = ["A", "B", "C", "D", "E", "F", "G", "H", "I"];
+    let asm = link_files(&test_files(&added), &cache);
+    assert_eq!(class_names(&asm), added);
+    assert!(cache_entries(&dir) - before < merges);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn cache_corrupted() {
+    let dir = test_cache_dir("corrupted");
+    let cache = LinkCache::new(Some(&dir), u64::MAX);
+    let classes = ["A", "B", "C"];
+    link_files(&test_files(&classes), &cache);
+    let key = cache.key(&test_files(&classes));
+    std::fs::write(cache.path(&key).unwrap(), b"not an assembly").unwrap();
+    assert!(cache.get(&key).is_none());
+    // The corrupted entry is ignored, and replaced.
+    let asm = link_files(&test_files(&classes), &cache);
+    assert_eq!(class_names(&asm), classes);
+    assert!(cache.get(&key).is_some());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn cache_eviction() {
+    let dir = test_cache_dir("eviction");
+    let cache = LinkCache::new(Some(&dir), 0);
+    let asm = link_files(&test_files(&["A", "B", "C"]), &cache);
+    assert_eq!(class_names(&asm), ["A", "B", "C"]);
+    // Every entry is larger than the size limit.
+    assert_eq!(cache_entries(&dir), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn link_order() {
+    use cilly::v2::{
+        asm::USER_INIT, cilnode::MethodKind, Access, BasicBlock, CILNode, CILRoot, Const,
+        MethodDef, MethodImpl, Type,
+    };
+    // The static initializers of the files are merged in link order, so each file records its index in one.
+    let files: Vec<_> = (0..16)
+        .map(|idx| {
+            let mut asm = cilly::v2::Assembly::default();
+            let main_module = asm.main_module();
+            let val = asm.alloc_node(Const::I32(idx));
+            let pop = asm.alloc_root(CILRoot::Pop(val));
+            let ret = asm.alloc_root(CILRoot::VoidRet);
+            let name = asm.alloc_string(USER_INIT);
+            let sig = asm.sig([], Type::Void);
+            asm.new_method(MethodDef::new(
+                Access::Extern,
+                main_module,
+                name,
+                sig,
+                MethodKind::Static,
+                MethodImpl::MethodBody {
+                    blocks: vec![BasicBlock::new(vec![pop, ret], 0, None)],
+                    locals: vec![],
+                },
+                vec![],
+            ));
+            AssemblyFile::new(idx.to_string(), postcard::to_stdvec(&asm).unwrap().into())
+        })
+        .collect();
+    let init_order = |mut asm: cilly::v2::Assembly| -> Vec<i32> {
+        let init = asm.user_init();
+        let blocks = asm.method_def(init).implementation().blocks().unwrap();
+        blocks[0]
+            .roots()
+            .iter()
+            .filter_map(|root| match asm[*root] {
+                CILRoot::Pop(val) => match asm[val] {
+                    CILNode::Const(ref val) => match val.as_ref() {
+                        Const::I32(idx) => Some(*idx),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    };
+    let sequential = files
+        .iter()
+        .fold(cilly::v2::Assembly::default(), |asm, file| {
+            asm.link(file.decode())
+        });
+    let sequential = init_order(sequential);
+    assert_eq!(sequential.len(), files.len());
+    let linked = link_files(&files, &LinkCache::new(None, u64::MAX));
+    assert_eq!(init_order(linked), sequential);
+}
@@ -168,7 +168,12 @@ fn main() {
 
     // Load assemblies from files
 
-    let (mut final_assembly, _) = load::load_assemblies(to_link.as_slice(), ar_to_link.as_slice());
+    let (mut final_assembly, _) = load::load_assemblies(
+        to_link.as_slice(),
+        ar_to_link.as_slice(),
+        LINK_CACHE.as_deref().map(Path::new),
+        *LINK_CACHE_SIZE * 1024 * 1024,
+    );
     /*
        {
            let msg = final_assembly.alloc_string("Starting constant initialization");
@@ -379,12 +384,20 @@ fn main() {
     cilly::v2::builtins::insert_bounds_check(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::casts::insert_casts(&mut final_assembly, &mut overrides);
     cilly::v2::builtins::insert_heap(&mut final_assembly, &mut overrides);
-    if !*C_MODE {
+    // The JS runtime provides its own atomics, threading and math functions.
+    if !*JS_MODE {
+        // The C exporter only lowers the `Interlocked` primitives (`CompareExchange`, `Exchange`, `Add`, ...)
+        // to `__atomic_*` builtins. Atomic xor/nand/min/max and the byte-sized compare-exchange are built on top
+        // of those primitives here, and have no other implementation in C mode.
         cilly::v2::builtins::atomics::generate_all_atomics(&mut final_assembly, &mut overrides);
-
-        cilly::v2::builtins::instert_threading(&mut final_assembly, &mut overrides);
-
-        cilly::v2::builtins::math::math(&mut final_assembly, &mut overrides);
+        // The JVM and C backends do not support threads yet.
+        if !*JAVA_MODE && !*C_MODE {
+            cilly::v2::builtins::instert_threading(&mut final_assembly, &mut overrides);
+        }
+        // C code can call libm directly.
+        if !*C_MODE {
+            cilly::v2::builtins::math::math(&mut final_assembly, &mut overrides);
+        }
     }
     // Ensure the cctor and tcctor exist!
     let _ = final_assembly.tcctor();
@@ -409,6 +422,9 @@ fn main() {
         final_assembly.eliminate_dead_code();
     }
     let mut fuel = final_assembly.fuel_from_env().fraction(0.5);
+    if *INLINE {
+        final_assembly.inline(&mut fuel);
+    }
     final_assembly.opt(&mut fuel);
     final_assembly.eliminate_dead_code();
 
@@ -416,14 +432,47 @@ fn main() {
         .save_tmp(&mut std::fs::File::create(path.with_extension("cilly2")).unwrap())
         .unwrap();
     if *C_MODE {
-        final_assembly.export(&path, cilly::v2::c_exporter::CExporter::new(is_lib));
+        final_assembly.export(
+            &path,
+            cilly::v2::c_exporter::CExporter::new(is_lib, *C_SANITIZE),
+        );
     } else if *JS_MODE {
-        todo!();
+        final_assembly.export(&path, cilly::v2::js_exporter::JsExporter::new(is_lib));
+        if cargo_support {
+            let bootstrap = bootstrap_source(
+                &path.with_extension("js"),
+                path.to_str().unwrap(),
+                "node",
+                &[],
+            );
+            let bootstrap_path = path.with_extension("rs");
+            let mut bootstrap_file = std::fs::File::create(&bootstrap_path).unwrap();
+            bootstrap_file.write_all(bootstrap.as_bytes()).unwrap();
+            let path = std::env::var("PATH").unwrap();
+            let out = std::process::Command::new("rustc")
+                .arg("-O")
+                .arg(bootstrap_path)
+                .arg("-o")
+                .arg(output_file_path)
+                .env_clear()
+                .env("PATH", path)
+                .output()
+                .unwrap();
+            assert!(
+                out.stderr.is_empty(),
+                "{}",
+                String::from_utf8(out.stderr).unwrap()
+            );
+        }
     } else if *JAVA_MODE {
         final_assembly.export(&path, cilly::v2::java_exporter::JavaExporter::new(is_lib));
         if cargo_support {
-            let bootstrap =
-                bootstrap_source(&path.with_extension("jar"), path.to_str().unwrap(), "java");
+            let bootstrap = bootstrap_source(
+                &path.with_extension("jar"),
+                path.to_str().unwrap(),
+                "java",
+                &["-jar"],
+            );
             let bootstrap_path = path.with_extension("rs");
             let mut bootstrap_file = std::fs::File::create(&bootstrap_path).unwrap();
             bootstrap_file.write_all(bootstrap.as_bytes()).unwrap();
@@ -444,15 +493,24 @@ fn main() {
             );
         }
     } else {
-        final_assembly.export(
-            &path,
-            cilly::v2::il_exporter::ILExporter::new(*ILASM_FLAVOUR, is_lib),
-        );
+        if *DIRECT_PE {
+            final_assembly.export(&path, cilly::v2::pe_exporter::PEExporter::new(is_lib));
+        } else {
+            final_assembly.export(
+                &path,
+                cilly::v2::il_exporter::ILExporter::new(*ILASM_FLAVOUR, is_lib),
+            );
+        }
+        // Libraries also get a C# facade, which lets C# projects compile against them.
+        if is_lib {
+            final_assembly.export(&path, cilly::v2::cs_exporter::CSharpExporter::default());
+        }
         if cargo_support {
             let bootstrap = bootstrap_source(
                 &path.with_extension("exe"),
                 path.to_str().unwrap(),
                 "dotnet",
+                &[],
             );
             let bootstrap_path = path.with_extension("rs");
             let mut bootstrap_file = std::fs::File::create(&bootstrap_path).unwrap();
@@ -477,7 +535,12 @@ fn main() {
 
     //todo!();
 }
-fn bootstrap_source(fpath: &Path, output_file_path: &str, jumpstart_cmd: &str) -> String {
+fn bootstrap_source(
+    fpath: &Path,
+    output_file_path: &str,
+    jumpstart_cmd: &str,
+    jumpstart_args: &[&str],
+) -> String {
     if let Err(err) = std::fs::remove_file(output_file_path) {
         match err.kind() {
             std::io::ErrorKind::NotFound => (),
@@ -489,6 +552,10 @@ fn bootstrap_source(fpath: &Path, output_file_path: &str, jumpstart_cmd: &str) -
     format!(
         include_str!("dotnet_jumpstart.rs"),
         jumpstart_cmd = jumpstart_cmd,
+        jumpstart_args = jumpstart_args
+            .iter()
+            .map(|arg| format!("{arg:?},"))
+            .collect::<String>(),
         exec_file = fpath.file_name().unwrap().to_string_lossy(),
         has_native_companion = *NATIVE_PASSTROUGH,
         has_pdb = match *ILASM_FLAVOUR {
@@ -545,6 +612,46 @@ lazy_static! {
         }).unwrap_or(false)
     };
 }
+lazy_static! {
+    #[doc = "Tells the codegen to compile the emmited C source files with sanitizers."]pub static ref C_SANITIZE:bool = {
+        std::env::vars().find_map(|(key,value)|if key == stringify!(C_SANITIZE){
+            Some(value)
+        }else {
+            None
+        }).map(|value|match value.as_ref(){
+            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(C_SANITIZE),value),
+        }).unwrap_or(false)
+    };
+}
+lazy_static! {
+    #[doc = "Specifies a directory, in which the linker caches partially linked assemblies."]pub static ref LINK_CACHE:Option<String> = {
+        std::env::vars().find_map(|(key,value)|if key == stringify!(LINK_CACHE){
+            Some(value)
+        }else {
+            None
+        })
+    };
+}
+lazy_static! {
+    #[doc = "The maximum size of the link cache, in megabytes. The least recently used entries are removed once it grows past that."]pub static ref LINK_CACHE_SIZE:u64 = {
+        std::env::vars().find_map(|(key,value)|if key == stringify!(LINK_CACHE_SIZE){
+            Some(value)
+        }else {
+            None
+        }).map(|value|value.parse().unwrap_or_else(|_|panic!("Enviroment variable {} has invalid value {}",stringify!(LINK_CACHE_SIZE),value))).unwrap_or(4096)
+    };
+}
+lazy_static! {
+    #[doc = "Tells the linker to inline calls across the whole assembly."]pub static ref INLINE:bool = {
+        std::env::vars().find_map(|(key,value)|if key == stringify!(INLINE){
+            Some(value)
+        }else {
+            None
+        }).map(|value|match value.as_ref(){
+            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(INLINE),value),
+        }).unwrap_or(false)
+    };
+}
 lazy_static! {
     #[doc = "Tells the linker to not remove any dead code."]pub static ref KEEP_DEAD_CODE:bool = {
         std::env::vars().find_map(|(key,value)|if key == stringify!(KEEP_DEAD_CODE){
@@ -578,6 +685,17 @@ lazy_static! {
         }).unwrap_or(false)
     };
 }
+lazy_static! {
+    #[doc = "Tells the linker to write the .NET assembly directly, without invoking ilasm."]pub static ref DIRECT_PE:bool = {
+        std::env::vars().find_map(|(key,value)|if key == stringify!(DIRECT_PE){
+            Some(value)
+        }else {
+            None
+        }).map(|value|match value.as_ref(){
+            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(DIRECT_PE),value),
+        }).unwrap_or(false)
+    };
+}
 lazy_static! {
     #[doc = "Tells the codegen to throw exceptions on panics"]pub static ref PANIC_MANAGED_BT:bool = {
         std::env::vars().find_map(|(key,value)|if key == stringify!(PANIC_MANAGED_BT){
@@ -176,6 +176,7 @@ impl<'a> Iterator for CILIter<'a> {
                     | CILRoot::Ret { tree }
                     | CILRoot::BTrue { cond: tree, .. }
                     | CILRoot::BFalse { cond: tree, .. }
+                    | CILRoot::Switch { value: tree, .. }
                     | CILRoot::Throw(tree)
                     | CILRoot::SetStaticField {
                         descr: _,
@@ -241,7 +242,8 @@ impl<'a> Iterator for CILIter<'a> {
                         continue;
                     }
                 },
-                CILIterElem::Root(CILRoot::Volatile(inner)) => match idx {
+                CILIterElem::Root(CILRoot::Volatile(inner) | CILRoot::TailCall(inner)) => match idx
+                {
                     1 => {
                         *idx += 1;
                         self.elems.push((0, CILIterElem::Root(inner)));
@@ -290,6 +290,7 @@ impl<'a> Iterator for CILIterMut<'a> {
                     | CILRoot::Pop { tree }
                     | CILRoot::BTrue { cond: tree, .. }
                     | CILRoot::BFalse { cond: tree, .. }
+                    | CILRoot::Switch { value: tree, .. }
                     | CILRoot::Throw(tree) => {
                         if *idx == 1 {
                             *idx += 1;
@@ -363,7 +364,7 @@ impl<'a> Iterator for CILIterMut<'a> {
                             continue;
                         }
                     },
-                    CILRoot::Volatile(inner) => match *idx {
+                    CILRoot::Volatile(inner) | CILRoot::TailCall(inner) => match *idx {
                         1 => {
                             *idx += 1;
                             self.elems.push((
@@ -1,4 +1,4 @@
-use crate::v2::{Assembly, ClassRef, FnSig, Type};
+use crate::v2::{cilroot::SwitchTargets, Assembly, ClassRef, FnSig, Int, Type};
 use crate::{
     call,
     call_site::CallSite,
@@ -149,6 +149,14 @@ pub enum CILRoot {
     OptimizedSourceFileInfo(std::ops::Range<u64>, std::ops::Range<u64>, AsmString),
     /// Marks the inner pointer operation as volatile.
     Volatile(Box<Self>),
+    /// Performs the inner [`Self::Call`] or [`Self::CallI`] as a tail call: its result is returned, and the frame of the caller may be reused.
+    TailCall(Box<Self>),
+    /// Jumps to one of `targets`, depending on `value`, an integer of type `tpe`.
+    Switch {
+        value: CILNode,
+        tpe: Int,
+        targets: Box<SwitchTargets>,
+    },
 }
 pub type SFI = Box<(std::ops::Range<u64>, std::ops::Range<u64>, IString)>;
 impl CILRoot {
@@ -163,6 +171,18 @@ impl CILRoot {
             args: [CILNode::LdStr(msg.into())].into(),
         })))
     }
+    /// Terminates the process with `msg`. Unlike [`Self::throw`], this can't be caught, so it is used where Rust requires an abort.
+    #[must_use]
+    pub fn fail_fast(msg: &str, asm: &mut Assembly) -> Self {
+        let class = ClassRef::enviroment(asm);
+
+        let name = "FailFast".to_owned().into();
+        let signature = FnSig::new(Box::new([Type::PlatformString]), Type::Void);
+        Self::Call {
+            site: Box::new(CallSite::new_extern(class, name, signature, true)),
+            args: [CILNode::LdStr(msg.into())].into(),
+        }
+    }
     #[must_use]
     pub fn debug(msg: &str, asm: &mut Assembly) -> Self {
         let class = ClassRef::console(asm);
@@ -192,6 +212,9 @@ impl CILRoot {
             | Self::GoTo { target, sub_target } => {
                 targets.push((*target, *sub_target));
             }
+            Self::Switch {
+                targets: switch, ..
+            } => targets.extend(switch.targets()),
             _ => (),
         }
     }
@@ -235,6 +258,15 @@ impl CILRoot {
                 *sub_target = *target;
                 *target = id;
             }
+            Self::Switch { targets, .. } => {
+                **targets = targets.map_targets(|(target, sub_target)| {
+                    assert_eq!(
+                        sub_target, 0,
+                        "An exception handler can't contain inner exception handler!"
+                    );
+                    (id, target)
+                });
+            }
             _ => (),
         }
     }
@@ -266,7 +298,7 @@ impl CILRoot {
         locals: &mut Vec<(Option<IString>, Type)>,
     ) {
         match self {
-            Self::Volatile(inner) => inner.allocate_tmps(curr_loc, locals),
+            Self::Volatile(inner) | Self::TailCall(inner) => inner.allocate_tmps(curr_loc, locals),
             Self::SourceFileInfo(_) => (),
             Self::OptimizedSourceFileInfo(_, _, _) => (),
             Self::STLoc { tree, .. } => {
@@ -274,6 +306,7 @@ impl CILRoot {
             }
             Self::BTrue { cond: ops, .. } => ops.allocate_tmps(curr_loc, locals),
             Self::BFalse { cond: ops, .. } => ops.allocate_tmps(curr_loc, locals),
+            Self::Switch { value, .. } => value.allocate_tmps(curr_loc, locals),
             Self::BEq { a, b, .. }
             | Self::BNe { a, b, .. }
             | Self::BLt { a, b, .. }
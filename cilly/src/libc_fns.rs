@@ -673,10 +673,60 @@ pub const LIBM_FNS: &[&str] = &[
     "tgamma",
     "tgammaf",
     "fmodl",
+    "fmodf128",
+    "sqrtf128",
+    "fabsf128",
+    "floorf128",
+    "ceilf128",
+    "truncf128",
+    "roundf128",
+    "roundevenf128",
+    "rintf128",
+    "nearbyintf128",
+    "fmaf128",
+    "powf128",
+    "expf128",
+    "exp2f128",
+    "logf128",
+    "log2f128",
+    "log10f128",
+    "sinf128",
+    "cosf128",
+    "copysignf128",
+    "fmaxf128",
+    "fminf128",
 ];
 pub const F128_SYMBOLS: &[&str] = &[
-    "__addtf3", "__subtf3", "__multf3", "__divtf3", "__eqtf2", "__netf2", "__getf2", "__lttf2",
-    "__letf2", "__gttf2",
+    "__addtf3",
+    "__subtf3",
+    "__multf3",
+    "__divtf3",
+    "__negtf2",
+    "__powitf2",
+    "__eqtf2",
+    "__netf2",
+    "__getf2",
+    "__lttf2",
+    "__letf2",
+    "__gttf2",
+    "__unordtf2",
+    "__extendsftf2",
+    "__extenddftf2",
+    "__trunctfsf2",
+    "__trunctfdf2",
+    "__trunctfhf2",
+    "__floatsitf",
+    "__floatditf",
+    "__floattitf",
+    "__floatunsitf",
+    "__floatunditf",
+    "__floatuntitf",
+    "__fixtfsi",
+    "__fixtfdi",
+    "__fixtfti",
+    "__fixunstfsi",
+    "__fixunstfdi",
+    "__fixunstfti",
 ];
 #[cfg(all(target_os = "linux", target_env = "gnu"))]
 // TODO: this is not portable at all.
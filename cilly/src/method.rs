@@ -7,7 +7,7 @@ use std::{
 
 use crate::{
     access_modifier::AccessModifer,
-    basic_block::BasicBlock,
+    basic_block::{BasicBlock, Handler},
     call_site::CallSite,
     cil_iter::{CILIterElem, CILIterTrait},
     cil_iter_mut::CILIterElemMut,
@@ -470,6 +470,18 @@ impl Method {
         self.blocks.retain(|block| !block.trees().is_empty());
     }
     fn count_jumps_to(&self, block_id: u32) -> usize {
+        // Handlers jump to a block by leaving the protected region.
+        let handler_exits = self
+            .blocks()
+            .iter()
+            .filter_map(BasicBlock::handler)
+            .filter_map(Handler::as_blocks)
+            .flatten()
+            .flat_map(BasicBlock::trees)
+            .filter(|tree| {
+                matches!(tree.root(), CILRoot::JumpingPad { target, .. } if *target == block_id)
+            })
+            .count();
         self.blocks()
             .iter()
             .flat_map(|block| block.targets())
@@ -481,6 +493,7 @@ impl Method {
                 }
             })
             .count()
+            + handler_exits
     }
     pub fn block_with_id(&self, id: u32) -> Option<usize> {
         self.blocks.iter().position(|block| block.id() == id)
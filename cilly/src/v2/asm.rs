@@ -17,7 +17,7 @@ pub type MissingMethodPatcher =
     FxHashMap<StringIdx, Box<dyn Fn(MethodRefIdx, &mut Assembly) -> MethodImpl>>;
 type StringMap = BiMap<StringIdx, IString>;
 type TypeMap = BiMap<TypeIdx, Type>;
-#[derive(Default, Serialize, Deserialize, Clone)]
+#[derive(Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Assembly {
     /// A list of strings used in this assembly
     strings: StringMap,
@@ -121,6 +121,21 @@ impl Assembly {
             tmp_method.typecheck(self);
         }
     }
+    /// Returns how many strings, types, class refs, signatures, method refs, fields, static fields, nodes and roots are
+    /// interned in this assembly.
+    pub(crate) fn interned_counts(&self) -> [usize; 9] {
+        [
+            self.strings.len(),
+            self.types.len(),
+            self.class_refs.len(),
+            self.sigs.len(),
+            self.method_refs.len(),
+            self.fields.len(),
+            self.statics.len(),
+            self.nodes.len(),
+            self.roots.len(),
+        ]
+    }
     #[must_use]
     pub fn class_defs(&self) -> &FxHashMap<ClassDefIdx, ClassDef> {
         &self.class_defs
@@ -737,7 +752,9 @@ impl Assembly {
                     crate::v2::CILIterElem::Node(CILNode::Call(args)) => Some(args.0),
                     crate::v2::CILIterElem::Node(CILNode::LdFtn(mref)) => Some(mref),
                     crate::v2::CILIterElem::Node(_) => None,
-                    crate::v2::CILIterElem::Root(CILRoot::Call(args)) => Some(args.0),
+                    crate::v2::CILIterElem::Root(CILRoot::Call(args) | CILRoot::TailCall(args)) => {
+                        Some(args.0)
+                    }
                     crate::v2::CILIterElem::Root(_) => None,
                 });
                 // Check if this method reference is also a def. If so, map it to a def
@@ -389,6 +389,20 @@ impl Assembly {
                 });
                 CILRoot::Branch(Box::new((*target, *sub_target, cond)))
             }
+            CILRoot::Switch(switch) => {
+                let (value, tpe, targets) = *switch;
+                let value = self.translate_node(source, source.get_node(value).clone());
+                let value = self.alloc_node(value);
+                CILRoot::Switch(Box::new((value, tpe, targets)))
+            }
+            CILRoot::TailCall(info) => match self.translate_root(source, CILRoot::Call(info)) {
+                CILRoot::Call(info) => CILRoot::TailCall(info),
+                _ => unreachable!(),
+            },
+            CILRoot::TailCallI(info) => match self.translate_root(source, CILRoot::CallI(info)) {
+                CILRoot::CallI(info) => CILRoot::TailCallI(info),
+                _ => unreachable!(),
+            },
             CILRoot::VoidRet | CILRoot::Break | CILRoot::Nop | CILRoot::ReThrow => root,
             CILRoot::SourceFileInfo {
                 line_start,
@@ -562,7 +576,7 @@ impl Assembly {
             .iter()
             .map(|arg| arg.map(|arg| self.alloc_string(source.get_string(arg).as_ref())))
             .collect();
-        MethodDef::new(
+        let mut translated = MethodDef::new(
             *def.access(),
             class,
             name,
@@ -570,7 +584,12 @@ impl Assembly {
             def.kind(),
             method_impl,
             arg_names,
-        )
+        );
+        translated.set_doc(
+            def.doc()
+                .map(|doc| self.alloc_string(source.get_string(doc).as_ref())),
+        );
+        translated
     }
     pub(crate) fn translate_class_def(&mut self, source: &Assembly, def: &ClassDef) -> ClassDef {
         let name = self.alloc_string(source.get_string(def.name()).as_ref());
@@ -595,7 +614,7 @@ impl Assembly {
                 (tpe, name, *thread_local)
             })
             .collect();
-        let translated = ClassDef::new(
+        let mut translated = ClassDef::new(
             name,
             def.is_valuetype(),
             def.generics(),
@@ -605,6 +624,10 @@ impl Assembly {
             *def.access(),
             def.explict_size(),
         );
+        for interface in def.implements() {
+            let interface = self.translate_class_ref(source, *interface);
+            translated.add_interface(interface);
+        }
         let class_ref = self.alloc_class_ref(translated.ref_to());
         let (defs_mut, _) = self.class_defs_mut_strings();
         match defs_mut.entry(ClassDefIdx(class_ref)) {
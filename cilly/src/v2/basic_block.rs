@@ -14,22 +14,16 @@ impl BasicBlock {
         &'block self,
         asm: &'asm Assembly,
     ) -> impl Iterator<Item = u32> + 'block {
-        self.roots().iter().filter_map(|root| {
-            match asm.get_root(*root) {
-                CILRoot::Branch(info) => {
-                    let (target, sub_target, _) = info.as_ref();
-                    //Some(*sub_target)
-                    //(eprintln!("{target} {sub_target}");
-                    if *sub_target == 0 {
-                        Some(*target)
-                    } else {
-                        Some(*sub_target)
-                    }
-                }
-                CILRoot::ExitSpecialRegion { target, .. } => Some(*target),
-                _ => None,
-            }
-        })
+        self.roots()
+            .iter()
+            .flat_map(|root| match asm.get_root(*root) {
+                CILRoot::ExitSpecialRegion { target, .. } => vec![*target],
+                root => root
+                    .targets()
+                    .into_iter()
+                    .map(|(target, sub_target)| if sub_target == 0 { target } else { sub_target })
+                    .collect(),
+            })
     }
     #[must_use]
     pub fn new(roots: Vec<RootIdx>, block_id: u32, handler: Option<Vec<Self>>) -> Self {
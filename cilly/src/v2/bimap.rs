@@ -11,6 +11,13 @@ impl<Key: IntoBiMapIndex + Eq + Hash + Clone, Value: Eq + Hash + Clone> Default
         Self(Vec::default(), FxHashMap::default())
     }
 }
+// The map from values to keys is derived from the list of values, so comparing the lists is enough.
+impl<Key, Value: Eq + Hash> PartialEq for BiMap<Key, Value> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl<Key, Value: Eq + Hash> Eq for BiMap<Key, Value> {}
 impl<Key: IntoBiMapIndex + Eq + Hash + Clone + Debug, Value: Eq + Hash + Clone + Debug> Index<Key>
     for BiMap<Key, Value>
 {
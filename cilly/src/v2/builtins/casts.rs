@@ -1,7 +1,9 @@
 use crate::v2::{
-    asm::MissingMethodPatcher, cilnode::ExtendKind, Assembly, BasicBlock, CILNode, CILRoot, Float,
-    Int, MethodImpl,
+    asm::MissingMethodPatcher, cilnode::ExtendKind, cilroot::BranchCond, Assembly, BasicBlock,
+    CILNode, CILRoot, Const, Float, Int, MethodImpl, NodeIdx, Type,
 };
+
+use super::math::{int_max, int_min};
 fn clampy_float_to_int(
     asm: &mut Assembly,
     int: Int,
@@ -111,6 +113,80 @@ fn float_to_int(asm: &mut Assembly, int: Int, float: Float, patcher: &mut Missin
     };
     patcher.insert(name, Box::new(generator));
 }
+/// Casts a `f128` to an int, using the `libgcc` soft-float routines. Those already saturate on overflow,
+/// so only NaNs(which get cast to 0) need special handling.
+fn f128_to_int(asm: &mut Assembly, int: Int, patcher: &mut MissingMethodPatcher) {
+    let name = format!("cast_f128_{}", int.name());
+    let name = asm.alloc_string(name);
+    let generator = move |_, asm: &mut Assembly| {
+        let (routine, routine_int) = match int {
+            Int::I8 | Int::I16 | Int::I32 => ("__fixtfsi", Int::I32),
+            Int::U8 | Int::U16 | Int::U32 => ("__fixunstfsi", Int::U32),
+            Int::I64 | Int::ISize => ("__fixtfdi", Int::I64),
+            Int::U64 | Int::USize => ("__fixunstfdi", Int::U64),
+            Int::I128 => ("__fixtfti", Int::I128),
+            Int::U128 => ("__fixunstfti", Int::U128),
+        };
+        let main_module = *asm.main_module();
+        let routine = asm.alloc_string(routine);
+        let routine = asm.class_ref(main_module).clone().static_mref(
+            &[Type::Float(Float::F128)],
+            Type::Int(routine_int),
+            routine,
+            asm,
+        );
+        // The routines only saturate to the range of the int they return, so smaller ints need clamping.
+        let bounds = match int {
+            Int::I8 => Some((Const::I32(i8::MIN.into()), Const::I32(i8::MAX.into()))),
+            Int::I16 => Some((Const::I32(i16::MIN.into()), Const::I32(i16::MAX.into()))),
+            Int::U8 => Some((Const::U32(u8::MIN.into()), Const::U32(u8::MAX.into()))),
+            Int::U16 => Some((Const::U32(u16::MIN.into()), Const::U32(u16::MAX.into()))),
+            _ => None,
+        };
+        let convert = |input: NodeIdx, asm: &mut Assembly| {
+            let mut val = asm.alloc_node(CILNode::Call(Box::new((routine, [input].into()))));
+            if let Some((min, max)) = bounds {
+                let min = asm.alloc_node(min);
+                let max = asm.alloc_node(max);
+                val = int_max(asm, val, min, routine_int);
+                val = int_min(asm, val, max, routine_int);
+            }
+            if int == routine_int {
+                return val;
+            }
+            asm.alloc_node(CILNode::IntCast {
+                input: val,
+                target: int,
+                extend: if int.is_signed() {
+                    ExtendKind::SignExtend
+                } else {
+                    ExtendKind::ZeroExtend
+                },
+            })
+        };
+        let ld_arg_0 = asm.alloc_node(CILNode::LdArg(0));
+        // If arg is NaN, jump to block 1.
+        let is_nan = Float::F128.is_nan(ld_arg_0, asm);
+        let nan = asm.alloc_root(CILRoot::Branch(Box::new((
+            1,
+            0,
+            Some(BranchCond::True(is_nan)),
+        ))));
+        let cast = convert(ld_arg_0, asm);
+        let return_cast = asm.alloc_root(CILRoot::Ret(cast));
+        let zero = Float::F128.zero(asm);
+        let zero = convert(zero, asm);
+        let return_zero = asm.alloc_root(CILRoot::Ret(zero));
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(vec![nan, return_cast], 0, None),
+                BasicBlock::new(vec![return_zero], 1, None),
+            ],
+            locals: vec![],
+        }
+    };
+    patcher.insert(name, Box::new(generator));
+}
 pub fn insert_casts(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
     let floats = [Float::F32, Float::F64];
     let ints = [
@@ -133,4 +209,21 @@ pub fn insert_casts(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
             clampy_float_to_int(asm, int, float, patcher);
         }
     }
+    let ints = [
+        Int::U8,
+        Int::U16,
+        Int::U32,
+        Int::U64,
+        Int::U128,
+        Int::USize,
+        Int::I8,
+        Int::I16,
+        Int::I32,
+        Int::I64,
+        Int::I128,
+        Int::ISize,
+    ];
+    for int in ints {
+        f128_to_int(asm, int, patcher);
+    }
 }
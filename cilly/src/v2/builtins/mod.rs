@@ -335,7 +335,28 @@ fn insert_catch_unwind(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
         let const_1 = asm.alloc_node(Const::I32(1));
         let ret_0 = asm.alloc_root(CILRoot::Ret(const_0));
         let ret_1 = asm.alloc_root(CILRoot::Ret(const_1));
-        let rethrow = asm.alloc_root(CILRoot::ReThrow);
+        // Foreign(.NET) exceptions are converted to Rust panics, if the panic runtime supports that. Otherwise, they
+        // are not caught.
+        let exception_to_native = asm.alloc_string("exception_to_native");
+        let exception_to_native = asm
+            .methods_with(|_, _, def| def.name() == exception_to_native)
+            .map(|(def, _)| **def)
+            .next();
+        let foreign_handler = match exception_to_native {
+            Some(exception_to_native) => {
+                let payload = asm.alloc_node(CILNode::Call(Box::new((
+                    exception_to_native,
+                    Box::new([ldloc_1]),
+                ))));
+                let calli_catch = asm.alloc_root(CILRoot::CallI(Box::new((
+                    ldarg_2,
+                    catch_sig,
+                    [ldarg_1, payload].into(),
+                ))));
+                vec![calli_catch, exit_try_faliure]
+            }
+            None => vec![asm.alloc_root(CILRoot::ReThrow)],
+        };
         MethodImpl::MethodBody {
             blocks: vec![
                 BasicBlock::new(
@@ -352,7 +373,7 @@ fn insert_catch_unwind(asm: &mut Assembly, patcher: &mut MissingMethodPatcher) {
                             1,
                             None,
                         ),
-                        BasicBlock::new(vec![rethrow], 4, None),
+                        BasicBlock::new(foreign_handler, 4, None),
                     ]),
                 ),
                 BasicBlock::new(vec![ret_0], 2, None),
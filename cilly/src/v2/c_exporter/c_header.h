@@ -1,274 +1,618 @@
-#include <stdio.h>
+// Runtime of the C backend.
+// Functions from the C standard library are declared under private names, bound to their real symbols with
+// `C_RT_SYM`. This way, they can never clash with functions defined by the exported assembly, even if those
+// share a name with a libc function.
 #include <stdint.h>
+#include <stddef.h>
 #include <stdbool.h>
-#include "threads.h"
-#include <stdlib.h>
-#include <string.h>
-#include <math.h>
-#ifdef __STDC_VERSION__
+#include <stdarg.h>
+#include <setjmp.h>
+#if !defined(__STDC_VERSION__) || __STDC_VERSION__ < 201112L
+#error "C version too old(< C11), and unsuported"
+#endif
+#define C_RT_STR2(x) #x
+#define C_RT_STR(x) C_RT_STR2(x)
+#define C_RT_SYM(name) __asm__(C_RT_STR(__USER_LABEL_PREFIX__) name)
+// Makes `return f(...);` a guaranteed tail call, on compilers which support that. Elsewhere, it is an ordinary call,
+// which the optimizer may still turn into a jump.
+#if defined(__has_attribute)
+#if __has_attribute(musttail)
+#define C_RT_MUSTTAIL __attribute__((musttail))
+#endif
+#endif
+#ifndef C_RT_MUSTTAIL
+#define C_RT_MUSTTAIL
+#endif
+// Fields of value types may sit at offsets below their alignment. Unlike `C_RT_MUSTTAIL`, this is needed for the
+// layout to be correct at all, so compilers without packed structs are rejected.
+#if defined(__has_attribute)
+#if __has_attribute(packed)
+#define C_RT_PACKED __attribute__((packed))
+#endif
+#elif defined(__GNUC__)
+#define C_RT_PACKED __attribute__((packed))
+#endif
+#ifndef C_RT_PACKED
+#error "The compiler does not support packed structs, which are needed for unaligned fields"
+#endif
 
+void *c_rt_malloc(size_t size) C_RT_SYM("malloc");
+void *c_rt_calloc(size_t count, size_t size) C_RT_SYM("calloc");
+void *c_rt_realloc(void *ptr, size_t size) C_RT_SYM("realloc");
+void c_rt_free(void *ptr) C_RT_SYM("free");
+_Noreturn void c_rt_exit(int code) C_RT_SYM("exit");
+_Noreturn void c_rt_abort(void) C_RT_SYM("abort");
+int c_rt_snprintf(char *buf, size_t len, const char *fmt, ...) C_RT_SYM("snprintf");
+double c_rt_strtod(const char *str, char **end) C_RT_SYM("strtod");
+intptr_t c_rt_write(int fd, const void *buf, size_t len) C_RT_SYM("write");
+
+// `_Float16` and `__float128` are compiler extensions. Where they are missing, `f16` and `f128` values are stored as
+// their bits, and converted and compared by the soft-float routines the .NET backend uses for `f128`. Those come from
+// libgcc / compiler-rt, or from the `compiler_builtins` crate, compiled into the exported code. Arithmetic on `f16` and
+// `f128` values is lowered to `System.Half` methods and soft-float calls before it reaches the C exporter, so the
+// exported code only uses these helpers. Defining `C_RT_SOFT_FLOAT` forces the fallback.
+#if defined(__FLT16_MANT_DIG__) && !defined(C_RT_SOFT_FLOAT)
+typedef _Float16 c_rt_f16;
+static inline float c_rt_f16_to_f32(c_rt_f16 val)
+{
+    return (float)val;
+}
+static inline c_rt_f16 c_rt_f32_to_f16(float val)
+{
+    return (c_rt_f16)val;
+}
+static inline c_rt_f16 c_rt_f64_to_f16(double val)
+{
+    return (c_rt_f16)val;
+}
 #else
-#error "C version too old(< C99), and unsuported"
+typedef struct c_rt_f16
+{
+    uint16_t bits;
+} c_rt_f16;
+float c_rt_f16_to_f32(c_rt_f16 val) C_RT_SYM("__extendhfsf2");
+c_rt_f16 c_rt_f32_to_f16(float val) C_RT_SYM("__truncsfhf2");
+c_rt_f16 c_rt_f64_to_f16(double val) C_RT_SYM("__truncdfhf2");
 #endif
-
-#define eprintf(...) fprintf(stderr, __VA_ARGS__)
-union System_Collections_IDictionary
+#if (defined(__SIZEOF_FLOAT128__) || (defined(__LDBL_MANT_DIG__) && __LDBL_MANT_DIG__ == 113)) && \
+    !defined(C_RT_SOFT_FLOAT)
+#ifdef __SIZEOF_FLOAT128__
+typedef __float128 c_rt_f128;
+#else
+typedef long double c_rt_f128;
+#endif
+static inline c_rt_f128 c_rt_f128_from_f64(double val)
 {
-};
-union System_Collections_IDictionaryEnumerator
+    return (c_rt_f128)val;
+}
+static inline double c_rt_f128_to_f64(c_rt_f128 val)
 {
-};
-union System_Collections_DictionaryEntry
+    return (double)val;
+}
+static inline c_rt_f128 c_rt_f128_from_i128(__int128 val)
 {
-};
-union System_String
+    return (c_rt_f128)val;
+}
+static inline c_rt_f128 c_rt_f128_from_u128(__uint128_t val)
 {
-};
-#define System_Runtime_InteropServices_Marshal_AllocHGlobali4is(size) malloc(size)
-#define System_Runtime_InteropServices_Marshal_AllocHGlobalisis(size) malloc(size)
-#define System_Runtime_InteropServices_Marshal_ReAllocHGlobalisisis(ptr, new_size) realloc(ptr, new_size)
-#define System_Runtime_InteropServices_Marshal_FreeHGlobalisv(ptr) free(ptr)
-#define System_Collections_ICollection_get_Count14System_Runtime30System_Collections_IDictionaryi4(dict) 0
-#define System_Runtime_InteropServices_NativeMemory_AlignedAllocususpv(size, align) aligned_alloc(align, size)
-#define System_Runtime_InteropServices_NativeMemory_AlignedFreepvv free
-void *System_Runtime_InteropServices_NativeMemory_AlignedReallocpvususpv(void *ptr, uintptr_t size, uintptr_t align)
+    return (c_rt_f128)val;
+}
+static inline __int128 c_rt_f128_to_i128_trunc(c_rt_f128 val)
 {
-    void *new_buff = aligned_alloc(align, size);
-    memcpy(new_buff, ptr, size);
-    free(ptr);
-    return new_buff;
+    return (__int128)val;
 }
-
-#define System_Collections_IEnumerator_MoveNext14System_Runtime40System_Collections_IDictionaryEnumeratorb(arg) false
-#define System_UInt128__ctorru16u8u8v(high, low) ((unsigned __int128)(low) | ((unsigned __int128)(high) << 64))
-#define System_Int128__ctorri16u8u8v(high, low) (__int128)((unsigned __int128)(low) | ((unsigned __int128)(high) << 64))
-
-#define System_UInt128_op_Additionu16u16u16(lhs, rhs) (lhs + rhs)
-#define System_Int128_op_Additioni16i16i16(lhs, rhs) (__int128)((unsigned __int128)lhs + (unsigned __int128)rhs)
-
-#define System_UInt128_op_Subtractionu16u16u16(lhs, rhs) (lhs - rhs)
-#define System_Int128_op_Subtractioni16i16i16(lhs, rhs) (__int128)((unsigned __int128)lhs - (unsigned __int128)rhs)
-
-#define System_UInt128_op_Equalityu16u16b(lhs, rhs) (lhs == rhs)
-#define System_Int128_op_Equalityi16i16b(lhs, rhs) (lhs == rhs)
-
-#define System_Int128_op_LessThani16i16b(lhs, rhs) (lhs < rhs)
-#define System_UInt128_op_LessThanu16u16b(lhs, rhs) (lhs < rhs)
-
-#define System_Int128_op_GreaterThani16i16b(lhs, rhs) (lhs > rhs)
-#define System_UInt128_op_GreaterThanu16u16b(lhs, rhs) (lhs > rhs)
-
-#define System_UInt128_op_Multiplyu16u16u16(lhs, rhs) (lhs * rhs)
-#define System_Int128_op_Multiplyi16i16i16(lhs, rhs) ((unsigned __int128)lhs * (unsigned __int128)rhs)
-
-#define System_UInt128_op_Divisionu16u16u16(lhs, rhs) (lhs / rhs)
-
-#define System_Int128_op_UnaryNegationi16i16(val) -(val)
-
-#define System_Int128_op_BitwiseOri16i16i16(lhs, rhs) (lhs | rhs)
-#define System_UInt128_op_BitwiseOru16u16u16(lhs, rhs) (lhs | rhs)
-
-#define System_Int128_op_ExclusiveOri16i16i16(lhs, rhs) (lhs ^ rhs)
-#define System_UInt128_op_ExclusiveOru16u16u16(lhs, rhs) (lhs ^ rhs)
-
-#define System_Int128_op_BitwiseAndi16i16i16(lhs, rhs) (lhs & rhs)
-#define System_UInt128_op_BitwiseAndu16u16u16(lhs, rhs) (lhs & rhs)
-
-#define System_UInt128_op_RightShiftu16i4u16(val, ammount) val >> ammount
-#define System_Int128_op_RightShifti16i4i16(val, ammount) val >> ammount
-
-#define System_Int128_op_LeftShifti16i4i16(val, ammount) val << ammount
-#define System_UInt128_op_LeftShiftu16i4u16(val, ammount) val << ammount
-
-#define System_UInt128_op_Explicitu16u8(val) (uint64_t)(val)
-#define System_UInt128_op_Explicitu16u4(val) (uint32_t)(val)
-#define System_UInt128_op_Explicitu16u2(val) (uint16_t)(val)
-#define System_UInt128_op_Explicitu16u1(val) (uint8_t)(val)
-#define System_UInt128_op_Explicitu16us(val) (uintptr_t) val
-#define System_UInt128_op_Explicitu16i8(val) (int64_t)(val)
-#define System_UInt128_op_Explicitu16i4(val) (int32_t)(val)
-#define System_UInt128_op_Explicitu16i2(val) (int16_t)(val)
-#define System_UInt128_op_Explicitu16i1(val) (int8_t)(val)
-#define System_UInt128_op_Explicitu16is(val) (intptr_t) val
-
-#define System_UInt128_op_Explicitu16f4(val) (float)(val)
-#define System_UInt128_op_Explicitu16f8(val) (double)(val)
-
-#define System_Int128_op_Expliciti16f4(val) (float)(val)
-#define System_Int128_op_Expliciti16f8(val) (double)(val)
-
-#define System_Int128_op_Expliciti16i1(val) (int8_t)(val)
-#define System_Int128_op_Expliciti16i2(val) (int16_t)(val)
-#define System_Int128_op_Expliciti16i4(val) (int32_t)(val)
-#define System_Int128_op_Expliciti16i8(val) (int64_t)(val)
-#define System_Int128_op_Expliciti16is(val) (intptr_t) val
-#define System_Int128_op_Expliciti16u1(val) (uint8_t)(val)
-#define System_Int128_op_Expliciti16u2(val) (uint16_t)(val)
-#define System_Int128_op_Expliciti16u4(val) (uint32_t)(val)
-#define System_Int128_op_Expliciti16u8(val) (uint64_t)(val)
-#define System_Int128_op_Expliciti16us(val) (uintptr_t) val
-
-#define System_UInt128_op_Explicitu16i16(val) (__int128_t)(val)
-
-#define System_UInt128_op_Expliciti1u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Expliciti2u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Expliciti4u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Expliciti8u16(val) (__uint128_t)(val)
-#define System_Int128_op_Expliciti16u16(val) (__uint128_t)(val)
-
-#define System_UInt128_op_Explicitf4u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Explicitf8u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Implicitusu16(val) (__uint128_t)(val)
-
-#define System_Int128_op_Explicitf8i16(val) (__int128_t)(val)
-#define System_Int128_op_Explicitf4i16(val) (__int128_t)(val)
-
-#define System_Int128_op_Impliciti1i16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitu1i16(val) (__int128_t)(val)
-#define System_Int128_op_Impliciti2i16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitu2i16(val) (__int128_t)(val)
-#define System_Int128_op_Impliciti4i16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitu4i16(val) (__int128_t)(val)
-#define System_Int128_op_Impliciti8i16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitu8i16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitisi16(val) (__int128_t)(val)
-#define System_Int128_op_Implicitusi16(val) (__int128_t)(val)
-
-#define System_UInt128_op_Implicitu1u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Implicitu2u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Implicitu4u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Implicitu8u16(val) (__uint128_t)(val)
-#define System_UInt128_op_Implicitusu16(val) (__uint128_t)(val)
-
-#define System_Int128_op_OnesComplementi16i16(val) ~val
-#define System_UInt128_op_OnesComplementu16u16(val) ~val
-
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessi16i16(val) (__int128_t) __builtin_bswap128((__uint128_t)val)
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessi1i1(val) val
-
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessu8u8 __builtin_bswap64
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessi8i8(val) (int64_t) __builtin_bswap64((uint64_t)val)
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessu4u4 __builtin_bswap32
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessi4i4(val) (int32_t) __builtin_bswap32((uint32_t)val)
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessu2u2 __builtin_bswap16
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessi2i2(val) (int16_t) __builtin_bswap16((uint16_t)val)
-// Assumes a 64 bit OS.
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessisis(val) (intptr_t) __builtin_bswap64((ulong)val)
-#define System_Buffers_Binary_BinaryPrimitives_ReverseEndiannessusus __builtin_bswap64
-
-#define System_Numerics_BitOperations_TrailingZeroCountusi4(val) (int32_t) __builtin_ctzl((uint64_t)val)
-#define System_Numerics_BitOperations_PopCountusi4(val) __builtin_popcountl((uint64_t)val)
-
-union System_Collections_IDictionary System_Environment_GetEnvironmentVariables14System_Runtime30System_Collections_IDictionary()
+static inline __uint128_t c_rt_f128_to_u128_trunc(c_rt_f128 val)
 {
-    union System_Collections_IDictionary res;
-    return res;
-};
-union System_Collections_IDictionaryEnumerator System_Collections_IDictionary_GetEnumerator14System_Runtime30System_Collections_IDictionary14System_Runtime40System_Collections_IDictionaryEnumerator(union System_Collections_IDictionary dict)
+    return (__uint128_t)val;
+}
+static inline c_rt_f128 c_rt_f128_add(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    union System_Collections_IDictionaryEnumerator res;
-    return res;
+    return lhs + rhs;
 }
-union System_Collections_DictionaryEntry System_Collections_IEnumerator_get_Current14System_Runtime40System_Collections_IDictionaryEnumeratoro(union System_Collections_IDictionaryEnumerator dict)
+static inline c_rt_f128 c_rt_f128_sub(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    union System_Collections_DictionaryEntry res;
-    return res;
+    return lhs - rhs;
 }
-union System_String System_String_Concat_()
+static inline c_rt_f128 c_rt_f128_mul(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    union System_String res;
-    return res;
+    return lhs * rhs;
 }
-const char *System_Runtime_InteropServices_Marshal_StringToCoTaskMemUTF8sis(union System_String str)
+static inline c_rt_f128 c_rt_f128_div(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    char *res = (char *)malloc(1);
-    res[0] = '\0';
-    return res;
+    return lhs / rhs;
 }
-void System_Console_WriteLineu8v(ulong arg)
+static inline bool c_rt_f128_eq(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    printf("%lu\n", arg);
+    return lhs == rhs;
 }
-void System_Console_WriteLinei8v(long arg)
+static inline bool c_rt_f128_lt(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    printf("%ld\n", arg);
+    return lhs < rhs;
 }
-void System_Console_WriteLineu4v(uint arg)
+static inline bool c_rt_f128_gt(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    printf("%u\n", arg);
+    return lhs > rhs;
 }
-void System_Console_WriteLinei4v(int arg)
+static inline bool c_rt_f128_le(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    printf("%u\n", arg);
+    return lhs <= rhs;
 }
-#define System_String_Concatooos(...) System_String_Concat_()
-
-#define System_UIntPtr_get_MaxValueus() UINTPTR_MAX
-#define System_UIntPtr_get_MinValueus() ((uintptr_t)0)
-
-#define System_IntPtr_get_MaxValueis() INTPTR_MAX
-#define System_IntPtr_get_MinValueis() INTPTR_MIN
-
-#define System_Exception__ctor14System_Runtime16System_Exceptionsv(arg) arg
-float System_Single_Clampf4f4f4f4(float d, float min, float max)
+static inline bool c_rt_f128_ge(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    const float t = d < min ? min : d;
-    return t > max ? max : t;
+    return lhs >= rhs;
 }
-double System_Double_Clampf8f8f8f8(double d, double min, double max)
+#else
+typedef struct c_rt_f128
+{
+    _Alignas(16) uint64_t bits[2];
+} c_rt_f128;
+c_rt_f128 c_rt_f128_from_f64(double val) C_RT_SYM("__extenddftf2");
+double c_rt_f128_to_f64(c_rt_f128 val) C_RT_SYM("__trunctfdf2");
+c_rt_f128 c_rt_f128_from_i128(__int128 val) C_RT_SYM("__floattitf");
+c_rt_f128 c_rt_f128_from_u128(__uint128_t val) C_RT_SYM("__floatuntitf");
+__int128 c_rt_f128_to_i128_trunc(c_rt_f128 val) C_RT_SYM("__fixtfti");
+__uint128_t c_rt_f128_to_u128_trunc(c_rt_f128 val) C_RT_SYM("__fixunstfti");
+c_rt_f128 c_rt_f128_add(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__addtf3");
+c_rt_f128 c_rt_f128_sub(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__subtf3");
+c_rt_f128 c_rt_f128_mul(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__multf3");
+c_rt_f128 c_rt_f128_div(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__divtf3");
+int c_rt_eqtf2(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__eqtf2");
+int c_rt_lttf2(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__lttf2");
+int c_rt_gttf2(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__gttf2");
+int c_rt_letf2(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__letf2");
+int c_rt_getf2(c_rt_f128 lhs, c_rt_f128 rhs) C_RT_SYM("__getf2");
+// The comparison routines return a value which makes the comparison false if either argument is NaN.
+static inline bool c_rt_f128_eq(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    const double t = d < min ? min : d;
-    return t > max ? max : t;
+    return c_rt_eqtf2(lhs, rhs) == 0;
 }
-double System_Double_FusedMultiplyAddf8f8f8f8(double left, double right, double addend)
+static inline bool c_rt_f128_lt(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    return left * right + addend;
+    return c_rt_lttf2(lhs, rhs) < 0;
 }
-#define System_Type_GetTypeFromHandle14System_Runtime24System_RuntimeTypeHandle14System_Runtime11System_Type
-#define System_Object_GetHashCode14System_Runtime11System_Typei4
-float System_Single_MaxNumberf4f4f4(float a, float b)
+static inline bool c_rt_f128_gt(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    if (a > b)
-        return a;
-    else
-        return b;
+    return c_rt_gttf2(lhs, rhs) > 0;
 }
-double System_Double_MaxNumberf8f8f8(double a, double b)
+static inline bool c_rt_f128_le(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    if (a > b)
-        return a;
-    else
-        return b;
+    return c_rt_letf2(lhs, rhs) <= 0;
 }
-float System_Single_MinNumberf4f4f4(float a, float b)
+static inline bool c_rt_f128_ge(c_rt_f128 lhs, c_rt_f128 rhs)
 {
-    if (a < b)
-        return a;
-    else
-        return b;
+    return c_rt_getf2(lhs, rhs) >= 0;
 }
-double System_Double_MinNumberf8f8f8(double a, double b)
+#endif
+static inline __uint128_t c_rt_f128_to_bits(c_rt_f128 val)
 {
-    if (a < b)
-        return a;
+    __uint128_t bits;
+    __builtin_memcpy(&bits, &val, sizeof(bits));
+    return bits;
+}
+static inline c_rt_f128 c_rt_f128_from_bits(__uint128_t bits)
+{
+    c_rt_f128 res;
+    __builtin_memcpy(&res, &bits, sizeof(res));
+    return res;
+}
+static inline c_rt_f128 c_rt_f128_neg(c_rt_f128 val)
+{
+    return c_rt_f128_from_bits(c_rt_f128_to_bits(val) ^ ((__uint128_t)1 << 127));
+}
+static inline bool c_rt_f128_is_nan(c_rt_f128 val)
+{
+    return c_rt_f128_to_bits(val) << 1 > (__uint128_t)0x7fff << 113;
+}
+// `fmodf128` is missing from some C libraries, so the remainder is computed on the bits of the operands, like
+// musl does for `f64`. The result is always exact.
+static inline c_rt_f128 c_rt_f128_rem(c_rt_f128 lhs, c_rt_f128 rhs)
+{
+    const __uint128_t sign = (__uint128_t)1 << 127;
+    const __uint128_t implicit_bit = (__uint128_t)1 << 112;
+    __uint128_t x = c_rt_f128_to_bits(lhs);
+    __uint128_t y = c_rt_f128_to_bits(rhs);
+    int32_t ex = (int32_t)(x >> 112 & 0x7fff);
+    int32_t ey = (int32_t)(y >> 112 & 0x7fff);
+    __uint128_t x_sign = x & sign;
+    __uint128_t zero = x_sign;
+    if (y << 1 == 0 || c_rt_f128_is_nan(rhs) || ex == 0x7fff)
+        return c_rt_f128_from_bits((__uint128_t)0x7fff8 << 108);
+    if (x << 1 <= y << 1)
+        return x << 1 == y << 1 ? c_rt_f128_from_bits(zero) : lhs;
+    // Normalizes the significands, so that their highest set bit is the implicit bit.
+    if (ex == 0)
+    {
+        for (__uint128_t tmp = x << 16; !(tmp & sign); ex--, tmp <<= 1)
+            ;
+        x <<= -ex + 1;
+    }
     else
-        return b;
+        x = (x & (implicit_bit - 1)) | implicit_bit;
+    if (ey == 0)
+    {
+        for (__uint128_t tmp = y << 16; !(tmp & sign); ey--, tmp <<= 1)
+            ;
+        y <<= -ey + 1;
+    }
+    else
+        y = (y & (implicit_bit - 1)) | implicit_bit;
+    for (; ex > ey; ex--)
+    {
+        if (x >= y)
+        {
+            if (x == y)
+                return c_rt_f128_from_bits(zero);
+            x -= y;
+        }
+        x <<= 1;
+    }
+    if (x >= y)
+    {
+        if (x == y)
+            return c_rt_f128_from_bits(zero);
+        x -= y;
+    }
+    for (; !(x & implicit_bit); x <<= 1, ex--)
+        ;
+    // Scales the result back, turning it into a subnormal number if needed.
+    if (ex > 0)
+        x = (x - implicit_bit) | (__uint128_t)ex << 112;
+    else
+        x >>= -ex + 1;
+    return c_rt_f128_from_bits(x | x_sign);
+}
+// Stands in for valuetypes defined outside of the exported assembly.
+typedef struct c_rt_opaque
+{
+    uintptr_t data[2];
+} c_rt_opaque;
+
+static inline void c_rt_write_str(int fd, const char *str)
+{
+    size_t len = __builtin_strlen(str);
+    while (len > 0)
+    {
+        intptr_t written = c_rt_write(fd, str, len);
+        if (written <= 0)
+            return;
+        str += written;
+        len -= (size_t)written;
+    }
+}
+static inline _Noreturn void c_rt_fatal(const char *msg)
+{
+    c_rt_write_str(2, msg);
+    c_rt_write_str(2, "\n");
+    c_rt_abort();
+}
+static inline _Noreturn void c_rt_missing(const char *name)
+{
+    c_rt_write_str(2, "Missing method ");
+    c_rt_fatal(name);
+}
+// Objects of reference types are prefixed by a header, which stores their type. They are never freed.
+typedef struct c_rt_type
+{
+    const char *name;
+    const struct c_rt_type *parent;
+} c_rt_type;
+typedef union c_rt_header
+{
+    const c_rt_type *type;
+    max_align_t align;
+} c_rt_header;
+static const c_rt_type c_rt_exception_type = {"System.Exception", 0};
+// Used for types no object created by the runtime can have.
+static const c_rt_type c_rt_unknown_type = {"?", 0};
+// An object created by one of the `System.Exception` constructors.
+typedef struct c_rt_exception_obj
+{
+    const char *message;
+} c_rt_exception_obj;
+static inline void *c_rt_new(size_t size, const c_rt_type *type)
+{
+    c_rt_header *obj = (c_rt_header *)c_rt_calloc(1, sizeof(c_rt_header) + size);
+    if (!obj)
+        c_rt_fatal("Out of memory.");
+    obj->type = type;
+    return obj + 1;
+}
+static inline bool c_rt_isinst(void *obj, const c_rt_type *type)
+{
+    if (!obj)
+        return false;
+    for (const c_rt_type *curr = ((c_rt_header *)obj)[-1].type; curr; curr = curr->parent)
+        if (curr == type)
+            return true;
+    return false;
+}
+static inline void *c_rt_cast(void *obj, const c_rt_type *type)
+{
+    if (obj && !c_rt_isinst(obj, type))
+    {
+        c_rt_write_str(2, "InvalidCastException: can't cast an object of type ");
+        c_rt_write_str(2, ((c_rt_header *)obj)[-1].type->name);
+        c_rt_write_str(2, " to ");
+        c_rt_fatal(type->name);
+    }
+    return obj;
+}
+static inline void *c_rt_exception_new(const char *message)
+{
+    c_rt_exception_obj *obj = (c_rt_exception_obj *)c_rt_new(sizeof(c_rt_exception_obj), &c_rt_exception_type);
+    obj->message = message;
+    return obj;
+}
+// Exceptions are implemented with `setjmp` and `longjmp`. Each protected block pushes a frame, which is popped when
+// the block is left, or when an exception is thrown.
+typedef struct c_rt_frame
+{
+    jmp_buf buf;
+    struct c_rt_frame *prev;
+} c_rt_frame;
+static _Thread_local c_rt_frame *c_rt_top;
+static _Thread_local void *c_rt_exception;
+static inline _Noreturn void c_rt_throw(void *exception)
+{
+    c_rt_frame *frame = c_rt_top;
+    c_rt_exception = exception;
+    if (!frame)
+    {
+        c_rt_write_str(2, "Unhandled exception");
+        if (exception)
+        {
+            const c_rt_type *type = ((c_rt_header *)exception)[-1].type;
+            c_rt_write_str(2, " of type ");
+            c_rt_write_str(2, type->name);
+            if (type == &c_rt_exception_type && ((c_rt_exception_obj *)exception)->message)
+            {
+                c_rt_write_str(2, ": ");
+                c_rt_write_str(2, ((c_rt_exception_obj *)exception)->message);
+            }
+        }
+        c_rt_fatal("");
+    }
+    c_rt_top = frame->prev;
+    longjmp(frame->buf, 1);
 }
-float System_Single_FusedMultiplyAddf4f4f4f4(float left, float right, float addend)
+// Arrays store their length right before their first element.
+static int c_rt_argc;
+static char **c_rt_argv;
+static inline char **c_rt_args(void)
 {
-    return left * right + addend;
+    uintptr_t *arr = (uintptr_t *)c_rt_malloc(sizeof(uintptr_t) * ((size_t)c_rt_argc + 1));
+    if (!arr)
+        c_rt_fatal("Out of memory.");
+    arr[0] = (uintptr_t)c_rt_argc;
+    for (int idx = 0; idx < c_rt_argc; idx++)
+        arr[idx + 1] = (uintptr_t)c_rt_argv[idx];
+    return (char **)(arr + 1);
 }
-float System_Single_CopySignf4f4f4(float mag, float sign)
+#define c_rt_ld_len(arr) (((uintptr_t *)(arr))[-1])
+static inline void *c_rt_align_up(void *ptr, uintptr_t align)
 {
-    if (sign > 0)
-        return fabs(mag);
+    return (void *)(((uintptr_t)ptr + align - 1) & ~(align - 1));
+}
+// Aligned allocations store the pointer returned by malloc and their size in the two words before the allocation.
+static inline void *c_rt_aligned_alloc(uintptr_t size, uintptr_t align)
+{
+    if (align < _Alignof(max_align_t))
+        align = _Alignof(max_align_t);
+    char *raw = (char *)c_rt_malloc(size + align + 2 * sizeof(uintptr_t));
+    if (!raw)
+        return 0;
+    uintptr_t *aligned = (uintptr_t *)c_rt_align_up(raw + 2 * sizeof(uintptr_t), align);
+    aligned[-1] = size;
+    aligned[-2] = (uintptr_t)raw;
+    return aligned;
+}
+static inline void c_rt_aligned_free(void *ptr)
+{
+    if (ptr)
+        c_rt_free((void *)((uintptr_t *)ptr)[-2]);
+}
+static inline void *c_rt_aligned_realloc(void *ptr, uintptr_t size, uintptr_t align)
+{
+    void *res = c_rt_aligned_alloc(size, align);
+    if (res && ptr)
+    {
+        uintptr_t old_size = ((uintptr_t *)ptr)[-1];
+        __builtin_memcpy(res, ptr, old_size < size ? old_size : size);
+        c_rt_aligned_free(ptr);
+    }
+    return res;
+}
+static inline char *c_rt_concat(size_t count, ...)
+{
+    va_list args;
+    size_t len = 1;
+    va_start(args, count);
+    for (size_t idx = 0; idx < count; idx++)
+    {
+        const char *str = va_arg(args, const char *);
+        len += str ? __builtin_strlen(str) : 0;
+    }
+    va_end(args);
+    char *res = (char *)c_rt_malloc(len);
+    if (!res)
+        c_rt_fatal("Out of memory.");
+    res[0] = '\0';
+    va_start(args, count);
+    for (size_t idx = 0; idx < count; idx++)
+    {
+        const char *str = va_arg(args, const char *);
+        if (str)
+            __builtin_strcat(res, str);
+    }
+    va_end(args);
+    return res;
+}
+// Conversions from floats to ints saturate, like they do in .NET.
+#define C_RT_FLOAT_TO_INT(FNAME, FT, INAME, IT, LOW, HIGH, MIN, MAX) \
+    static inline IT c_rt_##FNAME##_to_##INAME(FT val)              \
+    {                                                                 \
+        if (val != val)                                               \
+            return 0;                                                 \
+        if ((long double)val < (LOW))                                 \
+            return (MIN);                                             \
+        if ((long double)val >= (HIGH))                               \
+            return (MAX);                                             \
+        return (IT)val;                                               \
+    }
+#define C_RT_U128_MAX (~(__uint128_t)0)
+#define C_RT_I128_MAX ((__int128)(C_RT_U128_MAX >> 1))
+#define C_RT_FLOAT_TO_INTS(FNAME, FT)                                                                         \
+    C_RT_FLOAT_TO_INT(FNAME, FT, i8, int8_t, -0x1p7L, 0x1p7L, INT8_MIN, INT8_MAX)                           \
+    C_RT_FLOAT_TO_INT(FNAME, FT, i16, int16_t, -0x1p15L, 0x1p15L, INT16_MIN, INT16_MAX)                     \
+    C_RT_FLOAT_TO_INT(FNAME, FT, i32, int32_t, -0x1p31L, 0x1p31L, INT32_MIN, INT32_MAX)                     \
+    C_RT_FLOAT_TO_INT(FNAME, FT, i64, int64_t, -0x1p63L, 0x1p63L, INT64_MIN, INT64_MAX)                     \
+    C_RT_FLOAT_TO_INT(FNAME, FT, isize, intptr_t, -0x1p63L, 0x1p63L, INTPTR_MIN, INTPTR_MAX)                \
+    C_RT_FLOAT_TO_INT(FNAME, FT, i128, __int128, -0x1p127L, 0x1p127L, -C_RT_I128_MAX - 1, C_RT_I128_MAX)   \
+    C_RT_FLOAT_TO_INT(FNAME, FT, u8, uint8_t, 0.0L, 0x1p8L, 0, UINT8_MAX)                                   \
+    C_RT_FLOAT_TO_INT(FNAME, FT, u16, uint16_t, 0.0L, 0x1p16L, 0, UINT16_MAX)                               \
+    C_RT_FLOAT_TO_INT(FNAME, FT, u32, uint32_t, 0.0L, 0x1p32L, 0, UINT32_MAX)                               \
+    C_RT_FLOAT_TO_INT(FNAME, FT, u64, uint64_t, 0.0L, 0x1p64L, 0, UINT64_MAX)                               \
+    C_RT_FLOAT_TO_INT(FNAME, FT, usize, uintptr_t, 0.0L, 0x1p64L, 0, UINTPTR_MAX)                           \
+    C_RT_FLOAT_TO_INT(FNAME, FT, u128, __uint128_t, 0.0L, 0x1p128L, 0, C_RT_U128_MAX)
+C_RT_FLOAT_TO_INTS(f32, float)
+C_RT_FLOAT_TO_INTS(f64, double)
+// `f128` is converted trough the 128-bit integers, since it may not support casts.
+#define C_RT_F128_TO_INT(INAME, IT, LOW, HIGH, MIN, MAX, FIX)                          \
+    static inline IT c_rt_f128_to_##INAME(c_rt_f128 val)                               \
+    {                                                                                  \
+        if (c_rt_f128_is_nan(val))                                                     \
+            return 0;                                                                  \
+        if (c_rt_f128_lt(val, c_rt_f128_from_f64(LOW)))                                \
+            return (MIN);                                                              \
+        if (c_rt_f128_ge(val, c_rt_f128_from_f64(HIGH)))                               \
+            return (MAX);                                                              \
+        return (IT)FIX(val);                                                           \
+    }
+C_RT_F128_TO_INT(i8, int8_t, -0x1p7, 0x1p7, INT8_MIN, INT8_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(i16, int16_t, -0x1p15, 0x1p15, INT16_MIN, INT16_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(i32, int32_t, -0x1p31, 0x1p31, INT32_MIN, INT32_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(i64, int64_t, -0x1p63, 0x1p63, INT64_MIN, INT64_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(isize, intptr_t, -0x1p63, 0x1p63, INTPTR_MIN, INTPTR_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(i128, __int128, -0x1p127, 0x1p127, -C_RT_I128_MAX - 1, C_RT_I128_MAX, c_rt_f128_to_i128_trunc)
+C_RT_F128_TO_INT(u8, uint8_t, 0.0, 0x1p8, 0, UINT8_MAX, c_rt_f128_to_u128_trunc)
+C_RT_F128_TO_INT(u16, uint16_t, 0.0, 0x1p16, 0, UINT16_MAX, c_rt_f128_to_u128_trunc)
+C_RT_F128_TO_INT(u32, uint32_t, 0.0, 0x1p32, 0, UINT32_MAX, c_rt_f128_to_u128_trunc)
+C_RT_F128_TO_INT(u64, uint64_t, 0.0, 0x1p64, 0, UINT64_MAX, c_rt_f128_to_u128_trunc)
+C_RT_F128_TO_INT(usize, uintptr_t, 0.0, 0x1p64, 0, UINTPTR_MAX, c_rt_f128_to_u128_trunc)
+C_RT_F128_TO_INT(u128, __uint128_t, 0.0, 0x1p128, 0, C_RT_U128_MAX, c_rt_f128_to_u128_trunc)
+static inline float c_rt_f32_from_bits(uint32_t bits)
+{
+    float res;
+    __builtin_memcpy(&res, &bits, sizeof(res));
+    return res;
+}
+static inline double c_rt_f64_from_bits(uint64_t bits)
+{
+    double res;
+    __builtin_memcpy(&res, &bits, sizeof(res));
+    return res;
+}
+// Formats floats like .NET does: using the shortest representation which round-trips.
+static inline void c_rt_fmt_float(char *buf, size_t len, double val, bool is_f32)
+{
+    if (val != val)
+    {
+        c_rt_snprintf(buf, len, "NaN");
+        return;
+    }
+    if (val == __builtin_inf() || val == -__builtin_inf())
+    {
+        c_rt_snprintf(buf, len, val > 0 ? "∞" : "-∞");
+        return;
+    }
+    int prec = 1;
+    for (; prec < 17; prec++)
+    {
+        c_rt_snprintf(buf, len, "%.*g", prec, val);
+        double parsed = c_rt_strtod(buf, 0);
+        if (is_f32 ? (float)parsed == (float)val : parsed == val)
+            break;
+    }
+    // .NET only uses the exponential notation for numbers bigger than 1E+15.
+    char exp_buf[32];
+    c_rt_snprintf(exp_buf, sizeof(exp_buf), "%.*e", prec - 1, val);
+    const char *exp_str = __builtin_strchr(exp_buf, 'e');
+    int exp = 0;
+    bool exp_neg = exp_str[1] == '-';
+    for (const char *curr = exp_str + 2; *curr; curr++)
+        exp = exp * 10 + (*curr - '0');
+    if (!exp_neg && exp < 15 && exp + 1 > prec)
+        prec = exp + 1;
+    c_rt_snprintf(buf, len, "%.*g", prec, val);
+    for (char *curr = buf; *curr; curr++)
+        if (*curr == 'e')
+            *curr = 'E';
+}
+static inline void c_rt_fmt_u128(char *buf, __uint128_t val)
+{
+    char tmp[40];
+    int len = 0;
+    do
+    {
+        tmp[len++] = (char)('0' + (int)(val % 10));
+        val /= 10;
+    } while (val);
+    for (int idx = 0; idx < len; idx++)
+        buf[idx] = tmp[len - idx - 1];
+    buf[len] = '\0';
+}
+static inline void c_rt_fmt_i128(char *buf, __int128 val)
+{
+    if (val < 0)
+    {
+        buf[0] = '-';
+        c_rt_fmt_u128(buf + 1, -(__uint128_t)val);
+    }
     else
-        return -fabs(mag);
+        c_rt_fmt_u128(buf, (__uint128_t)val);
 }
-double System_Double_CopySignf8f8f8(double mag, double sign)
+static inline void c_rt_fmt_char(char *buf, uint16_t val)
 {
-    if (sign > 0)
-        return fabs(mag);
+    if (val < 0x80)
+    {
+        buf[0] = (char)val;
+        buf[1] = '\0';
+    }
+    else if (val < 0x800)
+    {
+        buf[0] = (char)(0xC0 | (val >> 6));
+        buf[1] = (char)(0x80 | (val & 0x3F));
+        buf[2] = '\0';
+    }
     else
-        return -fabs(mag);
-}
\ No newline at end of file
+    {
+        buf[0] = (char)(0xE0 | (val >> 12));
+        buf[1] = (char)(0x80 | ((val >> 6) & 0x3F));
+        buf[2] = (char)(0x80 | (val & 0x3F));
+        buf[3] = '\0';
+    }
+}
+static inline int32_t c_rt_clz128(__uint128_t val)
+{
+    uint64_t high = (uint64_t)(val >> 64);
+    if (high)
+        return __builtin_clzll(high);
+    uint64_t low = (uint64_t)val;
+    return low ? 64 + __builtin_clzll(low) : 128;
+}
+static inline int32_t c_rt_ctz128(__uint128_t val)
+{
+    uint64_t low = (uint64_t)val;
+    if (low)
+        return __builtin_ctzll(low);
+    uint64_t high = (uint64_t)(val >> 64);
+    return high ? 64 + __builtin_ctzll(high) : 128;
+}
+static inline int32_t c_rt_popcount128(__uint128_t val)
+{
+    return __builtin_popcountll((uint64_t)val) + __builtin_popcountll((uint64_t)(val >> 64));
+}
+static inline __uint128_t c_rt_bswap128(__uint128_t val)
+{
+    return ((__uint128_t)__builtin_bswap64((uint64_t)val) << 64) | __builtin_bswap64((uint64_t)(val >> 64));
+}
@@ -1,457 +1,1200 @@
-// This exporter is WIP.
-#![allow(dead_code, unused_imports, unused_variables, clippy::let_unit_value)]
-use std::{collections::HashSet, io::Write};
+//! Exports an [`Assembly`] as a single, portable C file, compiled with `$CC`.
+//!
+//! Valuetypes are exported as unions, with each field placed at its offset. Reference types are heap-allocated
+//! structs, preceded by a header pointing to their type info, which is used to implement `isinst` and `castclass`.
+//! A class embeds its parent as its first member, so upcasts are just pointer casts. Exceptions are implemented
+//! with `setjmp`/`longjmp`: entering a protected block pushes a frame, and `c_rt_throw` jumps to the innermost one.
+//! Calls to .NET methods are replaced with C shims, and libc functions are called directly, trough declarations
+//! bound to their symbol names with `asm` labels. This avoids including any system headers, whose declarations could
+//! clash with the names used by Rust code. Virtual calls are statically dispatched, like in the other non-.NET
+//! backends. The runtime used by the exported code lives in `c_header.h`.
+use std::{collections::BTreeMap, io::Write};
 
-use fxhash::{hash64, FxHashSet, FxHasher};
-
-use crate::{
-    utilis::{assert_unique, encode},
-    v2::{BiMap, MethodImpl, StringIdx},
-};
+use fxhash::FxHashSet;
 
 use super::{
     asm::MAIN_MODULE,
     bimap::IntoBiMapIndex,
-    cilnode::{ExtendKind, PtrCastRes},
-    cilroot::BranchCond,
-    int,
+    cilnode::{ExtendKind, MethodKind, PtrCastRes, UnOp},
+    cilroot::{BranchCond, CmpKind},
+    layout::{class_layout, int_bits, size_align},
     method::LocalDef,
-    Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, ClassRef, ClassRefIdx,
-    Const, Exporter, Int, MethodDef, MethodRef, NodeIdx, RootIdx, SigIdx, Type,
+    Assembly, BinOp, CILNode, CILRoot, ClassDefIdx, ClassRefIdx, Const, Exporter, Float, Int,
+    MethodDef, MethodImpl, MethodRef, MethodRefIdx, NodeIdx, RootIdx, SigIdx, Type,
 };
-
+/// Identifiers which can't be used by exported code, because they are C keywords, or are used by the runtime.
+const RESERVED: &[&str] = &[
+    "_Alignas",
+    "_Alignof",
+    "_Atomic",
+    "_Bool",
+    "_Complex",
+    "_Generic",
+    "_Imaginary",
+    "_Noreturn",
+    "_Static_assert",
+    "_Thread_local",
+    "_longjmp",
+    "_setjmp",
+    "alignas",
+    "alignof",
+    "asm",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "char",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "errno",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "int16_t",
+    "int32_t",
+    "int64_t",
+    "int8_t",
+    "intptr_t",
+    "jmp_buf",
+    "long",
+    "longjmp",
+    "main",
+    "max_align_t",
+    "noreturn",
+    "NULL",
+    "offsetof",
+    "ptrdiff_t",
+    "register",
+    "restrict",
+    "return",
+    "setjmp",
+    "short",
+    "siglongjmp",
+    "signed",
+    "sigjmp_buf",
+    "sigsetjmp",
+    "size_t",
+    "sizeof",
+    "static",
+    "static_assert",
+    "struct",
+    "switch",
+    "thread_local",
+    "true",
+    "typedef",
+    "typeof",
+    "uint16_t",
+    "uint32_t",
+    "uint64_t",
+    "uint8_t",
+    "uintptr_t",
+    "union",
+    "unsigned",
+    "va_arg",
+    "va_copy",
+    "va_end",
+    "va_list",
+    "va_start",
+    "void",
+    "volatile",
+    "wchar_t",
+    "while",
+];
+/// Replaces all characters not allowed in C identifiers.
+fn escape_chars(ident: &str) -> String {
+    ident
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c.to_string(),
+            '.' => "_".into(),
+            _ => format!("_u{:x}_", c as u32),
+        })
+        .collect()
+}
 fn escape_ident(ident: &str) -> String {
-    let mut escaped = ident.replace('.', "_");
-    if escaped.chars().next().unwrap().is_numeric() {
+    let mut escaped = escape_chars(ident);
+    if escaped.chars().next().is_none_or(|c| c.is_ascii_digit()) {
         escaped = format!("p{escaped}");
     }
-    // Check if reserved.
-    match escaped.as_str() {
-        "int" => encode(hash64(&escaped)),
-        _ => escaped,
+    if RESERVED.contains(&escaped.as_str()) {
+        escaped.push('_');
     }
+    escaped
 }
-fn nonvoid_c_type(field_tpe: Type, asm: &Assembly) -> String {
-    match field_tpe {
-        Type::Void => "RustVoid".into(),
-        _ => c_tpe(field_tpe, asm),
-    }
-}
-fn c_tpe(field_tpe: Type, asm: &Assembly) -> String {
-    match field_tpe {
-        Type::Ptr(type_idx) | Type::Ref(type_idx) => format!("{}*", c_tpe(asm[type_idx], asm)),
-        Type::Int(int) => match int {
-            Int::U8 => "uint8_t".into(),
-            Int::U16 => "uint16_t".into(),
-            Int::U32 => "uint32_t".into(),
-            Int::U64 => "uint64_t".into(),
-            Int::U128 => "__uint128_t".into(),
-            Int::USize => "uintptr_t".into(),
-            Int::I8 => "int8_t".into(),
-            Int::I16 => "int16_t".into(),
-            Int::I32 => "int32_t".into(),
-            Int::I64 => "int64_t".into(),
-            Int::I128 => "__int128".into(),
-            Int::ISize => "intptr_t".into(),
-        },
-        Type::ClassRef(class_ref_idx) => {
-            format!("union {}", escape_ident(&asm[asm[class_ref_idx].name()]))
-        }
-        Type::Float(float) => match float {
-            super::Float::F16 => todo!(),
-            super::Float::F32 => "float".into(),
-            super::Float::F64 => "double".into(),
-            super::Float::F128 => todo!(),
+/// Mangles a signature, so that overloads of a method get distinct names.
+fn sig_mangle(sig: SigIdx, asm: &Assembly) -> String {
+    escape_chars(
+        &asm[sig]
+            .iter_types()
+            .map(|tpe| match tpe {
+                Type::PlatformGeneric(idx, _) => format!("g{idx}"),
+                _ => tpe.mangle(asm),
+            })
+            .collect::<String>(),
+    )
+}
+fn c_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for byte in string.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            // `?` is escaped to avoid trigraphs.
+            b'?' => escaped.push_str("\\?"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+fn c_float(val: f64, is_f32: bool) -> String {
+    let suffix = if is_f32 { "f" } else { "" };
+    if val.is_nan() {
+        // Keeps the payload of the NaN.
+        if is_f32 {
+            format!("c_rt_f32_from_bits({:#x}u)", (val as f32).to_bits())
+        } else {
+            format!("c_rt_f64_from_bits({:#x}ull)", val.to_bits())
+        }
+    } else if val.is_infinite() {
+        let sign = if val < 0.0 { "-" } else { "" };
+        format!("({sign}__builtin_inf{suffix}())")
+    } else if is_f32 {
+        format!("({:?}f)", val as f32)
+    } else {
+        format!("({val:?})")
+    }
+}
+fn c_int(int: Int) -> &'static str {
+    match int {
+        Int::U8 => "uint8_t",
+        Int::U16 => "uint16_t",
+        Int::U32 => "uint32_t",
+        Int::U64 => "uint64_t",
+        Int::U128 => "__uint128_t",
+        Int::USize => "uintptr_t",
+        Int::I8 => "int8_t",
+        Int::I16 => "int16_t",
+        Int::I32 => "int32_t",
+        Int::I64 => "int64_t",
+        Int::I128 => "__int128",
+        Int::ISize => "intptr_t",
+    }
+}
+fn unsigned_int(int: Int) -> Int {
+    match int {
+        Int::I8 => Int::U8,
+        Int::I16 => Int::U16,
+        Int::I32 => Int::U32,
+        Int::I64 => Int::U64,
+        Int::I128 => Int::U128,
+        Int::ISize => Int::USize,
+        _ => int,
+    }
+}
+fn signed_int(int: Int) -> Int {
+    match int {
+        Int::U8 => Int::I8,
+        Int::U16 => Int::I16,
+        Int::U32 => Int::I32,
+        Int::U64 => Int::I64,
+        Int::U128 => Int::I128,
+        Int::USize => Int::ISize,
+        _ => int,
+    }
+}
+/// Returns the unsigned type arithmetic on `int` is done in. Integers smaller than 32 bits would otherwise be promoted
+/// to a signed `int`, which could overflow.
+fn arith_int(int: Int) -> &'static str {
+    if int_bits(int) < 32 {
+        "uint32_t"
+    } else {
+        c_int(unsigned_int(int))
+    }
+}
+fn is_signed(tpe: Type) -> bool {
+    match tpe {
+        Type::Int(int) => int.is_signed(),
+        _ => false,
+    }
+}
+/// Checks if `cref` is exported as a C type defined in this file.
+fn is_defined(cref: ClassRefIdx, asm: &Assembly) -> bool {
+    asm.class_ref_to_def(cref).is_some()
+}
+fn class_tpe(cref: ClassRefIdx, asm: &Assembly) -> String {
+    let class = &asm[cref];
+    let name = &asm[class.name()];
+    match (is_defined(cref, asm), class.is_valuetype()) {
+        (true, true) => escape_ident(name),
+        (true, false) => format!("{}*", escape_ident(name)),
+        (false, false) => "void*".into(),
+        (false, true) => match name {
+            "System.Int128" => "__int128".into(),
+            "System.UInt128" => "__uint128_t".into(),
+            "System.Half" => "c_rt_f16".into(),
+            "System.RuntimeTypeHandle" => "uintptr_t".into(),
+            _ => "c_rt_opaque".into(),
         },
+    }
+}
+fn c_tpe(tpe: Type, asm: &Assembly) -> String {
+    match tpe {
+        Type::Ptr(inner) | Type::Ref(inner) => format!("{}*", c_tpe(asm[inner], asm)),
+        Type::Int(int) => c_int(int).into(),
+        Type::ClassRef(cref) => class_tpe(cref, asm),
+        Type::Float(float) => c_float_tpe(float).into(),
         Type::PlatformString => "char*".into(),
-        Type::PlatformChar => "char".into(),
-        Type::PlatformGeneric(_, generic_kind) => todo!(),
-        Type::PlatformObject => "void*".into(),
+        Type::PlatformChar => "uint16_t".into(),
+        Type::PlatformGeneric(_, _) | Type::PlatformObject | Type::FnPtr(_) => "void*".into(),
         Type::Bool => "bool".into(),
         Type::Void => "void".into(),
-        Type::PlatformArray { elem, dims } => format!(
-            "{elem}{dims}",
-            elem = c_tpe(asm[elem], asm),
-            dims = "*".repeat(dims.get() as usize)
-        )
-        .into(),
-        Type::FnPtr(_) => "void*".into(),
+        // Only single dimensional arrays are supported, so arrays are just pointers to their first element.
+        Type::PlatformArray { elem, .. } => format!("{}*", nonvoid_c_type(asm[elem], asm)),
+    }
+}
+/// Returns the C type of `tpe`, in a place where `void` is not allowed.
+fn nonvoid_c_type(tpe: Type, asm: &Assembly) -> String {
+    match tpe {
+        Type::Void => "RustVoid".into(),
+        _ => c_tpe(tpe, asm),
     }
 }
+/// Checks if values of type `tpe` can be converted with a C cast.
+fn is_scalar(tpe: Type, asm: &Assembly) -> bool {
+    match tpe {
+        Type::Void => false,
+        Type::ClassRef(cref) => {
+            !asm[cref].is_valuetype()
+                || !(is_defined(cref, asm)
+                    || matches!(class_tpe(cref, asm).as_str(), "c_rt_opaque" | "c_rt_f16"))
+        }
+        // `f16` and `f128` may be structs, if the C compiler does not support them.
+        Type::Float(Float::F16 | Float::F128) => false,
+        _ => true,
+    }
+}
+/// Converts `expr` to the C type of `tpe`. The IR allows some implicit conversions (e.g. between pointer types),
+/// which C compilers reject or warn about.
+fn coerce(expr: String, tpe: Type, asm: &Assembly) -> String {
+    if is_scalar(tpe, asm) {
+        format!("(({})({expr}))", c_tpe(tpe, asm))
+    } else {
+        expr
+    }
+}
+/// Returns a pointer to the type info used to check casts to `tpe`, or `None` if any object is an instance of `tpe`.
+fn type_info(tpe: Type, asm: &Assembly) -> Option<String> {
+    let Type::ClassRef(cref) = tpe else {
+        return None;
+    };
+    let name = &asm[asm[cref].name()];
+    if is_defined(cref, asm) {
+        return Some(format!("&c_type_{}", escape_ident(name)));
+    }
+    match name {
+        "System.Object" => None,
+        "System.Exception" => Some("&c_rt_exception_type".into()),
+        _ => Some("&c_rt_unknown_type".into()),
+    }
+}
+fn class_member_name(class_name: &str, member_name: &str) -> String {
+    if class_name == MAIN_MODULE {
+        member_name.into()
+    } else {
+        format!("{class_name}_{member_name}")
+    }
+}
+/// Returns the name of a method defined in this assembly.
 fn mref_to_name(mref: &MethodRef, asm: &Assembly) -> String {
-    let class = &asm[mref.class()];
-    let class_name = escape_ident(&asm[class.name()]);
+    let class_name = escape_ident(&asm[asm[mref.class()].name()]);
     let mname = escape_ident(&asm[mref.name()]);
-    if class.asm().is_some() {
-        let mangled = escape_ident(
-            &asm[mref.sig()]
-                .iter_types()
-                .map(|tpe| tpe.mangle(asm))
-                .collect::<String>(),
-        );
-
-        let stem = class_member_name(&class_name, &mname);
-        format!("{stem}{mangled}")
+    if class_name == MAIN_MODULE {
+        mname
     } else {
-        class_member_name(&class_name, &mname)
+        format!(
+            "{}{}",
+            class_member_name(&class_name, &mname),
+            sig_mangle(mref.sig(), asm)
+        )
     }
 }
-fn class_member_name(class_name: &str, method_name: &str) -> String {
-    if class_name == MAIN_MODULE {
-        method_name.into()
+/// Returns the name a native function is imported under. Functions like `printf` may be declared with different
+/// signatures, so the name includes the signature.
+fn extern_name(mref: &MethodRef, asm: &Assembly) -> String {
+    format!(
+        "c_ext_{}_{}",
+        escape_chars(&asm[mref.name()]),
+        sig_mangle(mref.sig(), asm)
+    )
+}
+/// Returns the number of fixed arguments of a known variadic libc function.
+fn variadic_args(name: &str) -> Option<usize> {
+    Some(match name {
+        "printf" | "syscall" | "prctl" => 1,
+        "fprintf" | "sprintf" | "dprintf" | "open" | "open64" | "fcntl" | "fcntl64" | "ioctl"
+        | "execl" | "execlp" => 2,
+        "snprintf" | "openat" | "openat64" => 3,
+        _ => return None,
+    })
+}
+/// Returns the C declaration of a function named `name`, taking `inputs` and returning `output`.
+fn c_sig(name: &str, inputs: &[Type], output: Type, first_arg: usize, asm: &Assembly) -> String {
+    let params = inputs
+        .iter()
+        .enumerate()
+        .map(|(arg, tpe)| format!("{} A{}", nonvoid_c_type(*tpe, asm), arg + first_arg))
+        .collect::<Vec<_>>();
+    let params = if params.is_empty() {
+        "void".into()
     } else {
-        format!("{class_name}_{method_name}")
+        params.join(", ")
+    };
+    format!("{} {name}({params})", c_tpe(output, asm))
+}
+fn int_cast(val: &str, src: Type, target: Int, extend: ExtendKind) -> String {
+    let tpe = c_int(target);
+    match src {
+        // Like in .NET 9, float to int conversions saturate.
+        Type::Float(float) => {
+            let (float, val) = match float {
+                Float::F16 => ("f32", format!("c_rt_f16_to_f32({val})")),
+                Float::F32 => ("f32", val.to_string()),
+                Float::F64 => ("f64", val.to_string()),
+                Float::F128 => ("f128", val.to_string()),
+            };
+            format!("c_rt_{float}_to_{}({val})", target.name())
+        }
+        Type::Int(src) => {
+            let src = match extend {
+                ExtendKind::ZeroExtend => unsigned_int(src),
+                ExtendKind::SignExtend => signed_int(src),
+            };
+            format!("(({tpe})({})({val}))", c_int(src))
+        }
+        Type::Bool | Type::PlatformChar => format!("(({tpe})({val}))"),
+        _ => format!("(({tpe})(uintptr_t)({val}))"),
     }
 }
-pub struct CExporter {
-    is_lib: bool,
+fn c_float_tpe(float: Float) -> &'static str {
+    match float {
+        Float::F16 => "c_rt_f16",
+        Float::F32 => "float",
+        Float::F64 => "double",
+        Float::F128 => "c_rt_f128",
+    }
 }
-impl CExporter {
-    #[must_use]
-    pub fn new(is_lib: bool) -> Self {
-        Self { is_lib }
+/// Converts `val` to a float. `f16` and `f128` values are converted with the helpers from `c_header.h`, which also
+/// work when the C compiler does not support those types.
+fn float_cast(val: &str, src: Type, target: Float, is_signed: bool) -> String {
+    let tpe = c_float_tpe(target);
+    match (src, target) {
+        (Type::Float(src), target) if src == target => val.to_string(),
+        (Type::Float(Float::F16), _) => float_cast(
+            &format!("c_rt_f16_to_f32({val})"),
+            Type::Float(Float::F32),
+            target,
+            true,
+        ),
+        (Type::Float(Float::F128), _) => float_cast(
+            &format!("c_rt_f128_to_f64({val})"),
+            Type::Float(Float::F64),
+            target,
+            true,
+        ),
+        (Type::Float(_), Float::F16) => format!("c_rt_f64_to_f16((double)({val}))"),
+        (Type::Float(_), Float::F128) => format!("c_rt_f128_from_f64((double)({val}))"),
+        // Like in .NET, ints are converted to `f16` trough `f32`.
+        (Type::Int(_), Float::F16) => format!(
+            "c_rt_f32_to_f16({})",
+            float_cast(val, src, Float::F32, is_signed)
+        ),
+        (Type::Int(int), Float::F128) if is_signed => {
+            format!(
+                "c_rt_f128_from_i128((__int128)({})({val}))",
+                c_int(signed_int(int))
+            )
+        }
+        (Type::Int(int), Float::F128) => format!(
+            "c_rt_f128_from_u128((__uint128_t)({})({val}))",
+            c_int(unsigned_int(int))
+        ),
+        (Type::Int(int), _) => {
+            let int = if is_signed {
+                signed_int(int)
+            } else {
+                unsigned_int(int)
+            };
+            format!("(({tpe})({})({val}))", c_int(int))
+        }
+        (_, Float::F16 | Float::F128) => float_cast(
+            &format!("(uintptr_t)({val})"),
+            Type::Int(Int::USize),
+            target,
+            false,
+        ),
+        _ => format!("(({tpe})({val}))"),
     }
-    fn export_method_decl(
-        asm: &Assembly,
-        mref: &MethodRef,
-        method_decls: &mut impl Write,
-    ) -> std::io::Result<()> {
-        let method_name = mref_to_name(mref, asm);
-        let output = c_tpe(mref.output(asm), asm);
-        let inputs = mref
-            .stack_inputs(asm)
-            .iter()
-            .map(|i| nonvoid_c_type(*i, asm))
-            .intersperse(",".into())
-            .collect::<String>();
-
-        writeln!(method_decls, "{output} {method_name}({inputs});")
-    }
-    #[allow(clippy::too_many_arguments)]
-    fn binop_to_string(
-        lhs: CILNode,
-        rhs: CILNode,
-        op: BinOp,
-        tpe: Type,
-        asm: &mut Assembly,
-        locals: &[LocalDef],
-        inputs: &[(Type, Option<StringIdx>)],
-        sig: SigIdx,
-    ) -> String {
-        let lhs = Self::node_to_string(lhs, asm, locals, inputs, sig);
-        let rhs = Self::node_to_string(rhs, asm, locals, inputs, sig);
-        match op {
-            BinOp::Add => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => format!(
-                    "({tpe}*)((void*)({lhs}) + (uintptr_t)({rhs}))",
-                    tpe = c_tpe(asm[type_idx], asm)
+}
+fn convert(val: &str, src: Type, target: Type) -> Option<String> {
+    Some(match (src, target) {
+        (_, Type::Int(target)) => {
+            let extend = if is_signed(src) {
+                ExtendKind::SignExtend
+            } else {
+                ExtendKind::ZeroExtend
+            };
+            int_cast(val, src, target, extend)
+        }
+        (_, Type::Float(target)) => float_cast(val, src, target, is_signed(src)),
+        _ => return None,
+    })
+}
+/// Compares `lhs` and `rhs`. Unsigned float comparisons are unordered, and are true if either value is NaN.
+fn compare(lhs: &str, rhs: &str, tpe: Type, op: &str, unsigned: bool) -> String {
+    match tpe {
+        Type::Int(int) => {
+            let int = if unsigned || op == "==" || op == "!=" {
+                unsigned_int(int)
+            } else {
+                signed_int(int)
+            };
+            format!("(({int})({lhs}) {op} ({int})({rhs}))", int = c_int(int))
+        }
+        // `f16` values are compared as `f32`s, like .NET does.
+        Type::Float(Float::F16) => compare(
+            &format!("c_rt_f16_to_f32({lhs})"),
+            &format!("c_rt_f16_to_f32({rhs})"),
+            Type::Float(Float::F32),
+            op,
+            unsigned,
+        ),
+        Type::Float(Float::F128) => {
+            let (func, negate) = match (op, unsigned) {
+                ("==", _) => ("eq", false),
+                ("!=", _) => ("eq", true),
+                ("<", false) => ("lt", false),
+                (">", false) => ("gt", false),
+                ("<=", false) => ("le", false),
+                (">=", false) => ("ge", false),
+                ("<", true) => ("ge", true),
+                (">", true) => ("le", true),
+                ("<=", true) => ("gt", true),
+                (">=", true) => ("lt", true),
+                _ => panic!("Can't compare f128s using {op}"),
+            };
+            let negate = if negate { "!" } else { "" };
+            format!("({negate}c_rt_f128_{func}({lhs}, {rhs}))")
+        }
+        Type::Float(_) if unsigned => {
+            let inverse = match op {
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                _ => return format!("(({lhs}) {op} ({rhs}))"),
+            };
+            format!("(!(({lhs}) {inverse} ({rhs})))")
+        }
+        Type::Float(_) | Type::Bool | Type::PlatformChar => format!("(({lhs}) {op} ({rhs}))"),
+        _ => format!("((uintptr_t)({lhs}) {op} (uintptr_t)({rhs}))"),
+    }
+}
+/// Writes `val`, of type `tpe`, to `buf`, in the format used by .NET.
+fn fmt_value(val: &str, tpe: Type) -> Option<String> {
+    Some(match tpe {
+        Type::Int(Int::I128) => format!("c_rt_fmt_i128(buf, {val});"),
+        Type::Int(Int::U128) => format!("c_rt_fmt_u128(buf, {val});"),
+        Type::Int(int) if int.is_signed() => {
+            format!("c_rt_snprintf(buf, sizeof(buf), \"%lld\", (long long)({val}));")
+        }
+        Type::Int(_) => {
+            format!("c_rt_snprintf(buf, sizeof(buf), \"%llu\", (unsigned long long)({val}));")
+        }
+        Type::Float(float) => format!(
+            "c_rt_fmt_float(buf, sizeof(buf), {}, {});",
+            float_cast(val, tpe, Float::F64, true),
+            matches!(float, Float::F16 | Float::F32)
+        ),
+        Type::Bool => {
+            format!("c_rt_snprintf(buf, sizeof(buf), \"%s\", ({val}) ? \"True\" : \"False\");")
+        }
+        Type::PlatformChar => format!("c_rt_fmt_char(buf, {val});"),
+        _ => return None,
+    })
+}
+fn math_fn(name: &str, args: &[String], output: Type) -> Option<String> {
+    let func = match name {
+        "Sqrt" | "sqrt" => "sqrt",
+        "Cbrt" | "cbrt" => "cbrt",
+        "Abs" | "fabs" => "fabs",
+        "Floor" | "floor" => "floor",
+        "Ceiling" | "ceil" => "ceil",
+        "Truncate" | "trunc" => "trunc",
+        "round" => "round",
+        // .NET rounds to even by default.
+        "Round" | "rint" | "nearbyint" | "roundeven" => "rint",
+        "Exp" | "exp" => "exp",
+        "Exp2" | "exp2" => "exp2",
+        "expm1" => "expm1",
+        "Log" | "log" if args.len() == 1 => "log",
+        "Log2" | "log2" => "log2",
+        "Log10" | "log10" => "log10",
+        "log1p" => "log1p",
+        "Pow" | "pow" => "pow",
+        "Sin" | "sin" => "sin",
+        "Cos" | "cos" => "cos",
+        "Tan" | "tan" => "tan",
+        "Asin" | "asin" => "asin",
+        "Acos" | "acos" => "acos",
+        "Atan" | "atan" => "atan",
+        "Atan2" | "atan2" => "atan2",
+        "Sinh" | "sinh" => "sinh",
+        "Cosh" | "cosh" => "cosh",
+        "Tanh" | "tanh" => "tanh",
+        "Asinh" | "asinh" => "asinh",
+        "Acosh" | "acosh" => "acosh",
+        "Atanh" | "atanh" => "atanh",
+        "hypot" => "hypot",
+        "fmod" => "fmod",
+        "MinNumber" | "fmin" => "fmin",
+        "MaxNumber" | "fmax" => "fmax",
+        "CopySign" | "copysign" => "copysign",
+        "FusedMultiplyAdd" | "fma" => "fma",
+        "ScaleB" | "ldexp" => "ldexp",
+        _ => return None,
+    };
+    let args = args.join(", ");
+    Some(match output {
+        Type::Float(Float::F32) => format!("__builtin_{func}f({args})"),
+        _ => format!("__builtin_{func}({args})"),
+    })
+}
+/// Returns the body of a shim for the .NET method `class::name`.
+fn dotnet_shim(
+    class: &str,
+    name: &str,
+    kind: MethodKind,
+    inputs: &[Type],
+    output: Type,
+    asm: &Assembly,
+) -> Option<String> {
+    let args: Vec<String> = (0..inputs.len()).map(|arg| format!("A{arg}")).collect();
+    let ret = |expr: String| match output {
+        Type::Void => format!("{expr};"),
+        _ => format!("return {};", coerce(expr, output, asm)),
+    };
+    let int = |idx: usize| match inputs.get(idx) {
+        Some(Type::Int(int)) => Some(*int),
+        _ => None,
+    };
+    Some(match (class, name) {
+        ("System.Console", "WriteLine") if inputs.is_empty() => {
+            "c_rt_write_str(1, \"\\n\");".into()
+        }
+        ("System.Console", "WriteLine" | "Write") => {
+            let newline = if name == "WriteLine" { "\\n" } else { "" };
+            let write = match inputs[0] {
+                Type::PlatformString => "c_rt_write_str(1, A0 ? A0 : \"\");".into(),
+                tpe => format!(
+                    "char buf[64];\n{}\nc_rt_write_str(1, buf);",
+                    fmt_value(&args[0], tpe)?
                 ),
-                Type::FnPtr(_) => format!("({lhs}) + ({rhs})"),
-                Type::Float(_) => format!("({lhs}) + ({rhs})"),
-                Type::Int(int) => match int {
-                    Int::I128 => format!("(__int128)((__uint128_t)({lhs}) + (__uint128_t)({rhs}))"),
-                    _ => format!("({lhs}) + ({rhs})"),
-                },
-                _ => todo!(),
-            },
-            BinOp::Eq => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) == (void*)({rhs})",)
+            };
+            format!("{write}\nc_rt_write_str(1, \"{newline}\");")
+        }
+        ("System.Int128" | "System.UInt128", ".ctor") => {
+            // The value is built from its upper and lower 64 bits.
+            let val = format!(
+                "(((__uint128_t)(uint64_t)({}) << 64) | (uint64_t)({}))",
+                args[args.len() - 2],
+                args[args.len() - 1]
+            );
+            match kind {
+                MethodKind::Constructor => ret(val),
+                _ => format!("*A0 = {val};"),
+            }
+        }
+        ("System.Int128" | "System.UInt128", _) if name.starts_with("op_") => {
+            let (a, b) = (args.first().cloned(), args.get(1).cloned());
+            // Wrapping operations are done on unsigned values, to avoid UB on overflow.
+            let wrapping =
+                |op: &str| ret(format!("((__uint128_t)({}) {op} (__uint128_t)({}))", a.clone().unwrap(), b.clone().unwrap()));
+            let bin = |op: &str| ret(format!("(({}) {op} ({}))", a.clone().unwrap(), b.clone().unwrap()));
+            match name {
+                "op_Addition" => wrapping("+"),
+                "op_Subtraction" => wrapping("-"),
+                "op_Multiply" => wrapping("*"),
+                "op_Division" => bin("/"),
+                "op_Modulus" => bin("%"),
+                "op_BitwiseAnd" => bin("&"),
+                "op_BitwiseOr" => bin("|"),
+                "op_ExclusiveOr" => bin("^"),
+                "op_Equality" => bin("=="),
+                "op_Inequality" => bin("!="),
+                "op_LessThan" => bin("<"),
+                "op_GreaterThan" => bin(">"),
+                "op_LessThanOrEqual" => bin("<="),
+                "op_GreaterThanOrEqual" => bin(">="),
+                "op_LeftShift" => ret(format!("((__uint128_t)({}) << (({}) & 127))", a?, b?)),
+                "op_RightShift" => ret(format!("(({}) >> (({}) & 127))", a?, b?)),
+                "op_UnsignedRightShift" => {
+                    ret(format!("((__uint128_t)({}) >> (({}) & 127))", a?, b?))
                 }
-                Type::FnPtr(_) => format!("({lhs}) == ({rhs})"),
-                Type::Bool | Type::Float(_) | Type::Int(_) => format!("({lhs}) == ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::Sub => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => format!(
-                    "({tpe}*)((void*)({lhs}) - (uintptr_t)({rhs}))",
-                    tpe = c_tpe(asm[type_idx], asm)
-                ),
-                Type::FnPtr(_) => format!("({lhs}) - ({rhs})"),
-                Type::Float(_) | Type::Int(_) => format!("({lhs}) - ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::Mul => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => format!(
-                    "({tpe}*)((void*)({lhs}) * (uintptr_t)({rhs}))",
-                    tpe = c_tpe(asm[type_idx], asm)
+                "op_OnesComplement" => ret(format!("(~({}))", a?)),
+                "op_UnaryNegation" => ret(format!("(0 - (__uint128_t)({}))", a?)),
+                "op_Explicit" | "op_Implicit" => ret(convert(&a?, inputs[0], output)?),
+                _ => return None,
+            }
+        }
+        (_, "op_Explicit" | "op_Implicit") => ret(convert(&args[0], inputs[0], output)?),
+        ("System.Half" | "System.Single" | "System.Double", "IsNaN") => {
+            ret(format!("(({a}) != ({a}))", a = float_cast(&args[0], inputs[0], Float::F32, true)))
+        }
+        // Like in .NET, `System.Half` operations are done on `f32`s.
+        ("System.Half", _) => {
+            let args: Vec<String> = args
+                .iter()
+                .zip(inputs)
+                .map(|(arg, tpe)| match tpe {
+                    Type::Float(Float::F16) => format!("c_rt_f16_to_f32({arg})"),
+                    _ => arg.clone(),
+                })
+                .collect();
+            let f32_tpe = Type::Float(Float::F32);
+            let bin = |op: &str| format!("(({}) {op} ({}))", args[0], args[1]);
+            let res = match name {
+                "op_Addition" => bin("+"),
+                "op_Subtraction" => bin("-"),
+                "op_Multiply" => bin("*"),
+                "op_Division" => bin("/"),
+                "op_Modulus" => format!("__builtin_fmodf({}, {})", args[0], args[1]),
+                "op_UnaryNegation" => format!("(-({}))", args[0]),
+                "op_Equality" => return Some(ret(bin("=="))),
+                "op_Inequality" => return Some(ret(bin("!="))),
+                "op_LessThan" => return Some(ret(bin("<"))),
+                "op_GreaterThan" => return Some(ret(bin(">"))),
+                "op_LessThanOrEqual" => return Some(ret(bin("<="))),
+                "op_GreaterThanOrEqual" => return Some(ret(bin(">="))),
+                // The second argument is `MidpointRounding.AwayFromZero`.
+                "Round" if args.len() == 2 => math_fn("round", &args[..1], f32_tpe)?,
+                _ => math_fn(name, &args, f32_tpe)?,
+            };
+            ret(format!("c_rt_f32_to_f16({res})"))
+        }
+        ("System.Buffers.Binary.BinaryPrimitives", "ReverseEndianness") => {
+            let int = int(0)?;
+            ret(match int_bits(int) {
+                8 => args[0].clone(),
+                16 => format!("__builtin_bswap16((uint16_t)({}))", args[0]),
+                32 => format!("__builtin_bswap32((uint32_t)({}))", args[0]),
+                64 => format!("__builtin_bswap64((uint64_t)({}))", args[0]),
+                _ => format!("c_rt_bswap128((__uint128_t)({}))", args[0]),
+            })
+        }
+        ("System.Numerics.BitOperations", _) => {
+            let int = int(0)?;
+            let bits = int_bits(int);
+            let val = format!("(({})({}))", c_int(unsigned_int(int)), args[0]);
+            let (suffix, builtin_bits) = match bits {
+                64 => ("ll", 64),
+                _ => ("", 32),
+            };
+            ret(match (name, bits) {
+                ("PopCount", 128) => format!("c_rt_popcount128({val})"),
+                ("PopCount", _) => format!("__builtin_popcount{suffix}({val})"),
+                ("LeadingZeroCount", 128) => format!("c_rt_clz128({val})"),
+                ("LeadingZeroCount", _) => format!(
+                    "({val} == 0 ? {bits} : __builtin_clz{suffix}({val}) - {})",
+                    builtin_bits - bits
                 ),
-                Type::FnPtr(_) => format!("({lhs}) * ({rhs})"),
-                Type::Float(_) => format!("({lhs}) * ({rhs})"),
-                Type::Int(int) => match int {
-                    // Signed multiply is seemingly equivalent to unsigned multiply, looking at the assembly: TODO: check this.
-                    Int::I8 => format!("(int8_t)((uint8_t)({lhs}) * (uint8_t)({rhs}))"),
-                    Int::I16 => format!("(int16_t)((uint16_t)({lhs}) * (uint16_t)({rhs}))"),
-                    Int::I32 => format!("(int32_t)((uint32_t)({lhs}) * (uint32_t)({rhs}))"),
-                    Int::I64 => format!("(int64_t)((uint64_t)({lhs}) * (uint64_t)({rhs}))"),
-                    Int::I128 => format!("(__int128)((__uint128_t)({lhs}) * (__uint128_t)({rhs}))"),
-                    Int::ISize => format!("(intptr_t)((uintptr_t)({lhs}) * (uintptr_t)({rhs}))"),
-                    _ => format!("({lhs}) * ({rhs})"),
-                },
-                _ => todo!(),
-            },
-            BinOp::LtUn | BinOp::Lt => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) < (void*)({rhs})",)
-                }
-                Type::FnPtr(_) => format!("({lhs}) < ({rhs})"),
-                Type::Bool | Type::Float(_) | Type::Int(_) => format!("({lhs}) < ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::GtUn | BinOp::Gt => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) > (void*)({rhs})",)
+                ("Log2", 128) => format!("(127 - c_rt_clz128({val} | 1))"),
+                ("Log2", _) => {
+                    format!("({} - __builtin_clz{suffix}({val} | 1))", builtin_bits - 1)
                 }
-                Type::FnPtr(_) => format!("({lhs}) > ({rhs})"),
-                Type::Bool | Type::Float(_) | Type::Int(_) => format!("({lhs}) > ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::Or => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) | (void*)({rhs})",)
+                ("TrailingZeroCount", 128) => format!("c_rt_ctz128({val})"),
+                ("TrailingZeroCount", _) => {
+                    format!("({val} == 0 ? {bits} : __builtin_ctz{suffix}({val}))")
                 }
-                Type::FnPtr(_) => format!("({lhs}) | ({rhs})"),
-                Type::Int(_) => format!("({lhs}) | ({rhs})"),
-                Type::Bool => format!("({lhs}) || ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::XOr => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) ^ (void*)({rhs})",)
-                }
-                Type::FnPtr(_) => format!("({lhs}) ^ ({rhs})"),
-                Type::Int(_) => format!("({lhs}) ^ ({rhs})"),
-                Type::Bool => format!("({lhs}) != ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::And => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => {
-                    format!("(void*)({lhs}) & (void*)({rhs})",)
+                ("RotateLeft" | "RotateRight", _) => {
+                    let mask = bits - 1;
+                    let (left, right) = if name == "RotateLeft" {
+                        ("<<", ">>")
+                    } else {
+                        (">>", "<<")
+                    };
+                    format!(
+                        "(({val} {left} (({amount}) & {mask})) | ({val} {right} (({bits} - (({amount}) & {mask})) & {mask})))",
+                        amount = args[1]
+                    )
                 }
-                Type::FnPtr(_) => format!("({lhs}) & ({rhs})"),
-                Type::Int(_) => format!("({lhs}) & ({rhs})"),
-                Type::Bool => format!("({lhs}) && ({rhs})"),
-                _ => todo!(),
-            },
-            BinOp::Rem | BinOp::RemUn => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => format!(
-                    "({tpe}*)((void*)({lhs}) % (uintptr_t)({rhs}))",
-                    tpe = c_tpe(asm[type_idx], asm)
-                ),
-                Type::FnPtr(_) => format!("({lhs}) % ({rhs})"),
-                Type::Int(_) => format!("({lhs}) % ({rhs})"),
-                Type::Float(flt) => match flt {
-                    super::Float::F16 => todo!(),
-                    super::Float::F32 => format!("(float)fmod((double)({lhs}),((double)({rhs}))"),
-                    super::Float::F64 => format!("fmod(({lhs}),({rhs}))"),
-                    super::Float::F128 => todo!(),
-                },
-                // TODO: reminder of a bool can only be false or a segfault. Is this a valid operation?
-                Type::Bool => "false".into(),
-
-                _ => todo!("can't rem {tpe:?}"),
-            },
-            BinOp::Shl => match tpe {
-                Type::Int(_) => format!("({lhs}) << ({rhs})"),
-                _ => todo!("can't shl {tpe:?}"),
-            },
-            BinOp::Shr | BinOp::ShrUn => match tpe {
-                Type::Int(_) => format!("({lhs}) >> ({rhs})"),
-                _ => todo!("can't shr {tpe:?}"),
-            },
-            BinOp::DivUn | BinOp::Div => match tpe {
-                Type::Ptr(type_idx) | Type::Ref(type_idx) => format!(
-                    "({tpe}*)((void*)({lhs}) / (uintptr_t)({rhs}))",
-                    tpe = c_tpe(asm[type_idx], asm)
+                _ => return None,
+            })
+        }
+        ("System.Math", "Max" | "Min") if int(0).is_some() => {
+            let op = if name == "Max" { ">=" } else { "<=" };
+            ret(format!(
+                "(({a}) {op} ({b}) ? ({a}) : ({b}))",
+                a = args[0],
+                b = args[1]
+            ))
+        }
+        ("System.Math" | "System.MathF", "Abs") if int(0).is_some() => ret(format!(
+            "(({a}) < 0 ? 0 - ({a}) : ({a}))",
+            a = args[0]
+        )),
+        ("System.Math" | "System.MathF" | "System.Single" | "System.Double", "Max" | "Min") => {
+            // Unlike `fmax` and `fmin`, .NET propagates NaNs.
+            let func = if name == "Max" { "MaxNumber" } else { "MinNumber" };
+            ret(format!(
+                "(({a}) != ({a}) || ({b}) != ({b}) ? ({a}) + ({b}) : {})",
+                math_fn(func, &args, output)?,
+                a = args[0],
+                b = args[1]
+            ))
+        }
+        ("System.Math" | "System.MathF" | "System.Single" | "System.Double", "Clamp") => {
+            ret(format!(
+                "(({v}) < ({lo}) ? ({lo}) : ({v}) > ({hi}) ? ({hi}) : ({v}))",
+                v = args[0],
+                lo = args[1],
+                hi = args[2]
+            ))
+        }
+        ("System.Math" | "System.MathF" | "System.Single" | "System.Double", _) => {
+            ret(math_fn(name, &args, output)?)
+        }
+        ("System.Runtime.InteropServices.Marshal", "AllocHGlobal") => {
+            ret(format!("c_rt_malloc((size_t)({}))", args[0]))
+        }
+        ("System.Runtime.InteropServices.Marshal", "ReAllocHGlobal") => ret(format!(
+            "c_rt_realloc((void*)({}), (size_t)({}))",
+            args[0], args[1]
+        )),
+        ("System.Runtime.InteropServices.Marshal", "FreeHGlobal") => {
+            format!("c_rt_free((void*)({}));", args[0])
+        }
+        ("System.Runtime.InteropServices.Marshal", "StringToCoTaskMemUTF8") => {
+            ret(format!("c_rt_concat(1, {})", args[0]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedAlloc") => ret(format!(
+            "c_rt_aligned_alloc((size_t)({}), (size_t)({}))",
+            args[0], args[1]
+        )),
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedRealloc") => ret(format!(
+            "c_rt_aligned_realloc((void*)({}), (size_t)({}), (size_t)({}))",
+            args[0], args[1], args[2]
+        )),
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedFree") => {
+            format!("c_rt_aligned_free((void*)({}));", args[0])
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "Alloc") => {
+            ret(format!("c_rt_malloc((size_t)({}))", args[0]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "AllocZeroed") => {
+            ret(format!("c_rt_calloc(1, (size_t)({}))", args[0]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "Realloc") => ret(format!(
+            "c_rt_realloc((void*)({}), (size_t)({}))",
+            args[0], args[1]
+        )),
+        ("System.Runtime.InteropServices.NativeMemory", "Free") => {
+            format!("c_rt_free((void*)({}));", args[0])
+        }
+        ("System.Threading.Interlocked", "CompareExchange") => format!(
+            "__atomic_compare_exchange_n({}, &{}, {}, false, __ATOMIC_SEQ_CST, __ATOMIC_SEQ_CST);\nreturn {};",
+            args[0], args[2], args[1], args[2]
+        ),
+        ("System.Threading.Interlocked", "Exchange") => ret(format!(
+            "__atomic_exchange_n({}, {}, __ATOMIC_SEQ_CST)",
+            args[0], args[1]
+        )),
+        // `Interlocked.Add` returns the new value.
+        ("System.Threading.Interlocked", "Add") => ret(format!(
+            "__atomic_add_fetch({}, {}, __ATOMIC_SEQ_CST)",
+            args[0], args[1]
+        )),
+        ("System.Threading.Interlocked", "Increment") => {
+            ret(format!("__atomic_add_fetch({}, 1, __ATOMIC_SEQ_CST)", args[0]))
+        }
+        ("System.Threading.Interlocked", "Decrement") => {
+            ret(format!("__atomic_sub_fetch({}, 1, __ATOMIC_SEQ_CST)", args[0]))
+        }
+        // `Interlocked.And` and `Interlocked.Or` return the original value.
+        ("System.Threading.Interlocked", "And") => ret(format!(
+            "__atomic_fetch_and({}, {}, __ATOMIC_SEQ_CST)",
+            args[0], args[1]
+        )),
+        ("System.Threading.Interlocked", "Or") => ret(format!(
+            "__atomic_fetch_or({}, {}, __ATOMIC_SEQ_CST)",
+            args[0], args[1]
+        )),
+        ("System.Threading.Interlocked", "Read") => {
+            ret(format!("__atomic_load_n({}, __ATOMIC_SEQ_CST)", args[0]))
+        }
+        ("System.Threading.Interlocked" | "System.Threading.Thread", "MemoryBarrier") => {
+            "__atomic_thread_fence(__ATOMIC_SEQ_CST);".into()
+        }
+        ("System.Environment", "GetCommandLineArgs") => ret("c_rt_args()".into()),
+        ("System.Environment", "Exit") => format!("c_rt_exit((int)({}));", args[0]),
+        ("System.Environment", "FailFast") => format!("c_rt_fatal((const char*)({}));", args[0]),
+        // The environment is not exposed, so it looks empty.
+        ("System.Environment", "GetEnvironmentVariables" | "GetEnvironmentVariable")
+        | ("System.Collections.IDictionary", "GetEnumerator") => ret("0".into()),
+        ("System.Collections.ICollection", "get_Count") => ret("0".into()),
+        ("System.Collections.IEnumerator", "MoveNext") => ret("false".into()),
+        ("System.Exception", ".ctor") => match kind {
+            MethodKind::Constructor if inputs.is_empty() => ret("c_rt_exception_new(0)".into()),
+            MethodKind::Constructor => ret(format!("c_rt_exception_new({})", args[0])),
+            // Calls to the base constructor don't need to do anything.
+            _ => String::new(),
+        },
+        ("System.String", "Concat") => ret(format!(
+            "c_rt_concat({}, {})",
+            args.len(),
+            args.iter()
+                .map(|arg| format!("(const char*)({arg})"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        ("System.Type", "GetTypeFromHandle") => ret(format!("(void*)({})", args[0])),
+        ("System.Type", "op_Equality") => ret(format!("(({}) == ({}))", args[0], args[1])),
+        ("System.Object", "GetHashCode") => ret(format!("(int32_t)(uintptr_t)({})", args[0])),
+        ("System.UIntPtr", "get_MaxValue") => ret("UINTPTR_MAX".into()),
+        ("System.UIntPtr", "get_MinValue") => ret("0".into()),
+        ("System.IntPtr", "get_MaxValue") => ret("INTPTR_MAX".into()),
+        ("System.IntPtr", "get_MinValue") => ret("INTPTR_MIN".into()),
+        _ => return None,
+    })
+}
+/// State shared by all the methods of a module.
+#[derive(Default)]
+struct ModuleCtx {
+    /// Shims for the .NET methods called by this module.
+    externs: BTreeMap<String, String>,
+}
+impl ModuleCtx {
+    /// Returns the name of the C function implementing the method `mref_idx`.
+    fn method_path(&mut self, mref_idx: MethodRefIdx, asm: &Assembly) -> String {
+        let mref = &asm[mref_idx];
+        if asm[mref.class()].asm().is_some() {
+            return self.extern_path(mref, asm);
+        }
+        let is_native = asm
+            .method_ref_to_def(mref_idx)
+            .is_some_and(|def| asm[def].resolved_implementation(asm).is_extern());
+        match mref.kind() {
+            // `fmodf128` is missing from some C libraries.
+            _ if is_native && &asm[mref.name()] == "fmodf128" => "c_rt_f128_rem".into(),
+            _ if is_native => extern_name(mref, asm),
+            MethodKind::Constructor => format!("{}_new", mref_to_name(mref, asm)),
+            _ => mref_to_name(mref, asm),
+        }
+    }
+    /// Returns the name of a shim for an extern .NET method, adding it to the module if needed.
+    fn extern_path(&mut self, mref: &MethodRef, asm: &Assembly) -> String {
+        let class = &asm[asm[mref.class()].name()];
+        let mut path = format!(
+            "{}_{}{}",
+            escape_chars(class),
+            escape_chars(&asm[mref.name()]),
+            sig_mangle(mref.sig(), asm)
+        );
+        if mref.kind() == MethodKind::Constructor {
+            path.push_str("_new");
+        }
+        if !self.externs.contains_key(&path) {
+            let sig = &asm[mref.sig()];
+            // Constructors return the new object, instead of initializing `this`.
+            let (inputs, output) = match mref.kind() {
+                MethodKind::Constructor => (&sig.inputs()[1..], Type::ClassRef(mref.class())),
+                _ => (sig.inputs(), *sig.output()),
+            };
+            let body = dotnet_shim(class, &asm[mref.name()], mref.kind(), inputs, output, asm)
+                .unwrap_or_else(|| {
+                    format!(
+                        "c_rt_missing({});",
+                        c_string(&format!("{class}::{}", &asm[mref.name()]))
+                    )
+                });
+            self.externs.insert(
+                path.clone(),
+                format!(
+                    "static {} {{\n{body}\n}}",
+                    c_sig(&path, inputs, output, 0, asm)
                 ),
-                Type::FnPtr(_) => format!("({lhs}) / ({rhs})"),
-                Type::Float(_) | Type::Int(_) => format!("({lhs}) / ({rhs})"),
-                _ => todo!(),
-            },
+            );
         }
+        path
+    }
+}
+/// State needed to export a single method body.
+struct MethodCtx<'a> {
+    sig: SigIdx,
+    locals: &'a [LocalDef],
+    /// Locals are volatile in methods with handlers, so that they keep their values after a `longjmp`.
+    has_handlers: bool,
+    /// The id of the protected block being exported.
+    protected: Option<u32>,
+    /// The id of the protected block whose handler is being exported, and the ids of the blocks of that handler.
+    handler: Option<(u32, Vec<u32>)>,
+    module: &'a mut ModuleCtx,
+}
+impl<'a> MethodCtx<'a> {
+    fn tpe(&self, node: NodeIdx, asm: &mut Assembly) -> Type {
+        asm[node]
+            .clone()
+            .typecheck(self.sig, self.locals, asm)
+            .unwrap()
+    }
+    /// Exports `node`, converting it to `target`.
+    fn node_as(&mut self, node: NodeIdx, target: Type, asm: &mut Assembly) -> String {
+        let expr = self.node(node, asm);
+        coerce(expr, target, asm)
+    }
+    fn args(&mut self, args: &[NodeIdx], inputs: &[Type], asm: &mut Assembly) -> Vec<String> {
+        args.iter()
+            .zip(inputs)
+            .map(|(arg, tpe)| self.node_as(*arg, *tpe, asm))
+            .collect()
+    }
+    fn call(&mut self, mref_idx: MethodRefIdx, args: &[NodeIdx], asm: &mut Assembly) -> String {
+        let mref = asm[mref_idx].clone();
+        let inputs = asm[mref.sig()].inputs().to_vec();
+        let inputs = match mref.kind() {
+            MethodKind::Constructor => &inputs[1..],
+            _ => &inputs[..],
+        };
+        let args = self.args(args, inputs, asm);
+        let path = self.module.method_path(mref_idx, asm);
+        format!("{path}({})", args.join(", "))
     }
-    fn node_to_string(
-        node: CILNode,
+    fn compare(
+        &mut self,
+        lhs: NodeIdx,
+        rhs: NodeIdx,
+        op: &str,
+        unsigned: bool,
         asm: &mut Assembly,
-        locals: &[LocalDef],
-        inputs: &[(Type, Option<StringIdx>)],
-        sig: SigIdx,
     ) -> String {
-        match node {
-            CILNode::Const(cst) => match cst.as_ref() {
-                Const::I8(v) => format!("(int8_t)0x{v:x}"),
-                Const::I16(v) => format!("(int16_t)0x{v:x}"),
-                Const::I32(v) => format!("0x{v:x}"),
-                Const::I64(v) => format!("0x{v:x}L"),
-                Const::ISize(v) => format!("(intptr_t)0x{v:x}L"),
-                Const::U8(v) => format!("(uint8_t)0x{v:x}"),
-                Const::U16(v) => format!("(uint16_t)0x{v:x}"),
-                Const::U32(v) => format!("0x{v:x}u"),
-                Const::U64(v) => format!("0x{v:x}uL"),
-                Const::USize(v) => format!("(uintptr_t)0x{v:x}uL"),
-                Const::PlatformString(string_idx) => format!("{:?}", &asm[*string_idx]),
-                Const::Bool(val) => {
-                    if *val {
-                        "true".into()
-                    } else {
-                        "false".into()
+        let tpe = self.tpe(lhs, asm);
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        compare(&lhs, &rhs, tpe, op, unsigned)
+    }
+    fn binop(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp, asm: &mut Assembly) -> String {
+        match op {
+            BinOp::Eq => return self.compare(lhs, rhs, "==", false, asm),
+            BinOp::Lt => return self.compare(lhs, rhs, "<", false, asm),
+            BinOp::LtUn => return self.compare(lhs, rhs, "<", true, asm),
+            BinOp::Gt => return self.compare(lhs, rhs, ">", false, asm),
+            BinOp::GtUn => return self.compare(lhs, rhs, ">", true, asm),
+            _ => (),
+        }
+        let tpe = self.tpe(lhs, asm);
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        let c_op = match op {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Or => "|",
+            BinOp::XOr => "^",
+            BinOp::And => "&",
+            BinOp::Rem | BinOp::RemUn => "%",
+            BinOp::Shl => "<<",
+            BinOp::Shr | BinOp::ShrUn => ">>",
+            BinOp::Div | BinOp::DivUn => "/",
+            BinOp::Eq | BinOp::Lt | BinOp::LtUn | BinOp::Gt | BinOp::GtUn => unreachable!(),
+        };
+        match tpe {
+            Type::Int(int) => {
+                let wide = arith_int(int);
+                let mask = int_bits(int).max(32) - 1;
+                let expr = match op {
+                    BinOp::Shl => format!("({wide})({lhs}) << (({rhs}) & {mask})"),
+                    BinOp::Shr => {
+                        format!("({})({lhs}) >> (({rhs}) & {mask})", c_int(signed_int(int)))
                     }
-                }
-                Const::F32(hashable_f32) => {
-                    if !hashable_f32.0.is_nan() {
-                        format!("{:?}f", hashable_f32.0)
-                    } else {
-                        "NAN".into()
+                    BinOp::ShrUn => format!(
+                        "({})({lhs}) >> (({rhs}) & {mask})",
+                        c_int(unsigned_int(int))
+                    ),
+                    BinOp::Div | BinOp::Rem => {
+                        let signed = c_int(signed_int(int));
+                        format!("({signed})({lhs}) {c_op} ({signed})({rhs})")
                     }
-                }
-                Const::F64(hashable_f64) => {
-                    if !hashable_f64.0.is_nan() {
-                        format!("{:?}", hashable_f64.0)
-                    } else {
-                        "NAN".into()
+                    BinOp::DivUn | BinOp::RemUn => {
+                        let unsigned = c_int(unsigned_int(int));
+                        format!("({unsigned})({lhs}) {c_op} ({unsigned})({rhs})")
                     }
-                }
-                Const::Null(class_ref_idx) => todo!(),
-            },
-            CILNode::BinOp(lhs, rhs, bin_op) => {
-                let tpe = node.typecheck(sig, locals, asm).unwrap();
-                Self::binop_to_string(
-                    asm[lhs].clone(),
-                    asm[rhs].clone(),
-                    bin_op,
-                    tpe,
-                    asm,
-                    locals,
-                    inputs,
-                    sig,
-                )
+                    _ => format!("({wide})({lhs}) {c_op} ({wide})({rhs})"),
+                };
+                format!("(({})({expr}))", c_int(int))
             }
-            CILNode::UnOp(node_idx, un_op) => match un_op {
-                super::cilnode::UnOp::Not => format!(
-                    "~({})",
-                    Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-                ),
-                super::cilnode::UnOp::Neg => format!(
-                    "-({})",
-                    Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-                ),
-            },
-            CILNode::LdLoc(loc) => match locals[loc as usize].0 {
-                Some(local_name) => escape_ident(&asm[local_name]),
-                None => format!("L{loc}",),
-            },
-            CILNode::LdArg(arg) => match inputs[arg as usize].1 {
-                Some(arg_name) => escape_ident(&asm[arg_name]),
-                None => format!("A{arg}",),
-            },
-            CILNode::LdArgA(arg) => match inputs[arg as usize].1 {
-                Some(arg_name) => format!("&{}", escape_ident(&asm[arg_name])),
-                None => format!("&A{arg}",),
+            // `f16` arithmetic is done on `f32`s, like .NET does.
+            Type::Float(Float::F16) => {
+                let lhs = format!("c_rt_f16_to_f32({lhs})");
+                let rhs = format!("c_rt_f16_to_f32({rhs})");
+                let res = match op {
+                    BinOp::Rem | BinOp::RemUn => format!("__builtin_fmodf({lhs}, {rhs})"),
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::DivUn => {
+                        format!("({lhs}) {c_op} ({rhs})")
+                    }
+                    _ => panic!("Can't {op:?} floats"),
+                };
+                format!("c_rt_f32_to_f16({res})")
+            }
+            Type::Float(Float::F128) => {
+                let func = match op {
+                    BinOp::Rem | BinOp::RemUn => "rem",
+                    BinOp::Add => "add",
+                    BinOp::Sub => "sub",
+                    BinOp::Mul => "mul",
+                    BinOp::Div | BinOp::DivUn => "div",
+                    _ => panic!("Can't {op:?} floats"),
+                };
+                format!("c_rt_f128_{func}({lhs}, {rhs})")
+            }
+            Type::Float(float) => match (op, float) {
+                (BinOp::Rem | BinOp::RemUn, Float::F32) => {
+                    format!("__builtin_fmodf({lhs}, {rhs})")
+                }
+                (BinOp::Rem | BinOp::RemUn, Float::F64) => format!("__builtin_fmod({lhs}, {rhs})"),
+                (BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::DivUn, _) => {
+                    format!("(({lhs}) {c_op} ({rhs}))")
+                }
+                _ => panic!("Can't {op:?} floats"),
             },
-            CILNode::LdLocA(loc) => match locals[loc as usize].0 {
-                Some(local_name) => format!("&{}", escape_ident(&asm[local_name])),
-                None => format!("&L{loc}",),
+            Type::Bool => format!("((bool)(({lhs}) {c_op} ({rhs})))"),
+            // Pointer arithmetic is done on integers, since the IR offsets pointers by bytes.
+            _ => format!(
+                "(({})((uintptr_t)({lhs}) {c_op} (uintptr_t)({rhs})))",
+                c_tpe(tpe, asm)
+            ),
+        }
+    }
+    /// Returns the name of the C type a field of `owner` is accessed trough.
+    fn field_owner(&self, owner: ClassRefIdx, asm: &Assembly) -> String {
+        escape_ident(&asm[asm[owner].name()])
+    }
+    fn node(&mut self, idx: NodeIdx, asm: &mut Assembly) -> String {
+        match asm[idx].clone() {
+            CILNode::Const(cst) => match cst.as_ref() {
+                Const::I8(v) => format!("((int8_t){v:#x}u)"),
+                Const::I16(v) => format!("((int16_t){v:#x}u)"),
+                Const::I32(v) => format!("((int32_t){v:#x}u)"),
+                Const::I64(v) => format!("((int64_t){v:#x}ull)"),
+                Const::ISize(v) => format!("((intptr_t){v:#x}ull)"),
+                Const::U8(v) => format!("((uint8_t){v:#x}u)"),
+                Const::U16(v) => format!("((uint16_t){v:#x}u)"),
+                Const::U32(v) => format!("((uint32_t){v:#x}u)"),
+                Const::U64(v) => format!("((uint64_t){v:#x}ull)"),
+                Const::USize(v) => format!("((uintptr_t){v:#x}ull)"),
+                Const::PlatformString(string_idx) => c_string(&asm[*string_idx]),
+                Const::Bool(val) => format!("{val}"),
+                Const::F32(hashable_f32) => c_float(f64::from(hashable_f32.0), true),
+                Const::F64(hashable_f64) => c_float(hashable_f64.0, false),
+                Const::Null(cref) => format!("(({})0)", class_tpe(*cref, asm)),
             },
+            CILNode::BinOp(lhs, rhs, bin_op) => self.binop(lhs, rhs, bin_op, asm),
+            CILNode::UnOp(input, un_op) => {
+                let tpe = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                match (&un_op, tpe) {
+                    (UnOp::Not, Type::Bool) => format!("(!({input}))"),
+                    (UnOp::Not, Type::Int(int)) => format!("(({})~({input}))", c_int(int)),
+                    (UnOp::Neg, Type::Int(int)) => {
+                        format!("(({})(0 - ({})({input})))", c_int(int), arith_int(int))
+                    }
+                    (UnOp::Neg, Type::Float(Float::F16)) => {
+                        format!("c_rt_f32_to_f16(-c_rt_f16_to_f32({input}))")
+                    }
+                    (UnOp::Neg, Type::Float(Float::F128)) => format!("c_rt_f128_neg({input})"),
+                    (UnOp::Neg, Type::Float(_)) => format!("(-({input}))"),
+                    (UnOp::Not, _) => format!("(({})~(uintptr_t)({input}))", c_tpe(tpe, asm)),
+                    (UnOp::Neg, _) => panic!("Can't negate {tpe:?}"),
+                }
+            }
+            CILNode::LdLoc(loc) => format!("L{loc}"),
+            CILNode::LdArg(arg) => format!("A{arg}"),
+            CILNode::LdLocA(loc) if self.has_handlers => {
+                // Casts away the `volatile` qualifier.
+                let tpe = nonvoid_c_type(asm[self.locals[loc as usize].1], asm);
+                format!("(({tpe}*)&L{loc})")
+            }
+            CILNode::LdLocA(loc) => format!("(&L{loc})"),
+            CILNode::LdArgA(arg) => format!("(&A{arg})"),
             CILNode::Call(info) => {
                 let (method, args) = info.as_ref();
-                let method = asm[*method].clone();
-                let call_args = args
-                    .iter()
-                    .map(|arg| {
-                        format!(
-                            "({})",
-                            Self::node_to_string(asm[*arg].clone(), asm, locals, inputs, sig)
-                        )
-                    })
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let class = &asm[method.class()];
-                let class_name = escape_ident(&asm[class.name()]);
-                let mname = escape_ident(&asm[method.name()]);
-                let method_name = mref_to_name(&method, asm);
-                format!("{method_name}({call_args})")
+                self.call(*method, args, asm)
             }
             CILNode::IntCast {
                 input,
                 target,
                 extend,
             } => {
-                let input = Self::node_to_string(asm[input].clone(), asm, locals, inputs, sig);
-                match (target, extend) {
-                    (Int::U8, ExtendKind::ZeroExtend) => format!("(uint8_t)({input})"),
-                    (Int::U8, ExtendKind::SignExtend) => todo!(),
-                    (Int::U16, ExtendKind::ZeroExtend) => format!("(uint16_t)({input})"),
-                    (Int::U16, ExtendKind::SignExtend) => todo!(),
-                    (Int::U32, ExtendKind::ZeroExtend) => format!("(uint32_t)({input})"),
-                    (Int::U32, ExtendKind::SignExtend) => format!("(uint32_t)(int32_t)({input})"),
-                    (Int::U64, ExtendKind::ZeroExtend) => format!("(uint64_t)({input})"),
-                    (Int::U64, ExtendKind::SignExtend) => format!("(uint64_t)(int64_t)({input})"),
-                    (Int::U128, ExtendKind::ZeroExtend) => format!("(__uint128_t)({input})"),
-                    (Int::U128, ExtendKind::SignExtend) => todo!(),
-                    (Int::USize, ExtendKind::ZeroExtend) => format!("(uintptr_t)({input})"),
-                    (Int::USize, ExtendKind::SignExtend) => {
-                        format!("(uintptr_t)(intptr_t)({input})")
-                    }
-                    (Int::I8, ExtendKind::ZeroExtend) => todo!(),
-                    (Int::I8, ExtendKind::SignExtend) => format!("(int8_t)({input})"),
-                    (Int::I16, ExtendKind::ZeroExtend) => todo!(),
-                    (Int::I16, ExtendKind::SignExtend) => format!("(int16_t)({input})"),
-                    (Int::I32, ExtendKind::ZeroExtend) => format!("(int32_t)(uint32_t)({input})"),
-                    (Int::I32, ExtendKind::SignExtend) => format!("(int32_t)({input})"),
-                    (Int::I64, ExtendKind::ZeroExtend) => format!("(int64_t)(uint64_t)({input})"),
-                    (Int::I64, ExtendKind::SignExtend) => format!("(int64_t)({input})"),
-                    (Int::I128, ExtendKind::ZeroExtend) => todo!(),
-                    (Int::I128, ExtendKind::SignExtend) => todo!(),
-                    (Int::ISize, ExtendKind::ZeroExtend) => {
-                        format!("(intptr_t)(uintptr_t)({input})")
-                    }
-                    (Int::ISize, ExtendKind::SignExtend) => format!("(intptr_t)({input})"),
-                }
+                let src = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                int_cast(&input, src, target, extend)
             }
             CILNode::FloatCast {
                 input,
                 target,
                 is_signed,
             } => {
-                let input = Self::node_to_string(asm[input].clone(), asm, locals, inputs, sig);
-                match target {
-                    super::Float::F16 => todo!(),
-                    super::Float::F32 => format!("(float)({input})"),
-                    super::Float::F64 => format!("(double)({input})"),
-                    super::Float::F128 => todo!(),
-                }
+                let src = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                float_cast(&input, src, target, is_signed)
             }
-            CILNode::RefToPtr(node_idx) => {
-                Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-            }
-            CILNode::PtrCast(node_idx, ptr_cast_res) => {
-                let node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig);
-                match ptr_cast_res.as_ref() {
-                    PtrCastRes::Ptr(type_idx) | PtrCastRes::Ref(type_idx) => {
-                        format!("({tpe}*)({node})", tpe = c_tpe(asm[*type_idx], asm),)
+            CILNode::RefToPtr(input) => self.node(input, asm),
+            CILNode::PtrCast(input, res) => {
+                let input = self.node(input, asm);
+                let tpe = match res.as_ref() {
+                    PtrCastRes::Ptr(inner) | PtrCastRes::Ref(inner) => {
+                        format!("{}*", c_tpe(asm[*inner], asm))
                     }
-                    PtrCastRes::FnPtr(_) => format!("(void*)({node})"),
-                    PtrCastRes::USize => format!("(uintptr_t)({node})"),
-                    PtrCastRes::ISize => format!("(intptr_t)({node})"),
-                }
+                    PtrCastRes::FnPtr(_) => "void*".into(),
+                    PtrCastRes::USize => "uintptr_t".into(),
+                    PtrCastRes::ISize => "intptr_t".into(),
+                };
+                format!("(({tpe})({input}))")
             }
             CILNode::LdFieldAdress { addr, field } => {
-                let addr = asm[addr].clone();
-                let addr = Self::node_to_string(addr, asm, locals, inputs, sig);
+                let addr = self.node(addr, asm);
                 let field = asm[field];
-                let name = &asm[field.name()];
-                format!("&({addr})->{name}.f")
+                format!(
+                    "(&(({}*)({addr}))->{}.f)",
+                    self.field_owner(field.owner(), asm),
+                    escape_ident(&asm[field.name()])
+                )
             }
             CILNode::LdField { addr, field } => {
-                let addr = asm[addr].clone();
-                let addr_tpe = addr.typecheck(sig, locals, asm).unwrap();
-                let addr = Self::node_to_string(addr, asm, locals, inputs, sig);
+                let addr_tpe = self.tpe(addr, asm);
+                let addr = self.node(addr, asm);
                 let field = asm[field];
-                let name = &asm[field.name()];
+                let name = escape_ident(&asm[field.name()]);
                 match addr_tpe {
-                    Type::Ref(_) | Type::Ptr(_) => format!("({addr})->{name}.f"),
-                    Type::ClassRef(_) => format!("({addr}).{name}.f"),
-                    _ => panic!(),
+                    // Valuetypes can be accessed directly.
+                    Type::ClassRef(cref) if asm[cref].is_valuetype() => {
+                        format!("(({addr}).{name}.f)")
+                    }
+                    _ => format!(
+                        "((({}*)({addr}))->{name}.f)",
+                        self.field_owner(field.owner(), asm)
+                    ),
                 }
             }
             CILNode::LdInd {
@@ -459,445 +1202,684 @@ impl CExporter {
                 tpe,
                 volitale,
             } => {
-                if volitale {
-                    format!(
-                        "*(volitale {tpe}*)({addr})",
-                        tpe = c_tpe(asm[tpe], asm),
-                        addr = Self::node_to_string(asm[addr].clone(), asm, locals, inputs, sig)
-                    )
-                } else {
-                    format!(
-                        "*({addr})",
-                        addr = Self::node_to_string(asm[addr].clone(), asm, locals, inputs, sig)
-                    )
+                let addr = self.node(addr, asm);
+                let volitale = if volitale { "volatile " } else { "" };
+                format!("(*(({volitale}{}*)({addr})))", c_tpe(asm[tpe], asm))
+            }
+            CILNode::SizeOf(tpe) => {
+                format!("((int32_t)sizeof({}))", nonvoid_c_type(asm[tpe], asm))
+            }
+            CILNode::GetException => "c_rt_exception".into(),
+            CILNode::IsInst(obj, tpe) => {
+                let obj = self.node(obj, asm);
+                match type_info(asm[tpe], asm) {
+                    Some(info) => format!("c_rt_isinst({obj}, {info})"),
+                    None => format!("(({obj}) != 0)"),
+                }
+            }
+            CILNode::CheckedCast(obj, tpe) => {
+                let obj = self.node(obj, asm);
+                let c_tpe = c_tpe(asm[tpe], asm);
+                match type_info(asm[tpe], asm) {
+                    Some(info) => format!("(({c_tpe})c_rt_cast({obj}, {info}))"),
+                    None => format!("(({c_tpe})({obj}))"),
                 }
             }
-            CILNode::SizeOf(type_idx) => format!("sizeof({tpe})", tpe = c_tpe(asm[type_idx], asm)),
-            CILNode::GetException => todo!(),
-            CILNode::IsInst(node_idx, type_idx) => todo!(),
-            CILNode::CheckedCast(node_idx, type_idx) => todo!(),
             CILNode::CallI(info) => {
-                let (fn_ptr, fn_ptr_sig, args) = info.as_ref();
-                let fn_ptr_sig = asm[*fn_ptr_sig].clone();
-                let call_args = args
-                    .iter()
-                    .map(|arg| {
-                        format!(
-                            "({})",
-                            Self::node_to_string(asm[*arg].clone(), asm, locals, inputs, sig)
-                        )
-                    })
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let ret = c_tpe(*fn_ptr_sig.output(), asm);
-                let args = fn_ptr_sig
-                    .inputs()
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let fn_ptr = Self::node_to_string(asm[*fn_ptr].clone(), asm, locals, inputs, sig);
-                format!("((*({ret}(*)({args}))({fn_ptr})))({call_args})")
-            }
-            CILNode::LocAlloc { size } => format!(
-                "alloca({})",
-                Self::node_to_string(asm[size].clone(), asm, locals, inputs, sig)
-            ),
-            CILNode::LdStaticField(static_field_idx) => {
-                let field = asm[static_field_idx];
-                let class = asm[field.owner()].clone();
-                let fname = class_member_name(&asm[class.name()], &asm[field.name()]);
-                fname.to_string()
-            }
-            CILNode::LdStaticFieldAdress(static_field_idx) => {
-                let field = asm[static_field_idx];
-                let class = asm[field.owner()].clone();
-                let fname = class_member_name(&asm[class.name()], &asm[field.name()]);
-                format!("&{}", fname)
-            }
-            CILNode::LdFtn(method) => mref_to_name(&asm[method], asm),
-            CILNode::LdTypeToken(type_idx) => format!("{}", type_idx.as_bimap_index()),
-            //TODO: ld len is not really supported in C, and is only there due to the argc emulation.
-            CILNode::LdLen(node_idx) => format!(
-                "ld_len({arr})",
-                arr = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-            ),
-            // TODO: loc alloc aligned does not respect the aligement ATM.
+                let (fn_ptr, sig, args) = info.as_ref();
+                self.calli(*fn_ptr, *sig, args, asm)
+            }
+            CILNode::LocAlloc { size } => {
+                format!("((uint8_t*)__builtin_alloca({}))", self.node(size, asm))
+            }
+            CILNode::LdStaticField(sfld) => {
+                let sfld = asm[sfld];
+                self.static_field_path(sfld.owner(), &asm[sfld.name()], asm)
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                let sfld = asm[sfld];
+                format!(
+                    "(&{})",
+                    self.static_field_path(sfld.owner(), &asm[sfld.name()], asm)
+                )
+            }
+            CILNode::LdFtn(method) => {
+                format!("((void*)&{})", self.module.method_path(method, asm))
+            }
+            CILNode::LdTypeToken(tpe) => format!("((uintptr_t){})", tpe.as_bimap_index()),
+            CILNode::LdLen(arr) => format!("((int32_t)c_rt_ld_len({}))", self.node(arr, asm)),
             CILNode::LocAllocAlgined { tpe, align } => {
-                format!("alloca(sizeof({tpe}))", tpe = c_tpe(asm[tpe], asm))
+                let tpe = nonvoid_c_type(asm[tpe], asm);
+                format!(
+                    "(({tpe}*)c_rt_align_up(__builtin_alloca(sizeof({tpe}) + {align}), {align}))"
+                )
             }
-            //TODO: ld elem ref is not really supported in C, and is only there due to the argc emulation.
             CILNode::LdElelemRef { array, index } => {
-                let tpe = node.typecheck(sig, locals, asm).unwrap();
-                let array = Self::node_to_string(asm[array].clone(), asm, locals, inputs, sig);
-                let index = Self::node_to_string(asm[index].clone(), asm, locals, inputs, sig);
-                format!("({array})[{index}]")
-            }
-            CILNode::UnboxAny { object, tpe } => format!(
-                "({object})",
-                object = Self::node_to_string(asm[object].clone(), asm, locals, inputs, sig)
-            ),
+                format!("(&({})[{}])", self.node(array, asm), self.node(index, asm))
+            }
+            CILNode::UnboxAny { object, tpe } => {
+                let obj = self.node(object, asm);
+                let tpe = asm[tpe];
+                if is_scalar(tpe, asm) && c_tpe(tpe, asm).ends_with('*') {
+                    format!("(({})({obj}))", c_tpe(tpe, asm))
+                } else {
+                    format!("(*({}*)({obj}))", nonvoid_c_type(tpe, asm))
+                }
+            }
         }
     }
-    fn root_to_string(
-        root: CILRoot,
-        asm: &mut Assembly,
-        locals: &[LocalDef],
-        inputs: &[(Type, Option<StringIdx>)],
+    fn calli(
+        &mut self,
+        fn_ptr: NodeIdx,
         sig: SigIdx,
+        args: &[NodeIdx],
+        asm: &mut Assembly,
     ) -> String {
-        match root {
-            CILRoot::StLoc(id, node_idx) => match locals[id as usize].0 {
-                Some(name) => format!(
-                    "{name} = {node};",
-                    node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig),
-                    name = escape_ident(&asm[name]),
-                ),
-                None => format!(
-                    "L{id} = {node};",
-                    node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig),
-                ),
-            },
-            CILRoot::StArg(arg, node_idx) =>match inputs[arg as usize].1 {
-                Some(name) => format!(
-                    "{name} = {node};",
-                    node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig),
-                    name = escape_ident(&asm[name]),
-                ),
-                None => format!(
-                    "A{arg} = {node};",
-                    node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig),
-                ),
-            },
-            CILRoot::Ret(node_idx) => format!(
-                "return {node};",
-                node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-            ),
-            CILRoot::Pop(node_idx) => format!(
-                "{node};",
-                node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-            ),
-            CILRoot::Throw(node_idx) =>  format!(
-                "eprintf(\"An error was encoutrered in %s, at %s:%d\\n\",__func__,__FILE__,__LINE__);eprintf(\"%s\\n\",{node}); abort();",
-                node = Self::node_to_string(asm[node_idx].clone(), asm, locals, inputs, sig)
-            ),
-            CILRoot::VoidRet => "return;".into(),
-            CILRoot::Break => "".into(),
-            CILRoot::Nop => todo!(),
-            CILRoot::Branch(binfo) => {
-                let (target, sub_target, cond) = binfo.as_ref();
-                let target = if *sub_target != 0{
-                    sub_target
-                }else {target};
-                let Some(cond) = cond else {
-                    return format!("goto bb{target};");
-                };
-                match cond {
-                    BranchCond::True(node_idx) => format!(
-                        "if({node}) goto bb{target};",
-                        node =
-                            Self::node_to_string(asm[*node_idx].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::False(node_idx) => format!(
-                        "if(!({node})) goto bb{target};",
-                        node =
-                            Self::node_to_string(asm[*node_idx].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Eq(lhs, rhs) => format!(
-                        "if(({lhs}) == ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Ne(lhs, rhs) => format!(
-                        "if(({lhs}) != ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Lt(lhs, rhs, _cmp_kind) => format!(
-                        "if(({lhs}) < ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Gt(lhs, rhs, _cmp_kind) => format!(
-                        "if(({lhs}) > ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Le(lhs, rhs, _cmp_kind) => format!(
-                        "if(({lhs}) <= ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
-                    BranchCond::Ge(lhs, rhs, _cmp_kind) => format!(
-                        "if(({lhs}) >= ({rhs})) goto bb{target};",
-                        lhs = Self::node_to_string(asm[*lhs].clone(), asm, locals, inputs, sig),
-                        rhs = Self::node_to_string(asm[*rhs].clone(), asm, locals, inputs, sig)
-                    ),
+        let fn_ptr = self.node(fn_ptr, asm);
+        let inputs = asm[sig].inputs().to_vec();
+        let args = self.args(args, &inputs, asm);
+        let params = if inputs.is_empty() {
+            "void".into()
+        } else {
+            inputs
+                .iter()
+                .map(|tpe| nonvoid_c_type(*tpe, asm))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!(
+            "((({}(*)({params}))({fn_ptr}))({}))",
+            c_tpe(*asm[sig].output(), asm),
+            args.join(", ")
+        )
+    }
+    fn static_field_path(&self, owner: ClassRefIdx, name: &str, asm: &Assembly) -> String {
+        class_member_name(&escape_ident(&asm[asm[owner].name()]), &escape_ident(name))
+    }
+    /// Pops the frame of the protected block being exported, if there is one.
+    fn pop_frame(&self) -> String {
+        match self.protected {
+            Some(block) => format!("c_rt_top = f{block}.prev; "),
+            None => String::new(),
+        }
+    }
+    /// Returns the result of `call`, which is a tail call.
+    fn tail_call(&self, call: &str, asm: &Assembly) -> String {
+        let output = *asm[self.sig].output();
+        if output == Type::Void {
+            return format!("{{ {call}; {}return; }}", self.pop_frame());
+        }
+        if self.protected.is_some() {
+            // The frame of the protected block must stay pushed until the call returns, so this can't be a tail call.
+            format!(
+                "{{ {} c_ret = {call}; {}return c_ret; }}",
+                c_tpe(output, asm),
+                self.pop_frame()
+            )
+        } else {
+            format!("C_RT_MUSTTAIL return {call};")
+        }
+    }
+    fn goto(&self, target: u32, sub_target: u32) -> String {
+        let dest = if sub_target == 0 { target } else { sub_target };
+        if let Some((protected, blocks)) = &self.handler {
+            if sub_target != 0 && blocks.contains(&sub_target) {
+                return format!("goto h{protected}_{sub_target};");
+            }
+            return format!("goto bb{dest};");
+        }
+        match self.protected {
+            // Jumps within the protected block should not push its frame again.
+            Some(block) if dest == block && sub_target == 0 => format!("goto bb{block}_try;"),
+            Some(block) => format!("{{ c_rt_top = f{block}.prev; goto bb{dest}; }}"),
+            None => format!("goto bb{dest};"),
+        }
+    }
+    fn cond(&mut self, cond: NodeIdx, asm: &mut Assembly) -> String {
+        self.node(cond, asm)
+    }
+    fn root(&mut self, idx: RootIdx, asm: &mut Assembly) -> String {
+        match asm[idx].clone() {
+            CILRoot::StLoc(loc, val) => {
+                let tpe = asm[self.locals[loc as usize].1];
+                format!("L{loc} = {};", self.node_as(val, tpe, asm))
+            }
+            CILRoot::StArg(arg, val) => {
+                let tpe = asm[self.sig].inputs()[arg as usize];
+                format!("A{arg} = {};", self.node_as(val, tpe, asm))
+            }
+            CILRoot::Ret(val) => {
+                let output = *asm[self.sig].output();
+                if output == Type::Void {
+                    // Returning the result of a void call.
+                    let val = self.node(val, asm);
+                    return format!("{{ {val}; {}return; }}", self.pop_frame());
+                }
+                let val = self.node_as(val, output, asm);
+                if self.protected.is_some() {
+                    // The value must be computed before leaving the protected block, since it may throw.
+                    format!(
+                        "{{ {} c_ret = {val}; {}return c_ret; }}",
+                        c_tpe(output, asm),
+                        self.pop_frame()
+                    )
+                } else {
+                    format!("return {val};")
+                }
+            }
+            CILRoot::Pop(val) => format!("(void)({});", self.node(val, asm)),
+            CILRoot::Throw(val) => format!("c_rt_throw((void*)({}));", self.node(val, asm)),
+            CILRoot::VoidRet => format!("{{ {}return; }}", self.pop_frame()),
+            CILRoot::Break | CILRoot::Nop => ";".into(),
+            CILRoot::SourceFileInfo {
+                line_start, file, ..
+            } => {
+                if line_start == 0 {
+                    String::new()
+                } else {
+                    format!("#line {line_start} {}", c_string(&asm[file]))
                 }
             }
-            CILRoot::SourceFileInfo { line_start, line_len, col_start, col_len, file  } => format!("#line {line_start} {file:?}", file = &asm[file]).into(),
-            CILRoot::SetField(info) =>{
-                let (field,addr,value) = info.as_ref();
-                let addr = Self::node_to_string(asm[*addr].clone(), asm, locals, inputs, sig);
-                let value = Self::node_to_string(asm[*value].clone(), asm, locals, inputs, sig);
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = info.as_ref();
+                let goto = self.goto(*target, *sub_target);
+                let cond = match cond {
+                    None => return goto,
+                    Some(BranchCond::True(cond)) => self.cond(*cond, asm),
+                    Some(BranchCond::False(cond)) => format!("!{}", self.cond(*cond, asm)),
+                    Some(BranchCond::Eq(lhs, rhs)) => self.compare(*lhs, *rhs, "==", false, asm),
+                    Some(BranchCond::Ne(lhs, rhs)) => self.compare(*lhs, *rhs, "!=", false, asm),
+                    Some(
+                        BranchCond::Lt(lhs, rhs, kind)
+                        | BranchCond::Gt(lhs, rhs, kind)
+                        | BranchCond::Le(lhs, rhs, kind)
+                        | BranchCond::Ge(lhs, rhs, kind),
+                    ) => {
+                        let op = match cond.as_ref().unwrap() {
+                            BranchCond::Lt(..) => "<",
+                            BranchCond::Gt(..) => ">",
+                            BranchCond::Le(..) => "<=",
+                            _ => ">=",
+                        };
+                        let unsigned = matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+                        self.compare(*lhs, *rhs, op, unsigned, asm)
+                    }
+                };
+                format!("if ({cond}) {goto}")
+            }
+            CILRoot::Switch(info) => {
+                let (value, int, targets) = info.as_ref();
+                // The cases are zero extended, so the value must be too.
+                let value = format!(
+                    "(uint64_t)({})({})",
+                    c_int(unsigned_int(*int)),
+                    self.node(*value, asm)
+                );
+                let cases: String = targets
+                    .cases()
+                    .into_iter()
+                    .map(|(case, (target, sub_target))| {
+                        format!("case {case}ull: {}\n", self.goto(target, sub_target))
+                    })
+                    .collect();
+                let (target, sub_target) = targets.otherwise();
+                format!(
+                    "switch ({value}) {{\n{cases}default: {}\n}}",
+                    self.goto(target, sub_target)
+                )
+            }
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = info.as_ref();
                 let field = asm[*field];
-                let name = &asm[field.name()];
-                format!("({addr})->{name}.f = ({value});")
+                let addr = self.node(*addr, asm);
+                let val = self.node_as(*val, field.tpe(), asm);
+                format!(
+                    "(({}*)({addr}))->{}.f = {val};",
+                    self.field_owner(field.owner(), asm),
+                    escape_ident(&asm[field.name()])
+                )
             }
             CILRoot::Call(info) => {
                 let (method, args) = info.as_ref();
-                let method = asm[*method].clone();
-                let call_args = args
-                    .iter()
-                    .map(|arg| {
-                        format!(
-                            "({})",
-                            Self::node_to_string(asm[*arg].clone(), asm, locals, inputs, sig)
-                        )
-                    })
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let method_name = mref_to_name(&method, asm);
-                format!("{method_name}({call_args});")
+                format!("{};", self.call(*method, args, asm))
             }
             CILRoot::StInd(info) => {
-                let (addr, value, tpe, is_volitle) = info.as_ref();
-                let addr = Self::node_to_string(asm[*addr].clone(), asm, locals, inputs, sig);
-                let value = Self::node_to_string(asm[*value].clone(), asm, locals, inputs, sig);
-                if *is_volitle {
-                    format!(
-                        "*((volitale {tpe}*)({addr})) = ({value});",
-                        tpe = c_tpe(*tpe, asm)
-                    )
-                } else {
-                    format!("*({addr}) = ({value});")
-                }
+                let (addr, val, tpe, volitale) = info.as_ref();
+                let addr = self.node(*addr, asm);
+                let val = self.node_as(*val, *tpe, asm);
+                let volitale = if *volitale { "volatile " } else { "" };
+                format!("*(({volitale}{}*)({addr})) = {val};", c_tpe(*tpe, asm))
             }
             CILRoot::InitBlk(blk) => {
                 let (dst, val, count) = blk.as_ref();
-                let dst = Self::node_to_string(asm[*dst].clone(), asm, locals, inputs, sig);
-                let val = Self::node_to_string(asm[*val].clone(), asm, locals, inputs, sig);
-                let count = Self::node_to_string(asm[*count].clone(), asm, locals, inputs, sig);
-                format!("memset(({dst}),({val}),({count}));")
+                format!(
+                    "__builtin_memset({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*val, asm),
+                    self.node(*count, asm)
+                )
             }
             CILRoot::CpBlk(blk) => {
                 let (dst, src, len) = blk.as_ref();
-                let dst = Self::node_to_string(asm[*dst].clone(), asm, locals, inputs, sig);
-                let src = Self::node_to_string(asm[*src].clone(), asm, locals, inputs, sig);
-                let len = Self::node_to_string(asm[*len].clone(), asm, locals, inputs, sig);
-                format!("memcpy(({dst}),({src}),({len}));")
+                format!(
+                    "__builtin_memcpy({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*src, asm),
+                    self.node(*len, asm)
+                )
             }
             CILRoot::CallI(info) => {
-                let (fn_ptr, fn_ptr_sig, args) = info.as_ref();
-                let fn_ptr_sig = asm[*fn_ptr_sig].clone();
-                let call_args = args
-                    .iter()
-                    .map(|arg| {
-                        format!(
-                            "({})",
-                            Self::node_to_string(asm[*arg].clone(), asm, locals, inputs, sig)
-                        )
-                    })
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let ret = c_tpe(*fn_ptr_sig.output(), asm);
-                let args = fn_ptr_sig
-                    .inputs()
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
-                let fn_ptr = Self::node_to_string(asm[*fn_ptr].clone(), asm, locals, inputs, sig);
-                format!("((*({ret}(*)({args}))({fn_ptr})))({call_args});")
-            }
-            CILRoot::ExitSpecialRegion { target, source } => format!("goto bb{target};"),
-            CILRoot::ReThrow => todo!(),
+                let (fn_ptr, sig, args) = info.as_ref();
+                format!("{};", self.calli(*fn_ptr, *sig, args, asm))
+            }
+            CILRoot::TailCall(info) => {
+                let (method, args) = info.as_ref();
+                let call = self.call(*method, args, asm);
+                self.tail_call(&call, asm)
+            }
+            CILRoot::TailCallI(info) => {
+                let (fn_ptr, sig, args) = info.as_ref();
+                let call = self.calli(*fn_ptr, *sig, args, asm);
+                self.tail_call(&call, asm)
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => {
+                format!("{{ {}goto bb{target}; }}", self.pop_frame())
+            }
+            CILRoot::ReThrow => "c_rt_throw(c_rt_exception);".into(),
             CILRoot::SetStaticField { field, val } => {
                 let field = asm[field];
-                let class = asm[field.owner()].clone();
-                let fname = class_member_name(&asm[class.name()], &asm[field.name()]);
-                let val = Self::node_to_string(asm[val].clone(), asm, locals, inputs, sig);
-                format!("{fname} = {val};")
+                let val = self.node_as(val, field.tpe(), asm);
+                format!(
+                    "{} = {val};",
+                    self.static_field_path(field.owner(), &asm[field.name()], asm)
+                )
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                let tpe = nonvoid_c_type(asm[tpe], asm);
+                format!(
+                    "*(({tpe}*)({})) = *(({tpe}*)({}));",
+                    self.node(dst, asm),
+                    self.node(src, asm)
+                )
             }
-            CILRoot::CpObj { src, dst, tpe } => todo!(),
-            CILRoot::Unreachable(string_idx) => todo!(),
+            CILRoot::Unreachable(msg) => format!("c_rt_fatal({});", c_string(&asm[msg])),
         }
     }
+}
+pub struct CExporter {
+    is_lib: bool,
+    sanitize: bool,
+}
+impl CExporter {
+    #[must_use]
+    pub fn new(is_lib: bool, sanitize: bool) -> Self {
+        Self { is_lib, sanitize }
+    }
+    fn export_method_decl(
+        asm: &Assembly,
+        def: &MethodDef,
+        method_decls: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let mref = def.ref_to();
+        let inputs = asm[def.sig()].inputs();
+        let output = *asm[def.sig()].output();
+        if let MethodImpl::Extern { .. } = def.resolved_implementation(asm) {
+            let name = &asm[def.name()];
+            let mut decl = c_sig(&extern_name(&mref, asm), inputs, output, 0, asm);
+            if let Some(fixed) = variadic_args(name).filter(|fixed| *fixed <= inputs.len()) {
+                decl = c_sig(&extern_name(&mref, asm), &inputs[..fixed], output, 0, asm);
+                decl.insert_str(decl.len() - 1, ", ...");
+            }
+            return writeln!(method_decls, "{decl} C_RT_SYM({});", c_string(name));
+        }
+        let name = mref_to_name(&mref, asm);
+        writeln!(method_decls, "{};", c_sig(&name, inputs, output, 0, asm))?;
+        if def.kind() == MethodKind::Constructor {
+            writeln!(
+                method_decls,
+                "{};",
+                c_sig(
+                    &format!("{name}_new"),
+                    &inputs[1..],
+                    Type::ClassRef(def.ref_to().class()),
+                    1,
+                    asm
+                )
+            )?;
+        }
+        Ok(())
+    }
+    /// Exports the function creating a new object with the constructor `def`.
+    fn export_factory(
+        asm: &Assembly,
+        def: &MethodDef,
+        method_defs: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let name = mref_to_name(&def.ref_to(), asm);
+        let inputs = asm[def.sig()].inputs();
+        let class = Type::ClassRef(def.ref_to().class());
+        let tpe = c_tpe(class, asm);
+        let alloc = if asm[def.class()].is_valuetype() {
+            format!("{tpe} obj;\n__builtin_memset(&obj, 0, sizeof(obj));")
+        } else {
+            let class_name = escape_ident(&asm[asm[def.class()].name()]);
+            format!("{tpe} obj = ({tpe})c_rt_new(sizeof({class_name}), &c_type_{class_name});")
+        };
+        let this = match inputs[0] {
+            Type::Ptr(_) | Type::Ref(_) => format!(
+                "({}*)&obj",
+                c_tpe(asm[inputs[0].pointed_to().unwrap()], asm)
+            ),
+            _ => "obj".into(),
+        };
+        let args: Vec<String> = std::iter::once(this)
+            .chain((1..inputs.len()).map(|arg| format!("A{arg}")))
+            .collect();
+        writeln!(
+            method_defs,
+            "{}{{\n{alloc}\n{name}({});\nreturn obj;\n}}",
+            c_sig(&format!("{name}_new"), &inputs[1..], class, 1, asm),
+            args.join(", ")
+        )
+    }
     fn export_method_def(
         asm: &mut Assembly,
         def: &MethodDef,
+        module: &mut ModuleCtx,
         method_defs: &mut impl Write,
     ) -> std::io::Result<()> {
-        let class = &asm[def.class()];
-        let class_name = escape_ident(&asm[class.name()]);
-        let mname = escape_ident(&asm[def.name()]);
-        let method_name = class_member_name(&class_name, &mname);
-        let output = c_tpe(def.ref_to().output(asm), asm);
+        let name = mref_to_name(&def.ref_to(), asm);
+        let sig = def.sig();
+        let inputs = asm[sig].inputs().to_vec();
+        let output = *asm[sig].output();
         match def.resolved_implementation(asm) {
-            MethodImpl::MethodBody { blocks, locals } => (),
-            MethodImpl::Extern {
-                lib,
-                preserve_errno,
-            } => return Ok(()),
+            MethodImpl::MethodBody { .. } => (),
+            MethodImpl::Extern { .. } => return Ok(()),
             MethodImpl::Missing => {
-                let inputs = def
-                    .ref_to()
-                    .stack_inputs(asm)
-                    .iter()
-                    .map(|i| nonvoid_c_type(*i, asm))
-                    .intersperse(",".into())
-                    .collect::<String>();
-                writeln!(
+                return writeln!(
                     method_defs,
-                    "{output} {method_name}({inputs}){{eprintf(\"Missing method {mname}\\n\");abort();}}"
-                )?;
-                return Ok(());
+                    "{}{{\nc_rt_missing({});\n}}",
+                    c_sig(&name, &inputs, output, 0, asm),
+                    c_string(&asm[def.name()])
+                );
             }
-            MethodImpl::AliasFor(method_ref_idx) => panic!("Impossible: unrechable reached."),
+            MethodImpl::AliasFor(_) => panic!("Impossible: unrechable reached."),
         }
-        let sig = def.sig();
-        let stack_inputs = def.stack_inputs(asm);
-        let inputs = stack_inputs
-            .iter()
-            .enumerate()
-            .map(|(idx, (tpe, name))| match name {
-                Some(name) => format!(
-                    "{} {name}",
-                    nonvoid_c_type(*tpe, asm),
-                    name = escape_ident(&asm[*name]),
-                ),
-                None => format!("{} A{idx} ", nonvoid_c_type(*tpe, asm)),
-            })
-            .intersperse(",".into())
-            .collect::<String>();
-        writeln!(method_defs, "{output} {method_name}({inputs}){{")?;
-        let locals: Vec<_> = def.iter_locals(asm).copied().collect();
-        for (idx, (local_name, local_type)) in locals.iter().enumerate() {
-            match local_name {
-                Some(local_name) => writeln!(
-                    method_defs,
-                    "{local_type} {local_name};",
-                    local_name = escape_ident(&asm[*local_name]),
-                    local_type = nonvoid_c_type(asm[*local_type], asm),
-                ),
-                None => writeln!(
-                    method_defs,
-                    "{local_type} L{idx};",
-                    local_type = nonvoid_c_type(asm[*local_type], asm),
-                ),
-            }?;
+        if def.kind() == MethodKind::Constructor {
+            Self::export_factory(asm, def, method_defs)?;
         }
+        writeln!(method_defs, "{}{{", c_sig(&name, &inputs, output, 0, asm))?;
+        let locals: Vec<LocalDef> = def.iter_locals(asm).copied().collect();
         let blocks = def.blocks(asm).unwrap().to_vec();
-        for block in blocks {
-            writeln!(method_defs, "bb{}:", block.block_id())?;
+        let has_handlers = blocks.iter().any(|block| block.handler().is_some());
+        let volatile = if has_handlers { " volatile" } else { "" };
+        for (loc, (_, tpe)) in locals.iter().enumerate() {
+            writeln!(
+                method_defs,
+                "{}{volatile} L{loc};",
+                nonvoid_c_type(asm[*tpe], asm)
+            )?;
+        }
+        for block in blocks.iter().filter(|block| block.handler().is_some()) {
+            writeln!(method_defs, "c_rt_frame f{};", block.block_id())?;
+        }
+        let mut ctx = MethodCtx {
+            sig,
+            locals: &locals,
+            has_handlers,
+            protected: None,
+            handler: None,
+            module,
+        };
+        for block in &blocks {
+            let id = block.block_id();
+            writeln!(method_defs, "bb{id}:;")?;
+            let handler = block.handler();
+            if let Some(handler) = handler {
+                // Entering a protected block pushes its frame. Exceptions thrown inside it jump to its handler.
+                writeln!(method_defs, "f{id}.prev = c_rt_top;\nc_rt_top = &f{id};")?;
+                match handler.first() {
+                    Some(first) => writeln!(
+                        method_defs,
+                        "if (setjmp(f{id}.buf)) goto h{id}_{};",
+                        first.block_id()
+                    )?,
+                    None => writeln!(
+                        method_defs,
+                        "if (setjmp(f{id}.buf)) c_rt_throw(c_rt_exception);"
+                    )?,
+                }
+                writeln!(method_defs, "bb{id}_try:;")?;
+                ctx.protected = Some(id);
+            }
             for root in block.roots() {
-                let root = Self::root_to_string(
-                    asm[*root].clone(),
-                    asm,
-                    &locals[..],
-                    &stack_inputs[..],
-                    sig,
-                );
-                writeln!(method_defs, "{root}")?;
+                writeln!(method_defs, "{}", ctx.root(*root, asm))?;
+            }
+            ctx.protected = None;
+            let Some(handler) = handler else {
+                continue;
+            };
+            ctx.handler = Some((id, handler.iter().map(|block| block.block_id()).collect()));
+            for hblock in handler {
+                writeln!(method_defs, "h{id}_{}:;", hblock.block_id())?;
+                for root in hblock.roots() {
+                    writeln!(method_defs, "{}", ctx.root(*root, asm))?;
+                }
             }
+            ctx.handler = None;
         }
         writeln!(method_defs, "}}")
     }
-    #[allow(clippy::too_many_arguments)]
-    fn export_class(
-        &self,
-        asm: &mut super::Assembly,
+    /// Checks if the definition of `defid` depends on types which are not defined yet.
+    fn is_delayed(
+        asm: &Assembly,
         defid: ClassDefIdx,
-        method_decls: &mut impl Write,
-        method_defs: &mut impl Write,
-        type_defs: &mut impl Write,
-        defined_types: &mut FxHashSet<ClassDefIdx>,
-        delayed_defs: &mut FxHashSet<ClassDefIdx>,
-    ) -> std::io::Result<()> {
-        let class = asm[defid].clone();
-        // Checks if this def needs to be delayed, if one of its fields is not yet defined
-        if !class
+        defined_types: &FxHashSet<ClassDefIdx>,
+    ) -> bool {
+        let class = &asm[defid];
+        // Valuetype fields, and the parent class, must be complete types.
+        class
             .fields()
             .iter()
             .filter_map(|(tpe, _, _)| tpe.as_class_ref())
+            .filter(|cref| asm[*cref].is_valuetype())
+            .chain(class.extends().filter(|_| !class.is_valuetype()))
             .filter_map(|cref| asm.class_ref_to_def(cref))
-            .all(|cdef| defined_types.contains(&cdef))
-        {
-            delayed_defs.insert(defid);
-            return Ok(());
-        }
+            .any(|cdef| cdef != defid && !defined_types.contains(&cdef))
+    }
+    fn export_class(
+        asm: &Assembly,
+        defid: ClassDefIdx,
+        type_defs: &mut impl Write,
+        statics: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let class = &asm[defid];
         let class_name = escape_ident(&asm[class.name()]);
-        writeln!(type_defs, "typedef union {class_name}{{")?;
-        for (field_tpe, fname, offset) in class.fields() {
-            let fname = escape_ident(&asm[*fname]);
-            let Some(offset) = offset else {
-                eprintln!(
-                    "ERR: Can't export field {fname} of {class_name}, becuase it has no offset."
-                );
-                continue;
+        if class.is_valuetype() {
+            writeln!(type_defs, "union {class_name}{{")?;
+            let (offsets, _, _) = class_layout(class, asm);
+            for ((field_tpe, fname, _), offset) in class.fields().iter().zip(offsets) {
+                let fname = escape_ident(&asm[*fname]);
+                let (_, align) = size_align(*field_tpe, asm);
+                let packed = if offset % align != 0 {
+                    "C_RT_PACKED "
+                } else {
+                    ""
+                };
+                let pad = if offset == 0 {
+                    String::new()
+                } else {
+                    format!("char pad[{offset}]; ")
+                };
+                writeln!(
+                    type_defs,
+                    "struct {packed}{{{pad}{} f;}} {fname};",
+                    nonvoid_c_type(*field_tpe, asm)
+                )?;
+            }
+            if let Some(size) = class.explict_size() {
+                writeln!(type_defs, "char force_size[{size}];", size = size.get())?;
+            }
+            writeln!(type_defs, "}};")?;
+        } else {
+            writeln!(type_defs, "struct {class_name}{{")?;
+            let parent = class.extends().filter(|parent| is_defined(*parent, asm));
+            if let Some(parent) = parent {
+                writeln!(
+                    type_defs,
+                    "struct {} c_base;",
+                    escape_ident(&asm[asm[parent].name()])
+                )?;
+            } else if class.fields().is_empty() {
+                writeln!(type_defs, "char c_empty;")?;
+            }
+            for (field_tpe, fname, _) in class.fields() {
+                writeln!(
+                    type_defs,
+                    "struct {{{} f;}} {};",
+                    nonvoid_c_type(*field_tpe, asm),
+                    escape_ident(&asm[*fname])
+                )?;
+            }
+            writeln!(type_defs, "}};")?;
+            let parent_info = match (parent, class.extends()) {
+                (Some(parent), _) => format!("&c_type_{}", escape_ident(&asm[asm[parent].name()])),
+                (None, Some(parent)) if &asm[asm[parent].name()] == "System.Exception" => {
+                    "&c_rt_exception_type".into()
+                }
+                _ => "0".into(),
             };
-            let field_tpe = c_tpe(*field_tpe, asm);
             writeln!(
                 type_defs,
-                "struct {{char pad[{offset}]; {field_tpe} f;}}{fname};"
+                "static const c_rt_type c_type_{class_name} = {{{}, {parent_info}}};",
+                c_string(&asm[class.name()])
             )?;
         }
-        if let Some(size) = class.explict_size() {
-            writeln!(type_defs, "char force_size[{size}];", size = size.get())?;
-        }
-        writeln!(type_defs, "}} {class_name};")?;
         for (sfield_tpe, sfname, is_thread_local) in class.static_fields() {
-            let fname = escape_ident(&asm[*sfname]);
-            let field_tpe = c_tpe(*sfield_tpe, asm);
-            let fname = class_member_name(&class_name, &fname);
-            if *is_thread_local {
-                writeln!(type_defs, "static thread_local {field_tpe} {fname};")?;
+            let fname = class_member_name(&class_name, &escape_ident(&asm[*sfname]));
+            let thread_local = if *is_thread_local {
+                "_Thread_local "
             } else {
-                writeln!(type_defs, "static {field_tpe} {fname};")?;
-            }
+                ""
+            };
+            writeln!(
+                statics,
+                "static {thread_local}{} {fname};",
+                nonvoid_c_type(*sfield_tpe, asm)
+            )?;
         }
-        for method in class.methods() {
-            let mref = &asm[method.0];
-            let def = asm[*method].clone();
-            let is_extern = def.resolved_implementation(asm).is_extern();
-            if !is_extern {
-                Self::export_method_decl(asm, mref, method_decls)?;
-                Self::export_method_def(asm, &def, method_defs)?;
+        Ok(())
+    }
+    /// Exports the `main` function of an executable, which initializes the statics and calls the entrypoint.
+    fn export_main(asm: &Assembly, out: &mut impl Write) -> std::io::Result<()> {
+        let main_module = asm
+            .iter_class_def_ids()
+            .find(|def| &asm[asm[**def].name()] == MAIN_MODULE)
+            .copied();
+        writeln!(
+            out,
+            "int main(int argc, char **argv){{\nc_rt_argc = argc;\nc_rt_argv = argv;"
+        )?;
+        if let Some(main_module) = main_module {
+            for name in [
+                super::asm::CCTOR,
+                super::asm::TCCTOR,
+                super::asm::ENTRYPOINT,
+            ] {
+                let Some(method) = asm[main_module]
+                    .methods()
+                    .iter()
+                    .find(|method| &asm[asm[**method].name()] == name)
+                else {
+                    continue;
+                };
+                let args = asm[asm[*method].sig()]
+                    .inputs()
+                    .iter()
+                    .map(|tpe| match tpe {
+                        Type::PlatformArray { .. } => "c_rt_args()".to_string(),
+                        _ => format!("({})0", c_tpe(*tpe, asm)),
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(
+                    out,
+                    "{}({});",
+                    mref_to_name(&asm[**method], asm),
+                    args.join(", ")
+                )?;
             }
         }
-        defined_types.insert(defid);
-        Ok(())
+        writeln!(out, "return 0;\n}}")
     }
-    fn export_to_write(&self, asm: &super::Assembly, out: &mut impl Write) -> std::io::Result<()> {
+    fn export_to_write(&self, asm: &Assembly, out: &mut impl Write) -> std::io::Result<()> {
         let mut asm = asm.clone();
-        let mut method_defs = Vec::new();
-        let mut method_decls = Vec::new();
+        let mut module = ModuleCtx::default();
+        let mut fwd_defs = Vec::new();
         let mut type_defs = Vec::new();
+        let mut statics = Vec::new();
+        let mut method_decls = Vec::new();
+        let mut method_defs = Vec::new();
+        // All types are declared up front, so that they can be referenced before they are defined.
+        let mut class_defs: Vec<ClassDefIdx> = asm.iter_class_def_ids().copied().collect();
+        class_defs.sort_by(|a, b| asm[asm[*a].name()].cmp(&asm[asm[*b].name()]));
+        for defid in &class_defs {
+            let class = &asm[*defid];
+            let class_name = escape_ident(&asm[class.name()]);
+            if class.is_valuetype() {
+                writeln!(fwd_defs, "typedef union {class_name} {class_name};")?;
+            } else {
+                writeln!(
+                    fwd_defs,
+                    "typedef struct {class_name} {class_name};\nstatic const c_rt_type c_type_{class_name};"
+                )?;
+            }
+        }
+        // Types are defined once all the types they contain are defined.
         let mut defined_types: FxHashSet<ClassDefIdx> = FxHashSet::default();
-        let mut delayed_defs: FxHashSet<ClassDefIdx> = asm.iter_class_def_ids().cloned().collect();
-        let mut delayed_defs_copy: FxHashSet<ClassDefIdx> = FxHashSet::default();
+        let mut delayed_defs = class_defs.clone();
         while !delayed_defs.is_empty() {
-            std::mem::swap(&mut delayed_defs, &mut delayed_defs_copy);
-            for class_def in &delayed_defs_copy {
-                self.export_class(
-                    &mut asm,
-                    *class_def,
-                    &mut method_decls,
-                    &mut method_defs,
-                    &mut type_defs,
-                    &mut defined_types,
-                    &mut delayed_defs,
-                )?;
+            let count = delayed_defs.len();
+            let mut still_delayed = Vec::new();
+            for defid in delayed_defs {
+                if Self::is_delayed(&asm, defid, &defined_types) {
+                    still_delayed.push(defid);
+                    continue;
+                }
+                Self::export_class(&asm, defid, &mut type_defs, &mut statics)?;
+                defined_types.insert(defid);
+            }
+            assert!(
+                still_delayed.len() < count,
+                "Types with recursive layouts can't be exported."
+            );
+            delayed_defs = still_delayed;
+        }
+        for defid in &class_defs {
+            for method in asm[*defid].methods().to_vec() {
+                let def = asm[method].clone();
+                Self::export_method_decl(&asm, &def, &mut method_decls)?;
+                Self::export_method_def(&mut asm, &def, &mut module, &mut method_defs)?;
             }
-            delayed_defs_copy.clear();
         }
         out.write_all(include_bytes!("c_header.h"))?;
         out.write_all(b"\n")?;
+        if self.sanitize {
+            // Objects are never freed, so leaks are expected.
+            writeln!(
+                out,
+                "const char *__asan_default_options(void){{return \"detect_leaks=0\";}}"
+            )?;
+        }
+        out.write_all(&fwd_defs)?;
         out.write_all(&type_defs)?;
+        out.write_all(&statics)?;
         out.write_all(&method_decls)?;
+        for shim in module.externs.values() {
+            writeln!(out, "{shim}")?;
+        }
         out.write_all(&method_defs)?;
         if !self.is_lib {
-            out.write_all(b"void main(){_cctor();entrypoint((void *)0);}")?;
+            Self::export_main(&asm, out)?;
         }
         Ok(())
     }
@@ -906,37 +1888,41 @@ impl CExporter {
 impl Exporter for CExporter {
     type Error = std::io::Error;
 
-    fn export(&self, asm: &super::Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
-        // The IL file should be next to the target
+    fn export(&self, asm: &Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
+        // The C file should be next to the target
         let c_path = target.with_extension("c");
         let mut c_out = std::io::BufWriter::new(std::fs::File::create(&c_path)?);
         self.export_to_write(asm, &mut c_out)?;
-        // Needed to ensure the IL file is valid!
-        c_out.flush().unwrap();
+        c_out.flush()?;
         drop(c_out);
-        let exe_out = target;
-
         let mut cmd = std::process::Command::new(std::env::var("CC").unwrap_or("cc".to_owned()));
-        cmd.arg(c_path)
-        .arg("-o")
-        .arg(exe_out)
-        .arg("-g")
-        .arg("-Ofast")
-        // .arg("-FOLD") saves up on space, consider enabling.
-        ;
+        cmd.arg(&c_path)
+            .arg("-o")
+            .arg(target)
+            .arg("-std=gnu11")
+            // Signed overflow is well defined in the IR.
+            .arg("-fwrapv")
+            .arg("-fno-strict-aliasing")
+            .arg("-g")
+            .arg("-O2");
+        if self.sanitize {
+            // Undefined behaviour aborts the program, instead of just being reported.
+            cmd.arg("-fsanitize=address,undefined")
+                .arg("-fno-sanitize-recover=undefined")
+                .arg("-fno-omit-frame-pointer");
+        }
         if self.is_lib {
             cmd.arg("-c");
+        } else {
+            cmd.arg("-lm");
         }
         let out = cmd.output().unwrap();
-        let stdout = String::from_utf8_lossy(&out.stdout);
-        let stderr = String::from_utf8_lossy(&out.stderr);
         assert!(
-            !(stderr.contains("error") || stderr.contains("fatal")),
+            out.status.success(),
             "stdout:{} stderr:{} cmd:{cmd:?}",
-            stdout,
+            String::from_utf8_lossy(&out.stdout),
             String::from_utf8_lossy(&out.stderr)
         );
-
         Ok(())
     }
 }
@@ -949,15 +1935,318 @@ pub fn class_to_mangled(class: &super::ClassRef, asm: &Assembly) -> String {
     };
     format!("{assembly}{name}", name = asm.get_string(class.name()))
 }
-#[must_use]
-pub fn name_sig_class_to_mangled(
-    name: &str,
-    sig: super::SigIdx,
-    class: Option<ClassRefIdx>,
-    asm: &Assembly,
-) -> String {
-    let class = match class {
-        Some(_) => todo!(),
-        None => todo!(),
+#[test]
+fn export_arithmetic() {
+    use super::{Access, BasicBlock, ClassRef};
+    // Exporting requires a C compiler.
+    if std::process::Command::new(std::env::var("CC").unwrap_or("cc".to_owned()))
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        return;
+    }
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let u64_tpe = asm.alloc_type(Type::Int(Int::U64));
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line_sig = asm.sig([Type::Int(Int::U64)], Type::Void);
+    let write_line = asm.alloc_methodref(MethodRef::new(
+        console,
+        write_line,
+        write_line_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let lhs = asm.alloc_node(Const::U64(u64::MAX));
+    let rhs = asm.alloc_node(Const::U64(43));
+    let product = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Mul));
+    let store = asm.alloc_root(CILRoot::StLoc(0, product));
+    let local = asm.alloc_node(CILNode::LdLoc(0));
+    let call = asm.alloc_root(CILRoot::Call(Box::new((write_line, [local].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![store, call, ret], 0, None)],
+            locals: vec![(None, u64_tpe)],
+        },
+        vec![],
+    ));
+    asm.export("/tmp/c_export_arithmetic", CExporter::new(false, false));
+    let out = std::process::Command::new("/tmp/c_export_arithmetic")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "18446744073709551573\n"
+    );
+}
+#[test]
+fn export_sanitized() {
+    use super::{Access, BasicBlock, ClassDef, ClassRef, FieldDesc};
+    // Exporting requires a C compiler with AddressSanitizer and UndefinedBehaviorSanitizer support.
+    if !compile_c(
+        "int main(void){return 0;}",
+        "/tmp/c_sanitizer_check",
+        &["-fsanitize=address,undefined"],
+    ) {
+        return;
+    }
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    // A `u64` at offset 1 requires a packed struct.
+    let name = asm.alloc_string("Unaligned");
+    let fields = ["tag", "val"].map(|field| asm.alloc_string(field));
+    let def = asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![
+            (Type::Int(Int::U8), fields[0], Some(0)),
+            (Type::Int(Int::U64), fields[1], Some(1)),
+        ],
+        vec![],
+        Access::Public,
+        std::num::NonZeroU32::new(9),
+    ));
+    let unaligned = asm.alloc_type(Type::ClassRef(*def));
+    let val = asm.alloc_field(FieldDesc::new(*def, fields[1], Type::Int(Int::U64)));
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line_sig = asm.sig([Type::Int(Int::U64)], Type::Void);
+    let write_line = asm.alloc_methodref(MethodRef::new(
+        console,
+        write_line,
+        write_line_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let addr = asm.alloc_node(CILNode::LdLocA(0));
+    let lhs = asm.alloc_node(Const::U64(u64::MAX));
+    let rhs = asm.alloc_node(Const::U64(43));
+    let product = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Mul));
+    let store = asm.alloc_root(CILRoot::SetField(Box::new((val, addr, product))));
+    let load = asm.alloc_node(CILNode::LdField { addr, field: val });
+    let call = asm.alloc_root(CILRoot::Call(Box::new((write_line, [load].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![store, call, ret], 0, None)],
+            locals: vec![(None, unaligned)],
+        },
+        vec![],
+    ));
+    asm.export("/tmp/c_export_sanitized", CExporter::new(false, true));
+    let out = std::process::Command::new("/tmp/c_export_sanitized")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "18446744073709551573\n"
+    );
+}
+/// Compiles the C source `src` with `flags`, returning `false` if there is no C compiler.
+#[cfg(test)]
+fn compile_c(src: &str, out: &str, flags: &[&str]) -> bool {
+    let cc = std::env::var("CC").unwrap_or("cc".to_owned());
+    if std::process::Command::new(&cc)
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        return false;
+    }
+    let c_path = format!("{out}.c");
+    std::fs::write(&c_path, src).unwrap();
+    let res = std::process::Command::new(&cc)
+        .arg(&c_path)
+        .arg("-o")
+        .arg(out)
+        .args(["-std=gnu11", "-fwrapv", "-fno-strict-aliasing"])
+        .args(flags)
+        .output()
+        .unwrap();
+    assert!(res.status.success(), "{res:?}");
+    true
+}
+#[test]
+fn export_f16_f128() {
+    use super::{hashable::HashableF64, Access, BasicBlock, ClassRef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let print = |tpe: Type, asm: &mut Assembly| {
+        let sig = asm.sig([tpe], Type::Void);
+        asm.alloc_methodref(MethodRef::new(
+            console,
+            write_line,
+            sig,
+            MethodKind::Static,
+            vec![].into(),
+        ))
+    };
+    let print_f64 = print(Type::Float(Float::F64), &mut asm);
+    let print_bool = print(Type::Bool, &mut asm);
+    let float = |val: f64, target: Float, asm: &mut Assembly| {
+        let input = asm.alloc_node(Const::F64(HashableF64(val)));
+        asm.alloc_node(CILNode::FloatCast {
+            input,
+            target,
+            is_signed: true,
+        })
     };
+    let to_f64 = |input: NodeIdx, asm: &mut Assembly| {
+        asm.alloc_node(CILNode::FloatCast {
+            input,
+            target: Float::F64,
+            is_signed: true,
+        })
+    };
+    // `f16` operations are calls to `System.Half` methods.
+    let half = ClassRef::half(&mut asm);
+    let add = asm.alloc_string("op_Addition");
+    let add_sig = asm.sig(
+        [Type::Float(Float::F16), Type::Float(Float::F16)],
+        Type::Float(Float::F16),
+    );
+    let add = asm.alloc_methodref(MethodRef::new(
+        half,
+        add,
+        add_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let lhs = float(1.5, Float::F16, &mut asm);
+    let rhs = float(2.25, Float::F16, &mut asm);
+    let sum = asm.alloc_node(CILNode::Call(Box::new((add, [lhs, rhs].into()))));
+    let neg = asm.alloc_node(CILNode::UnOp(sum, UnOp::Neg));
+    let neg = to_f64(neg, &mut asm);
+    let print_half = asm.alloc_root(CILRoot::Call(Box::new((print_f64, [neg].into()))));
+    let lhs = float(7.5, Float::F128, &mut asm);
+    let rhs = float(2.0, Float::F128, &mut asm);
+    let rem = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Rem));
+    let rem = to_f64(rem, &mut asm);
+    let print_rem = asm.alloc_root(CILRoot::Call(Box::new((print_f64, [rem].into()))));
+    let lhs = float(-0.5, Float::F128, &mut asm);
+    let rhs = float(0.25, Float::F128, &mut asm);
+    let lt = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Lt));
+    let print_lt = asm.alloc_root(CILRoot::Call(Box::new((print_bool, [lt].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(
+                vec![print_half, print_rem, print_lt, ret],
+                0,
+                None,
+            )],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let mut src = Vec::new();
+    CExporter::new(false, false)
+        .export_to_write(&asm, &mut src)
+        .unwrap();
+    let src = String::from_utf8(src).unwrap();
+    // Without compiler support for `f16` and `f128`, the soft-float routines come from the Rust code, so the
+    // fallback can only be compiled here.
+    if !compile_c(
+        &src,
+        "/tmp/c_export_f16_f128_soft",
+        &["-c", "-DC_RT_SOFT_FLOAT"],
+    ) {
+        return;
+    }
+    compile_c(&src, "/tmp/c_export_f16_f128", &["-lm"]);
+    let out = std::process::Command::new("/tmp/c_export_f16_f128")
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "-3.75\n1.5\nTrue\n");
+}
+#[test]
+fn f128_rem() {
+    // Checks that the remainder of two `f128`s matches `fmod`, for values which fit in a `f64`.
+    const TEST: &str = r#"
+double c_fmod(double lhs, double rhs) C_RT_SYM("fmod");
+// Only handles normal numbers and zeroes, which have the same representation in both types.
+static c_rt_f128 to_f128(double val)
+{
+    uint64_t bits;
+    __builtin_memcpy(&bits, &val, sizeof(bits));
+    __uint128_t res = (__uint128_t)(bits >> 63) << 127;
+    if (bits << 1)
+        res |= (__uint128_t)((bits >> 52 & 0x7ff) + 16383 - 1023) << 112 | (__uint128_t)(bits & ((1ull << 52) - 1)) << 60;
+    return c_rt_f128_from_bits(res);
+}
+static bool is_normal(double val)
+{
+    return val == 0.0 || (val == val && __builtin_fabs(val) >= 0x1p-1022 && __builtin_fabs(val) <= 0x1.fffffffffffffp1023);
+}
+int main(void)
+{
+    uint64_t seed = 0x2545F4914F6CDD1D;
+    for (int idx = 0; idx < 100000; idx++)
+    {
+        double vals[2];
+        for (int val = 0; val < 2; val++)
+        {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            // Keeps the exponents close, so that the remainder is not always the dividend.
+            vals[val] = c_rt_f64_from_bits(seed & 0x83ffffffffffffffull | 0x3c00000000000000ull);
+        }
+        double expected = c_fmod(vals[0], vals[1]);
+        if (!is_normal(expected))
+            continue;
+        __uint128_t res = c_rt_f128_to_bits(c_rt_f128_rem(to_f128(vals[0]), to_f128(vals[1])));
+        if (res != c_rt_f128_to_bits(to_f128(expected)))
+            return 1;
+    }
+    if (!c_rt_f128_is_nan(c_rt_f128_rem(to_f128(1.0), to_f128(0.0))))
+        return 2;
+    if (c_rt_f128_to_bits(c_rt_f128_rem(to_f128(-4.0), to_f128(2.0))) != (__uint128_t)1 << 127)
+        return 3;
+    return 0;
+}
+"#;
+    let src = format!("{}\n{TEST}", include_str!("c_header.h"));
+    // The remainder does not depend on compiler support for `f128`.
+    for (flags, exe) in [
+        (&["-lm"][..], "/tmp/c_f128_rem"),
+        (&["-lm", "-DC_RT_SOFT_FLOAT"][..], "/tmp/c_f128_rem_soft"),
+    ] {
+        if !compile_c(&src, exe, flags) {
+            return;
+        }
+        let out = std::process::Command::new(exe).output().unwrap();
+        assert!(out.status.success(), "{out:?}");
+    }
 }
@@ -58,6 +58,13 @@ pub enum CILRoot {
     },
     /// Executing this root is instant UB.
     Unreachable(StringIdx),
+    /// Jumps to the target selected by the value of an integer node, of type `Int`. Only integers up to 64 bits wide are supported.
+    /// Exporters may evaluate the value more than once, so it must not have side effects.
+    Switch(Box<(NodeIdx, Int, SwitchTargets)>),
+    /// Calls a method and returns its result, reusing the frame of the caller where the target supports that.
+    TailCall(Box<(MethodRefIdx, Box<[NodeIdx]>)>),
+    /// Calls a fn pointer like [`CILRoot::TailCall`]: fn pointer, signature, args.
+    TailCallI(Box<(NodeIdx, SigIdx, Box<[NodeIdx]>)>),
 }
 
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -84,6 +91,120 @@ impl BranchCond {
         }
     }
 }
+/// The targets of a [`CILRoot::Switch`]. Each target is a `(target, sub_target)` pair, just like the one of a [`CILRoot::Branch`].
+/// Case values are the bits of the switched on value, zero extended to 64 bits.
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum SwitchTargets {
+    /// A jump table: `base + idx` jumps to `table[idx]`.
+    Dense {
+        base: u64,
+        table: Box<[(u32, u32)]>,
+        otherwise: (u32, u32),
+    },
+    /// Each value jumps to its own target. The cases are sorted by their values.
+    Sparse {
+        cases: Box<[(u64, (u32, u32))]>,
+        otherwise: (u32, u32),
+    },
+}
+impl SwitchTargets {
+    /// Creates the targets of a switch. A jump table is used if at least half of its entries would not be `otherwise`.
+    #[must_use]
+    pub fn new(mut cases: Vec<(u64, (u32, u32))>, otherwise: (u32, u32)) -> Self {
+        cases.sort_by_key(|(value, _)| *value);
+        cases.dedup_by_key(|(value, _)| *value);
+        cases.retain(|(_, target)| *target != otherwise);
+        let (Some((min, _)), Some((max, _))) = (cases.first(), cases.last()) else {
+            return Self::Sparse {
+                cases: cases.into(),
+                otherwise,
+            };
+        };
+        let len = cases.len() as u64;
+        if cases.len() < 2 || (max - min) / 2 >= len {
+            return Self::Sparse {
+                cases: cases.into(),
+                otherwise,
+            };
+        }
+        let base = *min;
+        let mut table = vec![otherwise; usize::try_from(max - min + 1).unwrap()];
+        for (value, target) in cases {
+            table[usize::try_from(value - base).unwrap()] = target;
+        }
+        Self::Dense {
+            base,
+            table: table.into(),
+            otherwise,
+        }
+    }
+    /// The target jumped to if no case matches.
+    #[must_use]
+    pub fn otherwise(&self) -> (u32, u32) {
+        match self {
+            Self::Dense { otherwise, .. } | Self::Sparse { otherwise, .. } => *otherwise,
+        }
+    }
+    /// The cases of this switch, sorted by their values. Cases jumping to `otherwise` are not included.
+    #[must_use]
+    pub fn cases(&self) -> Vec<(u64, (u32, u32))> {
+        match self {
+            Self::Dense {
+                base,
+                table,
+                otherwise,
+            } => (*base..)
+                .zip(table.iter())
+                .filter(|(_, target)| *target != otherwise)
+                .map(|(value, target)| (value, *target))
+                .collect(),
+            Self::Sparse { cases, .. } => cases.to_vec(),
+        }
+    }
+    /// All the targets of this switch, including `otherwise`. A target may appear more than once.
+    pub fn targets(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let cases: Box<dyn Iterator<Item = (u32, u32)>> = match self {
+            Self::Dense { table, .. } => Box::new(table.iter().copied()),
+            Self::Sparse { cases, .. } => Box::new(cases.iter().map(|(_, target)| *target)),
+        };
+        cases.chain(std::iter::once(self.otherwise()))
+    }
+    /// The target `value` jumps to.
+    #[must_use]
+    pub fn target_of(&self, value: u64) -> (u32, u32) {
+        let target = match self {
+            Self::Dense {
+                base,
+                table,
+                otherwise,
+            } => usize::try_from(value.wrapping_sub(*base))
+                .ok()
+                .and_then(|idx| table.get(idx))
+                .unwrap_or(otherwise),
+            Self::Sparse { cases, otherwise } => cases
+                .binary_search_by_key(&value, |(value, _)| *value)
+                .map_or(otherwise, |idx| &cases[idx].1),
+        };
+        *target
+    }
+    /// Changes each target using `map`. The cases are rebuilt, so a jump table may turn into a sparse switch, or the other way around.
+    #[must_use]
+    pub fn map_targets(&self, mut map: impl FnMut((u32, u32)) -> (u32, u32)) -> Self {
+        let cases = self
+            .cases()
+            .into_iter()
+            .map(|(value, target)| (value, map(target)))
+            .collect();
+        Self::new(cases, map(self.otherwise()))
+    }
+    /// Returns the target of this switch, if all values jump to the same place.
+    #[must_use]
+    pub fn single_target(&self) -> Option<(u32, u32)> {
+        let mut targets = self.targets();
+        let first = targets.next()?;
+        targets.all(|target| target == first).then_some(first)
+    }
+}
 #[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub enum CmpKind {
     Ordered,
@@ -136,7 +257,7 @@ impl CILRoot {
                 let (_, addr, val) = info.as_mut();
                 [addr, val].into()
             }
-            CILRoot::Call(info) => many_mut(&mut info.1).into(),
+            CILRoot::Call(info) | CILRoot::TailCall(info) => many_mut(&mut info.1).into(),
             CILRoot::StInd(info) => {
                 let (addr, val, _, _) = info.as_mut();
                 [addr, val].into()
@@ -145,13 +266,14 @@ impl CILRoot {
                 let (addr, val, len) = info.as_mut();
                 [addr, val, len].into()
             }
-            CILRoot::CallI(info) => {
+            CILRoot::CallI(info) | CILRoot::TailCallI(info) => {
                 let (ptr, _, args) = info.as_mut();
                 let mut args = many_mut(args);
                 args.push(ptr);
                 args.into()
             }
             CILRoot::CpObj { src, dst, .. } => [src, dst].into(),
+            CILRoot::Switch(info) => [&mut info.0].into(),
         }
     }
     pub fn nodes(&self) -> Box<[&NodeIdx]> {
@@ -186,7 +308,7 @@ impl CILRoot {
                 let (_, addr, val) = info.as_ref();
                 [addr, val].into()
             }
-            CILRoot::Call(info) => many_ref(&info.1).into(),
+            CILRoot::Call(info) | CILRoot::TailCall(info) => many_ref(&info.1).into(),
             CILRoot::StInd(info) => {
                 let (addr, val, _, _) = info.as_ref();
                 [addr, val].into()
@@ -195,13 +317,44 @@ impl CILRoot {
                 let (addr, val, len) = info.as_ref();
                 [addr, val, len].into()
             }
-            CILRoot::CallI(info) => {
+            CILRoot::CallI(info) | CILRoot::TailCallI(info) => {
                 let (ptr, _, args) = info.as_ref();
                 let mut args = many_ref(args);
                 args.push(ptr);
                 args.into()
             }
             CILRoot::CpObj { src, dst, .. } => [src, dst].into(),
+            CILRoot::Switch(info) => [&info.0].into(),
+        }
+    }
+    /// Splits a [`CILRoot::TailCall`] or [`CILRoot::TailCallI`] into an ordinary call, and a return of its result.
+    /// Used by targets which can't express tail calls. Returns `None` for all other roots.
+    pub fn tail_call_as_ret(&self, asm: &mut Assembly) -> Option<Vec<Self>> {
+        let (call, output) = match self {
+            CILRoot::TailCall(info) => {
+                let output = *asm[asm[info.0].sig()].output();
+                (CILNode::Call(info.clone()), output)
+            }
+            CILRoot::TailCallI(info) => (CILNode::CallI(info.clone()), *asm[info.1].output()),
+            _ => return None,
+        };
+        if output == Type::Void {
+            let call = match call {
+                CILNode::Call(info) => CILRoot::Call(info),
+                CILNode::CallI(info) => CILRoot::CallI(info),
+                _ => unreachable!(),
+            };
+            return Some(vec![call, CILRoot::VoidRet]);
+        }
+        Some(vec![CILRoot::Ret(asm.alloc_node(call))])
+    }
+    /// The `(target, sub_target)` pairs this root may jump to.
+    #[must_use]
+    pub fn targets(&self) -> Vec<(u32, u32)> {
+        match self {
+            CILRoot::Branch(info) => vec![(info.0, info.1)],
+            CILRoot::Switch(info) => info.2.targets().collect(),
+            _ => vec![],
         }
     }
     #[allow(clippy::too_many_lines)]
@@ -465,7 +618,20 @@ impl CILRoot {
                 }
                 tmp
             }
+            V1Root::TailCall(inner) => match Self::from_v1(inner, asm) {
+                Self::Call(info) => Self::TailCall(info),
+                Self::CallI(info) => Self::TailCallI(info),
+                _ => panic!("Only calls can be tail calls, not {inner:?}"),
+            },
             V1Root::ReThrow => Self::ReThrow,
+            V1Root::Switch {
+                value,
+                tpe,
+                targets,
+            } => {
+                let value = CILNode::from_v1(value, asm);
+                Self::Switch(Box::new((asm.alloc_node(value), *tpe, (**targets).clone())))
+            }
             V1Root::SetStaticField { descr, value } => {
                 let descr = StaticFieldDesc::from_v1(descr, asm);
                 let val = CILNode::from_v1(value, asm);
@@ -656,6 +822,12 @@ impl CILRoot {
                 )));
                 root_map(root, asm)
             }
+            CILRoot::Switch(switch) => {
+                let (value, tpe, targets) = *switch;
+                let value = asm.get_node(value).clone().map(asm, node_map);
+                let root = CILRoot::Switch(Box::new((asm.alloc_node(value), tpe, targets)));
+                root_map(root, asm)
+            }
             CILRoot::CallI(call_info) => {
                 let (ptr, sig, args) = *call_info;
                 let args = args
@@ -669,6 +841,31 @@ impl CILRoot {
                 let root = CILRoot::CallI(Box::new((asm.alloc_node(ptr), sig, args)));
                 root_map(root, asm)
             }
+            CILRoot::TailCall(call_info) => {
+                let (method_id, args) = *call_info;
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let node = asm.get_node(*arg).clone().map(asm, node_map);
+                        asm.alloc_node(node)
+                    })
+                    .collect();
+                let root = CILRoot::TailCall(Box::new((method_id, args)));
+                root_map(root, asm)
+            }
+            CILRoot::TailCallI(call_info) => {
+                let (ptr, sig, args) = *call_info;
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let node = asm.get_node(*arg).clone().map(asm, node_map);
+                        asm.alloc_node(node)
+                    })
+                    .collect();
+                let ptr = asm.get_node(ptr).clone().map(asm, node_map);
+                let root = CILRoot::TailCallI(Box::new((asm.alloc_node(ptr), sig, args)));
+                root_map(root, asm)
+            }
         }
     }
 }
@@ -734,3 +931,27 @@ fn test_many_mut() {
     *many_mut(&mut [1, 2, 3, 4, 5])[3] = 4;
     *many_mut(&mut [1, 2, 3, 4, 5])[4] = 5;
 }
+#[test]
+fn switch_targets() {
+    // Close values get a jump table, with the gaps jumping to `otherwise`.
+    let dense = SwitchTargets::new(vec![(12, (1, 0)), (10, (2, 0)), (13, (3, 0))], (4, 0));
+    let SwitchTargets::Dense { base, table, .. } = &dense else {
+        panic!("Expected a jump table, got {dense:?}");
+    };
+    assert_eq!(*base, 10);
+    assert_eq!(&**table, [(2, 0), (4, 0), (1, 0), (3, 0)]);
+    assert_eq!(dense.target_of(9), (4, 0));
+    assert_eq!(dense.target_of(12), (1, 0));
+    assert_eq!(dense.target_of(14), (4, 0));
+    assert_eq!(dense.cases(), [(10, (2, 0)), (12, (1, 0)), (13, (3, 0))]);
+    // Values far apart don't.
+    let sparse = SwitchTargets::new(vec![(u64::MAX, (1, 0)), (0, (2, 0))], (4, 0));
+    assert!(matches!(sparse, SwitchTargets::Sparse { .. }));
+    assert_eq!(sparse.target_of(u64::MAX), (1, 0));
+    assert_eq!(sparse.target_of(1), (4, 0));
+    // Cases jumping to `otherwise` are removed.
+    let merged = dense.map_targets(|_| (4, 0));
+    assert!(merged.cases().is_empty());
+    assert_eq!(merged.single_target(), Some((4, 0)));
+    assert_eq!(dense.single_target(), None);
+}
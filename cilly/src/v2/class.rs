@@ -121,6 +121,22 @@ impl ClassRef {
         let asm_name = Some(asm.alloc_string("System.Runtime"));
         asm.alloc_class_ref(ClassRef::new(name, asm_name, true, [].into()))
     }
+    /// Returns a reference to the `System.Runtime.Intrinsics.Vector{bits}<elem>` type.
+    pub fn simd_vector(bits: u32, elem: Type, asm: &mut Assembly) -> ClassRefIdx {
+        assert!(
+            matches!(bits, 64 | 128 | 256),
+            "There is no {bits} bit .NET vector type"
+        );
+        let name = asm.alloc_string(format!("System.Runtime.Intrinsics.Vector{bits}"));
+        let asm_name = Some(asm.alloc_string("System.Runtime.Intrinsics"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, true, [elem].into()))
+    }
+    /// Returns a reference to the static class `System.Runtime.Intrinsics.Vector{bits}`, which contains the operations on `Vector{bits}<T>`.
+    pub fn simd_vector_ops(bits: u32, asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string(format!("System.Runtime.Intrinsics.Vector{bits}"));
+        let asm_name = Some(asm.alloc_string("System.Runtime.Intrinsics"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
     /// Returns a reference to the `System.UIntPtr` type.
     pub fn usize_type(asm: &mut Assembly) -> ClassRefIdx {
         let name = asm.alloc_string("System.UIntPtr");
@@ -151,6 +167,17 @@ impl ClassRef {
         let asm_name = Some(asm.alloc_string("System.Runtime"));
         asm.alloc_class_ref(ClassRef::new(name, asm_name, true, [].into()))
     }
+    /// Returns a reference to the `System.BitConverter` type.
+    pub fn bit_converter(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("System.BitConverter");
+        let asm_name = Some(asm.alloc_string("System.Runtime"));
+        asm.alloc_class_ref(ClassRef::new(name, asm_name, false, [].into()))
+    }
+    /// Returns a reference to the `f128` type, used to emulate 128 bit floats.
+    pub fn f128(asm: &mut Assembly) -> ClassRefIdx {
+        let name = asm.alloc_string("f128");
+        asm.alloc_class_ref(ClassRef::new(name, None, true, [].into()))
+    }
     /// Returns a reference to the `System.Byte` type.
     pub fn byte(asm: &mut Assembly) -> ClassRefIdx {
         let name = asm.alloc_string("System.Byte");
@@ -393,6 +420,7 @@ pub struct ClassDef {
     is_valuetype: bool,
     generics: u32,
     extends: Option<ClassRefIdx>,
+    implements: Vec<ClassRefIdx>,
     fields: Vec<(Type, StringIdx, Option<u32>)>,
     static_fields: Vec<(Type, StringIdx, bool)>,
     methods: Vec<MethodDefIdx>,
@@ -415,6 +443,7 @@ impl ClassDef {
             .chain(self.static_fields().iter().map(|(tpe, _, _)| tpe))
             .copied()
             .chain(self.extends.iter().map(|cref| Type::ClassRef(*cref)))
+            .chain(self.implements.iter().map(|cref| Type::ClassRef(*cref)))
     }
     #[allow(clippy::too_many_arguments)]
     #[must_use]
@@ -435,6 +464,7 @@ impl ClassDef {
             is_valuetype,
             generics,
             extends,
+            implements: vec![],
             fields,
             static_fields,
             methods: vec![],
@@ -471,6 +501,18 @@ impl ClassDef {
         self.extends
     }
 
+    /// Returns the interfaces this class implements.
+    #[must_use]
+    pub fn implements(&self) -> &[ClassRefIdx] {
+        &self.implements
+    }
+    /// Marks this class as implementing the interface `interface`.
+    pub fn add_interface(&mut self, interface: ClassRefIdx) {
+        if !self.implements.contains(&interface) {
+            self.implements.push(interface);
+        }
+    }
+
     pub(crate) fn has_explicit_layout(&self) -> bool {
         self.explict_size.is_some() || self.fields.iter().any(|(_, _, offset)| offset.is_some())
     }
@@ -515,6 +557,7 @@ impl ClassDef {
         assert_eq!(self.generics(), translated.generics());
         // Check inheretence matches
         assert_eq!(self.extends(), translated.extends());
+        assert_eq!(self.implements(), translated.implements());
 
         // Merge the static fields, removing duplicates
         self.static_fields_mut().extend(translated.static_fields());
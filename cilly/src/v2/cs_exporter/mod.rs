@@ -0,0 +1,566 @@
+//! Writes a C# facade of an assembly: a source file declaring its public API, with all method bodies replaced by `throw null`.
+//! C# projects can compile against the facade, and IDEs can use it to provide completion and documentation for Rust libraries.
+use std::{collections::BTreeMap, io::Write};
+
+use fxhash::FxHashSet;
+
+use super::{
+    cilnode::MethodKind, tpe::GenericKind, Access, Assembly, ClassDef, ClassDefIdx, ClassRefIdx,
+    Exporter, Float, Int, MethodDef, Type,
+};
+#[derive(Default)]
+pub struct CSharpExporter {}
+impl CSharpExporter {
+    fn export_to_write(&self, asm: &Assembly, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "// Facade of a Rust library compiled by rustc_codegen_clr. All method bodies are stubs: the implementation lives in the compiled assembly."
+        )?;
+        writeln!(out, "#pragma warning disable CS0114, CS0649")?;
+        // Group the classes by namespace, sorting them to make the output deterministic.
+        let mut namespaces: BTreeMap<String, Vec<(String, ClassDefIdx)>> = BTreeMap::new();
+        for class in public_classes(asm) {
+            let full_name = asm.get_string(asm.class_ref(*class).name());
+            let (namespace, name) = full_name
+                .rsplit_once('.')
+                .map_or(("", full_name.as_ref()), |(namespace, name)| {
+                    (namespace, name)
+                });
+            namespaces
+                .entry(namespace.to_owned())
+                .or_default()
+                .push((name.to_owned(), class));
+        }
+        for (namespace, mut classes) in namespaces {
+            classes.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let indent = if namespace.is_empty() {
+                ""
+            } else {
+                writeln!(out, "namespace {}\n{{", escape_path(&namespace))?;
+                "    "
+            };
+            for (name, class) in classes {
+                self.export_class(asm, asm.get_class_def(class), &name, indent, out)?;
+            }
+            if !namespace.is_empty() {
+                writeln!(out, "}}")?;
+            }
+        }
+        Ok(())
+    }
+    fn export_class(
+        &self,
+        asm: &Assembly,
+        class_def: &ClassDef,
+        name: &str,
+        indent: &str,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let name = escape_ident(name);
+        let methods: Vec<&MethodDef> = class_def
+            .methods()
+            .iter()
+            .map(|method| asm.method_def(*method))
+            .filter(|method| is_public(*method.access()) && !is_special(method, asm))
+            .collect();
+        let is_static = !class_def.is_valuetype()
+            && class_def.fields().is_empty()
+            && methods
+                .iter()
+                .all(|method| method.kind() == MethodKind::Static);
+        if let Some(size) = class_def.explict_size() {
+            writeln!(out,"{indent}[System.Runtime.InteropServices.StructLayout(System.Runtime.InteropServices.LayoutKind.Explicit, Size = {size})]")?;
+        } else if class_def.has_explicit_layout() {
+            writeln!(out,"{indent}[System.Runtime.InteropServices.StructLayout(System.Runtime.InteropServices.LayoutKind.Explicit)]")?;
+        }
+        let kind = match (class_def.is_valuetype(), is_static) {
+            (true, _) => "unsafe struct",
+            (false, true) => "static unsafe class",
+            (false, false) => "unsafe class",
+        };
+        let generics = generic_params(class_def.generics());
+        let bases: Vec<String> = class_def
+            .extends()
+            .filter(|_| !class_def.is_valuetype())
+            .filter(|parent| {
+                let name = asm.get_string(asm.class_ref(*parent).name());
+                !matches!(name.as_ref(), "System.Object" | "System.ValueType")
+            })
+            .into_iter()
+            .chain(class_def.implements().iter().copied())
+            .map(|base| class_ref_cs(base, asm))
+            .collect();
+        let bases = if bases.is_empty() {
+            String::new()
+        } else {
+            format!(" : {}", bases.join(", "))
+        };
+        writeln!(
+            out,
+            "{indent}public {kind} {name}{generics}{bases}\n{indent}{{"
+        )?;
+        for (tpe, field_name, offset) in class_def.fields() {
+            if let Some(offset) = offset {
+                writeln!(
+                    out,
+                    "{indent}    [System.Runtime.InteropServices.FieldOffset({offset})]"
+                )?;
+            }
+            writeln!(
+                out,
+                "{indent}    public {tpe} {field_name};",
+                tpe = type_cs(tpe, asm),
+                field_name = escape_ident(asm.get_string(*field_name))
+            )?;
+        }
+        for method in methods {
+            self.export_method(asm, method, &name, indent, out)?;
+        }
+        writeln!(out, "{indent}}}")
+    }
+    fn export_method(
+        &self,
+        asm: &Assembly,
+        method: &MethodDef,
+        class_name: &str,
+        indent: &str,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        if let Some(doc) = method.doc() {
+            writeln!(out, "{indent}    /// <summary>")?;
+            for line in asm.get_string(doc).lines() {
+                writeln!(out, "{indent}    /// {}", escape_xml(line))?;
+            }
+            writeln!(out, "{indent}    /// </summary>")?;
+        }
+        let sig = asm.get_sig(method.sig());
+        let inputs = match method.kind() {
+            MethodKind::Static => sig.inputs(),
+            MethodKind::Instance | MethodKind::Virtual | MethodKind::Constructor => {
+                &sig.inputs()[1..]
+            }
+        };
+        let arg_names = &method.arg_names()[(sig.inputs().len() - inputs.len())..];
+        let params = inputs
+            .iter()
+            .enumerate()
+            .map(|(idx, tpe)| {
+                let name = arg_names.get(idx).copied().flatten().map_or_else(
+                    || format!("arg{idx}"),
+                    |name| escape_ident(asm.get_string(name)),
+                );
+                format!("{tpe} {name}", tpe = type_cs(tpe, asm))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let name = escape_ident(asm.get_string(method.name()));
+        let ret = type_cs(sig.output(), asm);
+        let signature = match method.kind() {
+            MethodKind::Static => format!("public static {ret} {name}({params})"),
+            MethodKind::Instance => format!("public {ret} {name}({params})"),
+            MethodKind::Virtual => format!("public virtual {ret} {name}({params})"),
+            MethodKind::Constructor => format!("public {class_name}({params})"),
+        };
+        writeln!(out, "{indent}    {signature} {{ throw null; }}")
+    }
+}
+impl Exporter for CSharpExporter {
+    type Error = std::io::Error;
+
+    fn export(&self, asm: &Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
+        let cs_path = target.with_extension("cs");
+        let mut cs_out = std::io::BufWriter::new(std::fs::File::create(cs_path)?);
+        self.export_to_write(asm, &mut cs_out)
+    }
+}
+fn is_public(access: Access) -> bool {
+    matches!(access, Access::Extern | Access::Public)
+}
+/// Checks if a method is a runtime-specific method(like a static constructor), which can't be declared in C#.
+fn is_special(method: &MethodDef, asm: &Assembly) -> bool {
+    asm.get_string(method.name()).starts_with('.') && method.kind() != MethodKind::Constructor
+}
+/// Returns the classes making up the public API of an assembly: all `extern` classes, and all classes defined in this assembly that they expose.
+fn public_classes(asm: &Assembly) -> Vec<ClassDefIdx> {
+    let mut public: FxHashSet<ClassDefIdx> = asm
+        .class_defs()
+        .iter()
+        .filter(|(_, def)| def.access().is_extern())
+        .map(|(idx, _)| *idx)
+        .collect();
+    let mut to_visit: Vec<ClassDefIdx> = public.iter().copied().collect();
+    while let Some(class) = to_visit.pop() {
+        let def = asm.get_class_def(class);
+        let method_types = def
+            .methods()
+            .iter()
+            .map(|method| asm.method_def(*method))
+            .filter(|method| is_public(*method.access()))
+            .flat_map(|method| asm.get_sig(method.sig()).iter_types());
+        let types: Vec<Type> = def
+            .fields()
+            .iter()
+            .map(|(tpe, _, _)| *tpe)
+            .chain(method_types)
+            .chain(def.extends().map(Type::ClassRef))
+            .chain(def.implements().iter().copied().map(Type::ClassRef))
+            .collect();
+        // `f128` is stored in the `f128` struct defined by the linker, so the facade must declare it too.
+        let f128 = types
+            .iter()
+            .any(|tpe| contains_f128(tpe, asm))
+            .then(|| f128_def(asm))
+            .flatten();
+        let exposed: Vec<ClassDefIdx> = types
+            .iter()
+            .flat_map(|tpe| tpe.iter_class_refs(asm).collect::<Vec<_>>())
+            .filter_map(|cref| asm.class_ref_to_def(cref))
+            .chain(f128)
+            .collect();
+        for class in exposed {
+            if public.insert(class) {
+                to_visit.push(class);
+            }
+        }
+    }
+    public.into_iter().collect()
+}
+/// Checks if `tpe` contains a `f128`.
+fn contains_f128(tpe: &Type, asm: &Assembly) -> bool {
+    match tpe {
+        Type::Float(Float::F128) => true,
+        Type::PlatformArray { elem: inner, .. } | Type::Ptr(inner) | Type::Ref(inner) => {
+            contains_f128(asm.get_type(*inner), asm)
+        }
+        Type::FnPtr(sig) => asm
+            .get_sig(*sig)
+            .iter_types()
+            .any(|tpe| contains_f128(&tpe, asm)),
+        Type::ClassRef(cref) => asm
+            .class_ref(*cref)
+            .generics()
+            .iter()
+            .any(|tpe| contains_f128(tpe, asm)),
+        _ => false,
+    }
+}
+/// Returns the `f128` struct, if the linker defined it.
+fn f128_def(asm: &Assembly) -> Option<ClassDefIdx> {
+    asm.class_defs()
+        .iter()
+        .find(|(_, def)| asm.get_string(def.name()).as_ref() == "f128")
+        .map(|(idx, _)| *idx)
+}
+/// Returns the C# name of a class.
+fn class_ref_cs(cref: ClassRefIdx, asm: &Assembly) -> String {
+    let cref = asm.class_ref(cref);
+    let name = asm.get_string(cref.name());
+    // Generic .NET types have their arity appended to their name, which is not a part of the C# name.
+    let name = name.split_once('`').map_or(name.as_ref(), |(name, _)| name);
+    let generics = if cref.generics().is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<{}>",
+            cref.generics()
+                .iter()
+                .map(|tpe| type_cs(tpe, asm))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+    format!("global::{}{generics}", escape_path(name))
+}
+/// Returns the C# name of a type.
+fn type_cs(tpe: &Type, asm: &Assembly) -> String {
+    match tpe {
+        Type::Ptr(inner) => format!("{}*", type_cs(asm.get_type(*inner), asm)),
+        // Managed references can only appear in signatures, where they are passed by `ref`.
+        Type::Ref(inner) => format!("ref {}", type_cs(asm.get_type(*inner), asm)),
+        Type::Int(int) => match int {
+            Int::U8 => "byte",
+            Int::U16 => "ushort",
+            Int::U32 => "uint",
+            Int::U64 => "ulong",
+            Int::U128 => "global::System.UInt128",
+            Int::USize => "nuint",
+            Int::I8 => "sbyte",
+            Int::I16 => "short",
+            Int::I32 => "int",
+            Int::I64 => "long",
+            Int::I128 => "global::System.Int128",
+            Int::ISize => "nint",
+        }
+        .into(),
+        Type::ClassRef(cref) => class_ref_cs(*cref, asm),
+        Type::Float(float) => match float {
+            Float::F16 => "global::System.Half",
+            Float::F32 => "float",
+            Float::F64 => "double",
+            Float::F128 => "global::f128",
+        }
+        .into(),
+        Type::PlatformString => "string".into(),
+        Type::PlatformChar => "char".into(),
+        Type::PlatformGeneric(arg, GenericKind::CallGeneric) => format!("M{arg}"),
+        Type::PlatformGeneric(arg, GenericKind::MethodGeneric | GenericKind::TypeGeneric) => {
+            format!("T{arg}")
+        }
+        Type::PlatformObject => "object".into(),
+        Type::Bool => "bool".into(),
+        Type::Void => "void".into(),
+        Type::PlatformArray { elem, dims } => format!(
+            "{elem}[{dims}]",
+            elem = type_cs(asm.get_type(*elem), asm),
+            dims = ",".repeat(usize::from(dims.get()) - 1)
+        ),
+        Type::FnPtr(sig) => {
+            let sig = asm.get_sig(*sig);
+            let types: Vec<String> = sig
+                .inputs()
+                .iter()
+                .chain(std::iter::once(sig.output()))
+                .map(|tpe| type_cs(tpe, asm))
+                .collect();
+            format!("delegate*<{}>", types.join(", "))
+        }
+    }
+}
+fn generic_params(count: u32) -> String {
+    if count == 0 {
+        return String::new();
+    }
+    format!(
+        "<{}>",
+        (0..count)
+            .map(|idx| format!("T{idx}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+/// Escapes a dotted path(like a namespace), so that each of its parts is a valid C# identifier.
+fn escape_path(path: &str) -> String {
+    path.split('.')
+        .map(escape_ident)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+/// Turns `ident` into a valid C# identifier, by replacing all invalid characters and escaping keywords.
+fn escape_ident(ident: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract",
+        "as",
+        "base",
+        "bool",
+        "break",
+        "byte",
+        "case",
+        "catch",
+        "char",
+        "checked",
+        "class",
+        "const",
+        "continue",
+        "decimal",
+        "default",
+        "delegate",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "event",
+        "explicit",
+        "extern",
+        "false",
+        "finally",
+        "fixed",
+        "float",
+        "for",
+        "foreach",
+        "goto",
+        "if",
+        "implicit",
+        "in",
+        "int",
+        "interface",
+        "internal",
+        "is",
+        "lock",
+        "long",
+        "namespace",
+        "new",
+        "null",
+        "object",
+        "operator",
+        "out",
+        "override",
+        "params",
+        "private",
+        "protected",
+        "public",
+        "readonly",
+        "ref",
+        "return",
+        "sbyte",
+        "sealed",
+        "short",
+        "sizeof",
+        "stackalloc",
+        "static",
+        "string",
+        "struct",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typeof",
+        "uint",
+        "ulong",
+        "unchecked",
+        "unsafe",
+        "ushort",
+        "using",
+        "virtual",
+        "void",
+        "volatile",
+        "while",
+    ];
+    if KEYWORDS.contains(&ident) {
+        return format!("@{ident}");
+    }
+    let mut escaped: String = ident
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if escaped.is_empty() || escaped.starts_with(|c: char| c.is_ascii_digit()) {
+        escaped.insert(0, '_');
+    }
+    escaped
+}
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+#[test]
+fn exported_method() {
+    use super::{BasicBlock, CILRoot, ClassRef, MethodImpl};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("RustExports.Math");
+    let object = ClassRef::object(&mut asm);
+    let class = asm.class_def(ClassDef::new(
+        name,
+        false,
+        0,
+        Some(object),
+        vec![],
+        vec![],
+        Access::Extern,
+        None,
+    ));
+    let values = asm.alloc_type(Type::Int(Int::U64));
+    let sig = asm.sig(
+        [
+            Type::PlatformString,
+            Type::PlatformArray {
+                elem: values,
+                dims: std::num::NonZeroU8::new(1).unwrap(),
+            },
+        ],
+        Type::Int(Int::U64),
+    );
+    let ret = asm.alloc_node(super::Const::U64(0));
+    let ret = asm.alloc_root(CILRoot::Ret(ret));
+    let method_name = asm.alloc_string("Sum");
+    let arg_names = vec![Some(asm.alloc_string("string")), None];
+    let mut method = MethodDef::new(
+        Access::Extern,
+        class,
+        method_name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        arg_names,
+    );
+    method.set_doc(Some(asm.alloc_string("Sums <values>.")));
+    asm.new_method(method);
+    let mut out = Vec::new();
+    CSharpExporter::default()
+        .export_to_write(&asm, &mut out)
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("namespace RustExports"));
+    assert!(out.contains("public static unsafe class Math"));
+    assert!(out.contains("/// Sums &lt;values&gt;."));
+    assert!(
+        out.contains("public static ulong Sum(string @string, ulong[] arg1) { throw null; }"),
+        "{out}"
+    );
+}
+#[test]
+fn f128_struct() {
+    use super::{BasicBlock, CILRoot, ClassRef, MethodImpl};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("f128");
+    asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![],
+        vec![],
+        Access::Public,
+        std::num::NonZeroU32::new(16),
+    ));
+    let name = asm.alloc_string("RustExports.Math");
+    let object = ClassRef::object(&mut asm);
+    let class = asm.class_def(ClassDef::new(
+        name,
+        false,
+        0,
+        Some(object),
+        vec![],
+        vec![],
+        Access::Extern,
+        None,
+    ));
+    let sig = asm.sig([Type::Float(Float::F128)], Type::Void);
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let method_name = asm.alloc_string("Quad");
+    let arg_names = vec![Some(asm.alloc_string("value"))];
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        class,
+        method_name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        arg_names,
+    ));
+    let mut out = Vec::new();
+    CSharpExporter::default()
+        .export_to_write(&asm, &mut out)
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.contains("LayoutKind.Explicit, Size = 16)]\npublic unsafe struct f128"),
+        "{out}"
+    );
+    assert!(
+        out.contains("public static void Quad(global::f128 value) { throw null; }"),
+        "{out}"
+    );
+}
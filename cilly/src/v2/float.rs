@@ -3,7 +3,8 @@ use serde::{Deserialize, Serialize};
 use super::{
     cilnode::MethodKind,
     hashable::{HashableF32, HashableF64},
-    Assembly, CILNode, ClassRef, ClassRefIdx, Const, MethodRef, NodeIdx, Type,
+    Assembly, BinOp, CILNode, ClassRef, ClassRefIdx, Const, Int, MethodRef, MethodRefIdx, NodeIdx,
+    Type,
 };
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
@@ -15,15 +16,52 @@ pub enum Float {
 }
 impl Float {
     /// Returns a constant 0 of this float.
-    #[must_use]
-    pub fn zero(&self) -> Const {
+    pub fn zero(&self, asm: &mut Assembly) -> NodeIdx {
         match self {
-            Float::F16 => todo!(),
-            Float::F32 => Const::F32(HashableF32(0.0)),
-            Float::F64 => Const::F64(HashableF64(0.0)),
-            Float::F128 => todo!(),
+            Float::F32 => asm.alloc_node(Const::F32(HashableF32(0.0))),
+            Float::F64 => asm.alloc_node(Const::F64(HashableF64(0.0))),
+            // `f16` and `f128` have no constants of their own.
+            Float::F16 | Float::F128 => {
+                let zero = asm.alloc_node(Const::F64(HashableF64(0.0)));
+                self.cast_from_f64(zero, asm)
+            }
         }
     }
+    /// Converts a `f64` to this float type.
+    pub fn cast_from_f64(&self, val: NodeIdx, asm: &mut Assembly) -> NodeIdx {
+        match self {
+            Float::F32 => asm.alloc_node(CILNode::FloatCast {
+                input: val,
+                target: Float::F32,
+                is_signed: true,
+            }),
+            Float::F64 => val,
+            Float::F16 | Float::F128 => {
+                let mref = self.cast_from_f64_mref(asm);
+                asm.alloc_node(CILNode::Call(Box::new((mref, [val].into()))))
+            }
+        }
+    }
+    /// Returns the method converting a `f64` to a `f16` or a `f128`. Those types have no conversion instructions, so
+    /// `System.Half::op_Explicit` and the `__extenddftf2` soft-float routine are used instead.
+    /// # Panics
+    /// Panics if this is a `f32` or a `f64`.
+    pub fn cast_from_f64_mref(&self, asm: &mut Assembly) -> MethodRefIdx {
+        let (class, name) = match self {
+            Float::F16 => (ClassRef::half(asm), "op_Explicit"),
+            Float::F128 => (*asm.main_module(), "__extenddftf2"),
+            Float::F32 | Float::F64 => panic!("{self:?} can be converted from a f64 by `conv.r`"),
+        };
+        let name = asm.alloc_string(name);
+        let sig = asm.sig([Type::Float(Float::F64)], *self);
+        asm.alloc_methodref(MethodRef::new(
+            class,
+            name,
+            sig,
+            MethodKind::Static,
+            [].into(),
+        ))
+    }
     /// Checks if this float is NaN
     pub fn is_nan(&self, val: NodeIdx, asm: &mut Assembly) -> NodeIdx {
         let is_nan = asm.alloc_string("IsNaN");
@@ -52,7 +90,7 @@ impl Float {
                 asm.alloc_node(CILNode::Call(Box::new((mref, [val].into()))))
             }
             Float::F64 => {
-                let double = ClassRef::single(asm);
+                let double = ClassRef::double(asm);
                 let mref = asm.alloc_methodref(MethodRef::new(
                     double,
                     is_nan,
@@ -62,7 +100,25 @@ impl Float {
                 ));
                 asm.alloc_node(CILNode::Call(Box::new((mref, [val].into()))))
             }
-            Float::F128 => todo!(),
+            // `__unordtf2` returns a non-zero value if any of its arguments is NaN.
+            Float::F128 => {
+                let main_module = *asm.main_module();
+                let unord = asm.alloc_string("__unordtf2");
+                let sig = asm.sig(
+                    [Type::Float(*self), Type::Float(*self)],
+                    Type::Int(Int::I32),
+                );
+                let mref = asm.alloc_methodref(MethodRef::new(
+                    main_module,
+                    unord,
+                    sig,
+                    MethodKind::Static,
+                    [].into(),
+                ));
+                let unord = asm.alloc_node(CILNode::Call(Box::new((mref, [val, val].into()))));
+                let zero = asm.alloc_node(Const::I32(0));
+                asm.alloc_node(CILNode::BinOp(unord, zero, BinOp::GtUn))
+            }
         }
     }
     /// Returns a short name of the float
@@ -98,7 +154,7 @@ impl Float {
             Float::F16 => ClassRef::half(asm),
             Float::F32 => ClassRef::single(asm),
             Float::F64 => ClassRef::double(asm),
-            Float::F128 => todo!(),
+            Float::F128 => ClassRef::f128(asm),
         }
     }
     /// Raises base to power.
@@ -128,3 +184,16 @@ fn name() {
     assert_eq!(Float::F64.name(), "f64");
     assert_eq!(Float::F128.name(), "f128");
 }
+#[test]
+fn cast_from_f64() {
+    let mut asm = Assembly::default();
+    let val = asm.alloc_node(Const::F64(HashableF64(1.0)));
+    assert_eq!(Float::F64.cast_from_f64(val, &mut asm), val);
+    for float in [Float::F16, Float::F128] {
+        let cast = float.cast_from_f64(val, &mut asm);
+        let CILNode::Call(call) = asm.get_node(cast) else {
+            panic!("{float:?} should be converted from a f64 using a call");
+        };
+        assert_eq!(call.1.as_ref(), &[val]);
+    }
+}
@@ -5,11 +5,11 @@ use std::{io::Write, path::Path};
 use super::{
     asm::{IlasmFlavour, ILASM_FLAVOUR, ILASM_PATH},
     cilnode::{ExtendKind, UnOp},
-    cilroot::BranchCond,
+    cilroot::{BranchCond, SwitchTargets},
     int,
     method::LocalDef,
-    Assembly, BinOp, CILIter, CILIterElem, CILNode, ClassRefIdx, Exporter, Int, NodeIdx, RootIdx,
-    SigIdx, Type,
+    Assembly, BinOp, CILIter, CILIterElem, CILNode, ClassRefIdx, Exporter, Int, MethodRefIdx,
+    NodeIdx, RootIdx, SigIdx, Type,
 };
 
 pub struct ILExporter {
@@ -48,10 +48,23 @@ impl ILExporter {
             } else {
                 "auto"
             };
+            let implements = if class_def.implements().is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " implements {}",
+                    class_def
+                        .implements()
+                        .iter()
+                        .map(|interface| simple_class_ref(*interface, asm))
+                        .intersperse(",".to_string())
+                        .collect::<String>()
+                )
+            };
             let name = asm.get_string(class_def.name());
             writeln!(
                 out,
-                ".class {vis} ansi {sealed} {explicit} '{name}' extends {extends}{{"
+                ".class {vis} ansi {sealed} {explicit} '{name}' extends {extends}{implements}{{"
             )?;
             // Export size
             if let Some(size) = class_def.explict_size() {
@@ -433,7 +446,11 @@ impl ILExporter {
                     .collect();
                 let name = asm.get_string(mref.name());
                 let class = class_ref(mref.class(), asm);
-                writeln!(out, "{call_op} {output} {class}::'{name}'({inputs})")
+                let generics = method_generics(mref.generics(), asm);
+                writeln!(
+                    out,
+                    "{call_op} {output} {class}::'{name}'{generics}({inputs})"
+                )
             }
             CILNode::IntCast {
                 input,
@@ -486,14 +503,24 @@ impl ILExporter {
             } => {
                 self.export_node(asm, out, input, sig, locals)?;
                 match (target, is_signed) {
-                    (super::Float::F16, true) => todo!(),
-                    (super::Float::F16, false) => todo!(),
                     (super::Float::F32, true) => writeln!(out, "conv.r4"),
                     (super::Float::F32, false) => writeln!(out, "conv.r.un conv.r4"),
                     (super::Float::F64, true) => writeln!(out, "conv.r8"),
                     (super::Float::F64, false) => writeln!(out, "conv.r.un conv.r8"),
-                    (super::Float::F128, true) => todo!(),
-                    (super::Float::F128, false) => todo!(),
+                    // No instruction converts to `f16` or `f128`, so go trough a `f64`.
+                    (super::Float::F16 | super::Float::F128, is_signed) => {
+                        if is_signed {
+                            writeln!(out, "conv.r8")?;
+                        } else {
+                            writeln!(out, "conv.r.un conv.r8")?;
+                        }
+                        let mref = target.cast_from_f64_mref(asm);
+                        let mref = asm.get_mref(mref);
+                        let output = type_il(&Type::Float(target), asm);
+                        let class = class_ref(mref.class(), asm);
+                        let name = asm.get_string(mref.name());
+                        writeln!(out, "call {output} {class}::'{name}'(float64)")
+                    }
                 }
             }
             CILNode::RefToPtr(inner) => {
@@ -576,8 +603,10 @@ impl ILExporter {
                         writeln!(out, "ldobj {cref}", cref = class_ref(cref, asm))
                     }
                     (Type::Float(float), volitale) => match (float, volitale) {
-                        (super::Float::F16, true) => todo!(),
-                        (super::Float::F16, false) => todo!(),
+                        (super::Float::F16, true) => {
+                            writeln!(out, "volatile. ldobj {}", type_il(&tpe, asm))
+                        }
+                        (super::Float::F16, false) => writeln!(out, "ldobj {}", type_il(&tpe, asm)),
                         (super::Float::F32, true) => writeln!(out, "volatile. ldind.r4"),
                         (super::Float::F32, false) => writeln!(out, "ldind.r4"),
                         (super::Float::F64, true) => writeln!(out, "volatile. ldind.r8"),
@@ -679,9 +708,10 @@ impl ILExporter {
                     crate::v2::cilnode::MethodKind::Virtual => " ldftn instance",
                     crate::v2::cilnode::MethodKind::Constructor => "ldftn instance",
                 };
+                let generics = method_generics(mref.generics(), asm);
                 writeln!(
                     out,
-                    "{ldftn_op} {output} {class}::'{name}'({inputs}) //{ftn:?}"
+                    "{ldftn_op} {output} {class}::'{name}'{generics}({inputs}) //{ftn:?}"
                 )
             }
             CILNode::LdTypeToken(tok) => {
@@ -933,38 +963,13 @@ impl ILExporter {
                 writeln!(out, "stfld {tpe} {owner}::'{name}'")
             }
             super::CILRoot::Call(call) => {
-                for arg in &call.1 {
-                    self.export_node(asm, out, *arg, sig, locals)?;
-                }
-                let mref = asm.get_mref(call.0);
-                let call_op = match mref.kind() {
-                    crate::v2::cilnode::MethodKind::Static => "call",
-                    crate::v2::cilnode::MethodKind::Instance => "call instance",
-                    crate::v2::cilnode::MethodKind::Virtual => " callvirt instance",
-                    crate::v2::cilnode::MethodKind::Constructor => {
-                        panic!("A constructor can't be a CIL root")
-                    }
-                };
-                let sig = asm.get_sig(mref.sig());
-                let output = type_il(sig.output(), asm);
-                let inputs = match mref.kind() {
-                    crate::v2::cilnode::MethodKind::Static => sig.inputs(),
-                    crate::v2::cilnode::MethodKind::Instance
-                    | crate::v2::cilnode::MethodKind::Virtual
-                    | crate::v2::cilnode::MethodKind::Constructor => &sig.inputs()[1..],
-                };
-                let inputs: String = inputs
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
-                let name = asm.get_string(mref.name());
-                let class = class_ref(mref.class(), asm);
-                writeln!(
-                    out,
-                    "{call_op} {output} {class}::'{name}'({inputs}) //mref:{:?}",
-                    call.0
-                )
+                let (mref, args) = *call;
+                self.export_call(asm, out, mref, &args, "", sig, locals)
+            }
+            super::CILRoot::TailCall(call) => {
+                let (mref, args) = *call;
+                self.export_call(asm, out, mref, &args, "tail. ", sig, locals)?;
+                writeln!(out, "ret")
             }
             super::CILRoot::CpObj { src, dst, tpe } => {
                 self.export_node(asm, out, src, sig, locals)?;
@@ -1012,7 +1017,9 @@ impl ILExporter {
                         }
                     }
                     Type::Float(float) => match float {
-                        super::Float::F16 => todo!(),
+                        super::Float::F16 => {
+                            writeln!(out, "{is_volitale} stobj {}", type_il(&tpe, asm))
+                        }
                         super::Float::F32 => writeln!(out, "{is_volitale} stind.r4"),
                         super::Float::F64 => writeln!(out, "{is_volitale} stind.r8"),
                         super::Float::F128 => writeln!(out, "stobj {}", type_il(&tpe, asm)),
@@ -1041,20 +1048,13 @@ impl ILExporter {
                 writeln!(out, "cpblk")
             }
             super::CILRoot::CallI(calli) => {
-                let (fn_ptr, fn_sig, args) = calli.as_ref();
-                for arg in args {
-                    self.export_node(asm, out, *arg, sig, locals)?;
-                }
-                let fn_sig = asm[*fn_sig].clone();
-                let output = type_il(fn_sig.output(), asm);
-                self.export_node(asm, out, *fn_ptr, sig, locals)?;
-                let inputs: String = fn_sig
-                    .inputs()
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_owned())
-                    .collect();
-                writeln!(out, "calli {output} ({inputs})")
+                let (fn_ptr, fn_sig, args) = *calli;
+                self.export_calli(asm, out, fn_ptr, fn_sig, &args, "", sig, locals)
+            }
+            super::CILRoot::TailCallI(calli) => {
+                let (fn_ptr, fn_sig, args) = *calli;
+                self.export_calli(asm, out, fn_ptr, fn_sig, &args, "tail. ", sig, locals)?;
+                writeln!(out, "ret")
             }
             super::CILRoot::ExitSpecialRegion { target, source } => {
                 if is_handler {
@@ -1081,7 +1081,172 @@ impl ILExporter {
                     &asm[msg]
                 )
             }
+            super::CILRoot::Switch(switch) => {
+                let (value, int, targets) = *switch;
+                self.export_switch(asm, out, value, int, &targets, is_handler, sig, locals)
+            }
+        }
+    }
+    /// Exports a call to `mref`. `prefix` is placed right before the call opcode, and is used for `tail.` calls.
+    #[allow(clippy::too_many_arguments)]
+    fn export_call(
+        &self,
+        asm: &mut super::Assembly,
+        out: &mut impl Write,
+        mref: MethodRefIdx,
+        args: &[NodeIdx],
+        prefix: &str,
+        sig: SigIdx,
+        locals: &[LocalDef],
+    ) -> std::io::Result<()> {
+        for arg in args {
+            self.export_node(asm, out, *arg, sig, locals)?;
+        }
+        let call_idx = mref;
+        let mref = asm.get_mref(mref);
+        let call_op = match mref.kind() {
+            crate::v2::cilnode::MethodKind::Static => "call",
+            crate::v2::cilnode::MethodKind::Instance => "call instance",
+            crate::v2::cilnode::MethodKind::Virtual => " callvirt instance",
+            crate::v2::cilnode::MethodKind::Constructor => {
+                panic!("A constructor can't be a CIL root")
+            }
+        };
+        let sig = asm.get_sig(mref.sig());
+        let output = type_il(sig.output(), asm);
+        let inputs = match mref.kind() {
+            crate::v2::cilnode::MethodKind::Static => sig.inputs(),
+            crate::v2::cilnode::MethodKind::Instance
+            | crate::v2::cilnode::MethodKind::Virtual
+            | crate::v2::cilnode::MethodKind::Constructor => &sig.inputs()[1..],
+        };
+        let inputs: String = inputs
+            .iter()
+            .map(|tpe| non_void_type_il(tpe, asm))
+            .intersperse(",".to_owned())
+            .collect();
+        let name = asm.get_string(mref.name());
+        let class = class_ref(mref.class(), asm);
+        let generics = method_generics(mref.generics(), asm);
+        writeln!(
+            out,
+            "{prefix}{call_op} {output} {class}::'{name}'{generics}({inputs}) //mref:{call_idx:?}",
+        )
+    }
+    /// Exports an indirect call to `fn_ptr`. `prefix` is placed right before the `calli` opcode.
+    #[allow(clippy::too_many_arguments)]
+    fn export_calli(
+        &self,
+        asm: &mut super::Assembly,
+        out: &mut impl Write,
+        fn_ptr: NodeIdx,
+        fn_sig: SigIdx,
+        args: &[NodeIdx],
+        prefix: &str,
+        sig: SigIdx,
+        locals: &[LocalDef],
+    ) -> std::io::Result<()> {
+        for arg in args {
+            self.export_node(asm, out, *arg, sig, locals)?;
+        }
+        let fn_sig = asm[fn_sig].clone();
+        let output = type_il(fn_sig.output(), asm);
+        self.export_node(asm, out, fn_ptr, sig, locals)?;
+        let inputs: String = fn_sig
+            .inputs()
+            .iter()
+            .map(|tpe| non_void_type_il(tpe, asm))
+            .intersperse(",".to_owned())
+            .collect();
+        writeln!(out, "{prefix}calli {output} ({inputs})")
+    }
+    /// Exports a switch. Jump tables are lowered to the `switch` opcode, while sparse switches become a chain of `beq`s.
+    #[allow(clippy::too_many_arguments)]
+    fn export_switch(
+        &self,
+        asm: &mut super::Assembly,
+        out: &mut impl Write,
+        value: NodeIdx,
+        int: Int,
+        targets: &SwitchTargets,
+        is_handler: bool,
+        sig: SigIdx,
+        locals: &[LocalDef],
+    ) -> std::io::Result<()> {
+        let otherwise = switch_label(targets.otherwise(), is_handler);
+        // Small signed ints are sign extended when loaded, but the cases are zero extended.
+        let extend = match int {
+            Int::I8 => "conv.u1",
+            Int::I16 => "conv.u2",
+            _ => "",
+        };
+        match targets {
+            SwitchTargets::Dense { base, table, .. } => {
+                self.export_node(asm, out, value, sig, locals)?;
+                let labels: String = table
+                    .iter()
+                    .map(|target| switch_label(*target, is_handler))
+                    .intersperse(",".to_owned())
+                    .collect();
+                match int.size() {
+                    Some(1..=4) => {
+                        // The index is unsigned, so values below `base` wrap around, and are out of range too.
+                        writeln!(out, "{extend} {ldc} sub", ldc = switch_const(*base, int))?;
+                        writeln!(out, "switch ({labels})")?;
+                    }
+                    _ => {
+                        // `switch` only takes 32 bit indices, so bigger values can't be just truncated. The index is
+                        // offset by one, and multiplied by the result of the range check, so out of range values jump
+                        // to the first label, which is `otherwise`.
+                        let len = switch_const(table.len() as u64 + 1, int);
+                        let conv = if int.size().is_some() {
+                            "conv.u8"
+                        } else {
+                            "conv.u"
+                        };
+                        writeln!(
+                            out,
+                            "{ldc} sub dup {len} clt.un {conv} mul conv.u4",
+                            ldc = switch_const(base.wrapping_sub(1), int)
+                        )?;
+                        writeln!(out, "switch ({otherwise},{labels})")?;
+                    }
+                }
+            }
+            SwitchTargets::Sparse { cases, .. } => {
+                for (case, target) in cases.iter() {
+                    self.export_node(asm, out, value, sig, locals)?;
+                    writeln!(
+                        out,
+                        "{extend} {ldc} beq {label}",
+                        ldc = switch_const(*case, int),
+                        label = switch_label(*target, is_handler)
+                    )?;
+                }
+            }
         }
+        writeln!(out, "br {otherwise}")
+    }
+}
+/// The label of a branch target, as used by the IL exporter.
+fn switch_label((target, sub_target): (u32, u32), is_handler: bool) -> String {
+    if sub_target == 0 {
+        format!("bb{target}")
+    } else if is_handler {
+        format!("h{target}_{sub_target}")
+    } else {
+        format!("jp{target}_{sub_target}")
+    }
+}
+/// Loads the switch case `bits`, with the stack type of `int`.
+fn switch_const(bits: u64, int: Int) -> String {
+    match int.size() {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        Some(1..=4) => format!("ldc.i4 {}", bits as u32 as i32),
+        #[allow(clippy::cast_possible_wrap)]
+        Some(_) => format!("ldc.i8 {}", bits as i64),
+        #[allow(clippy::cast_possible_wrap)]
+        None => format!("ldc.i8 {} conv.u", bits as i64),
     }
 }
 #[cfg(not(target_os = "windows"))]
@@ -1232,6 +1397,20 @@ pub(crate) fn class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
         format!("{prefix} '{name}{generic_postfix}'{generic_list}")
     }
 }
+/// Returns the generic arguments of a method instantiation, or an empty string if the method is not generic.
+fn method_generics(generics: &[Type], asm: &Assembly) -> String {
+    if generics.is_empty() {
+        return String::new();
+    }
+    format!(
+        "<{generics}>",
+        generics = generics
+            .iter()
+            .map(|tpe| type_il(tpe, asm))
+            .intersperse(",".to_string())
+            .collect::<String>()
+    )
+}
 fn non_void_type_il(tpe: &Type, asm: &Assembly) -> String {
     match tpe {
         Type::Void => "valuetype RustVoid".into(),
@@ -131,7 +131,8 @@ impl<'asm> Iterator for CILIter<'asm> {
                         continue;
                     }
                 },
-                CILIterElem::Node(CILNode::Call(info)) | CILIterElem::Root(CILRoot::Call(info)) => {
+                CILIterElem::Node(CILNode::Call(info))
+                | CILIterElem::Root(CILRoot::Call(info) | CILRoot::TailCall(info)) => {
                     if *idx - 1 < info.1.len() {
                         let arg = &info.1[*idx - 1];
                         let arg = self.asm.get_node(*arg);
@@ -144,24 +145,26 @@ impl<'asm> Iterator for CILIter<'asm> {
                     }
                 }
                 CILIterElem::Node(CILNode::CallI(info))
-                | CILIterElem::Root(CILRoot::CallI(info)) => match (*idx - 1).cmp(&info.2.len()) {
-                    std::cmp::Ordering::Less => {
-                        let arg = &info.2[*idx - 1];
-                        let arg = self.asm.get_node(*arg);
-                        *idx += 1;
-                        self.elems.push((CILIterElem::Node(arg.clone()), 0));
-                        continue;
-                    }
-                    std::cmp::Ordering::Equal => {
-                        let arg = self.asm.get_node(info.0);
-                        *idx += 1;
-                        self.elems.push((CILIterElem::Node(arg.clone()), 0));
-                    }
-                    std::cmp::Ordering::Greater => {
-                        self.elems.pop();
-                        continue;
+                | CILIterElem::Root(CILRoot::CallI(info) | CILRoot::TailCallI(info)) => {
+                    match (*idx - 1).cmp(&info.2.len()) {
+                        std::cmp::Ordering::Less => {
+                            let arg = &info.2[*idx - 1];
+                            let arg = self.asm.get_node(*arg);
+                            *idx += 1;
+                            self.elems.push((CILIterElem::Node(arg.clone()), 0));
+                            continue;
+                        }
+                        std::cmp::Ordering::Equal => {
+                            let arg = self.asm.get_node(info.0);
+                            *idx += 1;
+                            self.elems.push((CILIterElem::Node(arg.clone()), 0));
+                        }
+                        std::cmp::Ordering::Greater => {
+                            self.elems.pop();
+                            continue;
+                        }
                     }
-                },
+                }
                 CILIterElem::Node(
                     CILNode::UnOp(val, _)
                     | CILNode::PtrCast(val, _)
@@ -220,6 +223,15 @@ impl<'asm> Iterator for CILIter<'asm> {
                 ) => {
                     self.elems.pop();
                 }
+                CILIterElem::Root(CILRoot::Switch(info)) => {
+                    if idx == &1 {
+                        *idx += 1;
+                        let val = self.asm.get_node(info.0);
+                        self.elems.push((CILIterElem::Node(val.clone()), 0));
+                    } else {
+                        self.elems.pop();
+                    }
+                }
                 CILIterElem::Root(CILRoot::InitBlk(blk) | CILRoot::CpBlk(blk)) => match idx {
                     1 => {
                         *idx += 1;
@@ -520,6 +532,7 @@ impl<'this, T: Iterator<Item = CILIterElem> + 'this> TpeIter<'this> for T {
                     | CILRoot::Break
                     | CILRoot::Nop
                     | CILRoot::Branch(_)
+                    | CILRoot::Switch(_)
                     | CILRoot::SourceFileInfo { .. }
                     | CILRoot::ExitSpecialRegion { .. }
                     | CILRoot::InitBlk(_)
@@ -542,7 +555,10 @@ impl<'this, T: Iterator<Item = CILIterElem> + 'this> TpeIter<'this> for T {
                         Some(Box::new(std::iter::once(*asm.get_type(tpe))))
                     }
                     // Since this method is called, then if it uses an "internal" type, we must assume it is defined in this module. Thus, its types are already included, and we don't need to include them again.
-                    CILRoot::Call(_) | CILRoot::CallI(_) => None,
+                    CILRoot::Call(_)
+                    | CILRoot::CallI(_)
+                    | CILRoot::TailCall(_)
+                    | CILRoot::TailCallI(_) => None,
                     CILRoot::StInd(info) => Some(Box::new(std::iter::once(info.2))),
                 },
             };
@@ -0,0 +1,404 @@
+import java.io.BufferedOutputStream;
+import java.io.FileOutputStream;
+import java.io.FileDescriptor;
+import java.io.IOException;
+import java.io.OutputStream;
+import java.lang.invoke.MethodHandle;
+import java.lang.reflect.Field;
+import java.math.BigDecimal;
+import java.math.BigInteger;
+import java.nio.charset.StandardCharsets;
+import java.util.ArrayList;
+import java.util.HashMap;
+
+// Runtime support for code emitted by the cilly Java exporter. Unmanaged memory lives outside of the java heap,
+// and is accessed using `sun.misc.Unsafe`.
+public final class Rt {
+    private Rt() {}
+    static final sun.misc.Unsafe U;
+    static {
+        try {
+            Field field = sun.misc.Unsafe.class.getDeclaredField("theUnsafe");
+            field.setAccessible(true);
+            U = (sun.misc.Unsafe) field.get(null);
+        } catch (ReflectiveOperationException err) {
+            throw new ExceptionInInitializerError(err);
+        }
+    }
+    // Shadow stack, used for valuetypes, `localloc`s and locals whose address is taken.
+    static final long STACK_SIZE = 32L << 20;
+    static final long STACK_BASE = U.allocateMemory(STACK_SIZE);
+    public static long sp = STACK_BASE;
+    static long align_up(long val, long align) {
+        return (val + align - 1) & -align;
+    }
+    static long stack_alloc_uninit(long size, long align) {
+        long ptr = align_up(sp, Math.max(align, 1));
+        if (ptr + size > STACK_BASE + STACK_SIZE) {
+            throw new StackOverflowError("Shadow stack overflow");
+        }
+        sp = ptr + size;
+        return ptr;
+    }
+    public static long stack_alloc(long size, long align) {
+        long ptr = stack_alloc_uninit(size, align);
+        U.setMemory(ptr, size, (byte) 0);
+        return ptr;
+    }
+    public static long stack_copy(long src, long size, long align) {
+        long ptr = stack_alloc_uninit(size, align);
+        U.copyMemory(src, ptr, size);
+        return ptr;
+    }
+    // Pops the frame starting at `fp`, and moves the returned valuetype to the top of the callers frame.
+    public static long ret_struct(long fp, long src, long size, long align) {
+        sp = fp;
+        long ptr = stack_alloc_uninit(size, align);
+        U.copyMemory(src, ptr, size);
+        return ptr;
+    }
+    // Heap. Each allocation is preceded by its size, and the address of the underlying block.
+    public static long aligned_alloc(long size, long align) {
+        align = Math.max(align, 16);
+        long raw = U.allocateMemory(size + align + 16);
+        long ptr = align_up(raw + 16, align);
+        U.putLong(ptr - 8, raw);
+        U.putLong(ptr - 16, size);
+        return ptr;
+    }
+    public static long malloc(long size) {
+        return aligned_alloc(size, 16);
+    }
+    public static long calloc(long count, long size) {
+        long ptr = malloc(count * size);
+        U.setMemory(ptr, count * size, (byte) 0);
+        return ptr;
+    }
+    public static long static_alloc(long size, long align) {
+        long ptr = aligned_alloc(size, align);
+        U.setMemory(ptr, size, (byte) 0);
+        return ptr;
+    }
+    public static void free(long ptr) {
+        if (ptr != 0) {
+            U.freeMemory(U.getLong(ptr - 8));
+        }
+    }
+    public static long realloc(long ptr, long size, long align) {
+        long res = aligned_alloc(size, align);
+        if (ptr != 0) {
+            U.copyMemory(ptr, res, Math.min(size, U.getLong(ptr - 16)));
+            free(ptr);
+        }
+        return res;
+    }
+    public static void memcpy(long dst, long src, long len) {
+        U.copyMemory(src, dst, len);
+    }
+    public static void memset(long dst, int val, long len) {
+        U.setMemory(dst, len, (byte) val);
+    }
+    public static int memcmp(long lhs, long rhs, long len) {
+        for (long i = 0; i < len; i++) {
+            int diff = (U.getByte(lhs + i) & 0xff) - (U.getByte(rhs + i) & 0xff);
+            if (diff != 0) {
+                return diff;
+            }
+        }
+        return 0;
+    }
+    public static long strlen(long ptr) {
+        long len = 0;
+        while (U.getByte(ptr + len) != 0) {
+            len++;
+        }
+        return len;
+    }
+    // Loads and stores
+    public static int ld_i8(long addr) { return U.getByte(addr); }
+    public static int ld_u8(long addr) { return U.getByte(addr) & 0xff; }
+    public static int ld_i16(long addr) { return U.getShort(addr); }
+    public static int ld_u16(long addr) { return U.getShort(addr) & 0xffff; }
+    public static int ld_i32(long addr) { return U.getInt(addr); }
+    public static int ld_u32(long addr) { return U.getInt(addr); }
+    public static long ld_i64(long addr) { return U.getLong(addr); }
+    public static long ld_u64(long addr) { return U.getLong(addr); }
+    public static long ld_ptr(long addr) { return U.getLong(addr); }
+    public static float ld_f32(long addr) { return U.getFloat(addr); }
+    public static double ld_f64(long addr) { return U.getDouble(addr); }
+    public static boolean ld_bool(long addr) { return U.getByte(addr) != 0; }
+    public static char ld_char(long addr) { return U.getChar(addr); }
+    public static BigInteger ld_i128(long addr) {
+        return BigInteger.valueOf(U.getLong(addr + 8)).shiftLeft(64).or(u64_to_big(U.getLong(addr)));
+    }
+    public static BigInteger ld_u128(long addr) { return wrap_u128(ld_i128(addr)); }
+    public static float ld_f16(long addr) { throw unsupported_op("f16"); }
+    public static double ld_f128(long addr) { throw unsupported_op("f128"); }
+    public static void st_i8(long addr, int val) { U.putByte(addr, (byte) val); }
+    public static void st_u8(long addr, int val) { U.putByte(addr, (byte) val); }
+    public static void st_i16(long addr, int val) { U.putShort(addr, (short) val); }
+    public static void st_u16(long addr, int val) { U.putShort(addr, (short) val); }
+    public static void st_i32(long addr, int val) { U.putInt(addr, val); }
+    public static void st_u32(long addr, int val) { U.putInt(addr, val); }
+    public static void st_i64(long addr, long val) { U.putLong(addr, val); }
+    public static void st_u64(long addr, long val) { U.putLong(addr, val); }
+    public static void st_ptr(long addr, long val) { U.putLong(addr, val); }
+    public static void st_f32(long addr, float val) { U.putFloat(addr, val); }
+    public static void st_f64(long addr, double val) { U.putDouble(addr, val); }
+    public static void st_bool(long addr, boolean val) { U.putByte(addr, (byte) (val ? 1 : 0)); }
+    public static void st_char(long addr, char val) { U.putChar(addr, val); }
+    public static void st_i128(long addr, BigInteger val) {
+        U.putLong(addr, val.longValue());
+        U.putLong(addr + 8, val.shiftRight(64).longValue());
+    }
+    public static void st_u128(long addr, BigInteger val) { st_i128(addr, val); }
+    public static void st_f16(long addr, float val) { throw unsupported_op("f16"); }
+    public static void st_f128(long addr, double val) { throw unsupported_op("f128"); }
+    // Managed references can't be stored in unmanaged memory, so they are kept in a map keyed by their address.
+    static final HashMap<Long, Object> objects = new HashMap<>();
+    public static Object ld_obj(long addr) { return objects.get(addr); }
+    public static void st_obj(long addr, Object val) { objects.put(addr, val); }
+    // Atomics
+    public static int cmpxchg_i32(long addr, int val, int cmp) {
+        for (;;) {
+            int old = U.getIntVolatile(null, addr);
+            if (old != cmp || U.compareAndSwapInt(null, addr, old, val)) {
+                return old;
+            }
+        }
+    }
+    public static long cmpxchg_i64(long addr, long val, long cmp) {
+        for (;;) {
+            long old = U.getLongVolatile(null, addr);
+            if (old != cmp || U.compareAndSwapLong(null, addr, old, val)) {
+                return old;
+            }
+        }
+    }
+    public static int xchg_i32(long addr, int val) { return U.getAndSetInt(null, addr, val); }
+    public static long xchg_i64(long addr, long val) { return U.getAndSetLong(null, addr, val); }
+    public static int xadd_i32(long addr, int val) { return U.getAndAddInt(null, addr, val); }
+    public static long xadd_i64(long addr, long val) { return U.getAndAddLong(null, addr, val); }
+    // Function pointers are indices into a table of method handles. 0 is the null pointer.
+    static final ArrayList<MethodHandle> fn_table = new ArrayList<>();
+    static { fn_table.add(null); }
+    public static long fn_ptr(MethodHandle handle) {
+        fn_table.add(handle);
+        return fn_table.size() - 1;
+    }
+    public static MethodHandle fn_at(long ptr) {
+        if (ptr <= 0 || ptr >= fn_table.size()) {
+            throw new IllegalStateException("Calling invalid function pointer " + ptr);
+        }
+        return fn_table.get((int) ptr);
+    }
+    // Integer helpers
+    static final BigInteger U64_MASK = BigInteger.ONE.shiftLeft(64).subtract(BigInteger.ONE);
+    static final BigInteger U128_MASK = BigInteger.ONE.shiftLeft(128).subtract(BigInteger.ONE);
+    public static BigInteger wrap_u128(BigInteger val) { return val.and(U128_MASK); }
+    public static BigInteger wrap_i128(BigInteger val) {
+        val = val.and(U128_MASK);
+        return val.testBit(127) ? val.subtract(BigInteger.ONE.shiftLeft(128)) : val;
+    }
+    public static BigInteger u64_to_big(long val) { return BigInteger.valueOf(val).and(U64_MASK); }
+    public static float u64_to_f32(long val) { return u64_to_big(val).floatValue(); }
+    public static double u64_to_f64(long val) { return u64_to_big(val).doubleValue(); }
+    public static long f2u64(double val) {
+        if (!(val > 0)) return 0;
+        if (val >= 18446744073709551615.0) return -1;
+        return new BigDecimal(val).toBigInteger().longValue();
+    }
+    public static BigInteger f2i128(double val) {
+        if (Double.isNaN(val)) return BigInteger.ZERO;
+        if (Double.isInfinite(val)) return val > 0 ? BigInteger.ONE.shiftLeft(127).subtract(BigInteger.ONE) : BigInteger.ONE.shiftLeft(127).negate();
+        BigInteger res = new BigDecimal(val).toBigInteger();
+        if (res.bitLength() > 127) return res.signum() > 0 ? BigInteger.ONE.shiftLeft(127).subtract(BigInteger.ONE) : BigInteger.ONE.shiftLeft(127).negate();
+        return res;
+    }
+    public static BigInteger f2u128(double val) {
+        if (!(val > 0)) return BigInteger.ZERO;
+        if (Double.isInfinite(val)) return U128_MASK;
+        BigInteger res = new BigDecimal(val).toBigInteger();
+        return res.bitLength() > 128 ? U128_MASK : res;
+    }
+    public static int shamt(int val) { return val; }
+    public static int shamt(long val) { return (int) val; }
+    public static int shamt(BigInteger val) { return val.intValue(); }
+    public static BigInteger reverse_bytes_128(BigInteger val) {
+        long lo = val.longValue();
+        long hi = val.shiftRight(64).longValue();
+        return u64_to_big(Long.reverseBytes(hi)).or(u64_to_big(Long.reverseBytes(lo)).shiftLeft(64));
+    }
+    public static int leading_zeros_128(BigInteger val) { return 128 - wrap_u128(val).bitLength(); }
+    public static int trailing_zeros_128(BigInteger val) {
+        val = wrap_u128(val);
+        return val.signum() == 0 ? 128 : val.getLowestSetBit();
+    }
+    public static BigInteger rotate_left_128(BigInteger val, int amount) {
+        val = wrap_u128(val);
+        amount &= 127;
+        return wrap_u128(val.shiftLeft(amount).or(val.shiftRight(128 - amount)));
+    }
+    // Float helpers
+    public static double round_away(double val) {
+        double abs = Math.abs(val);
+        return Math.copySign(abs - Math.floor(abs) >= 0.5 ? Math.ceil(abs) : Math.floor(abs), val);
+    }
+    public static double fmin(double lhs, double rhs) { return Double.isNaN(lhs) ? rhs : Double.isNaN(rhs) ? lhs : Math.min(lhs, rhs); }
+    public static double fmax(double lhs, double rhs) { return Double.isNaN(lhs) ? rhs : Double.isNaN(rhs) ? lhs : Math.max(lhs, rhs); }
+    public static float fminf(float lhs, float rhs) { return Float.isNaN(lhs) ? rhs : Float.isNaN(rhs) ? lhs : Math.min(lhs, rhs); }
+    public static float fmaxf(float lhs, float rhs) { return Float.isNaN(lhs) ? rhs : Float.isNaN(rhs) ? lhs : Math.max(lhs, rhs); }
+    // Strings and IO
+    static final OutputStream stdout = new BufferedOutputStream(new FileOutputStream(FileDescriptor.out), 1 << 16);
+    static final OutputStream stderr = new FileOutputStream(FileDescriptor.err);
+    static {
+        Runtime.getRuntime().addShutdownHook(new Thread(Rt::flush));
+    }
+    public static void flush() {
+        try {
+            stdout.flush();
+        } catch (IOException err) {
+            // Nothing can be done here.
+        }
+    }
+    public static void write_fd(int fd, byte[] data) {
+        try {
+            if (fd == 2) {
+                stdout.flush();
+                stderr.write(data);
+            } else {
+                stdout.write(data);
+            }
+        } catch (IOException err) {
+            throw new RuntimeException(err);
+        }
+    }
+    // .NET omits the fractional part of integral floats.
+    public static String fmt_float(double val) {
+        String text = Double.toString(val);
+        return text.endsWith(".0") ? text.substring(0, text.length() - 2) : text;
+    }
+    public static String fmt_float(float val) {
+        String text = Float.toString(val);
+        return text.endsWith(".0") ? text.substring(0, text.length() - 2) : text;
+    }
+    public static void print(String text) {
+        write_fd(1, text.getBytes(StandardCharsets.UTF_8));
+    }
+    public static long write(int fd, long ptr, long len) {
+        byte[] data = new byte[(int) len];
+        U.copyMemory(null, ptr, data, sun.misc.Unsafe.ARRAY_BYTE_BASE_OFFSET, len);
+        write_fd(fd, data);
+        return len;
+    }
+    public static String cstr(long ptr) {
+        byte[] data = new byte[(int) strlen(ptr)];
+        U.copyMemory(null, ptr, data, sun.misc.Unsafe.ARRAY_BYTE_BASE_OFFSET, data.length);
+        return new String(data, StandardCharsets.UTF_8);
+    }
+    public static long to_cstr(String str) {
+        byte[] data = str.getBytes(StandardCharsets.UTF_8);
+        long ptr = malloc(data.length + 1);
+        U.copyMemory(data, sun.misc.Unsafe.ARRAY_BYTE_BASE_OFFSET, null, ptr, data.length);
+        U.putByte(ptr + data.length, (byte) 0);
+        return ptr;
+    }
+    public static int puts(long ptr) {
+        print(cstr(ptr) + "\n");
+        return 0;
+    }
+    static long as_long(Object val) {
+        if (val instanceof Boolean) return ((Boolean) val) ? 1 : 0;
+        if (val instanceof Character) return (Character) val;
+        return ((Number) val).longValue();
+    }
+    static String as_unsigned(Object val) {
+        if (val instanceof Integer) return Integer.toUnsignedString((Integer) val);
+        if (val instanceof BigInteger) return wrap_u128((BigInteger) val).toString();
+        return Long.toUnsignedString(as_long(val));
+    }
+    // Formats `args` like the C `printf`.
+    public static String format(String fmt, Object... args) {
+        StringBuilder out = new StringBuilder();
+        int arg = 0;
+        for (int i = 0; i < fmt.length(); i++) {
+            char c = fmt.charAt(i);
+            if (c != '%') {
+                out.append(c);
+                continue;
+            }
+            int end = i + 1;
+            while (end < fmt.length() && "-+ #0123456789.hlLqjzt".indexOf(fmt.charAt(end)) >= 0) end++;
+            String spec = fmt.substring(i + 1, end);
+            char conv = end < fmt.length() ? fmt.charAt(end) : '%';
+            i = end;
+            String flags = spec.replaceAll("^([-+ #0]*).*$", "$1");
+            String rest = spec.substring(flags.length()).replaceAll("[hlLqjzt]", "");
+            String width = rest.contains(".") ? rest.substring(0, rest.indexOf('.')) : rest;
+            String precision = rest.contains(".") ? rest.substring(rest.indexOf('.') + 1) : null;
+            String text;
+            switch (conv) {
+                case '%': out.append('%'); continue;
+                case 'd': case 'i': text = args[arg] instanceof BigInteger ? args[arg].toString() : Long.toString(as_long(args[arg])); arg++; break;
+                case 'u': text = as_unsigned(args[arg++]); break;
+                case 'x': text = new BigInteger(as_unsigned(args[arg++])).toString(16); break;
+                case 'X': text = new BigInteger(as_unsigned(args[arg++])).toString(16).toUpperCase(); break;
+                case 'o': text = new BigInteger(as_unsigned(args[arg++])).toString(8); break;
+                case 'p': text = "0x" + Long.toHexString(as_long(args[arg++])); break;
+                case 'c': text = String.valueOf((char) as_long(args[arg++])); break;
+                case 's':
+                    text = cstr(as_long(args[arg++]));
+                    if (precision != null && !precision.isEmpty()) text = text.substring(0, Math.min(text.length(), Integer.parseInt(precision)));
+                    break;
+                case 'f': case 'F': case 'e': case 'g':
+                    text = String.format("%." + (precision == null || precision.isEmpty() ? "6" : precision) + (conv == 'F' ? 'f' : conv), ((Number) args[arg++]).doubleValue());
+                    break;
+                default: text = "%" + spec + conv;
+            }
+            if (!width.isEmpty() && text.length() < Integer.parseInt(width)) {
+                String pad = (flags.contains("0") && !flags.contains("-") ? "0" : " ").repeat(Integer.parseInt(width) - text.length());
+                text = flags.contains("-") ? text + pad : pad + text;
+            }
+            out.append(text);
+        }
+        return out.toString();
+    }
+    public static int printf(long fmt, Object... args) {
+        String text = format(cstr(fmt), args);
+        print(text);
+        return text.length();
+    }
+    // Program state
+    public static String[] args = new String[] { "main" };
+    public static void set_args(String[] main_args) {
+        args = new String[main_args.length + 1];
+        args[0] = "main";
+        System.arraycopy(main_args, 0, args, 1, main_args.length);
+    }
+    public static void exit(int code) {
+        flush();
+        System.exit(code);
+    }
+    // Can't be caught, unlike `abort`.
+    public static void fail_fast(String msg) {
+        flush();
+        System.err.println(msg);
+        Runtime.getRuntime().halt(134);
+    }
+    public static void abort() {
+        flush();
+        throw new IllegalStateException("Called abort!");
+    }
+    public static RuntimeException unsupported_op(String what) {
+        return new UnsupportedOperationException(what + " is not supported by the Java exporter");
+    }
+    // Used in expression position. Never returns.
+    public static Object unsupported(String what) {
+        throw unsupported_op(what);
+    }
+    public static RuntimeException missing(String name) {
+        return new UnsupportedOperationException("Missing method " + name);
+    }
+    // Discards a value.
+    public static void pop(Object val) {}
+}
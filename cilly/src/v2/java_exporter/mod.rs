@@ -1,409 +1,2090 @@
-// This is a WIP experiment.
-#![allow(dead_code, unused_imports)]
-use std::io::Write;
+//! Exports an [`Assembly`] as Java source files, compiled with `javac` and packaged into a `.jar`.
+//!
+//! Unmanaged memory is accessed trough `sun.misc.Unsafe`, and pointers are represented as `long`s. Like in the JS
+//! exporter, valuetypes and locals whose address is taken live on a shadow stack, and valuetype values are
+//! represented by their address. 8, 16 and 32 bit integers are `int`s, 64 bit integers are `long`s, and 128 bit
+//! integers are `BigInteger`s. Reference types are exported as Java classes, and Rust exceptions extend
+//! `RuntimeException`. Calls to .NET and libc functions are mapped to the Java runtime in `java_runtime.java`.
+//! The shadow stack is shared, so threads are not supported yet.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::Path,
+};
 
-use crate::v2::MethodImpl;
+use fxhash::{FxHashMap, FxHashSet};
+use lazy_static::lazy_static;
 
 use super::{
-    cilroot::BranchCond, int, Assembly, CILIter, CILIterElem, CILNode, ClassRefIdx, Exporter,
-    NodeIdx, RootIdx, Type,
+    cilnode::{ExtendKind, MethodKind, UnOp},
+    cilroot::{BranchCond, CmpKind, SwitchTargets},
+    layout::{class_layout, field_offset, int_bits, size_align, struct_def},
+    method::LocalDef,
+    Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, ClassRefIdx, Const,
+    Exporter, Float, Int, MethodDef, MethodImpl, MethodRef, MethodRefIdx, NodeIdx, RootIdx, SigIdx,
+    Type,
 };
-use lazy_static::lazy_static;
 lazy_static! {
-    #[doc = "Specifies the path to the java bytecode assembler."]
-    pub static ref JAVA_ASM_PATH:String = {
-        std::env::vars().find_map(|(key,value)|if key == "JAVA_ASM_PATH"{Some(value)}else{None}).unwrap_or("krak2".into())
+    #[doc = "Specifies the path to the java compiler."]
+    pub static ref JAVAC_PATH:String = {
+        std::env::vars().find_map(|(key,value)|if key == "JAVAC_PATH"{Some(value)}else{None}).unwrap_or("javac".into())
+    };
+    #[doc = "Specifies the path to the jar tool, used to package the compiled classes."]
+    pub static ref JAR_PATH:String = {
+        std::env::vars().find_map(|(key,value)|if key == "JAR_PATH"{Some(value)}else{None}).unwrap_or("jar".into())
     };
 }
-pub struct JavaExporter {
-    is_lib: bool,
+/// Methods of a class are split into nested classes of this size, to stay within the limits of a class file.
+const METHOD_CHUNK: usize = 1024;
+const RESERVED: &[&str] = &[
+    "abstract",
+    "assert",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "native",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "record",
+    "return",
+    "short",
+    "static",
+    "strictfp",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "transient",
+    "true",
+    "try",
+    "var",
+    "void",
+    "volatile",
+    "while",
+    "yield",
+    "_",
+];
+/// Class names used by the generated code, which can't be shadowed.
+const RESERVED_CLASSES: &[&str] = &[
+    "Rt",
+    "Ext",
+    "FnTable",
+    "Object",
+    "String",
+    "Math",
+    "Integer",
+    "Long",
+    "Short",
+    "Float",
+    "Double",
+    "Boolean",
+    "Character",
+    "System",
+    "Throwable",
+    "RuntimeException",
+    "IllegalStateException",
+    "BigInteger",
+    "MethodHandles",
+    "MethodType",
+];
+/// Replaces all characters which can't appear in a Java identifier.
+fn escape_chars(ident: &str) -> String {
+    ident
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$' => c.to_string(),
+            '.' => "_".into(),
+            _ => format!("$u{:x}$", c as u32),
+        })
+        .collect()
 }
-impl JavaExporter {
-    #[must_use]
-    pub fn new(is_lib: bool) -> Self {
-        Self { is_lib }
+fn escape_ident(ident: &str) -> String {
+    let mut escaped = escape_chars(ident);
+    if escaped.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+        escaped = format!("p{escaped}");
     }
-
-    fn export_to_write(&self, asm: &super::Assembly, out: &mut impl Write) -> std::io::Result<()> {
-        // Iterate trough all types
-        for class_def in asm.iter_class_defs() {
-            let vis = match class_def.access() {
-                crate::v2::Access::Extern | crate::v2::Access::Public => "public",
-                crate::v2::Access::Private => "private",
+    if RESERVED.contains(&escaped.as_str()) {
+        escaped.push('_');
+    }
+    escaped
+}
+/// Returns the name of the Java class of a class defined in this assembly.
+fn class_name(name: &str) -> String {
+    let mut escaped = escape_ident(name);
+    if RESERVED_CLASSES.contains(&escaped.as_str()) {
+        escaped.push('_');
+    }
+    // Each class is stored in its own file, so its name must fit within the file name length limits.
+    if escaped.len() > 128 {
+        let hash = fxhash::hash64(name);
+        escaped.truncate(96);
+        escaped = format!("{escaped}${hash:x}");
+    }
+    escaped
+}
+fn def_class_name(cref: ClassRefIdx, asm: &Assembly) -> String {
+    class_name(&asm[asm[cref].name()])
+}
+fn sig_mangle(sig: SigIdx, asm: &Assembly) -> String {
+    escape_chars(
+        &asm[sig]
+            .iter_types()
+            .map(|tpe| match tpe {
+                // Generics are erased, so they only need to be told apart by their index.
+                Type::PlatformGeneric(idx, _) => format!("g{idx}"),
+                _ => tpe.mangle(asm),
+            })
+            .collect::<String>(),
+    )
+}
+/// Returns the name of a Java method. Constructors can be overloaded, so their names include their signature.
+fn method_name(name: &str, sig: SigIdx, kind: MethodKind, asm: &Assembly) -> String {
+    match kind {
+        MethodKind::Constructor => format!("{}{}", escape_ident(name), sig_mangle(sig, asm)),
+        _ => escape_ident(name),
+    }
+}
+fn java_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ' '..='~' => escaped.push(c),
+            // Unicode escapes are processed before the source is parsed, so control characters can't use them.
+            '\0'..='\u{ff}' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            _ => {
+                let mut buf = [0; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    escaped.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+fn java_float(val: f64, is_f32: bool) -> String {
+    let (class, suffix) = if is_f32 {
+        ("Float", "f")
+    } else {
+        ("Double", "")
+    };
+    if val.is_nan() {
+        format!("{class}.NaN")
+    } else if val.is_infinite() {
+        if val > 0.0 {
+            format!("{class}.POSITIVE_INFINITY")
+        } else {
+            format!("{class}.NEGATIVE_INFINITY")
+        }
+    } else if val.is_sign_negative() {
+        format!("({val:?}{suffix})")
+    } else {
+        format!("{val:?}{suffix}")
+    }
+}
+/// Checks if values of this int type are represented by a `BigInteger`.
+fn is_bigint(int: Int) -> bool {
+    int_bits(int) > 64
+}
+/// Checks if values of this int type are represented by a `long`.
+fn is_long(int: Int) -> bool {
+    int_bits(int) == 64
+}
+fn is_signed(tpe: Type) -> bool {
+    match tpe {
+        Type::Int(int) => int.is_signed(),
+        _ => false,
+    }
+}
+/// Returns the Java type of values of type `tpe`.
+fn jtype(tpe: Type, asm: &Assembly) -> String {
+    match tpe {
+        Type::Int(int) if is_bigint(int) => "BigInteger".into(),
+        Type::Int(int) if is_long(int) => "long".into(),
+        Type::Int(_) => "int".into(),
+        Type::Float(Float::F16 | Float::F32) => "float".into(),
+        Type::Float(Float::F64 | Float::F128) => "double".into(),
+        Type::Bool => "boolean".into(),
+        Type::PlatformChar => "char".into(),
+        Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => "long".into(),
+        Type::Void => "void".into(),
+        Type::PlatformString => "String".into(),
+        Type::PlatformObject | Type::PlatformGeneric(_, _) => "Object".into(),
+        Type::PlatformArray { elem, dims } => {
+            let elem = jtype(asm[elem], asm);
+            format!("{elem}{}", "[]".repeat(dims.get() as usize))
+        }
+        Type::ClassRef(cref) => {
+            let class = &asm[cref];
+            if struct_def(cref, asm).is_some() {
+                "long".into()
+            } else if class.asm().is_none() {
+                def_class_name(cref, asm)
+            } else {
+                match &asm[class.name()] {
+                    "System.Exception" => "Throwable".into(),
+                    "System.String" => "String".into(),
+                    "System.Int128" | "System.UInt128" => "BigInteger".into(),
+                    _ => "Object".into(),
+                }
+            }
+        }
+    }
+}
+fn is_primitive(jtype: &str) -> bool {
+    matches!(
+        jtype,
+        "int" | "long" | "float" | "double" | "boolean" | "char" | "void"
+    )
+}
+/// Returns the suffix of the runtime functions loading and storing `tpe`, or `None` if `tpe` is a valuetype.
+fn mem_suffix(tpe: Type, asm: &Assembly) -> Option<&'static str> {
+    Some(match tpe {
+        Type::Int(int) => match int {
+            Int::I8 => "i8",
+            Int::U8 => "u8",
+            Int::I16 => "i16",
+            Int::U16 => "u16",
+            Int::I32 => "i32",
+            Int::U32 => "u32",
+            Int::I64 | Int::ISize => "i64",
+            Int::U64 | Int::USize => "u64",
+            Int::I128 => "i128",
+            Int::U128 => "u128",
+        },
+        Type::Float(float) => match float {
+            Float::F16 => "f16",
+            Float::F32 => "f32",
+            Float::F64 => "f64",
+            Float::F128 => "f128",
+        },
+        Type::Bool => "bool",
+        Type::PlatformChar => "char",
+        Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => "ptr",
+        Type::ClassRef(cref) if struct_def(cref, asm).is_some() => return None,
+        _ => "obj",
+    })
+}
+fn load(tpe: Type, addr: &str, asm: &Assembly) -> String {
+    match mem_suffix(tpe, asm) {
+        Some("obj") => format!("(({}) Rt.ld_obj({addr}))", jtype(tpe, asm)),
+        Some(suffix) => format!("Rt.ld_{suffix}({addr})"),
+        None => addr.to_owned(),
+    }
+}
+fn store(tpe: Type, addr: &str, val: &str, asm: &Assembly) -> String {
+    match mem_suffix(tpe, asm) {
+        Some(suffix) => format!("Rt.st_{suffix}({addr}, {val});"),
+        None => format!("Rt.memcpy({addr}, {val}, {});", size_align(tpe, asm).0),
+    }
+}
+fn zero(tpe: Type, asm: &Assembly) -> &'static str {
+    match jtype(tpe, asm).as_str() {
+        "int" => "0",
+        "long" => "0L",
+        "float" => "0.0f",
+        "double" => "0.0",
+        "boolean" => "false",
+        "char" => "'\\0'",
+        "BigInteger" => "BigInteger.ZERO",
+        _ => "null",
+    }
+}
+/// Converts `expr`, of type `from`, so that it can be used where a value of type `to` is expected.
+fn coerce(expr: String, from: Type, to: Type, asm: &Assembly) -> String {
+    let (from, to) = (jtype(from, asm), jtype(to, asm));
+    if from == to || to == "void" {
+        return expr;
+    }
+    match (from.as_str(), to.as_str()) {
+        ("boolean", "int") => format!("(({expr}) ? 1 : 0)"),
+        ("boolean", "long") => format!("(({expr}) ? 1L : 0L)"),
+        ("int" | "long" | "char", "boolean") => format!("(({expr}) != 0)"),
+        (_, "boolean") => format!("(({expr}) != null)"),
+        ("int" | "long", "char") => format!("((char)({expr}))"),
+        ("long", "int") => format!("((int)({expr}))"),
+        ("double", "float") => format!("((float)({expr}))"),
+        ("BigInteger", "long") => format!("({expr}).longValue()"),
+        ("BigInteger", "int") => format!("({expr}).intValue()"),
+        (_, "BigInteger") if is_primitive(&from) => format!("BigInteger.valueOf({expr})"),
+        (_, to) if !is_primitive(to) => format!("(({to})({expr}))"),
+        (_, to) if !is_primitive(&from) => format!("(({to})(Object)({expr}))"),
+        _ => expr,
+    }
+}
+/// Wraps the result of `expr` around, so that it fits within `int`.
+fn wrap(int: Int, expr: &str) -> String {
+    match int {
+        Int::I8 => format!("((byte)({expr}))"),
+        Int::U8 => format!("(({expr}) & 0xff)"),
+        Int::I16 => format!("((short)({expr}))"),
+        Int::U16 => format!("(({expr}) & 0xffff)"),
+        Int::I128 => format!("Rt.wrap_i128({expr})"),
+        Int::U128 => format!("Rt.wrap_u128({expr})"),
+        _ => format!("({expr})"),
+    }
+}
+/// Masks the value of a 8 or 16 bit integer, so that it is non-negative.
+fn mask_small(int: Int, expr: &str) -> String {
+    match int {
+        Int::I8 => format!("(({expr}) & 0xff)"),
+        Int::I16 => format!("(({expr}) & 0xffff)"),
+        _ => format!("({expr})"),
+    }
+}
+/// Emits a call to the runtime, which throws once an operation the exporter can't lower is reached.
+fn unsupported_op(op: impl std::fmt::Debug, tpe: Type, asm: &Assembly) -> String {
+    format!(
+        "(({})Rt.unsupported(\"{op:?} on {tpe:?}\"))",
+        jtype(tpe, asm)
+    )
+}
+/// Compares two values of type `tpe`.
+fn compare(lhs: &str, rhs: &str, tpe: Type, op: &str, unsigned: bool) -> String {
+    match tpe {
+        // Unordered float comparisons are true if either operand is NaN.
+        Type::Float(_) if unsigned => {
+            let inverse = match op {
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                _ => unreachable!(),
+            };
+            format!("!(({lhs}) {inverse} ({rhs}))")
+        }
+        Type::Int(int) if is_bigint(int) => {
+            if unsigned {
+                format!("(Rt.wrap_u128({lhs}).compareTo(Rt.wrap_u128({rhs})) {op} 0)")
+            } else {
+                format!("(({lhs}).compareTo({rhs}) {op} 0)")
+            }
+        }
+        Type::Int(int) if is_long(int) && unsigned => {
+            format!("(Long.compareUnsigned({lhs}, {rhs}) {op} 0)")
+        }
+        Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) if unsigned => {
+            format!("(Long.compareUnsigned({lhs}, {rhs}) {op} 0)")
+        }
+        Type::Int(_) if unsigned => format!("(Integer.compareUnsigned({lhs}, {rhs}) {op} 0)"),
+        Type::Bool => format!("(Boolean.compare({lhs}, {rhs}) {op} 0)"),
+        _ => format!("(({lhs}) {op} ({rhs}))"),
+    }
+}
+fn equals(lhs: &str, lhs_tpe: Type, rhs: &str, rhs_tpe: Type, asm: &Assembly) -> String {
+    let (lhs_j, rhs_j) = (jtype(lhs_tpe, asm), jtype(rhs_tpe, asm));
+    if lhs_j == "boolean" && rhs_j != "boolean" {
+        return format!(
+            "(({lhs}) == {})",
+            coerce(rhs.to_owned(), rhs_tpe, lhs_tpe, asm)
+        );
+    }
+    if rhs_j == "boolean" && lhs_j != "boolean" {
+        return format!(
+            "({} == ({rhs}))",
+            coerce(lhs.to_owned(), lhs_tpe, rhs_tpe, asm)
+        );
+    }
+    if lhs_j == "BigInteger" {
+        return format!("({lhs}).equals({rhs})");
+    }
+    format!("(({lhs}) == ({rhs}))")
+}
+/// Casts `val`, of type `src`, to `target`.
+fn cast_int(val: &str, src: Type, target: Int, extend: ExtendKind) -> String {
+    let unsigned = matches!(extend, ExtendKind::ZeroExtend) || !is_signed(src);
+    match src {
+        Type::Float(_) => match target {
+            Int::U32 => format!("((int)(long)({val}))"),
+            Int::I64 | Int::ISize => format!("((long)({val}))"),
+            Int::U64 | Int::USize => format!("Rt.f2u64({val})"),
+            Int::I128 => format!("Rt.f2i128({val})"),
+            Int::U128 => format!("Rt.f2u128({val})"),
+            _ => wrap(target, &format!("(int)({val})")),
+        },
+        Type::Bool => match target {
+            _ if is_bigint(target) => format!("(({val}) ? BigInteger.ONE : BigInteger.ZERO)"),
+            _ if is_long(target) => format!("(({val}) ? 1L : 0L)"),
+            _ => format!("(({val}) ? 1 : 0)"),
+        },
+        Type::Int(src) if is_bigint(src) => match target {
+            _ if is_bigint(target) => wrap(target, val),
+            _ if is_long(target) => format!("({val}).longValue()"),
+            _ => wrap(target, &format!("({val}).intValue()")),
+        },
+        Type::Int(Int::I64 | Int::U64 | Int::ISize | Int::USize)
+        | Type::Ptr(_)
+        | Type::Ref(_)
+        | Type::FnPtr(_) => match target {
+            _ if is_bigint(target) && unsigned => wrap(target, &format!("Rt.u64_to_big({val})")),
+            _ if is_bigint(target) => wrap(target, &format!("BigInteger.valueOf({val})")),
+            _ if is_long(target) => format!("({val})"),
+            _ => wrap(target, &format!("(int)({val})")),
+        },
+        _ => {
+            // Zero extending a signed int reinterprets it as unsigned first. Smaller ints are stored as 32 bit
+            // ones, so they get extended from 32 bits.
+            let long = if matches!(extend, ExtendKind::ZeroExtend) && is_signed(src) {
+                format!("Integer.toUnsignedLong({val})")
+            } else {
+                format!("((long)({val}))")
             };
-            let sealed = if class_def.is_valuetype() {
-                "final"
+            match target {
+                _ if is_bigint(target) => wrap(target, &format!("BigInteger.valueOf({long})")),
+                _ if is_long(target) => long,
+                Int::I32 | Int::U32 => format!("((int)({val}))"),
+                _ => wrap(target, val),
+            }
+        }
+    }
+}
+/// Converts `val`, of type `src`, to a float.
+fn cast_float(val: &str, src: Type, target: Float, is_signed: bool) -> String {
+    let tname = match target {
+        Float::F32 => "float",
+        Float::F64 => "double",
+        Float::F16 | Float::F128 => {
+            let jt = if target == Float::F16 {
+                "float"
             } else {
-                ""
+                "double"
             };
-            let extends = if let Some(parrent) = class_def.extends() {
-                simple_class_ref(parrent, asm)
+            return format!("(({jt})Rt.unsupported(\"Casting to {target:?}\"))");
+        }
+    };
+    match src {
+        Type::Int(int) if is_bigint(int) => {
+            let val = if is_signed {
+                val.to_owned()
             } else {
-                "java/lang/Object".into()
+                format!("Rt.wrap_u128({val})")
             };
-
-            let name = asm.get_string(class_def.name()).replace('.', "/");
-            writeln!(out, ".class {vis} {sealed} {name}\n.super {extends}")?;
-            // Export size
-            if let Some(size) = class_def.explict_size() {
-                writeln!(out, ".method public static sizeof : ()J\n.code stack 1 locals 0\nldc {size}\nlreturn\n.end code\n.end method", size = size.get())?;
-            }
-            // Export all fields
-            for (tpe, name, offset) in class_def.fields() {
-                let name = asm.get_string(*name);
-
-                if let Some(offset) = offset {
-                    writeln!(
-                        out,
-                        ".method public static get_{name}_Address : (J)J\n.code stack 3 locals 1\nlload_0\nldc {offset}\nladd\nlreturn\n.end code\n.end method"
+            format!("({val}).{tname}Value()")
+        }
+        Type::Int(int) if is_long(int) && !is_signed => match target {
+            Float::F32 => format!("Rt.u64_to_f32({val})"),
+            _ => format!("Rt.u64_to_f64({val})"),
+        },
+        Type::Int(Int::I32 | Int::U32) if !is_signed => {
+            format!("(({tname})Integer.toUnsignedLong({val}))")
+        }
+        Type::Int(int) if !is_signed => format!("(({tname}){})", mask_small(int, val)),
+        _ => format!("(({tname})({val}))"),
+    }
+}
+/// Converts `val` from `src` to `target`, like the `op_Explicit` and `op_Implicit` methods of .NET do.
+fn convert(val: &str, src: Type, target: Type) -> Option<String> {
+    Some(match (src, target) {
+        (_, Type::Int(target)) => {
+            let extend = if is_signed(src) {
+                ExtendKind::SignExtend
+            } else {
+                ExtendKind::ZeroExtend
+            };
+            cast_int(val, src, target, extend)
+        }
+        (_, Type::Float(target)) => cast_float(val, src, target, is_signed(src)),
+        _ => return None,
+    })
+}
+/// Converts `val` to a `String`, in the format used by .NET.
+fn to_string(val: &str, tpe: Type) -> String {
+    match tpe {
+        Type::Int(Int::U32) => format!("Integer.toUnsignedString({val})"),
+        Type::Int(Int::U64 | Int::USize) => format!("Long.toUnsignedString({val})"),
+        Type::Bool => format!("(({val}) ? \"True\" : \"False\")"),
+        Type::Float(_) => format!("Rt.fmt_float({val})"),
+        _ => format!("String.valueOf({val})"),
+    }
+}
+fn math_fn(name: &str, args: &[String], output: Type) -> Option<String> {
+    let arg = |idx: usize| args.get(idx).cloned().unwrap_or_default();
+    let res = match name {
+        "Sqrt" | "sqrt" => format!("Math.sqrt({})", arg(0)),
+        "Cbrt" | "cbrt" => format!("Math.cbrt({})", arg(0)),
+        "Abs" | "fabs" => format!("Math.abs({})", arg(0)),
+        "Floor" | "floor" => format!("Math.floor({})", arg(0)),
+        "Ceiling" | "ceil" => format!("Math.ceil({})", arg(0)),
+        "Truncate" | "trunc" => {
+            format!("(({a}) < 0 ? Math.ceil({a}) : Math.floor({a}))", a = arg(0))
+        }
+        "round" => format!("Rt.round_away({})", arg(0)),
+        "Round" | "rint" | "nearbyint" | "roundeven" => format!("Math.rint({})", arg(0)),
+        "Exp" | "exp" => format!("Math.exp({})", arg(0)),
+        "exp2" => format!("Math.pow(2.0, {})", arg(0)),
+        "expm1" => format!("Math.expm1({})", arg(0)),
+        "Log" | "log" => format!("Math.log({})", arg(0)),
+        "Log2" | "log2" => format!("(Math.log({}) / Math.log(2.0))", arg(0)),
+        "Log10" | "log10" => format!("Math.log10({})", arg(0)),
+        "log1p" => format!("Math.log1p({})", arg(0)),
+        "Pow" | "pow" => format!("Math.pow({}, {})", arg(0), arg(1)),
+        "Sin" | "sin" => format!("Math.sin({})", arg(0)),
+        "Cos" | "cos" => format!("Math.cos({})", arg(0)),
+        "Tan" | "tan" => format!("Math.tan({})", arg(0)),
+        "asin" => format!("Math.asin({})", arg(0)),
+        "acos" => format!("Math.acos({})", arg(0)),
+        "atan" => format!("Math.atan({})", arg(0)),
+        "Atan2" | "atan2" => format!("Math.atan2({}, {})", arg(0), arg(1)),
+        "sinh" => format!("Math.sinh({})", arg(0)),
+        "cosh" => format!("Math.cosh({})", arg(0)),
+        "tanh" => format!("Math.tanh({})", arg(0)),
+        "hypot" => format!("Math.hypot({}, {})", arg(0), arg(1)),
+        "fmod" => format!("(({}) % ({}))", arg(0), arg(1)),
+        "MinNumber" | "fmin" => format!("Rt.fmin({}, {})", arg(0), arg(1)),
+        "MaxNumber" | "fmax" => format!("Rt.fmax({}, {})", arg(0), arg(1)),
+        "CopySign" | "copysign" => format!("Math.copySign((double)({}), {})", arg(0), arg(1)),
+        "FusedMultiplyAdd" | "fma" => {
+            format!("Math.fma((double)({}), {}, {})", arg(0), arg(1), arg(2))
+        }
+        _ => return None,
+    };
+    Some(match output {
+        Type::Float(Float::F32) => format!("((float)({res}))"),
+        _ => res,
+    })
+}
+/// Returns the body of a shim for the .NET method `class::name`.
+fn dotnet_shim(
+    class: &str,
+    name: &str,
+    kind: MethodKind,
+    inputs: &[Type],
+    output: Type,
+) -> Option<String> {
+    let args: Vec<String> = (0..inputs.len()).map(|arg| format!("A{arg}")).collect();
+    let ret = |expr: String| match output {
+        Type::Void => format!("{expr};"),
+        _ => format!("return {expr};"),
+    };
+    let int = |idx: usize| match inputs.get(idx) {
+        Some(Type::Int(int)) => Some(*int),
+        _ => None,
+    };
+    Some(match (class, name) {
+        ("System.Console", "WriteLine") if inputs.is_empty() => "Rt.print(\"\\n\");".into(),
+        ("System.Console", "WriteLine") => {
+            format!("Rt.print({} + \"\\n\");", to_string(&args[0], inputs[0]))
+        }
+        ("System.Console", "Write") => format!("Rt.print({});", to_string(&args[0], inputs[0])),
+        ("System.Int128" | "System.UInt128", ".ctor") => {
+            // The value is built from its upper and lower 64 bits.
+            let val = format!(
+                "BigInteger.valueOf({}).shiftLeft(64).or(Rt.u64_to_big({}))",
+                args[args.len() - 2],
+                args[args.len() - 1]
+            );
+            let int = if class == "System.Int128" {
+                Int::I128
+            } else {
+                Int::U128
+            };
+            match kind {
+                MethodKind::Constructor => ret(wrap(int, &val)),
+                _ => format!("Rt.st_i128(A0, {val});"),
+            }
+        }
+        ("System.Int128" | "System.UInt128", _) if name.starts_with("op_") => {
+            let int = match class {
+                "System.Int128" => Int::I128,
+                _ => Int::U128,
+            };
+            let (a, b) = (args.first().cloned(), args.get(1).cloned());
+            let bin = |method: &str| {
+                ret(wrap(
+                    int,
+                    &format!("{}.{method}({})", a.clone().unwrap(), b.clone().unwrap()),
+                ))
+            };
+            let cmp = |op: &str| {
+                ret(format!(
+                    "({}.compareTo({}) {op} 0)",
+                    a.clone().unwrap(),
+                    b.clone().unwrap()
+                ))
+            };
+            match name {
+                "op_Addition" => bin("add"),
+                "op_Subtraction" => bin("subtract"),
+                "op_Multiply" => bin("multiply"),
+                "op_Division" => bin("divide"),
+                "op_Modulus" => bin("remainder"),
+                "op_BitwiseAnd" => bin("and"),
+                "op_BitwiseOr" => bin("or"),
+                "op_ExclusiveOr" => bin("xor"),
+                "op_Equality" => ret(format!("{}.equals({})", a?, b?)),
+                "op_Inequality" => ret(format!("!{}.equals({})", a?, b?)),
+                "op_LessThan" => cmp("<"),
+                "op_GreaterThan" => cmp(">"),
+                "op_LessThanOrEqual" => cmp("<="),
+                "op_GreaterThanOrEqual" => cmp(">="),
+                "op_LeftShift" => ret(wrap(int, &format!("{}.shiftLeft({} & 127)", a?, b?))),
+                "op_RightShift" => ret(format!("{}.shiftRight({} & 127)", a?, b?)),
+                "op_UnsignedRightShift" => ret(wrap(
+                    int,
+                    &format!("Rt.wrap_u128({}).shiftRight({} & 127)", a?, b?),
+                )),
+                "op_OnesComplement" => ret(wrap(int, &format!("{}.not()", a?))),
+                "op_UnaryNegation" => ret(wrap(int, &format!("{}.negate()", a?))),
+                "op_Explicit" | "op_Implicit" => ret(convert(&a?, inputs[0], output)?),
+                _ => return None,
+            }
+        }
+        (_, "op_Explicit" | "op_Implicit") => ret(convert(&args[0], inputs[0], output)?),
+        ("System.Buffers.Binary.BinaryPrimitives", "ReverseEndianness") => {
+            let int = int(0)?;
+            ret(match int {
+                Int::I8 | Int::U8 => args[0].clone(),
+                Int::I16 | Int::U16 => {
+                    wrap(int, &format!("Short.reverseBytes((short)({}))", args[0]))
+                }
+                Int::I32 | Int::U32 => format!("Integer.reverseBytes({})", args[0]),
+                _ if is_long(int) => format!("Long.reverseBytes({})", args[0]),
+                _ => wrap(int, &format!("Rt.reverse_bytes_128({})", args[0])),
+            })
+        }
+        ("System.Numerics.BitOperations", _) => {
+            let int = int(0)?;
+            let bits = int_bits(int);
+            let val = mask_small(int, &args[0]);
+            ret(match name {
+                "PopCount" if is_bigint(int) => format!("Rt.wrap_u128({val}).bitCount()"),
+                "PopCount" if is_long(int) => format!("Long.bitCount({val})"),
+                "PopCount" => format!("Integer.bitCount({val})"),
+                "LeadingZeroCount" if is_bigint(int) => format!("Rt.leading_zeros_128({val})"),
+                "LeadingZeroCount" if is_long(int) => format!("Long.numberOfLeadingZeros({val})"),
+                "LeadingZeroCount" => {
+                    format!("(Integer.numberOfLeadingZeros({val}) - {})", 32 - bits)
+                }
+                "TrailingZeroCount" if is_bigint(int) => format!("Rt.trailing_zeros_128({val})"),
+                "TrailingZeroCount" if is_long(int) => {
+                    format!("Long.numberOfTrailingZeros({val})")
+                }
+                "TrailingZeroCount" => {
+                    format!("Math.min(Integer.numberOfTrailingZeros({val}), {bits})")
+                }
+                "RotateLeft" | "RotateRight" => {
+                    let amount = if name == "RotateLeft" {
+                        format!("Rt.shamt({})", args[1])
+                    } else {
+                        format!("({bits} - Rt.shamt({}))", args[1])
+                    };
+                    match int {
+                        _ if is_bigint(int) => {
+                            wrap(int, &format!("Rt.rotate_left_128({val}, {amount})"))
+                        }
+                        _ if is_long(int) => format!("Long.rotateLeft({val}, {amount})"),
+                        Int::I32 | Int::U32 => format!("Integer.rotateLeft({val}, {amount})"),
+                        _ => wrap(
+                            int,
+                            &format!(
+                                "(({val}) << ({amount} & {m})) | (({val}) >>> (({bits} - ({amount} & {m})) & {m}))",
+                                m = bits - 1
+                            ),
+                        ),
+                    }
+                }
+                _ => return None,
+            })
+        }
+        ("System.Math", "Max" | "Min") if int(0).is_some() => {
+            let op = if name == "Max" { ">=" } else { "<=" };
+            let cond = compare(&args[0], &args[1], inputs[0], op, !is_signed(inputs[0]));
+            ret(format!("({cond} ? {} : {})", args[0], args[1]))
+        }
+        ("System.Math" | "System.MathF" | "System.Single" | "System.Double", "Clamp") => {
+            ret(format!(
+                "(({v}) < ({lo}) ? ({lo}) : ({v}) > ({hi}) ? ({hi}) : ({v}))",
+                v = args[0],
+                lo = args[1],
+                hi = args[2]
+            ))
+        }
+        ("System.Math" | "System.MathF" | "System.Single" | "System.Double", _) => {
+            ret(math_fn(name, &args, output)?)
+        }
+        ("System.Runtime.InteropServices.Marshal", "AllocHGlobal") => {
+            ret(format!("Rt.malloc({})", args[0]))
+        }
+        ("System.Runtime.InteropServices.Marshal", "ReAllocHGlobal") => {
+            ret(format!("Rt.realloc({}, {}, 16)", args[0], args[1]))
+        }
+        ("System.Runtime.InteropServices.Marshal", "FreeHGlobal") => {
+            format!("Rt.free({});", args[0])
+        }
+        ("System.Runtime.InteropServices.Marshal", "StringToCoTaskMemUTF8") => {
+            ret(format!("Rt.to_cstr({})", args[0]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedAlloc") => {
+            ret(format!("Rt.aligned_alloc({}, {})", args[0], args[1]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedRealloc") => {
+            ret(format!("Rt.realloc({}, {}, {})", args[0], args[1], args[2]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "AlignedFree" | "Free") => {
+            format!("Rt.free({});", args[0])
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "Alloc") => {
+            ret(format!("Rt.malloc({})", args[0]))
+        }
+        ("System.Runtime.InteropServices.NativeMemory", "Realloc") => {
+            ret(format!("Rt.realloc({}, {}, 16)", args[0], args[1]))
+        }
+        ("System.Threading.Interlocked", _) => {
+            let suffix = match inputs.get(1) {
+                Some(Type::Int(int)) if !is_long(*int) && !is_bigint(*int) => "i32",
+                Some(Type::Int(int)) if is_long(*int) => "i64",
+                Some(Type::Ptr(_) | Type::FnPtr(_)) => "i64",
+                _ => return None,
+            };
+            match name {
+                "CompareExchange" => ret(format!(
+                    "Rt.cmpxchg_{suffix}({}, {}, {})",
+                    args[0], args[1], args[2]
+                )),
+                "Exchange" => ret(format!("Rt.xchg_{suffix}({}, {})", args[0], args[1])),
+                // `Interlocked.Add` returns the new value.
+                "Add" => ret(format!(
+                    "(Rt.xadd_{suffix}({a}, {b}) + {b})",
+                    a = args[0],
+                    b = args[1]
+                )),
+                _ => return None,
+            }
+        }
+        ("System.Environment", "GetCommandLineArgs") => ret("Rt.args".into()),
+        ("System.Environment", "Exit") => format!("Rt.exit({});", args[0]),
+        ("System.Environment", "FailFast") => format!("Rt.fail_fast({});", args[0]),
+        ("System.Exception", ".ctor") => match kind {
+            MethodKind::Constructor if inputs.is_empty() => ret("new RuntimeException()".into()),
+            MethodKind::Constructor => {
+                ret(format!("new RuntimeException(String.valueOf({}))", args[0]))
+            }
+            // Calls to the base constructor don't need to do anything.
+            _ => String::new(),
+        },
+        ("System.String", "Concat") => ret(args
+            .iter()
+            .map(|arg| format!("String.valueOf({arg})"))
+            .collect::<Vec<_>>()
+            .join(" + ")),
+        ("System.UIntPtr", "get_MaxValue") => ret("-1L".into()),
+        ("System.UIntPtr", "get_MinValue") => ret("0L".into()),
+        ("System.IntPtr", "get_MaxValue") => ret("Long.MAX_VALUE".into()),
+        ("System.IntPtr", "get_MinValue") => ret("Long.MIN_VALUE".into()),
+        _ => return None,
+    })
+}
+/// Returns the body of a shim for the native function `name`.
+fn native_shim(name: &str, inputs: &[Type], output: Type, asm: &Assembly) -> Option<String> {
+    let args: Vec<String> = (0..inputs.len()).map(|arg| format!("A{arg}")).collect();
+    let ret = |expr: String, tpe: Type| match output {
+        Type::Void => format!("{expr};"),
+        _ => format!("return {};", coerce(expr, tpe, output, asm)),
+    };
+    let long = Type::Int(Int::I64);
+    let int = Type::Int(Int::I32);
+    Some(match name {
+        "puts" => ret(format!("Rt.puts({})", args[0]), int),
+        "printf" => ret(format!("Rt.printf({})", args.join(", ")), int),
+        "write" => ret(
+            format!("Rt.write((int)({}), {}, {})", args[0], args[1], args[2]),
+            long,
+        ),
+        "malloc" => ret(format!("Rt.malloc({})", args[0]), long),
+        "calloc" => ret(format!("Rt.calloc({}, {})", args[0], args[1]), long),
+        "realloc" => ret(format!("Rt.realloc({}, {}, 16)", args[0], args[1]), long),
+        "free" => format!("Rt.free({});", args[0]),
+        "aligned_alloc" => ret(format!("Rt.aligned_alloc({}, {})", args[1], args[0]), long),
+        "posix_memalign" => format!(
+            "Rt.st_ptr({}, Rt.aligned_alloc({}, {}));\n{}",
+            args[0],
+            args[2],
+            args[1],
+            ret("0".into(), int)
+        ),
+        "memcpy" | "memmove" => format!(
+            "Rt.memcpy({}, {}, {});\n{}",
+            args[0],
+            args[1],
+            args[2],
+            ret(args[0].clone(), long)
+        ),
+        "memset" => format!(
+            "Rt.memset({}, {}, {});\n{}",
+            args[0],
+            args[1],
+            args[2],
+            ret(args[0].clone(), long)
+        ),
+        "memcmp" => ret(
+            format!("Rt.memcmp({}, {}, {})", args[0], args[1], args[2]),
+            int,
+        ),
+        "strlen" => ret(format!("Rt.strlen({})", args[0]), long),
+        "getenv" => ret("0L".into(), long),
+        "abort" => "Rt.abort();".into(),
+        "exit" => format!("Rt.exit({});", args[0]),
+        _ => {
+            let is_f32 = matches!(output, Type::Float(Float::F32));
+            let base = if is_f32 {
+                name.strip_suffix('f')?
+            } else {
+                name
+            };
+            let res = match base {
+                "fmin" if is_f32 => format!("Rt.fminf({}, {})", args[0], args[1]),
+                "fmax" if is_f32 => format!("Rt.fmaxf({}, {})", args[0], args[1]),
+                _ => math_fn(base, &args, output)?,
+            };
+            format!("return {res};")
+        }
+    })
+}
+/// State shared by all the methods of a module.
+struct ModuleCtx {
+    /// Maps methods to the nested class they are stored in.
+    chunks: FxHashMap<MethodRefIdx, usize>,
+    /// Shims for the .NET methods called by this module.
+    externs: BTreeMap<String, String>,
+    /// Methods whose address is taken.
+    fn_ptrs: BTreeMap<String, String>,
+}
+impl ModuleCtx {
+    /// Returns the path of a method defined in this assembly.
+    fn method_path(&self, mref_idx: MethodRefIdx, mref: &MethodRef, asm: &Assembly) -> String {
+        let class = def_class_name(mref.class(), asm);
+        let name = method_name(&asm[mref.name()], mref.sig(), mref.kind(), asm);
+        match self.chunks.get(&mref_idx) {
+            Some(0) | None => format!("{class}.{name}"),
+            Some(chunk) => format!("{class}.$m{chunk}.{name}"),
+        }
+    }
+    /// Returns the path of a shim for an extern .NET method, adding it to `Ext` if needed.
+    fn extern_path(&mut self, mref: &MethodRef, asm: &Assembly) -> String {
+        let class = &asm[asm[mref.class()].name()];
+        let name = format!(
+            "{}_{}{}",
+            escape_chars(class),
+            escape_chars(&asm[mref.name()]),
+            sig_mangle(mref.sig(), asm)
+        );
+        if !self.externs.contains_key(&name) {
+            let sig = &asm[mref.sig()];
+            // Constructors return the new object, instead of initializing `this`.
+            let (inputs, output) = match mref.kind() {
+                MethodKind::Constructor => (&sig.inputs()[1..], Type::ClassRef(mref.class())),
+                _ => (sig.inputs(), *sig.output()),
+            };
+            let params = inputs
+                .iter()
+                .enumerate()
+                .map(|(arg, tpe)| format!("{} A{arg}", jtype(*tpe, asm)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let body = dotnet_shim(class, &asm[mref.name()], mref.kind(), inputs, output)
+                .unwrap_or_else(|| format!("throw Rt.missing({});", java_string(&name)));
+            self.externs.insert(
+                name.clone(),
+                format!(
+                    "public static {} {name}({params}) throws Throwable {{\n{body}\n}}",
+                    jtype(output, asm)
+                ),
+            );
+        }
+        format!("Ext.{name}")
+    }
+}
+fn method_type(sig: SigIdx, asm: &Assembly) -> String {
+    let sig = &asm[sig];
+    let mut classes = vec![format!("{}.class", jtype(*sig.output(), asm))];
+    classes.extend(
+        sig.inputs()
+            .iter()
+            .map(|tpe| format!("{}.class", jtype(*tpe, asm))),
+    );
+    format!("MethodType.methodType({})", classes.join(", "))
+}
+/// State needed to export a single method body.
+struct MethodCtx<'a> {
+    sig: SigIdx,
+    locals: &'a [LocalDef],
+    /// Locals and arguments living in linear memory, because they are valuetypes or their address is taken.
+    mem_locals: FxHashSet<u32>,
+    mem_args: FxHashSet<u32>,
+    module: &'a mut ModuleCtx,
+}
+impl<'a> MethodCtx<'a> {
+    fn tpe(&self, node: NodeIdx, asm: &mut Assembly) -> Type {
+        asm[node]
+            .clone()
+            .typecheck(self.sig, self.locals, asm)
+            .unwrap()
+    }
+    /// Exports `node`, converting it to `target`.
+    fn node_as(&mut self, node: NodeIdx, target: Type, asm: &mut Assembly) -> String {
+        let tpe = self.tpe(node, asm);
+        let expr = self.node(node, asm);
+        coerce(expr, tpe, target, asm)
+    }
+    fn args(&mut self, args: &[NodeIdx], inputs: &[Type], asm: &mut Assembly) -> Vec<String> {
+        args.iter()
+            .zip(inputs)
+            .map(|(arg, tpe)| self.node_as(*arg, *tpe, asm))
+            .collect()
+    }
+    fn call(&mut self, mref_idx: MethodRefIdx, args: &[NodeIdx], asm: &mut Assembly) -> String {
+        let mref = asm[mref_idx].clone();
+        let inputs = asm[mref.sig()].inputs().to_vec();
+        let is_extern = asm[mref.class()].asm().is_some();
+        if mref.kind() == MethodKind::Constructor {
+            let args = self.args(args, &inputs[1..], asm);
+            if is_extern {
+                let path = self.module.extern_path(&mref, asm);
+                return format!("{path}({})", args.join(", "));
+            }
+            let class = def_class_name(mref.class(), asm);
+            let name = method_name(&asm[mref.name()], mref.sig(), mref.kind(), asm);
+            return format!("{class}.new${name}({})", args.join(", "));
+        }
+        let mut args = self.args(args, &inputs, asm);
+        if is_extern {
+            let path = self.module.extern_path(&mref, asm);
+            return format!("{path}({})", args.join(", "));
+        }
+        let path = self.module.method_path(mref_idx, &mref, asm);
+        match mref.kind() {
+            MethodKind::Virtual if !args.is_empty() && struct_def(mref.class(), asm).is_none() => {
+                let this = args.remove(0);
+                format!(
+                    "(({})({this})).v${}({})",
+                    def_class_name(mref.class(), asm),
+                    escape_ident(&asm[mref.name()]),
+                    args.join(", ")
+                )
+            }
+            _ => format!("{path}({})", args.join(", ")),
+        }
+    }
+    fn compare(
+        &mut self,
+        lhs: NodeIdx,
+        rhs: NodeIdx,
+        op: &str,
+        unsigned: bool,
+        asm: &mut Assembly,
+    ) -> String {
+        let tpe = self.tpe(lhs, asm);
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        compare(&lhs, &rhs, tpe, op, unsigned)
+    }
+    fn equals(&mut self, lhs: NodeIdx, rhs: NodeIdx, asm: &mut Assembly) -> String {
+        let (lhs_tpe, rhs_tpe) = (self.tpe(lhs, asm), self.tpe(rhs, asm));
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        equals(&lhs, lhs_tpe, &rhs, rhs_tpe, asm)
+    }
+    fn binop(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp, asm: &mut Assembly) -> String {
+        match op {
+            BinOp::Eq => return self.equals(lhs, rhs, asm),
+            BinOp::Lt => return self.compare(lhs, rhs, "<", false, asm),
+            BinOp::LtUn => return self.compare(lhs, rhs, "<", true, asm),
+            BinOp::Gt => return self.compare(lhs, rhs, ">", false, asm),
+            BinOp::GtUn => return self.compare(lhs, rhs, ">", true, asm),
+            _ => (),
+        }
+        let lhs_tpe = self.tpe(lhs, asm);
+        let rhs_tpe = self.tpe(rhs, asm);
+        // Pointer arithmetic may have the pointer on either side.
+        let tpe = match (lhs_tpe, rhs_tpe) {
+            (Type::Int(_), Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_)) => rhs_tpe,
+            _ => lhs_tpe,
+        };
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        match tpe {
+            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => match op {
+                BinOp::Add => format!("(({lhs}) + ({rhs}))"),
+                BinOp::Sub => format!("(({lhs}) - ({rhs}))"),
+                BinOp::Mul => format!("(({lhs}) * ({rhs}))"),
+                BinOp::Div => format!("(({lhs}) / ({rhs}))"),
+                BinOp::DivUn => format!("Long.divideUnsigned({lhs}, {rhs})"),
+                BinOp::Rem => format!("(({lhs}) % ({rhs}))"),
+                BinOp::RemUn => format!("Long.remainderUnsigned({lhs}, {rhs})"),
+                BinOp::And => format!("(({lhs}) & ({rhs}))"),
+                BinOp::Or => format!("(({lhs}) | ({rhs}))"),
+                BinOp::XOr => format!("(({lhs}) ^ ({rhs}))"),
+                BinOp::Shl => format!("(({lhs}) << Rt.shamt({rhs}))"),
+                BinOp::Shr => format!("(({lhs}) >> Rt.shamt({rhs}))"),
+                BinOp::ShrUn => format!("(({lhs}) >>> Rt.shamt({rhs}))"),
+                _ => unsupported_op(op, tpe, asm),
+            },
+            Type::Bool => match op {
+                BinOp::And => format!("(({lhs}) & ({rhs}))"),
+                BinOp::Or => format!("(({lhs}) | ({rhs}))"),
+                BinOp::XOr => format!("(({lhs}) ^ ({rhs}))"),
+                BinOp::Mul => format!("(({lhs}) & ({rhs}))"),
+                // A reminder of a bool can only be false or a division by zero.
+                BinOp::Rem | BinOp::RemUn => "false".into(),
+                _ => unsupported_op(op, tpe, asm),
+            },
+            Type::Float(_) => match op {
+                BinOp::Add => format!("(({lhs}) + ({rhs}))"),
+                BinOp::Sub => format!("(({lhs}) - ({rhs}))"),
+                BinOp::Mul => format!("(({lhs}) * ({rhs}))"),
+                BinOp::Div | BinOp::DivUn => format!("(({lhs}) / ({rhs}))"),
+                BinOp::Rem | BinOp::RemUn => format!("(({lhs}) % ({rhs}))"),
+                _ => unsupported_op(op, tpe, asm),
+            },
+            Type::Int(int) if is_bigint(int) => {
+                let (lhs_un, rhs_un) = (
+                    format!("Rt.wrap_u128({lhs})"),
+                    format!("Rt.wrap_u128({rhs})"),
+                );
+                let shamt = format!("(Rt.shamt({rhs}) & 127)");
+                wrap(
+                    int,
+                    &match op {
+                        BinOp::Add => format!("({lhs}).add({rhs})"),
+                        BinOp::Sub => format!("({lhs}).subtract({rhs})"),
+                        BinOp::Mul => format!("({lhs}).multiply({rhs})"),
+                        BinOp::Div => format!("({lhs}).divide({rhs})"),
+                        BinOp::DivUn => format!("{lhs_un}.divide({rhs_un})"),
+                        BinOp::Rem => format!("({lhs}).remainder({rhs})"),
+                        BinOp::RemUn => format!("{lhs_un}.remainder({rhs_un})"),
+                        BinOp::And => format!("({lhs}).and({rhs})"),
+                        BinOp::Or => format!("({lhs}).or({rhs})"),
+                        BinOp::XOr => format!("({lhs}).xor({rhs})"),
+                        BinOp::Shl => format!("({lhs}).shiftLeft{shamt}"),
+                        BinOp::Shr => format!("({lhs}).shiftRight{shamt}"),
+                        BinOp::ShrUn => format!("{lhs_un}.shiftRight{shamt}"),
+                        _ => unreachable!(),
+                    },
+                )
+            }
+            Type::Int(int) => {
+                let (class, mask) = if is_long(int) {
+                    ("Long", 63)
+                } else {
+                    ("Integer", 31)
+                };
+                // Small ints are masked before unsigned operations.
+                let (lhs_un, rhs_un) = (mask_small(int, &lhs), mask_small(int, &rhs));
+                wrap(
+                    int,
+                    &match op {
+                        BinOp::Add => format!("({lhs}) + ({rhs})"),
+                        BinOp::Sub => format!("({lhs}) - ({rhs})"),
+                        BinOp::Mul => format!("({lhs}) * ({rhs})"),
+                        BinOp::Div => format!("({lhs}) / ({rhs})"),
+                        BinOp::DivUn => format!("{class}.divideUnsigned({lhs_un}, {rhs_un})"),
+                        BinOp::Rem => format!("({lhs}) % ({rhs})"),
+                        BinOp::RemUn => format!("{class}.remainderUnsigned({lhs_un}, {rhs_un})"),
+                        BinOp::And => format!("({lhs}) & ({rhs})"),
+                        BinOp::Or => format!("({lhs}) | ({rhs})"),
+                        BinOp::XOr => format!("({lhs}) ^ ({rhs})"),
+                        BinOp::Shl => format!("({lhs}) << (Rt.shamt({rhs}) & {mask})"),
+                        BinOp::Shr if int.is_signed() => {
+                            format!("({lhs}) >> (Rt.shamt({rhs}) & {mask})")
+                        }
+                        BinOp::Shr | BinOp::ShrUn => {
+                            format!("{lhs_un} >>> (Rt.shamt({rhs}) & {mask})")
+                        }
+                        _ => unreachable!(),
+                    },
+                )
+            }
+            _ => unsupported_op(op, tpe, asm),
+        }
+    }
+    fn static_field_path(&self, owner: ClassRefIdx, name: &str, asm: &Assembly) -> String {
+        format!("{}.{}", def_class_name(owner, asm), escape_ident(name))
+    }
+    fn node(&mut self, idx: NodeIdx, asm: &mut Assembly) -> String {
+        match asm[idx].clone() {
+            CILNode::Const(cst) => match cst.as_ref() {
+                Const::I8(v) => format!("({v})"),
+                Const::I16(v) => format!("({v})"),
+                Const::I32(v) => format!("({v})"),
+                Const::I64(v) | Const::ISize(v) => format!("({v}L)"),
+                Const::U8(v) => format!("{v}"),
+                Const::U16(v) => format!("{v}"),
+                Const::U32(v) => format!("({})", *v as i32),
+                Const::U64(v) | Const::USize(v) => format!("({}L)", *v as i64),
+                Const::PlatformString(string_idx) => java_string(&asm[*string_idx]),
+                Const::Bool(val) => format!("{val}"),
+                Const::F32(hashable_f32) => java_float(f64::from(hashable_f32.0), true),
+                Const::F64(hashable_f64) => java_float(hashable_f64.0, false),
+                Const::Null(_) => "null".into(),
+            },
+            CILNode::BinOp(lhs, rhs, bin_op) => self.binop(lhs, rhs, bin_op, asm),
+            CILNode::UnOp(input, un_op) => {
+                let tpe = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                match (&un_op, tpe) {
+                    (UnOp::Not, Type::Bool) => format!("!({input})"),
+                    (UnOp::Not, Type::Int(int)) if is_bigint(int) => {
+                        wrap(int, &format!("({input}).not()"))
+                    }
+                    (UnOp::Neg, Type::Int(int)) if is_bigint(int) => {
+                        wrap(int, &format!("({input}).negate()"))
+                    }
+                    (UnOp::Not, Type::Int(int)) => wrap(int, &format!("~({input})")),
+                    (UnOp::Neg, Type::Int(int)) => wrap(int, &format!("-({input})")),
+                    (UnOp::Not, Type::Ptr(_)) => format!("(~({input}))"),
+                    (UnOp::Neg, Type::Ptr(_)) => format!("(-({input}))"),
+                    (UnOp::Neg, Type::Float(_)) => format!("(-({input}))"),
+                    _ => unsupported_op(un_op, tpe, asm),
+                }
+            }
+            CILNode::LdLoc(loc) => {
+                if self.mem_locals.contains(&loc) {
+                    load(asm[self.locals[loc as usize].1], &format!("L{loc}"), asm)
+                } else {
+                    format!("L{loc}")
+                }
+            }
+            CILNode::LdArg(arg) => {
+                if self.mem_args.contains(&arg) {
+                    load(
+                        asm[self.sig].inputs()[arg as usize],
+                        &format!("AM{arg}"),
+                        asm,
                     )
                 } else {
-                    writeln!(
-                        out,
-                        ".field public {name} {tpe}",
-                        tpe = type_string(*tpe, asm)
+                    format!("A{arg}")
+                }
+            }
+            CILNode::LdLocA(loc) => format!("L{loc}"),
+            CILNode::LdArgA(arg) => format!("AM{arg}"),
+            CILNode::Call(info) => {
+                let (method, args) = info.as_ref();
+                self.call(*method, args, asm)
+            }
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => {
+                let src = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                cast_int(&input, src, target, extend)
+            }
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => {
+                let src = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                cast_float(&input, src, target, is_signed)
+            }
+            CILNode::RefToPtr(input) => self.node(input, asm),
+            CILNode::PtrCast(input, _) => self.node_as(input, Type::Int(Int::USize), asm),
+            CILNode::LdFieldAdress { addr, field } => {
+                let addr = self.node(addr, asm);
+                let field = asm[field];
+                format!("(({addr}) + {})", field_offset(field, asm))
+            }
+            CILNode::LdField { addr, field } => {
+                let addr = self.node(addr, asm);
+                let field = asm[field];
+                if struct_def(field.owner(), asm).is_some() {
+                    let addr = format!("({addr}) + {}", field_offset(field, asm));
+                    load(field.tpe(), &addr, asm)
+                } else if asm[field.owner()].asm().is_none() {
+                    format!(
+                        "(({})({addr})).{}",
+                        def_class_name(field.owner(), asm),
+                        escape_ident(&asm[field.name()])
                     )
-                }?;
+                } else {
+                    format!(
+                        "(({})Rt.unsupported(\"Fields of extern classes\"))",
+                        jtype(field.tpe(), asm)
+                    )
+                }
             }
-            /*// Export all static fields
-            for (tpe, name, thread_local) in class_def.static_fields() {
-                let name = asm.get_string(*name);
-                let tpe = non_void_type_il(tpe, asm);
-
-                writeln!(out, ".field static {tpe} '{name}'")?;
-                if *thread_local {
-                    writeln!(out,".custom instance void [System.Runtime]System.ThreadStaticAttribute::.ctor() = (01 00 00 00)")?;
-                };
+            CILNode::LdInd { addr, tpe, .. } => {
+                let addr = self.node(addr, asm);
+                load(asm[tpe], &addr, asm)
             }
-            // Export all methods
-
-            for method_id in class_def.methods() {
-                let method = asm.method_def(*method_id);
-                let vis = match method.access() {
-                    crate::v2::Access::Extern | crate::v2::Access::Public => "public",
-                    crate::v2::Access::Private => "private",
+            CILNode::SizeOf(tpe) => format!("{}", size_align(asm[tpe], asm).0),
+            CILNode::GetException => "exc".into(),
+            CILNode::IsInst(obj, tpe) => {
+                let obj = self.node(obj, asm);
+                match jtype(asm[tpe], asm).as_str() {
+                    jt if !is_primitive(jt) && jt != "Object" => {
+                        format!("(({obj}) instanceof {jt})")
+                    }
+                    _ => "((boolean)Rt.unsupported(\"IsInst\"))".into(),
+                }
+            }
+            CILNode::CheckedCast(obj, tpe) => {
+                let src = self.tpe(obj, asm);
+                let obj = self.node(obj, asm);
+                coerce(obj, src, asm[tpe], asm)
+            }
+            CILNode::CallI(info) => {
+                let (fn_ptr, sig, args) = info.as_ref();
+                let fn_ptr = self.node(*fn_ptr, asm);
+                let inputs = asm[*sig].inputs().to_vec();
+                let args = self.args(args, &inputs, asm);
+                let output = jtype(*asm[*sig].output(), asm);
+                format!(
+                    "(({output}) Rt.fn_at({fn_ptr}).invoke({}))",
+                    args.join(", ")
+                )
+            }
+            CILNode::LocAlloc { size } => {
+                format!("Rt.stack_alloc({}, 16)", self.node(size, asm))
+            }
+            CILNode::LdStaticField(sfld) => {
+                let sfld = asm[sfld];
+                let path = self.static_field_path(sfld.owner(), &asm[sfld.name()], asm);
+                if mem_suffix(sfld.tpe(), asm) == Some("obj") {
+                    path
+                } else {
+                    load(sfld.tpe(), &path, asm)
+                }
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                let sfld = asm[sfld];
+                self.static_field_path(sfld.owner(), &asm[sfld.name()], asm)
+            }
+            CILNode::LdFtn(method) => {
+                let mref = asm[method].clone();
+                let handle = if asm[mref.class()].asm().is_some() {
+                    let path = self.module.extern_path(&mref, asm);
+                    let name = path.strip_prefix("Ext.").unwrap();
+                    format!(
+                        "MethodHandles.lookup().findStatic(Ext.class, {}, {})",
+                        java_string(name),
+                        method_type(mref.sig(), asm)
+                    )
+                } else {
+                    let path = self.module.method_path(method, &mref, asm);
+                    let (class, name) = path.rsplit_once('.').unwrap();
+                    format!(
+                        "MethodHandles.lookup().findStatic({class}.class, {}, {})",
+                        java_string(name),
+                        method_type(mref.sig(), asm)
+                    )
                 };
-                let kind = match method.kind() {
-                    crate::v2::cilnode::MethodKind::Static => "static",
-                    crate::v2::cilnode::MethodKind::Instance => "instance",
-                    crate::v2::cilnode::MethodKind::Virtual => "virtual instance",
-                    crate::v2::cilnode::MethodKind::Constructor => {
-                        "rtspecialname specialname"
+                let id = self.module.fn_ptrs.len();
+                let field = self
+                    .module
+                    .fn_ptrs
+                    .entry(handle)
+                    .or_insert_with(|| format!("f{id}"));
+                format!("FnTable.{field}")
+            }
+            CILNode::LdTypeToken(tpe) => {
+                format!("((Object){})", java_string(&format!("{:?}", asm[tpe])))
+            }
+            CILNode::LdLen(arr) => format!("({}).length", self.node(arr, asm)),
+            CILNode::LocAllocAlgined { tpe, align } => {
+                format!("Rt.stack_alloc({}, {align})", size_align(asm[tpe], asm).0)
+            }
+            CILNode::LdElelemRef { array, index } => format!(
+                "({})[(int)({})]",
+                self.node(array, asm),
+                self.node(index, asm)
+            ),
+            CILNode::UnboxAny { object, tpe } => {
+                let obj = self.node(object, asm);
+                format!("(({})(Object)({obj}))", jtype(asm[tpe], asm))
+            }
+        }
+    }
+    fn cond(&mut self, cond: NodeIdx, asm: &mut Assembly) -> String {
+        self.node_as(cond, Type::Bool, asm)
+    }
+    fn root(
+        &mut self,
+        idx: RootIdx,
+        labels: &Labels,
+        parent: Option<u32>,
+        asm: &mut Assembly,
+    ) -> String {
+        match asm[idx].clone() {
+            CILRoot::StLoc(loc, val) => {
+                let tpe = asm[self.locals[loc as usize].1];
+                let val = self.node_as(val, tpe, asm);
+                if self.mem_locals.contains(&loc) {
+                    store(tpe, &format!("L{loc}"), &val, asm)
+                } else {
+                    format!("L{loc} = {val};")
+                }
+            }
+            CILRoot::StArg(arg, val) => {
+                let tpe = asm[self.sig].inputs()[arg as usize];
+                let val = self.node_as(val, tpe, asm);
+                if self.mem_args.contains(&arg) {
+                    store(tpe, &format!("AM{arg}"), &val, asm)
+                } else {
+                    format!("A{arg} = {val};")
+                }
+            }
+            CILRoot::Ret(val) => {
+                let output = *asm[self.sig].output();
+                let val = self.node_as(val, output, asm);
+                if output == Type::Void {
+                    // Returning the result of a void call.
+                    format!("{{ {val}; Rt.sp = fp; return; }}")
+                } else if mem_suffix(output, asm).is_none() {
+                    let (size, align) = size_align(output, asm);
+                    format!("return Rt.ret_struct(fp, {val}, {size}, {align});")
+                } else {
+                    format!(
+                        "{{ {} ret = {val}; Rt.sp = fp; return ret; }}",
+                        jtype(output, asm)
+                    )
+                }
+            }
+            CILRoot::Pop(val) => match asm[val] {
+                CILNode::Call(_) => format!("{};", self.node(val, asm)),
+                _ => format!("Rt.pop({});", self.node(val, asm)),
+            },
+            CILRoot::Throw(val) => format!("throw (Throwable)({});", self.node(val, asm)),
+            CILRoot::VoidRet => "{ Rt.sp = fp; return; }".into(),
+            CILRoot::Break | CILRoot::Nop | CILRoot::SourceFileInfo { .. } => String::new(),
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = info.as_ref();
+                let goto = labels.goto(*target, *sub_target, parent);
+                let cond = match cond {
+                    None => return goto,
+                    Some(BranchCond::True(cond)) => self.cond(*cond, asm),
+                    Some(BranchCond::False(cond)) => format!("!{}", self.cond(*cond, asm)),
+                    Some(BranchCond::Eq(lhs, rhs)) => self.equals(*lhs, *rhs, asm),
+                    Some(BranchCond::Ne(lhs, rhs)) => format!("!{}", self.equals(*lhs, *rhs, asm)),
+                    Some(
+                        BranchCond::Lt(lhs, rhs, kind)
+                        | BranchCond::Gt(lhs, rhs, kind)
+                        | BranchCond::Le(lhs, rhs, kind)
+                        | BranchCond::Ge(lhs, rhs, kind),
+                    ) => {
+                        let op = match cond.as_ref().unwrap() {
+                            BranchCond::Lt(..) => "<",
+                            BranchCond::Gt(..) => ">",
+                            BranchCond::Le(..) => "<=",
+                            _ => ">=",
+                        };
+                        let unsigned = matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+                        self.compare(*lhs, *rhs, op, unsigned, asm)
                     }
                 };
-                let pinvoke = if let MethodImpl::Extern {
-                    lib,
-                    preserve_errno,
-                } = method.implementation()
-                {
-                    let lib = asm.get_string(*lib);
-                    if *preserve_errno {
-                        format!("pinvokeimpl(\"{lib}\" cdecl lasterr)")
-                    } else {
-                        format!("pinvokeimpl(\"{lib}\" cdecl)")
+                format!("if ({cond}) {goto}")
+            }
+            CILRoot::Switch(info) => {
+                let (value, int, targets) = info.as_ref();
+                // The cases are zero extended, so the value must be too.
+                let value = mask_small(*int, &self.node(*value, asm));
+                let (target, sub_target) = targets.otherwise();
+                let otherwise = labels.goto(target, sub_target, parent);
+                let goto = |(target, sub_target)| labels.goto(target, sub_target, parent);
+                match (int.size(), targets) {
+                    // Java can only switch on `int`s. `javac` turns this into a `tableswitch` or `lookupswitch`.
+                    #[allow(clippy::cast_possible_truncation)]
+                    (Some(1..=4), _) => {
+                        let cases: String = targets
+                            .cases()
+                            .into_iter()
+                            .map(|(case, target)| {
+                                format!("case {}: {}\n", case as u32 as i32, goto(target))
+                            })
+                            .collect();
+                        format!("switch ({value}) {{\n{cases}default: {otherwise}\n}}")
                     }
+                    // Wider jump tables are offset, and range checked, to get an `int` index.
+                    (_, SwitchTargets::Dense { base, table, .. }) => {
+                        let cases: String = table
+                            .iter()
+                            .enumerate()
+                            .map(|(idx, target)| format!("case {idx}: {}\n", goto(*target)))
+                            .collect();
+                        format!(
+                            "{{ long switch_idx = ({value}) - {base}L; switch (Long.compareUnsigned(switch_idx, {len}L) < 0 ? (int)switch_idx : -1) {{\n{cases}default: {otherwise}\n}} }}",
+                            base = *base as i64,
+                            len = table.len()
+                        )
+                    }
+                    (_, SwitchTargets::Sparse { cases, .. }) => {
+                        let cases: String = cases
+                            .iter()
+                            .map(|(case, target)| {
+                                format!("if (({value}) == {}L) {}\n", *case as i64, goto(*target))
+                            })
+                            .collect();
+                        format!("{cases}{otherwise}")
+                    }
+                }
+            }
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = info.as_ref();
+                let field = asm[*field];
+                let addr = self.node(*addr, asm);
+                let val = self.node_as(*val, field.tpe(), asm);
+                if struct_def(field.owner(), asm).is_some() {
+                    let addr = format!("({addr}) + {}", field_offset(field, asm));
+                    store(field.tpe(), &addr, &val, asm)
                 } else {
-                    "".into()
-                };
-                let name = asm.get_string(method.name());
-                let sig = asm.get_sig(method.sig());
-                let ret = type_il(sig.output(), asm);
-                assert_eq!(method.arg_names().len(), sig.inputs().len(), "{name:?}");
-                let inputs = match method.kind() {
-                    crate::v2::cilnode::MethodKind::Static => sig.inputs(),
-                    crate::v2::cilnode::MethodKind::Instance
-                    | crate::v2::cilnode::MethodKind::Virtual
-                    | crate::v2::cilnode::MethodKind::Constructor => &sig.inputs()[1..],
-                };
-
-                let inputs: String = inputs
-                    .iter()
-                    .zip(method.arg_names())
-                    .map(|(tpe, name)| match name {
-                        Some(name) => {
-                            format!("{} '{}'", non_void_type_il(tpe, asm), asm.get_string(*name))
-                        }
-                        None => non_void_type_il(tpe, asm),
+                    format!(
+                        "(({})({addr})).{} = {val};",
+                        def_class_name(field.owner(), asm),
+                        escape_ident(&asm[field.name()])
+                    )
+                }
+            }
+            CILRoot::Call(info) => {
+                let (method, args) = info.as_ref();
+                format!("{};", self.call(*method, args, asm))
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, _) = info.as_ref();
+                let addr = self.node(*addr, asm);
+                let val = self.node_as(*val, *tpe, asm);
+                store(*tpe, &addr, &val, asm)
+            }
+            CILRoot::InitBlk(blk) => {
+                let (dst, val, count) = blk.as_ref();
+                format!(
+                    "Rt.memset({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*val, asm),
+                    self.node(*count, asm)
+                )
+            }
+            CILRoot::CpBlk(blk) => {
+                let (dst, src, len) = blk.as_ref();
+                format!(
+                    "Rt.memcpy({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*src, asm),
+                    self.node(*len, asm)
+                )
+            }
+            CILRoot::TailCall(_) | CILRoot::TailCallI(_) => {
+                // The JVM has no tail calls, so this is just a call followed by a return.
+                let roots = asm[idx].clone().tail_call_as_ret(asm).unwrap();
+                roots
+                    .into_iter()
+                    .map(|root| {
+                        let root = asm.alloc_root(root);
+                        self.root(root, labels, parent, asm)
                     })
-                    .intersperse(",".to_string())
-                    .collect();
-                let preservesig = if method.implementation().is_extern(){
-                    "preservesig"
-                }else{""};
+                    .collect()
+            }
+            CILRoot::CallI(info) => {
+                let (fn_ptr, sig, args) = info.as_ref();
+                let fn_ptr = self.node(*fn_ptr, asm);
+                let inputs = asm[*sig].inputs().to_vec();
+                let args = self.args(args, &inputs, asm);
+                let call = format!("Rt.fn_at({fn_ptr}).invoke({})", args.join(", "));
+                match jtype(*asm[*sig].output(), asm).as_str() {
+                    "void" => format!("{call};"),
+                    output => format!("Rt.pop(({output}) {call});"),
+                }
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => labels.goto(target, 0, None),
+            CILRoot::ReThrow => "throw exc;".into(),
+            CILRoot::SetStaticField { field, val } => {
+                let field = asm[field];
+                let val = self.node_as(val, field.tpe(), asm);
+                let path = self.static_field_path(field.owner(), &asm[field.name()], asm);
+                if mem_suffix(field.tpe(), asm) == Some("obj") {
+                    format!("{path} = {val};")
+                } else {
+                    store(field.tpe(), &path, &val, asm)
+                }
+            }
+            CILRoot::CpObj { src, dst, tpe } => format!(
+                "Rt.memcpy({}, {}, {});",
+                self.node(dst, asm),
+                self.node(src, asm),
+                size_align(asm[tpe], asm).0
+            ),
+            CILRoot::Unreachable(msg) => format!(
+                "throw new IllegalStateException({});",
+                java_string(&format!("Unreachable reached: {}", &asm[msg]))
+            ),
+        }
+    }
+}
+/// Checks if control flow never continues past `root`, so that it is the last reachable root of a block.
+fn is_terminator(root: &CILRoot) -> bool {
+    match root {
+        CILRoot::Ret(_)
+        | CILRoot::VoidRet
+        | CILRoot::Throw(_)
+        | CILRoot::ReThrow
+        | CILRoot::ExitSpecialRegion { .. }
+        | CILRoot::Switch(_)
+        | CILRoot::TailCall(_)
+        | CILRoot::TailCallI(_)
+        | CILRoot::Unreachable(_) => true,
+        CILRoot::Branch(info) => info.2.is_none(),
+        _ => false,
+    }
+}
+/// Numbers the blocks of a method. Blocks are identified by their id, and the id of the block they are a handler of.
+struct Labels(FxHashMap<(u32, Option<u32>), usize>);
+impl Labels {
+    fn label(&self, block: u32, parent: Option<u32>) -> usize {
+        self.0[&(block, parent)]
+    }
+    fn goto(&self, target: u32, sub_target: u32, parent: Option<u32>) -> String {
+        let label = match (sub_target, parent) {
+            (0, _) => self.label(target, None),
+            (_, Some(_)) => self.label(sub_target, Some(target)),
+            (_, None) => self.label(sub_target, None),
+        };
+        format!("{{ bb = {label}; continue; }}")
+    }
+}
+pub struct JavaExporter {
+    is_lib: bool,
+}
+impl JavaExporter {
+    #[must_use]
+    pub fn new(is_lib: bool) -> Self {
+        Self { is_lib }
+    }
+    fn export_method_def(
+        asm: &mut Assembly,
+        def: &MethodDef,
+        module: &mut ModuleCtx,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let name = asm[def.name()].to_string();
+        let mname = method_name(&name, def.sig(), def.kind(), asm);
+        let sig = def.sig();
+        let inputs = asm[sig].inputs().to_vec();
+        let output = *asm[sig].output();
+        let params = inputs
+            .iter()
+            .enumerate()
+            .map(|(arg, tpe)| format!("{} A{arg}", jtype(*tpe, asm)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "public static {} {mname}({params}) throws Throwable {{",
+            jtype(output, asm)
+        )?;
+        match def.resolved_implementation(asm) {
+            MethodImpl::MethodBody { .. } => (),
+            MethodImpl::Extern { .. } => {
+                let body = native_shim(&name, &inputs, output, asm)
+                    .unwrap_or_else(|| format!("throw Rt.missing({});", java_string(&name)));
+                return writeln!(out, "{body}\n}}");
+            }
+            MethodImpl::Missing => {
+                return writeln!(out, "throw Rt.missing({});\n}}", java_string(&name));
+            }
+            MethodImpl::AliasFor(_) => panic!("Impossible: unrechable reached."),
+        }
+        let locals: Vec<LocalDef> = def.iter_locals(asm).copied().collect();
+        let blocks = def.blocks(asm).unwrap().to_vec();
+        // Find all the locals and arguments whose address is taken.
+        let mut mem_locals: FxHashSet<u32> = FxHashSet::default();
+        let mut mem_args: FxHashSet<u32> = FxHashSet::default();
+        for root in blocks.iter().flat_map(super::BasicBlock::iter_roots) {
+            for elem in CILIter::new(asm[root].clone(), asm) {
+                match elem {
+                    CILIterElem::Node(CILNode::LdLocA(loc)) => {
+                        mem_locals.insert(loc);
+                    }
+                    CILIterElem::Node(CILNode::LdArgA(arg)) => {
+                        mem_args.insert(arg);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for (loc, (_, tpe)) in locals.iter().enumerate() {
+            if mem_suffix(asm[*tpe], asm).is_none() {
+                mem_locals.insert(loc as u32);
+            }
+        }
+        for (arg, tpe) in inputs.iter().enumerate() {
+            if mem_suffix(*tpe, asm).is_none() {
+                mem_args.insert(arg as u32);
+            }
+        }
+        writeln!(out, "final long fp = Rt.sp;")?;
+        for (arg, tpe) in inputs.iter().enumerate() {
+            if !mem_args.contains(&(arg as u32)) {
+                continue;
+            }
+            let (size, align) = size_align(*tpe, asm);
+            match mem_suffix(*tpe, asm) {
+                // Valuetypes are passed by address, so the callee needs to make its own copy.
+                None => writeln!(
+                    out,
+                    "long AM{arg} = Rt.stack_copy(A{arg}, {size}, {align});"
+                )?,
+                Some(_) => writeln!(
+                    out,
+                    "long AM{arg} = Rt.stack_alloc({size}, {align});\n{}",
+                    store(*tpe, &format!("AM{arg}"), &format!("A{arg}"), asm)
+                )?,
+            }
+        }
+        for (loc, (_, tpe)) in locals.iter().enumerate() {
+            let tpe = asm[*tpe];
+            if mem_locals.contains(&(loc as u32)) {
+                let (size, align) = size_align(tpe, asm);
+                writeln!(out, "long L{loc} = Rt.stack_alloc({size}, {align});")?;
+            } else {
+                writeln!(out, "{} L{loc} = {};", jtype(tpe, asm), zero(tpe, asm))?;
+            }
+        }
+        let mut labels = FxHashMap::default();
+        for block in &blocks {
+            let len = labels.len();
+            labels.insert((block.block_id(), None), len);
+            for hblock in block.handler().into_iter().flatten() {
+                let len = labels.len();
+                labels.insert((hblock.block_id(), Some(block.block_id())), len);
+            }
+        }
+        let labels = Labels(labels);
+        let has_handlers = blocks.iter().any(|block| block.handler().is_some());
+        writeln!(out, "int bb = 0;")?;
+        if has_handlers {
+            writeln!(out, "Throwable exc = null;")?;
+        }
+        writeln!(out, "for (;;) {{")?;
+        if has_handlers {
+            writeln!(out, "try {{")?;
+        }
+        writeln!(out, "switch (bb) {{")?;
+        let mut ctx = MethodCtx {
+            sig,
+            locals: &locals,
+            mem_locals,
+            mem_args,
+            module,
+        };
+        let mut export_block = |block: &super::BasicBlock,
+                                parent: Option<u32>,
+                                out: &mut dyn Write|
+         -> std::io::Result<()> {
+            let label = labels.label(block.block_id(), parent);
+            writeln!(out, "case {label}:")?;
+            // Blocks may fall trough into each other, so the current block needs to be known for exception handling.
+            if has_handlers {
+                writeln!(out, "bb = {label};")?;
+            }
+            for root in block.roots() {
+                writeln!(out, "{}", ctx.root(*root, &labels, parent, asm))?;
+                // Java rejects unreachable statements.
+                if is_terminator(&asm[*root]) {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        for block in &blocks {
+            export_block(block, None, out)?;
+            for hblock in block.handler().into_iter().flatten() {
+                export_block(hblock, Some(block.block_id()), out)?;
+            }
+        }
+        writeln!(out, "}}")?;
+        writeln!(
+            out,
+            "throw new IllegalStateException({});",
+            java_string(&format!("Control flow reached the end of {name}"))
+        )?;
+        if has_handlers {
+            writeln!(out, "}} catch (Throwable err) {{")?;
+            writeln!(out, "exc = err;")?;
+            writeln!(out, "switch (bb) {{")?;
+            for block in &blocks {
+                let Some(hblock) = block.handler().and_then(<[_]>::first) else {
+                    continue;
+                };
+                writeln!(
+                    out,
+                    "case {}: bb = {}; continue;",
+                    labels.label(block.block_id(), None),
+                    labels.label(hblock.block_id(), Some(block.block_id()))
+                )?;
+            }
+            writeln!(out, "default: throw err;")?;
+            writeln!(out, "}}")?;
+            writeln!(out, "}}")?;
+        }
+        writeln!(out, "}}")?;
+        writeln!(out, "}}")
+    }
+    fn export_class(
+        asm: &mut Assembly,
+        defid: ClassDefIdx,
+        module: &mut ModuleCtx,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let class = asm[defid].clone();
+        let cname = class_name(&asm[class.name()]);
+        let extends = match class.extends() {
+            Some(parent) if !class.is_valuetype() => match asm.class_ref_to_def(parent) {
+                Some(parent) => format!(" extends {}", class_name(&asm[asm[parent].name()])),
+                None if asm[asm[parent].name()].contains("Exception") => {
+                    " extends RuntimeException".into()
+                }
+                None => String::new(),
+            },
+            _ => String::new(),
+        };
+        let modifier = if class.is_valuetype() { "final " } else { "" };
+        writeln!(out, "public {modifier}class {cname}{extends} {{")?;
+        if !class.is_valuetype() {
+            for (tpe, fname, _) in class.fields() {
+                writeln!(
+                    out,
+                    "public {} {};",
+                    jtype(*tpe, asm),
+                    escape_ident(&asm[*fname])
+                )?;
+            }
+        }
+        for (tpe, fname, _) in class.static_fields() {
+            let fname = escape_ident(&asm[*fname]);
+            if mem_suffix(*tpe, asm) == Some("obj") {
+                writeln!(out, "public static {} {fname};", jtype(*tpe, asm))?;
+            } else {
+                let (size, align) = size_align(*tpe, asm);
                 writeln!(
                     out,
-                    ".method {vis} hidebysig {kind} {pinvoke} {ret} '{name}'({inputs}) cil managed {preservesig}{{// Method ID {method_id:?}"
+                    "public static final long {fname} = Rt.static_alloc({size}, {align});"
                 )?;
-                let stack_size = match method.implementation() {
-                    MethodImpl::MethodBody { blocks, .. } => blocks
+            }
+        }
+        let (_, struct_size, struct_align) = class_layout(&class, asm);
+        for (idx, chunk) in class.methods().chunks(METHOD_CHUNK).enumerate() {
+            if idx > 0 {
+                writeln!(out, "public static final class $m{idx} {{")?;
+            }
+            for method in chunk {
+                let def = asm[*method].clone();
+                Self::export_method_def(asm, &def, module, out)?;
+            }
+            if idx > 0 {
+                writeln!(out, "}}")?;
+            }
+        }
+        for method in class.methods() {
+            let def = asm[*method].clone();
+            let path = module.method_path(**method, &asm[**method].clone(), asm);
+            let inputs = asm[def.sig()].inputs().to_vec();
+            let output = jtype(*asm[def.sig()].output(), asm);
+            let ret = if output == "void" { "" } else { "return " };
+            let mname = method_name(&asm[def.name()], def.sig(), def.kind(), asm);
+            match def.kind() {
+                // Virtual calls are dispatched trough instance methods.
+                MethodKind::Virtual if !class.is_valuetype() && !inputs.is_empty() => {
+                    let params = inputs[1..]
                         .iter()
-                        .flat_map(|block| block.roots().iter())
-                        .map(|root| {
-                            crate::v2::CILIter::new(asm.get_root(*root).clone(), asm).count() + 4
-                        })
-                        .max()
-                        .unwrap_or(0),
-                    MethodImpl::Extern { .. } => 0,
-                    MethodImpl::AliasFor(_) => todo!(),
-                    MethodImpl::Missing => 3,
-                };
-                if stack_size > 6 {
-                    writeln!(out, ".maxstack {stack_size}")?;
+                        .enumerate()
+                        .map(|(arg, tpe)| format!("{} A{}", jtype(*tpe, asm), arg + 1))
+                        .collect::<Vec<_>>();
+                    let args = (1..inputs.len()).map(|arg| format!("A{arg}"));
+                    writeln!(
+                        out,
+                        "public {output} v${}({}) throws Throwable {{ {ret}{path}({}); }}",
+                        escape_ident(&asm[def.name()]),
+                        params.join(", "),
+                        std::iter::once("this".to_string())
+                            .chain(args)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
                 }
-                if **name == *"entrypoint" {
-                    writeln!(out, ".entrypoint")?;
+                // Constructors get a factory, which allocates the object and initializes it.
+                MethodKind::Constructor if !inputs.is_empty() => {
+                    let params = inputs[1..]
+                        .iter()
+                        .enumerate()
+                        .map(|(arg, tpe)| format!("{} A{}", jtype(*tpe, asm), arg + 1))
+                        .collect::<Vec<_>>();
+                    let args = (1..inputs.len())
+                        .map(|arg| format!("A{arg}"))
+                        .collect::<Vec<_>>();
+                    let (tpe, alloc) = if class.is_valuetype() {
+                        (
+                            "long".to_string(),
+                            format!("Rt.stack_alloc({struct_size}, {struct_align})"),
+                        )
+                    } else {
+                        (cname.clone(), format!("new {cname}()"))
+                    };
+                    writeln!(
+                        out,
+                        "public static {tpe} new${mname}({}) throws Throwable {{ {tpe} obj = {alloc}; {path}({}); return obj; }}",
+                        params.join(", "),
+                        std::iter::once("obj".to_string())
+                            .chain(args)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
                 }
-                // Export the implementation
-                self.export_method_imp(asm, out, method.resolved_implementation(asm), name)?;
-                writeln!(out, "}}")?;
-            }*/
-            writeln!(out, ".end class")?;
+                _ => (),
+            }
         }
-
-        Ok(())
+        writeln!(out, "}}")
+    }
+    /// Exports all the sources of this module to `dir`, returning the paths of the exported files.
+    fn export_sources(
+        &self,
+        asm: &Assembly,
+        dir: &Path,
+    ) -> std::io::Result<Vec<std::path::PathBuf>> {
+        const IMPORTS: &str = "import java.math.BigInteger;\nimport java.lang.invoke.MethodHandles;\nimport java.lang.invoke.MethodType;\n";
+        let mut asm = asm.clone();
+        let mut module = ModuleCtx {
+            chunks: FxHashMap::default(),
+            externs: BTreeMap::new(),
+            fn_ptrs: BTreeMap::new(),
+        };
+        let mut defs: Vec<ClassDefIdx> = asm.iter_class_def_ids().copied().collect();
+        defs.sort_by_key(|def| asm[asm[*def].name()].to_string());
+        for def in &defs {
+            for (idx, method) in asm[*def].methods().iter().enumerate() {
+                module.chunks.insert(**method, idx / METHOD_CHUNK);
+            }
+        }
+        let mut files = Vec::new();
+        let mut names = BTreeSet::new();
+        for def in &defs {
+            let name = class_name(&asm[asm[*def].name()]);
+            assert!(names.insert(name.clone()), "Duplicate class {name}");
+            let path = dir.join(format!("{name}.java"));
+            let mut out = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            writeln!(out, "{IMPORTS}")?;
+            Self::export_class(&mut asm, *def, &mut module, &mut out)?;
+            if name == "MainModule" && !self.is_lib {
+                Self::export_main(&asm, *def, &module, &mut out)?;
+            }
+            out.flush()?;
+            files.push(path);
+        }
+        let mut ext = std::io::BufWriter::new(std::fs::File::create(dir.join("Ext.java"))?);
+        writeln!(
+            ext,
+            "{IMPORTS}\n// Shims for extern methods.\npublic final class Ext {{"
+        )?;
+        for shim in module.externs.values() {
+            writeln!(ext, "{shim}")?;
+        }
+        writeln!(ext, "}}")?;
+        ext.flush()?;
+        let mut fn_table =
+            std::io::BufWriter::new(std::fs::File::create(dir.join("FnTable.java"))?);
+        writeln!(
+            fn_table,
+            "{IMPORTS}\n// Function pointers to the methods whose address is taken.\npublic final class FnTable {{"
+        )?;
+        for field in module.fn_ptrs.values() {
+            writeln!(fn_table, "public static final long {field};")?;
+        }
+        writeln!(fn_table, "static {{\ntry {{")?;
+        for (handle, field) in &module.fn_ptrs {
+            writeln!(fn_table, "{field} = Rt.fn_ptr({handle});")?;
+        }
+        writeln!(
+            fn_table,
+            "}} catch (Throwable err) {{\nthrow new ExceptionInInitializerError(err);\n}}\n}}\n}}"
+        )?;
+        fn_table.flush()?;
+        std::fs::write(dir.join("Rt.java"), include_str!("java_runtime.java"))?;
+        files.extend(["Ext.java", "FnTable.java", "Rt.java"].map(|file| dir.join(file)));
+        Ok(files)
+    }
+    /// Exports the entry class of an executable, which initializes the statics and calls the entrypoint. It is kept
+    /// separate from `MainModule`, since `MainModule` may already define a method named `main`.
+    fn export_main(
+        asm: &Assembly,
+        main_module: ClassDefIdx,
+        module: &ModuleCtx,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let mut body = vec!["Rt.set_args(args);".to_string()];
+        for name in [
+            super::asm::CCTOR,
+            super::asm::TCCTOR,
+            super::asm::ENTRYPOINT,
+        ] {
+            let Some(method) = asm[main_module]
+                .methods()
+                .iter()
+                .find(|method| &asm[asm[**method].name()] == name)
+            else {
+                continue;
+            };
+            let path = module.method_path(**method, &asm[**method].clone(), asm);
+            let args = asm[asm[*method].sig()]
+                .inputs()
+                .iter()
+                .map(|tpe| match jtype(*tpe, asm).as_str() {
+                    "String[]" => "Rt.args".to_string(),
+                    _ => zero(*tpe, asm).to_string(),
+                })
+                .collect::<Vec<_>>();
+            body.push(format!("{path}({});", args.join(", ")));
+        }
+        body.push("Rt.flush();".into());
+        writeln!(
+            out,
+            "class MainModule$Entry {{\npublic static void main(String[] args) throws Throwable {{\n{}\n}}\n}}",
+            body.join("\n")
+        )
     }
 }
-
 impl Exporter for JavaExporter {
     type Error = std::io::Error;
 
-    fn export(&self, asm: &super::Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
-        // The IL file should be next to the target
-        let il_path = target.with_extension("j");
-        let mut il_out = std::io::BufWriter::new(std::fs::File::create(&il_path)?);
-        self.export_to_write(asm, &mut il_out)?;
-        // Needed to ensure the IL file is valid!
-        il_out.flush().unwrap();
-        drop(il_out);
+    fn export(&self, asm: &Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
+        // The sources and classes are placed in a directory next to the target.
+        let src_dir = target.with_extension("java_src");
+        let class_dir = src_dir.join("classes");
+        if src_dir.exists() {
+            std::fs::remove_dir_all(&src_dir)?;
+        }
+        std::fs::create_dir_all(&class_dir)?;
+        let files = self.export_sources(asm, &src_dir)?;
+        let mut cmd = std::process::Command::new(JAVAC_PATH.clone());
+        cmd.arg("-nowarn")
+            .arg("-XDsuppressNotes")
+            .arg("-d")
+            .arg(&class_dir)
+            .args(&files);
+        let out = cmd.output().unwrap();
+        assert!(
+            out.status.success(),
+            "stdout:{} stderr:{} cmd:{cmd:?}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        );
         let jar_out = target.with_extension("jar");
-
-        let mut cmd = std::process::Command::new(JAVA_ASM_PATH.clone());
-        cmd.arg("asm").arg(il_path)
-        .arg("--out")
-        .arg(jar_out)
-        // .arg("-FOLD") saves up on space, consider enabling.
-        ;
+        let mut cmd = std::process::Command::new(JAR_PATH.clone());
+        cmd.arg("--create").arg("--file").arg(&jar_out);
+        if !self.is_lib {
+            cmd.arg("--main-class").arg("MainModule$Entry");
+        }
+        cmd.arg("-C").arg(&class_dir).arg(".");
         let out = cmd.output().unwrap();
-        let stdout = String::from_utf8_lossy(&out.stdout);
-        let stderr = String::from_utf8_lossy(&out.stderr);
         assert!(
-            !(stderr.contains("Error") || stdout.contains("Error")),
+            out.status.success(),
             "stdout:{} stderr:{} cmd:{cmd:?}",
-            stdout,
+            String::from_utf8_lossy(&out.stdout),
             String::from_utf8_lossy(&out.stderr)
         );
-
         Ok(())
     }
 }
-fn simple_class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
-    let cref = asm.class_ref(cref);
-    let name = asm.get_string(cref.name()).replace('.', "/");
-    if let Some(assembly) = cref.asm() {
-        let assembly = asm.get_string(assembly).replace('.', "/");
-        format!("{assembly}/{name}")
-    } else {
-        name
-    }
-}
-pub(crate) fn class_ref(cref: ClassRefIdx, asm: &Assembly) -> String {
-    let cref = asm.class_ref(cref);
-    let name = asm.get_string(cref.name());
-    let prefix = if cref.is_valuetype() {
-        "valuetype"
-    } else {
-        "class"
-    };
-    let generic_list = if cref.generics().is_empty() {
-        String::new()
-    } else {
-        format!(
-            "<{generics}>",
-            generics = cref
-                .generics()
-                .iter()
-                .map(|tpe| type_il(tpe, asm))
-                .intersperse(",".to_string())
-                .collect::<String>()
-        )
-    };
-    let generic_postfix = if cref.generics().is_empty() {
-        String::new()
-    } else {
-        format!("`{}", cref.generics().len())
-    };
-    if let Some(assembly) = cref.asm() {
-        let assembly = asm.get_string(assembly);
-        format!("{prefix} [{assembly}]'{name}{generic_postfix}'{generic_list}")
-    } else {
-        format!("{prefix} '{name}{generic_postfix}'{generic_list}")
-    }
-}
-fn non_void_type_il(tpe: &Type, asm: &Assembly) -> String {
-    match tpe {
-        Type::Void => "valuetype RustVoid".into(),
-        _ => type_il(tpe, asm),
+#[test]
+fn export_arithmetic() {
+    use super::{Access, BasicBlock, ClassRef};
+    // Exporting requires a JDK.
+    if std::process::Command::new(JAVAC_PATH.clone())
+        .arg("-version")
+        .output()
+        .is_err()
+    {
+        return;
     }
-}
-fn type_il(tpe: &Type, asm: &Assembly) -> String {
-    match tpe {
-        Type::Ptr(inner) => format!("{}*", type_il(asm.get_type(*inner), asm)),
-        Type::Ref(inner) => format!("{}&", type_il(asm.get_type(*inner), asm)),
-        Type::Int(int) => match int {
-            super::Int::U8 => "uint8".into(),
-            super::Int::U16 => "uint16".into(),
-            super::Int::U32 => "uint32".into(),
-            super::Int::U64 => "uint64".into(),
-            super::Int::U128 => "valuetype [System.Runtime]System.UInt128".into(),
-            super::Int::USize => "native uint".into(),
-            super::Int::I8 => "int8".into(),
-            super::Int::I16 => "int16".into(),
-            super::Int::I32 => "int32".into(),
-            super::Int::I64 => "int64".into(),
-            super::Int::I128 => "valuetype [System.Runtime]System.Int128".into(),
-            super::Int::ISize => "native int".into(),
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let u64_tpe = asm.alloc_type(Type::Int(Int::U64));
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line_sig = asm.sig([Type::Int(Int::U64)], Type::Void);
+    let write_line = asm.alloc_methodref(MethodRef::new(
+        console,
+        write_line,
+        write_line_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let lhs = asm.alloc_node(Const::U64(u64::MAX));
+    let rhs = asm.alloc_node(Const::U64(43));
+    let product = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Mul));
+    let store = asm.alloc_root(CILRoot::StLoc(0, product));
+    let local = asm.alloc_node(CILNode::LdLoc(0));
+    let call = asm.alloc_root(CILRoot::Call(Box::new((write_line, [local].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![store, call, ret], 0, None)],
+            locals: vec![(None, u64_tpe)],
         },
-        Type::ClassRef(cref) => class_ref(*cref, asm),
-        Type::Float(float) => match float {
-            super::Float::F16 => todo!(),
-            super::Float::F32 => "float32".into(),
-            super::Float::F64 => "float64".into(),
-            super::Float::F128 => todo!(),
-        },
-        Type::PlatformChar => "char".into(),
-        Type::PlatformGeneric(arg, generic) => match generic {
-            super::tpe::GenericKind::MethodGeneric => todo!(),
-            super::tpe::GenericKind::CallGeneric => format!("!!{arg}"),
-            super::tpe::GenericKind::TypeGeneric => format!("!{arg}"),
-        },
-        Type::Bool => "bool".into(),
-        Type::Void => "void".into(),
-        Type::PlatformArray { elem, dims } => format!(
-            "{elem}[{dims}]",
-            elem = type_il(asm.get_type(*elem), asm),
-            dims = (1..(dims.get())).map(|_| ',').collect::<String>()
-        ),
-        Type::FnPtr(sig) => {
-            let sig = asm.get_sig(*sig);
-            format!(
-                "method {output}*({inputs})",
-                output = type_il(sig.output(), asm),
-                inputs = sig
-                    .inputs()
-                    .iter()
-                    .map(|tpe| non_void_type_il(tpe, asm))
-                    .intersperse(",".to_string())
-                    .collect::<String>(),
-            )
-        }
-        Type::PlatformString => "string".into(),
-        Type::PlatformObject => "object".into(),
-    }
-}
-/*
-compile_test::aligned::stable::debug
-    compile_test::aligned::stable::release
-    compile_test::any::stable::debug
-    compile_test::any::stable::release
-    compile_test::arg_test::stable::debug
-    compile_test::arg_test::stable::release
-    compile_test::assert::stable::debug
-    compile_test::assert::stable::release
-    compile_test::assign::stable::debug
-    compile_test::assign::stable::release
-    compile_test::binops::stable::debug
-    compile_test::binops::stable::release
-    compile_test::branches::stable::debug
-    compile_test::branches::stable::release
-    compile_test::caller_location::stable::debug
-    compile_test::caller_location::stable::release
-    compile_test::calls::stable::debug
-    compile_test::calls::stable::release
-    compile_test::casts::stable::debug
-    compile_test::casts::stable::release
-    compile_test::catch::stable::debug
-    compile_test::catch::stable::release
-    compile_test::closure::stable::debug
-    compile_test::closure::stable::release
-    compile_test::cmp::stable::debug
-    compile_test::cmp::stable::release
-    compile_test::copy_nonoverlaping::stable::debug
-    compile_test::copy_nonoverlaping::stable::release
-    compile_test::dyns::stable::debug
-    compile_test::dyns::stable::release
-    compile_test::empty_string_slice::stable::debug
-    compile_test::empty_string_slice::stable::release
-    compile_test::fn_ptr::stable::debug
-    compile_test::fn_ptr::stable::release
-    compile_test::fold::stable::debug
-    compile_test::fold::stable::release
-    compile_test::fuzz100::stable::debug
-    compile_test::fuzz16::stable::debug
-    compile_test::fuzz16::stable::release
-    compile_test::fuzz43::stable::debug
-    compile_test::fuzz4::stable::debug
-    compile_test::fuzz4::stable::release
-    compile_test::fuzz67::stable::debug
-    compile_test::fuzz67::stable::release
-    compile_test::fuzz80::stable::debug
-    compile_test::fuzz88::stable::debug
-    compile_test::fuzz88::stable::release
-    compile_test::fuzz94::stable::debug
-    compile_test::fuzz94::stable::release
-    compile_test::fuzz9::stable::debug
-    compile_test::fuzz9::stable::release
-    compile_test::identity::stable::debug
-    compile_test::identity::stable::release
-    compile_test::interop::stable::debug
-    compile_test::interop::stable::release
-    compile_test::mutithreading::stable::debug
-    compile_test::mutithreading::stable::release
-    compile_test::recursive::stable::debug
-    compile_test::recursive::stable::release
-    compile_test::references::stable::debug
-    compile_test::references::stable::release
-    compile_test::slice::stable::debug
-    compile_test::slice::stable::release
-    compile_test::slice_from_end::stable::debug
-    compile_test::slice_from_end::stable::release
-    compile_test::slice_index_ref::stable::debug
-    compile_test::slice_index_ref::stable::release
-    compile_test::tlocal_key_test::stable::debug
-    compile_test::tlocal_key_test::stable::release
-    compile_test::tuple::stable::debug
-    compile_test::tuple::stable::release
-    compile_test::type_id::stable::debug
-    compile_test::type_id::stable::release
-    compile_test::types::stable::debug
-    compile_test::types::stable::release
-    compile_test::vec::stable::debug
-    compile_test::vec::stable::release
-
-*/
-fn type_string(tpe: Type, asm: &Assembly) -> String {
-    match tpe {
-        Type::Ptr(_) => "J".into(),
-        Type::Ref(_) => "J".into(),
-        Type::Int(_) => "I".into(),
-        Type::ClassRef(cref) => {
-            if asm.class_ref(cref).is_valuetype() {
-                "J".into()
-            } else {
-                todo!();
-            }
-        }
-        Type::Float(_) => "F".into(),
-        Type::PlatformString => todo!(),
-        Type::PlatformChar => "C".into(),
-        Type::PlatformGeneric(_, _) => todo!(),
-        Type::PlatformObject => todo!(),
-        Type::Bool => "B".into(),
-        Type::Void => "V".into(),
-        Type::PlatformArray { elem, dims } => format!(
-            "{arr}{elem}",
-            arr = (0..dims.get()).map(|_| "[").collect::<String>(),
-            elem = type_string(*asm.get_type(elem), asm)
-        ),
-        Type::FnPtr(_) => "J".into(),
+        vec![],
+    ));
+    asm.export("/tmp/java_export.exe", JavaExporter::new(false));
+    // Only run the module if a JVM is present.
+    if let Ok(out) = std::process::Command::new("java")
+        .arg("-jar")
+        .arg("/tmp/java_export.jar")
+        .output()
+    {
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "18446744073709551573\n"
+        );
     }
 }
+#[test]
+fn unsupported_ops() {
+    let asm = Assembly::default();
+    // Ops without a Java lowering throw at runtime, instead of failing the export.
+    assert_eq!(
+        unsupported_op(BinOp::And, Type::Float(Float::F64), &asm),
+        "((double)Rt.unsupported(\"And on Float(F64)\"))"
+    );
+}
@@ -0,0 +1,500 @@
+"use strict";
+// Runtime support for modules emitted by the cilly JS exporter.
+// Pointers are plain numbers indexing into a single linear memory, 8, 16 and 32 bit integers are numbers,
+// and 64/128 bit integers (including `isize`/`usize`) are `BigInt`s.
+const rt = {};
+{
+    const STACK_BASE = 4096;
+    const STACK_SIZE = 32 << 20;
+    const STACK_END = STACK_BASE + STACK_SIZE;
+    const memory = new ArrayBuffer(STACK_END + (16 << 20), { maxByteLength: 2 ** 32 });
+    const view = new DataView(memory);
+    const bytes = new Uint8Array(memory);
+    const fs = typeof require === "function" ? require("fs") : null;
+    const utf8_decoder = new TextDecoder();
+    const utf8_encoder = new TextEncoder();
+    rt.view = view;
+    rt.bytes = bytes;
+    // Shadow stack, used for locals whose address is taken, valuetypes and `localloc`.
+    rt.sp = STACK_BASE;
+    const align_up = (val, align) => Math.ceil(val / align) * align;
+    const stack_alloc_uninit = (size, align) => {
+        const ptr = align_up(rt.sp, align);
+        if (ptr + size > STACK_END) {
+            throw new RangeError("Shadow stack overflow");
+        }
+        rt.sp = ptr + size;
+        return ptr;
+    };
+    rt.stack_alloc = (size, align) => {
+        const ptr = stack_alloc_uninit(size, align);
+        bytes.fill(0, ptr, ptr + size);
+        return ptr;
+    };
+    rt.stack_copy = (src, size, align) => {
+        const ptr = stack_alloc_uninit(size, align);
+        bytes.copyWithin(ptr, src, src + size);
+        return ptr;
+    };
+    // Pops the frame starting at `fp`, and moves the returned valuetype to the top of the callers frame.
+    rt.ret_struct = (fp, src, size, align) => {
+        rt.sp = fp;
+        const ptr = stack_alloc_uninit(size, align);
+        bytes.copyWithin(ptr, src, src + size);
+        return ptr;
+    };
+    rt.leave = (fp, val) => {
+        rt.sp = fp;
+        return val;
+    };
+    // Heap: power-of-two size classes, with a 8 byte header(original block, size class) before each allocation.
+    let heap_top = STACK_END;
+    const free_lists = new Map();
+    rt.aligned_alloc = (size, align) => {
+        size = Number(size);
+        align = Math.max(Number(align), 16);
+        const needed = size + 8 + (align > 16 ? align : 8);
+        const class_size = 2 ** Math.max(5, Math.ceil(Math.log2(needed)));
+        let block = free_lists.get(class_size)?.pop();
+        if (block === undefined) {
+            block = heap_top;
+            heap_top += class_size;
+            if (heap_top > memory.byteLength) {
+                memory.resize(Math.min(Math.max(heap_top, memory.byteLength * 2), memory.maxByteLength));
+            }
+        }
+        const ptr = align_up(block + 8, align);
+        view.setUint32(ptr - 8, block, true);
+        view.setUint32(ptr - 4, class_size, true);
+        return ptr;
+    };
+    rt.malloc = (size) => rt.aligned_alloc(size, 16);
+    rt.calloc = (count, size) => {
+        const len = Number(count) * Number(size);
+        const ptr = rt.malloc(len);
+        bytes.fill(0, ptr, ptr + len);
+        return ptr;
+    };
+    rt.free = (ptr) => {
+        ptr = Number(ptr);
+        if (ptr === 0) return;
+        const block = view.getUint32(ptr - 8, true);
+        const class_size = view.getUint32(ptr - 4, true);
+        if (!free_lists.has(class_size)) free_lists.set(class_size, []);
+        free_lists.get(class_size).push(block);
+    };
+    rt.realloc = (ptr, size, align = 16) => {
+        ptr = Number(ptr);
+        size = Number(size);
+        if (ptr === 0) return rt.aligned_alloc(size, align);
+        const usable = view.getUint32(ptr - 8, true) + view.getUint32(ptr - 4, true) - ptr;
+        if (size <= usable && ptr % Number(align) === 0) return ptr;
+        const new_ptr = rt.aligned_alloc(size, align);
+        bytes.copyWithin(new_ptr, ptr, ptr + Math.min(usable, size));
+        rt.free(ptr);
+        return new_ptr;
+    };
+    rt.static_alloc = (size, align) => {
+        const ptr = rt.aligned_alloc(size, align);
+        bytes.fill(0, ptr, ptr + size);
+        return ptr;
+    };
+    // Loads and stores
+    rt.ld_i8 = (ptr) => view.getInt8(ptr);
+    rt.ld_u8 = (ptr) => view.getUint8(ptr);
+    rt.ld_i16 = (ptr) => view.getInt16(ptr, true);
+    rt.ld_u16 = (ptr) => view.getUint16(ptr, true);
+    rt.ld_i32 = (ptr) => view.getInt32(ptr, true);
+    rt.ld_u32 = (ptr) => view.getUint32(ptr, true);
+    rt.ld_i64 = (ptr) => view.getBigInt64(ptr, true);
+    rt.ld_u64 = (ptr) => view.getBigUint64(ptr, true);
+    rt.ld_u128 = (ptr) => view.getBigUint64(ptr, true) | (view.getBigUint64(ptr + 8, true) << 64n);
+    rt.ld_i128 = (ptr) => BigInt.asIntN(128, rt.ld_u128(ptr));
+    rt.ld_f32 = (ptr) => view.getFloat32(ptr, true);
+    rt.ld_f64 = (ptr) => view.getFloat64(ptr, true);
+    rt.ld_bool = (ptr) => view.getUint8(ptr) !== 0;
+    rt.ld_ptr = (ptr) => Number(view.getBigUint64(ptr, true));
+    rt.st_i8 = (ptr, val) => view.setInt8(ptr, val);
+    rt.st_u8 = (ptr, val) => view.setUint8(ptr, val);
+    rt.st_i16 = (ptr, val) => view.setInt16(ptr, val, true);
+    rt.st_u16 = (ptr, val) => view.setUint16(ptr, val, true);
+    rt.st_i32 = (ptr, val) => view.setInt32(ptr, val, true);
+    rt.st_u32 = (ptr, val) => view.setUint32(ptr, val, true);
+    rt.st_i64 = (ptr, val) => view.setBigInt64(ptr, BigInt(val), true);
+    rt.st_u64 = (ptr, val) => view.setBigUint64(ptr, BigInt(val), true);
+    rt.st_u128 = (ptr, val) => {
+        view.setBigUint64(ptr, BigInt.asUintN(64, val), true);
+        view.setBigUint64(ptr + 8, BigInt.asUintN(64, val >> 64n), true);
+    };
+    rt.st_i128 = rt.st_u128;
+    rt.st_f32 = (ptr, val) => view.setFloat32(ptr, val, true);
+    rt.st_f64 = (ptr, val) => view.setFloat64(ptr, val, true);
+    rt.st_bool = (ptr, val) => view.setUint8(ptr, val ? 1 : 0);
+    rt.st_ptr = (ptr, val) => view.setBigUint64(ptr, BigInt(val), true);
+    // Managed objects can't live in linear memory, so they are kept on the side, keyed by their address.
+    const objects = new Map();
+    rt.ld_obj = (ptr) => objects.get(ptr) ?? null;
+    rt.st_obj = (ptr, val) => objects.set(ptr, val);
+    rt.ld_f16 = rt.st_f16 = rt.ld_f128 = rt.st_f128 = () => rt.unsupported("f16 and f128 values");
+    // Function pointers are indices into a table of functions.
+    const fn_table = [null];
+    const fn_ids = new Map();
+    rt.fn_ptr = (fn) => {
+        let id = fn_ids.get(fn);
+        if (id === undefined) {
+            id = fn_table.length;
+            fn_table.push(fn);
+            fn_ids.set(fn, id);
+        }
+        return id;
+    };
+    rt.fn_at = (ptr) => fn_table[Number(ptr)] ?? rt.unsupported(`calling invalid function pointer ${ptr}`);
+    rt.f2i = (val) => (Number.isFinite(val) ? BigInt(Math.trunc(val)) : 0n);
+    rt.newobj = (cls, ctor, ...args) => {
+        const obj = new cls();
+        ctor(obj, ...args);
+        return obj;
+    };
+    // Valuetypes are constructed on the stack of the caller.
+    rt.new_struct = (size, align, ctor, ...args) => {
+        const ptr = rt.stack_alloc(size, align);
+        ctor(ptr, ...args);
+        return ptr;
+    };
+    rt.unsupported = (what) => {
+        throw new Error(`${what} is not supported by the JS exporter`);
+    };
+    rt.missing = (name) => () => {
+        throw new Error(`Missing method ${name}`);
+    };
+    // Strings and IO
+    rt.cstr = (ptr) => {
+        ptr = Number(ptr);
+        const end = bytes.indexOf(0, ptr);
+        return utf8_decoder.decode(bytes.slice(ptr, end));
+    };
+    rt.write_fd = (fd, data) => {
+        if (fs) {
+            fs.writeSync(fd, data);
+        } else {
+            const text = typeof data === "string" ? data : utf8_decoder.decode(data);
+            (fd === 2 ? console.error : console.log)(text);
+        }
+    };
+    const format = (fmt, args) => {
+        let out = "";
+        let arg = 0;
+        for (let i = 0; i < fmt.length; i++) {
+            if (fmt[i] !== "%") {
+                out += fmt[i];
+                continue;
+            }
+            let end = i + 1;
+            while (end < fmt.length && "-+ #0123456789.hlLqjzt".includes(fmt[end])) end++;
+            const spec = fmt.slice(i + 1, end);
+            const conv = fmt[end];
+            i = end;
+            const [, flags, width, precision] = /^([-+ #0]*)(\d*)(?:\.(\d+))?/.exec(spec);
+            let text;
+            switch (conv) {
+                case "%":
+                    out += "%";
+                    continue;
+                case "d":
+                case "i":
+                    text = BigInt(args[arg++]).toString();
+                    break;
+                case "u":
+                    text = BigInt.asUintN(64, BigInt(args[arg++])).toString();
+                    break;
+                case "x":
+                case "X":
+                case "o": {
+                    const val = BigInt.asUintN(64, BigInt(args[arg++])).toString(conv === "o" ? 8 : 16);
+                    text = conv === "X" ? val.toUpperCase() : val;
+                    break;
+                }
+                case "p":
+                    text = "0x" + BigInt(args[arg++]).toString(16);
+                    break;
+                case "c":
+                    text = String.fromCharCode(Number(args[arg++]));
+                    break;
+                case "s":
+                    text = rt.cstr(args[arg++]);
+                    if (precision !== undefined) text = text.slice(0, Number(precision));
+                    break;
+                case "f":
+                case "F":
+                    text = Number(args[arg++]).toFixed(precision === undefined ? 6 : Number(precision));
+                    break;
+                case "e":
+                    text = Number(args[arg++]).toExponential(precision === undefined ? 6 : Number(precision));
+                    break;
+                case "g":
+                    text = String(Number(args[arg++]));
+                    break;
+                default:
+                    text = "%" + spec + (conv ?? "");
+            }
+            if (width !== "" && text.length < Number(width)) {
+                const pad = flags.includes("0") && !flags.includes("-") ? "0" : " ";
+                text = flags.includes("-") ? text.padEnd(Number(width)) : text.padStart(Number(width), pad);
+            }
+            out += text;
+        }
+        return out;
+    };
+    // Rounds half-way cases to even, like `rint` in the default rounding mode.
+    const round_even = (val) => {
+        const rounded = Math.round(val);
+        return Math.abs(val % 1) === 0.5 && rounded % 2 !== 0 ? rounded - 1 : rounded;
+    };
+    const round_away = (val) => Math.sign(val) * Math.round(Math.abs(val));
+    // libc and libm
+    const libc = {
+        puts: (ptr) => {
+            rt.write_fd(1, rt.cstr(ptr) + "\n");
+            return 0;
+        },
+        printf: (fmt, ...args) => {
+            const text = format(rt.cstr(fmt), args);
+            rt.write_fd(1, text);
+            return text.length;
+        },
+        write: (fd, ptr, len) => {
+            ptr = Number(ptr);
+            rt.write_fd(Number(fd), bytes.slice(ptr, ptr + Number(len)));
+            return BigInt(len);
+        },
+        malloc: rt.malloc,
+        calloc: rt.calloc,
+        realloc: (ptr, size) => rt.realloc(ptr, size),
+        free: rt.free,
+        aligned_alloc: (align, size) => rt.aligned_alloc(size, align),
+        posix_memalign: (out, align, size) => {
+            rt.st_ptr(Number(out), rt.aligned_alloc(size, align));
+            return 0;
+        },
+        memcpy: (dst, src, len) => {
+            rt.memcpy(dst, src, len);
+            return Number(dst);
+        },
+        memmove: (dst, src, len) => {
+            rt.memcpy(dst, src, len);
+            return Number(dst);
+        },
+        memset: (dst, val, len) => {
+            rt.memset(dst, val, len);
+            return Number(dst);
+        },
+        memcmp: (lhs, rhs, len) => {
+            lhs = Number(lhs);
+            rhs = Number(rhs);
+            for (let i = 0; i < Number(len); i++) {
+                const diff = bytes[lhs + i] - bytes[rhs + i];
+                if (diff !== 0) return diff;
+            }
+            return 0;
+        },
+        strlen: (ptr) => BigInt(bytes.indexOf(0, Number(ptr)) - Number(ptr)),
+        getenv: () => 0,
+        abort: () => {
+            throw new Error("Called abort!");
+        },
+        exit: (code) => {
+            if (typeof process !== "undefined") process.exit(Number(code));
+            throw new Error(`exit(${code})`);
+        },
+        sqrt: Math.sqrt,
+        cbrt: Math.cbrt,
+        fabs: Math.abs,
+        floor: Math.floor,
+        ceil: Math.ceil,
+        trunc: Math.trunc,
+        round: round_away,
+        rint: round_even,
+        nearbyint: round_even,
+        roundeven: round_even,
+        exp: Math.exp,
+        exp2: (val) => 2 ** val,
+        expm1: Math.expm1,
+        log: Math.log,
+        log2: Math.log2,
+        log10: Math.log10,
+        log1p: Math.log1p,
+        pow: Math.pow,
+        sin: Math.sin,
+        cos: Math.cos,
+        tan: Math.tan,
+        asin: Math.asin,
+        acos: Math.acos,
+        atan: Math.atan,
+        atan2: Math.atan2,
+        sinh: Math.sinh,
+        cosh: Math.cosh,
+        tanh: Math.tanh,
+        hypot: Math.hypot,
+        fmod: (lhs, rhs) => lhs % rhs,
+        fmin: (lhs, rhs) => (Number.isNaN(lhs) ? rhs : Number.isNaN(rhs) ? lhs : Math.min(lhs, rhs)),
+        fmax: (lhs, rhs) => (Number.isNaN(lhs) ? rhs : Number.isNaN(rhs) ? lhs : Math.max(lhs, rhs)),
+        copysign: (mag, sign) => (sign < 0 || Object.is(sign, -0) ? -Math.abs(mag) : Math.abs(mag)),
+        fma: (a, b, c) => a * b + c,
+    };
+    // The `f32` variants of the libm functions.
+    for (const name of [
+        "sqrt", "cbrt", "fabs", "floor", "ceil", "trunc", "round", "rint", "nearbyint", "roundeven", "exp", "exp2",
+        "expm1", "log", "log2", "log10", "log1p", "pow", "sin", "cos", "tan", "asin", "acos", "atan", "atan2",
+        "sinh", "cosh", "tanh", "hypot", "fmod", "fmin", "fmax", "copysign", "fma",
+    ]) {
+        const fn = libc[name];
+        libc[name + "f"] = (...args) => Math.fround(fn(...args));
+    }
+    rt.libc = libc;
+    rt.extern_fn = (name) => libc[name] ?? rt.missing(name);
+    rt.memcpy = (dst, src, len) => {
+        src = Number(src);
+        bytes.copyWithin(Number(dst), src, src + Number(len));
+    };
+    rt.memset = (dst, val, len) => {
+        dst = Number(dst);
+        bytes.fill(Number(val) & 0xff, dst, dst + Number(len));
+    };
+    rt.args = () => (typeof process !== "undefined" ? process.argv.slice(1) : []);
+    // Shims for the .NET APIs used by the codegen, named like their counterparts in the C exporter.
+    const int_mangles = {
+        i1: [8, true], u1: [8, false], i2: [16, true], u2: [16, false], i4: [32, true], u4: [32, false],
+        i8: [64, true], u8: [64, false], is: [64, true], us: [64, false], i16: [128, true], u16: [128, false],
+    };
+    // Converts `val` to the type with the mangled name `target`, truncating and wrapping like C casts do.
+    const convert = (val, target) => {
+        if (target === "f4") return Math.fround(Number(val));
+        if (target === "f8") return Number(val);
+        const [bits, signed] = int_mangles[target];
+        if (typeof val === "number" && !Number.isInteger(val)) {
+            val = rt.f2i(val);
+        }
+        const wrapped = signed ? BigInt.asIntN(bits, BigInt(val)) : BigInt.asUintN(bits, BigInt(val));
+        return bits <= 32 ? Number(wrapped) : wrapped;
+    };
+    const all_mangles = [...Object.keys(int_mangles), "f4", "f8"];
+    for (const [cls, mangle] of [["Int128", "i16"], ["UInt128", "u16"]]) {
+        const wrap = (val) => convert(val, mangle);
+        const prefix = `System_${cls}_`;
+        const binop = (name, op) => {
+            rt[`${prefix}${name}${mangle}${mangle}${mangle}`] = (lhs, rhs) => wrap(op(lhs, rhs));
+        };
+        binop("op_Addition", (a, b) => a + b);
+        binop("op_Subtraction", (a, b) => a - b);
+        binop("op_Multiply", (a, b) => a * b);
+        binop("op_Division", (a, b) => a / b);
+        binop("op_Modulus", (a, b) => a % b);
+        binop("op_BitwiseOr", (a, b) => a | b);
+        binop("op_BitwiseAnd", (a, b) => a & b);
+        binop("op_ExclusiveOr", (a, b) => a ^ b);
+        for (const [name, op] of [
+            ["op_Equality", (a, b) => a === b],
+            ["op_Inequality", (a, b) => a !== b],
+            ["op_LessThan", (a, b) => a < b],
+            ["op_GreaterThan", (a, b) => a > b],
+            ["op_LessThanOrEqual", (a, b) => a <= b],
+            ["op_GreaterThanOrEqual", (a, b) => a >= b],
+        ]) {
+            rt[`${prefix}${name}${mangle}${mangle}b`] = op;
+        }
+        rt[`${prefix}op_LeftShift${mangle}i4${mangle}`] = (val, amount) => wrap(val << BigInt(amount & 127));
+        rt[`${prefix}op_RightShift${mangle}i4${mangle}`] = (val, amount) => val >> BigInt(amount & 127);
+        rt[`${prefix}op_UnsignedRightShift${mangle}i4${mangle}`] = (val, amount) =>
+            wrap(BigInt.asUintN(128, val) >> BigInt(amount & 127));
+        rt[`${prefix}op_OnesComplement${mangle}${mangle}`] = (val) => wrap(~val);
+        rt[`${prefix}op_UnaryNegation${mangle}${mangle}`] = (val) => wrap(-val);
+        rt[`${prefix}__ctorr${mangle}u8u8v`] = (high, low) => wrap((high << 64n) | low);
+        for (const other of all_mangles) {
+            for (const op of ["op_Explicit", "op_Implicit"]) {
+                rt[`${prefix}${op}${other}${mangle}`] = (val) => convert(val, mangle);
+                rt[`${prefix}${op}${mangle}${other}`] = (val) => convert(val, other);
+            }
+        }
+    }
+    for (const [mangle, [bits]] of Object.entries(int_mangles)) {
+        const to_bits = (val) => BigInt.asUintN(bits, BigInt(val));
+        rt[`System_Buffers_Binary_BinaryPrimitives_ReverseEndianness${mangle}${mangle}`] = (val) => {
+            let src = to_bits(val);
+            let res = 0n;
+            for (let i = 0; i < bits / 8; i++) {
+                res = (res << 8n) | (src & 0xffn);
+                src >>= 8n;
+            }
+            return convert(res, mangle);
+        };
+        rt[`System_Numerics_BitOperations_PopCount${mangle}i4`] = (val) =>
+            [...to_bits(val).toString(2)].filter((bit) => bit === "1").length;
+        rt[`System_Numerics_BitOperations_LeadingZeroCount${mangle}i4`] = (val) => {
+            const bin = to_bits(val).toString(2);
+            return to_bits(val) === 0n ? bits : bits - bin.length;
+        };
+        rt[`System_Numerics_BitOperations_TrailingZeroCount${mangle}i4`] = (val) => {
+            const bin = to_bits(val).toString(2);
+            return to_bits(val) === 0n ? bits : bin.length - 1 - bin.lastIndexOf("1");
+        };
+        for (const [name, rot] of [
+            ["RotateLeft", (v, n) => (v << n) | (v >> (BigInt(bits) - n))],
+            ["RotateRight", (v, n) => (v >> n) | (v << (BigInt(bits) - n))],
+        ]) {
+            rt[`System_Numerics_BitOperations_${name}${mangle}i4${mangle}`] = (val, amount) =>
+                convert(rot(to_bits(val), BigInt(amount % bits)), mangle);
+        }
+    }
+    for (const mangle of [...all_mangles, "b", "s", "c"]) {
+        rt[`System_Console_WriteLine${mangle}v`] = (val) => rt.write_fd(1, `${val}\n`);
+        rt[`System_Console_Write${mangle}v`] = (val) => rt.write_fd(1, `${val}`);
+    }
+    rt.System_Console_WriteLinev = () => rt.write_fd(1, "\n");
+    rt.System_Runtime_InteropServices_Marshal_AllocHGlobali4is = (size) => BigInt(rt.malloc(size));
+    rt.System_Runtime_InteropServices_Marshal_AllocHGlobalisis = (size) => BigInt(rt.malloc(size));
+    rt.System_Runtime_InteropServices_Marshal_ReAllocHGlobalisisis = (ptr, size) => BigInt(rt.realloc(ptr, size));
+    rt.System_Runtime_InteropServices_Marshal_FreeHGlobalisv = rt.free;
+    rt.System_Runtime_InteropServices_NativeMemory_AlignedAllocususpv = rt.aligned_alloc;
+    rt.System_Runtime_InteropServices_NativeMemory_AlignedReallocpvususpv = rt.realloc;
+    rt.System_Runtime_InteropServices_NativeMemory_AlignedFreepvv = rt.free;
+    rt.System_Runtime_InteropServices_Marshal_StringToCoTaskMemUTF8sis = (str) => {
+        const encoded = utf8_encoder.encode(str);
+        const ptr = rt.malloc(encoded.length + 1);
+        bytes.set(encoded, ptr);
+        bytes[ptr + encoded.length] = 0;
+        return BigInt(ptr);
+    };
+    rt.System_Environment_GetCommandLineArgsa1s = rt.args;
+    rt.System_Environment_Exiti4v = libc.exit;
+    rt.System_Environment_FailFastsv = (msg) => {
+        rt.write_fd(2, `${msg}\n`);
+        libc.exit(134);
+    };
+    rt.System_Exception__ctor14System_Runtime16System_Exceptionsv = (msg) => new Error(msg);
+    rt.System_String_Concatooos = (lhs, rhs) => `${lhs}${rhs}`;
+    rt.System_String_Concatssss = (lhs, rhs) => `${lhs}${rhs}`;
+    rt.System_String_Concatsssss = (a, b, c) => `${a}${b}${c}`;
+    rt.System_UIntPtr_get_MaxValueus = () => 2n ** 64n - 1n;
+    rt.System_UIntPtr_get_MinValueus = () => 0n;
+    rt.System_IntPtr_get_MaxValueis = () => 2n ** 63n - 1n;
+    rt.System_IntPtr_get_MinValueis = () => -(2n ** 63n);
+    for (const [cls, mangle, fix] of [["Single", "f4", Math.fround], ["Double", "f8", (val) => val]]) {
+        const math = cls === "Single" ? "System_MathF_" : "System_Math_";
+        for (const [name, fn] of Object.entries({
+            Sqrt: libc.sqrt, Abs: libc.fabs, Floor: libc.floor, Ceiling: libc.ceil, Truncate: libc.trunc,
+            Round: libc.rint, Exp: libc.exp, Log: libc.log, Log2: libc.log2, Log10: libc.log10, Sin: libc.sin,
+            Cos: libc.cos, Tan: libc.tan, Cbrt: libc.cbrt,
+        })) {
+            rt[`${math}${name}${mangle}${mangle}`] = (val) => fix(fn(val));
+        }
+        for (const [name, fn] of Object.entries({
+            Pow: libc.pow, Atan2: libc.atan2, CopySign: libc.copysign, MaxNumber: libc.fmax, MinNumber: libc.fmin,
+        })) {
+            rt[`${math}${name}${mangle}${mangle}${mangle}`] = (lhs, rhs) => fix(fn(lhs, rhs));
+            rt[`System_${cls}_${name}${mangle}${mangle}${mangle}`] = (lhs, rhs) => fix(fn(lhs, rhs));
+        }
+        rt[`System_${cls}_FusedMultiplyAdd${mangle}${mangle}${mangle}${mangle}`] = (a, b, c) => fix(a * b + c);
+        rt[`System_${cls}_Clamp${mangle}${mangle}${mangle}${mangle}`] = (val, min, max) =>
+            val < min ? min : val > max ? max : val;
+    }
+}
@@ -0,0 +1,1180 @@
+//! Exports an [`Assembly`] as a JavaScript module, which can be ran by a JS engine such as `node`.
+//!
+//! Pointers are offsets into a linear memory backed by an `ArrayBuffer`. Valuetypes, `localloc`s and locals whose
+//! address is taken live on a shadow stack in that memory, and valuetype values are represented by their address.
+//! 8, 16 and 32 bit integers, floats and pointers are JS numbers, while 64 and 128 bit integers are `BigInt`s.
+//! Reference types are exported as JS classes, and exceptions are mapped to JS `throw`.
+use std::{collections::BTreeSet, io::Write};
+
+use fxhash::FxHashSet;
+
+use super::{
+    bimap::IntoBiMapIndex,
+    cilnode::{ExtendKind, MethodKind, PtrCastRes, UnOp},
+    cilroot::{BranchCond, CmpKind},
+    layout::{class_layout, field_offset, int_bits, size_align, struct_def},
+    method::LocalDef,
+    Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, Const, Exporter, Float,
+    Int, MethodDef, MethodImpl, MethodRef, MethodRefIdx, NodeIdx, RootIdx, SigIdx, Type,
+};
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    "prototype",
+    "rt",
+];
+/// Replaces all characters which can't appear in a JS identifier.
+fn escape_chars(ident: &str) -> String {
+    ident
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$' => c.to_string(),
+            '.' => "_".into(),
+            _ => format!("$u{:x}$", c as u32),
+        })
+        .collect()
+}
+fn escape_ident(ident: &str) -> String {
+    let mut escaped = escape_chars(ident);
+    if escaped.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+        escaped = format!("p{escaped}");
+    }
+    if RESERVED.contains(&escaped.as_str()) {
+        escaped.push('_');
+    }
+    escaped
+}
+fn js_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+fn js_float(val: f64) -> String {
+    if val.is_nan() {
+        "NaN".into()
+    } else if val.is_infinite() {
+        if val > 0.0 {
+            "Infinity".into()
+        } else {
+            "(-Infinity)".into()
+        }
+    } else if val.is_sign_negative() {
+        format!("({val:?})")
+    } else {
+        format!("{val:?}")
+    }
+}
+/// Checks if values of this int type are represented by a `BigInt`.
+fn is_bigint(int: Int) -> bool {
+    int_bits(int) > 32
+}
+/// Wraps the result of `expr` around, so that it fits within `int`.
+fn wrap(int: Int, expr: &str) -> String {
+    match int {
+        Int::I8 => format!("(({expr}) << 24 >> 24)"),
+        Int::U8 => format!("(({expr}) & 0xff)"),
+        Int::I16 => format!("(({expr}) << 16 >> 16)"),
+        Int::U16 => format!("(({expr}) & 0xffff)"),
+        Int::I32 => format!("(({expr}) | 0)"),
+        Int::U32 => format!("(({expr}) >>> 0)"),
+        Int::I64 | Int::ISize => format!("BigInt.asIntN(64, {expr})"),
+        Int::U64 | Int::USize => format!("BigInt.asUintN(64, {expr})"),
+        Int::I128 => format!("BigInt.asIntN(128, {expr})"),
+        Int::U128 => format!("BigInt.asUintN(128, {expr})"),
+    }
+}
+/// Reinterprets the value of `expr`, of type `int`, as an unsigned integer of the same size.
+fn as_unsigned(int: Int, expr: &str) -> String {
+    match int {
+        Int::I8 => wrap(Int::U8, expr),
+        Int::I16 => wrap(Int::U16, expr),
+        Int::I32 => wrap(Int::U32, expr),
+        Int::I64 => wrap(Int::U64, expr),
+        Int::ISize => wrap(Int::USize, expr),
+        Int::I128 => wrap(Int::U128, expr),
+        _ => format!("({expr})"),
+    }
+}
+/// Emits a call to the runtime, which throws once an operation the exporter can't lower is reached.
+fn unsupported_op(op: impl std::fmt::Debug, tpe: Type) -> String {
+    format!("rt.unsupported(\"{op:?} on {tpe:?}\")")
+}
+/// Returns the suffix of the runtime functions loading and storing `tpe`, or `None` if `tpe` is a valuetype.
+fn mem_suffix(tpe: Type, asm: &Assembly) -> Option<&'static str> {
+    Some(match tpe {
+        Type::Int(int) => match int {
+            Int::I8 => "i8",
+            Int::U8 => "u8",
+            Int::I16 => "i16",
+            Int::U16 => "u16",
+            Int::I32 => "i32",
+            Int::U32 => "u32",
+            Int::I64 | Int::ISize => "i64",
+            Int::U64 | Int::USize => "u64",
+            Int::I128 => "i128",
+            Int::U128 => "u128",
+        },
+        Type::Float(float) => match float {
+            Float::F16 => "f16",
+            Float::F32 => "f32",
+            Float::F64 => "f64",
+            Float::F128 => "f128",
+        },
+        Type::Bool => "bool",
+        Type::PlatformChar => "u16",
+        Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => "ptr",
+        Type::ClassRef(cref) if struct_def(cref, asm).is_some() => return None,
+        _ => "obj",
+    })
+}
+fn load(tpe: Type, addr: &str, asm: &Assembly) -> String {
+    match mem_suffix(tpe, asm) {
+        Some(suffix) => format!("rt.ld_{suffix}({addr})"),
+        None => addr.to_owned(),
+    }
+}
+fn store(tpe: Type, addr: &str, val: &str, asm: &Assembly) -> String {
+    match mem_suffix(tpe, asm) {
+        Some(suffix) => format!("rt.st_{suffix}({addr}, {val});"),
+        None => format!(
+            "rt.memcpy({addr}, {val}, {size});",
+            size = size_align(tpe, asm).0
+        ),
+    }
+}
+fn zero(tpe: Type) -> &'static str {
+    match tpe {
+        Type::Int(int) if is_bigint(int) => "0n",
+        Type::Int(_)
+        | Type::Float(_)
+        | Type::PlatformChar
+        | Type::Ptr(_)
+        | Type::Ref(_)
+        | Type::FnPtr(_) => "0",
+        Type::Bool => "false",
+        _ => "null",
+    }
+}
+/// Returns the JS name of `mref`, and a flag indicating if it is provided by the runtime, rather than this module.
+fn method_path(mref: &MethodRef, asm: &Assembly) -> (String, bool) {
+    let class = &asm[mref.class()];
+    let class_name = escape_ident(&asm[class.name()]);
+    let mname = escape_ident(&asm[mref.name()]);
+    if class.asm().is_some() {
+        let mangled = escape_chars(
+            &asm[mref.sig()]
+                .iter_types()
+                .map(|tpe| tpe.mangle(asm))
+                .collect::<String>(),
+        );
+        (
+            format!(
+                "{}_{}{mangled}",
+                escape_chars(&asm[class.name()]),
+                escape_chars(&asm[mref.name()])
+            ),
+            true,
+        )
+    } else {
+        (format!("{class_name}.{mname}"), false)
+    }
+}
+fn branch_label(target: u32, sub_target: u32, is_handler: bool) -> String {
+    if sub_target == 0 {
+        format!("bb{target}")
+    } else if is_handler {
+        format!("h{target}_{sub_target}")
+    } else {
+        format!("bb{sub_target}")
+    }
+}
+fn goto(label: &str) -> String {
+    format!("{{ bb = \"{label}\"; continue; }}")
+}
+/// State needed to export a single method body.
+struct MethodCtx<'a> {
+    sig: SigIdx,
+    locals: &'a [LocalDef],
+    /// Locals and arguments living in linear memory, because they are valuetypes or their address is taken.
+    mem_locals: FxHashSet<u32>,
+    mem_args: FxHashSet<u32>,
+    /// Runtime provided methods used by this module.
+    externs: &'a mut BTreeSet<String>,
+}
+impl<'a> MethodCtx<'a> {
+    fn tpe(&self, node: NodeIdx, asm: &mut Assembly) -> Type {
+        asm[node]
+            .clone()
+            .typecheck(self.sig, self.locals, asm)
+            .unwrap()
+    }
+    fn call(&mut self, mref: MethodRefIdx, args: &[NodeIdx], asm: &mut Assembly) -> String {
+        let mref = asm[mref].clone();
+        let mut args: Vec<String> = args.iter().map(|arg| self.node(*arg, asm)).collect();
+        let (path, is_extern) = method_path(&mref, asm);
+        if is_extern {
+            let call = format!("rt.{path}({})", args.join(", "));
+            self.externs.insert(path);
+            return call;
+        }
+        let class_name = escape_ident(&asm[asm[mref.class()].name()]);
+        match mref.kind() {
+            MethodKind::Constructor => match struct_def(mref.class(), asm) {
+                Some(def) => {
+                    let (_, size, align) = class_layout(def, asm);
+                    args.insert(0, format!("{size}, {align}, {path}"));
+                    format!("rt.new_struct({})", args.join(", "))
+                }
+                None => {
+                    args.insert(0, format!("{class_name}, {path}"));
+                    format!("rt.newobj({})", args.join(", "))
+                }
+            },
+            MethodKind::Virtual if !args.is_empty() && !asm[mref.class()].is_valuetype() => {
+                let this = args.remove(0);
+                format!(
+                    "({this}).{mname}({})",
+                    args.join(", "),
+                    mname = escape_ident(&asm[mref.name()])
+                )
+            }
+            _ => format!("{path}({})", args.join(", ")),
+        }
+    }
+    fn compare(
+        &mut self,
+        lhs: NodeIdx,
+        rhs: NodeIdx,
+        op: &str,
+        unsigned: bool,
+        asm: &mut Assembly,
+    ) -> String {
+        let tpe = unsigned.then(|| self.tpe(lhs, asm));
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        match tpe {
+            // Unordered float comparisons are true if either operand is NaN.
+            Some(Type::Float(_)) => {
+                let inverse = match op {
+                    "<" => ">=",
+                    ">" => "<=",
+                    "<=" => ">",
+                    ">=" => "<",
+                    _ => unreachable!(),
+                };
+                format!("!(({lhs}) {inverse} ({rhs}))")
+            }
+            Some(Type::Int(int)) => {
+                format!("{} {op} {}", as_unsigned(int, &lhs), as_unsigned(int, &rhs))
+            }
+            _ => format!("({lhs}) {op} ({rhs})"),
+        }
+    }
+    fn binop(&mut self, lhs: NodeIdx, rhs: NodeIdx, op: BinOp, asm: &mut Assembly) -> String {
+        match op {
+            BinOp::Eq => return format!("({}) == ({})", self.node(lhs, asm), self.node(rhs, asm)),
+            BinOp::Lt => return self.compare(lhs, rhs, "<", false, asm),
+            BinOp::LtUn => return self.compare(lhs, rhs, "<", true, asm),
+            BinOp::Gt => return self.compare(lhs, rhs, ">", false, asm),
+            BinOp::GtUn => return self.compare(lhs, rhs, ">", true, asm),
+            _ => (),
+        }
+        let tpe = asm[lhs]
+            .clone()
+            .typecheck(self.sig, self.locals, asm)
+            .and_then(|lhs_tpe| match lhs_tpe {
+                // Pointer arithmetic may have the pointer on either side.
+                Type::Int(_) => {
+                    asm[rhs]
+                        .clone()
+                        .typecheck(self.sig, self.locals, asm)
+                        .map(|rhs_tpe| match rhs_tpe {
+                            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => rhs_tpe,
+                            _ => lhs_tpe,
+                        })
+                }
+                _ => Ok(lhs_tpe),
+            })
+            .unwrap();
+        let lhs = self.node(lhs, asm);
+        let rhs = self.node(rhs, asm);
+        match tpe {
+            Type::Ptr(_) | Type::Ref(_) | Type::FnPtr(_) => match op {
+                BinOp::Add => format!("(Number({lhs}) + Number({rhs}))"),
+                BinOp::Sub => format!("(Number({lhs}) - Number({rhs}))"),
+                BinOp::Mul => format!("(Number({lhs}) * Number({rhs}))"),
+                BinOp::Div | BinOp::DivUn => format!("Math.trunc(Number({lhs}) / Number({rhs}))"),
+                BinOp::Rem | BinOp::RemUn => format!("(Number({lhs}) % Number({rhs}))"),
+                BinOp::And => format!("Number(BigInt({lhs}) & BigInt({rhs}))"),
+                BinOp::Or => format!("Number(BigInt({lhs}) | BigInt({rhs}))"),
+                BinOp::XOr => format!("Number(BigInt({lhs}) ^ BigInt({rhs}))"),
+                BinOp::Shl => {
+                    format!("Number(BigInt.asUintN(64, BigInt({lhs}) << (BigInt({rhs}) & 63n)))")
+                }
+                // Pointers are never negative, so both shifts are the same.
+                BinOp::Shr | BinOp::ShrUn => {
+                    format!("Number(BigInt({lhs}) >> (BigInt({rhs}) & 63n))")
+                }
+                _ => unsupported_op(op, tpe),
+            },
+            Type::Bool => match op {
+                BinOp::And => format!("Boolean(({lhs}) & ({rhs}))"),
+                BinOp::Or => format!("Boolean(({lhs}) | ({rhs}))"),
+                BinOp::XOr => format!("(({lhs}) !== ({rhs}))"),
+                // A reminder of a bool can only be false or a division by zero.
+                BinOp::Rem | BinOp::RemUn => "false".into(),
+                BinOp::Mul => format!("Boolean(({lhs}) & ({rhs}))"),
+                _ => unsupported_op(op, tpe),
+            },
+            Type::Float(float) => {
+                let res = match op {
+                    BinOp::Add => format!("({lhs}) + ({rhs})"),
+                    BinOp::Sub => format!("({lhs}) - ({rhs})"),
+                    BinOp::Mul => format!("({lhs}) * ({rhs})"),
+                    BinOp::Div | BinOp::DivUn => format!("({lhs}) / ({rhs})"),
+                    BinOp::Rem | BinOp::RemUn => format!("({lhs}) % ({rhs})"),
+                    _ => return unsupported_op(op, tpe),
+                };
+                match float {
+                    Float::F32 => format!("Math.fround({res})"),
+                    _ => format!("({res})"),
+                }
+            }
+            Type::Int(int) if is_bigint(int) => {
+                let bits = int_bits(int);
+                let (lhs_un, rhs_un) = (as_unsigned(int, &lhs), as_unsigned(int, &rhs));
+                match op {
+                    BinOp::Add => wrap(int, &format!("({lhs}) + ({rhs})")),
+                    BinOp::Sub => wrap(int, &format!("({lhs}) - ({rhs})")),
+                    BinOp::Mul => wrap(int, &format!("({lhs}) * ({rhs})")),
+                    BinOp::Div => wrap(int, &format!("({lhs}) / ({rhs})")),
+                    BinOp::DivUn => wrap(int, &format!("{lhs_un} / {rhs_un}")),
+                    BinOp::Rem => wrap(int, &format!("({lhs}) % ({rhs})")),
+                    BinOp::RemUn => wrap(int, &format!("{lhs_un} % {rhs_un}")),
+                    BinOp::And => wrap(int, &format!("({lhs}) & ({rhs})")),
+                    BinOp::Or => wrap(int, &format!("({lhs}) | ({rhs})")),
+                    BinOp::XOr => wrap(int, &format!("({lhs}) ^ ({rhs})")),
+                    BinOp::Shl => wrap(int, &format!("({lhs}) << (BigInt({rhs}) & {}n)", bits - 1)),
+                    BinOp::Shr => wrap(int, &format!("({lhs}) >> (BigInt({rhs}) & {}n)", bits - 1)),
+                    BinOp::ShrUn => {
+                        wrap(int, &format!("{lhs_un} >> (BigInt({rhs}) & {}n)", bits - 1))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Type::Int(int) => {
+                let (lhs_un, rhs_un) = (as_unsigned(int, &lhs), as_unsigned(int, &rhs));
+                match op {
+                    BinOp::Add => wrap(int, &format!("({lhs}) + ({rhs})")),
+                    BinOp::Sub => wrap(int, &format!("({lhs}) - ({rhs})")),
+                    // Products of 32 bit ints don't fit within the 53 bit mantissa of a double.
+                    BinOp::Mul if matches!(int, Int::I32 | Int::U32) => {
+                        wrap(int, &format!("Math.imul({lhs}, {rhs})"))
+                    }
+                    BinOp::Mul => wrap(int, &format!("({lhs}) * ({rhs})")),
+                    BinOp::Div => wrap(int, &format!("Math.trunc(({lhs}) / ({rhs}))")),
+                    BinOp::DivUn => wrap(int, &format!("Math.trunc({lhs_un} / {rhs_un})")),
+                    BinOp::Rem => wrap(int, &format!("({lhs}) % ({rhs})")),
+                    BinOp::RemUn => wrap(int, &format!("{lhs_un} % {rhs_un}")),
+                    BinOp::And => wrap(int, &format!("({lhs}) & ({rhs})")),
+                    BinOp::Or => wrap(int, &format!("({lhs}) | ({rhs})")),
+                    BinOp::XOr => wrap(int, &format!("({lhs}) ^ ({rhs})")),
+                    BinOp::Shl => wrap(int, &format!("({lhs}) << Number({rhs})")),
+                    BinOp::Shr if int.is_signed() => format!("(({lhs}) >> Number({rhs}))"),
+                    BinOp::Shr | BinOp::ShrUn => wrap(int, &format!("{lhs_un} >>> Number({rhs})")),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unsupported_op(op, tpe),
+        }
+    }
+    fn int_cast(
+        &mut self,
+        input: NodeIdx,
+        target: Int,
+        extend: ExtendKind,
+        asm: &mut Assembly,
+    ) -> String {
+        let src = self.tpe(input, asm);
+        let input = self.node(input, asm);
+        match (src, is_bigint(target)) {
+            (Type::Float(_), false) => wrap(target, &format!("Math.trunc({input})")),
+            (Type::Float(_), true) => wrap(target, &format!("rt.f2i({input})")),
+            (Type::Bool, false) => format!("(({input}) ? 1 : 0)"),
+            (Type::Bool, true) => format!("(({input}) ? 1n : 0n)"),
+            (Type::Int(src), false) if is_bigint(src) => {
+                wrap(target, &format!("Number(BigInt.asIntN(32, {input}))"))
+            }
+            (Type::Int(src), true) => {
+                // Zero extending a signed int reinterprets it as unsigned first. Smaller ints live
+                // on the evaluation stack as 32 bit ones, so they get extended from 32 bits.
+                let input = match (src, extend) {
+                    (Int::I8 | Int::I16 | Int::I32, ExtendKind::ZeroExtend) => {
+                        format!("BigInt(({input}) >>> 0)")
+                    }
+                    (_, ExtendKind::ZeroExtend) if int_bits(src) < int_bits(target) => {
+                        as_unsigned(src, &format!("BigInt({input})"))
+                    }
+                    _ => format!("BigInt({input})"),
+                };
+                wrap(target, &input)
+            }
+            (_, false) => wrap(target, &input),
+            (_, true) => wrap(target, &format!("BigInt({input})")),
+        }
+    }
+    fn float_cast(
+        &mut self,
+        input: NodeIdx,
+        target: Float,
+        is_signed: bool,
+        asm: &mut Assembly,
+    ) -> String {
+        let src = self.tpe(input, asm);
+        let input = self.node(input, asm);
+        let input = match src {
+            Type::Int(int) if !is_signed => format!("Number({})", as_unsigned(int, &input)),
+            Type::Int(_) => format!("Number({input})"),
+            _ => format!("({input})"),
+        };
+        match target {
+            Float::F32 => format!("Math.fround({input})"),
+            Float::F64 => input,
+            Float::F16 | Float::F128 => format!("rt.unsupported(\"Casting to {target:?}\")"),
+        }
+    }
+    fn node(&mut self, idx: NodeIdx, asm: &mut Assembly) -> String {
+        match asm[idx].clone() {
+            CILNode::Const(cst) => match cst.as_ref() {
+                Const::I8(v) => format!("({v})"),
+                Const::I16(v) => format!("({v})"),
+                Const::I32(v) => format!("({v})"),
+                Const::I64(v) | Const::ISize(v) => format!("({v}n)"),
+                Const::U8(v) => format!("{v}"),
+                Const::U16(v) => format!("{v}"),
+                Const::U32(v) => format!("{v}"),
+                Const::U64(v) | Const::USize(v) => format!("{v}n"),
+                Const::PlatformString(string_idx) => js_string(&asm[*string_idx]),
+                Const::Bool(val) => format!("{val}"),
+                Const::F32(hashable_f32) => js_float(f64::from(hashable_f32.0)),
+                Const::F64(hashable_f64) => js_float(hashable_f64.0),
+                Const::Null(_) => "null".into(),
+            },
+            CILNode::BinOp(lhs, rhs, bin_op) => self.binop(lhs, rhs, bin_op, asm),
+            CILNode::UnOp(input, un_op) => {
+                let tpe = self.tpe(input, asm);
+                let input = self.node(input, asm);
+                match (&un_op, tpe) {
+                    (UnOp::Not, Type::Bool) => format!("!({input})"),
+                    (UnOp::Not, Type::Int(int)) => wrap(int, &format!("~({input})")),
+                    (UnOp::Neg, Type::Int(int)) => wrap(int, &format!("-({input})")),
+                    (UnOp::Neg, Type::Float(_)) => format!("(-({input}))"),
+                    _ => unsupported_op(un_op, tpe),
+                }
+            }
+            CILNode::LdLoc(loc) => {
+                if self.mem_locals.contains(&loc) {
+                    load(asm[self.locals[loc as usize].1], &format!("L{loc}"), asm)
+                } else {
+                    format!("L{loc}")
+                }
+            }
+            CILNode::LdArg(arg) => {
+                if self.mem_args.contains(&arg) {
+                    load(
+                        asm[self.sig].inputs()[arg as usize],
+                        &format!("A{arg}"),
+                        asm,
+                    )
+                } else {
+                    format!("A{arg}")
+                }
+            }
+            CILNode::LdLocA(loc) => format!("L{loc}"),
+            CILNode::LdArgA(arg) => format!("A{arg}"),
+            CILNode::Call(info) => {
+                let (method, args) = info.as_ref();
+                self.call(*method, args, asm)
+            }
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => self.int_cast(input, target, extend, asm),
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => self.float_cast(input, target, is_signed, asm),
+            CILNode::RefToPtr(input) => self.node(input, asm),
+            CILNode::PtrCast(input, res) => {
+                let input = self.node(input, asm);
+                match res.as_ref() {
+                    PtrCastRes::Ptr(_) | PtrCastRes::Ref(_) | PtrCastRes::FnPtr(_) => {
+                        format!("Number({input})")
+                    }
+                    PtrCastRes::USize => format!("BigInt.asUintN(64, BigInt({input}))"),
+                    PtrCastRes::ISize => format!("BigInt.asIntN(64, BigInt({input}))"),
+                }
+            }
+            CILNode::LdFieldAdress { addr, field } => {
+                let addr = self.node(addr, asm);
+                let field = asm[field];
+                format!("(({addr}) + {})", field_offset(field, asm))
+            }
+            CILNode::LdField { addr, field } => {
+                let addr = self.node(addr, asm);
+                let field = asm[field];
+                if struct_def(field.owner(), asm).is_some() {
+                    let addr = format!("({addr}) + {}", field_offset(field, asm));
+                    load(field.tpe(), &addr, asm)
+                } else {
+                    format!("({addr}).{}", escape_ident(&asm[field.name()]))
+                }
+            }
+            CILNode::LdInd { addr, tpe, .. } => {
+                let addr = self.node(addr, asm);
+                load(asm[tpe], &addr, asm)
+            }
+            CILNode::SizeOf(tpe) => format!("{}", size_align(asm[tpe], asm).0),
+            CILNode::GetException => "exc".into(),
+            CILNode::IsInst(obj, tpe) => {
+                let obj = self.node(obj, asm);
+                match asm[tpe] {
+                    Type::ClassRef(cref) if asm.class_ref_to_def(cref).is_some() => format!(
+                        "(({obj}) instanceof {})",
+                        escape_ident(&asm[asm[cref].name()])
+                    ),
+                    _ => "rt.unsupported(\"IsInst\")".into(),
+                }
+            }
+            CILNode::CheckedCast(obj, _) => self.node(obj, asm),
+            CILNode::CallI(info) => {
+                let (fn_ptr, _, args) = info.as_ref();
+                let fn_ptr = self.node(*fn_ptr, asm);
+                let args: Vec<String> = args.iter().map(|arg| self.node(*arg, asm)).collect();
+                format!("rt.fn_at({fn_ptr})({})", args.join(", "))
+            }
+            CILNode::LocAlloc { size } => {
+                format!("rt.stack_alloc(Number({}), 16)", self.node(size, asm))
+            }
+            CILNode::LdStaticField(sfld) => {
+                let sfld = asm[sfld];
+                let path = format!(
+                    "{}.{}",
+                    escape_ident(&asm[asm[sfld.owner()].name()]),
+                    escape_ident(&asm[sfld.name()])
+                );
+                if mem_suffix(sfld.tpe(), asm) == Some("obj") {
+                    path
+                } else {
+                    load(sfld.tpe(), &path, asm)
+                }
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                let sfld = asm[sfld];
+                format!(
+                    "{}.{}",
+                    escape_ident(&asm[asm[sfld.owner()].name()]),
+                    escape_ident(&asm[sfld.name()])
+                )
+            }
+            CILNode::LdFtn(method) => {
+                let (path, is_extern) = method_path(&asm[method], asm);
+                if is_extern {
+                    self.externs.insert(path.clone());
+                    format!("rt.fn_ptr(rt.{path})")
+                } else {
+                    format!("rt.fn_ptr({path})")
+                }
+            }
+            CILNode::LdTypeToken(tpe) => format!("{}", tpe.as_bimap_index()),
+            CILNode::LdLen(arr) => format!("({}).length", self.node(arr, asm)),
+            CILNode::LocAllocAlgined { tpe, align } => {
+                format!("rt.stack_alloc({}, {align})", size_align(asm[tpe], asm).0)
+            }
+            CILNode::LdElelemRef { array, index } => format!(
+                "({})[Number({})]",
+                self.node(array, asm),
+                self.node(index, asm)
+            ),
+            CILNode::UnboxAny { object, .. } => self.node(object, asm),
+        }
+    }
+    fn root(&mut self, idx: RootIdx, is_handler: bool, asm: &mut Assembly) -> String {
+        match asm[idx].clone() {
+            CILRoot::StLoc(loc, val) => {
+                let val = self.node(val, asm);
+                if self.mem_locals.contains(&loc) {
+                    store(
+                        asm[self.locals[loc as usize].1],
+                        &format!("L{loc}"),
+                        &val,
+                        asm,
+                    )
+                } else {
+                    format!("L{loc} = {val};")
+                }
+            }
+            CILRoot::StArg(arg, val) => {
+                let val = self.node(val, asm);
+                if self.mem_args.contains(&arg) {
+                    let tpe = asm[self.sig].inputs()[arg as usize];
+                    store(tpe, &format!("A{arg}"), &val, asm)
+                } else {
+                    format!("A{arg} = {val};")
+                }
+            }
+            CILRoot::Ret(val) => {
+                let val = self.node(val, asm);
+                let output = *asm[self.sig].output();
+                if mem_suffix(output, asm).is_none() {
+                    let (size, align) = size_align(output, asm);
+                    format!("return rt.ret_struct(fp, {val}, {size}, {align});")
+                } else {
+                    format!("return rt.leave(fp, {val});")
+                }
+            }
+            CILRoot::Pop(val) => format!("{};", self.node(val, asm)),
+            CILRoot::Throw(val) => format!("throw {};", self.node(val, asm)),
+            CILRoot::VoidRet => "rt.sp = fp; return;".into(),
+            CILRoot::Break => "debugger;".into(),
+            CILRoot::Nop => String::new(),
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = info.as_ref();
+                let goto = goto(&branch_label(*target, *sub_target, is_handler));
+                let cond = match cond {
+                    None => return goto,
+                    Some(BranchCond::True(cond)) => self.node(*cond, asm),
+                    Some(BranchCond::False(cond)) => format!("!({})", self.node(*cond, asm)),
+                    Some(BranchCond::Eq(lhs, rhs)) => {
+                        format!("({}) == ({})", self.node(*lhs, asm), self.node(*rhs, asm))
+                    }
+                    Some(BranchCond::Ne(lhs, rhs)) => {
+                        format!("({}) != ({})", self.node(*lhs, asm), self.node(*rhs, asm))
+                    }
+                    Some(
+                        BranchCond::Lt(lhs, rhs, kind)
+                        | BranchCond::Gt(lhs, rhs, kind)
+                        | BranchCond::Le(lhs, rhs, kind)
+                        | BranchCond::Ge(lhs, rhs, kind),
+                    ) => {
+                        let op = match cond.as_ref().unwrap() {
+                            BranchCond::Lt(..) => "<",
+                            BranchCond::Gt(..) => ">",
+                            BranchCond::Le(..) => "<=",
+                            _ => ">=",
+                        };
+                        let unsigned = matches!(kind, CmpKind::Unordered | CmpKind::Unsigned);
+                        self.compare(*lhs, *rhs, op, unsigned, asm)
+                    }
+                };
+                format!("if ({cond}) {goto}")
+            }
+            CILRoot::Switch(info) => {
+                let (value, int, targets) = info.as_ref();
+                // The cases are zero extended, so the value must be too.
+                let value = as_unsigned(*int, &self.node(*value, asm));
+                let suffix = if is_bigint(*int) { "n" } else { "" };
+                let cases: String = targets
+                    .cases()
+                    .into_iter()
+                    .map(|(case, (target, sub_target))| {
+                        let label = branch_label(target, sub_target, is_handler);
+                        format!("case {case}{suffix}: {}\n", goto(&label))
+                    })
+                    .collect();
+                let (target, sub_target) = targets.otherwise();
+                let otherwise = goto(&branch_label(target, sub_target, is_handler));
+                format!("switch ({value}) {{\n{cases}default: {otherwise}\n}}")
+            }
+            CILRoot::SourceFileInfo {
+                line_start, file, ..
+            } => format!("// {}:{line_start}", &asm[file]),
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = info.as_ref();
+                let addr = self.node(*addr, asm);
+                let val = self.node(*val, asm);
+                let field = asm[*field];
+                if struct_def(field.owner(), asm).is_some() {
+                    let addr = format!("({addr}) + {}", field_offset(field, asm));
+                    store(field.tpe(), &addr, &val, asm)
+                } else {
+                    format!("({addr}).{} = {val};", escape_ident(&asm[field.name()]))
+                }
+            }
+            CILRoot::Call(info) => {
+                let (method, args) = info.as_ref();
+                format!("{};", self.call(*method, args, asm))
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, _) = info.as_ref();
+                let addr = self.node(*addr, asm);
+                let val = self.node(*val, asm);
+                store(*tpe, &addr, &val, asm)
+            }
+            CILRoot::InitBlk(blk) => {
+                let (dst, val, count) = blk.as_ref();
+                format!(
+                    "rt.memset({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*val, asm),
+                    self.node(*count, asm)
+                )
+            }
+            CILRoot::CpBlk(blk) => {
+                let (dst, src, len) = blk.as_ref();
+                format!(
+                    "rt.memcpy({}, {}, {});",
+                    self.node(*dst, asm),
+                    self.node(*src, asm),
+                    self.node(*len, asm)
+                )
+            }
+            CILRoot::TailCall(_) | CILRoot::TailCallI(_) => {
+                let roots = asm[idx].clone().tail_call_as_ret(asm).unwrap();
+                roots
+                    .into_iter()
+                    .map(|root| {
+                        let root = asm.alloc_root(root);
+                        self.root(root, is_handler, asm)
+                    })
+                    .collect()
+            }
+            CILRoot::CallI(info) => {
+                let (fn_ptr, _, args) = info.as_ref();
+                let fn_ptr = self.node(*fn_ptr, asm);
+                let args: Vec<String> = args.iter().map(|arg| self.node(*arg, asm)).collect();
+                format!("rt.fn_at({fn_ptr})({});", args.join(", "))
+            }
+            CILRoot::ExitSpecialRegion { target, .. } => goto(&format!("bb{target}")),
+            CILRoot::ReThrow => "throw exc;".into(),
+            CILRoot::SetStaticField { field, val } => {
+                let field = asm[field];
+                let val = self.node(val, asm);
+                let path = format!(
+                    "{}.{}",
+                    escape_ident(&asm[asm[field.owner()].name()]),
+                    escape_ident(&asm[field.name()])
+                );
+                if mem_suffix(field.tpe(), asm) == Some("obj") {
+                    format!("{path} = {val};")
+                } else {
+                    store(field.tpe(), &path, &val, asm)
+                }
+            }
+            CILRoot::CpObj { src, dst, tpe } => format!(
+                "rt.memcpy({}, {}, {});",
+                self.node(dst, asm),
+                self.node(src, asm),
+                size_align(asm[tpe], asm).0
+            ),
+            CILRoot::Unreachable(msg) => format!(
+                "throw new Error({});",
+                js_string(&format!("Unreachable reached: {}", &asm[msg]))
+            ),
+        }
+    }
+}
+pub struct JsExporter {
+    is_lib: bool,
+}
+impl JsExporter {
+    #[must_use]
+    pub fn new(is_lib: bool) -> Self {
+        Self { is_lib }
+    }
+    fn export_method_def(
+        asm: &mut Assembly,
+        def: &MethodDef,
+        externs: &mut BTreeSet<String>,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let name = asm[def.name()].to_string();
+        let mname = escape_ident(&name);
+        let sig = def.sig();
+        let inputs = asm[sig].inputs().to_vec();
+        let args = (0..inputs.len())
+            .map(|arg| format!("A{arg}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match def.resolved_implementation(asm) {
+            MethodImpl::MethodBody { .. } => (),
+            MethodImpl::Extern { .. } => {
+                return writeln!(out, "static {mname} = rt.extern_fn({});", js_string(&name));
+            }
+            MethodImpl::Missing => {
+                return writeln!(out, "static {mname} = rt.missing({});", js_string(&name));
+            }
+            MethodImpl::AliasFor(_) => panic!("Impossible: unrechable reached."),
+        }
+        let locals: Vec<LocalDef> = def.iter_locals(asm).copied().collect();
+        let blocks = def.blocks(asm).unwrap().to_vec();
+        // Find all the locals and arguments whose address is taken.
+        let mut mem_locals: FxHashSet<u32> = FxHashSet::default();
+        let mut mem_args: FxHashSet<u32> = FxHashSet::default();
+        for root in blocks.iter().flat_map(super::BasicBlock::iter_roots) {
+            for elem in CILIter::new(asm[root].clone(), asm) {
+                match elem {
+                    CILIterElem::Node(CILNode::LdLocA(loc)) => {
+                        mem_locals.insert(loc);
+                    }
+                    CILIterElem::Node(CILNode::LdArgA(arg)) => {
+                        mem_args.insert(arg);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for (loc, (_, tpe)) in locals.iter().enumerate() {
+            if mem_suffix(asm[*tpe], asm).is_none() {
+                mem_locals.insert(loc as u32);
+            }
+        }
+        for (arg, tpe) in inputs.iter().enumerate() {
+            if mem_suffix(*tpe, asm).is_none() {
+                mem_args.insert(arg as u32);
+            }
+        }
+        writeln!(out, "static {mname}({args}) {{")?;
+        writeln!(out, "const fp = rt.sp;")?;
+        for (arg, tpe) in inputs.iter().enumerate() {
+            if !mem_args.contains(&(arg as u32)) {
+                continue;
+            }
+            let (size, align) = size_align(*tpe, asm);
+            match mem_suffix(*tpe, asm) {
+                // Valuetypes are passed by address, so the callee needs to make its own copy.
+                None => writeln!(out, "A{arg} = rt.stack_copy(A{arg}, {size}, {align});")?,
+                Some(_) => writeln!(
+                    out,
+                    "{{ const val = A{arg}; A{arg} = rt.stack_alloc({size}, {align}); {} }}",
+                    store(*tpe, &format!("A{arg}"), "val", asm)
+                )?,
+            }
+        }
+        for (loc, (_, tpe)) in locals.iter().enumerate() {
+            let tpe = asm[*tpe];
+            if mem_locals.contains(&(loc as u32)) {
+                let (size, align) = size_align(tpe, asm);
+                writeln!(out, "let L{loc} = rt.stack_alloc({size}, {align});")?;
+            } else {
+                writeln!(out, "let L{loc} = {};", zero(tpe))?;
+            }
+        }
+        let has_handlers = blocks.iter().any(|block| block.handler().is_some());
+        let Some(entry) = blocks.first() else {
+            return writeln!(out, "}}");
+        };
+        writeln!(out, "let bb = \"bb{}\";", entry.block_id())?;
+        if has_handlers {
+            writeln!(out, "let exc = null;")?;
+        }
+        writeln!(out, "for (;;) {{")?;
+        if has_handlers {
+            writeln!(out, "try {{")?;
+        }
+        writeln!(out, "switch (bb) {{")?;
+        let mut ctx = MethodCtx {
+            sig,
+            locals: &locals,
+            mem_locals,
+            mem_args,
+            externs,
+        };
+        for block in &blocks {
+            let label = format!("bb{}", block.block_id());
+            writeln!(out, "case \"{label}\":")?;
+            // Blocks may fall trough into each other, so the current block needs to be known for exception handling.
+            if has_handlers {
+                writeln!(out, "bb = \"{label}\";")?;
+            }
+            for root in block.roots() {
+                writeln!(out, "{}", ctx.root(*root, false, asm))?;
+            }
+            for hblock in block.handler().into_iter().flatten() {
+                let label = format!("h{}_{}", block.block_id(), hblock.block_id());
+                writeln!(out, "case \"{label}\":")?;
+                writeln!(out, "bb = \"{label}\";")?;
+                for root in hblock.roots() {
+                    writeln!(out, "{}", ctx.root(*root, true, asm))?;
+                }
+            }
+        }
+        writeln!(out, "}}")?;
+        writeln!(
+            out,
+            "throw new Error({});",
+            js_string(&format!("Control flow reached the end of {name}"))
+        )?;
+        if has_handlers {
+            writeln!(out, "}} catch (err) {{")?;
+            writeln!(out, "exc = err;")?;
+            writeln!(out, "switch (bb) {{")?;
+            for block in &blocks {
+                let Some(hblock) = block.handler().and_then(<[_]>::first) else {
+                    continue;
+                };
+                writeln!(
+                    out,
+                    "case \"bb{id}\": bb = \"h{id}_{}\"; continue;",
+                    hblock.block_id(),
+                    id = block.block_id()
+                )?;
+            }
+            writeln!(out, "default: throw err;")?;
+            writeln!(out, "}}")?;
+            writeln!(out, "}}")?;
+        }
+        writeln!(out, "}}")?;
+        writeln!(out, "}}")
+    }
+    fn export_class(
+        asm: &mut Assembly,
+        defid: ClassDefIdx,
+        externs: &mut BTreeSet<String>,
+        out: &mut impl Write,
+    ) -> std::io::Result<()> {
+        let class = asm[defid].clone();
+        let class_name = escape_ident(&asm[class.name()]);
+        let extends = match class.extends() {
+            Some(parent) if !class.is_valuetype() => match asm.class_ref_to_def(parent) {
+                Some(parent) => format!(" extends {}", escape_ident(&asm[asm[parent].name()])),
+                None if asm[asm[parent].name()].ends_with("Exception") => " extends Error".into(),
+                None => String::new(),
+            },
+            _ => String::new(),
+        };
+        writeln!(out, "class {class_name}{extends} {{")?;
+        if !class.is_valuetype() {
+            for (tpe, fname, _) in class.fields() {
+                writeln!(out, "{} = {};", escape_ident(&asm[*fname]), zero(*tpe))?;
+            }
+        }
+        for (tpe, fname, _) in class.static_fields() {
+            let fname = escape_ident(&asm[*fname]);
+            if mem_suffix(*tpe, asm) == Some("obj") {
+                writeln!(out, "static {fname} = {};", zero(*tpe))?;
+            } else {
+                let (size, align) = size_align(*tpe, asm);
+                writeln!(out, "static {fname} = rt.static_alloc({size}, {align});")?;
+            }
+        }
+        for method in class.methods() {
+            let def = asm[*method].clone();
+            Self::export_method_def(asm, &def, externs, out)?;
+            // Virtual calls are dispatched trough the prototype.
+            if def.kind() == MethodKind::Virtual {
+                let mname = escape_ident(&asm[def.name()]);
+                writeln!(
+                    out,
+                    "{mname}(...args) {{ return {class_name}.{mname}(this, ...args); }}"
+                )?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+    fn export_to_write(&self, asm: &Assembly, out: &mut impl Write) -> std::io::Result<()> {
+        let mut asm = asm.clone();
+        let mut externs = BTreeSet::new();
+        let mut classes = Vec::new();
+        // Classes need to be defined after the classes they extend.
+        let mut pending: Vec<ClassDefIdx> = asm.iter_class_def_ids().copied().collect();
+        pending.sort_by_key(|def| asm[asm[*def].name()].to_string());
+        let mut defined: FxHashSet<ClassDefIdx> = FxHashSet::default();
+        while !pending.is_empty() {
+            let (ready, delayed): (Vec<_>, Vec<_>) = pending.into_iter().partition(|def| {
+                asm[*def]
+                    .extends()
+                    .and_then(|parent| asm.class_ref_to_def(parent))
+                    .is_none_or(|parent| defined.contains(&parent))
+            });
+            assert!(!ready.is_empty(), "Cyclic class hierarchy");
+            for def in ready {
+                Self::export_class(&mut asm, def, &mut externs, &mut classes)?;
+                classes.push(b'\n');
+                defined.insert(def);
+            }
+            pending = delayed;
+        }
+        out.write_all(include_bytes!("js_header.js"))?;
+        out.write_all(b"\n")?;
+        out.write_all(&classes)?;
+        for ext in &externs {
+            writeln!(out, "rt.{ext} ??= rt.missing({});", js_string(ext))?;
+        }
+        writeln!(out, "MainModule._cctor?.();")?;
+        if self.is_lib {
+            let names = defined
+                .iter()
+                .map(|def| escape_ident(&asm[asm[*def].name()]))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                out,
+                "if (typeof module !== \"undefined\") module.exports = {{ rt, {names} }};"
+            )
+        } else {
+            writeln!(out, "MainModule.entrypoint(rt.args());")
+        }
+    }
+}
+impl Exporter for JsExporter {
+    type Error = std::io::Error;
+
+    fn export(&self, asm: &Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
+        let js_path = target.with_extension("js");
+        let mut js_out = std::io::BufWriter::new(std::fs::File::create(&js_path)?);
+        self.export_to_write(asm, &mut js_out)?;
+        js_out.flush()?;
+        drop(js_out);
+        // Check the syntax of the module, if a JS engine is available.
+        let mut cmd =
+            std::process::Command::new(std::env::var("JS_ENGINE").unwrap_or("node".to_owned()));
+        cmd.arg("--check").arg(&js_path);
+        if let Ok(out) = cmd.output() {
+            assert!(
+                out.status.success(),
+                "stdout:{} stderr:{} cmd:{cmd:?}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+        }
+        Ok(())
+    }
+}
+#[test]
+fn export_arithmetic() {
+    use super::{cilnode::MethodKind, Access, BasicBlock, ClassRef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let u64_tpe = asm.alloc_type(Type::Int(Int::U64));
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line_sig = asm.sig([Type::Int(Int::U64)], Type::Void);
+    let write_line = asm.alloc_methodref(MethodRef::new(
+        console,
+        write_line,
+        write_line_sig,
+        MethodKind::Static,
+        vec![].into(),
+    ));
+    let lhs = asm.alloc_node(Const::U64(u64::MAX));
+    let rhs = asm.alloc_node(Const::U64(43));
+    let product = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::Mul));
+    let store = asm.alloc_root(CILRoot::StLoc(0, product));
+    let local = asm.alloc_node(CILNode::LdLoc(0));
+    let call = asm.alloc_root(CILRoot::Call(Box::new((write_line, [local].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let name = asm.alloc_string("entrypoint");
+    let sig = asm.sig([], Type::Void);
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![store, call, ret], 0, None)],
+            locals: vec![(None, u64_tpe)],
+        },
+        vec![],
+    ));
+    asm.export("/tmp/js_export.exe", JsExporter::new(false));
+    // Only run the module if a JS engine is present.
+    if let Ok(out) = std::process::Command::new("node")
+        .arg("/tmp/js_export.js")
+        .output()
+    {
+        assert!(out.status.success(), "{out:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "18446744073709551573\n"
+        );
+    }
+}
+#[test]
+fn export_unsupported_op() {
+    use super::{cilnode::MethodKind, Access, BasicBlock};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let lhs = asm.alloc_node(Const::F64(super::hashable::HashableF64(1.0)));
+    let rhs = asm.alloc_node(Const::F64(super::hashable::HashableF64(2.0)));
+    let and = asm.alloc_node(CILNode::BinOp(lhs, rhs, BinOp::And));
+    let ret = asm.alloc_root(CILRoot::Ret(and));
+    let name = asm.alloc_string("float_and");
+    let sig = asm.sig([], Type::Float(Float::F64));
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    // Ops without a JS lowering throw at runtime, instead of failing the export.
+    asm.export("/tmp/js_unsupported.exe", JsExporter::new(false));
+    let module = std::fs::read_to_string("/tmp/js_unsupported.js").unwrap();
+    assert!(module.contains("rt.unsupported(\"And on Float(F64)\")"));
+}
@@ -0,0 +1,97 @@
+//! Layout of values stored in linear memory, shared by the exporters which don't target .NET(C, JS and Java).
+//! Fields without an explicit offset are laid out in order, each aligned to its own alignment.
+use super::{Assembly, ClassDef, ClassRefIdx, FieldDesc, Float, Int, Type};
+
+/// Returns the number of bits of `int`. Pointer-sized ints are 64 bit.
+#[must_use]
+pub fn int_bits(int: Int) -> u32 {
+    int.size().map_or(64, |size| u32::from(size) * 8)
+}
+/// Returns the definition of `cref`, if it is a valuetype stored in linear memory.
+#[must_use]
+pub fn struct_def(cref: ClassRefIdx, asm: &Assembly) -> Option<&ClassDef> {
+    if !asm[cref].is_valuetype() {
+        return None;
+    }
+    asm.class_ref_to_def(cref).map(|def| &asm[def])
+}
+/// Returns the field offsets, size and alignment of `def`.
+#[must_use]
+pub fn class_layout(def: &ClassDef, asm: &Assembly) -> (Vec<u32>, u32, u32) {
+    let mut offsets = Vec::with_capacity(def.fields().len());
+    let mut size = 0_u32;
+    let mut align = 1_u32;
+    for (tpe, _, offset) in def.fields() {
+        let (field_size, field_align) = size_align(*tpe, asm);
+        let offset = offset.unwrap_or_else(|| size.next_multiple_of(field_align));
+        size = size.max(offset + field_size);
+        align = align.max(field_align);
+        offsets.push(offset);
+    }
+    match def.explict_size() {
+        Some(explict_size) => (offsets, explict_size.get(), align),
+        None => (offsets, size.next_multiple_of(align), align),
+    }
+}
+/// Returns the size and alignment of `tpe` in linear memory.
+#[must_use]
+pub fn size_align(tpe: Type, asm: &Assembly) -> (u32, u32) {
+    match tpe {
+        Type::Int(int) => (int_bits(int) / 8, int_bits(int) / 8),
+        Type::Float(float) => match float {
+            Float::F16 => (2, 2),
+            Float::F32 => (4, 4),
+            Float::F64 => (8, 8),
+            Float::F128 => (16, 16),
+        },
+        Type::Bool => (1, 1),
+        Type::PlatformChar => (2, 2),
+        Type::Void => (0, 1),
+        Type::ClassRef(cref) => match struct_def(cref, asm) {
+            Some(def) => {
+                let (_, size, align) = class_layout(def, asm);
+                (size, align)
+            }
+            None => (8, 8),
+        },
+        _ => (8, 8),
+    }
+}
+/// Returns the offset of `field` within its owner.
+/// # Panics
+/// Panics if the owner of `field` is not a valuetype, or has no such field.
+#[must_use]
+pub fn field_offset(field: FieldDesc, asm: &Assembly) -> u32 {
+    let def = struct_def(field.owner(), asm).expect("Field owner is not a valuetype");
+    let idx = def
+        .fields()
+        .iter()
+        .position(|(_, name, _)| *name == field.name())
+        .unwrap_or_else(|| panic!("No field {} in {}", &asm[field.name()], &asm[def.name()]));
+    class_layout(def, asm).0[idx]
+}
+#[test]
+fn struct_layout() {
+    use super::Access;
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("Foo");
+    let fields = ["a", "b", "c"].map(|field| asm.alloc_string(field));
+    let def = asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![
+            (Type::Int(Int::U8), fields[0], None),
+            (Type::Int(Int::U32), fields[1], None),
+            (Type::Int(Int::U16), fields[2], None),
+        ],
+        vec![],
+        Access::Public,
+        None,
+    ));
+    assert_eq!(class_layout(&asm[def], &asm), (vec![0, 4, 8], 12, 4));
+    assert_eq!(size_align(Type::ClassRef(*def), &asm), (12, 4));
+    let field = FieldDesc::new(*def, fields[2], Type::Int(Int::U16));
+    assert_eq!(field_offset(field, &asm), 8);
+}
@@ -122,6 +122,7 @@ pub struct MethodDef {
     arg_names: Vec<Option<StringIdx>>,
     kind: MethodKind,
     implementation: MethodImpl,
+    doc: Option<StringIdx>,
 }
 
 impl MethodDef {
@@ -190,8 +191,17 @@ impl MethodDef {
             arg_names,
             kind,
             implementation,
+            doc: None,
         }
     }
+    /// The documentation of this method, used by exporters which emit source code or API descriptions.
+    #[must_use]
+    pub fn doc(&self) -> Option<StringIdx> {
+        self.doc
+    }
+    pub fn set_doc(&mut self, doc: Option<StringIdx>) {
+        self.doc = doc;
+    }
 
     #[must_use]
     pub fn class(&self) -> ClassDefIdx {
@@ -33,6 +33,7 @@ pub mod cillyir_exporter;
 pub mod cilnode;
 pub mod cilroot;
 pub mod class;
+pub mod cs_exporter;
 pub mod cst;
 pub mod field;
 pub mod float;
@@ -43,9 +44,13 @@ pub mod il_exporter;
 pub mod int;
 pub mod iter;
 pub mod java_exporter;
+pub mod js_exporter;
+pub mod layout;
 pub mod method;
 pub mod opt;
+pub mod pe_exporter;
 pub mod strings;
+pub mod text;
 pub mod tpe;
 pub mod typecheck;
 #[test]
@@ -1,10 +1,11 @@
+use fxhash::FxHashMap;
+
 use super::OptFuel;
 use crate::v2::{
-    cilnode::MethodKind, Assembly, CILIter, CILIterElem, CILNode, CILRoot, MethodDef, MethodImpl,
-    MethodRefIdx, NodeIdx, RootIdx,
+    cilnode::MethodKind, method::LocalDef, Assembly, BasicBlock, CILIter, CILIterElem, CILNode,
+    CILRoot, ClassDefIdx, Const, Float, Int, MethodDef, MethodDefIdx, MethodImpl, MethodRefIdx,
+    NodeIdx, RootIdx, Type, TypeIdx,
 };
-#[cfg(test)]
-use crate::v2::{BasicBlock, Const, Type};
 fn trivial_inline_block<'def, 'asm: 'def>(
     def: &'def MethodDef,
     asm: &'asm mut Assembly,
@@ -146,6 +147,528 @@ pub fn inline_trivial_call_root(
         None => CILRoot::Call(Box::new((calle, call_args.into()))),
     }
 }
+/// Callees at most this big are always inlined.
+const ALWAYS_INLINE_SIZE: usize = 16;
+/// Callees at most this big are inlined.
+const INLINE_SIZE: usize = 64;
+/// Each argument which is a constant, or the address of a local, makes inlining more profitable by this much.
+const ARG_BONUS: usize = 16;
+/// Callees with a single call site are removed after being inlined, so much bigger ones are worth inlining.
+const SINGLE_CALL_INLINE_SIZE: usize = 512;
+/// Methods are not grown past this size by inlining.
+const MAX_CALLER_SIZE: usize = 8192;
+/// The size of a method, in nodes and roots.
+fn method_size(def: &MethodDef, asm: &Assembly) -> usize {
+    def.iter_cil(asm).map_or(0, Iterator::count)
+}
+/// All the methods defined in this assembly, which `def` calls directly. A method called more than once is listed more than once.
+fn callees(def: &MethodDef, asm: &Assembly) -> Vec<MethodDefIdx> {
+    let Some(cil) = def.iter_cil(asm) else {
+        return vec![];
+    };
+    cil.filter_map(|elem| match elem {
+        CILIterElem::Node(CILNode::Call(info))
+        | CILIterElem::Root(CILRoot::Call(info) | CILRoot::TailCall(info)) => asm
+            .method_def_from_ref(info.0)
+            .map(|_| MethodDefIdx(info.0)),
+        _ => None,
+    })
+    .collect()
+}
+/// Splits the call graph into strongly connected components, using Tarjan's algorithm.
+/// Components are returned callees first: a component only calls methods from itself and from components before it.
+fn call_graph_sccs(graph: &FxHashMap<MethodDefIdx, Vec<MethodDefIdx>>) -> Vec<Vec<MethodDefIdx>> {
+    // The index and the lowlink of each visited method.
+    let mut info: FxHashMap<MethodDefIdx, (usize, usize)> = FxHashMap::default();
+    let mut stack = vec![];
+    let mut on_stack = fxhash::FxHashSet::default();
+    let mut sccs = vec![];
+    for root in graph.keys() {
+        if info.contains_key(root) {
+            continue;
+        }
+        // The methods currently visited, and the index of the next callee to visit.
+        let mut work = vec![(*root, 0)];
+        info.insert(*root, (info.len(), info.len()));
+        stack.push(*root);
+        on_stack.insert(*root);
+        while let Some((method, callee)) = work.last().copied() {
+            if let Some(callee) = graph[&method].get(callee).copied() {
+                work.last_mut().unwrap().1 += 1;
+                if !graph.contains_key(&callee) {
+                    continue;
+                }
+                match info.get(&callee) {
+                    None => {
+                        info.insert(callee, (info.len(), info.len()));
+                        stack.push(callee);
+                        on_stack.insert(callee);
+                        work.push((callee, 0));
+                    }
+                    Some((index, _)) if on_stack.contains(&callee) => {
+                        let index = *index;
+                        let lowlink = &mut info.get_mut(&method).unwrap().1;
+                        *lowlink = (*lowlink).min(index);
+                    }
+                    Some(_) => (),
+                }
+                continue;
+            }
+            work.pop();
+            let (index, lowlink) = info[&method];
+            if let Some((caller, _)) = work.last() {
+                let caller_lowlink = &mut info.get_mut(caller).unwrap().1;
+                *caller_lowlink = (*caller_lowlink).min(lowlink);
+            }
+            if index == lowlink {
+                let mut scc = vec![];
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(&member);
+                    scc.push(member);
+                    if member == method {
+                        break;
+                    }
+                }
+                sccs.push(scc);
+            }
+        }
+    }
+    sccs
+}
+/// Checks if the body of a method can be inlined into another one.
+fn can_inline_body(def: &MethodDef, asm: &Assembly) -> bool {
+    let MethodImpl::MethodBody { blocks, .. } = def.implementation() else {
+        return false;
+    };
+    if blocks.is_empty() {
+        return false;
+    }
+    // Memory from `localloc` is only freed on return, so inlining a call in a loop would leak stack space.
+    if def.iter_cil(asm).is_some_and(|mut cil| {
+        cil.any(|elem| {
+            matches!(
+                elem,
+                CILIterElem::Node(CILNode::LocAlloc { .. } | CILNode::LocAllocAlgined { .. })
+            )
+        })
+    }) {
+        return false;
+    }
+    // Returns become jumps, which can't leave protected regions or handlers.
+    blocks
+        .iter()
+        .filter(|block| block.handler().is_some())
+        .flat_map(BasicBlock::iter_roots)
+        .all(|root| {
+            !matches!(
+                asm[root],
+                CILRoot::Ret(_) | CILRoot::VoidRet | CILRoot::TailCall(_) | CILRoot::TailCallI(_)
+            )
+        })
+}
+/// What happens with the value returned by an inlined call.
+#[derive(Clone, Copy)]
+enum ReturnSink {
+    Discard,
+    Local(u32),
+    Return,
+}
+/// If `root` is a call to a method defined in this assembly, returns the callee, its arguments, and what is done with its result.
+fn call_site(root: &CILRoot, asm: &Assembly) -> Option<(MethodDefIdx, Box<[NodeIdx]>, ReturnSink)> {
+    let (info, sink) = match root {
+        CILRoot::Call(info) => (info.clone(), ReturnSink::Discard),
+        CILRoot::StLoc(loc, call) => match &asm[*call] {
+            CILNode::Call(info) => (info.clone(), ReturnSink::Local(*loc)),
+            _ => return None,
+        },
+        CILRoot::Pop(call) => match &asm[*call] {
+            CILNode::Call(info) => (info.clone(), ReturnSink::Discard),
+            _ => return None,
+        },
+        CILRoot::Ret(call) => match &asm[*call] {
+            CILNode::Call(info) => (info.clone(), ReturnSink::Return),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    asm.method_def_from_ref(info.0)?;
+    Some((MethodDefIdx(info.0), info.1, sink))
+}
+/// The call graph, and what is known about each method, used to decide if a call should be inlined.
+struct Inliner {
+    graph: FxHashMap<MethodDefIdx, Vec<MethodDefIdx>>,
+    scc: FxHashMap<MethodDefIdx, usize>,
+    call_sites: FxHashMap<MethodDefIdx, usize>,
+    sizes: FxHashMap<MethodDefIdx, usize>,
+    inlinable: FxHashMap<MethodDefIdx, bool>,
+}
+impl Inliner {
+    /// Decides if a call from `caller` (of size `caller_size`, defined in `class`) to `callee` should be inlined.
+    fn should_inline(
+        &mut self,
+        caller: MethodDefIdx,
+        caller_size: usize,
+        class: ClassDefIdx,
+        callee: MethodDefIdx,
+        args: &[NodeIdx],
+        asm: &Assembly,
+    ) -> bool {
+        // Recursive calls can't be inlined.
+        if self.scc.get(&caller) == self.scc.get(&callee) {
+            return false;
+        }
+        let def = &asm.method_defs()[&callee];
+        // The body of the callee may refer to private members of its class, so it can only be inlined in that class.
+        if def.kind() != MethodKind::Static
+            || def.class() != class
+            || asm[def.sig()].inputs().len() != args.len()
+        {
+            return false;
+        }
+        if !*self
+            .inlinable
+            .entry(callee)
+            .or_insert_with(|| can_inline_body(def, asm))
+        {
+            return false;
+        }
+        let size = self.sizes[&callee];
+        if caller_size + size > MAX_CALLER_SIZE {
+            return false;
+        }
+        // Constant arguments and addresses of locals can often be propagated into the inlined body.
+        let bonus = args
+            .iter()
+            .filter(|arg| matches!(asm[**arg], CILNode::Const(_) | CILNode::LdLocA(_)))
+            .count()
+            * ARG_BONUS;
+        let single_call = self.call_sites.get(&callee) == Some(&1) && !def.access().is_extern();
+        let threshold = if single_call {
+            SINGLE_CALL_INLINE_SIZE
+        } else {
+            INLINE_SIZE + bonus
+        };
+        size <= ALWAYS_INLINE_SIZE || size <= threshold
+    }
+    /// Updates the call graph after `callee` got inlined into `caller`.
+    fn inlined(&mut self, callee: MethodDefIdx) {
+        if let Some(sites) = self.call_sites.get_mut(&callee) {
+            *sites = sites.saturating_sub(1);
+        }
+        for callee in &self.graph[&callee] {
+            *self.call_sites.entry(*callee).or_default() += 1;
+        }
+    }
+}
+/// Block ids from this one up belong to the entry blocks of handlers: `u32::MAX`, and the terminate handler and the
+/// landing pads emitted by the codegen. Those are never jumped to, so they are neither shifted, nor used for new blocks.
+const FIRST_RESERVED_ID: u32 = u32::MAX - 2;
+/// Returns the highest block id of `blocks` and their handlers, ignoring the reserved ids.
+fn max_block_id(blocks: &[BasicBlock]) -> Option<u32> {
+    blocks
+        .iter()
+        .chain(
+            blocks
+                .iter()
+                .flat_map(|block| block.handler().unwrap_or(&[])),
+        )
+        .map(BasicBlock::block_id)
+        .filter(|id| *id < FIRST_RESERVED_ID)
+        .max()
+}
+/// Returns a root setting `local`, of type `tpe`, to zero. Locals are zeroed on method entry, but the locals of an
+/// inlined body have to be zeroed each time it is entered, since it may run more than once(eg. in a loop).
+fn zero_local(local: u32, tpe: TypeIdx, asm: &mut Assembly) -> RootIdx {
+    let zero = match asm[tpe] {
+        Type::Bool => Some(asm.alloc_node(Const::Bool(false))),
+        Type::Int(int) => match int {
+            Int::U8 => Some(Const::U8(0)),
+            Int::U16 => Some(Const::U16(0)),
+            Int::U32 => Some(Const::U32(0)),
+            Int::U64 => Some(Const::U64(0)),
+            Int::USize => Some(Const::USize(0)),
+            Int::I8 => Some(Const::I8(0)),
+            Int::I16 => Some(Const::I16(0)),
+            Int::I32 => Some(Const::I32(0)),
+            Int::I64 => Some(Const::I64(0)),
+            Int::ISize => Some(Const::ISize(0)),
+            Int::U128 | Int::I128 => None,
+        }
+        .map(|zero| asm.alloc_node(zero)),
+        Type::Float(float @ (Float::F32 | Float::F64)) => Some(float.zero(asm)),
+        _ => None,
+    };
+    let root = match zero {
+        Some(zero) => CILRoot::StLoc(local, zero),
+        // Other types are zeroed like `initobj` would.
+        None => {
+            let addr = asm.alloc_node(CILNode::LdLocA(local));
+            let val = asm.alloc_node(Const::U8(0));
+            let size = asm.alloc_node(CILNode::SizeOf(tpe));
+            CILRoot::InitBlk(Box::new((addr, val, size)))
+        }
+    };
+    asm.alloc_root(root)
+}
+/// Inlines the body of `callee` in place of the root `site` of `blocks[block]`.
+/// The roots following the call are moved to a new block, to which the inlined body jumps when returning.
+#[allow(clippy::too_many_arguments)]
+fn inline_call_site(
+    blocks: &mut Vec<BasicBlock>,
+    locals: &mut Vec<LocalDef>,
+    next_id: &mut u32,
+    block: usize,
+    site: usize,
+    callee: &MethodDef,
+    args: &[NodeIdx],
+    sink: ReturnSink,
+    asm: &mut Assembly,
+) -> Option<std::ops::Range<usize>> {
+    let MethodImpl::MethodBody {
+        blocks: callee_blocks,
+        locals: callee_locals,
+    } = callee.implementation()
+    else {
+        return None;
+    };
+    // Block ids of the callee are shifted past the ones of the caller, except for the reserved ones.
+    let callee_max_id = max_block_id(callee_blocks)?;
+    let offset = *next_id;
+    let continuation = offset.checked_add(callee_max_id)?.checked_add(1)?;
+    if continuation >= FIRST_RESERVED_ID {
+        return None;
+    }
+    *next_id = continuation + 1;
+    let map_id = |id: u32| {
+        if id >= FIRST_RESERVED_ID {
+            id
+        } else {
+            id + offset
+        }
+    };
+    let map_target = |(target, sub_target): (u32, u32)| {
+        (
+            map_id(target),
+            if sub_target == 0 {
+                0
+            } else {
+                map_id(sub_target)
+            },
+        )
+    };
+    // The arguments of the callee become locals of the caller, followed by the locals of the callee.
+    let arg_base = locals.len() as u32;
+    for tpe in asm[callee.sig()].inputs().to_vec() {
+        locals.push((None, asm.alloc_type(tpe)));
+    }
+    let local_base = locals.len() as u32;
+    locals.extend(callee_locals.iter().copied());
+    let map_root = |root: RootIdx, asm: &mut Assembly| -> Vec<RootIdx> {
+        let roots = asm[root]
+            .clone()
+            .tail_call_as_ret(asm)
+            .unwrap_or_else(|| vec![asm[root].clone()]);
+        roots
+            .into_iter()
+            .flat_map(|root| {
+                let root = root.map(
+                    asm,
+                    &mut |root, _| match root {
+                        CILRoot::StLoc(loc, val) => CILRoot::StLoc(loc + local_base, val),
+                        CILRoot::StArg(arg, val) => CILRoot::StLoc(arg + arg_base, val),
+                        CILRoot::Branch(info) => {
+                            let (target, sub_target) = map_target((info.0, info.1));
+                            CILRoot::Branch(Box::new((target, sub_target, info.2)))
+                        }
+                        CILRoot::Switch(info) => CILRoot::Switch(Box::new((
+                            info.0,
+                            info.1,
+                            info.2.map_targets(map_target),
+                        ))),
+                        CILRoot::ExitSpecialRegion { target, source } => {
+                            CILRoot::ExitSpecialRegion {
+                                target: map_id(target),
+                                source: map_id(source),
+                            }
+                        }
+                        _ => root,
+                    },
+                    &mut |node, _| match node {
+                        CILNode::LdLoc(loc) => CILNode::LdLoc(loc + local_base),
+                        CILNode::LdLocA(loc) => CILNode::LdLocA(loc + local_base),
+                        CILNode::LdArg(arg) => CILNode::LdLoc(arg + arg_base),
+                        CILNode::LdArgA(arg) => CILNode::LdLocA(arg + arg_base),
+                        _ => node,
+                    },
+                );
+                let jump = CILRoot::Branch(Box::new((continuation, 0, None)));
+                let roots = match (root, sink) {
+                    (CILRoot::Ret(val), ReturnSink::Discard) => vec![CILRoot::Pop(val), jump],
+                    (CILRoot::Ret(val), ReturnSink::Local(loc)) => {
+                        vec![CILRoot::StLoc(loc, val), jump]
+                    }
+                    (CILRoot::VoidRet, _) => vec![jump],
+                    (root, _) => vec![root],
+                };
+                roots
+                    .into_iter()
+                    .map(|root| asm.alloc_root(root))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    };
+    let inlined: Vec<_> = callee_blocks
+        .iter()
+        .map(|callee_block| {
+            let handler = callee_block.handler().map(|handler| {
+                handler
+                    .iter()
+                    .map(|hblock| {
+                        let roots = hblock.roots().iter().flat_map(|root| map_root(*root, asm));
+                        BasicBlock::new(roots.collect(), map_id(hblock.block_id()), None)
+                    })
+                    .collect()
+            });
+            let roots = callee_block
+                .roots()
+                .iter()
+                .flat_map(|root| map_root(*root, asm))
+                .collect();
+            BasicBlock::new(roots, map_id(callee_block.block_id()), handler)
+        })
+        .collect();
+    // Arguments are evaluated in order, before jumping to the inlined body.
+    let rest = blocks[block].roots_mut().split_off(site + 1);
+    blocks[block].roots_mut().pop();
+    for (arg, val) in args.iter().enumerate() {
+        let root = asm.alloc_root(CILRoot::StLoc(arg_base + arg as u32, *val));
+        blocks[block].roots_mut().push(root);
+    }
+    for (local, (_, tpe)) in callee_locals.iter().enumerate() {
+        let root = zero_local(local_base + local as u32, *tpe, asm);
+        blocks[block].roots_mut().push(root);
+    }
+    let entry = map_id(callee_blocks[0].block_id());
+    let jump = asm.alloc_root(CILRoot::Branch(Box::new((entry, 0, None))));
+    blocks[block].roots_mut().push(jump);
+    let start = blocks.len();
+    blocks.extend(inlined);
+    let end = blocks.len();
+    // The inlined body returns from the caller, so the roots after the call are never reached.
+    if !matches!(sink, ReturnSink::Return) {
+        blocks.push(BasicBlock::new(rest, continuation, None));
+    }
+    Some(start..end)
+}
+impl Assembly {
+    /// Inlines calls across the whole assembly. Methods are visited callees first, so the inlined bodies are already
+    /// inlined into. Calls are inlined if the callee is small, or if it is called only once, and removed as dead code afterwards.
+    pub fn inline(&mut self, fuel: &mut OptFuel) {
+        let graph: FxHashMap<MethodDefIdx, Vec<MethodDefIdx>> = self
+            .method_defs()
+            .iter()
+            .map(|(idx, def)| (*idx, callees(def, self)))
+            .collect();
+        let sccs = call_graph_sccs(&graph);
+        let mut inliner = Inliner {
+            scc: sccs
+                .iter()
+                .enumerate()
+                .flat_map(|(scc, methods)| methods.iter().map(move |method| (*method, scc)))
+                .collect(),
+            call_sites: FxHashMap::default(),
+            sizes: self
+                .method_defs()
+                .iter()
+                .map(|(idx, def)| (*idx, method_size(def, self)))
+                .collect(),
+            inlinable: FxHashMap::default(),
+            graph,
+        };
+        for callee in inliner.graph.values().flatten() {
+            *inliner.call_sites.entry(*callee).or_default() += 1;
+        }
+        for caller in sccs.into_iter().flatten() {
+            if fuel.exchausted() {
+                break;
+            }
+            let mut def = self.borrow_methoddef(caller);
+            if inline_calls(&mut def, caller, &mut inliner, self, fuel) {
+                inliner.sizes.insert(caller, method_size(&def, self));
+                inliner.graph.insert(caller, callees(&def, self));
+            }
+            self.return_methoddef(caller, def);
+        }
+    }
+}
+/// Inlines the calls in `def` chosen by `inliner`. Returns true if any call was inlined.
+fn inline_calls(
+    def: &mut MethodDef,
+    caller: MethodDefIdx,
+    inliner: &mut Inliner,
+    asm: &mut Assembly,
+    fuel: &mut OptFuel,
+) -> bool {
+    let class = def.class();
+    let mut size = inliner.sizes[&caller];
+    let MethodImpl::MethodBody { blocks, locals } = def.implementation_mut() else {
+        return false;
+    };
+    let Some(mut next_id) = max_block_id(blocks).and_then(|id| id.checked_add(1)) else {
+        return false;
+    };
+    let mut changed = false;
+    // Inlined bodies are not inlined into again: they were already inlined into, and this prevents unbounded inlining of recursive calls.
+    let mut inlined_blocks: Vec<std::ops::Range<usize>> = vec![];
+    let mut block = 0;
+    while block < blocks.len() {
+        // Jumping out of a protected region requires leaving it first, so calls in protected blocks can't be inlined.
+        if blocks[block].handler().is_some()
+            || inlined_blocks.iter().any(|range| range.contains(&block))
+        {
+            block += 1;
+            continue;
+        }
+        let site = blocks[block]
+            .roots()
+            .iter()
+            .enumerate()
+            .find_map(|(idx, root)| {
+                let (callee, args, sink) = call_site(&asm[*root], asm)?;
+                inliner
+                    .should_inline(caller, size, class, callee, &args, asm)
+                    .then_some((idx, callee, args, sink))
+            });
+        let Some((site, callee, args, sink)) = site else {
+            block += 1;
+            continue;
+        };
+        if !fuel.consume(8) {
+            break;
+        }
+        let callee_def = asm.method_defs()[&callee].clone();
+        match inline_call_site(
+            blocks,
+            locals,
+            &mut next_id,
+            block,
+            site,
+            &callee_def,
+            &args,
+            sink,
+            asm,
+        ) {
+            Some(range) => {
+                inlined_blocks.push(range);
+                inliner.inlined(callee);
+                size += inliner.sizes[&callee];
+                changed = true;
+            }
+            None => block += 1,
+        }
+    }
+    changed
+}
 #[test]
 fn test_inline() {
     let mut asm = Assembly::default();
@@ -190,3 +713,215 @@ fn test_inline() {
     )
     .is_some());
 }
+#[test]
+fn inline_multiblock() {
+    use crate::v2::{
+        cilroot::{BranchCond, CmpKind},
+        BinOp, Int,
+    };
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let i32_tpe = asm.alloc_type(Type::Int(Int::I32));
+    // fn callee(a: i32, b: i32) -> i32 { let sum = a + b; if sum < 0 { a = 0; return a; } sum }
+    let sig = asm.sig(
+        [Type::Int(Int::I32), Type::Int(Int::I32)],
+        Type::Int(Int::I32),
+    );
+    let name = asm.alloc_string("callee");
+    let callee = asm.alloc_methodref(crate::v2::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        [].into(),
+    ));
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let arg1 = asm.alloc_node(CILNode::LdArg(1));
+    let sum = asm.alloc_node(CILNode::BinOp(arg0, arg1, BinOp::Add));
+    let zero = asm.alloc_node(Const::I32(0));
+    let loc0 = asm.alloc_node(CILNode::LdLoc(0));
+    let roots = [
+        CILRoot::StLoc(0, sum),
+        CILRoot::Branch(Box::new((
+            2,
+            0,
+            Some(BranchCond::Lt(loc0, zero, CmpKind::Signed)),
+        ))),
+        CILRoot::Branch(Box::new((1, 0, None))),
+        CILRoot::Ret(loc0),
+        CILRoot::StArg(0, zero),
+        CILRoot::Ret(arg0),
+    ]
+    .map(|root| asm.alloc_root(root));
+    asm.new_method(MethodDef::new(
+        crate::v2::Access::Private,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(roots[..3].to_vec(), 0, None),
+                BasicBlock::new(vec![roots[3]], 1, None),
+                BasicBlock::new(roots[4..].to_vec(), 2, None),
+            ],
+            locals: vec![(None, i32_tpe)],
+        },
+        vec![None, None],
+    ));
+    // fn caller(x: i32) -> i32 { let res = callee(x, 1); res }
+    let sig = asm.sig([Type::Int(Int::I32)], Type::Int(Int::I32));
+    let name = asm.alloc_string("caller");
+    let one = asm.alloc_node(Const::I32(1));
+    let call = asm.alloc_node(CILNode::Call(Box::new((callee, [arg0, one].into()))));
+    let roots = [CILRoot::StLoc(0, call), CILRoot::Ret(loc0)].map(|root| asm.alloc_root(root));
+    let caller = asm.new_method(MethodDef::new(
+        crate::v2::Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(roots.to_vec(), 0, None)],
+            locals: vec![(None, i32_tpe)],
+        },
+        vec![None],
+    ));
+    // fn recursive(x: i32) -> i32 { recursive(x) }
+    let name = asm.alloc_string("recursive");
+    let recursive_ref = asm.alloc_methodref(crate::v2::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        [].into(),
+    ));
+    let call = asm.alloc_node(CILNode::Call(Box::new((recursive_ref, [arg0].into()))));
+    let ret = asm.alloc_root(CILRoot::Ret(call));
+    let recursive = asm.new_method(MethodDef::new(
+        crate::v2::Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        vec![None],
+    ));
+    asm.inline(&mut OptFuel::new(1000));
+    // The recursive call is left alone.
+    let MethodImpl::MethodBody { blocks, .. } = asm.method_defs()[&recursive].implementation()
+    else {
+        panic!("Expected a method body");
+    };
+    assert_eq!(blocks[0].roots(), [ret]);
+    let def = &asm.method_defs()[&caller];
+    assert!(callees(def, &asm).is_empty());
+    let MethodImpl::MethodBody { blocks, locals } = def.implementation() else {
+        panic!("Expected a method body");
+    };
+    // The arguments and the local of the callee are appended to the locals of the caller.
+    assert_eq!(locals.len(), 4);
+    // The caller jumps to the inlined blocks 1, 2 and 3, which return to the continuation block 4.
+    let ids: Vec<_> = blocks.iter().map(BasicBlock::block_id).collect();
+    assert_eq!(ids, [0, 1, 2, 3, 4]);
+    let roots = |block: usize| -> Vec<CILRoot> {
+        blocks[block]
+            .roots()
+            .iter()
+            .map(|root| asm[*root].clone())
+            .collect()
+    };
+    // The local of the callee is zeroed each time the inlined body is entered.
+    let roots_0 = roots(0);
+    assert_eq!(
+        roots_0[..2],
+        [CILRoot::StLoc(1, arg0), CILRoot::StLoc(2, one)]
+    );
+    assert!(
+        matches!(roots_0[2], CILRoot::StLoc(3, val) if asm[val] == CILNode::Const(Box::new(Const::I32(0))))
+    );
+    assert_eq!(roots_0[3], CILRoot::Branch(Box::new((1, 0, None))));
+    assert!(matches!(&roots(1)[1], CILRoot::Branch(info) if info.0 == 3));
+    let roots_3 = roots(3);
+    assert_eq!(roots_3[0], CILRoot::StLoc(1, zero));
+    assert!(matches!(roots_3[1], CILRoot::StLoc(0, val) if asm[val] == CILNode::LdLoc(1)));
+    assert_eq!(roots_3[2], CILRoot::Branch(Box::new((4, 0, None))));
+    assert_eq!(roots(4), [CILRoot::Ret(loc0)]);
+}
+#[test]
+fn inline_reserved_ids() {
+    use crate::v2::Int;
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let sig = asm.sig([], Type::Void);
+    let name = asm.alloc_string("callee");
+    let callee = asm.alloc_methodref(crate::v2::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        [].into(),
+    ));
+    let void_ret = asm.alloc_root(CILRoot::VoidRet);
+    let rethrow = asm.alloc_root(CILRoot::ReThrow);
+    // The callee has a block protected by a handler with a reserved id, and a local.
+    let u128_tpe = asm.alloc_type(Type::Int(Int::U128));
+    let jump = asm.alloc_root(CILRoot::Branch(Box::new((1, 0, None))));
+    let handler = BasicBlock::new(vec![rethrow], FIRST_RESERVED_ID, None);
+    asm.new_method(MethodDef::new(
+        crate::v2::Access::Private,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(vec![jump], 0, Some(vec![handler.clone()])),
+                BasicBlock::new(vec![void_ret], 1, None),
+            ],
+            locals: vec![(None, u128_tpe)],
+        },
+        vec![],
+    ));
+    // The caller has a block protected by the terminate handler, and calls the callee in another one.
+    let call = asm.alloc_root(CILRoot::Call(Box::new((callee, [].into()))));
+    let terminate = BasicBlock::new(vec![rethrow], u32::MAX - 1, None);
+    let name = asm.alloc_string("caller");
+    let caller = asm.new_method(MethodDef::new(
+        crate::v2::Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![
+                BasicBlock::new(vec![jump], 0, Some(vec![terminate])),
+                BasicBlock::new(vec![call, void_ret], 1, None),
+            ],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    asm.inline(&mut OptFuel::new(1000));
+    let def = &asm.method_defs()[&caller];
+    assert!(callees(def, &asm).is_empty());
+    let MethodImpl::MethodBody { blocks, .. } = def.implementation() else {
+        panic!("Expected a method body");
+    };
+    // New blocks get ids right after the ones of the caller, while the reserved ids are kept.
+    let ids: Vec<_> = blocks.iter().map(BasicBlock::block_id).collect();
+    assert_eq!(ids, [0, 1, 2, 3, 4]);
+    assert_eq!(
+        blocks[2].handler().unwrap()[0].block_id(),
+        FIRST_RESERVED_ID
+    );
+    // A local which is not a primitive is zeroed through its address.
+    let roots = blocks[1].roots();
+    assert!(
+        matches!(asm[roots[0]], CILRoot::InitBlk(ref info) if asm[info.0] == CILNode::LdLocA(0))
+    );
+    assert_eq!(asm[roots[1]], CILRoot::Branch(Box::new((2, 0, None))));
+}
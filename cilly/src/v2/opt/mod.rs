@@ -4,10 +4,14 @@ use inline::inline_trivial_call_root;
 use super::Float;
 
 use super::{
-    cilroot::BranchCond, method::LocalDef, typecheck::display_typecheck_err, BasicBlock, BinOp,
-    CILIter, CILIterElem, CILNode, CILRoot, Const, Int, MethodImpl, NodeIdx, RootIdx, Type,
+    cilroot::{BranchCond, SwitchTargets},
+    method::LocalDef,
+    typecheck::display_typecheck_err,
+    BasicBlock, BinOp, CILIter, CILIterElem, CILNode, CILRoot, Const, Int, MethodImpl, NodeIdx,
+    RootIdx, Type,
 };
 use crate::v2::{Assembly, MethodDef};
+use fxhash::{FxHashMap, FxHashSet};
 pub use opt_fuel::OptFuel;
 pub use side_effect::*;
 mod inline;
@@ -15,6 +19,8 @@ mod opt_fuel;
 mod opt_node;
 mod side_effect;
 mod simplify_handlers;
+mod sroa;
+mod ssa;
 mod test;
 pub fn opt_if_fuel<T>(new: T, original: T, fuel: &mut OptFuel) -> T {
     if fuel.consume(1) {
@@ -290,7 +296,12 @@ impl BasicBlock {
                             break 'm;
                         }
                         let mut tmp_root = asm.get_root(*root).clone();
-
+                        // A sparse switch compares its value once per case, so the value is kept in a local.
+                        if let CILRoot::Switch(info) = &tmp_root {
+                            if matches!(info.2, SwitchTargets::Sparse { .. }) {
+                                break 'm;
+                            }
+                        }
                         for node in tmp_root.nodes_mut() {
                             // If this node has side effects, do not bother attempting propagation.
                             // Break out of the loop to prevent further propagation.
@@ -431,6 +442,72 @@ impl MethodImpl {
         }
         self.remove_nops(asm);
     }
+    /// Simplifies switches. Switches on constants, or with only one target, become jumps. Cases jumping to blocks which
+    /// are unreachable jump to `otherwise` instead, and jumps to blocks which only jump somewhere else skip those blocks.
+    pub fn simplify_switches(&mut self, asm: &mut Assembly, fuel: &mut OptFuel) {
+        let MethodImpl::MethodBody { blocks, .. } = self else {
+            return;
+        };
+        let main_blocks = || blocks.iter().filter(|block| block.handler().is_none());
+        let unreachable: FxHashSet<u32> = main_blocks()
+            .filter(|block| {
+                block
+                    .meaningfull_roots(asm)
+                    .next()
+                    .is_some_and(|root| matches!(asm.get_root(root), CILRoot::Unreachable(_)))
+            })
+            .map(BasicBlock::block_id)
+            .collect();
+        let jumps: FxHashMap<u32, u32> = main_blocks()
+            .filter_map(|block| match block.is_direct_jump(asm)? {
+                (target, 0) if target != block.block_id() => Some((block.block_id(), target)),
+                _ => None,
+            })
+            .collect();
+        let thread = |(target, sub_target): (u32, u32)| match jumps.get(&target) {
+            Some(target) if sub_target == 0 => (*target, 0),
+            _ => (target, sub_target),
+        };
+        for block in blocks.iter_mut() {
+            for root in block.roots_mut() {
+                let CILRoot::Switch(info) = asm.get_root(*root) else {
+                    continue;
+                };
+                let (value, int, targets) = info.as_ref();
+                let (value, int) = (*value, *int);
+                let constant = match asm.get_node(value) {
+                    CILNode::Const(cst) => ssa::int_const(cst),
+                    _ => None,
+                };
+                let otherwise = thread(targets.otherwise());
+                let cases = targets
+                    .cases()
+                    .into_iter()
+                    .map(|(case, target)| match thread(target) {
+                        (target, 0) if unreachable.contains(&target) => (case, otherwise),
+                        target => (case, target),
+                    })
+                    .collect();
+                let new_targets = SwitchTargets::new(cases, otherwise);
+                let jump = constant
+                    .map(|(bits, _)| new_targets.target_of(bits))
+                    .or_else(|| new_targets.single_target());
+                // The value of a switch has no side effects, so it can be just removed.
+                let new = match jump {
+                    Some((target, sub_target)) => {
+                        CILRoot::Branch(Box::new((target, sub_target, None)))
+                    }
+                    None if new_targets != *targets => {
+                        CILRoot::Switch(Box::new((value, int, new_targets)))
+                    }
+                    None => continue,
+                };
+                if fuel.consume(1) {
+                    *root = asm.alloc_root(new);
+                }
+            }
+        }
+    }
     pub fn remove_nops(&mut self, asm: &mut Assembly) {
         // Optimization only suported for methods with locals
         let MethodImpl::MethodBody { blocks, .. } = self else {
@@ -490,6 +567,62 @@ impl MethodDef {
             })
         }
     }
+    /// Turns self-recursive tail calls into loops: the arguments are overwritten, and the method jumps back to its entry block.
+    pub fn tail_calls_to_loops(&mut self, asm: &mut Assembly, fuel: &mut OptFuel) {
+        // Memory from `localloc` is only freed on return, so each iteration of the loop would leak some stack space.
+        if self.iter_cil(asm).is_some_and(|mut cil| {
+            cil.any(|elem| {
+                matches!(
+                    elem,
+                    CILIterElem::Node(CILNode::LocAlloc { .. } | CILNode::LocAllocAlgined { .. })
+                )
+            })
+        }) {
+            return;
+        }
+        let this = asm.alloc_methodref(self.ref_to());
+        let inputs = asm[self.sig()].inputs().to_vec();
+        let MethodImpl::MethodBody { blocks, locals } = self.implementation_mut() else {
+            return;
+        };
+        let Some(entry) = blocks.first().map(BasicBlock::block_id) else {
+            return;
+        };
+        for block in blocks.iter_mut() {
+            // Jumping out of a protected block requires leaving it first.
+            if block.handler().is_some() {
+                continue;
+            }
+            let mut roots = Vec::with_capacity(block.roots().len());
+            for root in block.roots() {
+                let CILRoot::TailCall(info) = asm[*root].clone() else {
+                    roots.push(*root);
+                    continue;
+                };
+                if info.0 != this || !fuel.consume(5) {
+                    roots.push(*root);
+                    continue;
+                }
+                // The new arguments may depend on the old ones, so they are all computed before any argument is set.
+                let tmps: Vec<u32> = inputs
+                    .iter()
+                    .map(|tpe| {
+                        locals.push((None, asm.alloc_type(*tpe)));
+                        (locals.len() - 1) as u32
+                    })
+                    .collect();
+                for (tmp, arg) in tmps.iter().zip(info.1.iter()) {
+                    roots.push(asm.alloc_root(CILRoot::StLoc(*tmp, *arg)));
+                }
+                for (arg, tmp) in tmps.iter().enumerate() {
+                    let tmp = asm.alloc_node(CILNode::LdLoc(*tmp));
+                    roots.push(asm.alloc_root(CILRoot::StArg(arg as u32, tmp)));
+                }
+                roots.push(asm.alloc_root(CILRoot::Branch(Box::new((entry, 0, None)))));
+            }
+            *block.roots_mut() = roots;
+        }
+    }
     pub fn optimize(
         &mut self,
         asm: &mut Assembly,
@@ -497,9 +630,13 @@ impl MethodDef {
         fuel: &mut OptFuel,
     ) {
         let nop = asm.alloc_root(CILRoot::Nop);
+        self.tail_calls_to_loops(asm, fuel);
+        self.implementation_mut().split_locals(asm, fuel);
         self.implementation_mut().propagate_locals(asm, cache, fuel);
         self.implementation_mut()
             .remove_dead_writes(asm, cache, fuel);
+        self.implementation_mut().ssa_opt(asm, cache, fuel);
+        self.implementation_mut().simplify_switches(asm, fuel);
         if fuel.consume(1) {
             self.implementation_mut().realloc_locals(asm);
         }
@@ -1071,6 +1208,13 @@ fn local_prop() {
     let sum_arg0 = asm.alloc_node(sum_arg0);
     let ret = asm.alloc_root(CILRoot::Ret(sum_arg0));
     assert_eq!(ret, *opt_ret);
+    // The value of a sparse switch is read once per case, so it stays in its local.
+    let loc0 = asm.alloc_node(CILNode::LdLoc(0));
+    let targets = SwitchTargets::new(vec![(0, (1, 0)), (1000, (2, 0))], (3, 0));
+    let switch = asm.alloc_root(CILRoot::Switch(Box::new((loc0, Int::ISize, targets))));
+    let mut block = BasicBlock::new(vec![stloc_0, switch], 0, None);
+    block.local_opt(&mut asm, &[(None, isize_tpe)], &mut cache, &mut fuel);
+    assert_eq!(block.roots(), [stloc_0, switch]);
 }
 #[test]
 fn remove_nops() {
@@ -1092,3 +1236,84 @@ fn remove_nops() {
     mimpl.remove_nops(&mut asm);
     assert_eq!(mimpl.blocks_mut().unwrap()[0].roots().len(), 2);
 }
+#[test]
+fn simplify_switches() {
+    let mut asm = Assembly::default();
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let targets = SwitchTargets::new(vec![(0, (1, 0)), (1, (2, 0)), (2, (3, 0))], (4, 0));
+    let switch = asm.alloc_root(CILRoot::Switch(Box::new((arg0, Int::U8, targets))));
+    let unreachable = asm.alloc_string("unreachable");
+    let unreachable = asm.alloc_root(CILRoot::Unreachable(unreachable));
+    let jump = asm.alloc_root(CILRoot::Branch(Box::new((4, 0, None))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let mut mimpl = MethodImpl::MethodBody {
+        blocks: vec![
+            BasicBlock::new(vec![switch], 0, None),
+            BasicBlock::new(vec![unreachable], 1, None),
+            BasicBlock::new(vec![jump], 2, None),
+            BasicBlock::new(vec![ret], 3, None),
+            BasicBlock::new(vec![ret], 4, None),
+        ],
+        locals: vec![],
+    };
+    mimpl.simplify_switches(&mut asm, &mut OptFuel::new(1000));
+    // The unreachable block is never jumped to, and the block 2 is skipped.
+    let root = mimpl.blocks_mut().unwrap()[0].roots()[0];
+    let CILRoot::Switch(info) = asm.get_root(root) else {
+        panic!("Expected a switch");
+    };
+    assert_eq!(info.2.cases(), [(2, (3, 0))]);
+    assert_eq!(info.2.otherwise(), (4, 0));
+    // A switch on a constant is just a jump.
+    let targets = info.2.clone();
+    let two = asm.alloc_node(Const::U8(2));
+    let root = asm.alloc_root(CILRoot::Switch(Box::new((two, Int::U8, targets))));
+    mimpl.blocks_mut().unwrap()[0] = BasicBlock::new(vec![root], 0, None);
+    mimpl.simplify_switches(&mut asm, &mut OptFuel::new(1000));
+    let root = mimpl.blocks_mut().unwrap()[0].roots()[0];
+    assert_eq!(asm.get_root(root), &CILRoot::Branch(Box::new((3, 0, None))));
+}
+#[test]
+fn tail_calls_to_loops() {
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let name = asm.alloc_string("countdown");
+    let sig = asm.sig([Type::Int(Int::U32)], Type::Int(Int::U32));
+    let this = asm.alloc_methodref(super::MethodRef::new(
+        *main_module,
+        name,
+        sig,
+        super::cilnode::MethodKind::Static,
+        [].into(),
+    ));
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let one = asm.alloc_node(Const::U32(1));
+    let sub = asm.alloc_node(CILNode::BinOp(arg0, one, BinOp::Sub));
+    let tail_call = asm.alloc_root(CILRoot::TailCall(Box::new((this, [sub].into()))));
+    let mut def = MethodDef::new(
+        super::Access::Extern,
+        main_module,
+        name,
+        sig,
+        super::cilnode::MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![tail_call], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    );
+    def.tail_calls_to_loops(&mut asm, &mut OptFuel::new(1000));
+    let MethodImpl::MethodBody { blocks, locals } = def.implementation() else {
+        panic!("Expected a method body");
+    };
+    // The new argument is computed into a local, stored, and the method jumps back to its start.
+    assert_eq!(locals.len(), 1);
+    let roots: Vec<_> = blocks[0]
+        .roots()
+        .iter()
+        .map(|root| asm.get_root(*root).clone())
+        .collect();
+    assert_eq!(roots[0], CILRoot::StLoc(0, sub));
+    assert!(matches!(roots[1], CILRoot::StArg(0, _)));
+    assert_eq!(roots[2], CILRoot::Branch(Box::new((0, 0, None))));
+}
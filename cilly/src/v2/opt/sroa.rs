@@ -0,0 +1,239 @@
+use fxhash::FxHashMap;
+
+use super::OptFuel;
+use crate::v2::{
+    Assembly, CILIter, CILIterElem, CILNode, CILRoot, FieldIdx, Float, MethodImpl, Type,
+};
+
+/// Returns an upper bound on the size of `tpe`, or `None` if it is not known.
+fn max_size(tpe: Type, asm: &Assembly) -> Option<u32> {
+    match tpe {
+        // The pointer size is not known here, so 8 bytes are assumed. Overestimating a size is always safe.
+        Type::Int(int) => Some(int.size().map_or(8, u32::from)),
+        Type::Float(Float::F16) | Type::PlatformChar => Some(2),
+        Type::Float(Float::F32) => Some(4),
+        Type::Float(Float::F64) => Some(8),
+        Type::Float(Float::F128) => Some(16),
+        Type::Bool => Some(1),
+        Type::Ptr(_)
+        | Type::Ref(_)
+        | Type::FnPtr(_)
+        | Type::PlatformString
+        | Type::PlatformObject
+        | Type::PlatformArray { .. } => Some(8),
+        Type::ClassRef(cref) => asm.class_defs()[&asm.class_ref_to_def(cref)?]
+            .explict_size()
+            .map(std::num::NonZeroU32::get),
+        Type::PlatformGeneric(_, _) | Type::Void => None,
+    }
+}
+/// Checks if the fields of `tpe` in `fields` can be stored in separate locals.
+/// This is the case if `tpe` is a valuetype, and none of those fields overlap.
+fn can_split(tpe: Type, fields: &[FieldIdx], asm: &Assembly) -> bool {
+    let Type::ClassRef(cref) = tpe else {
+        return false;
+    };
+    let Some(def) = asm.class_ref_to_def(cref) else {
+        return false;
+    };
+    let def = &asm.class_defs()[&def];
+    if !def.is_valuetype() {
+        return false;
+    }
+    let mut ranges = Vec::with_capacity(fields.len());
+    for field in fields {
+        let field = asm[*field];
+        if field.owner() != cref || field.tpe() == Type::Void {
+            return false;
+        }
+        let Some((_, _, offset)) = def
+            .fields()
+            .iter()
+            .find(|(tpe, name, _)| *tpe == field.tpe() && *name == field.name())
+        else {
+            return false;
+        };
+        // Without an explicit offset, a field never overlaps with other fields.
+        let Some(offset) = offset else {
+            continue;
+        };
+        let Some(size) = max_size(field.tpe(), asm) else {
+            return false;
+        };
+        ranges.push((*offset, offset + size));
+    }
+    ranges.sort_unstable();
+    ranges.windows(2).all(|pair| pair[0].1 <= pair[1].0)
+}
+impl MethodImpl {
+    /// Scalar replacement of aggregates: splits valuetype locals, which are only accessed field by field, into one local per field.
+    /// A local whose address escapes, even as the address of one of its fields, is never split: that pointer could be offset or cast
+    /// to reach the rest of the struct.
+    pub fn split_locals(&mut self, asm: &mut Assembly, fuel: &mut OptFuel) {
+        let MethodImpl::MethodBody { blocks, locals } = self else {
+            return;
+        };
+        // How many times the address of each local is taken, how many of those are field accesses, and which fields are accessed.
+        let mut address_of = vec![0_usize; locals.len()];
+        let mut field_accesses = vec![0_usize; locals.len()];
+        let mut fields: Vec<Vec<FieldIdx>> = vec![vec![]; locals.len()];
+        // Locals which are read or written as a whole can't be split.
+        let mut escapes = vec![false; locals.len()];
+        let mut field_access = |addr, field, asm: &Assembly| {
+            let CILNode::LdLocA(loc) = asm[addr] else {
+                return;
+            };
+            field_accesses[loc as usize] += 1;
+            let fields = &mut fields[loc as usize];
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        };
+        for elem in blocks
+            .iter()
+            .flat_map(super::super::basic_block::BasicBlock::iter_roots)
+            .flat_map(|root| CILIter::new(asm[root].clone(), asm))
+        {
+            match elem {
+                CILIterElem::Node(CILNode::LdLocA(loc)) => address_of[loc as usize] += 1,
+                CILIterElem::Node(CILNode::LdLoc(loc))
+                | CILIterElem::Root(CILRoot::StLoc(loc, _)) => {
+                    escapes[loc as usize] = true;
+                }
+                CILIterElem::Node(CILNode::LdField { addr, field }) => {
+                    field_access(addr, field, asm);
+                }
+                CILIterElem::Root(CILRoot::SetField(info)) => field_access(info.1, info.0, asm),
+                _ => (),
+            }
+        }
+        // Maps each accessed field of a split local to its new local.
+        let mut split: FxHashMap<(u32, FieldIdx), u32> = FxHashMap::default();
+        for loc in 0..locals.len() {
+            if escapes[loc]
+                || address_of[loc] == 0
+                || address_of[loc] != field_accesses[loc]
+                || !can_split(asm[locals[loc].1], &fields[loc], asm)
+                || !fuel.consume(fields[loc].len() as u32)
+            {
+                continue;
+            }
+            for field in &fields[loc] {
+                let tpe = asm.alloc_type(asm[*field].tpe());
+                locals.push((None, tpe));
+                split.insert((loc as u32, *field), (locals.len() - 1) as u32);
+            }
+        }
+        if split.is_empty() {
+            return;
+        }
+        let new_local = |addr, field, asm: &Assembly| match asm[addr] {
+            CILNode::LdLocA(loc) => split.get(&(loc, field)).copied(),
+            _ => None,
+        };
+        for block in blocks.iter_mut() {
+            block.map_roots(
+                asm,
+                &mut |root, asm| match root {
+                    CILRoot::SetField(info) => match new_local(info.1, info.0, asm) {
+                        Some(loc) => CILRoot::StLoc(loc, info.2),
+                        None => CILRoot::SetField(info),
+                    },
+                    _ => root,
+                },
+                &mut |node, asm| match node {
+                    CILNode::LdField { addr, field } => match new_local(addr, field, asm) {
+                        Some(loc) => CILNode::LdLoc(loc),
+                        None => node,
+                    },
+                    _ => node,
+                },
+            );
+        }
+    }
+}
+#[test]
+fn split_locals() {
+    use crate::v2::{BasicBlock, ClassDef, FieldDesc, Int};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("Pair");
+    let first = asm.alloc_string("first");
+    let second = asm.alloc_string("second");
+    let whole = asm.alloc_string("whole");
+    let usize_tpe = Type::Int(Int::USize);
+    let u128_tpe = Type::Int(Int::U128);
+    let pair = asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![
+            (usize_tpe, first, Some(0)),
+            (usize_tpe, second, Some(8)),
+            (u128_tpe, whole, Some(0)),
+        ],
+        vec![],
+        crate::v2::Access::Public,
+        std::num::NonZeroU32::new(16),
+    ));
+    let first = asm.alloc_field(FieldDesc::new(*pair, first, usize_tpe));
+    let second = asm.alloc_field(FieldDesc::new(*pair, second, usize_tpe));
+    let whole = asm.alloc_field(FieldDesc::new(*pair, whole, u128_tpe));
+    // pair.first = arg0; pair.second = arg1; return pair.first + pair.second;
+    let addr = asm.alloc_node(CILNode::LdLocA(0));
+    let arg0 = asm.alloc_node(CILNode::LdArg(0));
+    let arg1 = asm.alloc_node(CILNode::LdArg(1));
+    let set_first = asm.alloc_root(CILRoot::SetField(Box::new((first, addr, arg0))));
+    let set_second = asm.alloc_root(CILRoot::SetField(Box::new((second, addr, arg1))));
+    let ld_first = asm.alloc_node(CILNode::LdField { addr, field: first });
+    let ld_second = asm.alloc_node(CILNode::LdField {
+        addr,
+        field: second,
+    });
+    let sum = asm.alloc_node(CILNode::BinOp(ld_first, ld_second, crate::v2::BinOp::Add));
+    let ret = asm.alloc_root(CILRoot::Ret(sum));
+    let pair_tpe = asm.alloc_type(Type::ClassRef(*pair));
+    let mut mimpl = MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(vec![set_first, set_second, ret], 0, None)],
+        locals: vec![(None, pair_tpe)],
+    };
+    mimpl.split_locals(&mut asm, &mut OptFuel::new(1000));
+    let MethodImpl::MethodBody { blocks, locals } = &mimpl else {
+        panic!("Expected a method body");
+    };
+    assert_eq!(locals.len(), 3);
+    let roots: Vec<_> = blocks[0]
+        .roots()
+        .iter()
+        .map(|root| asm[*root].clone())
+        .collect();
+    assert_eq!(roots[0], CILRoot::StLoc(1, arg0));
+    assert_eq!(roots[1], CILRoot::StLoc(2, arg1));
+    let CILRoot::Ret(sum) = roots[2] else {
+        panic!("Expected a return");
+    };
+    let CILNode::BinOp(lhs, rhs, _) = asm[sum] else {
+        panic!("Expected an addition");
+    };
+    assert_eq!(asm[lhs], CILNode::LdLoc(1));
+    assert_eq!(asm[rhs], CILNode::LdLoc(2));
+    // Overlapping fields, like the ones of an enum, can't be split.
+    assert!(!can_split(Type::ClassRef(*pair), &[first, whole], &asm));
+    // *(&mut pair.first as *mut usize) = arg1; return pair.first;
+    let field_addr = asm.alloc_node(CILNode::LdFieldAdress { addr, field: first });
+    let write = asm.alloc_root(CILRoot::StInd(Box::new((
+        field_addr, arg1, usize_tpe, false,
+    ))));
+    let ret = asm.alloc_root(CILRoot::Ret(ld_first));
+    let mut mimpl = MethodImpl::MethodBody {
+        blocks: vec![BasicBlock::new(vec![set_first, write, ret], 0, None)],
+        locals: vec![(None, pair_tpe)],
+    };
+    mimpl.split_locals(&mut asm, &mut OptFuel::new(1000));
+    let MethodImpl::MethodBody { blocks, locals } = &mimpl else {
+        panic!("Expected a method body");
+    };
+    // The address of a field escapes, so the local is left alone.
+    assert_eq!(locals.len(), 1);
+    assert_eq!(blocks[0].roots(), [set_first, write, ret]);
+}
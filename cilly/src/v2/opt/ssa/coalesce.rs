@@ -0,0 +1,205 @@
+use fxhash::FxHashSet;
+
+use super::Cfg;
+use crate::v2::{
+    method::LocalDef, opt::OptFuel, Assembly, BasicBlock, CILIter, CILIterElem, CILNode, CILRoot,
+};
+/// A set of locals, stored as a bitset.
+#[derive(Clone, PartialEq, Eq)]
+struct LocalSet(Vec<u64>);
+impl LocalSet {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+    fn insert(&mut self, loc: u32) {
+        self.0[loc as usize / 64] |= 1 << (loc % 64);
+    }
+    fn remove(&mut self, loc: u32) {
+        self.0[loc as usize / 64] &= !(1 << (loc % 64));
+    }
+    fn contains(&self, loc: u32) -> bool {
+        self.0[loc as usize / 64] & (1 << (loc % 64)) != 0
+    }
+    fn union(&mut self, other: &Self) {
+        self.0.iter_mut().zip(&other.0).for_each(|(a, b)| *a |= b);
+    }
+    fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().enumerate().flat_map(|(idx, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| (idx * 64 + bit) as u32)
+        })
+    }
+}
+/// How a root accesses locals.
+struct Access {
+    reads: Vec<u32>,
+    write: Option<u32>,
+    /// Set if this root copies one local into another.
+    copy_of: Option<u32>,
+}
+/// Merges locals which are copied into each other, or are paired in `affinities`, if they are never alive at the same time.
+/// Copies between merged locals are then removed. Locals whose address is taken, or which may be read before being written, are never merged.
+pub(crate) fn coalesce_locals(
+    blocks: &mut [BasicBlock],
+    locals: &[LocalDef],
+    affinities: &[(u32, u32)],
+    asm: &mut Assembly,
+    fuel: &mut OptFuel,
+) {
+    let Some(cfg) = Cfg::new(blocks, asm) else {
+        return;
+    };
+    let mut address_taken = vec![false; locals.len()];
+    let accesses: Vec<Vec<Access>> = blocks
+        .iter()
+        .map(|block| {
+            block
+                .roots()
+                .iter()
+                .map(|root| {
+                    let mut reads = Vec::new();
+                    for node in CILIter::new(asm.get_root(*root).clone(), asm)
+                        .filter_map(CILIterElem::as_node)
+                    {
+                        match node {
+                            CILNode::LdLoc(loc) if !reads.contains(&loc) => reads.push(loc),
+                            CILNode::LdLocA(loc) => address_taken[loc as usize] = true,
+                            _ => (),
+                        }
+                    }
+                    let (write, copy_of) = match asm.get_root(*root) {
+                        CILRoot::StLoc(loc, tree) => match asm.get_node(*tree) {
+                            CILNode::LdLoc(src) => (Some(*loc), Some(*src)),
+                            _ => (Some(*loc), None),
+                        },
+                        _ => (None, None),
+                    };
+                    Access {
+                        reads,
+                        write,
+                        copy_of,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    // Compute which locals are alive at the start and end of each block.
+    let empty = LocalSet::new(locals.len());
+    let mut gen = vec![empty.clone(); blocks.len()];
+    let mut kill = vec![empty.clone(); blocks.len()];
+    for (block, accesses) in accesses.iter().enumerate() {
+        for access in accesses {
+            for read in &access.reads {
+                if !kill[block].contains(*read) {
+                    gen[block].insert(*read);
+                }
+            }
+            if let Some(write) = access.write {
+                kill[block].insert(write);
+            }
+        }
+    }
+    let mut live_in = vec![empty.clone(); blocks.len()];
+    let mut live_out = vec![empty.clone(); blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in cfg.rpo().iter().rev() {
+            let mut out = empty.clone();
+            for succ in cfg.succs(*block) {
+                out.union(&live_in[*succ]);
+            }
+            let mut new_in = out.clone();
+            for (word, kill) in new_in.0.iter_mut().zip(&kill[*block].0) {
+                *word &= !kill;
+            }
+            new_in.union(&gen[*block]);
+            live_out[*block] = out;
+            if new_in != live_in[*block] {
+                live_in[*block] = new_in;
+                changed = true;
+            }
+        }
+    }
+    // Two locals interfere if one is written while the other is alive.
+    let mut interference: Vec<FxHashSet<u32>> = vec![FxHashSet::default(); locals.len()];
+    for block in cfg.rpo() {
+        let mut live = live_out[*block].clone();
+        for access in accesses[*block].iter().rev() {
+            if let Some(write) = access.write {
+                for loc in live.iter() {
+                    if loc != write && Some(loc) != access.copy_of {
+                        interference[write as usize].insert(loc);
+                        interference[loc as usize].insert(write);
+                    }
+                }
+                live.remove(write);
+            }
+            for read in &access.reads {
+                live.insert(*read);
+            }
+        }
+    }
+    let can_merge = |loc: u32| !address_taken[loc as usize] && !live_in[0].contains(loc);
+    let mut parent: Vec<u32> = (0..u32::try_from(locals.len()).unwrap()).collect();
+    let copies = accesses
+        .iter()
+        .flatten()
+        .filter_map(|access| Some((access.write?, access.copy_of?)));
+    let mut merged = false;
+    for (a, b) in copies.chain(affinities.iter().copied()) {
+        let (a, b) = (find(&parent, a), find(&parent, b));
+        if a == b
+            || !can_merge(a)
+            || !can_merge(b)
+            || locals[a as usize].1 != locals[b as usize].1
+            || interference[a as usize].contains(&b)
+        {
+            continue;
+        }
+        if !fuel.consume(1) {
+            break;
+        }
+        parent[b as usize] = a;
+        merged = true;
+        for other in std::mem::take(&mut interference[b as usize]) {
+            interference[other as usize].remove(&b);
+            interference[other as usize].insert(a);
+            interference[a as usize].insert(other);
+        }
+    }
+    if !merged {
+        return;
+    }
+    let rename: Vec<u32> = (0..parent.len())
+        .map(|loc| find(&parent, u32::try_from(loc).unwrap()))
+        .collect();
+    for block in blocks.iter_mut() {
+        block.map_roots(
+            asm,
+            &mut |root, asm| match root {
+                CILRoot::StLoc(loc, tree) => {
+                    let loc = rename[loc as usize];
+                    // Copies between merged locals do nothing.
+                    if *asm.get_node(tree) == CILNode::LdLoc(loc) {
+                        CILRoot::Nop
+                    } else {
+                        CILRoot::StLoc(loc, tree)
+                    }
+                }
+                root => root,
+            },
+            &mut |node, _| match node {
+                CILNode::LdLoc(loc) => CILNode::LdLoc(rename[loc as usize]),
+                node => node,
+            },
+        );
+    }
+}
+fn find(parent: &[u32], mut loc: u32) -> u32 {
+    while parent[loc as usize] != loc {
+        loc = parent[loc as usize];
+    }
+    loc
+}
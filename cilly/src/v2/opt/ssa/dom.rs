@@ -0,0 +1,192 @@
+use fxhash::FxHashMap;
+
+use crate::v2::{Assembly, BasicBlock, CILRoot, RootIdx};
+/// The control flow graph of a method without exception handlers. Blocks are referred to by their index, not their id.
+pub(crate) struct Cfg {
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+    /// Blocks reachable from the entry block, in reverse postorder.
+    rpo: Vec<usize>,
+    /// Position of each block in `rpo`, or `usize::MAX` if it is unreachable.
+    rpo_idx: Vec<usize>,
+}
+impl Cfg {
+    /// Builds the CFG of `blocks`. Returns `None` if the method contains jumps this analysis does not understand.
+    pub fn new(blocks: &[BasicBlock], asm: &Assembly) -> Option<Self> {
+        let ids: Vec<u32> = blocks.iter().map(BasicBlock::block_id).collect();
+        let roots: Vec<&[RootIdx]> = blocks.iter().map(BasicBlock::roots).collect();
+        Self::from_roots(&ids, &roots, asm)
+    }
+    /// Builds the CFG of blocks with ids `ids`, containing `roots`.
+    pub fn from_roots(
+        ids: &[u32],
+        roots: &[impl AsRef<[RootIdx]>],
+        asm: &Assembly,
+    ) -> Option<Self> {
+        if ids.is_empty() {
+            return None;
+        }
+        let id_map: FxHashMap<u32, usize> =
+            ids.iter().enumerate().map(|(idx, id)| (*id, idx)).collect();
+        let mut succs = vec![Vec::new(); ids.len()];
+        let mut preds = vec![Vec::new(); ids.len()];
+        for (block, roots) in roots.iter().enumerate() {
+            for root in roots.as_ref() {
+                let root = asm.get_root(*root);
+                if let CILRoot::ExitSpecialRegion { .. } = root {
+                    return None;
+                }
+                for (target, sub_target) in root.targets() {
+                    // Jumps into handlers are not supported.
+                    if sub_target != 0 {
+                        return None;
+                    }
+                    let target = *id_map.get(&target)?;
+                    if !succs[block].contains(&target) {
+                        succs[block].push(target);
+                        preds[target].push(block);
+                    }
+                }
+            }
+        }
+        // Iterative DFS, computing the postorder.
+        let mut postorder = Vec::with_capacity(ids.len());
+        let mut visited = vec![false; ids.len()];
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            if let Some(succ) = succs[*block].get(*next).copied() {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+        let rpo: Vec<usize> = postorder.into_iter().rev().collect();
+        let mut rpo_idx = vec![usize::MAX; ids.len()];
+        for (idx, block) in rpo.iter().enumerate() {
+            rpo_idx[*block] = idx;
+        }
+        Some(Self {
+            succs,
+            preds,
+            rpo,
+            rpo_idx,
+        })
+    }
+    /// The blocks `block` may jump to.
+    pub fn succs(&self, block: usize) -> &[usize] {
+        &self.succs[block]
+    }
+    /// The blocks which may jump to `block`.
+    pub fn preds(&self, block: usize) -> &[usize] {
+        &self.preds[block]
+    }
+    /// Reachable blocks, in reverse postorder.
+    pub fn rpo(&self) -> &[usize] {
+        &self.rpo
+    }
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.rpo_idx[block] != usize::MAX
+    }
+    /// The number of blocks, including unreachable ones.
+    pub fn len(&self) -> usize {
+        self.succs.len()
+    }
+}
+/// The dominator tree of a [`Cfg`], computed using the Cooper-Harvey-Kennedy algorithm.
+pub(crate) struct DomTree {
+    /// The immediate dominator of each block. The entry block is its own immediate dominator, and unreachable blocks have none.
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+impl DomTree {
+    pub fn new(cfg: &Cfg) -> Self {
+        let mut idom: Vec<Option<usize>> = vec![None; cfg.len()];
+        idom[0] = Some(0);
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while cfg.rpo_idx[a] > cfg.rpo_idx[b] {
+                    a = idom[a].unwrap();
+                }
+                while cfg.rpo_idx[b] > cfg.rpo_idx[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in cfg.rpo().iter().skip(1) {
+                let mut new_idom = None;
+                for pred in cfg.preds(*block) {
+                    if idom[*pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(curr) => intersect(&idom, *pred, curr),
+                    });
+                }
+                if new_idom != idom[*block] {
+                    idom[*block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let mut children = vec![Vec::new(); cfg.len()];
+        for block in cfg.rpo().iter().skip(1) {
+            children[idom[*block].unwrap()].push(*block);
+        }
+        Self { idom, children }
+    }
+    /// The immediate dominator of `block`. Returns `None` for the entry block and unreachable blocks.
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        self.idom[block].filter(|idom| *idom != block)
+    }
+    /// Blocks immediately dominated by `block`.
+    pub fn children(&self, block: usize) -> &[usize] {
+        &self.children[block]
+    }
+    /// Checks if `a` dominates `b`. Every block dominates itself.
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+    /// Computes the dominance frontier of each block.
+    pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<usize>> {
+        let mut frontiers = vec![Vec::new(); cfg.len()];
+        for block in cfg.rpo() {
+            let preds = cfg.preds(*block);
+            if preds.len() < 2 {
+                continue;
+            }
+            let idom = self.idom[*block];
+            for pred in preds {
+                let mut runner = Some(*pred);
+                while let Some(curr) = runner {
+                    if Some(curr) == idom || !cfg.is_reachable(curr) {
+                        break;
+                    }
+                    if !frontiers[curr].contains(block) {
+                        frontiers[curr].push(*block);
+                    }
+                    runner = self.idom(curr);
+                }
+            }
+        }
+        frontiers
+    }
+}
@@ -0,0 +1,153 @@
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{resolve, SsaForm, Visit};
+use crate::v2::{
+    method::LocalDef, opt::OptFuel, Assembly, CILIter, CILIterElem, CILNode, CILRoot, NodeIdx,
+    TypeIdx,
+};
+/// Identifies a computation: two values with the same key are always equal.
+#[derive(Hash, PartialEq, Eq, Clone)]
+enum Key {
+    /// A phi in a block, and its arguments, ordered by predecessor.
+    Phi(usize, Vec<usize>, TypeIdx),
+    /// A pure expression, and the values of the locals it reads.
+    Expr(NodeIdx, Vec<(u32, usize)>, TypeIdx),
+}
+impl SsaForm {
+    /// Global value numbering. Walks the dominator tree, replacing values which were already computed by a dominating definition.
+    /// This also propagates copies, and removes phis which always have the same value.
+    pub(crate) fn gvn(&mut self, locals: &[LocalDef], asm: &mut Assembly, fuel: &mut OptFuel) {
+        // Arguments which are written to can't be numbered.
+        let mut mutable_args = FxHashSet::default();
+        for root in self.roots.iter().flatten() {
+            if let CILRoot::StArg(arg, _) = asm.get_root(*root) {
+                mutable_args.insert(*arg);
+            }
+            for node in
+                CILIter::new(asm.get_root(*root).clone(), asm).filter_map(CILIterElem::as_node)
+            {
+                if let CILNode::LdArgA(arg) = node {
+                    mutable_args.insert(arg);
+                }
+            }
+        }
+        let nop = asm.alloc_root(CILRoot::Nop);
+        let mut table: FxHashMap<Key, usize> = FxHashMap::default();
+        let mut visits = vec![Visit::Enter(0)];
+        while let Some(visit) = visits.pop() {
+            let block = match visit {
+                Visit::Enter(block) => block,
+                Visit::Exit(inserted) => {
+                    for key in inserted {
+                        table.remove(&key);
+                    }
+                    continue;
+                }
+            };
+            let mut inserted = Vec::new();
+            for phi in &self.phis[block] {
+                let value = phi.value;
+                let tpe = self.value_type(value, locals);
+                let mut args: Vec<(usize, usize)> = phi
+                    .args
+                    .iter()
+                    .map(|(pred, arg)| (*pred, resolve(&self.forward, *arg)))
+                    .collect();
+                args.sort_unstable();
+                // A phi whose arguments are all the same value(or the phi itself) is just that value.
+                let mut distinct = args.iter().map(|(_, arg)| *arg).filter(|arg| *arg != value);
+                if let Some(first) = distinct.next() {
+                    if distinct.all(|arg| arg == first)
+                        && self.value_type(first, locals) == tpe
+                        && fuel.consume(1)
+                    {
+                        self.forward[value] = first;
+                        continue;
+                    }
+                }
+                let key = Key::Phi(block, args.into_iter().map(|(_, arg)| arg).collect(), tpe);
+                match table.get(&key) {
+                    Some(leader) => {
+                        if fuel.consume(1) {
+                            self.forward[value] = *leader;
+                        }
+                    }
+                    None => {
+                        table.insert(key.clone(), value);
+                        inserted.push(key);
+                    }
+                }
+            }
+            for idx in 0..self.roots[block].len() {
+                for (_, value) in &mut self.uses[block][idx] {
+                    *value = resolve(&self.forward, *value);
+                }
+                let Some(value) = self.defs[block][idx] else {
+                    continue;
+                };
+                let CILRoot::StLoc(_, tree) = asm.get_root(self.roots[block][idx]) else {
+                    continue;
+                };
+                let tree = *tree;
+                let tpe = self.value_type(value, locals);
+                let leader = match asm.get_node(tree) {
+                    CILNode::LdLoc(src) if self.tracked[*src as usize] => self.uses[block][idx]
+                        .iter()
+                        .find(|(loc, _)| loc == src)
+                        .map(|(_, value)| *value),
+                    _ if self.is_pure(tree, &mutable_args, asm) => {
+                        let mut uses = self.uses[block][idx].clone();
+                        uses.sort_unstable();
+                        let key = Key::Expr(tree, uses, tpe);
+                        if let Some(leader) = table.get(&key) {
+                            Some(*leader)
+                        } else {
+                            table.insert(key.clone(), value);
+                            inserted.push(key);
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                let Some(leader) = leader else {
+                    continue;
+                };
+                debug_assert!(self.dom.dominates(self.def_block(leader), block));
+                if self.value_type(leader, locals) == tpe && fuel.consume(1) {
+                    self.forward[value] = leader;
+                    self.roots[block][idx] = nop;
+                    self.defs[block][idx] = None;
+                    self.uses[block][idx].clear();
+                }
+            }
+            visits.push(Visit::Exit(inserted));
+            visits.extend(
+                self.dom
+                    .children(block)
+                    .iter()
+                    .map(|child| Visit::Enter(*child)),
+            );
+        }
+    }
+    /// Checks if `node` always evaluates to the same value, given the same values of locals.
+    fn is_pure(&self, node: NodeIdx, mutable_args: &FxHashSet<u32>, asm: &Assembly) -> bool {
+        match asm.get_node(node) {
+            CILNode::Const(_)
+            | CILNode::SizeOf(_)
+            | CILNode::LdFtn(_)
+            | CILNode::LdTypeToken(_) => true,
+            CILNode::LdLoc(loc) => self.tracked[*loc as usize],
+            CILNode::LdArg(arg) => !mutable_args.contains(arg),
+            CILNode::BinOp(lhs, rhs, _) => {
+                self.is_pure(*lhs, mutable_args, asm) && self.is_pure(*rhs, mutable_args, asm)
+            }
+            CILNode::UnOp(input, _)
+            | CILNode::IntCast { input, .. }
+            | CILNode::FloatCast { input, .. }
+            | CILNode::RefToPtr(input)
+            | CILNode::PtrCast(input, _)
+            | CILNode::LdFieldAdress { addr: input, .. } => self.is_pure(*input, mutable_args, asm),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,853 @@
+//! SSA construction and destruction for method bodies, and the optimizations built on top of it.
+//!
+//! Instead of rewriting the method into a new IR, the SSA form is kept on the side: each root remembers which definition of a local it reads,
+//! and phis are stored per block. Locals whose address is taken are not tracked, and are left as-is.
+use fxhash::{FxHashMap, FxHashSet};
+
+use super::{OptFuel, SideEffectInfoCache};
+use crate::v2::{
+    method::LocalDef, Assembly, BasicBlock, CILIter, CILIterElem, CILNode, CILRoot, MethodImpl,
+    RootIdx, TypeIdx,
+};
+use coalesce::coalesce_locals;
+pub(crate) use dom::{Cfg, DomTree};
+pub(crate) use sccp::int_const;
+
+mod coalesce;
+mod dom;
+mod gvn;
+mod sccp;
+/// Methods with more SSA values than this are not optimized, to keep compile times reasonable.
+const MAX_VALUES: usize = 1 << 14;
+/// Where an SSA value is defined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ValueDef {
+    /// The value a local has when the method is entered.
+    Entry,
+    /// A phi at the start of a block.
+    Phi(usize),
+    /// The `StLoc` root at index `.1` of block `.0`.
+    Root(usize, usize),
+}
+/// The value of `local` at the start of a block, which depends on the block we came from.
+pub(crate) struct Phi {
+    pub local: u32,
+    pub value: usize,
+    /// The predecessor block, and the value `local` had at its end.
+    pub args: Vec<(usize, usize)>,
+}
+/// A method body in SSA form.
+pub(crate) struct SsaForm {
+    pub dom: DomTree,
+    block_ids: Vec<u32>,
+    id_map: FxHashMap<u32, usize>,
+    pub roots: Vec<Vec<RootIdx>>,
+    /// The local each value belongs to, and where it is defined.
+    pub values: Vec<(u32, ValueDef)>,
+    pub phis: Vec<Vec<Phi>>,
+    /// For each root, the values of the tracked locals it reads.
+    pub uses: Vec<Vec<Vec<(u32, usize)>>>,
+    /// For each root, the value it defines, if any.
+    pub defs: Vec<Vec<Option<usize>>>,
+    pub tracked: Vec<bool>,
+    /// Values which were replaced by some other value.
+    forward: Vec<usize>,
+}
+/// The result of converting a method out of SSA form.
+struct Destructed {
+    /// Pairs of locals which hold different values of the same original local.
+    affinities: Vec<(u32, u32)>,
+    /// Blocks inserted to hold the copies on critical edges.
+    edge_blocks: Vec<u32>,
+}
+impl SsaForm {
+    /// Converts `blocks` into SSA form. Returns `None` if the method uses features this analysis does not support.
+    pub fn new(blocks: &[BasicBlock], locals: &[LocalDef], asm: &Assembly) -> Option<Self> {
+        if blocks.iter().any(|block| block.handler().is_some()) {
+            return None;
+        }
+        let cfg = Cfg::new(blocks, asm)?;
+        // There would be no place to put the phis of an entry block with predecessors.
+        if !cfg.preds(0).is_empty() {
+            return None;
+        }
+        let dom = DomTree::new(&cfg);
+        let roots: Vec<Vec<RootIdx>> = blocks.iter().map(|block| block.roots().to_vec()).collect();
+        let mut tracked = vec![true; locals.len()];
+        let mut reads: Vec<Vec<Vec<u32>>> = Vec::with_capacity(roots.len());
+        let mut def_count = 0;
+        for block_roots in &roots {
+            let mut terminated = false;
+            let mut block_reads = Vec::with_capacity(block_roots.len());
+            for root in block_roots {
+                let mut root_reads = Vec::new();
+                for node in
+                    CILIter::new(asm.get_root(*root).clone(), asm).filter_map(CILIterElem::as_node)
+                {
+                    match node {
+                        CILNode::LdLoc(loc) if !root_reads.contains(&loc) => root_reads.push(loc),
+                        CILNode::LdLocA(loc) => tracked[loc as usize] = false,
+                        _ => (),
+                    }
+                }
+                block_reads.push(root_reads);
+                match asm.get_root(*root) {
+                    // If a local was set after the first jump, its value at the end of the block would depend on the branch taken.
+                    CILRoot::StLoc(..) if terminated => return None,
+                    CILRoot::StLoc(..) => def_count += 1,
+                    root if is_exit(root) => terminated = true,
+                    _ => (),
+                }
+            }
+            reads.push(block_reads);
+        }
+        if def_count > MAX_VALUES {
+            return None;
+        }
+        // Blocks must not fall trough into the next block.
+        if cfg
+            .rpo()
+            .iter()
+            .any(|block| !ends_with_terminator(&roots[*block], asm))
+        {
+            return None;
+        }
+        // Only locals live across blocks need phis("semi-pruned" SSA).
+        let mut global = vec![false; locals.len()];
+        let mut def_blocks: Vec<Vec<usize>> = vec![Vec::new(); locals.len()];
+        for block in cfg.rpo() {
+            let mut killed = FxHashSet::default();
+            for (root, root_reads) in roots[*block].iter().zip(&reads[*block]) {
+                for loc in root_reads {
+                    if !killed.contains(loc) {
+                        global[*loc as usize] = true;
+                    }
+                }
+                if let CILRoot::StLoc(loc, _) = asm.get_root(*root) {
+                    killed.insert(*loc);
+                    if def_blocks[*loc as usize].last() != Some(block) {
+                        def_blocks[*loc as usize].push(*block);
+                    }
+                }
+            }
+        }
+        // Place phis in the iterated dominance frontier of the definitions.
+        let frontiers = dom.frontiers(&cfg);
+        let mut phis: Vec<Vec<Phi>> = (0..roots.len()).map(|_| Vec::new()).collect();
+        let mut values = Vec::new();
+        let mut has_phi_for = vec![usize::MAX; roots.len()];
+        for (loc, def_blocks) in def_blocks.iter().enumerate() {
+            if !tracked[loc] || !global[loc] {
+                continue;
+            }
+            let mut worklist = def_blocks.clone();
+            while let Some(block) = worklist.pop() {
+                for frontier in &frontiers[block] {
+                    if has_phi_for[*frontier] == loc {
+                        continue;
+                    }
+                    has_phi_for[*frontier] = loc;
+                    phis[*frontier].push(Phi {
+                        local: loc as u32,
+                        value: values.len(),
+                        args: Vec::new(),
+                    });
+                    values.push((loc as u32, ValueDef::Phi(*frontier)));
+                    worklist.push(*frontier);
+                }
+            }
+        }
+        // Rename the locals, walking the dominator tree.
+        let mut uses: Vec<Vec<Vec<(u32, usize)>>> = roots
+            .iter()
+            .map(|roots| vec![Vec::new(); roots.len()])
+            .collect();
+        let mut defs: Vec<Vec<Option<usize>>> =
+            roots.iter().map(|roots| vec![None; roots.len()]).collect();
+        let mut entry_values = vec![None; locals.len()];
+        let mut stacks: Vec<Vec<usize>> = vec![Vec::new(); locals.len()];
+        let mut visits = vec![Visit::Enter(0)];
+        while let Some(visit) = visits.pop() {
+            let block = match visit {
+                Visit::Enter(block) => block,
+                Visit::Exit(pushed) => {
+                    for loc in pushed {
+                        stacks[loc as usize].pop();
+                    }
+                    continue;
+                }
+            };
+            let mut pushed = Vec::new();
+            for phi in &phis[block] {
+                stacks[phi.local as usize].push(phi.value);
+                pushed.push(phi.local);
+            }
+            for (idx, root) in roots[block].iter().enumerate() {
+                uses[block][idx] = reads[block][idx]
+                    .iter()
+                    .filter(|loc| tracked[**loc as usize])
+                    .map(|loc| {
+                        (
+                            *loc,
+                            current_value(*loc, &stacks, &mut entry_values, &mut values),
+                        )
+                    })
+                    .collect();
+                if let CILRoot::StLoc(loc, _) = asm.get_root(*root) {
+                    if tracked[*loc as usize] {
+                        let value = values.len();
+                        values.push((*loc, ValueDef::Root(block, idx)));
+                        defs[block][idx] = Some(value);
+                        stacks[*loc as usize].push(value);
+                        pushed.push(*loc);
+                    }
+                }
+            }
+            for succ in cfg.succs(block) {
+                for phi in 0..phis[*succ].len() {
+                    let loc = phis[*succ][phi].local;
+                    let value = current_value(loc, &stacks, &mut entry_values, &mut values);
+                    phis[*succ][phi].args.push((block, value));
+                }
+            }
+            visits.push(Visit::Exit(pushed));
+            visits.extend(dom.children(block).iter().map(|child| Visit::Enter(*child)));
+        }
+        if values.len() > MAX_VALUES {
+            return None;
+        }
+        let block_ids: Vec<u32> = blocks.iter().map(BasicBlock::block_id).collect();
+        Some(Self {
+            dom,
+            id_map: block_ids
+                .iter()
+                .enumerate()
+                .map(|(idx, id)| (*id, idx))
+                .collect(),
+            block_ids,
+            roots,
+            forward: (0..values.len()).collect(),
+            values,
+            phis,
+            uses,
+            defs,
+            tracked,
+        })
+    }
+    /// Returns the index of the block with id `id`.
+    pub fn block_idx(&self, id: u32) -> usize {
+        self.id_map[&id]
+    }
+    /// Returns the value `value` was replaced with, or `value` itself.
+    pub fn resolve(&self, value: usize) -> usize {
+        resolve(&self.forward, value)
+    }
+    /// The block `value` is defined in. Entry values are defined in the entry block.
+    pub fn def_block(&self, value: usize) -> usize {
+        match self.values[value].1 {
+            ValueDef::Entry => 0,
+            ValueDef::Phi(block) | ValueDef::Root(block, _) => block,
+        }
+    }
+    /// The type of the local `value` belongs to.
+    pub fn value_type(&self, value: usize, locals: &[LocalDef]) -> TypeIdx {
+        locals[self.values[value].0 as usize].1
+    }
+    /// Converts the method back out of SSA form. Each SSA value gets its own local, and phis are replaced with copies at the end of predecessor blocks.
+    /// Definitions of values which are never read are removed, and so are unreachable blocks.
+    fn destruct(
+        mut self,
+        blocks: &mut Vec<BasicBlock>,
+        locals: &mut Vec<LocalDef>,
+        asm: &mut Assembly,
+        cache: &mut SideEffectInfoCache,
+    ) -> Destructed {
+        // The passes may have removed some jumps.
+        let cfg = Cfg::from_roots(&self.block_ids, &self.roots, asm)
+            .expect("SSA passes produced an invalid CFG");
+        let mut needed = vec![false; self.values.len()];
+        let mut worklist = Vec::new();
+        for block in cfg.rpo() {
+            for (_, value) in self.uses[*block].iter().flatten() {
+                let value = self.resolve(*value);
+                if !needed[value] {
+                    needed[value] = true;
+                    worklist.push(value);
+                }
+            }
+        }
+        while let Some(value) = worklist.pop() {
+            let ValueDef::Phi(block) = self.values[value].1 else {
+                continue;
+            };
+            let phi = self.phis[block]
+                .iter()
+                .find(|phi| phi.value == value)
+                .unwrap();
+            for (pred, arg) in &phi.args {
+                if !cfg.is_reachable(*pred) {
+                    continue;
+                }
+                let arg = self.resolve(*arg);
+                if !needed[arg] {
+                    needed[arg] = true;
+                    worklist.push(arg);
+                }
+            }
+        }
+        // Give each value its own local. Entry values stay in the original local, since they may be read before being written.
+        let mut local_of = vec![u32::MAX; self.values.len()];
+        let mut affinities = Vec::new();
+        for (value, (loc, def)) in self.values.iter().enumerate() {
+            if *def == ValueDef::Entry {
+                local_of[value] = *loc;
+            } else if needed[value] {
+                let new_local = u32::try_from(locals.len()).unwrap();
+                local_of[value] = new_local;
+                affinities.push((*loc, new_local));
+                locals.push(locals[*loc as usize]);
+            }
+        }
+        for block in cfg.rpo() {
+            for idx in 0..self.roots[*block].len() {
+                let def = self.defs[*block][idx];
+                if self.uses[*block][idx].is_empty() && def.is_none() {
+                    continue;
+                }
+                let renames: Vec<(u32, u32)> = self.uses[*block][idx]
+                    .iter()
+                    .map(|(loc, value)| (*loc, local_of[self.resolve(*value)]))
+                    .collect();
+                let root = asm.get_root(self.roots[*block][idx]).clone();
+                let root = root.map(asm, &mut |root, _| root, &mut |node, _| match node {
+                    CILNode::LdLoc(loc) => CILNode::LdLoc(
+                        renames
+                            .iter()
+                            .find(|(old, _)| *old == loc)
+                            .map_or(loc, |(_, new)| *new),
+                    ),
+                    node => node,
+                });
+                let root = match (root, def) {
+                    (CILRoot::StLoc(_, tree), Some(value)) if needed[value] => {
+                        CILRoot::StLoc(local_of[value], tree)
+                    }
+                    (CILRoot::StLoc(_, tree), Some(_)) => {
+                        if cache.has_side_effects(tree, asm) {
+                            CILRoot::Pop(tree)
+                        } else {
+                            CILRoot::Nop
+                        }
+                    }
+                    (root, _) => root,
+                };
+                self.roots[*block][idx] = asm.alloc_root(root);
+            }
+        }
+        // Replace phis with copies.
+        let mut next_id = self.block_ids.iter().max().unwrap() + 1;
+        let mut edge_blocks = Vec::new();
+        for block in cfg.rpo() {
+            let target = self.block_ids[*block];
+            for pred in cfg.preds(*block) {
+                if !cfg.is_reachable(*pred) {
+                    continue;
+                }
+                let copies: Vec<(u32, u32)> = self.phis[*block]
+                    .iter()
+                    .filter(|phi| needed[phi.value])
+                    .map(|phi| {
+                        let (_, arg) = phi
+                            .args
+                            .iter()
+                            .find(|(arg_pred, _)| arg_pred == pred)
+                            .expect("Phi has no argument for an edge");
+                        (local_of[phi.value], local_of[self.resolve(*arg)])
+                    })
+                    .filter(|(dst, src)| dst != src)
+                    .collect();
+                if copies.is_empty() {
+                    continue;
+                }
+                let copies = sequentialize_copies(copies, locals, asm);
+                let pred_roots = &mut self.roots[*pred];
+                let first_exit = pred_roots
+                    .iter()
+                    .position(|root| is_exit(asm.get_root(*root)))
+                    .unwrap();
+                let mut exits = pred_roots[first_exit..]
+                    .iter()
+                    .filter(|root| !is_nop(asm.get_root(**root)));
+                let only_jump = matches!(exits.next().map(|root| asm.get_root(*root)), Some(CILRoot::Branch(info)) if info.0 == target && info.2.is_none())
+                    && exits.next().is_none();
+                if only_jump {
+                    pred_roots.splice(first_exit..first_exit, copies);
+                    continue;
+                }
+                // The predecessor may also jump somewhere else, so the copies need a block of their own.
+                let id = next_id;
+                next_id += 1;
+                for root in pred_roots.iter_mut() {
+                    if let Some(retargeted) = retarget(asm.get_root(*root), target, id) {
+                        *root = asm.alloc_root(retargeted);
+                    }
+                }
+                let mut roots = copies;
+                roots.push(asm.alloc_root(CILRoot::Branch(Box::new((target, 0, None)))));
+                self.roots.push(roots);
+                self.block_ids.push(id);
+                edge_blocks.push(id);
+            }
+        }
+        *blocks = self
+            .roots
+            .into_iter()
+            .zip(self.block_ids)
+            .enumerate()
+            .filter(|(block, _)| *block >= cfg.len() || cfg.is_reachable(*block))
+            .map(|(_, (roots, id))| BasicBlock::new(roots, id, None))
+            .collect();
+        Destructed {
+            affinities,
+            edge_blocks,
+        }
+    }
+}
+enum Visit<T> {
+    Enter(usize),
+    Exit(T),
+}
+fn resolve(forward: &[usize], mut value: usize) -> usize {
+    while forward[value] != value {
+        value = forward[value];
+    }
+    value
+}
+/// Returns the current value of `loc`, creating its entry value if needed.
+fn current_value(
+    loc: u32,
+    stacks: &[Vec<usize>],
+    entry_values: &mut [Option<usize>],
+    values: &mut Vec<(u32, ValueDef)>,
+) -> usize {
+    if let Some(value) = stacks[loc as usize].last() {
+        return *value;
+    }
+    *entry_values[loc as usize].get_or_insert_with(|| {
+        values.push((loc, ValueDef::Entry));
+        values.len() - 1
+    })
+}
+/// Checks if `root` may end the execution of a block.
+fn is_exit(root: &CILRoot) -> bool {
+    matches!(
+        root,
+        CILRoot::Branch(_)
+            | CILRoot::Switch(_)
+            | CILRoot::Ret(_)
+            | CILRoot::VoidRet
+            | CILRoot::TailCall(_)
+            | CILRoot::TailCallI(_)
+            | CILRoot::Throw(_)
+            | CILRoot::ReThrow
+            | CILRoot::Unreachable(_)
+    )
+}
+fn is_nop(root: &CILRoot) -> bool {
+    matches!(root, CILRoot::Nop | CILRoot::SourceFileInfo { .. })
+}
+/// Checks if the last meaningful root of a block always ends its execution.
+fn ends_with_terminator(roots: &[RootIdx], asm: &Assembly) -> bool {
+    match roots
+        .iter()
+        .map(|root| asm.get_root(*root))
+        .rfind(|root| !is_nop(root))
+    {
+        Some(CILRoot::Branch(info)) => info.2.is_none(),
+        Some(root) => is_exit(root),
+        None => false,
+    }
+}
+/// Turns a set of copies, which should happen at the same time, into a sequence of `StLoc`s.
+/// Cycles are broken using temporary locals.
+fn sequentialize_copies(
+    mut copies: Vec<(u32, u32)>,
+    locals: &mut Vec<LocalDef>,
+    asm: &mut Assembly,
+) -> Vec<RootIdx> {
+    let mut roots = Vec::with_capacity(copies.len());
+    while !copies.is_empty() {
+        // A copy can be preformed once no other copy reads its destination.
+        if let Some(ready) = copies
+            .iter()
+            .position(|(dst, _)| !copies.iter().any(|(_, src)| src == dst))
+        {
+            let (dst, src) = copies.remove(ready);
+            let src = asm.alloc_node(CILNode::LdLoc(src));
+            roots.push(asm.alloc_root(CILRoot::StLoc(dst, src)));
+            continue;
+        }
+        // Only cycles remain: save one of the destinations in a temporary.
+        let (dst, _) = copies[0];
+        let tmp = u32::try_from(locals.len()).unwrap();
+        locals.push(locals[dst as usize]);
+        let old = asm.alloc_node(CILNode::LdLoc(dst));
+        roots.push(asm.alloc_root(CILRoot::StLoc(tmp, old)));
+        for (_, src) in &mut copies {
+            if *src == dst {
+                *src = tmp;
+            }
+        }
+    }
+    roots
+}
+/// Makes sure the entry block has no predecessors, by adding a new entry block if needed.
+fn add_preheader(blocks: &mut Vec<BasicBlock>, asm: &mut Assembly) {
+    let entry = blocks[0].block_id();
+    if !blocks
+        .iter()
+        .flat_map(|block| block.targets(asm))
+        .any(|target| target == entry)
+    {
+        return;
+    }
+    let id = blocks.iter().map(BasicBlock::block_id).max().unwrap() + 1;
+    let jump = asm.alloc_root(CILRoot::Branch(Box::new((entry, 0, None))));
+    blocks.insert(0, BasicBlock::new(vec![jump], id, None));
+}
+/// Removes the blocks in `ids` which do nothing besides jumping to another block.
+fn remove_jump_blocks(blocks: &mut Vec<BasicBlock>, ids: &[u32], asm: &mut Assembly) {
+    let jumps: FxHashMap<u32, u32> = blocks
+        .iter()
+        .filter(|block| ids.contains(&block.block_id()))
+        .filter_map(|block| Some((block.block_id(), block.is_direct_jump(asm)?.0)))
+        .collect();
+    if jumps.is_empty() {
+        return;
+    }
+    blocks.retain(|block| !jumps.contains_key(&block.block_id()));
+    for block in blocks.iter_mut() {
+        for root in block.roots_mut() {
+            for (from, to) in &jumps {
+                if let Some(retargeted) = retarget(asm.get_root(*root), *from, *to) {
+                    *root = asm.alloc_root(retargeted);
+                }
+            }
+        }
+    }
+}
+/// Returns `root`, with its jumps to the block `from` changed to jump to `to` instead, or `None` if it does not jump to `from`.
+fn retarget(root: &CILRoot, from: u32, to: u32) -> Option<CILRoot> {
+    match root {
+        CILRoot::Branch(info) if info.0 == from => {
+            let mut info = info.clone();
+            info.0 = to;
+            Some(CILRoot::Branch(info))
+        }
+        CILRoot::Switch(info) if info.2.targets().any(|(target, _)| target == from) => {
+            let (value, int, targets) = info.as_ref();
+            let targets = targets.map_targets(|(target, sub_target)| {
+                (if target == from { to } else { target }, sub_target)
+            });
+            Some(CILRoot::Switch(Box::new((*value, *int, targets))))
+        }
+        _ => None,
+    }
+}
+impl MethodImpl {
+    /// Converts this method into SSA form, and runs sparse conditional constant propagation and global value numbering on it.
+    /// The method is then converted back, and the copies this introduced are coalesced.
+    /// Methods with exception handlers are not supported yet, and are left unchanged.
+    pub fn ssa_opt(
+        &mut self,
+        asm: &mut Assembly,
+        cache: &mut SideEffectInfoCache,
+        fuel: &mut OptFuel,
+    ) {
+        let MethodImpl::MethodBody { blocks, locals } = self else {
+            return;
+        };
+        if blocks.is_empty() || blocks.iter().any(|block| block.handler().is_some()) {
+            return;
+        }
+        if !fuel.consume(2) {
+            return;
+        }
+        add_preheader(blocks, asm);
+        let Some(mut ssa) = SsaForm::new(blocks, locals, asm) else {
+            return;
+        };
+        ssa.sccp(locals, asm, fuel);
+        ssa.gvn(locals, asm, fuel);
+        let destructed = ssa.destruct(blocks, locals, asm, cache);
+        coalesce_locals(blocks, locals, &destructed.affinities, asm, fuel);
+        remove_jump_blocks(blocks, &destructed.edge_blocks, asm);
+    }
+}
+#[cfg(test)]
+use crate::v2::{cilroot::BranchCond, cilroot::CmpKind, BinOp, Const, Int, Type};
+#[cfg(test)]
+fn test_method(
+    blocks: Vec<Vec<CILRoot>>,
+    locals: usize,
+    asm: &mut Assembly,
+) -> (Vec<BasicBlock>, Vec<LocalDef>) {
+    let i32_tpe = asm.alloc_type(Type::Int(Int::I32));
+    let blocks = blocks
+        .into_iter()
+        .enumerate()
+        .map(|(id, roots)| {
+            let roots = roots.into_iter().map(|root| asm.alloc_root(root)).collect();
+            BasicBlock::new(roots, u32::try_from(id).unwrap(), None)
+        })
+        .collect();
+    (blocks, vec![(None, i32_tpe); locals])
+}
+#[test]
+fn dominators() {
+    let mut asm = Assembly::default();
+    let jump = |target: u32, asm: &mut Assembly| {
+        asm.alloc_root(CILRoot::Branch(Box::new((target, 0, None))))
+    };
+    let cond = asm.alloc_node(Const::Bool(true));
+    let cond_jump = asm.alloc_root(CILRoot::Branch(Box::new((
+        2,
+        0,
+        Some(BranchCond::True(cond)),
+    ))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    // A diamond: 0 -> (1 | 2) -> 3
+    let blocks = [
+        BasicBlock::new(vec![cond_jump, jump(1, &mut asm)], 0, None),
+        BasicBlock::new(vec![jump(3, &mut asm)], 1, None),
+        BasicBlock::new(vec![jump(3, &mut asm)], 2, None),
+        BasicBlock::new(vec![ret], 3, None),
+    ];
+    let cfg = Cfg::new(&blocks, &asm).unwrap();
+    let dom = DomTree::new(&cfg);
+    assert_eq!(dom.idom(0), None);
+    assert_eq!(dom.idom(1), Some(0));
+    assert_eq!(dom.idom(2), Some(0));
+    assert_eq!(dom.idom(3), Some(0));
+    assert!(dom.dominates(0, 3));
+    assert!(!dom.dominates(1, 3));
+    let frontiers = dom.frontiers(&cfg);
+    assert_eq!(frontiers[1], [3]);
+    assert_eq!(frontiers[2], [3]);
+    assert!(frontiers[0].is_empty());
+}
+#[test]
+fn sccp_folds_branch() {
+    let mut asm = Assembly::default();
+    let five = asm.alloc_node(Const::I32(5));
+    let x = asm.alloc_node(CILNode::LdLoc(0));
+    let zero = asm.alloc_node(Const::I32(0));
+    let (mut blocks, mut locals) = test_method(
+        vec![
+            vec![
+                CILRoot::StLoc(0, five),
+                CILRoot::Branch(Box::new((2, 0, Some(BranchCond::Eq(x, five))))),
+                CILRoot::Branch(Box::new((1, 0, None))),
+            ],
+            vec![CILRoot::Ret(zero)],
+            vec![CILRoot::Ret(x)],
+        ],
+        1,
+        &mut asm,
+    );
+    let mut method = MethodImpl::MethodBody {
+        blocks: blocks.clone(),
+        locals: locals.clone(),
+    };
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(1000),
+    );
+    let MethodImpl::MethodBody { blocks: opt, .. } = &method else {
+        unreachable!()
+    };
+    // The block returning 0 is never reached.
+    assert_eq!(opt.len(), 2);
+    assert!(opt.iter().all(|block| block.block_id() != 1));
+    assert_eq!(
+        asm.get_root(*opt[1].roots().last().unwrap()),
+        &CILRoot::Ret(five)
+    );
+    // With no fuel, nothing changes.
+    let mut method = MethodImpl::MethodBody {
+        blocks: std::mem::take(&mut blocks),
+        locals: std::mem::take(&mut locals),
+    };
+    let before = method.clone();
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(0),
+    );
+    assert_eq!(method, before);
+}
+#[test]
+fn sccp_folds_switch() {
+    use crate::v2::cilroot::SwitchTargets;
+    let mut asm = Assembly::default();
+    let two = asm.alloc_node(Const::I32(2));
+    let x = asm.alloc_node(CILNode::LdLoc(0));
+    let zero = asm.alloc_node(Const::I32(0));
+    let one = asm.alloc_node(Const::I32(1));
+    let targets = SwitchTargets::new(vec![(1, (1, 0)), (2, (2, 0))], (3, 0));
+    let (blocks, locals) = test_method(
+        vec![
+            vec![
+                CILRoot::StLoc(0, two),
+                CILRoot::Switch(Box::new((x, Int::I32, targets))),
+            ],
+            vec![CILRoot::Ret(zero)],
+            vec![CILRoot::Ret(x)],
+            vec![CILRoot::Ret(one)],
+        ],
+        1,
+        &mut asm,
+    );
+    let mut method = MethodImpl::MethodBody { blocks, locals };
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(1000),
+    );
+    let MethodImpl::MethodBody { blocks: opt, .. } = &method else {
+        unreachable!()
+    };
+    // Only the case for 2 can be taken.
+    assert_eq!(
+        opt.iter().map(BasicBlock::block_id).collect::<Vec<_>>(),
+        [0, 2]
+    );
+    assert_eq!(
+        asm.get_root(*opt[0].roots().last().unwrap()),
+        &CILRoot::Branch(Box::new((2, 0, None)))
+    );
+    assert_eq!(
+        asm.get_root(*opt[1].roots().last().unwrap()),
+        &CILRoot::Ret(two)
+    );
+}
+#[test]
+fn gvn_removes_recomputation() {
+    let mut asm = Assembly::default();
+    let arg = asm.alloc_node(CILNode::LdArg(0));
+    let one = asm.alloc_node(Const::I32(1));
+    let sum = asm.alloc_node(CILNode::BinOp(arg, one, BinOp::Add));
+    let a = asm.alloc_node(CILNode::LdLoc(0));
+    let b = asm.alloc_node(CILNode::LdLoc(1));
+    let mul = asm.alloc_node(CILNode::BinOp(a, b, BinOp::Mul));
+    let (blocks, locals) = test_method(
+        vec![vec![
+            CILRoot::StLoc(0, sum),
+            CILRoot::StLoc(1, sum),
+            CILRoot::Ret(mul),
+        ]],
+        2,
+        &mut asm,
+    );
+    let mut method = MethodImpl::MethodBody { blocks, locals };
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(1000),
+    );
+    let roots: Vec<_> = method
+        .blocks()
+        .unwrap()
+        .iter()
+        .flat_map(BasicBlock::roots)
+        .map(|root| asm.get_root(*root).clone())
+        .filter(|root| !is_nop(root))
+        .collect();
+    assert_eq!(roots.len(), 2);
+    let CILRoot::StLoc(loc, _) = roots[0] else {
+        panic!("{roots:?}")
+    };
+    let CILRoot::Ret(ret) = roots[1] else {
+        panic!("{roots:?}")
+    };
+    let CILNode::BinOp(lhs, rhs, BinOp::Mul) = asm.get_node(ret) else {
+        panic!("{roots:?}")
+    };
+    assert_eq!(lhs, rhs);
+    assert_eq!(asm.get_node(*lhs), &CILNode::LdLoc(loc));
+}
+#[test]
+fn loop_coalesces_phis() {
+    let mut asm = Assembly::default();
+    let zero = asm.alloc_node(Const::I32(0));
+    let one = asm.alloc_node(Const::I32(1));
+    let ten = asm.alloc_node(Const::I32(10));
+    let i = asm.alloc_node(CILNode::LdLoc(0));
+    let inc = asm.alloc_node(CILNode::BinOp(i, one, BinOp::Add));
+    let (blocks, locals) = test_method(
+        vec![
+            vec![
+                CILRoot::StLoc(0, zero),
+                CILRoot::Branch(Box::new((1, 0, None))),
+            ],
+            vec![
+                CILRoot::Branch(Box::new((
+                    2,
+                    0,
+                    Some(BranchCond::Lt(i, ten, CmpKind::Signed)),
+                ))),
+                CILRoot::Branch(Box::new((3, 0, None))),
+            ],
+            vec![
+                CILRoot::StLoc(0, inc),
+                CILRoot::Branch(Box::new((1, 0, None))),
+            ],
+            vec![CILRoot::Ret(i)],
+        ],
+        1,
+        &mut asm,
+    );
+    let mut method = MethodImpl::MethodBody { blocks, locals };
+    method.ssa_opt(
+        &mut asm,
+        &mut SideEffectInfoCache::default(),
+        &mut OptFuel::new(1000),
+    );
+    let blocks = method.blocks().unwrap();
+    assert_eq!(blocks.len(), 4);
+    // The copies introduced by phis were coalesced, so all accesses use the same local again.
+    let mut accessed = FxHashSet::default();
+    let mut stores = 0;
+    for root in blocks.iter().flat_map(BasicBlock::roots) {
+        for node in CILIter::new(asm.get_root(*root).clone(), &asm) {
+            match node {
+                CILIterElem::Node(CILNode::LdLoc(loc))
+                | CILIterElem::Root(CILRoot::StLoc(loc, _)) => {
+                    accessed.insert(loc);
+                }
+                _ => (),
+            }
+        }
+        if let CILRoot::StLoc(..) = asm.get_root(*root) {
+            stores += 1;
+        }
+    }
+    assert_eq!(accessed.len(), 1);
+    assert_eq!(stores, 2);
+}
+#[test]
+fn parallel_copies() {
+    let mut asm = Assembly::default();
+    let (_, mut locals) = test_method(vec![], 2, &mut asm);
+    // Swapping two locals needs a temporary.
+    let roots = sequentialize_copies(vec![(0, 1), (1, 0)], &mut locals, &mut asm);
+    assert_eq!(roots.len(), 3);
+    assert_eq!(locals.len(), 3);
+    let roots = sequentialize_copies(vec![(0, 1), (1, 2)], &mut locals, &mut asm);
+    let ld_1 = asm.alloc_node(CILNode::LdLoc(1));
+    let ld_2 = asm.alloc_node(CILNode::LdLoc(2));
+    // 1 must be read before it is overwritten.
+    assert_eq!(asm.get_root(roots[0]), &CILRoot::StLoc(0, ld_1));
+    assert_eq!(asm.get_root(roots[1]), &CILRoot::StLoc(1, ld_2));
+}
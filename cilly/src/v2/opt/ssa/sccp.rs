@@ -0,0 +1,494 @@
+use fxhash::FxHashSet;
+
+use super::{is_exit, SsaForm, ValueDef};
+use crate::v2::{
+    cilnode::{ExtendKind, UnOp},
+    cilroot::{BranchCond, CmpKind},
+    method::LocalDef,
+    opt::OptFuel,
+    Assembly, BinOp, CILNode, CILRoot, Const, Int, NodeIdx,
+};
+/// What is known about a value.
+#[derive(Clone, Debug, PartialEq)]
+enum Lattice {
+    /// Nothing yet: the definition was not reached.
+    Top,
+    Const(Const),
+    /// Not a constant.
+    Bottom,
+}
+impl Lattice {
+    fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Top, other) | (other, Self::Top) => other.clone(),
+            (Self::Const(a), Self::Const(b)) if a == b => self.clone(),
+            _ => Self::Bottom,
+        }
+    }
+}
+impl SsaForm {
+    /// Sparse conditional constant propagation. Replaces reads of locals which always hold the same constant with that constant,
+    /// and folds branches which are always or never taken.
+    pub(crate) fn sccp(&mut self, locals: &[LocalDef], asm: &mut Assembly, fuel: &mut OptFuel) {
+        let mut lattice: Vec<Lattice> = self
+            .values
+            .iter()
+            .map(|(_, def)| match def {
+                ValueDef::Entry => Lattice::Bottom,
+                _ => Lattice::Top,
+            })
+            .collect();
+        // Blocks which need to be revisited when a value changes.
+        let mut users: Vec<Vec<usize>> = vec![Vec::new(); self.values.len()];
+        for block in 0..self.roots.len() {
+            let used = self.uses[block]
+                .iter()
+                .flatten()
+                .map(|(_, value)| *value)
+                .chain(
+                    self.phis[block]
+                        .iter()
+                        .flat_map(|phi| phi.args.iter().map(|(_, arg)| *arg)),
+                );
+            for value in used {
+                if users[value].last() != Some(&block) {
+                    users[value].push(block);
+                }
+            }
+        }
+        let mut executable = vec![false; self.roots.len()];
+        let mut edges: FxHashSet<(usize, usize)> = FxHashSet::default();
+        executable[0] = true;
+        let mut worklist = vec![0];
+        while let Some(block) = worklist.pop() {
+            let mut changed = Vec::new();
+            for phi in &self.phis[block] {
+                let new = phi
+                    .args
+                    .iter()
+                    .filter(|(pred, _)| edges.contains(&(*pred, block)))
+                    .fold(Lattice::Top, |acc, (_, arg)| acc.meet(&lattice[*arg]));
+                update(&mut lattice, phi.value, &new, &mut changed);
+            }
+            for (idx, root) in self.roots[block].iter().enumerate() {
+                let env = &self.uses[block][idx];
+                let root = asm.get_root(*root);
+                match root {
+                    CILRoot::StLoc(_, tree) => {
+                        let Some(value) = self.defs[block][idx] else {
+                            continue;
+                        };
+                        let mut new = eval(*tree, env, &lattice, asm);
+                        // Stores may implicitly convert values, so only constants of the exact type of the local are propagated.
+                        if let Lattice::Const(cst) = &new {
+                            if cst.get_type() != asm[self.value_type(value, locals)] {
+                                new = Lattice::Bottom;
+                            }
+                        }
+                        update(&mut lattice, value, &new, &mut changed);
+                    }
+                    CILRoot::Branch(info) => {
+                        let (target, _, cond) = info.as_ref();
+                        let taken = match cond {
+                            Some(cond) => eval_cond(cond, env, &lattice, asm),
+                            None => Lattice::Const(Const::Bool(true)),
+                        };
+                        if taken == Lattice::Const(Const::Bool(false)) {
+                            continue;
+                        }
+                        // Nothing is known about the condition yet, so we don't know where this jumps.
+                        if taken == Lattice::Top {
+                            break;
+                        }
+                        let target = self.block_idx(*target);
+                        if edges.insert((block, target)) {
+                            executable[target] = true;
+                            worklist.push(target);
+                        }
+                        if taken == Lattice::Const(Const::Bool(true)) {
+                            break;
+                        }
+                    }
+                    CILRoot::Switch(info) => {
+                        let (value, _, targets) = info.as_ref();
+                        let targets = match eval(*value, env, &lattice, asm) {
+                            Lattice::Top => break,
+                            Lattice::Const(cst) => match int_const(&cst) {
+                                Some((bits, _)) => vec![targets.target_of(bits)],
+                                None => targets.targets().collect(),
+                            },
+                            Lattice::Bottom => targets.targets().collect(),
+                        };
+                        for (target, _) in targets {
+                            let target = self.block_idx(target);
+                            if edges.insert((block, target)) {
+                                executable[target] = true;
+                                worklist.push(target);
+                            }
+                        }
+                        break;
+                    }
+                    _ if is_exit(root) => break,
+                    _ => (),
+                }
+            }
+            for value in changed {
+                worklist.extend(users[value].iter().filter(|user| executable[**user]));
+            }
+        }
+        let nop = asm.alloc_root(CILRoot::Nop);
+        for block in (0..self.roots.len()).filter(|block| executable[*block]) {
+            for idx in 0..self.roots[block].len() {
+                let root = asm.get_root(self.roots[block][idx]).clone();
+                if let CILRoot::Branch(info) = &root {
+                    let (target, sub_target, Some(cond)) = info.as_ref() else {
+                        continue;
+                    };
+                    match eval_cond(cond, &self.uses[block][idx], &lattice, asm) {
+                        Lattice::Const(Const::Bool(true)) if fuel.consume(1) => {
+                            self.roots[block][idx] = asm.alloc_root(CILRoot::Branch(Box::new((
+                                *target,
+                                *sub_target,
+                                None,
+                            ))));
+                            self.uses[block][idx].clear();
+                            // Everything after an unconditional jump is dead.
+                            for dead in idx + 1..self.roots[block].len() {
+                                self.roots[block][dead] = nop;
+                                self.uses[block][dead].clear();
+                            }
+                            break;
+                        }
+                        Lattice::Const(Const::Bool(false)) if fuel.consume(1) => {
+                            self.roots[block][idx] = nop;
+                            self.uses[block][idx].clear();
+                            continue;
+                        }
+                        _ => (),
+                    }
+                }
+                if let CILRoot::Switch(info) = &root {
+                    let (value, _, targets) = info.as_ref();
+                    if let Lattice::Const(cst) = eval(*value, &self.uses[block][idx], &lattice, asm)
+                    {
+                        if let Some((bits, _)) = int_const(&cst) {
+                            if fuel.consume(1) {
+                                let (target, sub_target) = targets.target_of(bits);
+                                self.roots[block][idx] = asm.alloc_root(CILRoot::Branch(Box::new(
+                                    (target, sub_target, None),
+                                )));
+                                self.uses[block][idx].clear();
+                                break;
+                            }
+                        }
+                    }
+                }
+                let consts: Vec<(u32, Const)> = self.uses[block][idx]
+                    .iter()
+                    .filter_map(|(loc, value)| match &lattice[*value] {
+                        Lattice::Const(cst) => Some((*loc, *cst)),
+                        _ => None,
+                    })
+                    .filter(|_| fuel.consume(1))
+                    .collect();
+                if consts.is_empty() {
+                    continue;
+                }
+                let root = root.map(asm, &mut |root, _| root, &mut |node, _| match node {
+                    CILNode::LdLoc(loc) => consts
+                        .iter()
+                        .find(|(cst_loc, _)| *cst_loc == loc)
+                        .map_or(node, |(_, cst)| (*cst).into()),
+                    node => node,
+                });
+                self.roots[block][idx] = asm.alloc_root(root);
+                self.uses[block][idx]
+                    .retain(|(loc, _)| !consts.iter().any(|(cst_loc, _)| cst_loc == loc));
+            }
+        }
+    }
+}
+fn update(lattice: &mut [Lattice], value: usize, new: &Lattice, changed: &mut Vec<usize>) {
+    // Values can only move down the lattice.
+    let new = lattice[value].meet(new);
+    if new != lattice[value] {
+        lattice[value] = new;
+        changed.push(value);
+    }
+}
+/// Evaluates `node`, given the values of the locals it reads.
+fn eval(node: NodeIdx, env: &[(u32, usize)], lattice: &[Lattice], asm: &Assembly) -> Lattice {
+    let res = match asm.get_node(node) {
+        CILNode::Const(cst) => return Lattice::Const(*cst.as_ref()),
+        CILNode::LdLoc(loc) => {
+            return env
+                .iter()
+                .find(|(used, _)| used == loc)
+                .map_or(Lattice::Bottom, |(_, value)| lattice[*value].clone())
+        }
+        CILNode::BinOp(lhs, rhs, op) => {
+            match (eval(*lhs, env, lattice, asm), eval(*rhs, env, lattice, asm)) {
+                (Lattice::Const(lhs), Lattice::Const(rhs)) => fold_binop(&lhs, &rhs, *op),
+                (Lattice::Bottom, _) | (_, Lattice::Bottom) => None,
+                _ => return Lattice::Top,
+            }
+        }
+        CILNode::UnOp(input, op) => match eval(*input, env, lattice, asm) {
+            Lattice::Const(input) => fold_unop(&input, op),
+            Lattice::Bottom => None,
+            Lattice::Top => return Lattice::Top,
+        },
+        CILNode::IntCast {
+            input,
+            target,
+            extend,
+        } => match eval(*input, env, lattice, asm) {
+            Lattice::Const(input) => fold_int_cast(&input, *target, *extend),
+            Lattice::Bottom => None,
+            Lattice::Top => return Lattice::Top,
+        },
+        _ => None,
+    };
+    res.map_or(Lattice::Bottom, Lattice::Const)
+}
+/// Evaluates a branch condition, returning a bool constant if it is known.
+fn eval_cond(
+    cond: &BranchCond,
+    env: &[(u32, usize)],
+    lattice: &[Lattice],
+    asm: &Assembly,
+) -> Lattice {
+    let binop = |lhs: NodeIdx, rhs: NodeIdx, op: BinOp| match (
+        eval(lhs, env, lattice, asm),
+        eval(rhs, env, lattice, asm),
+    ) {
+        (Lattice::Const(lhs), Lattice::Const(rhs)) => {
+            fold_binop(&lhs, &rhs, op).map_or(Lattice::Bottom, Lattice::Const)
+        }
+        (Lattice::Bottom, _) | (_, Lattice::Bottom) => Lattice::Bottom,
+        _ => Lattice::Top,
+    };
+    let signed = |kind: &CmpKind| matches!(kind, CmpKind::Ordered | CmpKind::Signed);
+    match cond {
+        BranchCond::True(cond) => truthy(eval(*cond, env, lattice, asm)),
+        BranchCond::False(cond) => not(truthy(eval(*cond, env, lattice, asm))),
+        BranchCond::Eq(lhs, rhs) => binop(*lhs, *rhs, BinOp::Eq),
+        BranchCond::Ne(lhs, rhs) => not(binop(*lhs, *rhs, BinOp::Eq)),
+        BranchCond::Lt(lhs, rhs, kind) => binop(
+            *lhs,
+            *rhs,
+            if signed(kind) { BinOp::Lt } else { BinOp::LtUn },
+        ),
+        BranchCond::Gt(lhs, rhs, kind) => binop(
+            *lhs,
+            *rhs,
+            if signed(kind) { BinOp::Gt } else { BinOp::GtUn },
+        ),
+        // Only integer comparisons are folded, so `a <= b` is the same as `!(a > b)`.
+        BranchCond::Le(lhs, rhs, kind) => not(binop(
+            *lhs,
+            *rhs,
+            if signed(kind) { BinOp::Gt } else { BinOp::GtUn },
+        )),
+        BranchCond::Ge(lhs, rhs, kind) => not(binop(
+            *lhs,
+            *rhs,
+            if signed(kind) { BinOp::Lt } else { BinOp::LtUn },
+        )),
+    }
+}
+fn truthy(val: Lattice) -> Lattice {
+    match val {
+        Lattice::Const(Const::Bool(val)) => Lattice::Const(Const::Bool(val)),
+        Lattice::Const(cst) => match int_const(&cst) {
+            Some((bits, _)) => Lattice::Const(Const::Bool(bits != 0)),
+            None => Lattice::Bottom,
+        },
+        other => other,
+    }
+}
+fn not(val: Lattice) -> Lattice {
+    match val {
+        Lattice::Const(Const::Bool(val)) => Lattice::Const(Const::Bool(!val)),
+        other => other,
+    }
+}
+/// Returns the bits of an integer constant, and its type.
+pub(crate) fn int_const(cst: &Const) -> Option<(u64, Int)> {
+    Some(match cst {
+        Const::I8(val) => (u64::from(*val as u8), Int::I8),
+        Const::I16(val) => (u64::from(*val as u16), Int::I16),
+        Const::I32(val) => (u64::from(*val as u32), Int::I32),
+        Const::I64(val) => (*val as u64, Int::I64),
+        Const::ISize(val) => (*val as u64, Int::ISize),
+        Const::U8(val) => (u64::from(*val), Int::U8),
+        Const::U16(val) => (u64::from(*val), Int::U16),
+        Const::U32(val) => (u64::from(*val), Int::U32),
+        Const::U64(val) => (*val, Int::U64),
+        Const::USize(val) => (*val, Int::USize),
+        _ => return None,
+    })
+}
+fn from_bits(bits: u64, int: Int) -> Option<Const> {
+    Some(match int {
+        Int::I8 => Const::I8(bits as i8),
+        Int::I16 => Const::I16(bits as i16),
+        Int::I32 => Const::I32(bits as i32),
+        Int::I64 => Const::I64(bits as i64),
+        Int::ISize => Const::ISize(bits as i64),
+        Int::U8 => Const::U8(bits as u8),
+        Int::U16 => Const::U16(bits as u16),
+        Int::U32 => Const::U32(bits as u32),
+        Int::U64 => Const::U64(bits),
+        Int::USize => Const::USize(bits),
+        Int::I128 | Int::U128 => return None,
+    })
+}
+/// The width of `int`, in bits. Native ints are assumed to be 64 bit wide.
+fn width(int: Int) -> u32 {
+    int.size().map_or(64, |size| u32::from(size) * 8)
+}
+fn sign_extend(bits: u64, width: u32) -> i64 {
+    ((bits << (64 - width)) as i64) >> (64 - width)
+}
+fn mask(bits: u64, width: u32) -> u64 {
+    if width == 64 {
+        bits
+    } else {
+        bits & ((1 << width) - 1)
+    }
+}
+fn fold_binop(lhs: &Const, rhs: &Const, op: BinOp) -> Option<Const> {
+    if let (Const::Bool(lhs), Const::Bool(rhs)) = (lhs, rhs) {
+        return match op {
+            BinOp::And => Some(Const::Bool(lhs & rhs)),
+            BinOp::Or => Some(Const::Bool(lhs | rhs)),
+            BinOp::XOr => Some(Const::Bool(lhs ^ rhs)),
+            BinOp::Eq => Some(Const::Bool(lhs == rhs)),
+            _ => None,
+        };
+    }
+    let (lhs, int) = int_const(lhs)?;
+    let (rhs, rhs_int) = int_const(rhs)?;
+    let width = width(int);
+    // Small ints get widened on the evaluation stack, which changes the result of some operations.
+    if width < 32 {
+        return None;
+    }
+    if matches!(op, BinOp::Shl | BinOp::Shr | BinOp::ShrUn) {
+        // Shifting by more than the width of the value is unspecified.
+        let shift = u32::try_from(rhs).ok().filter(|shift| *shift < width)?;
+        let bits = match op {
+            BinOp::Shl => lhs << shift,
+            BinOp::Shr => (sign_extend(lhs, width) >> shift) as u64,
+            _ => lhs >> shift,
+        };
+        return from_bits(mask(bits, width), int);
+    }
+    if int != rhs_int {
+        return None;
+    }
+    let (slhs, srhs) = (sign_extend(lhs, width), sign_extend(rhs, width));
+    // Dividing the smallest value by -1 overflows, and throws.
+    let overflows = srhs == -1 && slhs == sign_extend(1 << (width - 1), width);
+    let bits = match op {
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::And => lhs & rhs,
+        BinOp::Or => lhs | rhs,
+        BinOp::XOr => lhs ^ rhs,
+        BinOp::Div if !overflows => slhs.checked_div(srhs)? as u64,
+        BinOp::Rem if !overflows => slhs.checked_rem(srhs)? as u64,
+        BinOp::Div | BinOp::Rem => return None,
+        BinOp::DivUn => lhs.checked_div(rhs)?,
+        BinOp::RemUn => lhs.checked_rem(rhs)?,
+        BinOp::Eq => return Some(Const::Bool(lhs == rhs)),
+        BinOp::Lt => return Some(Const::Bool(slhs < srhs)),
+        BinOp::Gt => return Some(Const::Bool(slhs > srhs)),
+        BinOp::LtUn => return Some(Const::Bool(lhs < rhs)),
+        BinOp::GtUn => return Some(Const::Bool(lhs > rhs)),
+        BinOp::Shl | BinOp::Shr | BinOp::ShrUn => unreachable!(),
+    };
+    from_bits(mask(bits, width), int)
+}
+fn fold_unop(input: &Const, op: &UnOp) -> Option<Const> {
+    let (bits, int) = int_const(input)?;
+    let width = width(int);
+    if width < 32 {
+        return None;
+    }
+    let bits = match op {
+        UnOp::Not => !bits,
+        UnOp::Neg => bits.wrapping_neg(),
+    };
+    from_bits(mask(bits, width), int)
+}
+fn fold_int_cast(input: &Const, target: Int, extend: ExtendKind) -> Option<Const> {
+    let (bits, int) = int_const(input)?;
+    // Small ints are extended to 32 bits when loaded onto the evaluation stack.
+    let (bits, input_width) = if width(int) < 32 {
+        let bits = if int.is_signed() {
+            sign_extend(bits, width(int)) as u64
+        } else {
+            bits
+        };
+        (mask(bits, 32), 32)
+    } else {
+        (bits, width(int))
+    };
+    let target_width = width(target);
+    // Casts to small ints extend the result to 32 bits again, so the kind of extension must match the type.
+    if target_width < 32 && target.is_signed() != (extend == ExtendKind::SignExtend) {
+        return None;
+    }
+    let bits = match extend {
+        ExtendKind::SignExtend => sign_extend(bits, input_width) as u64,
+        ExtendKind::ZeroExtend => bits,
+    };
+    from_bits(mask(bits, target_width), target)
+}
+#[test]
+fn fold() {
+    assert_eq!(
+        fold_binop(&Const::I32(i32::MAX), &Const::I32(1), BinOp::Add),
+        Some(Const::I32(i32::MIN))
+    );
+    // `clt` compares its arguments as signed integers, even if they are unsigned.
+    assert_eq!(
+        fold_binop(&Const::U32(u32::MAX), &Const::U32(1), BinOp::Lt),
+        Some(Const::Bool(true))
+    );
+    assert_eq!(
+        fold_binop(&Const::U32(u32::MAX), &Const::U32(1), BinOp::LtUn),
+        Some(Const::Bool(false))
+    );
+    assert_eq!(
+        fold_binop(&Const::I64(7), &Const::I32(1), BinOp::Shl),
+        Some(Const::I64(14))
+    );
+    // Division by zero and overflowing division throw, so they can't be folded.
+    assert_eq!(fold_binop(&Const::I32(1), &Const::I32(0), BinOp::Div), None);
+    assert_eq!(
+        fold_binop(&Const::I32(i32::MIN), &Const::I32(-1), BinOp::Div),
+        None
+    );
+    assert_eq!(fold_binop(&Const::U8(1), &Const::U8(1), BinOp::Add), None);
+    assert_eq!(
+        fold_int_cast(&Const::I32(-1), Int::U64, ExtendKind::SignExtend),
+        Some(Const::U64(u64::MAX))
+    );
+    assert_eq!(
+        fold_int_cast(&Const::I32(-1), Int::U64, ExtendKind::ZeroExtend),
+        Some(Const::U64(u64::from(u32::MAX)))
+    );
+    assert_eq!(
+        fold_int_cast(&Const::U8(200), Int::I64, ExtendKind::SignExtend),
+        Some(Const::I64(200))
+    );
+    assert_eq!(
+        fold_int_cast(&Const::I32(300), Int::U8, ExtendKind::ZeroExtend),
+        Some(Const::U8(44))
+    );
+    assert_eq!(fold_unop(&Const::I64(5), &UnOp::Neg), Some(Const::I64(-5)));
+}
@@ -0,0 +1,202 @@
+use fxhash::FxHashMap;
+
+/// Writes an ECMA-335 compressed unsigned integer(II.23.2).
+pub fn write_compressed_u32(val: u32, out: &mut Vec<u8>) {
+    match val {
+        0..=0x7F => out.push(val as u8),
+        0x80..=0x3FFF => out.extend(((val as u16) | 0x8000).to_be_bytes()),
+        0x4000..=0x1FFF_FFFF => out.extend((val | 0xC000_0000).to_be_bytes()),
+        _ => panic!("Value {val} too big to be compressed"),
+    }
+}
+/// Writes an ECMA-335 compressed signed integer(II.23.2): the value is rotated left by one bit, moving the sign into the lowest bit.
+pub fn write_compressed_i32(val: i32, out: &mut Vec<u8>) {
+    let sign = u32::from(val < 0);
+    match val {
+        -0x40..=0x3F => out.push(((val as u32) << 1 & 0x7F | sign) as u8),
+        -0x2000..=0x1FFF => {
+            out.extend(((((val as u32) << 1 & 0x3FFF | sign) as u16) | 0x8000).to_be_bytes());
+        }
+        -0x1000_0000..=0x0FFF_FFFF => {
+            out.extend(((val as u32) << 1 & 0x1FFF_FFFF | sign | 0xC000_0000).to_be_bytes());
+        }
+        _ => panic!("Value {val} too big to be compressed"),
+    }
+}
+/// The `#Strings` heap: null terminated UTF-8 strings, deduplicated.
+pub struct StringHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<str>, u32>,
+}
+impl Default for StringHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl StringHeap {
+    /// Adds a string to this heap, returning its offset. An empty string is always at offset 0.
+    pub fn alloc(&mut self, val: &str) -> u32 {
+        if val.is_empty() {
+            return 0;
+        }
+        if let Some(offset) = self.map.get(val) {
+            return *offset;
+        }
+        assert!(
+            !val.contains('\0'),
+            "Metadata strings can't contain internal nulls, but {val:?} does"
+        );
+        let offset = u32::try_from(self.data.len()).expect("String heap overflow");
+        self.data.extend(val.as_bytes());
+        self.data.push(0);
+        self.map.insert(val.into(), offset);
+        offset
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#Blob` heap: length-prefixed byte sequences, deduplicated.
+pub struct BlobHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<[u8]>, u32>,
+}
+impl Default for BlobHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl BlobHeap {
+    /// Adds a blob to this heap, returning its offset. An empty blob is always at offset 0.
+    pub fn alloc(&mut self, val: &[u8]) -> u32 {
+        if val.is_empty() {
+            return 0;
+        }
+        if let Some(offset) = self.map.get(val) {
+            return *offset;
+        }
+        let offset = u32::try_from(self.data.len()).expect("Blob heap overflow");
+        write_compressed_u32(
+            u32::try_from(val.len()).expect("Blob too big"),
+            &mut self.data,
+        );
+        self.data.extend(val);
+        self.map.insert(val.into(), offset);
+        offset
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#US` heap: UTF-16 string literals used by `ldstr`.
+pub struct UserStringHeap {
+    data: Vec<u8>,
+    map: FxHashMap<Box<str>, u32>,
+}
+impl Default for UserStringHeap {
+    fn default() -> Self {
+        Self {
+            data: vec![0],
+            map: FxHashMap::default(),
+        }
+    }
+}
+impl UserStringHeap {
+    /// Adds a string literal to this heap, returning its offset.
+    pub fn alloc(&mut self, val: &str) -> u32 {
+        if let Some(offset) = self.map.get(val) {
+            return *offset;
+        }
+        let offset = u32::try_from(self.data.len()).expect("User string heap overflow");
+        let utf16: Vec<u16> = val.encode_utf16().collect();
+        write_compressed_u32(
+            u32::try_from(utf16.len() * 2 + 1).expect("User string too long"),
+            &mut self.data,
+        );
+        // The trailing byte is set if any char requires special handling(II.24.2.4)
+        let mut special = false;
+        for char in utf16 {
+            special |=
+                char > 0xFF || matches!(char, 0x01..=0x08 | 0x0E..=0x1F | 0x27 | 0x2D | 0x7F);
+            self.data.extend(char.to_le_bytes());
+        }
+        self.data.push(u8::from(special));
+        self.map.insert(val.into(), offset);
+        offset
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#GUID` heap. Indices into it are 1-based.
+#[derive(Default)]
+pub struct GuidHeap {
+    data: Vec<u8>,
+}
+impl GuidHeap {
+    pub fn alloc(&mut self, guid: [u8; 16]) -> u32 {
+        self.data.extend(guid);
+        u32::try_from(self.data.len() / 16).expect("GUID heap overflow")
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+#[test]
+fn compressed_u32() {
+    let mut out = vec![];
+    write_compressed_u32(0x03, &mut out);
+    assert_eq!(out, [0x03]);
+    out.clear();
+    write_compressed_u32(0x80, &mut out);
+    assert_eq!(out, [0x80, 0x80]);
+    out.clear();
+    write_compressed_u32(0x2E57, &mut out);
+    assert_eq!(out, [0xAE, 0x57]);
+    out.clear();
+    write_compressed_u32(0x4000, &mut out);
+    assert_eq!(out, [0xC0, 0x00, 0x40, 0x00]);
+    out.clear();
+    write_compressed_u32(0x1FFF_FFFF, &mut out);
+    assert_eq!(out, [0xDF, 0xFF, 0xFF, 0xFF]);
+}
+#[test]
+fn compressed_i32() {
+    let mut out = vec![];
+    for (val, encoded) in [
+        (3, &[0x06][..]),
+        (-3, &[0x7B]),
+        (64, &[0x80, 0x80]),
+        (-8192, &[0x80, 0x01]),
+        (268_435_455, &[0xDF, 0xFF, 0xFF, 0xFE]),
+        (-268_435_456, &[0xC0, 0x00, 0x00, 0x01]),
+    ] {
+        out.clear();
+        write_compressed_i32(val, &mut out);
+        assert_eq!(out, encoded, "{val}");
+    }
+}
+#[test]
+fn heaps() {
+    let mut strings = StringHeap::default();
+    assert_eq!(strings.alloc(""), 0);
+    let hi = strings.alloc("Hi");
+    assert_eq!(hi, 1);
+    assert_eq!(strings.alloc("Hi"), hi);
+    assert_eq!(strings.data(), b"\0Hi\0");
+    let mut blobs = BlobHeap::default();
+    let blob = blobs.alloc(&[1, 2, 3]);
+    assert_eq!(blobs.alloc(&[1, 2, 3]), blob);
+    assert_eq!(blobs.data(), [0, 3, 1, 2, 3]);
+    let mut us = UserStringHeap::default();
+    let a = us.alloc("A");
+    assert_eq!(us.data()[a as usize..], [3, b'A', 0, 0]);
+    let special = us.alloc("'");
+    assert_eq!(us.data()[special as usize..], [3, b'\'', 0, 1]);
+}
@@ -0,0 +1,345 @@
+use fxhash::FxHashMap;
+
+/// CIL opcodes(III.1.2.1). Two byte opcodes have the `0xFE` prefix in the high byte.
+#[allow(dead_code)]
+pub mod op {
+    pub const NOP: u16 = 0x00;
+    pub const BREAK: u16 = 0x01;
+    pub const LDARG_0: u16 = 0x02;
+    pub const LDLOC_0: u16 = 0x06;
+    pub const STLOC_0: u16 = 0x0A;
+    pub const LDARG_S: u16 = 0x0E;
+    pub const LDARGA_S: u16 = 0x0F;
+    pub const STARG_S: u16 = 0x10;
+    pub const LDLOC_S: u16 = 0x11;
+    pub const LDLOCA_S: u16 = 0x12;
+    pub const STLOC_S: u16 = 0x13;
+    pub const LDNULL: u16 = 0x14;
+    pub const LDC_I4_M1: u16 = 0x15;
+    pub const LDC_I4_0: u16 = 0x16;
+    pub const LDC_I4_S: u16 = 0x1F;
+    pub const LDC_I4: u16 = 0x20;
+    pub const LDC_I8: u16 = 0x21;
+    pub const LDC_R4: u16 = 0x22;
+    pub const LDC_R8: u16 = 0x23;
+    pub const DUP: u16 = 0x25;
+    pub const POP: u16 = 0x26;
+    pub const CALL: u16 = 0x28;
+    pub const CALLI: u16 = 0x29;
+    pub const RET: u16 = 0x2A;
+    pub const BR: u16 = 0x38;
+    pub const BRFALSE: u16 = 0x39;
+    pub const BRTRUE: u16 = 0x3A;
+    pub const BEQ: u16 = 0x3B;
+    pub const BGE: u16 = 0x3C;
+    pub const BGT: u16 = 0x3D;
+    pub const BLE: u16 = 0x3E;
+    pub const BLT: u16 = 0x3F;
+    pub const BNE_UN: u16 = 0x40;
+    pub const BGE_UN: u16 = 0x41;
+    pub const BGT_UN: u16 = 0x42;
+    pub const BLE_UN: u16 = 0x43;
+    pub const BLT_UN: u16 = 0x44;
+    pub const SWITCH: u16 = 0x45;
+    pub const LDIND_I1: u16 = 0x46;
+    pub const LDIND_U1: u16 = 0x47;
+    pub const LDIND_I2: u16 = 0x48;
+    pub const LDIND_U2: u16 = 0x49;
+    pub const LDIND_I4: u16 = 0x4A;
+    pub const LDIND_U4: u16 = 0x4B;
+    pub const LDIND_I8: u16 = 0x4C;
+    pub const LDIND_I: u16 = 0x4D;
+    pub const LDIND_R4: u16 = 0x4E;
+    pub const LDIND_R8: u16 = 0x4F;
+    pub const LDIND_REF: u16 = 0x50;
+    pub const STIND_REF: u16 = 0x51;
+    pub const STIND_I1: u16 = 0x52;
+    pub const STIND_I2: u16 = 0x53;
+    pub const STIND_I4: u16 = 0x54;
+    pub const STIND_I8: u16 = 0x55;
+    pub const STIND_R4: u16 = 0x56;
+    pub const STIND_R8: u16 = 0x57;
+    pub const ADD: u16 = 0x58;
+    pub const SUB: u16 = 0x59;
+    pub const MUL: u16 = 0x5A;
+    pub const DIV: u16 = 0x5B;
+    pub const DIV_UN: u16 = 0x5C;
+    pub const REM: u16 = 0x5D;
+    pub const REM_UN: u16 = 0x5E;
+    pub const AND: u16 = 0x5F;
+    pub const OR: u16 = 0x60;
+    pub const XOR: u16 = 0x61;
+    pub const SHL: u16 = 0x62;
+    pub const SHR: u16 = 0x63;
+    pub const SHR_UN: u16 = 0x64;
+    pub const NEG: u16 = 0x65;
+    pub const NOT: u16 = 0x66;
+    pub const CONV_I1: u16 = 0x67;
+    pub const CONV_I2: u16 = 0x68;
+    pub const CONV_I4: u16 = 0x69;
+    pub const CONV_I8: u16 = 0x6A;
+    pub const CONV_R4: u16 = 0x6B;
+    pub const CONV_R8: u16 = 0x6C;
+    pub const CONV_U4: u16 = 0x6D;
+    pub const CONV_U8: u16 = 0x6E;
+    pub const CALLVIRT: u16 = 0x6F;
+    pub const CPOBJ: u16 = 0x70;
+    pub const LDOBJ: u16 = 0x71;
+    pub const LDSTR: u16 = 0x72;
+    pub const NEWOBJ: u16 = 0x73;
+    pub const CASTCLASS: u16 = 0x74;
+    pub const ISINST: u16 = 0x75;
+    pub const CONV_R_UN: u16 = 0x76;
+    pub const THROW: u16 = 0x7A;
+    pub const LDFLD: u16 = 0x7B;
+    pub const LDFLDA: u16 = 0x7C;
+    pub const STFLD: u16 = 0x7D;
+    pub const LDSFLD: u16 = 0x7E;
+    pub const LDSFLDA: u16 = 0x7F;
+    pub const STSFLD: u16 = 0x80;
+    pub const STOBJ: u16 = 0x81;
+    pub const LDLEN: u16 = 0x8E;
+    pub const LDELEM_REF: u16 = 0x9A;
+    pub const UNBOX_ANY: u16 = 0xA5;
+    pub const LDTOKEN: u16 = 0xD0;
+    pub const CONV_U2: u16 = 0xD1;
+    pub const CONV_U1: u16 = 0xD2;
+    pub const CONV_I: u16 = 0xD3;
+    pub const LEAVE: u16 = 0xDD;
+    pub const STIND_I: u16 = 0xDF;
+    pub const CONV_U: u16 = 0xE0;
+    pub const CEQ: u16 = 0xFE01;
+    pub const CGT: u16 = 0xFE02;
+    pub const CGT_UN: u16 = 0xFE03;
+    pub const CLT: u16 = 0xFE04;
+    pub const CLT_UN: u16 = 0xFE05;
+    pub const LDFTN: u16 = 0xFE06;
+    pub const LDARG: u16 = 0xFE09;
+    pub const LDARGA: u16 = 0xFE0A;
+    pub const STARG: u16 = 0xFE0B;
+    pub const LDLOC: u16 = 0xFE0C;
+    pub const LDLOCA: u16 = 0xFE0D;
+    pub const STLOC: u16 = 0xFE0E;
+    pub const LOCALLOC: u16 = 0xFE0F;
+    pub const VOLATILE: u16 = 0xFE13;
+    pub const TAIL: u16 = 0xFE14;
+    pub const CPBLK: u16 = 0xFE17;
+    pub const INITBLK: u16 = 0xFE18;
+    pub const RETHROW: u16 = 0xFE1A;
+    pub const SIZEOF: u16 = 0xFE1C;
+}
+/// A jump target inside a method body. Mirrors the labels used by the textual IL exporter:
+/// `bb{id}`, `h{block}_{handler_block}` and `jp{source}_{target}`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Label {
+    Block(u32),
+    Handler(u32, u32),
+    Jump(u32, u32),
+}
+/// A protected region, and the catch handler associated with it.
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionClause {
+    pub try_start: u32,
+    pub try_end: u32,
+    pub handler_start: u32,
+    pub handler_end: u32,
+    pub class_token: u32,
+}
+/// Emits the bytecode of a single method, resolving branches to [`Label`]s.
+#[derive(Default)]
+pub struct ILWriter {
+    code: Vec<u8>,
+    labels: FxHashMap<Label, u32>,
+    /// Position of a branch offset, the offset of the next instruction, and the target of the branch
+    fixups: Vec<(usize, u32, Label)>,
+}
+impl ILWriter {
+    /// Current offset into the method body.
+    #[must_use]
+    pub fn offset(&self) -> u32 {
+        u32::try_from(self.code.len()).expect("Method body too big")
+    }
+    pub fn op(&mut self, op: u16) {
+        if op > 0xFF {
+            self.code.extend(op.to_be_bytes());
+        } else {
+            self.code.push(op as u8);
+        }
+    }
+    pub fn op_u8(&mut self, op: u16, arg: u8) {
+        self.op(op);
+        self.code.push(arg);
+    }
+    pub fn op_u16(&mut self, op: u16, arg: u16) {
+        self.op(op);
+        self.code.extend(arg.to_le_bytes());
+    }
+    pub fn op_i32(&mut self, op: u16, arg: i32) {
+        self.op(op);
+        self.code.extend(arg.to_le_bytes());
+    }
+    pub fn op_u32(&mut self, op: u16, arg: u32) {
+        self.op(op);
+        self.code.extend(arg.to_le_bytes());
+    }
+    pub fn op_i64(&mut self, op: u16, arg: i64) {
+        self.op(op);
+        self.code.extend(arg.to_le_bytes());
+    }
+    pub fn op_bytes(&mut self, op: u16, arg: &[u8]) {
+        self.op(op);
+        self.code.extend(arg);
+    }
+    /// Emits an `ldc.i4` in its shortest form.
+    pub fn ldc_i4(&mut self, val: i32) {
+        match val {
+            -1..=8 => self.op((op::LDC_I4_0 as i32 + val) as u16),
+            -128..=127 => self.op_u8(op::LDC_I4_S, val as i8 as u8),
+            _ => self.op_i32(op::LDC_I4, val),
+        }
+    }
+    /// Emits a branch using its long form, with the target resolved in [`Self::finish`].
+    pub fn branch(&mut self, op: u16, target: Label) {
+        self.op(op);
+        let pos = self.code.len();
+        self.code.extend(0_i32.to_le_bytes());
+        self.fixups.push((pos, self.offset(), target));
+    }
+    /// Emits a `switch`, with its targets resolved in [`Self::finish`]. The offsets are relative to the end of the
+    /// whole instruction.
+    pub fn switch(&mut self, targets: &[Label]) {
+        self.op_u32(
+            op::SWITCH,
+            u32::try_from(targets.len()).expect("Switch table too big"),
+        );
+        let next = self.offset() + u32::try_from(targets.len() * 4).expect("Switch table too big");
+        for target in targets {
+            let pos = self.code.len();
+            self.code.extend(0_i32.to_le_bytes());
+            self.fixups.push((pos, next, *target));
+        }
+    }
+    /// Marks the current position with `label`. If a label is defined more than once, the first definition wins.
+    pub fn mark(&mut self, label: Label) {
+        let offset = self.offset();
+        self.labels.entry(label).or_insert(offset);
+    }
+    /// Resolves all branches, and returns the bytecode.
+    ///
+    /// # Panics
+    /// Panics if a branch targets an undefined label.
+    #[must_use]
+    pub fn finish(mut self) -> Vec<u8> {
+        for (pos, next, label) in self.fixups {
+            let target = *self
+                .labels
+                .get(&label)
+                .unwrap_or_else(|| panic!("Branch to an undefined label {label:?}"));
+            let delta = i64::from(target) - i64::from(next);
+            let delta = i32::try_from(delta).expect("Branch offset out of range");
+            self.code[pos..pos + 4].copy_from_slice(&delta.to_le_bytes());
+        }
+        self.code
+    }
+}
+/// Encodes a method body: its header, bytecode, and exception handling sections(II.25.4).
+#[must_use]
+pub fn encode_method_body(
+    code: &[u8],
+    max_stack: u16,
+    locals_token: u32,
+    clauses: &[ExceptionClause],
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(code.len() + 12);
+    let code_size = u32::try_from(code.len()).expect("Method body too big");
+    if code_size < 64 && max_stack <= 8 && locals_token == 0 && clauses.is_empty() {
+        // Tiny header
+        out.push(((code_size as u8) << 2) | 0x2);
+        out.extend(code);
+        return out;
+    }
+    // Fat header: flags, and the size of the header in dwords in the top 4 bits.
+    let mut flags: u16 = 0x3 | (3 << 12);
+    if !clauses.is_empty() {
+        // More sections
+        flags |= 0x8;
+    }
+    out.extend(flags.to_le_bytes());
+    out.extend(max_stack.to_le_bytes());
+    out.extend(code_size.to_le_bytes());
+    out.extend(locals_token.to_le_bytes());
+    out.extend(code);
+    if clauses.is_empty() {
+        return out;
+    }
+    // Extra sections are 4 byte aligned
+    while !out.len().is_multiple_of(4) {
+        out.push(0);
+    }
+    // Exception handling table, in the fat format.
+    out.push(0x41);
+    let data_size = u32::try_from(clauses.len() * 24 + 4).expect("Too many exception clauses");
+    out.extend(&data_size.to_le_bytes()[..3]);
+    for clause in clauses {
+        // COR_ILEXCEPTION_CLAUSE_EXCEPTION
+        out.extend(0_u32.to_le_bytes());
+        out.extend(clause.try_start.to_le_bytes());
+        out.extend((clause.try_end - clause.try_start).to_le_bytes());
+        out.extend(clause.handler_start.to_le_bytes());
+        out.extend((clause.handler_end - clause.handler_start).to_le_bytes());
+        out.extend(clause.class_token.to_le_bytes());
+    }
+    out
+}
+#[test]
+fn branches() {
+    let mut writer = ILWriter::default();
+    writer.mark(Label::Block(0));
+    writer.op(op::NOP);
+    writer.branch(op::BR, Label::Block(1));
+    writer.branch(op::BR, Label::Block(0));
+    writer.mark(Label::Block(1));
+    writer.op(op::RET);
+    let code = writer.finish();
+    assert_eq!(
+        code,
+        [0x00, 0x38, 5, 0, 0, 0, 0x38, 0xF5, 0xFF, 0xFF, 0xFF, 0x2A]
+    );
+}
+#[test]
+fn small_consts() {
+    let mut writer = ILWriter::default();
+    writer.ldc_i4(-1);
+    writer.ldc_i4(8);
+    writer.ldc_i4(-100);
+    writer.ldc_i4(1000);
+    writer.op(op::CEQ);
+    assert_eq!(
+        writer.finish(),
+        [0x15, 0x1E, 0x1F, 0x9C, 0x20, 0xE8, 0x03, 0, 0, 0xFE, 0x01]
+    );
+}
+#[test]
+fn method_headers() {
+    let tiny = encode_method_body(&[0x2A], 8, 0, &[]);
+    assert_eq!(tiny, [0x06, 0x2A]);
+    let fat = encode_method_body(&[0x2A], 8, 0x1100_0001, &[]);
+    assert_eq!(fat.len(), 12 + 1);
+    assert_eq!(fat[0..2], [0x03, 0x30]);
+    let with_eh = encode_method_body(
+        &[0x00, 0x2A],
+        8,
+        0,
+        &[ExceptionClause {
+            try_start: 0,
+            try_end: 1,
+            handler_start: 1,
+            handler_end: 2,
+            class_token: 0x0100_0001,
+        }],
+    );
+    // Header, 2 bytes of code, 2 bytes of padding, 4 byte section header, one 24 byte clause
+    assert_eq!(with_eh.len(), 12 + 2 + 2 + 4 + 24);
+    assert_eq!(with_eh[0..2], [0x0B, 0x30]);
+    assert_eq!(with_eh[16], 0x41);
+}
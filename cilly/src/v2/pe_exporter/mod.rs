@@ -0,0 +1,1613 @@
+//! Writes an [`Assembly`] directly into a loadable .NET PE file, without going trough `ilasm`.
+//!
+//! The lowering of nodes and roots mirrors the one in [`super::il_exporter`], so both exporters should produce
+//! equivalent assemblies.
+use std::hash::{Hash, Hasher};
+
+use fxhash::{FxHashMap, FxHasher};
+
+use heaps::{BlobHeap, GuidHeap, StringHeap, UserStringHeap};
+use il::{op, ExceptionClause, ILWriter, Label};
+use pdb::{MethodDebugInfo, Pdb, PdbBuilder, SequencePoint};
+use tables::{CodedIndex, TableId, Tables};
+
+use super::{
+    asm::MAIN_MODULE,
+    cilnode::{ExtendKind, MethodKind, UnOp},
+    cilroot::{BranchCond, CmpKind, SwitchTargets},
+    method::LocalDef,
+    tpe::GenericKind,
+    Access, Assembly, BinOp, CILIter, CILIterElem, CILNode, CILRoot, ClassDefIdx, ClassRefIdx,
+    Const, Exporter, FieldIdx, FnSig, Int, MethodDefIdx, MethodImpl, MethodRefIdx, NodeIdx,
+    RootIdx, StaticFieldIdx, StringIdx, Type,
+};
+
+mod heaps;
+mod il;
+mod pdb;
+mod pe;
+mod tables;
+
+/// Element types used in signatures(II.23.1.16).
+mod element {
+    pub const VOID: u8 = 0x01;
+    pub const BOOLEAN: u8 = 0x02;
+    pub const CHAR: u8 = 0x03;
+    pub const I1: u8 = 0x04;
+    pub const U1: u8 = 0x05;
+    pub const I2: u8 = 0x06;
+    pub const U2: u8 = 0x07;
+    pub const I4: u8 = 0x08;
+    pub const U4: u8 = 0x09;
+    pub const I8: u8 = 0x0A;
+    pub const U8: u8 = 0x0B;
+    pub const R4: u8 = 0x0C;
+    pub const R8: u8 = 0x0D;
+    pub const STRING: u8 = 0x0E;
+    pub const PTR: u8 = 0x0F;
+    pub const BYREF: u8 = 0x10;
+    pub const VALUETYPE: u8 = 0x11;
+    pub const CLASS: u8 = 0x12;
+    pub const VAR: u8 = 0x13;
+    pub const ARRAY: u8 = 0x14;
+    pub const GENERICINST: u8 = 0x15;
+    pub const I: u8 = 0x18;
+    pub const U: u8 = 0x19;
+    pub const FNPTR: u8 = 0x1B;
+    pub const OBJECT: u8 = 0x1C;
+    pub const SZARRAY: u8 = 0x1D;
+    pub const MVAR: u8 = 0x1E;
+}
+/// Name of the core assembly all the builtin .NET types are referenced from.
+const SYSTEM_RUNTIME: &str = "System.Runtime";
+/// Writes a .NET assembly directly, without invoking `ilasm`.
+pub struct PEExporter {
+    is_lib: bool,
+}
+impl PEExporter {
+    #[must_use]
+    pub fn new(is_lib: bool) -> Self {
+        Self { is_lib }
+    }
+    /// Encodes `asm` as a PE image named `name`.
+    pub fn export_to_bytes(&self, asm: &Assembly, name: &str) -> std::io::Result<Vec<u8>> {
+        let mut builder = MetadataBuilder::new(asm, name);
+        let entrypoint = self.define_types(&mut builder);
+        let (bodies, metadata, _) = builder.finish(entrypoint)?;
+        Ok(pe::write_image(
+            &bodies,
+            &metadata,
+            entrypoint,
+            self.is_lib,
+            None,
+        ))
+    }
+    /// Encodes `asm` as a PE image named `name`, and its debug info as a Portable PDB, which will be stored at `pdb_path`.
+    /// Returns the image and the PDB.
+    pub fn export_with_pdb(
+        &self,
+        asm: &Assembly,
+        name: &str,
+        pdb_path: &str,
+    ) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+        let mut builder = MetadataBuilder::new(asm, name);
+        let entrypoint = self.define_types(&mut builder);
+        let (bodies, metadata, Pdb { id, data: pdb }) = builder.finish(entrypoint)?;
+        let pdb_ref = pe::PdbRef { id, path: pdb_path };
+        let image = pe::write_image(&bodies, &metadata, entrypoint, self.is_lib, Some(&pdb_ref));
+        Ok((image, pdb))
+    }
+    fn define_types(&self, builder: &mut MetadataBuilder) -> u32 {
+        let entrypoint = builder.define_types();
+        if !self.is_lib {
+            assert_ne!(entrypoint, 0, "An executable must have an entrypoint");
+        }
+        entrypoint
+    }
+}
+impl Exporter for PEExporter {
+    type Error = std::io::Error;
+
+    fn export(&self, asm: &Assembly, target: &std::path::Path) -> Result<(), Self::Error> {
+        // Use the same file name as the IL exporter.
+        let exe_out = std::path::absolute(target.with_extension("exe"))?;
+        let pdb_out = exe_out.with_extension("pdb");
+        let name = exe_out
+            .file_stem()
+            .map_or_else(|| "_".into(), |stem| stem.to_string_lossy().to_string());
+        let (image, pdb) = self.export_with_pdb(asm, &name, &pdb_out.to_string_lossy())?;
+        std::fs::write(&exe_out, image)?;
+        std::fs::write(&pdb_out, pdb)
+    }
+}
+/// Splits a full type name into its namespace and name.
+fn split_name(full: &str) -> (&str, &str) {
+    match full.rfind('.') {
+        Some(dot) => (&full[..dot], &full[dot + 1..]),
+        None => ("", full),
+    }
+}
+/// Builds the metadata tables, heaps and method bodies of a module.
+struct MetadataBuilder<'asm> {
+    asm: &'asm Assembly,
+    name: String,
+    tables: Tables,
+    strings: StringHeap,
+    blobs: BlobHeap,
+    guids: GuidHeap,
+    user_strings: UserStringHeap,
+    bodies: Vec<u8>,
+    assembly_refs: FxHashMap<String, u32>,
+    module_refs: FxHashMap<StringIdx, u32>,
+    type_refs: FxHashMap<(Option<String>, String), u32>,
+    /// Maps the names of local classes to their `TypeDef` rows.
+    type_defs: FxHashMap<String, u32>,
+    type_specs: FxHashMap<u32, u32>,
+    member_refs: FxHashMap<(u32, u32, u32), u32>,
+    method_specs: FxHashMap<(u32, u32), u32>,
+    standalone_sigs: FxHashMap<u32, u32>,
+    class_tokens: FxHashMap<ClassRefIdx, (TableId, u32)>,
+    /// Rows of local fields, keyed by their owner, name, type and "staticness".
+    field_defs: FxHashMap<(ClassRefIdx, StringIdx, Type, bool), u32>,
+    method_defs: FxHashMap<MethodDefIdx, u32>,
+    pdb: PdbBuilder,
+    /// Sequence points of the method currently being emitted.
+    sequence_points: Vec<SequencePoint>,
+    /// Constructs which can't be encoded. They are reported as an error once the whole module is built.
+    unsupported: Vec<String>,
+}
+impl<'asm> MetadataBuilder<'asm> {
+    fn new(asm: &'asm Assembly, name: &str) -> Self {
+        Self {
+            asm,
+            name: name.to_owned(),
+            tables: Tables::default(),
+            strings: StringHeap::default(),
+            blobs: BlobHeap::default(),
+            guids: GuidHeap::default(),
+            user_strings: UserStringHeap::default(),
+            bodies: Vec::new(),
+            assembly_refs: FxHashMap::default(),
+            module_refs: FxHashMap::default(),
+            type_refs: FxHashMap::default(),
+            type_defs: FxHashMap::default(),
+            type_specs: FxHashMap::default(),
+            member_refs: FxHashMap::default(),
+            method_specs: FxHashMap::default(),
+            standalone_sigs: FxHashMap::default(),
+            class_tokens: FxHashMap::default(),
+            field_defs: FxHashMap::default(),
+            method_defs: FxHashMap::default(),
+            pdb: PdbBuilder::default(),
+            sequence_points: Vec::new(),
+            unsupported: Vec::new(),
+        }
+    }
+    fn assembly_ref(&mut self, name: &str) -> u32 {
+        if let Some(row) = self.assembly_refs.get(name) {
+            return *row;
+        }
+        let name_idx = self.strings.alloc(name);
+        // Version 0.0.0.0, no flags, no public key, culture or hash
+        let row = self
+            .tables
+            .push(TableId::AssemblyRef, [0, 0, 0, 0, 0, 0, name_idx, 0, 0]);
+        self.assembly_refs.insert(name.to_owned(), row);
+        row
+    }
+    /// Returns a `TypeDef` or `TypeRef` for a type with the given name, coming from `assembly`.
+    fn named_type(&mut self, full_name: &str, assembly: Option<&str>) -> (TableId, u32) {
+        if assembly.is_none() {
+            if let Some(row) = self.type_defs.get(full_name) {
+                return (TableId::TypeDef, *row);
+            }
+        }
+        let key = (assembly.map(str::to_owned), full_name.to_owned());
+        if let Some(row) = self.type_refs.get(&key) {
+            return (TableId::TypeRef, *row);
+        }
+        // Types without an assembly, that are not defined here, get resolved in the scope of this module.
+        let scope = match assembly {
+            Some(assembly) => {
+                let assembly = self.assembly_ref(assembly);
+                CodedIndex::ResolutionScope.encode(TableId::AssemblyRef, assembly)
+            }
+            None => CodedIndex::ResolutionScope.encode(TableId::Module, 1),
+        };
+        let (namespace, name) = split_name(full_name);
+        let name = self.strings.alloc(name);
+        let namespace = self.strings.alloc(namespace);
+        let row = self.tables.push(TableId::TypeRef, [scope, name, namespace]);
+        self.type_refs.insert(key, row);
+        (TableId::TypeRef, row)
+    }
+    fn system_type(&mut self, name: &str) -> (TableId, u32) {
+        self.named_type(name, Some(SYSTEM_RUNTIME))
+    }
+    /// Returns the `TypeDef`, `TypeRef` or `TypeSpec` corresponding to a class reference.
+    fn class_ref(&mut self, cref: ClassRefIdx) -> (TableId, u32) {
+        if let Some(token) = self.class_tokens.get(&cref) {
+            return *token;
+        }
+        let class = self.asm.class_ref(cref);
+        let name = self.asm.get_string(class.name());
+        let assembly = class.asm().map(|asm| self.asm.get_string(asm).as_ref());
+        let token = if class.generics().is_empty() {
+            self.named_type(name, assembly)
+        } else {
+            let mut sig = Vec::new();
+            self.encode_type(&Type::ClassRef(cref), &mut sig);
+            (TableId::TypeSpec, self.type_spec(&sig))
+        };
+        self.class_tokens.insert(cref, token);
+        token
+    }
+    fn type_spec(&mut self, sig: &[u8]) -> u32 {
+        let blob = self.blobs.alloc(sig);
+        if let Some(row) = self.type_specs.get(&blob) {
+            return *row;
+        }
+        let row = self.tables.push(TableId::TypeSpec, [blob]);
+        self.type_specs.insert(blob, row);
+        row
+    }
+    /// Returns a token, usable as an operand of instructions like `sizeof` or `ldobj`.
+    fn type_token(&mut self, tpe: &Type) -> u32 {
+        let (table, row) = match tpe {
+            Type::ClassRef(cref) => self.class_ref(*cref),
+            _ => {
+                let mut sig = Vec::new();
+                self.encode_type(tpe, &mut sig);
+                (TableId::TypeSpec, self.type_spec(&sig))
+            }
+        };
+        table.token(row)
+    }
+    fn encode_class_ref(&mut self, name: &str, assembly: Option<&str>, out: &mut Vec<u8>) {
+        let (table, row) = self.named_type(name, assembly);
+        let tag = match table {
+            TableId::TypeDef => 0,
+            TableId::TypeRef => 1,
+            _ => unreachable!(),
+        };
+        heaps::write_compressed_u32((row << 2) | tag, out);
+    }
+    /// Encodes a type in a non-return position, replacing `void` with `RustVoid`.
+    fn encode_non_void(&mut self, tpe: &Type, out: &mut Vec<u8>) {
+        match tpe {
+            Type::Void => {
+                out.push(element::VALUETYPE);
+                self.encode_class_ref("RustVoid", None, out);
+            }
+            _ => self.encode_type(tpe, out),
+        }
+    }
+    /// Encodes a type signature(II.23.2.12).
+    fn encode_type(&mut self, tpe: &Type, out: &mut Vec<u8>) {
+        match tpe {
+            Type::Ptr(inner) => {
+                out.push(element::PTR);
+                self.encode_type(self.asm.get_type(*inner), out);
+            }
+            Type::Ref(inner) => {
+                out.push(element::BYREF);
+                self.encode_type(self.asm.get_type(*inner), out);
+            }
+            Type::Int(int) => match int {
+                Int::U8 => out.push(element::U1),
+                Int::U16 => out.push(element::U2),
+                Int::U32 => out.push(element::U4),
+                Int::U64 => out.push(element::U8),
+                Int::USize => out.push(element::U),
+                Int::I8 => out.push(element::I1),
+                Int::I16 => out.push(element::I2),
+                Int::I32 => out.push(element::I4),
+                Int::I64 => out.push(element::I8),
+                Int::ISize => out.push(element::I),
+                Int::U128 => {
+                    out.push(element::VALUETYPE);
+                    self.encode_class_ref("System.UInt128", Some(SYSTEM_RUNTIME), out);
+                }
+                Int::I128 => {
+                    out.push(element::VALUETYPE);
+                    self.encode_class_ref("System.Int128", Some(SYSTEM_RUNTIME), out);
+                }
+            },
+            Type::ClassRef(cref) => {
+                let class = self.asm.class_ref(*cref);
+                let kind = if class.is_valuetype() {
+                    element::VALUETYPE
+                } else {
+                    element::CLASS
+                };
+                let name = self.asm.get_string(class.name());
+                let assembly = class.asm().map(|asm| self.asm.get_string(asm).as_ref());
+                if class.generics().is_empty() {
+                    out.push(kind);
+                    self.encode_class_ref(name, assembly, out);
+                } else {
+                    out.push(element::GENERICINST);
+                    out.push(kind);
+                    // Generic types defined elsewhere have their arity in their name.
+                    let name = match assembly {
+                        Some(_) => format!("{name}`{}", class.generics().len()),
+                        None => name.to_string(),
+                    };
+                    self.encode_class_ref(&name, assembly, out);
+                    heaps::write_compressed_u32(
+                        u32::try_from(class.generics().len()).unwrap(),
+                        out,
+                    );
+                    for generic in class.generics() {
+                        self.encode_type(generic, out);
+                    }
+                }
+            }
+            Type::Float(float) => match float {
+                super::Float::F16 => {
+                    out.push(element::VALUETYPE);
+                    self.encode_class_ref("System.Half", Some(SYSTEM_RUNTIME), out);
+                }
+                super::Float::F32 => out.push(element::R4),
+                super::Float::F64 => out.push(element::R8),
+                super::Float::F128 => {
+                    out.push(element::VALUETYPE);
+                    self.encode_class_ref("f128", None, out);
+                }
+            },
+            Type::PlatformString => out.push(element::STRING),
+            Type::PlatformChar => out.push(element::CHAR),
+            Type::PlatformGeneric(arg, kind) => {
+                match kind {
+                    GenericKind::MethodGeneric | GenericKind::TypeGeneric => out.push(element::VAR),
+                    GenericKind::CallGeneric => out.push(element::MVAR),
+                }
+                heaps::write_compressed_u32(*arg, out);
+            }
+            Type::PlatformObject => out.push(element::OBJECT),
+            Type::Bool => out.push(element::BOOLEAN),
+            Type::Void => out.push(element::VOID),
+            Type::PlatformArray { elem, dims } => {
+                if dims.get() == 1 {
+                    out.push(element::SZARRAY);
+                    self.encode_type(self.asm.get_type(*elem), out);
+                } else {
+                    out.push(element::ARRAY);
+                    self.encode_type(self.asm.get_type(*elem), out);
+                    // Rank, no sizes and no lower bounds
+                    heaps::write_compressed_u32(u32::from(dims.get()), out);
+                    out.extend([0, 0]);
+                }
+            }
+            Type::FnPtr(sig) => {
+                out.push(element::FNPTR);
+                let sig = self.asm.get_sig(*sig).clone();
+                self.encode_method_sig(&sig, false, 0, out);
+            }
+        }
+    }
+    /// Encodes a method signature(II.23.2.1). If `has_this` is set, the first input is treated as the `this` argument.
+    fn encode_method_sig(&mut self, sig: &FnSig, has_this: bool, generics: u32, out: &mut Vec<u8>) {
+        let mut conv = 0;
+        if has_this {
+            conv |= 0x20;
+        }
+        if generics > 0 {
+            conv |= 0x10;
+        }
+        out.push(conv);
+        if generics > 0 {
+            heaps::write_compressed_u32(generics, out);
+        }
+        let inputs = if has_this {
+            &sig.inputs()[1..]
+        } else {
+            sig.inputs()
+        };
+        heaps::write_compressed_u32(u32::try_from(inputs.len()).unwrap(), out);
+        self.encode_type(sig.output(), out);
+        for input in inputs {
+            self.encode_non_void(input, out);
+        }
+    }
+    fn method_sig_blob(&mut self, sig: &FnSig, kind: MethodKind, generics: u32) -> u32 {
+        let mut blob = Vec::new();
+        self.encode_method_sig(sig, kind != MethodKind::Static, generics, &mut blob);
+        self.blobs.alloc(&blob)
+    }
+    fn field_sig_blob(&mut self, tpe: &Type) -> u32 {
+        let mut blob = vec![0x06];
+        self.encode_non_void(tpe, &mut blob);
+        self.blobs.alloc(&blob)
+    }
+    fn member_ref(&mut self, parent: (TableId, u32), name: &str, sig: u32) -> u32 {
+        let parent = CodedIndex::MemberRefParent.encode(parent.0, parent.1);
+        let name = self.strings.alloc(name);
+        if let Some(row) = self.member_refs.get(&(parent, name, sig)) {
+            return TableId::MemberRef.token(*row);
+        }
+        let row = self.tables.push(TableId::MemberRef, [parent, name, sig]);
+        self.member_refs.insert((parent, name, sig), row);
+        TableId::MemberRef.token(row)
+    }
+    fn standalone_sig(&mut self, blob: &[u8]) -> u32 {
+        let blob = self.blobs.alloc(blob);
+        if let Some(row) = self.standalone_sigs.get(&blob) {
+            return TableId::StandAloneSig.token(*row);
+        }
+        let row = self.tables.push(TableId::StandAloneSig, [blob]);
+        self.standalone_sigs.insert(blob, row);
+        TableId::StandAloneSig.token(row)
+    }
+    /// Returns the token of a method, defined in this assembly or elsewhere.
+    fn method_token(&mut self, mref_idx: MethodRefIdx) -> u32 {
+        let mref = self.asm.get_mref(mref_idx).clone();
+        let generics = u32::try_from(mref.generics().len()).unwrap();
+        let base = if let Some(row) = self.method_defs.get(&MethodDefIdx(mref_idx)) {
+            TableId::MethodDef.token(*row)
+        } else {
+            let parent = self.class_ref(mref.class());
+            let sig = self.asm.get_sig(mref.sig()).clone();
+            let sig = self.method_sig_blob(&sig, mref.kind(), generics);
+            let name = self.asm.get_string(mref.name());
+            self.member_ref(parent, name, sig)
+        };
+        if generics == 0 {
+            return base;
+        }
+        let method = if base >> 24 == TableId::MethodDef as u32 {
+            CodedIndex::MethodDefOrRef.encode(TableId::MethodDef, base & 0x00FF_FFFF)
+        } else {
+            CodedIndex::MethodDefOrRef.encode(TableId::MemberRef, base & 0x00FF_FFFF)
+        };
+        let mut inst = vec![0x0A];
+        heaps::write_compressed_u32(generics, &mut inst);
+        for generic in mref.generics() {
+            self.encode_type(generic, &mut inst);
+        }
+        let inst = self.blobs.alloc(&inst);
+        let row = match self.method_specs.get(&(method, inst)) {
+            Some(row) => *row,
+            None => {
+                let row = self.tables.push(TableId::MethodSpec, [method, inst]);
+                self.method_specs.insert((method, inst), row);
+                row
+            }
+        };
+        TableId::MethodSpec.token(row)
+    }
+    fn field_token(&mut self, field: FieldIdx) -> u32 {
+        let field = *self.asm.get_field(field);
+        if let Some(row) = self
+            .field_defs
+            .get(&(field.owner(), field.name(), field.tpe(), false))
+        {
+            return TableId::Field.token(*row);
+        }
+        let parent = self.class_ref(field.owner());
+        let sig = self.field_sig_blob(&field.tpe());
+        let name = self.asm.get_string(field.name());
+        self.member_ref(parent, name, sig)
+    }
+    fn static_field_token(&mut self, field: StaticFieldIdx) -> u32 {
+        let field = *self.asm.get_static_field(field);
+        if let Some(row) = self
+            .field_defs
+            .get(&(field.owner(), field.name(), field.tpe(), true))
+        {
+            return TableId::Field.token(*row);
+        }
+        let parent = self.class_ref(field.owner());
+        let sig = self.field_sig_blob(&field.tpe());
+        let name = self.asm.get_string(field.name());
+        self.member_ref(parent, name, sig)
+    }
+    /// `[System.Runtime]System.Exception::.ctor(string)`
+    fn exception_ctor(&mut self) -> u32 {
+        let exception = self.system_type("System.Exception");
+        let sig = self
+            .blobs
+            .alloc(&[0x20, 0x01, element::VOID, element::STRING]);
+        self.member_ref(exception, ".ctor", sig)
+    }
+    /// `System.Half::op_Explicit(float64)` or `MainModule::__extenddftf2(float64)`, used to convert to `f16` and `f128`.
+    fn cast_from_f64_token(&mut self, float: super::Float) -> u32 {
+        let (parent, name) = match float {
+            super::Float::F16 => (self.system_type("System.Half"), "op_Explicit"),
+            super::Float::F128 => (self.named_type(MAIN_MODULE, None), "__extenddftf2"),
+            super::Float::F32 | super::Float::F64 => {
+                panic!("{float:?} can be converted from a f64 by `conv.r`")
+            }
+        };
+        let sig = FnSig::new([Type::Float(super::Float::F64)].into(), Type::Float(float));
+        let sig = self.method_sig_blob(&sig, MethodKind::Static, 0);
+        self.member_ref(parent, name, sig)
+    }
+    /// Defines all the types of this assembly, and their members. Returns the entrypoint token.
+    fn define_types(&mut self) -> u32 {
+        let asm = self.asm;
+        // Sort the classes by name, so that the output is deterministic.
+        let mut class_defs: Vec<(ClassDefIdx, &super::ClassDef)> = asm
+            .class_defs()
+            .iter()
+            .map(|(idx, def)| (*idx, def))
+            .collect();
+        class_defs.sort_by(|(_, a), (_, b)| asm.get_string(a.name()).cmp(asm.get_string(b.name())));
+        // Assign rows to all types, fields and methods upfront, so that they can be referenced before they are emitted.
+        let mut field_row = 1;
+        let mut method_row = 1;
+        for (type_row, (idx, def)) in (2..).zip(class_defs.iter()) {
+            self.type_defs
+                .insert(asm.get_string(def.name()).to_string(), type_row);
+            for (tpe, name, _) in def.fields() {
+                self.field_defs
+                    .insert((idx.0, *name, *tpe, false), field_row);
+                field_row += 1;
+            }
+            for (tpe, name, _) in def.static_fields() {
+                self.field_defs
+                    .insert((idx.0, *name, *tpe, true), field_row);
+                field_row += 1;
+            }
+            for method in def.methods() {
+                self.method_defs.insert(*method, method_row);
+                method_row += 1;
+            }
+        }
+        let module_name = self.strings.alloc(&self.name);
+        let mvid = self.guids.alloc(self.mvid());
+        self.tables
+            .push(TableId::Module, [0, module_name, mvid, 0, 0]);
+        // SHA1 hash algorithm, version 0.0.0.0, no flags or public key, neutral culture
+        self.tables.push(
+            TableId::Assembly,
+            [0x8004, 0, 0, 0, 0, 0, 0, module_name, 0],
+        );
+        let module_type = self.strings.alloc("<Module>");
+        self.tables
+            .push(TableId::TypeDef, [0, module_type, 0, 0, 1, 1]);
+        let mut field_row = 1;
+        let mut method_row = 1;
+        for (type_row, (_, def)) in (2..).zip(class_defs.iter()) {
+            let mut flags = match def.access() {
+                Access::Extern | Access::Public => 0x1,
+                Access::Private => 0x0,
+            };
+            if def.is_valuetype() {
+                // Sealed
+                flags |= 0x100;
+            }
+            if def.has_explicit_layout() {
+                flags |= 0x10;
+            }
+            let extends = if let Some(parent) = def.extends() {
+                self.class_ref(parent)
+            } else if def.is_valuetype() {
+                self.system_type("System.ValueType")
+            } else {
+                self.system_type("System.Object")
+            };
+            let extends = CodedIndex::TypeDefOrRef.encode(extends.0, extends.1);
+            let (namespace, name) = split_name(asm.get_string(def.name()));
+            let name = self.strings.alloc(name);
+            let namespace = self.strings.alloc(namespace);
+            let row = self.tables.push(
+                TableId::TypeDef,
+                [flags, name, namespace, extends, field_row, method_row],
+            );
+            debug_assert_eq!(row, type_row);
+            field_row += u32::try_from(def.fields().len() + def.static_fields().len()).unwrap();
+            method_row += u32::try_from(def.methods().len()).unwrap();
+            if let Some(size) = def.explict_size() {
+                self.tables
+                    .push(TableId::ClassLayout, [0, size.get(), type_row]);
+            }
+            for interface in def.implements() {
+                let interface = self.class_ref(*interface);
+                let interface = CodedIndex::TypeDefOrRef.encode(interface.0, interface.1);
+                self.tables
+                    .push(TableId::InterfaceImpl, [type_row, interface]);
+            }
+        }
+        for (idx, def) in &class_defs {
+            self.define_fields(*idx, def);
+        }
+        let mut entrypoint = 0;
+        for (_, def) in &class_defs {
+            for method in def.methods() {
+                let token = self.define_method(*method);
+                if asm.get_string(asm.method_def(*method).name()).as_ref() == "entrypoint" {
+                    entrypoint = token;
+                }
+            }
+        }
+        entrypoint
+    }
+    fn define_fields(&mut self, idx: ClassDefIdx, def: &super::ClassDef) {
+        // Fields are public, so that they can be accessed from other assemblies.
+        for (tpe, name, offset) in def.fields() {
+            let sig = self.field_sig_blob(tpe);
+            let name_idx = self.strings.alloc(self.asm.get_string(*name));
+            let row = self.tables.push(TableId::Field, [0x0006, name_idx, sig]);
+            debug_assert_eq!(self.field_defs[&(idx.0, *name, *tpe, false)], row);
+            if let Some(offset) = offset {
+                self.tables.push(TableId::FieldLayout, [*offset, row]);
+            }
+        }
+        for (tpe, name, thread_local) in def.static_fields() {
+            let sig = self.field_sig_blob(tpe);
+            let name_idx = self.strings.alloc(self.asm.get_string(*name));
+            let row = self
+                .tables
+                .push(TableId::Field, [0x0006 | 0x0010, name_idx, sig]);
+            if *thread_local {
+                let attr = self.system_type("System.ThreadStaticAttribute");
+                let sig = self.blobs.alloc(&[0x20, 0x00, element::VOID]);
+                let ctor = self.member_ref(attr, ".ctor", sig);
+                let value = self.blobs.alloc(&[0x01, 0x00, 0x00, 0x00]);
+                self.tables.push(
+                    TableId::CustomAttribute,
+                    [
+                        CodedIndex::HasCustomAttribute.encode(TableId::Field, row),
+                        CodedIndex::CustomAttributeType
+                            .encode(TableId::MemberRef, ctor & 0x00FF_FFFF),
+                        value,
+                    ],
+                );
+            }
+        }
+    }
+    /// Emits a method definition, its parameters and its body. Returns its token.
+    fn define_method(&mut self, idx: MethodDefIdx) -> u32 {
+        let asm = self.asm;
+        let method = asm.method_def(idx);
+        let name = asm.get_string(method.name());
+        let sig = asm.get_sig(method.sig()).clone();
+        assert_eq!(method.arg_names().len(), sig.inputs().len(), "{name:?}");
+        let mut flags: u32 = match method.access() {
+            Access::Extern | Access::Public => 0x0006,
+            Access::Private => 0x0001,
+        };
+        // HideBySig
+        flags |= 0x0080;
+        match method.kind() {
+            MethodKind::Static => flags |= 0x0010,
+            MethodKind::Instance => (),
+            MethodKind::Virtual => flags |= 0x0040,
+            MethodKind::Constructor => flags |= 0x0800 | 0x1000,
+        }
+        if matches!(name.as_ref(), ".ctor" | ".cctor") {
+            flags |= 0x0800 | 0x1000;
+        }
+        let mut impl_flags = 0;
+        let mimpl = method.resolved_implementation(asm);
+        let (rva, debug_info) = if let MethodImpl::Extern {
+            lib,
+            preserve_errno,
+        } = mimpl
+        {
+            // PinvokeImpl
+            flags |= 0x2000;
+            // PreserveSig
+            impl_flags |= 0x0080;
+            let module_ref = match self.module_refs.get(lib) {
+                Some(row) => *row,
+                None => {
+                    let lib_name = self.strings.alloc(asm.get_string(*lib));
+                    let row = self.tables.push(TableId::ModuleRef, [lib_name]);
+                    self.module_refs.insert(*lib, row);
+                    row
+                }
+            };
+            let row = self.method_defs[&idx];
+            // CallConvCdecl, optionally SupportsLastError
+            let mapping = 0x0200 | if *preserve_errno { 0x0040 } else { 0 };
+            let import_name = self.strings.alloc(name);
+            self.tables.push(
+                TableId::ImplMap,
+                [
+                    mapping,
+                    CodedIndex::MemberForwarded.encode(TableId::MethodDef, row),
+                    import_name,
+                    module_ref,
+                ],
+            );
+            (0, None)
+        } else {
+            let (body, debug_info) = self.method_body(mimpl, name);
+            // Fat headers must be 4 byte aligned.
+            while !self.bodies.len().is_multiple_of(4) {
+                self.bodies.push(0);
+            }
+            let rva = pe::BODIES_RVA + u32::try_from(self.bodies.len()).unwrap();
+            self.bodies.extend(body);
+            (rva, debug_info)
+        };
+        let name_idx = self.strings.alloc(name);
+        let sig = self.method_sig_blob(&sig, method.kind(), 0);
+        let param_list = self.tables.len(TableId::Param) + 1;
+        let row = self.tables.push(
+            TableId::MethodDef,
+            [rva, impl_flags, flags, name_idx, sig, param_list],
+        );
+        debug_assert_eq!(self.method_defs[&idx], row);
+        self.pdb.add_method(row, debug_info.as_ref());
+        // The names of arguments. For instance methods, the first argument is `this`, and can't be named.
+        let arg_names = match method.kind() {
+            MethodKind::Static => method.arg_names(),
+            _ => &method.arg_names()[1..],
+        };
+        for (sequence, name) in (1..).zip(arg_names) {
+            if let Some(name) = name {
+                let name = self.strings.alloc(asm.get_string(*name));
+                self.tables.push(TableId::Param, [0, sequence, name]);
+            }
+        }
+        TableId::MethodDef.token(row)
+    }
+    /// Encodes the body of a method, returning it and its debug info.
+    fn method_body(
+        &mut self,
+        mimpl: &MethodImpl,
+        name: &str,
+    ) -> (Vec<u8>, Option<MethodDebugInfo>) {
+        let mut writer = ILWriter::default();
+        match mimpl {
+            MethodImpl::MethodBody { blocks, locals } => {
+                let locals_token = if locals.is_empty() {
+                    0
+                } else {
+                    let mut sig = vec![0x07];
+                    heaps::write_compressed_u32(u32::try_from(locals.len()).unwrap(), &mut sig);
+                    for (_, tpe) in locals {
+                        self.encode_non_void(self.asm.get_type(*tpe), &mut sig);
+                    }
+                    self.standalone_sig(&sig)
+                };
+                let max_stack = blocks
+                    .iter()
+                    .flat_map(|block| block.roots().iter())
+                    .map(|root| {
+                        CILIter::new(self.asm.get_root(*root).clone(), self.asm).count() + 10
+                    })
+                    .max()
+                    .unwrap_or(0);
+                let mut clauses = Vec::new();
+                for block in blocks {
+                    let try_start = writer.offset();
+                    writer.mark(Label::Block(block.block_id()));
+                    for root in block.roots() {
+                        self.export_root(&mut writer, *root, false, locals);
+                    }
+                    let Some(handler) = block.handler() else {
+                        continue;
+                    };
+                    let try_end = writer.offset();
+                    // Check for the GetException intrinsic. If it is not used, pop the exception.
+                    if !handler
+                        .iter()
+                        .flat_map(super::basic_block::BasicBlock::roots)
+                        .flat_map(|root| CILIter::new(self.asm.get_root(*root).clone(), self.asm))
+                        .any(|elem| matches!(elem, CILIterElem::Node(CILNode::GetException)))
+                    {
+                        writer.op(op::POP);
+                    }
+                    for hblock in handler {
+                        writer.mark(Label::Handler(block.block_id(), hblock.block_id()));
+                        for root in hblock.roots() {
+                            self.export_root(&mut writer, *root, true, locals);
+                        }
+                    }
+                    let (table, row) = self.system_type("System.Object");
+                    clauses.push(ExceptionClause {
+                        try_start,
+                        try_end,
+                        handler_start: try_end,
+                        handler_end: writer.offset(),
+                        class_token: table.token(row),
+                    });
+                }
+                let max_stack = u16::try_from(max_stack).unwrap_or(u16::MAX);
+                let code = writer.finish();
+                let code_size = u32::try_from(code.len()).unwrap();
+                let mut sequence_points = std::mem::take(&mut self.sequence_points);
+                sequence_points.retain(|point| point.il_offset() < code_size);
+                let debug_info = MethodDebugInfo {
+                    local_sig: locals_token & 0x00FF_FFFF,
+                    sequence_points,
+                    locals: (0..=u16::MAX)
+                        .zip(locals)
+                        .filter_map(|(slot, (name, _))| {
+                            Some((slot, self.asm.get_string((*name)?).to_string()))
+                        })
+                        .collect(),
+                    code_size,
+                };
+                (
+                    il::encode_method_body(&code, max_stack, locals_token, &clauses),
+                    Some(debug_info),
+                )
+            }
+            MethodImpl::Missing => {
+                let msg = self.user_strings.alloc(&format!("missing methiod {name}"));
+                writer.op_u32(op::LDSTR, 0x7000_0000 | msg);
+                let ctor = self.exception_ctor();
+                writer.op_u32(op::NEWOBJ, ctor);
+                writer.op(op::THROW);
+                (il::encode_method_body(&writer.finish(), 3, 0, &[]), None)
+            }
+            MethodImpl::Extern { .. } => unreachable!(),
+            MethodImpl::AliasFor(_) => panic!("resolved_implementation returned `AliasFor`"),
+        }
+    }
+    fn ldstr(&mut self, writer: &mut ILWriter, msg: &str) {
+        let msg = self.user_strings.alloc(msg);
+        writer.op_u32(op::LDSTR, 0x7000_0000 | msg);
+    }
+    /// Calls `mref`. If `is_tail` is set, the call is prefixed with `tail.`, and must be followed by a `ret`.
+    fn call(
+        &mut self,
+        writer: &mut ILWriter,
+        mref: MethodRefIdx,
+        args: &[NodeIdx],
+        is_tail: bool,
+        locals: &[LocalDef],
+    ) {
+        for arg in args {
+            self.export_node(writer, *arg, locals);
+        }
+        if is_tail {
+            writer.op(op::TAIL);
+        }
+        let call_op = match self.asm.get_mref(mref).kind() {
+            MethodKind::Static | MethodKind::Instance => op::CALL,
+            MethodKind::Virtual => op::CALLVIRT,
+            MethodKind::Constructor => op::NEWOBJ,
+        };
+        let token = self.method_token(mref);
+        writer.op_u32(call_op, token);
+    }
+    fn calli(
+        &mut self,
+        writer: &mut ILWriter,
+        calli: &(NodeIdx, super::SigIdx, Box<[NodeIdx]>),
+        is_tail: bool,
+        locals: &[LocalDef],
+    ) {
+        let (fn_ptr, fn_sig, args) = calli;
+        for arg in args {
+            self.export_node(writer, *arg, locals);
+        }
+        self.export_node(writer, *fn_ptr, locals);
+        if is_tail {
+            writer.op(op::TAIL);
+        }
+        let fn_sig = self.asm[*fn_sig].clone();
+        let mut sig = Vec::new();
+        self.encode_method_sig(&fn_sig, false, 0, &mut sig);
+        let token = self.standalone_sig(&sig);
+        writer.op_u32(op::CALLI, token);
+    }
+    fn export_node(&mut self, writer: &mut ILWriter, node: NodeIdx, locals: &[LocalDef]) {
+        let node = self.asm.get_node(node).clone();
+        match node {
+            CILNode::Const(cst) => self.export_const(writer, &cst),
+            CILNode::BinOp(lhs, rhs, op) => {
+                self.export_node(writer, lhs, locals);
+                self.export_node(writer, rhs, locals);
+                writer.op(match op {
+                    BinOp::Add => op::ADD,
+                    BinOp::Eq => op::CEQ,
+                    BinOp::Sub => op::SUB,
+                    BinOp::Mul => op::MUL,
+                    BinOp::LtUn => op::CLT_UN,
+                    BinOp::Lt => op::CLT,
+                    BinOp::GtUn => op::CGT_UN,
+                    BinOp::Gt => op::CGT,
+                    BinOp::Or => op::OR,
+                    BinOp::XOr => op::XOR,
+                    BinOp::And => op::AND,
+                    BinOp::Rem => op::REM,
+                    BinOp::RemUn => op::REM_UN,
+                    BinOp::Shl => op::SHL,
+                    BinOp::Shr => op::SHR,
+                    BinOp::ShrUn => op::SHR_UN,
+                    BinOp::DivUn => op::DIV_UN,
+                    BinOp::Div => op::DIV,
+                });
+            }
+            CILNode::UnOp(arg, un) => {
+                self.export_node(writer, arg, locals);
+                match un {
+                    UnOp::Not => writer.op(op::NOT),
+                    UnOp::Neg => writer.op(op::NEG),
+                }
+            }
+            CILNode::LdLoc(loc) => match loc {
+                0..=3 => writer.op(op::LDLOC_0 + loc as u16),
+                4..=255 => writer.op_u8(op::LDLOC_S, loc as u8),
+                _ => writer.op_u16(op::LDLOC, u16::try_from(loc).unwrap()),
+            },
+            CILNode::LdLocA(loc) => match loc {
+                0..=255 => writer.op_u8(op::LDLOCA_S, loc as u8),
+                _ => writer.op_u16(op::LDLOCA, u16::try_from(loc).unwrap()),
+            },
+            CILNode::LdArg(arg) => match arg {
+                0..=3 => writer.op(op::LDARG_0 + arg as u16),
+                4..=255 => writer.op_u8(op::LDARG_S, arg as u8),
+                _ => writer.op_u16(op::LDARG, u16::try_from(arg).unwrap()),
+            },
+            CILNode::LdArgA(arg) => match arg {
+                0..=255 => writer.op_u8(op::LDARGA_S, arg as u8),
+                _ => writer.op_u16(op::LDARGA, u16::try_from(arg).unwrap()),
+            },
+            CILNode::Call(call) => self.call(writer, call.0, &call.1, false, locals),
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => {
+                self.export_node(writer, input, locals);
+                if let Int::U128 | Int::I128 = target {
+                    // 128 bit ints are valuetypes, so casting to them requires knowing the type of `input`.
+                    self.unsupported
+                        .push(format!("an int cast to {}", target.name()));
+                    return;
+                }
+                writer.op(match (target, extend) {
+                    (Int::U8 | Int::I8, ExtendKind::ZeroExtend) => op::CONV_U1,
+                    (Int::U8 | Int::I8, ExtendKind::SignExtend) => op::CONV_I1,
+                    (Int::U16 | Int::I16, ExtendKind::ZeroExtend) => op::CONV_U2,
+                    (Int::U16 | Int::I16, ExtendKind::SignExtend) => op::CONV_I2,
+                    (Int::U32 | Int::I32, ExtendKind::ZeroExtend) => op::CONV_U4,
+                    (Int::U32 | Int::I32, ExtendKind::SignExtend) => op::CONV_I4,
+                    (Int::U64 | Int::I64, ExtendKind::ZeroExtend) => op::CONV_U8,
+                    (Int::U64 | Int::I64, ExtendKind::SignExtend) => op::CONV_I8,
+                    (Int::USize | Int::ISize, ExtendKind::SignExtend) => op::CONV_I,
+                    (Int::USize | Int::ISize, ExtendKind::ZeroExtend) => op::CONV_U,
+                    (Int::U128 | Int::I128, _) => unreachable!(),
+                });
+            }
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => {
+                self.export_node(writer, input, locals);
+                match (target, is_signed) {
+                    (super::Float::F32, true) => writer.op(op::CONV_R4),
+                    (super::Float::F32, false) => {
+                        writer.op(op::CONV_R_UN);
+                        writer.op(op::CONV_R4);
+                    }
+                    (super::Float::F64, true) => writer.op(op::CONV_R8),
+                    (super::Float::F64, false) => {
+                        writer.op(op::CONV_R_UN);
+                        writer.op(op::CONV_R8);
+                    }
+                    // No instruction converts to `f16` or `f128`, so go trough a `f64`.
+                    (super::Float::F16 | super::Float::F128, is_signed) => {
+                        if !is_signed {
+                            writer.op(op::CONV_R_UN);
+                        }
+                        writer.op(op::CONV_R8);
+                        let token = self.cast_from_f64_token(target);
+                        writer.op_u32(op::CALL, token);
+                    }
+                }
+            }
+            CILNode::RefToPtr(inner) => {
+                self.export_node(writer, inner, locals);
+                writer.op(op::CONV_U);
+            }
+            CILNode::PtrCast(val, _) => self.export_node(writer, val, locals),
+            CILNode::LdFieldAdress { addr, field } => {
+                self.export_node(writer, addr, locals);
+                let token = self.field_token(field);
+                writer.op_u32(op::LDFLDA, token);
+            }
+            CILNode::LdField { addr, field } => {
+                self.export_node(writer, addr, locals);
+                let token = self.field_token(field);
+                writer.op_u32(op::LDFLD, token);
+            }
+            CILNode::LdInd {
+                addr,
+                tpe,
+                volitale,
+            } => {
+                self.export_node(writer, addr, locals);
+                let tpe = self.asm[tpe];
+                if volitale {
+                    writer.op(op::VOLATILE);
+                }
+                match tpe {
+                    Type::Ptr(_) | Type::FnPtr(_) => writer.op(op::LDIND_I),
+                    Type::PlatformGeneric(_, _) => {
+                        let token = self.type_token(&tpe);
+                        writer.op_u32(op::LDOBJ, token);
+                    }
+                    // Managed references can't be stored in memory, outside of ref fields.
+                    Type::Ref(_) => self
+                        .unsupported
+                        .push("a load of a managed reference".into()),
+                    Type::Int(int) => match int {
+                        Int::U8 => writer.op(op::LDIND_U1),
+                        Int::U16 => writer.op(op::LDIND_U2),
+                        Int::U32 => writer.op(op::LDIND_U4),
+                        Int::U64 | Int::I64 => writer.op(op::LDIND_I8),
+                        Int::USize | Int::ISize => writer.op(op::LDIND_I),
+                        Int::I8 => writer.op(op::LDIND_I1),
+                        Int::I16 => writer.op(op::LDIND_I2),
+                        Int::I32 => writer.op(op::LDIND_I4),
+                        Int::U128 | Int::I128 => {
+                            let token = self.type_token(&tpe);
+                            writer.op_u32(op::LDOBJ, token);
+                        }
+                    },
+                    Type::ClassRef(_) => {
+                        let token = self.type_token(&tpe);
+                        writer.op_u32(op::LDOBJ, token);
+                    }
+                    Type::Float(float) => match float {
+                        super::Float::F32 => writer.op(op::LDIND_R4),
+                        super::Float::F64 => writer.op(op::LDIND_R8),
+                        super::Float::F16 | super::Float::F128 => {
+                            let token = self.type_token(&tpe);
+                            writer.op_u32(op::LDOBJ, token);
+                        }
+                    },
+                    Type::PlatformString | Type::PlatformObject | Type::PlatformArray { .. } => {
+                        writer.op(op::LDIND_REF);
+                    }
+                    Type::PlatformChar => writer.op(op::LDIND_I2),
+                    Type::Bool => writer.op(op::LDIND_I1),
+                    Type::Void => panic!("Void can't be dereferenced!"),
+                }
+            }
+            CILNode::SizeOf(tpe) => {
+                let token = self.type_token(&self.asm[tpe]);
+                writer.op_u32(op::SIZEOF, token);
+            }
+            CILNode::GetException => (),
+            CILNode::IsInst(val, tpe) => {
+                self.export_node(writer, val, locals);
+                let token = self.type_token(&self.asm[tpe]);
+                writer.op_u32(op::ISINST, token);
+            }
+            CILNode::CheckedCast(val, tpe) => {
+                self.export_node(writer, val, locals);
+                let token = self.type_token(&self.asm[tpe]);
+                writer.op_u32(op::CASTCLASS, token);
+            }
+            CILNode::CallI(calli) => self.calli(writer, &calli, false, locals),
+            CILNode::LocAlloc { size } => {
+                self.export_node(writer, size, locals);
+                writer.op(op::LOCALLOC);
+            }
+            CILNode::LdStaticField(sfld) => {
+                let token = self.static_field_token(sfld);
+                writer.op_u32(op::LDSFLD, token);
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                let token = self.static_field_token(sfld);
+                writer.op_u32(op::LDSFLDA, token);
+            }
+            CILNode::LdFtn(ftn) => {
+                let token = self.method_token(ftn);
+                writer.op_u32(op::LDFTN, token);
+            }
+            CILNode::LdTypeToken(tok) => {
+                let token = self.type_token(self.asm.get_type(tok));
+                writer.op_u32(op::LDTOKEN, token);
+            }
+            CILNode::LdLen(array) => {
+                self.export_node(writer, array, locals);
+                writer.op(op::LDLEN);
+            }
+            CILNode::LocAllocAlgined { tpe, align } => {
+                let align = i64::try_from(align).expect("Alignment too big");
+                let token = self.type_token(&self.asm[tpe]);
+                // Allocate size + align bytes, and then round the address up to align.
+                writer.op_u32(op::SIZEOF, token);
+                writer.op_i64(op::LDC_I8, align);
+                writer.op(op::CONV_I);
+                writer.op(op::ADD);
+                writer.op(op::LOCALLOC);
+                writer.op(op::DUP);
+                writer.op_i64(op::LDC_I8, align);
+                writer.op(op::ADD);
+                writer.op_i64(op::LDC_I8, align);
+                writer.op(op::REM);
+                writer.op(op::SUB);
+                writer.op_i64(op::LDC_I8, align);
+                writer.op(op::ADD);
+                writer.op(op::CONV_U);
+            }
+            CILNode::LdElelemRef { array, index } => {
+                self.export_node(writer, array, locals);
+                self.export_node(writer, index, locals);
+                writer.op(op::LDELEM_REF);
+            }
+            CILNode::UnboxAny { object, tpe } => {
+                self.export_node(writer, object, locals);
+                let token = self.type_token(&self.asm[tpe]);
+                writer.op_u32(op::UNBOX_ANY, token);
+            }
+        }
+    }
+    fn export_const(&mut self, writer: &mut ILWriter, cst: &Const) {
+        match cst {
+            Const::Null(_) => writer.op(op::LDNULL),
+            Const::I8(val) => writer.ldc_i4(i32::from(*val)),
+            Const::I16(val) => writer.ldc_i4(i32::from(*val)),
+            Const::I32(val) => writer.ldc_i4(*val),
+            Const::U8(val) => writer.ldc_i4(i32::from(*val)),
+            Const::U16(val) => writer.ldc_i4(i32::from(*val)),
+            Const::U32(val) => writer.ldc_i4(*val as i32),
+            Const::I64(val) => match i32::try_from(*val) {
+                Ok(small) => {
+                    writer.ldc_i4(small);
+                    writer.op(op::CONV_I8);
+                }
+                Err(_) => writer.op_i64(op::LDC_I8, *val),
+            },
+            Const::ISize(val) => {
+                match i32::try_from(*val) {
+                    Ok(small) => writer.ldc_i4(small),
+                    Err(_) => writer.op_i64(op::LDC_I8, *val),
+                }
+                writer.op(op::CONV_I);
+            }
+            Const::U64(val) => match i32::try_from(*val) {
+                Ok(small) => {
+                    writer.ldc_i4(small);
+                    writer.op(op::CONV_U8);
+                }
+                Err(_) => writer.op_i64(op::LDC_I8, *val as i64),
+            },
+            Const::USize(val) => {
+                match i32::try_from(*val) {
+                    Ok(small) => writer.ldc_i4(small),
+                    Err(_) => writer.op_i64(op::LDC_I8, *val as i64),
+                }
+                writer.op(op::CONV_U);
+            }
+            Const::PlatformString(msg) => self.ldstr(writer, self.asm.get_string(*msg)),
+            Const::Bool(val) => writer.ldc_i4(i32::from(*val)),
+            Const::F32(float) => writer.op_bytes(op::LDC_R4, &float.to_le_bytes()),
+            Const::F64(float) => writer.op_bytes(op::LDC_R8, &float.to_le_bytes()),
+        }
+    }
+    fn branch_target(target: u32, sub_target: u32, is_handler: bool) -> Label {
+        if sub_target == 0 {
+            Label::Block(target)
+        } else if is_handler {
+            Label::Handler(target, sub_target)
+        } else {
+            Label::Jump(target, sub_target)
+        }
+    }
+    /// Exports a switch, the same way the IL exporter does.
+    fn export_switch(
+        &mut self,
+        writer: &mut ILWriter,
+        value: NodeIdx,
+        int: Int,
+        targets: &SwitchTargets,
+        is_handler: bool,
+        locals: &[LocalDef],
+    ) {
+        let (target, sub_target) = targets.otherwise();
+        let otherwise = Self::branch_target(target, sub_target, is_handler);
+        // Small signed ints are sign extended when loaded, but the cases are zero extended.
+        let extend = |writer: &mut ILWriter| match int {
+            Int::I8 => writer.op(op::CONV_U1),
+            Int::I16 => writer.op(op::CONV_U2),
+            _ => (),
+        };
+        let ldc = |writer: &mut ILWriter, bits: u64| match int.size() {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            Some(1..=4) => writer.ldc_i4(bits as u32 as i32),
+            #[allow(clippy::cast_possible_wrap)]
+            Some(_) => writer.op_i64(op::LDC_I8, bits as i64),
+            #[allow(clippy::cast_possible_wrap)]
+            None => {
+                writer.op_i64(op::LDC_I8, bits as i64);
+                writer.op(op::CONV_U);
+            }
+        };
+        match targets {
+            SwitchTargets::Dense { base, table, .. } => {
+                self.export_node(writer, value, locals);
+                let labels = table.iter().map(|(target, sub_target)| {
+                    Self::branch_target(*target, *sub_target, is_handler)
+                });
+                if matches!(int.size(), Some(1..=4)) {
+                    extend(writer);
+                    ldc(writer, *base);
+                    writer.op(op::SUB);
+                    writer.switch(&labels.collect::<Vec<_>>());
+                } else {
+                    // Out of range values get the index 0, which is `otherwise`.
+                    ldc(writer, base.wrapping_sub(1));
+                    writer.op(op::SUB);
+                    writer.op(op::DUP);
+                    ldc(writer, table.len() as u64 + 1);
+                    writer.op(op::CLT_UN);
+                    writer.op(if int.size().is_some() {
+                        op::CONV_U8
+                    } else {
+                        op::CONV_U
+                    });
+                    writer.op(op::MUL);
+                    writer.op(op::CONV_U4);
+                    writer.switch(&std::iter::once(otherwise).chain(labels).collect::<Vec<_>>());
+                }
+            }
+            SwitchTargets::Sparse { cases, .. } => {
+                for (case, (target, sub_target)) in cases.iter() {
+                    self.export_node(writer, value, locals);
+                    extend(writer);
+                    ldc(writer, *case);
+                    writer.branch(
+                        op::BEQ,
+                        Self::branch_target(*target, *sub_target, is_handler),
+                    );
+                }
+            }
+        }
+        writer.branch(op::BR, otherwise);
+    }
+    fn export_root(
+        &mut self,
+        writer: &mut ILWriter,
+        root: RootIdx,
+        is_handler: bool,
+        locals: &[LocalDef],
+    ) {
+        let root = self.asm.get_root(root).clone();
+        match root {
+            CILRoot::StLoc(loc, val) => {
+                self.export_node(writer, val, locals);
+                match loc {
+                    0..=3 => writer.op(op::STLOC_0 + loc as u16),
+                    4..=255 => writer.op_u8(op::STLOC_S, loc as u8),
+                    _ => writer.op_u16(op::STLOC, u16::try_from(loc).unwrap()),
+                }
+            }
+            CILRoot::StArg(arg, val) => {
+                self.export_node(writer, val, locals);
+                match arg {
+                    0..=255 => writer.op_u8(op::STARG_S, arg as u8),
+                    _ => writer.op_u16(op::STARG, u16::try_from(arg).unwrap()),
+                }
+            }
+            CILRoot::Ret(val) => {
+                self.export_node(writer, val, locals);
+                writer.op(op::RET);
+            }
+            CILRoot::Pop(val) => {
+                self.export_node(writer, val, locals);
+                writer.op(op::POP);
+            }
+            CILRoot::Throw(val) => {
+                self.export_node(writer, val, locals);
+                writer.op(op::THROW);
+            }
+            CILRoot::VoidRet => writer.op(op::RET),
+            CILRoot::Break => writer.op(op::BREAK),
+            CILRoot::Nop => writer.op(op::NOP),
+            CILRoot::Branch(branch) => {
+                let (target, sub_target, cond) = branch.as_ref();
+                let label = Self::branch_target(*target, *sub_target, is_handler);
+                let (nodes, branch_op): (&[NodeIdx], u16) = match cond {
+                    Some(BranchCond::Eq(a, b)) => (&[*a, *b], op::BEQ),
+                    Some(BranchCond::Ne(a, b)) => (&[*a, *b], op::BNE_UN),
+                    Some(BranchCond::Lt(a, b, kind)) => (
+                        &[*a, *b],
+                        match kind {
+                            CmpKind::Ordered | CmpKind::Signed => op::BLT,
+                            CmpKind::Unordered | CmpKind::Unsigned => op::BLT_UN,
+                        },
+                    ),
+                    Some(BranchCond::Gt(a, b, kind)) => (
+                        &[*a, *b],
+                        match kind {
+                            CmpKind::Ordered | CmpKind::Signed => op::BGT,
+                            CmpKind::Unordered | CmpKind::Unsigned => op::BGT_UN,
+                        },
+                    ),
+                    Some(BranchCond::Le(a, b, kind)) => (
+                        &[*a, *b],
+                        match kind {
+                            CmpKind::Ordered | CmpKind::Signed => op::BLE,
+                            CmpKind::Unordered | CmpKind::Unsigned => op::BLE_UN,
+                        },
+                    ),
+                    Some(BranchCond::Ge(a, b, kind)) => (
+                        &[*a, *b],
+                        match kind {
+                            CmpKind::Ordered | CmpKind::Signed => op::BGE,
+                            CmpKind::Unordered | CmpKind::Unsigned => op::BGE_UN,
+                        },
+                    ),
+                    Some(BranchCond::True(cond)) => (&[*cond], op::BRTRUE),
+                    Some(BranchCond::False(cond)) => (&[*cond], op::BRFALSE),
+                    None => {
+                        if *sub_target == 0 || is_handler {
+                            writer.branch(op::BR, label);
+                        } else {
+                            // If it is not a handler, then this is the only block in this try, then all jumps are extern, then we can just use leave
+                            writer.branch(op::LEAVE, Label::Block(*sub_target));
+                        }
+                        return;
+                    }
+                };
+                for node in nodes {
+                    self.export_node(writer, *node, locals);
+                }
+                writer.branch(branch_op, label);
+            }
+            CILRoot::Switch(switch) => {
+                let (value, int, targets) = *switch;
+                self.export_switch(writer, value, int, &targets, is_handler, locals);
+            }
+            CILRoot::SourceFileInfo {
+                line_start,
+                line_len,
+                col_start,
+                col_len,
+                file,
+            } => {
+                let document = self.pdb.document(self.asm.get_string(file));
+                let offset = writer.offset();
+                // If multiple roots describe the same code, only the last one matters.
+                if self
+                    .sequence_points
+                    .last()
+                    .is_some_and(|point| point.il_offset() == offset)
+                {
+                    self.sequence_points.pop();
+                }
+                self.sequence_points.extend(SequencePoint::new(
+                    offset, document, line_start, line_len, col_start, col_len,
+                ));
+            }
+            CILRoot::SetField(info) => {
+                self.export_node(writer, info.1, locals);
+                self.export_node(writer, info.2, locals);
+                let token = self.field_token(info.0);
+                writer.op_u32(op::STFLD, token);
+            }
+            CILRoot::Call(call) => {
+                assert_ne!(
+                    self.asm.get_mref(call.0).kind(),
+                    MethodKind::Constructor,
+                    "A constructor can't be a CIL root"
+                );
+                self.call(writer, call.0, &call.1, false, locals);
+            }
+            CILRoot::TailCall(call) => {
+                self.call(writer, call.0, &call.1, true, locals);
+                writer.op(op::RET);
+            }
+            CILRoot::TailCallI(calli) => {
+                self.calli(writer, &calli, true, locals);
+                writer.op(op::RET);
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, is_volitale) = info.as_ref();
+                self.export_node(writer, *addr, locals);
+                self.export_node(writer, *val, locals);
+                if *is_volitale {
+                    writer.op(op::VOLATILE);
+                }
+                match tpe {
+                    Type::Ptr(_) | Type::FnPtr(_) => writer.op(op::STIND_I),
+                    Type::PlatformGeneric(_, _) => {
+                        let token = self.type_token(tpe);
+                        writer.op_u32(op::STOBJ, token);
+                    }
+                    Type::Ref(_) => self
+                        .unsupported
+                        .push("a store of a managed reference".into()),
+                    Type::Int(int) => match int {
+                        Int::U8 | Int::I8 => writer.op(op::STIND_I1),
+                        Int::U16 | Int::I16 => writer.op(op::STIND_I2),
+                        Int::U32 | Int::I32 => writer.op(op::STIND_I4),
+                        Int::U64 | Int::I64 => writer.op(op::STIND_I8),
+                        Int::USize | Int::ISize => writer.op(op::STIND_I),
+                        Int::U128 | Int::I128 => {
+                            let token = self.type_token(tpe);
+                            writer.op_u32(op::STOBJ, token);
+                        }
+                    },
+                    Type::ClassRef(cref) => {
+                        if self.asm.class_ref(*cref).is_valuetype() {
+                            let token = self.type_token(tpe);
+                            writer.op_u32(op::STOBJ, token);
+                        } else {
+                            writer.op(op::STIND_REF);
+                        }
+                    }
+                    Type::Float(float) => match float {
+                        super::Float::F32 => writer.op(op::STIND_R4),
+                        super::Float::F64 => writer.op(op::STIND_R8),
+                        super::Float::F16 | super::Float::F128 => {
+                            let token = self.type_token(tpe);
+                            writer.op_u32(op::STOBJ, token);
+                        }
+                    },
+                    Type::PlatformString | Type::PlatformObject | Type::PlatformArray { .. } => {
+                        writer.op(op::STIND_REF);
+                    }
+                    Type::PlatformChar => writer.op(op::STIND_I2),
+                    Type::Bool => writer.op(op::STIND_I1),
+                    Type::Void => {
+                        // TODO: forbid this, since this is NEVER valid.
+                        writer.op(op::POP);
+                        writer.op(op::POP);
+                        self.ldstr(writer, "Attempted to wrtie to a zero-sized type(void).");
+                        let ctor = self.exception_ctor();
+                        writer.op_u32(op::NEWOBJ, ctor);
+                        writer.op(op::THROW);
+                    }
+                }
+            }
+            CILRoot::InitBlk(blk) => {
+                self.export_node(writer, blk.0, locals);
+                self.export_node(writer, blk.1, locals);
+                self.export_node(writer, blk.2, locals);
+                writer.op(op::INITBLK);
+            }
+            CILRoot::CpBlk(blk) => {
+                self.export_node(writer, blk.0, locals);
+                self.export_node(writer, blk.1, locals);
+                self.export_node(writer, blk.2, locals);
+                writer.op(op::CPBLK);
+            }
+            CILRoot::CallI(calli) => self.calli(writer, &calli, false, locals),
+            CILRoot::ExitSpecialRegion { target, source } => {
+                if is_handler {
+                    writer.mark(Label::Handler(source, target));
+                } else {
+                    writer.mark(Label::Jump(source, target));
+                }
+                writer.branch(op::LEAVE, Label::Block(target));
+            }
+            CILRoot::ReThrow => writer.op(op::RETHROW),
+            CILRoot::SetStaticField { field, val } => {
+                self.export_node(writer, val, locals);
+                let token = self.static_field_token(field);
+                writer.op_u32(op::STSFLD, token);
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                self.export_node(writer, src, locals);
+                self.export_node(writer, dst, locals);
+                let token = self.type_token(&self.asm[tpe]);
+                writer.op_u32(op::CPOBJ, token);
+            }
+            CILRoot::Unreachable(msg) => {
+                self.ldstr(writer, &self.asm[msg]);
+                let ctor = self.exception_ctor();
+                writer.op_u32(op::NEWOBJ, ctor);
+                writer.op(op::THROW);
+            }
+        }
+    }
+    /// Calculates a stable module version ID, derived from the name and contents of this assembly.
+    fn mvid(&self) -> [u8; 16] {
+        let mut hasher = FxHasher::default();
+        self.name.hash(&mut hasher);
+        self.asm.method_defs().len().hash(&mut hasher);
+        self.asm.class_defs().len().hash(&mut hasher);
+        let low = hasher.finish();
+        low.hash(&mut hasher);
+        let high = hasher.finish();
+        let mut mvid = [0; 16];
+        mvid[..8].copy_from_slice(&low.to_le_bytes());
+        mvid[8..].copy_from_slice(&high.to_le_bytes());
+        // Mark it as a version 4(random) GUID.
+        mvid[7] = (mvid[7] & 0x0F) | 0x40;
+        mvid[8] = (mvid[8] & 0x3F) | 0x80;
+        mvid
+    }
+    /// Returns the method bodies, the metadata, and the Portable PDB, or an error listing the constructs which could not be encoded.
+    fn finish(self, entrypoint: u32) -> std::io::Result<(Vec<u8>, Vec<u8>, Pdb)> {
+        if !self.unsupported.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "The PE exporter can't encode: {}",
+                    self.unsupported.join(", ")
+                ),
+            ));
+        }
+        let pdb = self.pdb.finish(&self.tables, entrypoint);
+        let tables = self.tables.encode(&self.strings, &self.blobs, &self.guids);
+        let metadata = pe::encode_metadata(
+            &tables,
+            self.strings.data(),
+            self.user_strings.data(),
+            self.guids.data(),
+            self.blobs.data(),
+        );
+        Ok((self.bodies, metadata, pdb))
+    }
+}
+#[test]
+fn export_hello() {
+    use super::{BasicBlock, ClassRef, MethodDef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let console = ClassRef::console(&mut asm);
+    let write_line = asm.alloc_string("WriteLine");
+    let write_line = asm.class_ref(console).clone().static_mref(
+        &[Type::PlatformString],
+        Type::Void,
+        write_line,
+        &mut asm,
+    );
+    let msg = asm.alloc_string("Hello!");
+    let msg = asm.alloc_node(Const::PlatformString(msg));
+    let call = asm.alloc_root(CILRoot::Call(Box::new((write_line, [msg].into()))));
+    let ret = asm.alloc_root(CILRoot::VoidRet);
+    let sig = asm.sig([], Type::Void);
+    let name = asm.alloc_string("entrypoint");
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![call, ret], 0, None)],
+            locals: vec![],
+        },
+        vec![],
+    ));
+    let image = PEExporter::new(false)
+        .export_to_bytes(&asm, "hello")
+        .unwrap();
+    assert_eq!(&image[0..2], b"MZ");
+    // The code of the entrypoint: ldstr, call, ret
+    let code = [0x72, 0x01, 0x00, 0x00, 0x70, 0x28];
+    assert!(image.windows(code.len()).any(|window| window == code));
+    // The string literal is stored as UTF-16
+    let hello: Vec<u8> = "Hello!".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    assert!(image.windows(hello.len()).any(|window| window == hello));
+    assert!(image.windows(8).any(|window| window == b"System.C"));
+}
+#[test]
+fn export_unsupported() {
+    use super::{BasicBlock, MethodDef};
+    let mut asm = Assembly::default();
+    let main_module = asm.main_module();
+    let arg = asm.alloc_node(CILNode::LdArg(0));
+    let cast = asm.alloc_node(CILNode::IntCast {
+        input: arg,
+        target: Int::I128,
+        extend: ExtendKind::SignExtend,
+    });
+    let ret = asm.alloc_root(CILRoot::Ret(cast));
+    let sig = asm.sig([Type::Int(Int::I64)], Type::Int(Int::I128));
+    let name = asm.alloc_string("widen");
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody {
+            blocks: vec![BasicBlock::new(vec![ret], 0, None)],
+            locals: vec![],
+        },
+        vec![None],
+    ));
+    // Casts to 128 bit ints can't be encoded, so the export fails, naming the construct.
+    let err = PEExporter::new(true)
+        .export_to_bytes(&asm, "widen")
+        .unwrap_err();
+    assert!(err.to_string().contains("int cast to i128"), "{err}");
+}
@@ -0,0 +1,324 @@
+//! Writes the debug info of an assembly as a Portable PDB.
+//!
+//! A Portable PDB is a metadata file with its own `#~` stream, containing only the debug tables, and an extra `#Pdb` stream, which refers to the
+//! type system tables of the assembly it describes.
+use std::hash::{Hash, Hasher};
+
+use fxhash::{FxHashMap, FxHasher};
+
+use super::{
+    heaps::{self, BlobHeap, GuidHeap, StringHeap, UserStringHeap},
+    pe,
+    tables::{TableId, Tables},
+};
+/// Version string of the metadata root of a Portable PDB.
+const PDB_VERSION: &str = "PDB v1.0";
+/// Maps a range of IL code to a range of source code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequencePoint {
+    il_offset: u32,
+    document: u32,
+    start_line: u32,
+    end_line: u32,
+    start_col: u32,
+    end_col: u32,
+}
+impl SequencePoint {
+    /// Creates a new sequence point, starting at `il_offset`. Returns `None` if the source location can't be represented in a PDB.
+    #[must_use]
+    pub fn new(
+        il_offset: u32,
+        document: u32,
+        line_start: u32,
+        line_len: u16,
+        col_start: u16,
+        col_len: u16,
+    ) -> Option<Self> {
+        let end_line = line_start.checked_add(u32::from(line_len))?;
+        // 0xFEEFEE marks hidden sequence points, and can't be used as a line number.
+        if line_start == 0 || line_start == 0xFEEFEE || end_line >= 0x2000_0000 {
+            return None;
+        }
+        let start_col = u32::from(col_start);
+        // A single line sequence point must span at least one column.
+        let col_len = if line_len == 0 {
+            col_len.max(1)
+        } else {
+            col_len
+        };
+        let end_col = start_col + u32::from(col_len);
+        if end_col > 0xFFFF {
+            return None;
+        }
+        Some(Self {
+            il_offset,
+            document,
+            start_line: line_start,
+            end_line,
+            start_col,
+            end_col,
+        })
+    }
+    #[must_use]
+    pub fn il_offset(&self) -> u32 {
+        self.il_offset
+    }
+}
+/// The debug info of a single method.
+#[derive(Default)]
+pub struct MethodDebugInfo {
+    /// The `StandAloneSig` row of the locals signature, or 0 if the method has no locals.
+    pub local_sig: u32,
+    /// Sequence points, ordered by their IL offset.
+    pub sequence_points: Vec<SequencePoint>,
+    /// Slots and names of the named locals.
+    pub locals: Vec<(u16, String)>,
+    /// The size of the IL code of this method.
+    pub code_size: u32,
+}
+/// An encoded Portable PDB.
+pub struct Pdb {
+    /// Identifies this PDB: a GUID, followed by a timestamp. The image referring to this PDB contains the same id.
+    pub id: [u8; 20],
+    pub data: Vec<u8>,
+}
+/// Builds the debug tables of a Portable PDB.
+#[derive(Default)]
+pub struct PdbBuilder {
+    tables: Tables,
+    strings: StringHeap,
+    blobs: BlobHeap,
+    guids: GuidHeap,
+    documents: FxHashMap<String, u32>,
+    root_import_scope: u32,
+}
+impl PdbBuilder {
+    /// Returns the `Document` row of the source file at `path`.
+    pub fn document(&mut self, path: &str) -> u32 {
+        if let Some(row) = self.documents.get(path) {
+            return *row;
+        }
+        // The name is stored as a list of blobs, separated by a single character.
+        let separator = if path.contains('/') {
+            Some('/')
+        } else if path.contains('\\') {
+            Some('\\')
+        } else {
+            None
+        };
+        let mut name = Vec::new();
+        match separator {
+            Some(separator) => {
+                name.push(separator as u8);
+                for part in path.split(separator) {
+                    let part = self.blobs.alloc(part.as_bytes());
+                    heaps::write_compressed_u32(part, &mut name);
+                }
+            }
+            None => {
+                name.push(0);
+                let part = self.blobs.alloc(path.as_bytes());
+                heaps::write_compressed_u32(part, &mut name);
+            }
+        }
+        let name = self.blobs.alloc(&name);
+        // No hash, and no language: there is no well-known GUID for Rust.
+        let row = self.tables.push(TableId::Document, [name, 0, 0, 0]);
+        self.documents.insert(path.to_owned(), row);
+        row
+    }
+    /// Adds the debug info of the method at `method_row`. Methods must be added in the order of their rows, including
+    /// methods without any debug info.
+    pub fn add_method(&mut self, method_row: u32, info: Option<&MethodDebugInfo>) {
+        debug_assert_eq!(
+            self.tables.len(TableId::MethodDebugInformation) + 1,
+            method_row
+        );
+        let Some(info) = info else {
+            self.tables.push(TableId::MethodDebugInformation, [0, 0]);
+            return;
+        };
+        let (document, sequence_points) = self.sequence_points_blob(info);
+        self.tables
+            .push(TableId::MethodDebugInformation, [document, sequence_points]);
+        if info.locals.is_empty() {
+            return;
+        }
+        if self.root_import_scope == 0 {
+            self.root_import_scope = self.tables.push(TableId::ImportScope, [0, 0]);
+        }
+        let variable_list = self.tables.len(TableId::LocalVariable) + 1;
+        let constant_list = self.tables.len(TableId::LocalConstant) + 1;
+        self.tables.push(
+            TableId::LocalScope,
+            [
+                method_row,
+                self.root_import_scope,
+                variable_list,
+                constant_list,
+                0,
+                info.code_size,
+            ],
+        );
+        for (slot, name) in &info.locals {
+            let name = self.strings.alloc(name);
+            self.tables
+                .push(TableId::LocalVariable, [0, u32::from(*slot), name]);
+        }
+    }
+    /// Encodes the sequence points of a method(Portable PDB spec, "Sequence Points Blob"). Returns the document of the method, and the blob.
+    fn sequence_points_blob(&mut self, info: &MethodDebugInfo) -> (u32, u32) {
+        let Some(first) = info.sequence_points.first() else {
+            return (0, 0);
+        };
+        let single_document = info
+            .sequence_points
+            .iter()
+            .all(|point| point.document == first.document);
+        let mut blob = Vec::new();
+        heaps::write_compressed_u32(info.local_sig, &mut blob);
+        if !single_document {
+            heaps::write_compressed_u32(first.document, &mut blob);
+        }
+        let mut prev: Option<&SequencePoint> = None;
+        for point in &info.sequence_points {
+            match prev {
+                Some(prev) => {
+                    debug_assert!(point.il_offset > prev.il_offset);
+                    if point.document != prev.document {
+                        blob.push(0);
+                        heaps::write_compressed_u32(point.document, &mut blob);
+                    }
+                    heaps::write_compressed_u32(point.il_offset - prev.il_offset, &mut blob);
+                }
+                None => heaps::write_compressed_u32(point.il_offset, &mut blob),
+            }
+            let delta_lines = point.end_line - point.start_line;
+            heaps::write_compressed_u32(delta_lines, &mut blob);
+            if delta_lines == 0 {
+                heaps::write_compressed_u32(point.end_col - point.start_col, &mut blob);
+            } else {
+                heaps::write_compressed_i32(
+                    point.end_col as i32 - point.start_col as i32,
+                    &mut blob,
+                );
+            }
+            match prev {
+                Some(prev) => {
+                    heaps::write_compressed_i32(
+                        point.start_line as i32 - prev.start_line as i32,
+                        &mut blob,
+                    );
+                    heaps::write_compressed_i32(
+                        point.start_col as i32 - prev.start_col as i32,
+                        &mut blob,
+                    );
+                }
+                None => {
+                    heaps::write_compressed_u32(point.start_line, &mut blob);
+                    heaps::write_compressed_u32(point.start_col, &mut blob);
+                }
+            }
+            prev = Some(point);
+        }
+        let document = if single_document { first.document } else { 0 };
+        (document, self.blobs.alloc(&blob))
+    }
+    /// Encodes the PDB, describing an assembly with the type system tables `assembly_tables`.
+    #[must_use]
+    pub fn finish(mut self, assembly_tables: &Tables, entrypoint: u32) -> Pdb {
+        let mut referenced = 0_u64;
+        let mut row_counts = Vec::new();
+        for (table, rows) in assembly_tables.row_counts() {
+            self.tables.set_external_len(table, rows);
+            referenced |= 1 << (table as u64);
+            row_counts.extend(rows.to_le_bytes());
+        }
+        let tables = self.tables.encode(&self.strings, &self.blobs, &self.guids);
+        // The id only depends on the contents of the PDB, so that builds are deterministic.
+        let mut hasher = FxHasher::default();
+        tables.hash(&mut hasher);
+        self.strings.data().hash(&mut hasher);
+        self.blobs.data().hash(&mut hasher);
+        let low = hasher.finish();
+        row_counts.hash(&mut hasher);
+        let high = hasher.finish();
+        let mut id = [0; 20];
+        id[..8].copy_from_slice(&low.to_le_bytes());
+        id[8..16].copy_from_slice(&high.to_le_bytes());
+        id[7] = (id[7] & 0x0F) | 0x40;
+        id[8] = (id[8] & 0x3F) | 0x80;
+        // The stamp is the upper 4 bytes of the hash, just like the one in the debug directory.
+        id[16..].copy_from_slice(&high.to_le_bytes()[4..]);
+        let mut pdb_stream = id.to_vec();
+        pdb_stream.extend(entrypoint.to_le_bytes());
+        pdb_stream.extend(referenced.to_le_bytes());
+        pdb_stream.extend(row_counts);
+        let metadata = pe::encode_metadata_root(
+            PDB_VERSION,
+            &[
+                ("#Pdb", &pdb_stream),
+                ("#~", &tables),
+                ("#Strings", self.strings.data()),
+                ("#US", UserStringHeap::default().data()),
+                ("#GUID", self.guids.data()),
+                ("#Blob", self.blobs.data()),
+            ],
+        );
+        Pdb { id, data: metadata }
+    }
+}
+#[test]
+fn sequence_points() {
+    let mut pdb = PdbBuilder::default();
+    let doc = pdb.document("/src/main.rs");
+    assert_eq!(pdb.document("/src/main.rs"), doc);
+    let info = MethodDebugInfo {
+        local_sig: 1,
+        sequence_points: vec![
+            SequencePoint::new(0, doc, 5, 0, 4, 6).unwrap(),
+            SequencePoint::new(3, doc, 6, 1, 8, 2).unwrap(),
+        ],
+        locals: vec![(0, "x".into())],
+        code_size: 10,
+    };
+    let (document, blob) = pdb.sequence_points_blob(&info);
+    assert_eq!(document, doc);
+    let blob = &pdb.blobs.data()[blob as usize..];
+    // Length, local sig, then: offset 0, 0 lines, 6 columns, line 5, column 4, then: offset +3, 1 line, +2 columns, line +1, column +4
+    assert_eq!(blob[..12], [11, 1, 0, 0, 6, 5, 4, 3, 1, 4, 2, 8]);
+    assert!(SequencePoint::new(0, doc, 0, 0, 1, 1).is_none());
+    // A zero-width point still covers a column.
+    assert_eq!(SequencePoint::new(0, doc, 1, 0, 1, 0).unwrap().end_col, 2);
+}
+#[test]
+fn pdb_stream() {
+    let mut pdb = PdbBuilder::default();
+    let doc = pdb.document("lib.rs");
+    pdb.add_method(1, None);
+    pdb.add_method(
+        2,
+        Some(&MethodDebugInfo {
+            local_sig: 0,
+            sequence_points: vec![SequencePoint::new(0, doc, 1, 0, 1, 1).unwrap()],
+            locals: vec![(1, "y".into())],
+            code_size: 1,
+        }),
+    );
+    let mut assembly = Tables::default();
+    assembly.push(TableId::MethodDef, [0, 0, 0, 0, 0, 0]);
+    assembly.push(TableId::MethodDef, [0, 0, 0, 0, 0, 0]);
+    let Pdb { id, data: metadata } = pdb.finish(&assembly, TableId::MethodDef.token(2));
+    assert_eq!(&metadata[0..4], b"BSJB");
+    assert!(metadata.windows(8).any(|window| window == b"PDB v1.0"));
+    // The #Pdb stream starts with the id, followed by the entrypoint.
+    let start = metadata
+        .windows(20)
+        .position(|window| window == id)
+        .unwrap();
+    assert_eq!(
+        metadata[start + 20..start + 24],
+        0x0600_0002_u32.to_le_bytes()
+    );
+    assert!(metadata.windows(2).any(|window| window == b"y\0"));
+}
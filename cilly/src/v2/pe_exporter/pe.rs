@@ -0,0 +1,358 @@
+//! Writes the PE/COFF container(II.25) around the CLI metadata and method bodies.
+const FILE_ALIGNMENT: u32 = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x2000;
+/// RVA of the `.text` section.
+const TEXT_RVA: u32 = 0x2000;
+/// Size of the import address table at the start of `.text`.
+const IAT_SIZE: u32 = 8;
+/// Size of the CLI header.
+const CLI_HEADER_SIZE: u32 = 72;
+/// RVA of the first method body. Method bodies are placed right after the IAT and the CLI header.
+pub const BODIES_RVA: u32 = TEXT_RVA + IAT_SIZE + CLI_HEADER_SIZE;
+const RUNTIME_VERSION: &str = "v4.0.30319";
+/// Size of a single entry in the debug directory.
+const DEBUG_DIRECTORY_SIZE: u32 = 28;
+/// Size of the headers, rounded up to the file alignment.
+const HEADERS_SIZE: u32 = 0x200;
+
+fn align(val: u32, alignment: u32) -> u32 {
+    val.div_ceil(alignment) * alignment
+}
+fn pad_to(out: &mut Vec<u8>, alignment: usize) {
+    while !out.len().is_multiple_of(alignment) {
+        out.push(0);
+    }
+}
+/// Builds the metadata root(II.24.2.1), and all the metadata streams.
+#[must_use]
+pub fn encode_metadata(
+    tables: &[u8],
+    strings: &[u8],
+    user_strings: &[u8],
+    guids: &[u8],
+    blobs: &[u8],
+) -> Vec<u8> {
+    encode_metadata_root(
+        RUNTIME_VERSION,
+        &[
+            ("#~", tables),
+            ("#Strings", strings),
+            ("#US", user_strings),
+            ("#GUID", guids),
+            ("#Blob", blobs),
+        ],
+    )
+}
+/// Builds a metadata root with the version string `version`, followed by `streams`.
+#[must_use]
+pub fn encode_metadata_root(version: &str, streams: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut version = version.as_bytes().to_vec();
+    version.push(0);
+    pad_to(&mut version, 4);
+    let stream_header_size: usize = streams
+        .iter()
+        .map(|(name, _)| 8 + (name.len() + 1).div_ceil(4) * 4)
+        .sum();
+    let mut out = Vec::new();
+    // Signature: "BSJB"
+    out.extend(0x424A_5342_u32.to_le_bytes());
+    // Major & minor version
+    out.extend(1_u16.to_le_bytes());
+    out.extend(1_u16.to_le_bytes());
+    // Reserved
+    out.extend(0_u32.to_le_bytes());
+    out.extend(u32::try_from(version.len()).unwrap().to_le_bytes());
+    out.extend(&version);
+    // Flags
+    out.extend(0_u16.to_le_bytes());
+    out.extend(u16::try_from(streams.len()).unwrap().to_le_bytes());
+    let mut offset = out.len() + stream_header_size;
+    for (name, data) in streams {
+        let size = data.len().div_ceil(4) * 4;
+        out.extend(u32::try_from(offset).unwrap().to_le_bytes());
+        out.extend(u32::try_from(size).unwrap().to_le_bytes());
+        out.extend(name.as_bytes());
+        out.push(0);
+        pad_to(&mut out, 4);
+        offset += size;
+    }
+    for (_, data) in streams {
+        out.extend(*data);
+        pad_to(&mut out, 4);
+    }
+    out
+}
+/// The standard MS-DOS header and stub, with `e_lfanew` pointing right after it.
+fn dos_header() -> Vec<u8> {
+    let mut out = vec![0; 0x40];
+    out[0..2].copy_from_slice(b"MZ");
+    out[2..4].copy_from_slice(&0x90_u16.to_le_bytes());
+    out[4..6].copy_from_slice(&3_u16.to_le_bytes());
+    out[8..10].copy_from_slice(&4_u16.to_le_bytes());
+    out[0xC..0xE].copy_from_slice(&0xFFFF_u16.to_le_bytes());
+    out[0x10..0x12].copy_from_slice(&0xB8_u16.to_le_bytes());
+    out[0x18..0x1A].copy_from_slice(&0x40_u16.to_le_bytes());
+    out[0x3C..0x40].copy_from_slice(&0x80_u32.to_le_bytes());
+    out.extend([
+        0x0E, 0x1F, 0xBA, 0x0E, 0x00, 0xB4, 0x09, 0xCD, 0x21, 0xB8, 0x01, 0x4C, 0xCD, 0x21,
+    ]);
+    out.extend(b"This program cannot be run in DOS mode.\r\r\n$");
+    out.resize(0x80, 0);
+    out
+}
+fn section_header(
+    out: &mut Vec<u8>,
+    name: &[u8; 8],
+    virtual_size: u32,
+    rva: u32,
+    raw_size: u32,
+    raw_ptr: u32,
+    characteristics: u32,
+) {
+    out.extend(name);
+    out.extend(virtual_size.to_le_bytes());
+    out.extend(rva.to_le_bytes());
+    out.extend(raw_size.to_le_bytes());
+    out.extend(raw_ptr.to_le_bytes());
+    // Relocations, line numbers and their counts are unused.
+    out.extend([0; 12]);
+    out.extend(characteristics.to_le_bytes());
+}
+/// Identifies the Portable PDB containing the debug info of an image.
+pub struct PdbRef<'a> {
+    /// The PDB id: a GUID, followed by a timestamp.
+    pub id: [u8; 20],
+    pub path: &'a str,
+}
+/// Assembles a complete PE image, containing the method bodies(which must start at [`BODIES_RVA`]) and the metadata.
+/// If `pdb` is set, the image gets a debug directory pointing to that PDB.
+#[must_use]
+pub fn write_image(
+    bodies: &[u8],
+    metadata: &[u8],
+    entrypoint_token: u32,
+    is_lib: bool,
+    pdb: Option<&PdbRef>,
+) -> Vec<u8> {
+    let image_base: u32 = if is_lib { 0x1000_0000 } else { 0x0040_0000 };
+    // Layout the .text section
+    let mut text = Vec::new();
+    let iat_rva = TEXT_RVA;
+    // The IAT is filled in once the hint/name table position is known.
+    text.extend([0; IAT_SIZE as usize]);
+    let cli_header_pos = text.len();
+    text.extend([0; CLI_HEADER_SIZE as usize]);
+    debug_assert_eq!(TEXT_RVA + u32::try_from(text.len()).unwrap(), BODIES_RVA);
+    text.extend(bodies);
+    pad_to(&mut text, 4);
+    let metadata_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    text.extend(metadata);
+    pad_to(&mut text, 4);
+    // Debug directory, with a single CodeView entry in the format used for Portable PDBs.
+    let debug_dir = pdb.map(|pdb| {
+        let debug_dir_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+        let mut codeview = Vec::new();
+        codeview.extend(b"RSDS");
+        codeview.extend(&pdb.id[..16]);
+        // Age
+        codeview.extend(1_u32.to_le_bytes());
+        codeview.extend(pdb.path.as_bytes());
+        codeview.push(0);
+        let codeview_rva = debug_dir_rva + DEBUG_DIRECTORY_SIZE;
+        // Characteristics
+        text.extend(0_u32.to_le_bytes());
+        text.extend(&pdb.id[16..]);
+        // Major version 0x100, minor version "PM": marks this as a Portable PDB.
+        text.extend(0x0100_u16.to_le_bytes());
+        text.extend(0x504D_u16.to_le_bytes());
+        // IMAGE_DEBUG_TYPE_CODEVIEW
+        text.extend(2_u32.to_le_bytes());
+        text.extend(u32::try_from(codeview.len()).unwrap().to_le_bytes());
+        text.extend(codeview_rva.to_le_bytes());
+        text.extend((codeview_rva - TEXT_RVA + HEADERS_SIZE).to_le_bytes());
+        text.extend(&codeview);
+        pad_to(&mut text, 4);
+        debug_dir_rva
+    });
+    // Import directory, with a single entry for mscoree.dll, followed by a null entry.
+    let import_dir_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    let import_dir_pos = text.len();
+    text.extend([0; 40]);
+    let ilt_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    let ilt_pos = text.len();
+    text.extend([0; 8]);
+    let hint_name_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    text.extend(0_u16.to_le_bytes());
+    text.extend(if is_lib {
+        b"_CorDllMain\0".as_slice()
+    } else {
+        b"_CorExeMain\0".as_slice()
+    });
+    pad_to(&mut text, 2);
+    let dll_name_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    text.extend(b"mscoree.dll\0");
+    // The jump stub operand must be 4 byte aligned.
+    while !(text.len() + 2).is_multiple_of(4) {
+        text.push(0);
+    }
+    let entry_rva = TEXT_RVA + u32::try_from(text.len()).unwrap();
+    text.extend([0xFF, 0x25]);
+    text.extend((image_base + iat_rva).to_le_bytes());
+    // Fill in the IAT, ILT and import directory
+    text[0..4].copy_from_slice(&hint_name_rva.to_le_bytes());
+    text[ilt_pos..ilt_pos + 4].copy_from_slice(&hint_name_rva.to_le_bytes());
+    let import_dir = &mut text[import_dir_pos..import_dir_pos + 20];
+    import_dir[0..4].copy_from_slice(&ilt_rva.to_le_bytes());
+    import_dir[12..16].copy_from_slice(&dll_name_rva.to_le_bytes());
+    import_dir[16..20].copy_from_slice(&iat_rva.to_le_bytes());
+    // Fill in the CLI header
+    let cli_header = &mut text[cli_header_pos..cli_header_pos + CLI_HEADER_SIZE as usize];
+    cli_header[0..4].copy_from_slice(&CLI_HEADER_SIZE.to_le_bytes());
+    // Runtime version 2.5
+    cli_header[4..6].copy_from_slice(&2_u16.to_le_bytes());
+    cli_header[6..8].copy_from_slice(&5_u16.to_le_bytes());
+    cli_header[8..12].copy_from_slice(&metadata_rva.to_le_bytes());
+    cli_header[12..16].copy_from_slice(&u32::try_from(metadata.len()).unwrap().to_le_bytes());
+    // COMIMAGE_FLAGS_ILONLY
+    cli_header[16..20].copy_from_slice(&1_u32.to_le_bytes());
+    cli_header[20..24].copy_from_slice(&entrypoint_token.to_le_bytes());
+
+    let text_size = u32::try_from(text.len()).unwrap();
+    let text_raw_size = align(text_size, FILE_ALIGNMENT);
+    // The .reloc section contains a single fixup, for the jump stub.
+    let reloc_rva = align(TEXT_RVA + text_size, SECTION_ALIGNMENT);
+    let fixup = entry_rva + 2;
+    let mut reloc = Vec::new();
+    reloc.extend((fixup & !0xFFF).to_le_bytes());
+    reloc.extend(12_u32.to_le_bytes());
+    reloc.extend(((3 << 12) | (fixup & 0xFFF) as u16).to_le_bytes());
+    reloc.extend(0_u16.to_le_bytes());
+    let reloc_size = u32::try_from(reloc.len()).unwrap();
+    let reloc_raw_size = align(reloc_size, FILE_ALIGNMENT);
+    let image_size = align(reloc_rva + reloc_size, SECTION_ALIGNMENT);
+
+    let mut out = dos_header();
+    out.extend(b"PE\0\0");
+    // COFF header: i386, 2 sections
+    out.extend(0x14C_u16.to_le_bytes());
+    out.extend(2_u16.to_le_bytes());
+    // Timestamp, symbol table pointer, symbol count
+    out.extend([0; 12]);
+    // Size of the optional header
+    out.extend(0xE0_u16.to_le_bytes());
+    // IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_32BIT_MACHINE (| IMAGE_FILE_DLL)
+    let characteristics: u16 = if is_lib { 0x2102 } else { 0x0102 };
+    out.extend(characteristics.to_le_bytes());
+    // PE32 optional header
+    out.extend(0x10B_u16.to_le_bytes());
+    // Linker version
+    out.extend([8, 0]);
+    out.extend(text_raw_size.to_le_bytes());
+    out.extend(reloc_raw_size.to_le_bytes());
+    out.extend(0_u32.to_le_bytes());
+    out.extend(entry_rva.to_le_bytes());
+    out.extend(TEXT_RVA.to_le_bytes());
+    out.extend(reloc_rva.to_le_bytes());
+    out.extend(image_base.to_le_bytes());
+    out.extend(SECTION_ALIGNMENT.to_le_bytes());
+    out.extend(FILE_ALIGNMENT.to_le_bytes());
+    // OS version 4.0, image version 0.0, subsystem version 4.0
+    for version in [4_u16, 0, 0, 0, 4, 0] {
+        out.extend(version.to_le_bytes());
+    }
+    // Win32VersionValue
+    out.extend(0_u32.to_le_bytes());
+    out.extend(image_size.to_le_bytes());
+    out.extend(HEADERS_SIZE.to_le_bytes());
+    // Checksum
+    out.extend(0_u32.to_le_bytes());
+    // Subsystem: console
+    out.extend(3_u16.to_le_bytes());
+    // DYNAMIC_BASE | NX_COMPAT | NO_SEH | TERMINAL_SERVER_AWARE
+    out.extend(0x8540_u16.to_le_bytes());
+    // Stack reserve & commit, heap reserve & commit
+    for size in [0x10_0000_u32, 0x1000, 0x10_0000, 0x1000] {
+        out.extend(size.to_le_bytes());
+    }
+    // Loader flags
+    out.extend(0_u32.to_le_bytes());
+    // Number of data directories
+    out.extend(16_u32.to_le_bytes());
+    let mut directories = [(0_u32, 0_u32); 16];
+    directories[1] = (import_dir_rva, 40);
+    directories[5] = (reloc_rva, reloc_size);
+    if let Some(debug_dir_rva) = debug_dir {
+        directories[6] = (debug_dir_rva, DEBUG_DIRECTORY_SIZE);
+    }
+    directories[12] = (iat_rva, IAT_SIZE);
+    directories[14] = (TEXT_RVA + IAT_SIZE, CLI_HEADER_SIZE);
+    for (rva, size) in directories {
+        out.extend(rva.to_le_bytes());
+        out.extend(size.to_le_bytes());
+    }
+    // CODE | EXECUTE | READ
+    section_header(
+        &mut out,
+        b".text\0\0\0",
+        text_size,
+        TEXT_RVA,
+        text_raw_size,
+        HEADERS_SIZE,
+        0x6000_0020,
+    );
+    // INITIALIZED_DATA | DISCARDABLE | READ
+    section_header(
+        &mut out,
+        b".reloc\0\0",
+        reloc_size,
+        reloc_rva,
+        reloc_raw_size,
+        HEADERS_SIZE + text_raw_size,
+        0x4200_0040,
+    );
+    assert!(out.len() <= HEADERS_SIZE as usize);
+    out.resize(HEADERS_SIZE as usize, 0);
+    out.extend(&text);
+    out.resize((HEADERS_SIZE + text_raw_size) as usize, 0);
+    out.extend(&reloc);
+    out.resize((HEADERS_SIZE + text_raw_size + reloc_raw_size) as usize, 0);
+    out
+}
+#[test]
+fn image_layout() {
+    let metadata = encode_metadata(&[0; 4], &[0], &[0], &[], &[0]);
+    assert_eq!(&metadata[0..4], b"BSJB");
+    let image = write_image(&[0x06, 0x2A], &metadata, 0x0600_0001, false, None);
+    assert_eq!(&image[0..2], b"MZ");
+    assert_eq!(&image[0x80..0x84], b"PE\0\0");
+    assert_eq!(image.len() % FILE_ALIGNMENT as usize, 0);
+    // The method body is at its expected file offset.
+    let body_offset = (HEADERS_SIZE + BODIES_RVA - TEXT_RVA) as usize;
+    assert_eq!(image[body_offset..body_offset + 2], [0x06, 0x2A]);
+    // The CLI header points to the metadata
+    let cli = HEADERS_SIZE as usize + IAT_SIZE as usize;
+    let metadata_rva = u32::from_le_bytes(image[cli + 8..cli + 12].try_into().unwrap());
+    let metadata_offset = (metadata_rva - TEXT_RVA + HEADERS_SIZE) as usize;
+    assert_eq!(&image[metadata_offset..metadata_offset + 4], b"BSJB");
+}
+#[test]
+fn debug_directory() {
+    let metadata = encode_metadata(&[0; 4], &[0], &[0], &[], &[0]);
+    let pdb = PdbRef {
+        id: [7; 20],
+        path: "/tmp/a.pdb",
+    };
+    let image = write_image(&[0x2A], &metadata, 0x0600_0001, false, Some(&pdb));
+    // The debug data directory is the 7th one, right after the 96 byte standard part of the optional header.
+    let dir = 0x80 + 4 + 20 + 96 + 6 * 8;
+    let rva = u32::from_le_bytes(image[dir..dir + 4].try_into().unwrap());
+    assert_eq!(
+        u32::from_le_bytes(image[dir + 4..dir + 8].try_into().unwrap()),
+        DEBUG_DIRECTORY_SIZE
+    );
+    let entry = (rva - TEXT_RVA + HEADERS_SIZE) as usize;
+    let raw_ptr = u32::from_le_bytes(image[entry + 24..entry + 28].try_into().unwrap()) as usize;
+    assert_eq!(&image[raw_ptr..raw_ptr + 4], b"RSDS");
+    assert_eq!(image[raw_ptr + 4..raw_ptr + 20], [7; 16]);
+    assert!(image[raw_ptr + 24..].starts_with(b"/tmp/a.pdb\0"));
+}
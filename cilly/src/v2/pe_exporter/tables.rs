@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+
+use super::heaps::{BlobHeap, GuidHeap, StringHeap};
+
+/// IDs of the metadata tables used by the exporter(II.22).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum TableId {
+    Module = 0x00,
+    TypeRef = 0x01,
+    TypeDef = 0x02,
+    Field = 0x04,
+    MethodDef = 0x06,
+    Param = 0x08,
+    InterfaceImpl = 0x09,
+    MemberRef = 0x0A,
+    CustomAttribute = 0x0C,
+    DeclSecurity = 0x0E,
+    ClassLayout = 0x0F,
+    FieldLayout = 0x10,
+    StandAloneSig = 0x11,
+    Event = 0x14,
+    Property = 0x17,
+    ModuleRef = 0x1A,
+    TypeSpec = 0x1B,
+    ImplMap = 0x1C,
+    Assembly = 0x20,
+    AssemblyRef = 0x23,
+    File = 0x26,
+    ExportedType = 0x27,
+    ManifestResource = 0x28,
+    GenericParam = 0x2A,
+    MethodSpec = 0x2B,
+    GenericParamConstraint = 0x2C,
+    // Debug tables, only used in Portable PDBs.
+    Document = 0x30,
+    MethodDebugInformation = 0x31,
+    LocalScope = 0x32,
+    LocalVariable = 0x33,
+    LocalConstant = 0x34,
+    ImportScope = 0x35,
+}
+/// A single column of a metadata table.
+#[derive(Clone, Copy, Debug)]
+pub enum Column {
+    U16,
+    U32,
+    String,
+    Guid,
+    Blob,
+    Index(TableId),
+    Coded(CodedIndex),
+}
+impl TableId {
+    /// The layout of rows in this table.
+    #[must_use]
+    pub fn columns(self) -> &'static [Column] {
+        use Column::{Blob, Coded, Guid, Index, String, U16, U32};
+        match self {
+            Self::Module => &[U16, String, Guid, Guid, Guid],
+            Self::TypeRef => &[Coded(CodedIndex::ResolutionScope), String, String],
+            Self::TypeDef => &[
+                U32,
+                String,
+                String,
+                Coded(CodedIndex::TypeDefOrRef),
+                Index(Self::Field),
+                Index(Self::MethodDef),
+            ],
+            Self::Field => &[U16, String, Blob],
+            Self::MethodDef => &[U32, U16, U16, String, Blob, Index(Self::Param)],
+            Self::Param => &[U16, U16, String],
+            Self::InterfaceImpl => &[Index(Self::TypeDef), Coded(CodedIndex::TypeDefOrRef)],
+            Self::MemberRef => &[Coded(CodedIndex::MemberRefParent), String, Blob],
+            Self::CustomAttribute => &[
+                Coded(CodedIndex::HasCustomAttribute),
+                Coded(CodedIndex::CustomAttributeType),
+                Blob,
+            ],
+            Self::DeclSecurity => &[U16, Coded(CodedIndex::HasDeclSecurity), Blob],
+            Self::ClassLayout => &[U16, U32, Index(Self::TypeDef)],
+            Self::FieldLayout => &[U32, Index(Self::Field)],
+            Self::StandAloneSig => &[Blob],
+            Self::Event => &[U16, String, Coded(CodedIndex::TypeDefOrRef)],
+            Self::Property => &[U16, String, Blob],
+            Self::ModuleRef => &[String],
+            Self::TypeSpec => &[Blob],
+            Self::ImplMap => &[
+                U16,
+                Coded(CodedIndex::MemberForwarded),
+                String,
+                Index(Self::ModuleRef),
+            ],
+            Self::Assembly => &[U32, U16, U16, U16, U16, U32, Blob, String, String],
+            Self::AssemblyRef => &[U16, U16, U16, U16, U32, Blob, String, String, Blob],
+            Self::File => &[U32, String, Blob],
+            Self::ExportedType => &[U32, U32, String, String, Coded(CodedIndex::Implementation)],
+            Self::ManifestResource => &[U32, U32, String, Coded(CodedIndex::Implementation)],
+            Self::GenericParam => &[U16, U16, Coded(CodedIndex::TypeOrMethodDef), String],
+            Self::MethodSpec => &[Coded(CodedIndex::MethodDefOrRef), Blob],
+            Self::GenericParamConstraint => {
+                &[Index(Self::GenericParam), Coded(CodedIndex::TypeDefOrRef)]
+            }
+            Self::Document => &[Blob, Guid, Blob, Guid],
+            Self::MethodDebugInformation => &[Index(Self::Document), Blob],
+            Self::LocalScope => &[
+                Index(Self::MethodDef),
+                Index(Self::ImportScope),
+                Index(Self::LocalVariable),
+                Index(Self::LocalConstant),
+                U32,
+                U32,
+            ],
+            Self::LocalVariable => &[U16, U16, String],
+            Self::LocalConstant => &[String, Blob],
+            Self::ImportScope => &[Index(Self::ImportScope), Blob],
+        }
+    }
+    /// The column this table must be sorted by, if any.
+    fn sort_column(self) -> Option<usize> {
+        match self {
+            Self::CustomAttribute
+            | Self::InterfaceImpl
+            | Self::GenericParamConstraint
+            | Self::LocalScope => Some(0),
+            Self::FieldLayout | Self::ImplMap | Self::DeclSecurity => Some(1),
+            Self::ClassLayout | Self::GenericParam => Some(2),
+            _ => None,
+        }
+    }
+    /// Returns the metadata token of a row in this table.
+    #[must_use]
+    pub fn token(self, row: u32) -> u32 {
+        debug_assert!(row < (1 << 24));
+        ((self as u32) << 24) | row
+    }
+}
+/// Coded indices(II.24.2.6): a tag selecting a table, and a row in that table, packed together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodedIndex {
+    TypeDefOrRef,
+    HasCustomAttribute,
+    HasDeclSecurity,
+    MemberRefParent,
+    Implementation,
+    CustomAttributeType,
+    ResolutionScope,
+    MemberForwarded,
+    TypeOrMethodDef,
+    MethodDefOrRef,
+}
+impl CodedIndex {
+    /// The tables this coded index can point into, in tag order. `None` marks unused tags.
+    fn tables(self) -> &'static [Option<TableId>] {
+        use TableId as T;
+        match self {
+            Self::TypeDefOrRef => &[Some(T::TypeDef), Some(T::TypeRef), Some(T::TypeSpec)],
+            Self::HasCustomAttribute => &[
+                Some(T::MethodDef),
+                Some(T::Field),
+                Some(T::TypeRef),
+                Some(T::TypeDef),
+                Some(T::Param),
+                Some(T::InterfaceImpl),
+                Some(T::MemberRef),
+                Some(T::Module),
+                Some(T::DeclSecurity),
+                Some(T::Property),
+                Some(T::Event),
+                Some(T::StandAloneSig),
+                Some(T::ModuleRef),
+                Some(T::TypeSpec),
+                Some(T::Assembly),
+                Some(T::AssemblyRef),
+                Some(T::File),
+                Some(T::ExportedType),
+                Some(T::ManifestResource),
+                Some(T::GenericParam),
+                Some(T::GenericParamConstraint),
+                Some(T::MethodSpec),
+            ],
+            Self::HasDeclSecurity => &[Some(T::TypeDef), Some(T::MethodDef), Some(T::Assembly)],
+            Self::MemberRefParent => &[
+                Some(T::TypeDef),
+                Some(T::TypeRef),
+                Some(T::ModuleRef),
+                Some(T::MethodDef),
+                Some(T::TypeSpec),
+            ],
+            Self::Implementation => &[Some(T::File), Some(T::AssemblyRef), Some(T::ExportedType)],
+            Self::CustomAttributeType => {
+                &[None, None, Some(T::MethodDef), Some(T::MemberRef), None]
+            }
+            Self::ResolutionScope => &[
+                Some(T::Module),
+                Some(T::ModuleRef),
+                Some(T::AssemblyRef),
+                Some(T::TypeRef),
+            ],
+            Self::MemberForwarded => &[Some(T::Field), Some(T::MethodDef)],
+            Self::TypeOrMethodDef => &[Some(T::TypeDef), Some(T::MethodDef)],
+            Self::MethodDefOrRef => &[Some(T::MethodDef), Some(T::MemberRef)],
+        }
+    }
+    fn tag_bits(self) -> u32 {
+        let tags = u32::try_from(self.tables().len()).unwrap();
+        u32::BITS - (tags - 1).leading_zeros()
+    }
+    /// Encodes a reference to `row` of `table` as this coded index.
+    #[must_use]
+    pub fn encode(self, table: TableId, row: u32) -> u32 {
+        let tag = self
+            .tables()
+            .iter()
+            .position(|tbl| *tbl == Some(table))
+            .unwrap_or_else(|| panic!("{table:?} can't be referenced by {self:?}"));
+        (row << self.tag_bits()) | u32::try_from(tag).unwrap()
+    }
+}
+/// All the metadata tables of a module, stored as rows of raw column values.
+#[derive(Default)]
+pub struct Tables {
+    tables: BTreeMap<TableId, Vec<Box<[u32]>>>,
+    /// Row counts of tables stored in another module. A Portable PDB references the tables of its assembly.
+    external: BTreeMap<TableId, u32>,
+}
+impl Tables {
+    /// Adds a new row to `table`, returning its 1-based index.
+    pub fn push(&mut self, table: TableId, row: impl Into<Box<[u32]>>) -> u32 {
+        let row = row.into();
+        assert_eq!(
+            row.len(),
+            table.columns().len(),
+            "Invalid row for {table:?}"
+        );
+        let rows = self.tables.entry(table).or_default();
+        rows.push(row);
+        u32::try_from(rows.len()).expect("Too many metadata rows")
+    }
+    /// The amount of rows in `table`.
+    #[must_use]
+    pub fn len(&self, table: TableId) -> u32 {
+        self.tables
+            .get(&table)
+            .map_or(0, |rows| u32::try_from(rows.len()).unwrap())
+    }
+    /// The row counts of all non-empty tables.
+    pub fn row_counts(&self) -> impl Iterator<Item = (TableId, u32)> + '_ {
+        self.tables
+            .keys()
+            .map(|table| (*table, self.len(*table)))
+            .filter(|(_, rows)| *rows > 0)
+    }
+    /// Records that `table`, stored in another module, has `rows` rows. Used to size indices into that table.
+    pub fn set_external_len(&mut self, table: TableId, rows: u32) {
+        self.external.insert(table, rows);
+    }
+    fn index_size(&self, table: TableId) -> usize {
+        let rows = self
+            .external
+            .get(&table)
+            .map_or(self.len(table), |external| self.len(table).max(*external));
+        if rows < (1 << 16) {
+            2
+        } else {
+            4
+        }
+    }
+    fn coded_size(&self, coded: CodedIndex) -> usize {
+        let max_rows = coded
+            .tables()
+            .iter()
+            .flatten()
+            .map(|table| self.len(*table))
+            .max()
+            .unwrap_or(0);
+        if max_rows < (1 << (16 - coded.tag_bits())) {
+            2
+        } else {
+            4
+        }
+    }
+    /// Serializes the tables into a `#~` stream.
+    #[must_use]
+    pub fn encode(mut self, strings: &StringHeap, blobs: &BlobHeap, guids: &GuidHeap) -> Vec<u8> {
+        for (table, rows) in &mut self.tables {
+            if let Some(column) = table.sort_column() {
+                rows.sort_by_key(|row| row[column]);
+            }
+        }
+        let string_size = if strings.data().len() < (1 << 16) {
+            2
+        } else {
+            4
+        };
+        let guid_size = if guids.data().len() / 16 < (1 << 16) {
+            2
+        } else {
+            4
+        };
+        let blob_size = if blobs.data().len() < (1 << 16) { 2 } else { 4 };
+        let heap_sizes = u8::from(string_size == 4)
+            | (u8::from(guid_size == 4) << 1)
+            | (u8::from(blob_size == 4) << 2);
+        let mut out = Vec::new();
+        // Reserved
+        out.extend(0_u32.to_le_bytes());
+        // Major and minor version
+        out.extend([2, 0]);
+        out.push(heap_sizes);
+        // Reserved, always 1
+        out.push(1);
+        let valid = self
+            .tables
+            .iter()
+            .filter(|(_, rows)| !rows.is_empty())
+            .fold(0_u64, |valid, (table, _)| valid | (1 << (*table as u64)));
+        out.extend(valid.to_le_bytes());
+        // The sorted bitmask(the same as the one emitted by other tools).
+        out.extend(0x0004_1600_3301_FA00_u64.to_le_bytes());
+        for rows in self.tables.values().filter(|rows| !rows.is_empty()) {
+            out.extend(u32::try_from(rows.len()).unwrap().to_le_bytes());
+        }
+        for (table, rows) in &self.tables {
+            let sizes: Vec<usize> = table
+                .columns()
+                .iter()
+                .map(|column| match column {
+                    Column::U16 => 2,
+                    Column::U32 => 4,
+                    Column::String => string_size,
+                    Column::Guid => guid_size,
+                    Column::Blob => blob_size,
+                    Column::Index(table) => self.index_size(*table),
+                    Column::Coded(coded) => self.coded_size(*coded),
+                })
+                .collect();
+            for row in rows {
+                for (val, size) in row.iter().zip(&sizes) {
+                    if *size == 2 {
+                        let val = u16::try_from(*val).unwrap_or_else(|_| {
+                            panic!("Value {val} does not fit in a 2 byte column of {table:?}")
+                        });
+                        out.extend(val.to_le_bytes());
+                    } else {
+                        out.extend(val.to_le_bytes());
+                    }
+                }
+            }
+        }
+        // Pad the stream to a multiple of 4
+        while !out.len().is_multiple_of(4) {
+            out.push(0);
+        }
+        out
+    }
+}
+#[test]
+fn coded_index() {
+    assert_eq!(CodedIndex::TypeDefOrRef.tag_bits(), 2);
+    assert_eq!(CodedIndex::HasCustomAttribute.tag_bits(), 5);
+    assert_eq!(CodedIndex::MemberRefParent.tag_bits(), 3);
+    assert_eq!(CodedIndex::CustomAttributeType.tag_bits(), 3);
+    assert_eq!(CodedIndex::MethodDefOrRef.tag_bits(), 1);
+    assert_eq!(CodedIndex::TypeDefOrRef.encode(TableId::TypeRef, 3), 0b1101);
+    assert_eq!(
+        CodedIndex::CustomAttributeType.encode(TableId::MemberRef, 1),
+        0b1011
+    );
+    assert_eq!(TableId::MethodDef.token(5), 0x0600_0005);
+}
+#[test]
+fn module_table() {
+    let mut tables = Tables::default();
+    assert_eq!(tables.push(TableId::Module, [0, 1, 1, 0, 0]), 1);
+    let strings = StringHeap::default();
+    let stream = tables.encode(&strings, &BlobHeap::default(), &GuidHeap::default());
+    // Header(24 bytes), one row count(4 bytes), one module row(10 bytes), padding(2 bytes).
+    assert_eq!(stream.len(), 24 + 4 + 10 + 2);
+    assert_eq!(stream[8], 1);
+}
@@ -0,0 +1,405 @@
+//! A textual format for [`Assembly`], which can be parsed back.
+//!
+//! Assemblies are printed as s-expressions, one `(class ...)` form per class definition. Classes contain their
+//! fields, static fields and method definitions, and methods contain their locals and basic blocks. `CILRoot`s and
+//! `CILNode`s are printed as trees, with every type, method, and field reference spelled out in full:
+//! ```text
+//! (class public "MainModule"
+//!   (method public static "add" (sig (i32 i32) i32)
+//!     (args "a" "b")
+//!     (body
+//!       (locals)
+//!       (block 0
+//!         (ret (add (ldarg 0) (ldarg 1)))))))
+//! ```
+//! Strings use the same escapes as Rust, and `;` starts a comment which lasts until the end of the line.
+//!
+//! The classes are preceded by an `(interned ...)` form, which lists every string, type, reference, node and root
+//! interned in the assembly, in an order which reproduces their indices. Nodes within it refer to other nodes by their
+//! position, as in `(node (add #0 #1))`. Parsing the output of [`print`] yields an assembly equal to the printed one,
+//! so the format can be used to store and diff assemblies. The `interned` form is optional in hand-written text.
+use std::fmt::Display;
+
+use super::{cilnode::MethodKind, cilroot::CmpKind, tpe::GenericKind, Assembly, BinOp, Float, Int};
+
+mod parse;
+mod print;
+
+/// An error encountered while parsing the textual format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    msg: String,
+}
+impl ParseError {
+    /// The line the error occurred on, starting from 1.
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+impl std::error::Error for ParseError {}
+/// Prints all the class and method definitions in `asm`. Classes are sorted by name, so the output does not depend
+/// on the order in which they were added.
+#[must_use]
+pub fn print(asm: &Assembly) -> String {
+    let mut classes: Vec<_> = asm.class_defs().iter().collect();
+    classes.sort_by_key(|(_, def)| (&asm[def.name()], def.is_valuetype()));
+    let mut printer = print::Printer::new(asm);
+    printer.interned();
+    for (class, _) in classes {
+        printer.class(*class);
+    }
+    printer.finish()
+}
+/// Parses an assembly printed by [`print`].
+pub fn parse(src: &str) -> Result<Assembly, ParseError> {
+    let mut parser = parse::Parser::new();
+    let forms = parse::Reader::new(src).read_all()?;
+    let classes = match forms.split_first() {
+        Some((interned, classes)) if interned.args_of("interned").is_some() => {
+            parser.interned(interned)?;
+            classes
+        }
+        _ => &forms[..],
+    };
+    for class in classes {
+        parser.class(class)?;
+    }
+    Ok(parser.finish())
+}
+/// The heads of the forms used for the items of each interned table, in the order of [`Assembly::interned_counts`].
+const TABLES: [&str; 9] = [
+    "string",
+    "type",
+    "classref",
+    "sig",
+    "methodref",
+    "fieldref",
+    "staticref",
+    "node",
+    "root",
+];
+const INTS: [Int; 12] = [
+    Int::U8,
+    Int::U16,
+    Int::U32,
+    Int::U64,
+    Int::U128,
+    Int::USize,
+    Int::I8,
+    Int::I16,
+    Int::I32,
+    Int::I64,
+    Int::I128,
+    Int::ISize,
+];
+const FLOATS: [Float; 4] = [Float::F16, Float::F32, Float::F64, Float::F128];
+const BINOPS: [(BinOp, &str); 18] = [
+    (BinOp::Add, "add"),
+    (BinOp::Eq, "eq"),
+    (BinOp::Sub, "sub"),
+    (BinOp::Mul, "mul"),
+    (BinOp::LtUn, "lt_un"),
+    (BinOp::Lt, "lt"),
+    (BinOp::GtUn, "gt_un"),
+    (BinOp::Gt, "gt"),
+    (BinOp::Or, "or"),
+    (BinOp::XOr, "xor"),
+    (BinOp::And, "and"),
+    (BinOp::Rem, "rem"),
+    (BinOp::RemUn, "rem_un"),
+    (BinOp::Shl, "shl"),
+    (BinOp::Shr, "shr"),
+    (BinOp::ShrUn, "shr_un"),
+    (BinOp::DivUn, "div_un"),
+    (BinOp::Div, "div"),
+];
+const CMP_KINDS: [(CmpKind, &str); 4] = [
+    (CmpKind::Ordered, "ordered"),
+    (CmpKind::Unordered, "unordered"),
+    (CmpKind::Signed, "signed"),
+    (CmpKind::Unsigned, "unsigned"),
+];
+const GENERIC_KINDS: [(GenericKind, &str); 3] = [
+    (GenericKind::MethodGeneric, "method"),
+    (GenericKind::CallGeneric, "call"),
+    (GenericKind::TypeGeneric, "type"),
+];
+const METHOD_KINDS: [(MethodKind, &str); 4] = [
+    (MethodKind::Static, "static"),
+    (MethodKind::Instance, "instance"),
+    (MethodKind::Virtual, "virtual"),
+    (MethodKind::Constructor, "ctor"),
+];
+fn name_of<T: PartialEq>(table: &[(T, &'static str)], val: &T) -> &'static str {
+    table
+        .iter()
+        .find(|(entry, _)| entry == val)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+#[cfg(test)]
+fn test_asm() -> Assembly {
+    use super::{
+        cilnode::{ExtendKind, PtrCastRes, UnOp},
+        cilroot::{BranchCond, SwitchTargets},
+        hashable::{HashableF32, HashableF64},
+        Access, BasicBlock, CILNode, CILRoot, ClassDef, ClassRef, Const, FieldDesc, MethodDef,
+        MethodImpl, StaticFieldDesc, Type,
+    };
+    let mut asm = Assembly::default();
+    // Items which no definition refers to must survive a round trip too.
+    asm.alloc_string("unused");
+    let unused = asm.alloc_node(CILNode::LdArg(7));
+    asm.alloc_node(CILNode::UnOp(unused, UnOp::Neg));
+    let main_module = asm.main_module();
+    let name = asm.alloc_string("Pair \"quoted\"\n");
+    let (a, b, flag) = (
+        asm.alloc_string("a"),
+        asm.alloc_string("b"),
+        asm.alloc_string("flag"),
+    );
+    let pair = asm.class_def(ClassDef::new(
+        name,
+        true,
+        0,
+        None,
+        vec![
+            (Type::Int(Int::I32), a, Some(0)),
+            (Type::Float(Float::F64), b, Some(8)),
+        ],
+        vec![(Type::Bool, flag, true)],
+        Access::Private,
+        std::num::NonZeroU32::new(16),
+    ));
+    let object = ClassRef::object(&mut asm);
+    let dict = ClassRef::dictionary(Type::PlatformString, Type::Int(Int::U8), &mut asm);
+    asm.class_mut(pair).add_interface(dict);
+    let field = asm.alloc_field(FieldDesc::new(*pair, a, Type::Int(Int::I32)));
+    let sfld = asm.alloc_sfld(StaticFieldDesc::new(*pair, flag, Type::Bool));
+    let pair_ptr = asm.nptr(Type::ClassRef(*pair));
+    let sig = asm.sig([pair_ptr, Type::Float(Float::F32)], Type::Int(Int::I32));
+    let callee = asm.new_methodref(*main_module, "callee", sig, MethodKind::Static, []);
+    let arr = asm.alloc_type(Type::PlatformObject);
+    let arr = Type::PlatformArray {
+        elem: arr,
+        dims: std::num::NonZeroU8::new(2).unwrap(),
+    };
+    let arr = asm.alloc_type(arr);
+    let generic = asm.alloc_type(Type::PlatformGeneric(1, GenericKind::CallGeneric));
+    let pair_tpe = asm.alloc_type(Type::ClassRef(*pair));
+    let fn_ptr = asm.fn_ptr([Type::PlatformChar], Type::Void);
+    let fn_ptr = asm.alloc_type(fn_ptr);
+
+    let arg = asm.alloc_node(CILNode::LdArg(0));
+    let float = asm.alloc_node(Const::F32(HashableF32(-0.1)));
+    let nan = asm.alloc_node(Const::F64(HashableF64(f64::from_bits(
+        0x7ff8_0000_0000_0123,
+    ))));
+    let nan = asm.alloc_node(CILNode::FloatCast {
+        input: nan,
+        target: Float::F32,
+        is_signed: true,
+    });
+    let call = asm.alloc_node(CILNode::Call(Box::new((callee, [arg, nan].into()))));
+    let neg = asm.alloc_node(CILNode::UnOp(call, UnOp::Neg));
+    let loaded = asm.alloc_node(CILNode::LdField { addr: arg, field });
+    let sum = asm.alloc_node(CILNode::BinOp(neg, loaded, BinOp::ShrUn));
+    let cast = asm.alloc_node(CILNode::IntCast {
+        input: sum,
+        target: Int::I128,
+        extend: ExtendKind::SignExtend,
+    });
+    let usize_ptr = asm.alloc_node(CILNode::PtrCast(arg, Box::new(PtrCastRes::USize)));
+    let fn_cast = asm.alloc_node(CILNode::PtrCast(arg, Box::new(PtrCastRes::Ptr(fn_ptr))));
+    let ldind = asm.alloc_node(CILNode::LdInd {
+        addr: fn_cast,
+        tpe: fn_ptr,
+        volitale: true,
+    });
+    let csig = asm.sig([Type::PlatformChar], Type::Void);
+    let chr = asm.alloc_node(CILNode::LdLoc(1));
+    let ldtoken = asm.alloc_node(CILNode::LdTypeToken(generic));
+    let null = asm.alloc_node(Const::Null(object));
+    let len = asm.alloc_node(CILNode::LdLen(null));
+    let elem = asm.alloc_node(CILNode::LdElelemRef {
+        array: null,
+        index: len,
+    });
+    let unbox = asm.alloc_node(CILNode::UnboxAny {
+        object: elem,
+        tpe: pair_tpe,
+    });
+    let isinst = asm.alloc_node(CILNode::IsInst(null, arr));
+    let lds = asm.alloc_node(CILNode::LdStaticField(sfld));
+    let ldsa = asm.alloc_node(CILNode::LdStaticFieldAdress(sfld));
+    let size = asm.alloc_node(CILNode::SizeOf(pair_tpe));
+    let alloca = asm.alloc_node(CILNode::LocAllocAlgined {
+        tpe: pair_tpe,
+        align: 16,
+    });
+    let ftn = asm.alloc_node(CILNode::LdFtn(callee));
+    let msg = asm.ldstr("tab\there");
+    let msg = asm.alloc_node(msg);
+    let roots = [
+        CILRoot::SourceFileInfo {
+            line_start: 1,
+            line_len: 2,
+            col_start: 3,
+            col_len: 4,
+            file: asm.alloc_string("src/lib.rs"),
+        },
+        CILRoot::StLoc(0, cast),
+        CILRoot::Pop(usize_ptr),
+        CILRoot::CallI(Box::new((ldind, csig, [chr].into()))),
+        CILRoot::Pop(ldtoken),
+        CILRoot::Pop(unbox),
+        CILRoot::SetStaticField {
+            field: sfld,
+            val: isinst,
+        },
+        CILRoot::StInd(Box::new((ldsa, lds, Type::Bool, false))),
+        CILRoot::InitBlk(Box::new((alloca, float, size))),
+        CILRoot::CpObj {
+            src: alloca,
+            dst: arg,
+            tpe: pair_tpe,
+        },
+        CILRoot::Pop(ftn),
+        CILRoot::Pop(msg),
+        CILRoot::Branch(Box::new((
+            1,
+            0,
+            Some(BranchCond::Lt(
+                sum,
+                loaded,
+                super::cilroot::CmpKind::Unsigned,
+            )),
+        ))),
+        CILRoot::Switch(Box::new((
+            chr,
+            Int::U32,
+            SwitchTargets::new(vec![(1, (1, 0)), (3, (0, 2)), (2, (0, 3))], (1, 0)),
+        ))),
+        CILRoot::Switch(Box::new((
+            loaded,
+            Int::I64,
+            SwitchTargets::new(vec![(u64::MAX, (1, 0)), (7, (0, 3))], (0, 2)),
+        ))),
+        CILRoot::Unreachable(asm.alloc_string("unreachable")),
+    ];
+    let roots = roots.map(|root| asm.alloc_root(root)).into();
+    let throw = asm.alloc_root(CILRoot::Throw(null));
+    let rethrow = asm.alloc_root(CILRoot::ReThrow);
+    let exit = asm.alloc_root(CILRoot::ExitSpecialRegion {
+        target: 1,
+        source: 0,
+    });
+    let ret = asm.alloc_root(CILRoot::Ret(sum));
+    let blocks = vec![
+        BasicBlock::new(
+            roots,
+            0,
+            Some(vec![
+                BasicBlock::new(vec![exit], 2, None),
+                BasicBlock::new(vec![rethrow], 3, Some(vec![])),
+            ]),
+        ),
+        BasicBlock::new(vec![throw, ret], 1, None),
+    ];
+    let locals = vec![
+        (
+            Some(asm.alloc_string("x")),
+            asm.alloc_type(Type::Int(Int::I128)),
+        ),
+        (None, asm.alloc_type(Type::PlatformChar)),
+    ];
+    let name = asm.alloc_string("body");
+    let arg_names = vec![Some(asm.alloc_string("pair")), None];
+    let mut body = MethodDef::new(
+        Access::Public,
+        pair,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::MethodBody { blocks, locals },
+        arg_names,
+    );
+    body.set_doc(Some(asm.alloc_string("Does everything.")));
+    asm.new_method(body);
+    let name = asm.alloc_string("callee");
+    let lib = asm.alloc_string("libc");
+    asm.new_method(MethodDef::new(
+        Access::Extern,
+        main_module,
+        name,
+        sig,
+        MethodKind::Static,
+        MethodImpl::Extern {
+            lib,
+            preserve_errno: true,
+        },
+        vec![],
+    ));
+    asm
+}
+#[test]
+fn round_trip() {
+    let asm = test_asm();
+    let text = print(&asm);
+    let parsed = parse(&text).unwrap_or_else(|err| panic!("{err}\n{text}"));
+    assert!(parsed == asm, "{text}");
+    assert_eq!(print(&parsed), text);
+}
+#[test]
+fn hand_written() {
+    let asm = parse(
+        r#"
+        ; A comment
+        (class public "MainModule"
+          (method public static "add" (sig (i32 i32) i32)
+            (args "a" _)
+            (body
+              (locals)
+              (block 0
+                (br 1 0 (ge signed (ldarg 0) (const i32 -1)))
+                (ret (add (ldarg 0) (ldarg 1))))
+              (block 1
+                (ret (const i32 0))))))
+        "#,
+    )
+    .unwrap();
+    let (_, class) = asm.class_defs().iter().next().unwrap();
+    let [add] = class.methods() else {
+        panic!("Expected a single method");
+    };
+    let add = asm.method_def(*add);
+    assert_eq!(&asm[add.name()], "add");
+    assert_eq!(add.arg_names()[1], None);
+    let blocks = add.implementation().blocks().unwrap();
+    assert_eq!(blocks.len(), 2);
+    let super::CILRoot::Ret(sum) = asm[blocks[0].roots()[1]] else {
+        panic!("Expected a return");
+    };
+    assert!(matches!(asm[sum], super::CILNode::BinOp(_, _, BinOp::Add)));
+}
+#[test]
+fn errors() {
+    let Err(err) = parse("(class public \"A\"\n  (field \"a\" u33))") else {
+        panic!("Expected an error");
+    };
+    assert_eq!(err.line(), 2);
+    assert!(parse("(class public \"A\"").is_err());
+    assert!(parse("(class public \"A\"))").is_err());
+    assert!(parse("(class public \"A\") (class public \"A\")").is_err());
+    // Interned items must be unique, and can only refer to nodes before them.
+    assert!(parse("(interned (string \"a\") (string \"a\"))").is_err());
+    assert!(parse("(interned (node (neg #0)))").is_err());
+    assert!(parse("(interned (node (ldarg 0)) (node (neg #0)))").is_ok());
+}
@@ -0,0 +1,885 @@
+use std::{
+    iter::Peekable,
+    num::{NonZeroU32, NonZeroU8},
+    str::Chars,
+    str::FromStr,
+};
+
+use super::{ParseError, BINOPS, CMP_KINDS, FLOATS, GENERIC_KINDS, INTS, METHOD_KINDS, TABLES};
+use crate::v2::{
+    bimap::IntoBiMapIndex,
+    cilnode::{ExtendKind, PtrCastRes, UnOp},
+    cilroot::{BranchCond, SwitchTargets},
+    hashable::{HashableF32, HashableF64},
+    Access, Assembly, BasicBlock, CILNode, CILRoot, ClassDef, ClassDefIdx, ClassRef, ClassRefIdx,
+    Const, FieldDesc, FieldIdx, FnSig, Int, MethodDef, MethodImpl, MethodRef, MethodRefIdx,
+    NodeIdx, RootIdx, SigIdx, StaticFieldDesc, StaticFieldIdx, StringIdx, Type,
+};
+
+/// A single s-expression, together with the line it starts on.
+pub(super) enum SExp {
+    Atom(Box<str>, usize),
+    Str(Box<str>, usize),
+    List(Vec<SExp>, usize),
+}
+impl SExp {
+    fn line(&self) -> usize {
+        match self {
+            SExp::Atom(_, line) | SExp::Str(_, line) | SExp::List(_, line) => *line,
+        }
+    }
+    fn error(&self, msg: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line(),
+            msg: msg.into(),
+        }
+    }
+    fn atom(&self) -> Result<&str, ParseError> {
+        match self {
+            SExp::Atom(atom, _) => Ok(atom),
+            _ => Err(self.error("expected an atom")),
+        }
+    }
+    fn string(&self) -> Result<&str, ParseError> {
+        match self {
+            SExp::Str(string, _) => Ok(string),
+            _ => Err(self.error("expected a string")),
+        }
+    }
+    fn num<T: FromStr>(&self) -> Result<T, ParseError> {
+        self.atom()?
+            .parse()
+            .map_err(|_| self.error(format!("invalid number {:?}", self.atom().unwrap())))
+    }
+    /// Splits a list into its head atom and its arguments.
+    fn form(&self) -> Result<(&str, &[SExp]), ParseError> {
+        match self {
+            SExp::List(elems, _) => match elems.split_first() {
+                Some((head, args)) => Ok((head.atom()?, args)),
+                None => Err(self.error("expected a non-empty list")),
+            },
+            _ => Err(self.error("expected a list")),
+        }
+    }
+    /// Returns the arguments of this form, if its head is `head`.
+    pub(super) fn args_of(&self, head: &str) -> Option<&[SExp]> {
+        self.form()
+            .ok()
+            .and_then(|(form_head, args)| (form_head == head).then_some(args))
+    }
+}
+/// Splits source text into s-expressions.
+pub(super) struct Reader<'src> {
+    chars: Peekable<Chars<'src>>,
+    line: usize,
+}
+impl<'src> Reader<'src> {
+    pub(super) fn new(src: &'src str) -> Self {
+        Self {
+            chars: src.chars().peekable(),
+            line: 1,
+        }
+    }
+    fn error(&self, msg: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            msg: msg.into(),
+        }
+    }
+    fn next_char(&mut self) -> Option<char> {
+        let next = self.chars.next();
+        if next == Some('\n') {
+            self.line += 1;
+        }
+        next
+    }
+    /// Skips whitespace and `;` comments.
+    fn skip_trivia(&mut self) {
+        while let Some(next) = self.chars.peek() {
+            match next {
+                ';' => while !matches!(self.next_char(), Some('\n') | None) {},
+                _ if next.is_whitespace() => {
+                    self.next_char();
+                }
+                _ => return,
+            }
+        }
+    }
+    /// Reads all the s-expressions left in the source.
+    pub(super) fn read_all(&mut self) -> Result<Vec<SExp>, ParseError> {
+        let mut sexps = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.chars.peek() {
+                None => return Ok(sexps),
+                Some(')') => return Err(self.error("unmatched `)`")),
+                Some(_) => sexps.push(self.read()?),
+            }
+        }
+    }
+    fn read(&mut self) -> Result<SExp, ParseError> {
+        let line = self.line;
+        match self.chars.peek() {
+            Some('(') => {
+                self.next_char();
+                let mut elems = Vec::new();
+                loop {
+                    self.skip_trivia();
+                    match self.chars.peek() {
+                        None => {
+                            return Err(ParseError {
+                                line,
+                                msg: "unclosed `(`".into(),
+                            })
+                        }
+                        Some(')') => {
+                            self.next_char();
+                            return Ok(SExp::List(elems, line));
+                        }
+                        Some(_) => elems.push(self.read()?),
+                    }
+                }
+            }
+            Some('"') => {
+                self.next_char();
+                let mut string = String::new();
+                loop {
+                    match self.next_char() {
+                        None => {
+                            return Err(ParseError {
+                                line,
+                                msg: "unterminated string".into(),
+                            })
+                        }
+                        Some('"') => return Ok(SExp::Str(string.into(), line)),
+                        Some('\\') => string.push(self.escape()?),
+                        Some(other) => string.push(other),
+                    }
+                }
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(next) = self.chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '"' | ';') {
+                        break;
+                    }
+                    atom.push(*next);
+                    self.next_char();
+                }
+                Ok(SExp::Atom(atom.into(), line))
+            }
+        }
+    }
+    /// Reads an escape sequence, in the format used by Rust's `Debug` implementation for `str`.
+    fn escape(&mut self) -> Result<char, ParseError> {
+        Ok(match self.next_char() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('u') => {
+                if self.next_char() != Some('{') {
+                    return Err(self.error("expected `{` after `\\u`"));
+                }
+                let mut hex = String::new();
+                loop {
+                    match self.next_char() {
+                        Some('}') => break,
+                        Some(digit) if digit.is_ascii_hexdigit() => hex.push(digit),
+                        _ => return Err(self.error("invalid unicode escape")),
+                    }
+                }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("invalid unicode escape"))?
+            }
+            _ => return Err(self.error("invalid escape sequence")),
+        })
+    }
+}
+/// Builds an assembly from parsed s-expressions.
+pub(super) struct Parser {
+    asm: Assembly,
+}
+impl Parser {
+    pub(super) fn new() -> Self {
+        Self {
+            asm: Assembly::default(),
+        }
+    }
+    pub(super) fn finish(self) -> Assembly {
+        self.asm
+    }
+    fn string(&mut self, sexp: &SExp) -> Result<StringIdx, ParseError> {
+        let string = sexp.string()?;
+        Ok(self.asm.alloc_string(string))
+    }
+    /// Parses either a string, or `_`.
+    fn optional_string(&mut self, sexp: &SExp) -> Result<Option<StringIdx>, ParseError> {
+        match sexp {
+            SExp::Atom(atom, _) if &**atom == "_" => Ok(None),
+            _ => self.string(sexp).map(Some),
+        }
+    }
+    pub(super) fn tpe(&mut self, sexp: &SExp) -> Result<Type, ParseError> {
+        if let SExp::Atom(atom, _) = sexp {
+            if let Some(int) = INTS.iter().find(|int| int.name() == &**atom) {
+                return Ok(Type::Int(*int));
+            }
+            if let Some(float) = FLOATS.iter().find(|float| float.name() == &**atom) {
+                return Ok(Type::Float(*float));
+            }
+            return match &**atom {
+                "string" => Ok(Type::PlatformString),
+                "char" => Ok(Type::PlatformChar),
+                "object" => Ok(Type::PlatformObject),
+                "bool" => Ok(Type::Bool),
+                "void" => Ok(Type::Void),
+                _ => Err(sexp.error(format!("unknown type {atom:?}"))),
+            };
+        }
+        Ok(match sexp.form()? {
+            ("ptr", [inner]) => {
+                let inner = self.tpe(inner)?;
+                self.asm.nptr(inner)
+            }
+            ("ref", [inner]) => {
+                let inner = self.tpe(inner)?;
+                self.asm.nref(inner)
+            }
+            ("generic", [idx, kind]) => {
+                Type::PlatformGeneric(idx.num()?, lookup(&GENERIC_KINDS, kind, "generic kind")?)
+            }
+            ("array", [elem, dims]) => {
+                let elem = self.tpe(elem)?;
+                Type::PlatformArray {
+                    elem: self.asm.alloc_type(elem),
+                    dims: dims.num::<NonZeroU8>()?,
+                }
+            }
+            ("fnptr", [sig]) => Type::FnPtr(self.sig(sig)?),
+            ("class" | "valuetype", _) => Type::ClassRef(self.cref(sexp)?),
+            _ => return Err(sexp.error("invalid type")),
+        })
+    }
+    fn types(&mut self, sexps: &[SExp]) -> Result<Box<[Type]>, ParseError> {
+        sexps.iter().map(|sexp| self.tpe(sexp)).collect()
+    }
+    fn cref(&mut self, sexp: &SExp) -> Result<ClassRefIdx, ParseError> {
+        let (head, args) = sexp.form()?;
+        let is_valuetype = match head {
+            "class" => false,
+            "valuetype" => true,
+            _ => return Err(sexp.error("expected a class reference")),
+        };
+        let Some((name, mut args)) = args.split_first() else {
+            return Err(sexp.error("expected a class name"));
+        };
+        let name = self.string(name)?;
+        let mut asm = None;
+        if let Some((first, rest)) = args.split_first() {
+            if let Some(asm_args) = first.args_of("asm") {
+                let [asm_name] = asm_args else {
+                    return Err(first.error("expected an assembly name"));
+                };
+                asm = Some(self.string(asm_name)?);
+                args = rest;
+            }
+        }
+        let generics = match args {
+            [] => Box::default(),
+            [generics] => match generics.args_of("generics") {
+                Some(generics) => self.types(generics)?,
+                None => return Err(generics.error("expected generic arguments")),
+            },
+            _ => return Err(sexp.error("invalid class reference")),
+        };
+        Ok(self
+            .asm
+            .alloc_class_ref(ClassRef::new(name, asm, is_valuetype, generics)))
+    }
+    fn sig(&mut self, sexp: &SExp) -> Result<SigIdx, ParseError> {
+        let ("sig", [SExp::List(inputs, _), output]) = sexp.form()? else {
+            return Err(sexp.error("expected a signature"));
+        };
+        let inputs = self.types(inputs)?;
+        let output = self.tpe(output)?;
+        Ok(self.asm.alloc_sig(FnSig::new(inputs, output)))
+    }
+    fn mref(&mut self, sexp: &SExp) -> Result<MethodRefIdx, ParseError> {
+        let ("methodref", [class, name, sig, kind, generics @ ..]) = sexp.form()? else {
+            return Err(sexp.error("expected a method reference"));
+        };
+        let class = self.cref(class)?;
+        let name = self.string(name)?;
+        let sig = self.sig(sig)?;
+        let kind = lookup(&METHOD_KINDS, kind, "method kind")?;
+        let generics = match generics {
+            [] => Box::default(),
+            [generics] => match generics.args_of("generics") {
+                Some(generics) => self.types(generics)?,
+                None => return Err(generics.error("expected generic arguments")),
+            },
+            _ => return Err(sexp.error("invalid method reference")),
+        };
+        Ok(self
+            .asm
+            .alloc_methodref(MethodRef::new(class, name, sig, kind, generics)))
+    }
+    fn field(&mut self, sexp: &SExp) -> Result<FieldIdx, ParseError> {
+        let ("fieldref", [owner, name, tpe]) = sexp.form()? else {
+            return Err(sexp.error("expected a field reference"));
+        };
+        let owner = self.cref(owner)?;
+        let name = self.string(name)?;
+        let tpe = self.tpe(tpe)?;
+        Ok(self.asm.alloc_field(FieldDesc::new(owner, name, tpe)))
+    }
+    fn sfld(&mut self, sexp: &SExp) -> Result<StaticFieldIdx, ParseError> {
+        let ("staticref", [owner, name, tpe]) = sexp.form()? else {
+            return Err(sexp.error("expected a static field reference"));
+        };
+        let owner = self.cref(owner)?;
+        let name = self.string(name)?;
+        let tpe = self.tpe(tpe)?;
+        Ok(self.asm.alloc_sfld(StaticFieldDesc::new(owner, name, tpe)))
+    }
+    fn cst(&mut self, sexp: &SExp, args: &[SExp]) -> Result<Const, ParseError> {
+        let tpe = args.first().map(SExp::atom).transpose()?;
+        Ok(match (tpe, args) {
+            (Some("i8"), [_, val]) => Const::I8(val.num()?),
+            (Some("i16"), [_, val]) => Const::I16(val.num()?),
+            (Some("i32"), [_, val]) => Const::I32(val.num()?),
+            (Some("i64"), [_, val]) => Const::I64(val.num()?),
+            (Some("isize"), [_, val]) => Const::ISize(val.num()?),
+            (Some("u8"), [_, val]) => Const::U8(val.num()?),
+            (Some("u16"), [_, val]) => Const::U16(val.num()?),
+            (Some("u32"), [_, val]) => Const::U32(val.num()?),
+            (Some("u64"), [_, val]) => Const::U64(val.num()?),
+            (Some("usize"), [_, val]) => Const::USize(val.num()?),
+            (Some("string"), [_, val]) => Const::PlatformString(self.string(val)?),
+            (Some("bool"), [_, val]) => Const::Bool(val.num()?),
+            (Some("f32"), [_, val]) => Const::F32(HashableF32(val.num()?)),
+            (Some("f64"), [_, val]) => Const::F64(HashableF64(val.num()?)),
+            (Some("f32"), [_, bits, val]) if bits.atom()? == "bits" => {
+                Const::F32(HashableF32(f32::from_bits(
+                    hex(val)?
+                        .try_into()
+                        .map_err(|_| val.error("f32 bits out of range"))?,
+                )))
+            }
+            (Some("f64"), [_, bits, val]) if bits.atom()? == "bits" => {
+                Const::F64(HashableF64(f64::from_bits(hex(val)?)))
+            }
+            (Some("null"), [_, cref]) => Const::Null(self.cref(cref)?),
+            _ => return Err(sexp.error("invalid constant")),
+        })
+    }
+    fn nodes(&mut self, sexps: &[SExp]) -> Result<Box<[NodeIdx]>, ParseError> {
+        sexps.iter().map(|sexp| self.node(sexp)).collect()
+    }
+    pub(super) fn node(&mut self, sexp: &SExp) -> Result<NodeIdx, ParseError> {
+        // A reference to a node of the `interned` form.
+        if let Some(idx) = sexp.atom().ok().and_then(|atom| atom.strip_prefix('#')) {
+            let idx: usize = idx
+                .parse()
+                .map_err(|_| sexp.error(format!("invalid node reference {idx:?}")))?;
+            if idx >= self.asm.interned_counts()[7] {
+                return Err(sexp.error(format!("undefined node #{idx}")));
+            }
+            let idx =
+                u32::try_from(idx + 1).map_err(|_| sexp.error("node reference out of range"))?;
+            return Ok(NodeIdx::from_index(NonZeroU32::new(idx).unwrap()));
+        }
+        let (head, args) = sexp.form()?;
+        if let Some((op, _)) = BINOPS.iter().find(|(_, name)| *name == head) {
+            let [lhs, rhs] = args else {
+                return Err(sexp.error("binary operators take 2 arguments"));
+            };
+            let node = CILNode::BinOp(self.node(lhs)?, self.node(rhs)?, *op);
+            return Ok(self.asm.alloc_node(node));
+        }
+        let node = match (head, args) {
+            ("const", _) => CILNode::Const(Box::new(self.cst(sexp, args)?)),
+            ("not", [val]) => CILNode::UnOp(self.node(val)?, UnOp::Not),
+            ("neg", [val]) => CILNode::UnOp(self.node(val)?, UnOp::Neg),
+            ("ldloc", [idx]) => CILNode::LdLoc(idx.num()?),
+            ("ldloca", [idx]) => CILNode::LdLocA(idx.num()?),
+            ("ldarg", [idx]) => CILNode::LdArg(idx.num()?),
+            ("ldarga", [idx]) => CILNode::LdArgA(idx.num()?),
+            ("call", [mref, call_args @ ..]) => {
+                CILNode::Call(Box::new((self.mref(mref)?, self.nodes(call_args)?)))
+            }
+            ("intcast", [target, extend, input]) => CILNode::IntCast {
+                input: self.node(input)?,
+                target: *INTS
+                    .iter()
+                    .find(|int| int.name() == target.atom().unwrap_or_default())
+                    .ok_or_else(|| target.error("expected an integer type"))?,
+                extend: match extend.atom()? {
+                    "zext" => ExtendKind::ZeroExtend,
+                    "sext" => ExtendKind::SignExtend,
+                    _ => return Err(extend.error("expected `zext` or `sext`")),
+                },
+            },
+            ("floatcast", [target, signed, input]) => CILNode::FloatCast {
+                input: self.node(input)?,
+                target: *FLOATS
+                    .iter()
+                    .find(|float| float.name() == target.atom().unwrap_or_default())
+                    .ok_or_else(|| target.error("expected a float type"))?,
+                is_signed: match signed.atom()? {
+                    "signed" => true,
+                    "unsigned" => false,
+                    _ => return Err(signed.error("expected `signed` or `unsigned`")),
+                },
+            },
+            ("ref_to_ptr", [val]) => CILNode::RefToPtr(self.node(val)?),
+            ("ptrcast", [res, val]) => {
+                let res = match self.tpe(res)? {
+                    Type::Ptr(inner) => PtrCastRes::Ptr(inner),
+                    Type::Ref(inner) => PtrCastRes::Ref(inner),
+                    Type::FnPtr(sig) => PtrCastRes::FnPtr(sig),
+                    Type::Int(Int::USize) => PtrCastRes::USize,
+                    Type::Int(Int::ISize) => PtrCastRes::ISize,
+                    _ => return Err(res.error("invalid pointer cast target")),
+                };
+                CILNode::PtrCast(self.node(val)?, Box::new(res))
+            }
+            ("ldflda", [field, addr]) => CILNode::LdFieldAdress {
+                field: self.field(field)?,
+                addr: self.node(addr)?,
+            },
+            ("ldfld", [field, addr]) => CILNode::LdField {
+                field: self.field(field)?,
+                addr: self.node(addr)?,
+            },
+            ("ldind", [volitale, tpe, addr]) if volitale.atom().ok() == Some("volatile") => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::LdInd {
+                    tpe: self.asm.alloc_type(tpe),
+                    addr: self.node(addr)?,
+                    volitale: true,
+                }
+            }
+            ("ldind", [tpe, addr]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::LdInd {
+                    tpe: self.asm.alloc_type(tpe),
+                    addr: self.node(addr)?,
+                    volitale: false,
+                }
+            }
+            ("sizeof", [tpe]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::SizeOf(self.asm.alloc_type(tpe))
+            }
+            ("get_exception", []) => CILNode::GetException,
+            ("isinst", [tpe, val]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::IsInst(self.node(val)?, self.asm.alloc_type(tpe))
+            }
+            ("castclass", [tpe, val]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::CheckedCast(self.node(val)?, self.asm.alloc_type(tpe))
+            }
+            ("calli", [sig, ptr, call_args @ ..]) => CILNode::CallI(Box::new((
+                self.node(ptr)?,
+                self.sig(sig)?,
+                self.nodes(call_args)?,
+            ))),
+            ("localloc", [size]) => CILNode::LocAlloc {
+                size: self.node(size)?,
+            },
+            ("ldsfld", [sfld]) => CILNode::LdStaticField(self.sfld(sfld)?),
+            ("ldsflda", [sfld]) => CILNode::LdStaticFieldAdress(self.sfld(sfld)?),
+            ("ldftn", [mref]) => CILNode::LdFtn(self.mref(mref)?),
+            ("ldtoken", [tpe]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::LdTypeToken(self.asm.alloc_type(tpe))
+            }
+            ("ldlen", [arr]) => CILNode::LdLen(self.node(arr)?),
+            ("localloc_aligned", [tpe, align]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::LocAllocAlgined {
+                    tpe: self.asm.alloc_type(tpe),
+                    align: align.num()?,
+                }
+            }
+            ("ldelema", [array, index]) => CILNode::LdElelemRef {
+                array: self.node(array)?,
+                index: self.node(index)?,
+            },
+            ("unbox_any", [tpe, object]) => {
+                let tpe = self.tpe(tpe)?;
+                CILNode::UnboxAny {
+                    object: self.node(object)?,
+                    tpe: self.asm.alloc_type(tpe),
+                }
+            }
+            _ => return Err(sexp.error(format!("invalid node `{head}`"))),
+        };
+        Ok(self.asm.alloc_node(node))
+    }
+    fn cond(&mut self, sexp: &SExp) -> Result<BranchCond, ParseError> {
+        Ok(match sexp.form()? {
+            ("true", [val]) => BranchCond::True(self.node(val)?),
+            ("false", [val]) => BranchCond::False(self.node(val)?),
+            ("eq", [lhs, rhs]) => BranchCond::Eq(self.node(lhs)?, self.node(rhs)?),
+            ("ne", [lhs, rhs]) => BranchCond::Ne(self.node(lhs)?, self.node(rhs)?),
+            (head @ ("lt" | "gt" | "le" | "ge"), [kind, lhs, rhs]) => {
+                let kind = lookup(&CMP_KINDS, kind, "comparison kind")?;
+                let (lhs, rhs) = (self.node(lhs)?, self.node(rhs)?);
+                match head {
+                    "lt" => BranchCond::Lt(lhs, rhs, kind),
+                    "gt" => BranchCond::Gt(lhs, rhs, kind),
+                    "le" => BranchCond::Le(lhs, rhs, kind),
+                    _ => BranchCond::Ge(lhs, rhs, kind),
+                }
+            }
+            _ => return Err(sexp.error("invalid branch condition")),
+        })
+    }
+    pub(super) fn root(&mut self, sexp: &SExp) -> Result<RootIdx, ParseError> {
+        let (head, args) = sexp.form()?;
+        let root = match (head, args) {
+            ("stloc", [idx, val]) => CILRoot::StLoc(idx.num()?, self.node(val)?),
+            ("starg", [idx, val]) => CILRoot::StArg(idx.num()?, self.node(val)?),
+            ("ret", [val]) => CILRoot::Ret(self.node(val)?),
+            ("ret", []) => CILRoot::VoidRet,
+            ("pop", [val]) => CILRoot::Pop(self.node(val)?),
+            ("throw", [val]) => CILRoot::Throw(self.node(val)?),
+            ("break", []) => CILRoot::Break,
+            ("nop", []) => CILRoot::Nop,
+            ("br", [target, sub_target]) => {
+                CILRoot::Branch(Box::new((target.num()?, sub_target.num()?, None)))
+            }
+            ("br", [target, sub_target, cond]) => CILRoot::Branch(Box::new((
+                target.num()?,
+                sub_target.num()?,
+                Some(self.cond(cond)?),
+            ))),
+            ("switch", [int, val, otherwise, cases @ ..]) => {
+                let int = *INTS
+                    .iter()
+                    .find(|tpe| tpe.name() == int.atom().unwrap_or_default())
+                    .ok_or_else(|| int.error("expected an integer type"))?;
+                let Some([target, sub_target]) = otherwise.args_of("default") else {
+                    return Err(otherwise.error("expected the default target of a switch"));
+                };
+                let otherwise = (target.num()?, sub_target.num()?);
+                let cases = cases
+                    .iter()
+                    .map(|case| match case.args_of("case") {
+                        Some([value, target, sub_target]) => {
+                            Ok((value.num()?, (target.num()?, sub_target.num()?)))
+                        }
+                        _ => Err(case.error("expected a switch case")),
+                    })
+                    .collect::<Result<_, _>>()?;
+                CILRoot::Switch(Box::new((
+                    self.node(val)?,
+                    int,
+                    SwitchTargets::new(cases, otherwise),
+                )))
+            }
+            ("source", [file, line_start, line_len, col_start, col_len]) => {
+                CILRoot::SourceFileInfo {
+                    line_start: line_start.num()?,
+                    line_len: line_len.num()?,
+                    col_start: col_start.num()?,
+                    col_len: col_len.num()?,
+                    file: self.string(file)?,
+                }
+            }
+            ("stfld", [field, addr, val]) => CILRoot::SetField(Box::new((
+                self.field(field)?,
+                self.node(addr)?,
+                self.node(val)?,
+            ))),
+            ("call", [mref, call_args @ ..]) => {
+                CILRoot::Call(Box::new((self.mref(mref)?, self.nodes(call_args)?)))
+            }
+            ("stind", [volitale, tpe, addr, val]) if volitale.atom().ok() == Some("volatile") => {
+                CILRoot::StInd(Box::new((
+                    self.node(addr)?,
+                    self.node(val)?,
+                    self.tpe(tpe)?,
+                    true,
+                )))
+            }
+            ("stind", [tpe, addr, val]) => CILRoot::StInd(Box::new((
+                self.node(addr)?,
+                self.node(val)?,
+                self.tpe(tpe)?,
+                false,
+            ))),
+            ("initblk", [dst, val, count]) => CILRoot::InitBlk(Box::new((
+                self.node(dst)?,
+                self.node(val)?,
+                self.node(count)?,
+            ))),
+            ("cpblk", [dst, src, len]) => CILRoot::CpBlk(Box::new((
+                self.node(dst)?,
+                self.node(src)?,
+                self.node(len)?,
+            ))),
+            ("calli", [sig, ptr, call_args @ ..]) => CILRoot::CallI(Box::new((
+                self.node(ptr)?,
+                self.sig(sig)?,
+                self.nodes(call_args)?,
+            ))),
+            ("tail_call", [mref, call_args @ ..]) => {
+                CILRoot::TailCall(Box::new((self.mref(mref)?, self.nodes(call_args)?)))
+            }
+            ("tail_calli", [sig, ptr, call_args @ ..]) => CILRoot::TailCallI(Box::new((
+                self.node(ptr)?,
+                self.sig(sig)?,
+                self.nodes(call_args)?,
+            ))),
+            ("exit_special_region", [target, source]) => CILRoot::ExitSpecialRegion {
+                target: target.num()?,
+                source: source.num()?,
+            },
+            ("rethrow", []) => CILRoot::ReThrow,
+            ("stsfld", [sfld, val]) => CILRoot::SetStaticField {
+                field: self.sfld(sfld)?,
+                val: self.node(val)?,
+            },
+            ("cpobj", [tpe, src, dst]) => {
+                let tpe = self.tpe(tpe)?;
+                CILRoot::CpObj {
+                    src: self.node(src)?,
+                    dst: self.node(dst)?,
+                    tpe: self.asm.alloc_type(tpe),
+                }
+            }
+            ("unreachable", [msg]) => CILRoot::Unreachable(self.string(msg)?),
+            _ => return Err(sexp.error(format!("invalid root `{head}`"))),
+        };
+        Ok(self.asm.alloc_root(root))
+    }
+    fn block(&mut self, sexp: &SExp) -> Result<BasicBlock, ParseError> {
+        let ("block", [block_id, body @ ..]) = sexp.form()? else {
+            return Err(sexp.error("expected a block"));
+        };
+        let mut body = body;
+        let mut handler = None;
+        if let Some((last, rest)) = body.split_last() {
+            if let Some(handler_blocks) = last.args_of("handler") {
+                handler = Some(
+                    handler_blocks
+                        .iter()
+                        .map(|block| self.block(block))
+                        .collect::<Result<_, _>>()?,
+                );
+                body = rest;
+            }
+        }
+        let roots = body
+            .iter()
+            .map(|root| self.root(root))
+            .collect::<Result<_, _>>()?;
+        Ok(BasicBlock::new(roots, block_id.num()?, handler))
+    }
+    fn implementation(&mut self, sexp: &SExp) -> Result<MethodImpl, ParseError> {
+        Ok(match sexp.form()? {
+            ("body", [locals, blocks @ ..]) => {
+                let Some(locals) = locals.args_of("locals") else {
+                    return Err(locals.error("expected a list of locals"));
+                };
+                let locals = locals
+                    .iter()
+                    .map(|local| {
+                        let ("local", [name, tpe]) = local.form()? else {
+                            return Err(local.error("expected a local"));
+                        };
+                        let name = self.optional_string(name)?;
+                        let tpe = self.tpe(tpe)?;
+                        Ok((name, self.asm.alloc_type(tpe)))
+                    })
+                    .collect::<Result<_, _>>()?;
+                let blocks = blocks
+                    .iter()
+                    .map(|block| self.block(block))
+                    .collect::<Result<_, _>>()?;
+                MethodImpl::MethodBody { blocks, locals }
+            }
+            ("extern", [lib]) => MethodImpl::Extern {
+                lib: self.string(lib)?,
+                preserve_errno: false,
+            },
+            ("extern", [lib, preserve_errno]) if preserve_errno.atom()? == "preserve_errno" => {
+                MethodImpl::Extern {
+                    lib: self.string(lib)?,
+                    preserve_errno: true,
+                }
+            }
+            ("alias", [mref]) => MethodImpl::AliasFor(self.mref(mref)?),
+            ("missing", []) => MethodImpl::Missing,
+            _ => return Err(sexp.error("invalid method implementation")),
+        })
+    }
+    fn method(&mut self, class: ClassDefIdx, sexp: &SExp) -> Result<(), ParseError> {
+        let ("method", [access, kind, name, sig, rest @ ..]) = sexp.form()? else {
+            return Err(sexp.error("expected a method definition"));
+        };
+        let mut rest = rest;
+        let access = parse_access(access)?;
+        let kind = lookup(&METHOD_KINDS, kind, "method kind")?;
+        let name = self.string(name)?;
+        let sig = self.sig(sig)?;
+        let mut arg_names = vec![];
+        if let Some((first, tail)) = rest.split_first() {
+            if let Some(args) = first.args_of("args") {
+                arg_names = args
+                    .iter()
+                    .map(|arg| self.optional_string(arg))
+                    .collect::<Result<_, _>>()?;
+                rest = tail;
+            }
+        }
+        let mut doc = None;
+        if let Some((first, tail)) = rest.split_first() {
+            if let Some(doc_args) = first.args_of("doc") {
+                let [doc_string] = doc_args else {
+                    return Err(first.error("expected a doc string"));
+                };
+                doc = Some(self.string(doc_string)?);
+                rest = tail;
+            }
+        }
+        let [implementation] = rest else {
+            return Err(sexp.error("expected a single method implementation"));
+        };
+        let implementation = self.implementation(implementation)?;
+        let mut def = MethodDef::new(access, class, name, sig, kind, implementation, arg_names);
+        def.set_doc(doc);
+        self.asm.new_method(def);
+        Ok(())
+    }
+    /// Parses the `interned` form, checking that every item in it is interned at the next index of its table.
+    pub(super) fn interned(&mut self, sexp: &SExp) -> Result<(), ParseError> {
+        let (_, items) = sexp.form()?;
+        for item in items {
+            let (head, args) = item.form()?;
+            let Some(table) = TABLES.iter().position(|table| *table == head) else {
+                return Err(item.error(format!("{head:?} is not an interned item")));
+            };
+            let expected = self.asm.interned_counts()[table] + 1;
+            let idx = match (head, args) {
+                ("string", [string]) => self.string(string)?.as_bimap_index(),
+                ("type", [tpe]) => {
+                    let tpe = self.tpe(tpe)?;
+                    self.asm.alloc_type(tpe).as_bimap_index()
+                }
+                ("classref", [cref]) => self.cref(cref)?.as_bimap_index(),
+                ("sig", _) => self.sig(item)?.as_bimap_index(),
+                ("methodref", _) => self.mref(item)?.as_bimap_index(),
+                ("fieldref", _) => self.field(item)?.as_bimap_index(),
+                ("staticref", _) => self.sfld(item)?.as_bimap_index(),
+                ("node", [node]) => self.node(node)?.as_bimap_index(),
+                ("root", [root]) => self.root(root)?.as_bimap_index(),
+                _ => return Err(item.error(format!("invalid {head}"))),
+            };
+            if idx.get() as usize != expected {
+                return Err(
+                    item.error("interned items must be unique, and follow the items they refer to")
+                );
+            }
+        }
+        Ok(())
+    }
+    pub(super) fn class(&mut self, sexp: &SExp) -> Result<(), ParseError> {
+        let ("class", [access, rest @ ..]) = sexp.form()? else {
+            return Err(sexp.error("expected a class definition"));
+        };
+        let access = parse_access(access)?;
+        let (is_valuetype, name, members) = match rest {
+            [SExp::Atom(valuetype, _), name, members @ ..] if &**valuetype == "valuetype" => {
+                (true, name, members)
+            }
+            [name, members @ ..] => (false, name, members),
+            [] => return Err(sexp.error("expected a class name")),
+        };
+        let name = self.string(name)?;
+        let mut extends = None;
+        let mut implements = vec![];
+        let mut explict_size = None;
+        let mut fields = vec![];
+        let mut static_fields = vec![];
+        let mut methods = vec![];
+        for member in members {
+            match member.form()? {
+                ("extends", [cref]) => extends = Some(self.cref(cref)?),
+                ("implements", crefs) => {
+                    for cref in crefs {
+                        implements.push(self.cref(cref)?);
+                    }
+                }
+                ("size", [size]) => explict_size = Some(size.num()?),
+                ("field", [name, tpe]) => fields.push((self.tpe(tpe)?, self.string(name)?, None)),
+                ("field", [name, tpe, offset]) => {
+                    fields.push((self.tpe(tpe)?, self.string(name)?, Some(offset.num()?)));
+                }
+                ("static", [name, tpe]) => {
+                    static_fields.push((self.tpe(tpe)?, self.string(name)?, false));
+                }
+                ("static", [name, tpe, thread_local]) if thread_local.atom()? == "thread_local" => {
+                    static_fields.push((self.tpe(tpe)?, self.string(name)?, true));
+                }
+                ("method", _) => methods.push(member),
+                _ => return Err(member.error("invalid class member")),
+            }
+        }
+        let mut def = ClassDef::new(
+            name,
+            is_valuetype,
+            0,
+            extends,
+            fields,
+            static_fields,
+            access,
+            explict_size,
+        );
+        for interface in implements {
+            def.add_interface(interface);
+        }
+        let cref = self.asm.alloc_class_ref(def.ref_to());
+        if self.asm.class_defs().contains_key(&ClassDefIdx(cref)) {
+            return Err(sexp.error("duplicate class definition"));
+        }
+        let class = self.asm.class_def(def);
+        for method in methods {
+            self.method(class, method)?;
+        }
+        Ok(())
+    }
+}
+fn parse_access(sexp: &SExp) -> Result<Access, ParseError> {
+    match sexp.atom()? {
+        "extern" => Ok(Access::Extern),
+        "public" => Ok(Access::Public),
+        "private" => Ok(Access::Private),
+        _ => Err(sexp.error("expected an access modifier")),
+    }
+}
+/// Finds the value named by `sexp` in a table.
+fn lookup<T: Clone>(table: &[(T, &str)], sexp: &SExp, what: &str) -> Result<T, ParseError> {
+    let atom = sexp.atom()?;
+    table
+        .iter()
+        .find(|(_, name)| *name == atom)
+        .map(|(val, _)| val.clone())
+        .ok_or_else(|| sexp.error(format!("expected a {what}, got {atom:?}")))
+}
+fn hex(sexp: &SExp) -> Result<u64, ParseError> {
+    let atom = sexp.atom()?;
+    atom.strip_prefix("0x")
+        .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| sexp.error(format!("invalid hex number {atom:?}")))
+}
@@ -0,0 +1,852 @@
+use std::fmt::Write;
+
+use super::{name_of, BINOPS, CMP_KINDS, GENERIC_KINDS, METHOD_KINDS, TABLES};
+use crate::v2::{
+    bimap::IntoBiMapIndex,
+    cilnode::{ExtendKind, UnOp},
+    cilroot::BranchCond,
+    Access, Assembly, BasicBlock, CILNode, CILRoot, ClassDefIdx, ClassRefIdx, Const, FieldIdx,
+    MethodDefIdx, MethodImpl, MethodRefIdx, NodeIdx, RootIdx, SigIdx, StaticFieldIdx, StringIdx,
+    Type, TypeIdx,
+};
+
+pub(super) struct Printer<'asm> {
+    asm: &'asm Assembly,
+    out: String,
+    /// If set, nodes are printed as references to their position in the `interned` form.
+    node_refs: bool,
+}
+impl<'asm> Printer<'asm> {
+    pub(super) fn new(asm: &'asm Assembly) -> Self {
+        Self {
+            asm,
+            out: String::new(),
+            node_refs: false,
+        }
+    }
+    /// Prints every interned item, in the order they need to be interned in, so that parsing them reproduces the
+    /// indices of all the items in the assembly.
+    pub(super) fn interned(&mut self) {
+        let counts = self.asm.interned_counts();
+        if counts.iter().all(|count| *count == 0) {
+            return;
+        }
+        self.out.push_str("(interned");
+        self.node_refs = true;
+        let mut printed = [0; TABLES.len()];
+        for (table, count) in counts.iter().enumerate() {
+            if let Some(last) = count.checked_sub(1) {
+                self.interned_up_to(Interned::new(table, last), &mut printed);
+            }
+        }
+        self.node_refs = false;
+        self.out.push_str(")\n");
+    }
+    /// Prints `item`, together with all the items it depends on, and all the items of the same kind interned before it.
+    fn interned_up_to(&mut self, item: Interned, printed: &mut [usize; TABLES.len()]) {
+        let (table, idx) = item.position();
+        while printed[table] <= idx {
+            let next = Interned::new(table, printed[table]);
+            // An item can only refer to items interned before it, so this always terminates.
+            for dep in next.deps(self.asm) {
+                self.interned_up_to(dep, printed);
+            }
+            self.newline(1);
+            self.interned_item(next);
+            printed[table] += 1;
+        }
+    }
+    fn interned_item(&mut self, item: Interned) {
+        match item {
+            Interned::String(string) => {
+                self.out.push_str("(string ");
+                self.string(&self.asm[string]);
+                self.out.push(')');
+            }
+            Interned::Type(tpe) => {
+                self.out.push_str("(type ");
+                self.tpe(self.asm[tpe]);
+                self.out.push(')');
+            }
+            Interned::ClassRef(cref) => {
+                self.out.push_str("(classref ");
+                self.cref(cref);
+                self.out.push(')');
+            }
+            Interned::Sig(sig) => self.sig(sig),
+            Interned::MethodRef(mref) => self.mref(mref),
+            Interned::Field(field) => self.field(field),
+            Interned::StaticField(sfld) => self.sfld(sfld),
+            Interned::Node(node) => {
+                self.out.push_str("(node ");
+                self.node_form(node);
+                self.out.push(')');
+            }
+            Interned::Root(root) => {
+                self.out.push_str("(root ");
+                self.root(root);
+                self.out.push(')');
+            }
+        }
+    }
+    pub(super) fn finish(self) -> String {
+        self.out
+    }
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        for _ in 0..indent {
+            self.out.push_str("  ");
+        }
+    }
+    fn string(&mut self, string: &str) {
+        write!(self.out, "{string:?}").unwrap();
+    }
+    fn optional_string(&mut self, string: Option<crate::v2::StringIdx>) {
+        match string {
+            Some(string) => self.string(&self.asm[string]),
+            None => self.out.push('_'),
+        }
+    }
+    pub(super) fn tpe(&mut self, tpe: Type) {
+        match tpe {
+            Type::Ptr(inner) => {
+                self.out.push_str("(ptr ");
+                self.tpe(self.asm[inner]);
+                self.out.push(')');
+            }
+            Type::Ref(inner) => {
+                self.out.push_str("(ref ");
+                self.tpe(self.asm[inner]);
+                self.out.push(')');
+            }
+            Type::Int(int) => self.out.push_str(int.name()),
+            Type::ClassRef(cref) => self.cref(cref),
+            Type::Float(float) => self.out.push_str(float.name()),
+            Type::PlatformString => self.out.push_str("string"),
+            Type::PlatformChar => self.out.push_str("char"),
+            Type::PlatformGeneric(idx, kind) => {
+                write!(
+                    self.out,
+                    "(generic {idx} {})",
+                    name_of(&GENERIC_KINDS, &kind)
+                )
+                .unwrap();
+            }
+            Type::PlatformObject => self.out.push_str("object"),
+            Type::Bool => self.out.push_str("bool"),
+            Type::Void => self.out.push_str("void"),
+            Type::PlatformArray { elem, dims } => {
+                self.out.push_str("(array ");
+                self.tpe(self.asm[elem]);
+                write!(self.out, " {dims})").unwrap();
+            }
+            Type::FnPtr(sig) => {
+                self.out.push_str("(fnptr ");
+                self.sig(sig);
+                self.out.push(')');
+            }
+        }
+    }
+    fn types(&mut self, types: &[Type]) {
+        for tpe in types {
+            self.out.push(' ');
+            self.tpe(*tpe);
+        }
+    }
+    fn cref(&mut self, cref: ClassRefIdx) {
+        let cref = &self.asm[cref];
+        if cref.is_valuetype() {
+            self.out.push_str("(valuetype ");
+        } else {
+            self.out.push_str("(class ");
+        }
+        self.string(&self.asm[cref.name()]);
+        if let Some(asm) = cref.asm() {
+            self.out.push_str(" (asm ");
+            self.string(&self.asm[asm]);
+            self.out.push(')');
+        }
+        if !cref.generics().is_empty() {
+            self.out.push_str(" (generics");
+            self.types(cref.generics());
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn sig(&mut self, sig: SigIdx) {
+        let sig = &self.asm[sig];
+        self.out.push_str("(sig (");
+        for (idx, tpe) in sig.inputs().iter().enumerate() {
+            if idx != 0 {
+                self.out.push(' ');
+            }
+            self.tpe(*tpe);
+        }
+        self.out.push_str(") ");
+        self.tpe(*sig.output());
+        self.out.push(')');
+    }
+    fn mref(&mut self, mref: MethodRefIdx) {
+        let mref = &self.asm[mref];
+        self.out.push_str("(methodref ");
+        self.cref(mref.class());
+        self.out.push(' ');
+        self.string(&self.asm[mref.name()]);
+        self.out.push(' ');
+        self.sig(mref.sig());
+        write!(self.out, " {}", name_of(&METHOD_KINDS, &mref.kind())).unwrap();
+        if !mref.generics().is_empty() {
+            self.out.push_str(" (generics");
+            self.types(mref.generics());
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn field(&mut self, field: FieldIdx) {
+        let field = self.asm[field];
+        self.out.push_str("(fieldref ");
+        self.cref(field.owner());
+        self.out.push(' ');
+        self.string(&self.asm[field.name()]);
+        self.out.push(' ');
+        self.tpe(field.tpe());
+        self.out.push(')');
+    }
+    fn sfld(&mut self, sfld: StaticFieldIdx) {
+        let sfld = self.asm[sfld];
+        self.out.push_str("(staticref ");
+        self.cref(sfld.owner());
+        self.out.push(' ');
+        self.string(&self.asm[sfld.name()]);
+        self.out.push(' ');
+        self.tpe(sfld.tpe());
+        self.out.push(')');
+    }
+    fn cst(&mut self, cst: &Const) {
+        self.out.push_str("(const ");
+        match cst {
+            Const::I8(val) => write!(self.out, "i8 {val}"),
+            Const::I16(val) => write!(self.out, "i16 {val}"),
+            Const::I32(val) => write!(self.out, "i32 {val}"),
+            Const::I64(val) => write!(self.out, "i64 {val}"),
+            Const::ISize(val) => write!(self.out, "isize {val}"),
+            Const::U8(val) => write!(self.out, "u8 {val}"),
+            Const::U16(val) => write!(self.out, "u16 {val}"),
+            Const::U32(val) => write!(self.out, "u32 {val}"),
+            Const::U64(val) => write!(self.out, "u64 {val}"),
+            Const::USize(val) => write!(self.out, "usize {val}"),
+            Const::PlatformString(val) => {
+                self.out.push_str("string ");
+                self.string(&self.asm[*val]);
+                Ok(())
+            }
+            Const::Bool(val) => write!(self.out, "bool {val}"),
+            // The shortest round-tripping representation is used for finite floats. Infinities and NaNs are stored
+            // as raw bits, to preserve NaN payloads.
+            Const::F32(val) if val.is_finite() => write!(self.out, "f32 {:?}", val.0),
+            Const::F32(val) => write!(self.out, "f32 bits {:#010x}", val.to_bits()),
+            Const::F64(val) if val.is_finite() => write!(self.out, "f64 {:?}", val.0),
+            Const::F64(val) => write!(self.out, "f64 bits {:#018x}", val.to_bits()),
+            Const::Null(cref) => {
+                self.out.push_str("null ");
+                self.cref(*cref);
+                Ok(())
+            }
+        }
+        .unwrap();
+        self.out.push(')');
+    }
+    /// Writes the head of a form, followed by its arguments.
+    fn form(&mut self, head: &str, args: &[NodeIdx]) {
+        self.out.push('(');
+        self.out.push_str(head);
+        self.args(args);
+        self.out.push(')');
+    }
+    fn args(&mut self, args: &[NodeIdx]) {
+        for arg in args {
+            self.out.push(' ');
+            self.node(*arg);
+        }
+    }
+    fn node(&mut self, node: NodeIdx) {
+        if self.node_refs {
+            write!(self.out, "#{}", node.as_bimap_index().get() - 1).unwrap();
+        } else {
+            self.node_form(node);
+        }
+    }
+    fn node_form(&mut self, node: NodeIdx) {
+        match &self.asm[node] {
+            CILNode::Const(cst) => self.cst(cst),
+            CILNode::BinOp(lhs, rhs, op) => self.form(name_of(&BINOPS, op), &[*lhs, *rhs]),
+            CILNode::UnOp(val, UnOp::Not) => self.form("not", &[*val]),
+            CILNode::UnOp(val, UnOp::Neg) => self.form("neg", &[*val]),
+            CILNode::LdLoc(idx) => write!(self.out, "(ldloc {idx})").unwrap(),
+            CILNode::LdLocA(idx) => write!(self.out, "(ldloca {idx})").unwrap(),
+            CILNode::LdArg(idx) => write!(self.out, "(ldarg {idx})").unwrap(),
+            CILNode::LdArgA(idx) => write!(self.out, "(ldarga {idx})").unwrap(),
+            CILNode::Call(info) => {
+                let (mref, args) = info.as_ref();
+                self.out.push_str("(call ");
+                self.mref(*mref);
+                self.args(args);
+                self.out.push(')');
+            }
+            CILNode::IntCast {
+                input,
+                target,
+                extend,
+            } => {
+                let extend = match extend {
+                    ExtendKind::ZeroExtend => "zext",
+                    ExtendKind::SignExtend => "sext",
+                };
+                write!(self.out, "(intcast {} {extend}", target.name()).unwrap();
+                self.args(&[*input]);
+                self.out.push(')');
+            }
+            CILNode::FloatCast {
+                input,
+                target,
+                is_signed,
+            } => {
+                let signed = if *is_signed { "signed" } else { "unsigned" };
+                write!(self.out, "(floatcast {} {signed}", target.name()).unwrap();
+                self.args(&[*input]);
+                self.out.push(')');
+            }
+            CILNode::RefToPtr(val) => self.form("ref_to_ptr", &[*val]),
+            CILNode::PtrCast(val, res) => {
+                self.out.push_str("(ptrcast ");
+                self.tpe(res.as_type());
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILNode::LdFieldAdress { addr, field } => {
+                self.out.push_str("(ldflda ");
+                self.field(*field);
+                self.args(&[*addr]);
+                self.out.push(')');
+            }
+            CILNode::LdField { addr, field } => {
+                self.out.push_str("(ldfld ");
+                self.field(*field);
+                self.args(&[*addr]);
+                self.out.push(')');
+            }
+            CILNode::LdInd {
+                addr,
+                tpe,
+                volitale,
+            } => {
+                self.out.push_str(if *volitale {
+                    "(ldind volatile "
+                } else {
+                    "(ldind "
+                });
+                self.tpe(self.asm[*tpe]);
+                self.args(&[*addr]);
+                self.out.push(')');
+            }
+            CILNode::SizeOf(tpe) => {
+                self.out.push_str("(sizeof ");
+                self.tpe(self.asm[*tpe]);
+                self.out.push(')');
+            }
+            CILNode::GetException => self.out.push_str("(get_exception)"),
+            CILNode::IsInst(val, tpe) => {
+                self.out.push_str("(isinst ");
+                self.tpe(self.asm[*tpe]);
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILNode::CheckedCast(val, tpe) => {
+                self.out.push_str("(castclass ");
+                self.tpe(self.asm[*tpe]);
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILNode::CallI(info) => {
+                let (ptr, sig, args) = info.as_ref();
+                self.out.push_str("(calli ");
+                self.sig(*sig);
+                self.args(&[*ptr]);
+                self.args(args);
+                self.out.push(')');
+            }
+            CILNode::LocAlloc { size } => self.form("localloc", &[*size]),
+            CILNode::LdStaticField(sfld) => {
+                self.out.push_str("(ldsfld ");
+                self.sfld(*sfld);
+                self.out.push(')');
+            }
+            CILNode::LdStaticFieldAdress(sfld) => {
+                self.out.push_str("(ldsflda ");
+                self.sfld(*sfld);
+                self.out.push(')');
+            }
+            CILNode::LdFtn(mref) => {
+                self.out.push_str("(ldftn ");
+                self.mref(*mref);
+                self.out.push(')');
+            }
+            CILNode::LdTypeToken(tpe) => {
+                self.out.push_str("(ldtoken ");
+                self.tpe(self.asm[*tpe]);
+                self.out.push(')');
+            }
+            CILNode::LdLen(arr) => self.form("ldlen", &[*arr]),
+            CILNode::LocAllocAlgined { tpe, align } => {
+                self.out.push_str("(localloc_aligned ");
+                self.tpe(self.asm[*tpe]);
+                write!(self.out, " {align})").unwrap();
+            }
+            CILNode::LdElelemRef { array, index } => self.form("ldelema", &[*array, *index]),
+            CILNode::UnboxAny { object, tpe } => {
+                self.out.push_str("(unbox_any ");
+                self.tpe(self.asm[*tpe]);
+                self.args(&[*object]);
+                self.out.push(')');
+            }
+        }
+    }
+    fn cond(&mut self, cond: &BranchCond) {
+        match cond {
+            BranchCond::True(val) => self.form("true", &[*val]),
+            BranchCond::False(val) => self.form("false", &[*val]),
+            BranchCond::Eq(lhs, rhs) => self.form("eq", &[*lhs, *rhs]),
+            BranchCond::Ne(lhs, rhs) => self.form("ne", &[*lhs, *rhs]),
+            BranchCond::Lt(lhs, rhs, kind)
+            | BranchCond::Gt(lhs, rhs, kind)
+            | BranchCond::Le(lhs, rhs, kind)
+            | BranchCond::Ge(lhs, rhs, kind) => {
+                let head = match cond {
+                    BranchCond::Lt(..) => "lt",
+                    BranchCond::Gt(..) => "gt",
+                    BranchCond::Le(..) => "le",
+                    _ => "ge",
+                };
+                write!(self.out, "({head} {}", name_of(&CMP_KINDS, kind)).unwrap();
+                self.args(&[*lhs, *rhs]);
+                self.out.push(')');
+            }
+        }
+    }
+    fn root(&mut self, root: RootIdx) {
+        match &self.asm[root] {
+            CILRoot::StLoc(idx, val) => {
+                write!(self.out, "(stloc {idx}").unwrap();
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILRoot::StArg(idx, val) => {
+                write!(self.out, "(starg {idx}").unwrap();
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILRoot::Ret(val) => self.form("ret", &[*val]),
+            CILRoot::Pop(val) => self.form("pop", &[*val]),
+            CILRoot::Throw(val) => self.form("throw", &[*val]),
+            CILRoot::VoidRet => self.out.push_str("(ret)"),
+            CILRoot::Break => self.out.push_str("(break)"),
+            CILRoot::Nop => self.out.push_str("(nop)"),
+            CILRoot::Branch(info) => {
+                let (target, sub_target, cond) = info.as_ref();
+                write!(self.out, "(br {target} {sub_target}").unwrap();
+                if let Some(cond) = cond {
+                    self.out.push(' ');
+                    self.cond(cond);
+                }
+                self.out.push(')');
+            }
+            CILRoot::Switch(info) => {
+                let (value, int, targets) = info.as_ref();
+                write!(self.out, "(switch {}", int.name()).unwrap();
+                self.args(&[*value]);
+                let (target, sub_target) = targets.otherwise();
+                write!(self.out, " (default {target} {sub_target})").unwrap();
+                for (case, (target, sub_target)) in targets.cases() {
+                    write!(self.out, " (case {case} {target} {sub_target})").unwrap();
+                }
+                self.out.push(')');
+            }
+            CILRoot::SourceFileInfo {
+                line_start,
+                line_len,
+                col_start,
+                col_len,
+                file,
+            } => {
+                self.out.push_str("(source ");
+                self.string(&self.asm[*file]);
+                write!(self.out, " {line_start} {line_len} {col_start} {col_len})").unwrap();
+            }
+            CILRoot::SetField(info) => {
+                let (field, addr, val) = info.as_ref();
+                self.out.push_str("(stfld ");
+                self.field(*field);
+                self.args(&[*addr, *val]);
+                self.out.push(')');
+            }
+            CILRoot::Call(info) | CILRoot::TailCall(info) => {
+                let (mref, args) = info.as_ref();
+                self.out
+                    .push_str(if matches!(self.asm[root], CILRoot::TailCall(_)) {
+                        "(tail_call "
+                    } else {
+                        "(call "
+                    });
+                self.mref(*mref);
+                self.args(args);
+                self.out.push(')');
+            }
+            CILRoot::StInd(info) => {
+                let (addr, val, tpe, volitale) = info.as_ref();
+                self.out.push_str(if *volitale {
+                    "(stind volatile "
+                } else {
+                    "(stind "
+                });
+                self.tpe(*tpe);
+                self.args(&[*addr, *val]);
+                self.out.push(')');
+            }
+            CILRoot::InitBlk(info) => {
+                let (dst, val, count) = info.as_ref();
+                self.form("initblk", &[*dst, *val, *count]);
+            }
+            CILRoot::CpBlk(info) => {
+                let (dst, src, len) = info.as_ref();
+                self.form("cpblk", &[*dst, *src, *len]);
+            }
+            CILRoot::CallI(info) | CILRoot::TailCallI(info) => {
+                let (ptr, sig, args) = info.as_ref();
+                self.out
+                    .push_str(if matches!(self.asm[root], CILRoot::TailCallI(_)) {
+                        "(tail_calli "
+                    } else {
+                        "(calli "
+                    });
+                self.sig(*sig);
+                self.args(&[*ptr]);
+                self.args(args);
+                self.out.push(')');
+            }
+            CILRoot::ExitSpecialRegion { target, source } => {
+                write!(self.out, "(exit_special_region {target} {source})").unwrap();
+            }
+            CILRoot::ReThrow => self.out.push_str("(rethrow)"),
+            CILRoot::SetStaticField { field, val } => {
+                self.out.push_str("(stsfld ");
+                self.sfld(*field);
+                self.args(&[*val]);
+                self.out.push(')');
+            }
+            CILRoot::CpObj { src, dst, tpe } => {
+                self.out.push_str("(cpobj ");
+                self.tpe(self.asm[*tpe]);
+                self.args(&[*src, *dst]);
+                self.out.push(')');
+            }
+            CILRoot::Unreachable(msg) => {
+                self.out.push_str("(unreachable ");
+                self.string(&self.asm[*msg]);
+                self.out.push(')');
+            }
+        }
+    }
+    fn block(&mut self, block: &BasicBlock, indent: usize) {
+        write!(self.out, "(block {}", block.block_id()).unwrap();
+        for root in block.roots() {
+            self.newline(indent + 1);
+            self.root(*root);
+        }
+        if let Some(handler) = block.handler() {
+            self.newline(indent + 1);
+            self.out.push_str("(handler");
+            for block in handler {
+                self.newline(indent + 2);
+                self.block(block, indent + 2);
+            }
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn method(&mut self, method: MethodDefIdx, indent: usize) {
+        let def = self.asm.method_def(method);
+        write!(
+            self.out,
+            "(method {} {} ",
+            access_name(*def.access()),
+            name_of(&METHOD_KINDS, &def.kind())
+        )
+        .unwrap();
+        self.string(&self.asm[def.name()]);
+        self.out.push(' ');
+        self.sig(def.sig());
+        if !def.arg_names().is_empty() {
+            self.newline(indent + 1);
+            self.out.push_str("(args");
+            for name in def.arg_names() {
+                self.out.push(' ');
+                self.optional_string(*name);
+            }
+            self.out.push(')');
+        }
+        if let Some(doc) = def.doc() {
+            self.newline(indent + 1);
+            self.out.push_str("(doc ");
+            self.string(&self.asm[doc]);
+            self.out.push(')');
+        }
+        self.newline(indent + 1);
+        match def.implementation() {
+            MethodImpl::MethodBody { blocks, locals } => {
+                self.out.push_str("(body");
+                self.newline(indent + 2);
+                self.out.push_str("(locals");
+                for (name, tpe) in locals {
+                    self.newline(indent + 3);
+                    self.out.push_str("(local ");
+                    self.optional_string(*name);
+                    self.out.push(' ');
+                    self.tpe(self.asm[*tpe]);
+                    self.out.push(')');
+                }
+                self.out.push(')');
+                for block in blocks {
+                    self.newline(indent + 2);
+                    self.block(block, indent + 2);
+                }
+                self.out.push(')');
+            }
+            MethodImpl::Extern {
+                lib,
+                preserve_errno,
+            } => {
+                self.out.push_str("(extern ");
+                self.string(&self.asm[*lib]);
+                if *preserve_errno {
+                    self.out.push_str(" preserve_errno");
+                }
+                self.out.push(')');
+            }
+            MethodImpl::AliasFor(mref) => {
+                self.out.push_str("(alias ");
+                self.mref(*mref);
+                self.out.push(')');
+            }
+            MethodImpl::Missing => self.out.push_str("(missing)"),
+        }
+        self.out.push(')');
+    }
+    pub(super) fn class(&mut self, class: ClassDefIdx) {
+        let def = &self.asm[class];
+        write!(self.out, "(class {} ", access_name(*def.access())).unwrap();
+        if def.is_valuetype() {
+            self.out.push_str("valuetype ");
+        }
+        self.string(&self.asm[def.name()]);
+        if let Some(extends) = def.extends() {
+            self.newline(1);
+            self.out.push_str("(extends ");
+            self.cref(extends);
+            self.out.push(')');
+        }
+        if !def.implements().is_empty() {
+            self.newline(1);
+            self.out.push_str("(implements");
+            for interface in def.implements() {
+                self.out.push(' ');
+                self.cref(*interface);
+            }
+            self.out.push(')');
+        }
+        if let Some(size) = def.explict_size() {
+            self.newline(1);
+            write!(self.out, "(size {size})").unwrap();
+        }
+        for (tpe, name, offset) in def.fields() {
+            self.newline(1);
+            self.out.push_str("(field ");
+            self.string(&self.asm[*name]);
+            self.out.push(' ');
+            self.tpe(*tpe);
+            if let Some(offset) = offset {
+                write!(self.out, " {offset}").unwrap();
+            }
+            self.out.push(')');
+        }
+        for (tpe, name, thread_local) in def.static_fields() {
+            self.newline(1);
+            self.out.push_str("(static ");
+            self.string(&self.asm[*name]);
+            self.out.push(' ');
+            self.tpe(*tpe);
+            if *thread_local {
+                self.out.push_str(" thread_local");
+            }
+            self.out.push(')');
+        }
+        for method in def.methods() {
+            self.newline(1);
+            self.method(*method, 1);
+        }
+        self.out.push_str(")\n");
+    }
+}
+fn access_name(access: Access) -> &'static str {
+    match access {
+        Access::Extern => "extern",
+        Access::Public => "public",
+        Access::Private => "private",
+    }
+}
+/// An item interned in one of the tables of an assembly.
+#[derive(Clone, Copy)]
+enum Interned {
+    String(StringIdx),
+    Type(TypeIdx),
+    ClassRef(ClassRefIdx),
+    Sig(SigIdx),
+    MethodRef(MethodRefIdx),
+    Field(FieldIdx),
+    StaticField(StaticFieldIdx),
+    Node(NodeIdx),
+    Root(RootIdx),
+}
+impl Interned {
+    /// The item at position `idx` of the table `table`, using the order of [`TABLES`].
+    fn new(table: usize, idx: usize) -> Self {
+        let key = std::num::NonZeroU32::new(u32::try_from(idx + 1).unwrap()).unwrap();
+        match table {
+            0 => Self::String(IntoBiMapIndex::from_index(key)),
+            1 => Self::Type(IntoBiMapIndex::from_index(key)),
+            2 => Self::ClassRef(IntoBiMapIndex::from_index(key)),
+            3 => Self::Sig(IntoBiMapIndex::from_index(key)),
+            4 => Self::MethodRef(IntoBiMapIndex::from_index(key)),
+            5 => Self::Field(IntoBiMapIndex::from_index(key)),
+            6 => Self::StaticField(IntoBiMapIndex::from_index(key)),
+            7 => Self::Node(IntoBiMapIndex::from_index(key)),
+            _ => Self::Root(IntoBiMapIndex::from_index(key)),
+        }
+    }
+    /// Returns the table of this item, and its position in that table.
+    fn position(self) -> (usize, usize) {
+        let (table, key) = match self {
+            Self::String(key) => (0, key.as_bimap_index()),
+            Self::Type(key) => (1, key.as_bimap_index()),
+            Self::ClassRef(key) => (2, key.as_bimap_index()),
+            Self::Sig(key) => (3, key.as_bimap_index()),
+            Self::MethodRef(key) => (4, key.as_bimap_index()),
+            Self::Field(key) => (5, key.as_bimap_index()),
+            Self::StaticField(key) => (6, key.as_bimap_index()),
+            Self::Node(key) => (7, key.as_bimap_index()),
+            Self::Root(key) => (8, key.as_bimap_index()),
+        };
+        (table, key.get() as usize - 1)
+    }
+    /// Returns the items which get interned while parsing this item.
+    fn deps(self, asm: &Assembly) -> Vec<Self> {
+        match self {
+            Self::String(_) => vec![],
+            Self::Type(tpe) => type_deps(asm[tpe]),
+            Self::ClassRef(cref) => {
+                let cref = &asm[cref];
+                let mut deps = vec![Self::String(cref.name())];
+                deps.extend(cref.asm().map(Self::String));
+                deps.extend(cref.generics().iter().flat_map(|tpe| type_deps(*tpe)));
+                deps
+            }
+            Self::Sig(sig) => asm[sig]
+                .inputs()
+                .iter()
+                .chain(std::iter::once(asm[sig].output()))
+                .flat_map(|tpe| type_deps(*tpe))
+                .collect(),
+            Self::MethodRef(mref) => {
+                let mref = &asm[mref];
+                let mut deps = vec![
+                    Self::ClassRef(mref.class()),
+                    Self::String(mref.name()),
+                    Self::Sig(mref.sig()),
+                ];
+                deps.extend(mref.generics().iter().flat_map(|tpe| type_deps(*tpe)));
+                deps
+            }
+            Self::Field(field) => {
+                let field = asm[field];
+                let mut deps = vec![Self::ClassRef(field.owner()), Self::String(field.name())];
+                deps.extend(type_deps(field.tpe()));
+                deps
+            }
+            Self::StaticField(sfld) => {
+                let sfld = asm[sfld];
+                let mut deps = vec![Self::ClassRef(sfld.owner()), Self::String(sfld.name())];
+                deps.extend(type_deps(sfld.tpe()));
+                deps
+            }
+            Self::Node(node) => {
+                let node = &asm[node];
+                let mut deps: Vec<_> = node.child_nodes().into_iter().map(Self::Node).collect();
+                match node {
+                    CILNode::Const(cst) => match cst.as_ref() {
+                        Const::PlatformString(string) => deps.push(Self::String(*string)),
+                        Const::Null(cref) => deps.push(Self::ClassRef(*cref)),
+                        _ => (),
+                    },
+                    CILNode::Call(info) => deps.push(Self::MethodRef(info.0)),
+                    CILNode::CallI(info) => deps.push(Self::Sig(info.1)),
+                    CILNode::LdFtn(mref) => deps.push(Self::MethodRef(*mref)),
+                    CILNode::PtrCast(_, res) => deps.extend(type_deps(res.as_type())),
+                    CILNode::LdFieldAdress { field, .. } | CILNode::LdField { field, .. } => {
+                        deps.push(Self::Field(*field));
+                    }
+                    CILNode::LdStaticField(sfld) | CILNode::LdStaticFieldAdress(sfld) => {
+                        deps.push(Self::StaticField(*sfld));
+                    }
+                    CILNode::LdInd { tpe, .. }
+                    | CILNode::SizeOf(tpe)
+                    | CILNode::IsInst(_, tpe)
+                    | CILNode::CheckedCast(_, tpe)
+                    | CILNode::LdTypeToken(tpe)
+                    | CILNode::LocAllocAlgined { tpe, .. }
+                    | CILNode::UnboxAny { tpe, .. } => deps.push(Self::Type(*tpe)),
+                    _ => (),
+                }
+                deps
+            }
+            Self::Root(root) => {
+                let root = &asm[root];
+                let mut deps: Vec<_> = root.nodes().iter().map(|node| Self::Node(**node)).collect();
+                match root {
+                    CILRoot::SourceFileInfo { file: string, .. } | CILRoot::Unreachable(string) => {
+                        deps.push(Self::String(*string));
+                    }
+                    CILRoot::SetField(info) => deps.push(Self::Field(info.0)),
+                    CILRoot::Call(info) | CILRoot::TailCall(info) => {
+                        deps.push(Self::MethodRef(info.0));
+                    }
+                    CILRoot::CallI(info) | CILRoot::TailCallI(info) => deps.push(Self::Sig(info.1)),
+                    CILRoot::StInd(info) => deps.extend(type_deps(info.2)),
+                    CILRoot::SetStaticField { field, .. } => deps.push(Self::StaticField(*field)),
+                    CILRoot::CpObj { tpe, .. } => deps.push(Self::Type(*tpe)),
+                    _ => (),
+                }
+                deps
+            }
+        }
+    }
+}
+/// Returns the items referenced by a type.
+fn type_deps(tpe: Type) -> Vec<Interned> {
+    match tpe {
+        Type::Ptr(inner) | Type::Ref(inner) | Type::PlatformArray { elem: inner, .. } => {
+            vec![Interned::Type(inner)]
+        }
+        Type::ClassRef(cref) => vec![Interned::ClassRef(cref)],
+        Type::FnPtr(sig) => vec![Interned::Sig(sig)],
+        _ => vec![],
+    }
+}
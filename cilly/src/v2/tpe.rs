@@ -216,6 +216,41 @@ impl Type {
             _ => None,
         }
     }
+    /// Replaces the generic parameters of a class (`!N`) with `generics[N]`. Used to get the types a method of a generic class instance
+    /// works with, from its generic signature.
+    pub fn subst_type_generics(self, generics: &[Type], asm: &mut Assembly) -> Type {
+        if generics.is_empty() {
+            return self;
+        }
+        match self {
+            Type::PlatformGeneric(idx, GenericKind::TypeGeneric) => {
+                generics.get(idx as usize).copied().unwrap_or(self)
+            }
+            Type::Ptr(inner) => {
+                let inner = asm[inner].subst_type_generics(generics, asm);
+                asm.nptr(inner)
+            }
+            Type::Ref(inner) => {
+                let inner = asm[inner].subst_type_generics(generics, asm);
+                asm.nref(inner)
+            }
+            Type::ClassRef(cref) => {
+                let cref = asm[cref].clone();
+                let class_generics: Box<[_]> = cref
+                    .generics()
+                    .iter()
+                    .map(|generic| generic.subst_type_generics(generics, asm))
+                    .collect();
+                Type::ClassRef(asm.alloc_class_ref(super::ClassRef::new(
+                    cref.name(),
+                    cref.asm(),
+                    cref.is_valuetype(),
+                    class_generics,
+                )))
+            }
+            _ => self,
+        }
+    }
     pub fn is_assignable_to(&self, to: Type, asm: &Assembly) -> bool {
         if *self == to {
             return true;
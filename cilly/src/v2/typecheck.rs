@@ -91,6 +91,17 @@ pub enum TypeCheckError {
         expected: super::FnSig,
         got: super::FnSig,
     },
+    LocAllocSizeWrong {
+        tpe: Type,
+    },
+    SwitchValueWrongType {
+        got: Type,
+        expected: Int,
+    },
+    TailCallWrongReturnType {
+        got: Type,
+        expected: Type,
+    },
 }
 pub fn display_typecheck_err(root: CILRoot, asm: &mut Assembly, sig: SigIdx, locals: &[LocalDef]) {
     let mut set = FxHashSet::default();
@@ -219,7 +230,7 @@ impl BinOp {
                 (Type::Int(lhs), Type::Int(rhs)) if rhs == lhs && rhs.is_signed() => {
                     Ok(Type::Int(lhs))
                 }
-                (Type::Float(lhs), Type::Float(rhs)) if rhs == lhs => Ok(Type::Bool),
+                (Type::Float(lhs), Type::Float(rhs)) if rhs == lhs => Ok(Type::Float(lhs)),
                 _ => Err(TypeCheckError::WrongBinopArgs {
                     lhs,
                     rhs,
@@ -230,7 +241,7 @@ impl BinOp {
                 (Type::Int(lhs), Type::Int(rhs)) if rhs == lhs && !rhs.is_signed() => {
                     Ok(Type::Int(lhs))
                 }
-                (Type::Float(lhs), Type::Float(rhs)) if rhs == lhs => Ok(Type::Bool),
+                (Type::Float(lhs), Type::Float(rhs)) if rhs == lhs => Ok(Type::Float(lhs)),
                 _ => Err(TypeCheckError::WrongBinopArgs {
                     lhs,
                     rhs,
@@ -414,7 +425,14 @@ impl CILNode {
             CILNode::Call(call_info) => {
                 let (mref, args) = call_info.as_ref();
                 let mref = asm.get_mref(*mref).clone();
-                let inputs: Box<[_]> = mref.stack_inputs(asm).into();
+                // Methods of generic class instances refer to the type arguments of the class through generic parameters.
+                let generics = asm[mref.class()].generics().to_vec();
+                let inputs: Box<[_]> = mref
+                    .stack_inputs(asm)
+                    .to_vec()
+                    .into_iter()
+                    .map(|input| input.subst_type_generics(&generics, asm))
+                    .collect();
                 if args.len() != inputs.len() {
                     return Err(TypeCheckError::CallArgcWrong {
                         expected: inputs.len(),
@@ -434,7 +452,7 @@ impl CILNode {
                         });
                     }
                 }
-                Ok(mref.output(asm))
+                Ok(mref.output(asm).subst_type_generics(&generics, asm))
             }
             CILNode::CallI(info) => {
                 let (fn_ptr, called_sig, args) = info.as_ref();
@@ -624,7 +642,16 @@ impl CILNode {
                 Ok(*asm.get_type(*cast_res))
             }
 
-            CILNode::LocAlloc { size } => todo!(),
+            CILNode::LocAlloc { size } => {
+                let size = asm.get_node(*size).clone();
+                let size = size.typecheck(sig, locals, asm)?;
+                match size {
+                    Type::Int(Int::USize | Int::ISize | Int::U32 | Int::I32) => {
+                        Ok(asm.nptr(Type::Int(Int::U8)))
+                    }
+                    _ => Err(TypeCheckError::LocAllocSizeWrong { tpe: size }),
+                }
+            }
             CILNode::LdStaticField(sfld) => {
                 let sfld = *asm.get_static_field(*sfld);
                 Ok(sfld.tpe())
@@ -697,6 +724,27 @@ impl CILRoot {
         for node in self.nodes() {
             asm.get_node(*node).clone().typecheck(sig, locals, asm)?;
         }
+        if let CILRoot::Switch(info) = self {
+            let (value, expected, _) = info.as_ref();
+            let got = asm.get_node(*value).clone().typecheck(sig, locals, asm)?;
+            if got != Type::Int(*expected) || expected.size().is_some_and(|size| size > 8) {
+                return Err(TypeCheckError::SwitchValueWrongType {
+                    got,
+                    expected: *expected,
+                });
+            }
+        }
+        let callee_output = match self {
+            CILRoot::TailCall(info) => Some(*asm[asm[info.0].sig()].output()),
+            CILRoot::TailCallI(info) => Some(*asm[info.1].output()),
+            _ => None,
+        };
+        if let Some(got) = callee_output {
+            let expected = *asm[sig].output();
+            if got != expected {
+                return Err(TypeCheckError::TailCallWrongReturnType { got, expected });
+            }
+        }
         Ok(())
     }
 }
@@ -711,3 +759,47 @@ fn test() {
     let mut set = FxHashSet::default();
     panic!("{}", display_node(sum, &mut asm, sig, &[], &mut set));
 }
+#[test]
+fn generic_class_call() {
+    use super::{cilnode::MethodKind, tpe::GenericKind, MethodRef};
+    let mut asm = Assembly::default();
+    let name = asm.alloc_string("System.Threading.Tasks.Task");
+    let runtime = Some(asm.alloc_string("System.Runtime"));
+    let task_int = asm.alloc_class_ref(ClassRef::new(
+        name,
+        runtime,
+        false,
+        [Type::Int(Int::I32)].into(),
+    ));
+    // `!0 Task<int32>::get_Result()` returns an `int32`.
+    let get_result_sig = asm.sig(
+        [Type::ClassRef(task_int)],
+        Type::PlatformGeneric(0, GenericKind::TypeGeneric),
+    );
+    let get_result = asm.alloc_string("get_Result");
+    let get_result = asm.alloc_methodref(MethodRef::new(
+        task_int,
+        get_result,
+        get_result_sig,
+        MethodKind::Instance,
+        [].into(),
+    ));
+    let sig = asm.sig([Type::ClassRef(task_int)], Type::Int(Int::I32));
+    let this = asm.alloc_node(CILNode::LdArg(0));
+    let call = CILNode::Call(Box::new((get_result, [this].into())));
+    assert_eq!(
+        call.typecheck(sig, &[], &mut asm).unwrap(),
+        Type::Int(Int::I32)
+    );
+    // Generic parameters nested in other classes are replaced too.
+    let nested = asm.alloc_class_ref(ClassRef::new(
+        name,
+        runtime,
+        false,
+        [Type::PlatformGeneric(0, GenericKind::TypeGeneric)].into(),
+    ));
+    assert_eq!(
+        Type::ClassRef(nested).subst_type_generics(&[Type::Int(Int::I32)], &mut asm),
+        Type::ClassRef(task_int)
+    );
+}
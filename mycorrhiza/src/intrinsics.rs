@@ -115,6 +115,65 @@ impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str>
     }
 }
 
+/// A reference to an instance of a generic managed class with a single type argument, like `Task<T>`.
+#[repr(C)]
+pub struct RustcCLRInteropManagedGenericClass<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    T,
+> {
+    size_hint: usize,
+    pd: core::marker::PhantomData<T>,
+}
+/// An instance of a generic managed valuetype with a single type argument, like `Span<T>`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RustcCLRInteropManagedGenericStruct<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    T,
+    const SIZE: usize,
+> {
+    size_hint: [u8; SIZE],
+    pd: core::marker::PhantomData<T>,
+}
+// Deriving those would require `T: Copy`, but a reference is always copyable.
+impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, T> Clone
+    for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, T>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, T> Copy
+    for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, T>
+{
+}
+/// Stands in for the `INDEX`th generic parameter of a generic class, in the signatures of its methods.
+/// It is never constructed.
+#[repr(C)]
+pub struct RustcCLRInteropManagedGenericArg<const INDEX: u32> {
+    size_hint: usize,
+}
+impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, T>
+    RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, T>
+{
+    #[inline(always)]
+    pub fn ctor0() -> Self {
+        rustc_clr_interop_generic_ctor0_::<Self>()
+    }
+    /// Calls the instance method `METHOD`. `Sig` is the signature of that method, as declared by the generic class:
+    /// a function pointer type, using [`RustcCLRInteropManagedGenericArg`] in place of the class's generic parameters.
+    #[inline(always)]
+    pub fn instance0<const METHOD: &'static str, Sig, Ret>(self) -> Ret {
+        rustc_clr_interop_generic_call1_::<Self, METHOD, false, Sig, Ret, Self>(self)
+    }
+    /// Like [`Self::instance0`], but for a method taking one argument.
+    #[inline(always)]
+    pub fn instance1<const METHOD: &'static str, Sig, Arg1, Ret>(self, arg1: Arg1) -> Ret {
+        rustc_clr_interop_generic_call2_::<Self, METHOD, false, Sig, Ret, Self, Arg1>(self, arg1)
+    }
+}
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct RustcCLRInteropManagedChar {
@@ -264,6 +323,42 @@ pub fn rustc_clr_interop_managed_ctor0_<
 pub fn rustc_clr_interop_managed_ld_null<T>() -> T {
     core::intrinsics::abort();
 }
+// Generic classes
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_generic_ctor0_<Class>() -> Class {
+    core::intrinsics::abort();
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_generic_call1_<
+    Class,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Sig,
+    Ret,
+    Arg1,
+>(
+    arg1: Arg1,
+) -> Ret {
+    core::intrinsics::abort();
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_clr_interop_generic_call2_<
+    Class,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Sig,
+    Ret,
+    Arg1,
+    Arg2,
+>(
+    arg1: Arg1,
+    arg2: Arg2,
+) -> Ret {
+    core::intrinsics::abort();
+}
 #[allow(unused_variables)]
 #[inline(never)]
 pub fn rustc_clr_interop_managed_checked_cast<DST, SRC>(src: SRC) -> DST {
@@ -377,18 +472,39 @@ unsafe impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, const
     ManagedSafe for RustcCLRInteropManagedStruct<ASSEMBLY, CLASS_PATH, SIZE>
 {
 }
+unsafe impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, T: ManagedSafe>
+    ManagedSafe for RustcCLRInteropManagedGenericClass<ASSEMBLY, CLASS_PATH, T>
+{
+}
 impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str, const SIZE: usize>
     RustcCLRInteropManagedStruct<ASSEMBLY, CLASS_PATH, SIZE>
 {
+    #[inline(always)]
+    pub fn ctor1<Arg1>(arg1: Arg1) -> Self {
+        rustc_clr_interop_managed_ctor1_::<ASSEMBLY, CLASS_PATH, true, Arg1>(arg1)
+    }
     #[inline(always)]
     pub fn instance0<const METHOD: &'static str, Ret>(self) -> Ret {
-        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, false, METHOD, false, Ret, &Self>(
+        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, true, METHOD, false, Ret, &Self>(
             &self,
         )
     }
     #[inline(always)]
+    pub fn instance1<const METHOD: &'static str, Arg1, Ret>(self, arg1: Arg1) -> Ret {
+        rustc_clr_interop_managed_call2_::<
+            ASSEMBLY,
+            CLASS_PATH,
+            true,
+            METHOD,
+            false,
+            Ret,
+            &Self,
+            Arg1,
+        >(&self, arg1)
+    }
+    #[inline(always)]
     pub fn static1<const METHOD: &'static str, Arg1, Ret>(arg1: Arg1) -> Ret {
-        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, false, METHOD, true, Ret, Arg1>(
+        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, true, METHOD, true, Ret, Arg1>(
             arg1,
         )
     }
@@ -4,7 +4,7 @@
 //! implement an equivalent APIs in standard Rust.
 
 #![allow(internal_features, incomplete_features)]
-#![feature(core_intrinsics, unsized_const_params, inherent_associated_types)]
+#![feature(core_intrinsics, unsized_const_params, inherent_associated_types, macro_attr)]
 #[allow(non_snake_case, unused_imports)]
 pub mod bindings;
 pub use bindings::*;
@@ -14,6 +14,10 @@ pub mod intrinsics;
 use class::*;
 /// Wrappers around types from the `System` namespace
 pub mod system;
+/// Conversions between Rust futures and .NET tasks.
+pub mod task;
+/// Definitions of .NET classes in Rust.
+pub mod typedef;
 /// C# `char` type
 pub type DotNetChar = crate::intrinsics::RustcCLRInteropManagedChar;
 
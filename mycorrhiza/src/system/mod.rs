@@ -4,6 +4,18 @@ pub mod console;
 pub mod diagnostics;
 pub mod runtime;
 pub mod text;
+pub mod threading;
+pub type Object = crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Object">;
+/// A .NET delegate taking no arguments and returning nothing.
+pub type Action = crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Action">;
+impl Action {
+    /// Creates a delegate calling the Rust function `f`.
+    #[inline(always)]
+    pub fn from_fn(f: fn()) -> Self {
+        // Delegates are constructed from a target object(null for static methods) and a method pointer.
+        Self::ctor2::<Object, isize>(Object::null(), f as isize)
+    }
+}
 pub type MString =
     crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.String">;
 
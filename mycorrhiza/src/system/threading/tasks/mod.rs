@@ -0,0 +1,126 @@
+use crate::{
+    intrinsics::{
+        rustc_clr_interop_managed_checked_cast, RustcCLRInteropManagedClass,
+        RustcCLRInteropManagedGenericArg, RustcCLRInteropManagedGenericClass,
+        RustcCLRInteropManagedStruct,
+    },
+    system::Action,
+    ManagedSafe,
+};
+pub type Task = RustcCLRInteropManagedClass<"System.Runtime", "System.Threading.Tasks.Task">;
+impl Task {
+    /// Returns a task that has already completed.
+    #[inline(always)]
+    pub fn completed() -> Self {
+        Self::static0::<"get_CompletedTask", Self>()
+    }
+    #[inline(always)]
+    pub fn is_completed(self) -> bool {
+        self.instance0::<"get_IsCompleted", bool>()
+    }
+    #[inline(always)]
+    pub fn is_faulted(self) -> bool {
+        self.instance0::<"get_IsFaulted", bool>()
+    }
+    #[inline(always)]
+    pub fn is_canceled(self) -> bool {
+        self.instance0::<"get_IsCanceled", bool>()
+    }
+    /// Blocks until this task completes. Rethrows the exception of a faulted or canceled task.
+    #[inline(always)]
+    pub fn wait(self) {
+        self.instance0::<"Wait", ()>()
+    }
+    #[inline(always)]
+    pub fn get_awaiter(self) -> TaskAwaiter {
+        self.instance0::<"GetAwaiter", TaskAwaiter>()
+    }
+}
+pub type TaskAwaiter = RustcCLRInteropManagedStruct<
+    "System.Runtime",
+    "System.Runtime.CompilerServices.TaskAwaiter",
+    { size_of::<usize>() },
+>;
+impl TaskAwaiter {
+    /// Schedules `continuation` to run once the awaited task completes.
+    #[inline(always)]
+    pub fn on_completed(self, continuation: Action) {
+        self.instance1::<"OnCompleted", Action, ()>(continuation)
+    }
+}
+/// The non-generic `TaskCompletionSource`, used to create a [`Task`] completed from Rust code.
+pub type TaskCompletionSource =
+    RustcCLRInteropManagedClass<"System.Runtime", "System.Threading.Tasks.TaskCompletionSource">;
+impl TaskCompletionSource {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::ctor0()
+    }
+    #[inline(always)]
+    pub fn task(self) -> Task {
+        self.instance0::<"get_Task", Task>()
+    }
+    #[inline(always)]
+    pub fn set_result(self) {
+        self.instance0::<"SetResult", ()>()
+    }
+}
+/// `Task<T>`: a task producing a value of type `T`.
+pub type GenericTask<T> =
+    RustcCLRInteropManagedGenericClass<"System.Runtime", "System.Threading.Tasks.Task", T>;
+impl<T: ManagedSafe> GenericTask<T> {
+    /// Returns the value this task produced. Blocks until the task completes, and rethrows its exception if it faulted.
+    #[inline(always)]
+    pub fn result(self) -> T {
+        self.instance0::<"get_Result", fn(Self) -> RustcCLRInteropManagedGenericArg<0>, T>()
+    }
+    /// Returns this task as a non-generic [`Task`], its base class.
+    #[inline(always)]
+    pub fn as_task(self) -> Task {
+        rustc_clr_interop_managed_checked_cast(self)
+    }
+}
+/// `TaskCompletionSource<T>`, used to create a [`GenericTask`] completed from Rust code.
+pub type GenericTaskCompletionSource<T> = RustcCLRInteropManagedGenericClass<
+    "System.Runtime",
+    "System.Threading.Tasks.TaskCompletionSource",
+    T,
+>;
+impl<T: ManagedSafe> GenericTaskCompletionSource<T> {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::ctor0()
+    }
+    #[inline(always)]
+    pub fn task(self) -> GenericTask<T> {
+        self.instance0::<"get_Task", fn(
+            Self,
+        ) -> RustcCLRInteropManagedGenericClass<
+            "System.Runtime",
+            "System.Threading.Tasks.Task",
+            RustcCLRInteropManagedGenericArg<0>,
+        >, GenericTask<T>>()
+    }
+    #[inline(always)]
+    pub fn set_result(self, value: T) {
+        self.instance1::<"SetResult", fn(Self, RustcCLRInteropManagedGenericArg<0>), T, ()>(value)
+    }
+}
+/// The non-generic `ValueTask`.
+pub type ValueTask = RustcCLRInteropManagedStruct<
+    "System.Runtime",
+    "System.Threading.Tasks.ValueTask",
+    { 2 * size_of::<usize>() },
+>;
+impl ValueTask {
+    /// Wraps `task` in a value task.
+    #[inline(always)]
+    pub fn from_task(task: Task) -> Self {
+        Self::ctor1(task)
+    }
+    /// Returns a [`Task`] completing together with this value task. A value task may only be consumed once.
+    #[inline(always)]
+    pub fn as_task(self) -> Task {
+        self.instance0::<"AsTask", Task>()
+    }
+}
@@ -0,0 +1,304 @@
+//! Bridges between Rust futures and .NET tasks.
+//!
+//! A Rust [`Future`] can be exposed to managed code as a [`Task`] with [`spawn`], or as a [`GenericTask`] producing its output with
+//! [`spawn_with_result`]. A .NET [`Task`], [`GenericTask`] or [`ValueTask`] can be awaited from Rust code by wrapping it in a [`TaskFuture`] or
+//! [`GenericTaskFuture`]. [`block_on`] runs a future to completion on the current thread.
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use crate::{
+    class::Class,
+    intrinsics::rustc_clr_interop_managed_checked_cast,
+    system::{
+        threading::tasks::{
+            GenericTask, GenericTaskCompletionSource, Task, TaskCompletionSource, ValueTask,
+        },
+        Action,
+    },
+    ManagedSafe,
+};
+/// A GC handle keeping a task alive while Rust code holds onto it.
+type TaskHandle = Class<"System.Runtime", "System.Threading.Tasks.Task">;
+type TaskCompletionSourceHandle =
+    Class<"System.Runtime", "System.Threading.Tasks.TaskCompletionSource">;
+/// A GC handle to an object whose type can't be named by [`Class`], like an instance of a generic class.
+type ObjectHandle = Class<"System.Runtime", "System.Object">;
+type BoxedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs `future` to completion, blocking the current thread while it is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let parker = Arc::new(Parker::default());
+    let waker = Waker::from(parker.clone());
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+            return res;
+        }
+        parker.park();
+    }
+}
+/// Wakes up a thread blocked in [`block_on`].
+#[derive(Default)]
+struct Parker {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+impl Parker {
+    fn park(&self) {
+        let mut woken = self.woken.lock().unwrap();
+        while !*woken {
+            woken = self.condvar.wait(woken).unwrap();
+        }
+        *woken = false;
+    }
+}
+impl Wake for Parker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        *self.woken.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+}
+/// A future spawned by [`spawn`]. It has no dedicated thread: it is polled by whoever wakes it up.
+struct Spawned {
+    future: Mutex<Option<BoxedFuture>>,
+    notified: AtomicBool,
+}
+impl Spawned {
+    fn run(self: &Arc<Self>) {
+        loop {
+            // If the future is already being polled, the thread polling it will see `notified` and poll it again.
+            let Ok(mut slot) = self.future.try_lock() else {
+                return;
+            };
+            self.notified.store(false, Ordering::SeqCst);
+            if let Some(future) = slot.as_mut() {
+                let waker = Waker::from(self.clone());
+                if future
+                    .as_mut()
+                    .poll(&mut Context::from_waker(&waker))
+                    .is_ready()
+                {
+                    *slot = None;
+                }
+            }
+            drop(slot);
+            if !self.notified.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+}
+impl Wake for Spawned {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::SeqCst);
+        self.run();
+    }
+}
+/// Starts running `future` in the background, and returns a [`Task`] completing once it finishes.
+///
+/// `future` runs on the current thread, until it first returns [`Poll::Pending`]. After that, it is polled on whatever thread wakes it up.
+pub fn spawn<F>(future: F) -> Task
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let source = TaskCompletionSourceHandle::from_naked_ref(TaskCompletionSource::new());
+    let task = unsafe { source.get_naked_ref() }.task();
+    run_spawned(Box::pin(async move {
+        future.await;
+        unsafe { source.get_naked_ref() }.set_result();
+    }));
+    task
+}
+/// Like [`spawn`], but the returned task also carries the value `future` finished with, which managed code can read using `Task<T>.Result`.
+pub fn spawn_with_result<F>(future: F) -> GenericTask<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: ManagedSafe + Send + 'static,
+{
+    let source = GenericTaskCompletionSource::<F::Output>::new();
+    let task = source.task();
+    let source = ObjectHandle::from_naked_ref(rustc_clr_interop_managed_checked_cast(source));
+    run_spawned(Box::pin(async move {
+        let res = future.await;
+        let source: GenericTaskCompletionSource<F::Output> =
+            rustc_clr_interop_managed_checked_cast(unsafe { source.get_naked_ref() });
+        source.set_result(res);
+    }));
+    task
+}
+fn run_spawned(future: BoxedFuture) {
+    let spawned = Arc::new(Spawned {
+        future: Mutex::new(Some(future)),
+        notified: AtomicBool::new(false),
+    });
+    spawned.run();
+}
+/// Tasks awaited by a [`TaskFuture`], and the wakers to call once they complete.
+static PENDING: Mutex<Vec<(TaskHandle, Registration)>> = Mutex::new(Vec::new());
+/// The waker of a [`TaskFuture`], shared with [`PENDING`]. The future updates it when polled with a new waker, without registering again.
+type Registration = Arc<Mutex<Waker>>;
+/// Called by .NET once an awaited task completes. Delegates created from Rust functions can't carry any state, so this checks all pending tasks.
+fn wake_completed() {
+    let completed: Vec<_> = {
+        let mut pending = PENDING.lock().unwrap();
+        let (completed, still_pending) = std::mem::take(&mut *pending)
+            .into_iter()
+            .partition(|(task, _)| unsafe { task.get_naked_ref() }.is_completed());
+        *pending = still_pending;
+        completed
+    };
+    // Wakers may poll futures right away, so they must be called without holding the lock.
+    for (_, registration) in completed {
+        let waker = registration.lock().unwrap().clone();
+        waker.wake();
+    }
+}
+/// Stores `waker` in `registration`, and returns the shared registration. Also returns `true` if this is the first waker, and so the awaited
+/// task still needs a continuation.
+fn register_waker(registration: &mut Option<Registration>, waker: &Waker) -> (bool, Registration) {
+    match registration {
+        Some(registration) => {
+            let mut stored = registration.lock().unwrap();
+            if !stored.will_wake(waker) {
+                stored.clone_from(waker);
+            }
+            (false, registration.clone())
+        }
+        None => {
+            let new = Arc::new(Mutex::new(waker.clone()));
+            *registration = Some(new.clone());
+            (true, new)
+        }
+    }
+}
+/// A .NET [`Task`] awaitable from Rust code.
+pub struct TaskFuture {
+    task: TaskHandle,
+    registration: Option<Registration>,
+}
+impl TaskFuture {
+    pub fn new(task: Task) -> Self {
+        Self {
+            task: TaskHandle::from_naked_ref(task),
+            registration: None,
+        }
+    }
+}
+impl From<Task> for TaskFuture {
+    fn from(task: Task) -> Self {
+        Self::new(task)
+    }
+}
+impl From<ValueTask> for TaskFuture {
+    fn from(task: ValueTask) -> Self {
+        Self::new(task.as_task())
+    }
+}
+impl Future for TaskFuture {
+    type Output = ();
+    /// Completes once the task completes. If the task faulted or was canceled, its exception is rethrown.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let task = unsafe { self.task.get_naked_ref() };
+        if task.is_completed() {
+            if task.is_faulted() || task.is_canceled() {
+                task.wait();
+            }
+            return Poll::Ready(());
+        }
+        let (first, registration) = register_waker(&mut self.registration, cx.waker());
+        if first {
+            PENDING
+                .lock()
+                .unwrap()
+                .push((self.task.clone(), registration));
+            // If the task completed in the meantime, the continuation runs right away, so no wakeup is lost.
+            task.get_awaiter()
+                .on_completed(Action::from_fn(wake_completed));
+        }
+        Poll::Pending
+    }
+}
+/// A .NET [`GenericTask`] awaitable from Rust code, producing the result of the task.
+pub struct GenericTaskFuture<T> {
+    inner: TaskFuture,
+    result: PhantomData<fn() -> T>,
+}
+impl<T: ManagedSafe> GenericTaskFuture<T> {
+    pub fn new(task: GenericTask<T>) -> Self {
+        Self {
+            inner: TaskFuture::new(task.as_task()),
+            result: PhantomData,
+        }
+    }
+}
+impl<T: ManagedSafe> From<GenericTask<T>> for GenericTaskFuture<T> {
+    fn from(task: GenericTask<T>) -> Self {
+        Self::new(task)
+    }
+}
+impl<T: ManagedSafe> Future for GenericTaskFuture<T> {
+    type Output = T;
+    /// Completes with the result of the task once it completes. If the task faulted or was canceled, its exception is rethrown.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if Pin::new(&mut self.inner).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let task: GenericTask<T> =
+            rustc_clr_interop_managed_checked_cast(unsafe { self.inner.task.get_naked_ref() });
+        Poll::Ready(task.result())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    /// Counts how many times it was woken.
+    #[derive(Default)]
+    struct CountingWaker(std::sync::atomic::AtomicUsize);
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    #[test]
+    fn registers_once() {
+        let waker = Waker::from(Arc::new(CountingWaker::default()));
+        let mut registration = None;
+        let (first, shared) = register_waker(&mut registration, &waker);
+        assert!(first);
+        for _ in 0..16 {
+            let (first, again) = register_waker(&mut registration, &waker);
+            assert!(!first);
+            assert!(Arc::ptr_eq(&shared, &again));
+        }
+        // The future and `PENDING` share a single registration.
+        assert_eq!(Arc::strong_count(&shared), 2);
+    }
+    #[test]
+    fn updates_waker() {
+        let old = Arc::new(CountingWaker::default());
+        let new = Arc::new(CountingWaker::default());
+        let mut registration = None;
+        let (_, shared) = register_waker(&mut registration, &Waker::from(old.clone()));
+        let (first, _) = register_waker(&mut registration, &Waker::from(new.clone()));
+        assert!(!first);
+        // Waking the registration wakes the waker the future was last polled with.
+        shared.lock().unwrap().wake_by_ref();
+        assert_eq!(old.0.load(Ordering::SeqCst), 0);
+        assert_eq!(new.0.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,173 @@
+//! Definitions of .NET classes from Rust source.
+//!
+//! The [`dotnet_typedef`](crate::dotnet_typedef) attribute turns a struct into a `rustc_codegen_clr_comptime_entrypoint` function. This function is never called:
+//! `rustc_codegen_clr` interprets it at compile time, and emits a class with the base class, interfaces, fields, methods and constructors it describes.
+//! The struct itself is not kept, so its name is free to use for the class type.
+//! Fields of type `fn(..)` marked with `#[fnimpl(..)]` or `#[ctor(..)]` become methods and constructors, which forward all their arguments(including `this`)
+//! to the given Rust function. Methods are instance methods by default, and can be marked as `virtual` or `static`.
+//! ```ignore
+//! #![feature(macro_attr)]
+//! type Counter = mycorrhiza::intrinsics::RustcCLRInteropManagedClass<"", "Counter">;
+//! fn counter_ctor(this: Counter) {}
+//! fn counter_to_string(this: Counter) -> MString {
+//!     "Counter".into()
+//! }
+//! fn counter_dispose(this: Counter) {}
+//! #[dotnet_typedef(inherits [System::Runtime] System::Object, implements [System::Runtime] System::IDisposable)]
+//! struct Counter {
+//!     count: i32,
+//!     #[ctor(counter_ctor)]
+//!     new: fn(Counter),
+//!     #[fnimpl(virtual counter_to_string)]
+//!     ToString: fn(Counter) -> MString,
+//!     #[fnimpl(virtual counter_dispose)]
+//!     Dispose: fn(Counter),
+//! }
+//! ```
+//! Valuetypes are defined with `#[dotnet_typedef(valuetype)]`, and can't inherit from other types. Their methods get a copy of `this`,
+//! so they can't have constructors.
+use core::hint::black_box;
+/// A class definition, which is being built at compile time. Can't be constructed at runtime.
+#[allow(dead_code)]
+pub struct ClassDef {
+    prevent_construction: usize,
+}
+#[inline(never)]
+pub fn rustc_codegen_clr_new_typedef<
+    const NAME: &'static str,
+    const IS_VALUETYPE: bool,
+    const INHERITS_ASM: &'static str,
+    const INHERITS: &'static str,
+>() -> ClassDef {
+    black_box(());
+    core::intrinsics::abort()
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_codegen_clr_add_interface_def<
+    const INTERFACE_ASM: &'static str,
+    const INTERFACE: &'static str,
+>(
+    class: ClassDef,
+) -> ClassDef {
+    black_box(());
+    core::intrinsics::abort()
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_codegen_clr_add_field_def<T, const FNAME: &'static str>(class: ClassDef) -> ClassDef {
+    black_box(());
+    core::intrinsics::abort()
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_codegen_clr_add_method_def<
+    const VIS: &'static str,
+    const MODIFIERS: &'static str,
+    const FNAME: &'static str,
+    FnType,
+>(
+    class: ClassDef,
+    fn_type: FnType,
+) -> ClassDef {
+    black_box(());
+    core::intrinsics::abort()
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_codegen_clr_add_ctor_def<const VIS: &'static str, FnType>(
+    class: ClassDef,
+    fn_type: FnType,
+) -> ClassDef {
+    black_box(());
+    core::intrinsics::abort()
+}
+#[allow(unused_variables)]
+#[inline(never)]
+pub fn rustc_codegen_clr_finish_type(class: ClassDef) {
+    black_box(());
+    core::intrinsics::abort()
+}
+/// Defines a .NET class or struct. See the [`typedef`](crate::typedef) module for more info.
+#[macro_export]
+macro_rules! dotnet_typedef {
+    attr(inherits [$superasm:path] $superclass:path $(, implements $([$iasm:path] $iface:path),+)?) ($(#[$meta:meta])* $vis:vis struct $name:ident { $($inner:tt)* }) => {
+        $crate::dotnet_typedef!(@typedef $name, false, [$superasm] $superclass, [$($([$iasm] $iface),+)?], $($inner)*);
+    };
+    attr(valuetype $(, implements $([$iasm:path] $iface:path),+)?) ($(#[$meta:meta])* $vis:vis struct $name:ident { $($inner:tt)* }) => {
+        $crate::dotnet_typedef!(@typedef $name, true, [System::Runtime] System::ValueType, [$($([$iasm] $iface),+)?], $($inner)*);
+    };
+    (@typedef $name:ident, $is_valuetype:literal, [$superasm:path] $superclass:path, [$([$iasm:path] $iface:path),*], $($inner:tt)*) => {
+        const _: () = {
+            #[used]
+            static PREVENT_DEAD_CODE_REMOVAL: fn() = rustc_codegen_clr_comptime_entrypoint;
+            #[inline(never)]
+            fn rustc_codegen_clr_comptime_entrypoint() {
+                const NAME: &str = stringify!($name);
+                const SUPER_ASM: &str = stringify!($superasm);
+                const SUPER_CLASS: &str = stringify!($superclass);
+                #[allow(unused_mut)]
+                let mut class = $crate::typedef::rustc_codegen_clr_new_typedef::<
+                    NAME,
+                    $is_valuetype,
+                    SUPER_ASM,
+                    SUPER_CLASS,
+                >();
+                $(
+                    class = {
+                        const ASM: &str = stringify!($iasm);
+                        const INTERFACE: &str = stringify!($iface);
+                        $crate::typedef::rustc_codegen_clr_add_interface_def::<ASM, INTERFACE>(class)
+                    };
+                )*
+                $crate::typedef_members!(class, $($inner)*);
+                $crate::typedef::rustc_codegen_clr_finish_type(class);
+            }
+        };
+    };
+}
+/// Adds the fields, methods and constructors to a class defined by [`dotnet_typedef`](crate::dotnet_typedef).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! typedef_members {
+    ($typedef:ident $(,)?) => {};
+    ($typedef:ident, #[ctor($ctor:path)] $fname:ident : $fn_type:ty $(, $($tail:tt)*)?) => {
+        $typedef = {
+            #[used]
+            static KEEP_FN: $fn_type = $ctor;
+            $crate::typedef::rustc_codegen_clr_add_ctor_def::<"pub", _>($typedef, $ctor)
+        };
+        $crate::typedef_members!($typedef, $($($tail)*)?)
+    };
+    ($typedef:ident, #[fnimpl(virtual $fnimpl:path)] $fname:ident : $fn_type:ty $(, $($tail:tt)*)?) => {
+        $crate::typedef_members!(@method $typedef, "virtual", $fnimpl, $fname : $fn_type);
+        $crate::typedef_members!($typedef, $($($tail)*)?)
+    };
+    ($typedef:ident, #[fnimpl(static $fnimpl:path)] $fname:ident : $fn_type:ty $(, $($tail:tt)*)?) => {
+        $crate::typedef_members!(@method $typedef, "static", $fnimpl, $fname : $fn_type);
+        $crate::typedef_members!($typedef, $($($tail)*)?)
+    };
+    ($typedef:ident, #[fnimpl($fnimpl:path)] $fname:ident : $fn_type:ty $(, $($tail:tt)*)?) => {
+        $crate::typedef_members!(@method $typedef, "instance", $fnimpl, $fname : $fn_type);
+        $crate::typedef_members!($typedef, $($($tail)*)?)
+    };
+    (@method $typedef:ident, $modifiers:literal, $fnimpl:path, $fname:ident : $fn_type:ty) => {
+        $typedef = {
+            // Checks the signature of the implementation, and keeps it alive.
+            #[used]
+            static KEEP_FN: $fn_type = $fnimpl;
+            const FNAME: &str = stringify!($fname);
+            $crate::typedef::rustc_codegen_clr_add_method_def::<"pub", $modifiers, FNAME, _>(
+                $typedef,
+                $fnimpl,
+            )
+        };
+    };
+    ($typedef:ident, $field_name:ident : $field_type:ty $(, $($tail:tt)*)?) => {
+        $typedef = {
+            const FNAME: &str = stringify!($field_name);
+            $crate::typedef::rustc_codegen_clr_add_field_def::<$field_type, FNAME>($typedef)
+        };
+        $crate::typedef_members!($typedef, $($($tail)*)?)
+    };
+}
@@ -166,6 +166,48 @@ pub fn handle_aggregate<'tcx>(
                 Box::new(place_get(target_location, ctx)),
             )))
         }
+        AggregateKind::Coroutine(_def_id, _args) => {
+            let coroutine_ty = ctx
+                .monomorphize(target_location.ty(ctx.body(), ctx.tcx()))
+                .ty;
+            let coroutine_type = get_type(coroutine_ty, ctx);
+            let coroutine_dotnet = coroutine_type
+                .as_class_ref()
+                .expect("Invalid coroutine type!");
+            let coroutine_getter = super::place::place_adress(target_location, ctx);
+            let mut sub_trees = vec![];
+            // Store the upvars
+            for (index, value) in value_index.iter_enumerated() {
+                let field_ty = ctx.monomorphize(value.ty(ctx.body(), ctx.tcx()));
+                let field_type = get_type(field_ty, ctx);
+                if field_type == cilly::v2::Type::Void {
+                    continue;
+                }
+                sub_trees.push(CILRoot::SetField {
+                    addr: Box::new(coroutine_getter.clone()),
+                    value: Box::new(handle_operand(value, ctx)),
+                    desc: Box::new(FieldDescriptor::new(
+                        coroutine_dotnet,
+                        field_type,
+                        format!("f_{}", index.as_u32()).into(),
+                    )),
+                });
+            }
+            // A freshly created coroutine is always in the `UNRESUMED` state.
+            let layout = ctx.layout_of(coroutine_ty);
+            sub_trees.push(set_discr(
+                layout.layout,
+                rustc_target::abi::VariantIdx::from_u32(0),
+                coroutine_getter,
+                coroutine_dotnet,
+                coroutine_ty,
+                ctx,
+            ));
+            CILNode::SubTrees(Box::new((
+                sub_trees.into(),
+                Box::new(place_get(target_location, ctx)),
+            )))
+        }
         AggregateKind::RawPtr(pointee, mutability) => {
             let pointee = ctx.monomorphize(*pointee);
             let [data, meta] = &*value_index.raw else {
@@ -244,20 +286,37 @@ fn aggregate_adt<'tcx>(
     match adt.adt_kind() {
         AdtKind::Struct => {
             let obj_getter = crate::place::place_adress(target_location, ctx);
-
+            let is_simd = crate::r#type::simd_vector(adt_type, ctx).is_some();
             let mut sub_trees = Vec::new();
             for field in fields {
                 let field_def = adt
                     .all_fields()
                     .nth(field.0 as usize)
                     .expect("Could not find field!");
-                let field_type = field_def.ty(ctx.tcx(), subst);
-                let field_type = ctx.monomorphize(field_type);
-                let field_type = ctx.type_from_cache(field_type);
+                let field_ty = field_def.ty(ctx.tcx(), subst);
+                let field_ty = ctx.monomorphize(field_ty);
+                let field_type = ctx.type_from_cache(field_ty);
                 // Seting a void field is a no-op.
                 if field_type == Type::Void {
                     continue;
                 }
+                // .NET vectors have no fields, so their lanes are set through their address.
+                if is_simd {
+                    let addr = crate::place::simd_field_address(
+                        adt_type,
+                        field.0,
+                        field_ty,
+                        obj_getter.clone(),
+                        ctx,
+                    );
+                    sub_trees.push(crate::place::ptr_set_op(
+                        field_ty.into(),
+                        ctx,
+                        addr,
+                        field.1,
+                    ));
+                    continue;
+                }
                 let field_desc = crate::utilis::field_descrptor(adt_type, field.0, ctx);
 
                 sub_trees.push(CILRoot::SetField {
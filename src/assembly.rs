@@ -1,8 +1,8 @@
 pub use crate::fn_ctx::MethodCompileCtx;
 use crate::{
-    basic_block::handler_for_block,
+    basic_block::{handler_for_block, live_cleanup_blocks, protected_block},
     cil::span_source_info,
-    codegen_error::{CodegenError, MethodCodegenError},
+    codegen_error::{CodegenError, MethodCodegenError, StubbedFunctions},
     r#type::get_type,
     rustc_middle::dep_graph::DepContext,
     utilis::field_descrptor,
@@ -260,63 +260,31 @@ fn calculate_hash<T: std::hash::Hash>(t: &T) -> u64 {
     t.hash(&mut s);
     s.finish()
 }
-/// Turns a terminator into ops, if `ABORT_ON_ERROR` set to false, will handle and recover from errors.
+/// Turns a terminator into ops. Errors(including panics) are turned into a [`CodegenError`].
 pub fn terminator_to_ops<'tcx>(
     term: &Terminator<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> Result<Vec<CILTree>, CodegenError> {
-    let terminator = if *crate::config::ABORT_ON_ERROR {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         crate::terminator::handle_terminator(term, ctx)
-    } else {
-        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            crate::terminator::handle_terminator(term, ctx)
-        })) {
-            Ok(ok) => ok,
-            Err(payload) => {
-                let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion message {msg:?}!")}
-                } else {
-                    eprintln!("handle_terminator panicked with a non-string message when trying to compile {term:?} !");
-                    rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion failed with a no-string message!")
-                    }
-                };
-                vec![CILRoot::throw(&msg, ctx.asm_mut()).into()]
-            }
-        }
-    };
-
-    Ok(terminator)
+    }))
+    .map_err(CodegenError::from_panic)
 }
-/// Turns a statement into ops, if `ABORT_ON_ERROR` set to false, will handle and recover from errors.
+/// Turns a statement into ops. Errors(including panics) are turned into a [`CodegenError`].
 pub fn statement_to_ops<'tcx>(
     statement: &Statement<'tcx>,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> Result<Option<CILTree>, CodegenError> {
-    if *crate::config::ABORT_ON_ERROR {
-        Ok(crate::statement::handle_statement(statement, ctx))
-    } else {
-        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            crate::statement::handle_statement(statement, ctx)
-        })) {
-            Ok(success) => Ok(success),
-            Err(payload) => {
-                if let Some(msg) = payload.downcast_ref::<&str>() {
-                    Err(crate::codegen_error::CodegenError::from_panic_message(msg))
-                } else {
-                    Err(crate::codegen_error::CodegenError::from_panic_message(
-                        "statement_to_ops panicked with a non-string message!",
-                    ))
-                }
-            }
-        }
-    }
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        crate::statement::handle_statement(statement, ctx)
+    }))
+    .map_err(CodegenError::from_panic)
 }
 /// Adds a rust MIR function to the assembly.
 pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     name: &str,
     ctx: &'a mut MethodCompileCtx<'tcx, 'asm>,
+    stubbed: &mut StubbedFunctions,
 ) -> Result<(), MethodCodegenError> {
     if let TyKind::FnDef(_, _) = ctx.instance().ty(ctx.tcx(), ParamEnv::reveal_all()).kind() {
         //ALL OK.
@@ -324,9 +292,13 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
         ctx.instance().ty(ctx.tcx(), ParamEnv::reveal_all()).kind()
     {
         //println!("CLOSURE")
+    } else if let TyKind::Coroutine(_, _) =
+        ctx.instance().ty(ctx.tcx(), ParamEnv::reveal_all()).kind()
+    {
+        // The MIR of a coroutine is its resume function, already turned into a state machine.
     } else {
         println!(
-            "fn item {instance:?} is not a function definition type or a closure. Skippping.",
+            "fn item {instance:?} is not a function definition type, a closure or a coroutine. Skippping.",
             instance = ctx.instance()
         );
         return Ok(());
@@ -334,10 +306,10 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     let mir = ctx.tcx().instance_mir(ctx.instance().def);
     let mut ctx = ctx.with_body(mir);
     let ctx = &mut ctx;
-    if name.contains("rustc_codegen_clr_comptime_entrypoint") {
-        if name.contains("rustc_codegen_clr_not_magic") {
-            return Ok(());
-        }
+    // Functions implementing methods of interop types are nested inside the entrypoint, but are compiled normally.
+    if name.contains("rustc_codegen_clr_comptime_entrypoint")
+        && !name.contains("rustc_codegen_clr_not_magic")
+    {
         crate::comptime::interpret(ctx, mir);
         return Ok(());
     }
@@ -366,8 +338,15 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     }
 
     let blocks = &mir.basic_blocks;
-    let mut normal_bbs = Vec::new();
-    let mut cleanup_bbs = Vec::new();
+    // The path of this function, used in diagnostics.
+    let item = rustc_middle::ty::print::with_no_trimmed_paths! {ctx.instance().to_string()};
+    // The trees, id and handler of each block.
+    let mut block_trees = Vec::new();
+    // Holds the exception being unwound, while the cleanup blocks run.
+    let unwind_exception = u32::try_from(locals.len()).unwrap();
+    if blocks.iter().any(|block| block.is_cleanup) {
+        locals.push((Some("unwind_exception".into()), Type::PlatformObject));
+    }
     // Used for funcrions with the rust_call ABI
     let mut repack_cil = if let Some(spread_arg) = mir.spread_arg {
         // Prepare for repacking the argument tuple, by allocating a local
@@ -413,10 +392,9 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
             let statement_tree = match statement_to_ops(statement, ctx) {
                 Ok(ops) => ops,
                 Err(err) => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {eprintln!(
-                        "Method \"{name}\" failed to compile statement {statement:?} with message {err:?}\n"
-                    )};
-                    rustc_middle::ty::print::with_no_trimmed_paths! {Some(CILRoot::throw(&format!("Tired to run a statement {statement:?} which failed to compile with error message {err:?}."),ctx.asm_mut()).into())}
+                    err.report(ctx.tcx(), statement.source_info.span, &item);
+                    stubbed.record(&item, err.code());
+                    rustc_middle::ty::print::with_no_trimmed_paths! {Some(CILRoot::throw(&format!("Tired to run a statement {statement:?} which failed to compile with error message {err}."),ctx.asm_mut()).into())}
                 }
             };
             // Only save debuginfo for statements which result in ops.
@@ -434,7 +412,9 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
                 rustc_middle::ty::print::with_no_trimmed_paths! {trees.push(CILRoot::debug(&format!("{term:?}"),ctx.asm_mut()).into())};
             }
             let term_trees = terminator_to_ops(term, ctx).unwrap_or_else(|err| {
-                panic!("Could not compile terminator {term:?} because {err:?}")
+                err.report(ctx.tcx(), term.source_info.span, &item);
+                stubbed.record(&item, err.code());
+                rustc_middle::ty::print::with_no_trimmed_paths! {vec![CILRoot::throw(&format!("Tried to execute terminator {term:?} which failed to compile with error message {err}."),ctx.asm_mut()).into()]}
             });
             if !term_trees.is_empty() {
                 trees.push(span_source_info(ctx.tcx(), term.source_info.span).into());
@@ -442,41 +422,44 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
             trees.extend(term_trees);
         }
         if block_data.is_cleanup {
-            cleanup_bbs.push(BasicBlock::new(
-                trees,
-                u32::try_from(last_bb_id).unwrap(),
-                handler_for_block(
-                    block_data,
-                    &mir.basic_blocks,
-                    ctx.tcx(),
-                    &ctx.instance(),
-                    mir,
-                ),
-            ));
-        } else {
-            normal_bbs.push(BasicBlock::new(
-                trees,
-                u32::try_from(last_bb_id).unwrap(),
-                handler_for_block(
-                    block_data,
-                    &mir.basic_blocks,
-                    ctx.tcx(),
-                    &ctx.instance(),
-                    mir,
-                ),
-            ));
+            // Cleanup blocks don't run inside a handler, so resuming unwinding throws the saved exception again.
+            for tree in &mut trees {
+                if let CILRoot::ReThrow = tree.root() {
+                    *tree = CILRoot::Throw(CILNode::LDLoc(unwind_exception)).into();
+                }
+            }
         }
+        let handler = handler_for_block(
+            block_data,
+            &mir.basic_blocks,
+            ctx.tcx(),
+            &ctx.instance(),
+            mir,
+        );
+        block_trees.push((trees, u32::try_from(last_bb_id).unwrap(), handler));
         //ops.extend(trees.iter().flat_map(|tree| tree.flatten()))
     }
-
-    normal_bbs
-        .iter_mut()
-        .for_each(|bb| bb.resolve_exception_handlers(&cleanup_bbs));
-    normal_bbs
-        .iter_mut()
+    // Cleanup blocks are only reachable trough landing pads, which may skip some of them.
+    let live_cleanup = live_cleanup_blocks(&block_trees);
+    let mut bbs: Vec<BasicBlock> = block_trees
+        .into_iter()
+        .filter(|(_, id, _)| !blocks[(*id).into()].is_cleanup || live_cleanup.contains(id))
+        .map(|(trees, id, handler)| {
+            let is_cleanup = blocks[id.into()].is_cleanup;
+            protected_block(
+                trees,
+                id,
+                handler,
+                is_cleanup,
+                unwind_exception,
+                ctx.asm_mut(),
+            )
+        })
+        .collect();
+    bbs.iter_mut()
         .for_each(cilly::basic_block::BasicBlock::sheed_trees);
     // Get the first bb, and append repack_cil at its start
-    let first_bb: &mut BasicBlock = &mut normal_bbs[0];
+    let first_bb: &mut BasicBlock = &mut bbs[0];
     repack_cil.append(first_bb.trees_mut());
     *first_bb.trees_mut() = repack_cil;
 
@@ -486,7 +469,7 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
         sig.clone(),
         name,
         locals,
-        normal_bbs,
+        bbs,
         arg_names,
     );
 
@@ -504,6 +487,7 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
     let main_module = ctx.asm_mut().main_module();
     let method = MethodDef::from_v1(&method, ctx.asm_mut(), main_module);
     ctx.asm_mut().new_method(method);
+    crate::export::export_fn(name, &sig, mir, ctx);
     drop(timer);
     Ok(())
     //todo!("Can't add function")
@@ -512,8 +496,9 @@ pub fn add_fn<'tcx, 'asm, 'a: 'asm>(
 pub fn checked_add_fn<'a: 'c, 'b: 'c, 'c>(
     ctx: &'a mut MethodCompileCtx<'b, 'c>,
     name: &str,
+    stubbed: &mut StubbedFunctions,
 ) -> Result<(), MethodCodegenError> {
-    add_fn(name, ctx)
+    add_fn(name, ctx, stubbed)
     /*match std::panic::catch_unwind(add_fn) {
         Ok(success) => success,
         Err(payload) => {
@@ -528,11 +513,13 @@ pub fn checked_add_fn<'a: 'c, 'b: 'c, 'c>(
         }
     }*/
 }
-/// Adds a MIR item (method,inline assembly code, etc.) to the assembly.
+/// Adds a MIR item (method,inline assembly code, etc.) to the assembly. Functions which could not be fully compiled
+/// are recorded in `stubbed`.
 pub fn add_item<'tcx>(
     asm: &mut Assembly,
     item: MonoItem<'tcx>,
     tcx: TyCtxt<'tcx>,
+    stubbed: &mut StubbedFunctions,
 ) -> Result<(), CodegenError> {
     match item {
         MonoItem::Fn(instance) => {
@@ -542,7 +529,7 @@ pub fn add_item<'tcx>(
             let function_compile_timer = tcx
                 .profiler()
                 .generic_activity_with_arg("compile function", item.symbol_name(tcx).to_string());
-            rustc_middle::ty::print::with_no_trimmed_paths! {checked_add_fn(  &mut ctx,&symbol_name,)
+            rustc_middle::ty::print::with_no_trimmed_paths! {checked_add_fn(  &mut ctx,&symbol_name,stubbed)
             .expect("Could not add function!")};
             drop(function_compile_timer);
             Ok(())
@@ -635,12 +622,11 @@ pub fn add_allocation(alloc_id: u64, asm: &mut cilly::v2::Assembly, tcx: TyCtxt<
                     Some(def_id.krate),
                 )
             }
-            GlobalAlloc::VTable(..) => {
-                //TODO: handle VTables
-                let alloc_fld: IString = format!("al_{alloc_id:x}").into();
-                let field_desc = StaticFieldDescriptor::new(None, uint8_ptr, alloc_fld.clone());
-                asm.add_static(uint8_ptr, alloc_fld, false, main_module_id);
-                return CILNode::LDStaticField(Box::new(field_desc));
+            GlobalAlloc::VTable(ty, trait_ref) => {
+                // Vtable pointers inside constants refer to this kind of allocation: the vtable itself is stored
+                // in a memory allocation, which contains the function pointers.
+                let vtable = tcx.vtable_allocation((ty, trait_ref));
+                return add_allocation(crate::utilis::alloc_id_to_u64(vtable), asm, tcx);
             }
             GlobalAlloc::Function { .. } => {
                 //TODO: handle constant functions
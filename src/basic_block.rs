@@ -1,27 +1,40 @@
-use cilly::basic_block::Handler;
+use cilly::{
+    basic_block::{BasicBlock, Handler},
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    cil_tree::CILTree,
+    v2::Assembly,
+};
+use fxhash::FxHashSet;
 use rustc_middle::mir::BasicBlockData;
-use rustc_middle::mir::UnwindAction;
+use rustc_middle::mir::{UnwindAction, UnwindTerminateReason};
 use rustc_middle::{
     mir::{BasicBlocks, Body, TerminatorKind},
     ty::{Instance, InstanceKind, TyCtxt},
 };
 
+/// Returns the id of the handler block `block_data` unwinds into: either the first cleanup block which has to run, or
+/// [`TERMINATE_HANDLER`].
 pub(crate) fn handler_for_block<'tcx>(
     block_data: &BasicBlockData,
     blocks: &BasicBlocks<'tcx>,
     tcx: TyCtxt<'tcx>,
     method_instance: &Instance<'tcx>,
     method: &Body<'tcx>,
-) -> Option<Handler> {
+) -> Option<u32> {
     let term = block_data.terminator.as_ref()?;
     let unwind = term.unwind()?;
-    Some(Handler::RawID(simplify_handler(
+    // Cleanup blocks run outside of any handler(see [`landing_pad`]), and a panic in one of them must abort the program.
+    if block_data.is_cleanup {
+        return (!*crate::config::NO_UNWIND).then_some(TERMINATE_HANDLER);
+    }
+    simplify_handler(
         handler_from_action(*unwind),
         blocks,
         tcx,
         method_instance,
         method,
-    )?))
+    )
 }
 #[allow(clippy::match_same_arms)]
 fn simplify_handler<'tcx>(
@@ -35,6 +48,9 @@ fn simplify_handler<'tcx>(
         return None;
     }
     let handler = handler?;
+    if handler == TERMINATE_HANDLER {
+        return Some(handler);
+    }
     if !blocks[handler.into()].statements.is_empty() {
         return Some(handler);
     }
@@ -85,6 +101,93 @@ fn simplify_handler<'tcx>(
         TerminatorKind::UnwindTerminate(_) => Some(handler),
     }
 }
+/// Id of the handler block which aborts the program when an exception reaches it. See [`terminate_handler`].
+pub(crate) const TERMINATE_HANDLER: u32 = u32::MAX - 1;
+/// Creates the handler block used for `UnwindAction::Terminate`. An exception(a Rust panic, or a foreign .NET exception)
+/// which would unwind out of a function that can't unwind, or out of a cleanup block, aborts the program with `reason`,
+/// instead of escaping it.
+pub(crate) fn terminate_handler(reason: UnwindTerminateReason, asm: &mut Assembly) -> BasicBlock {
+    BasicBlock::new(
+        vec![
+            CILRoot::fail_fast(reason.as_str(), asm).into(),
+            CILRoot::ReThrow.into(),
+        ],
+        TERMINATE_HANDLER,
+        None,
+    )
+}
+/// Id of the handler block which starts running the cleanup blocks. See [`landing_pad`].
+pub(crate) const LANDING_PAD: u32 = u32::MAX - 2;
+/// Creates the handler of block `block`, whose cleanup starts at the block `cleanup`. Cleanup blocks are regular blocks,
+/// protected by the [`terminate_handler`], since nested handlers are not supported. So, this handler only saves the
+/// exception in the local `exception`, and leaves to the cleanup blocks. They then throw the exception again, when
+/// unwinding should resume.
+pub(crate) fn landing_pad(block: u32, cleanup: u32, exception: u32) -> BasicBlock {
+    BasicBlock::new(
+        vec![
+            CILRoot::STLoc {
+                local: exception,
+                tree: CILNode::GetException,
+            }
+            .into(),
+            CILRoot::JumpingPad {
+                source: block,
+                target: cleanup,
+            }
+            .into(),
+        ],
+        LANDING_PAD,
+        None,
+    )
+}
+/// Creates the block `id`, made up from `trees`, and protected by the handler `handler` returned by [`handler_for_block`].
+pub(crate) fn protected_block(
+    trees: Vec<CILTree>,
+    id: u32,
+    handler: Option<u32>,
+    is_cleanup: bool,
+    exception: u32,
+    asm: &mut Assembly,
+) -> BasicBlock {
+    let Some(handler) = handler else {
+        return BasicBlock::new(trees, id, None);
+    };
+    let handler = if handler == TERMINATE_HANDLER {
+        let reason = if is_cleanup {
+            UnwindTerminateReason::InCleanup
+        } else {
+            UnwindTerminateReason::Abi
+        };
+        terminate_handler(reason, asm)
+    } else {
+        landing_pad(id, handler, exception)
+    };
+    let mut block = BasicBlock::new(trees, id, Some(Handler::RawID(handler.id())));
+    block.resolve_exception_handlers(&[handler]);
+    block
+}
+/// Returns which cleanup blocks can be reached from the landing pads of `blocks`, which contains the trees, id and handler
+/// of each block.
+pub(crate) fn live_cleanup_blocks(blocks: &[(Vec<CILTree>, u32, Option<u32>)]) -> FxHashSet<u32> {
+    let mut live = FxHashSet::default();
+    let mut to_visit: Vec<u32> = blocks
+        .iter()
+        .filter_map(|(_, _, handler)| *handler)
+        .filter(|handler| *handler != TERMINATE_HANDLER)
+        .collect();
+    while let Some(block) = to_visit.pop() {
+        if !live.insert(block) {
+            continue;
+        }
+        let Some((trees, _, _)) = blocks.iter().find(|(_, id, _)| *id == block) else {
+            continue;
+        };
+        let mut targets = Vec::new();
+        trees.iter().for_each(|tree| tree.targets(&mut targets));
+        to_visit.extend(targets.into_iter().map(|(target, _)| target));
+    }
+    live
+}
 /// Convert an `UnwindAction` into an id of the block this will jump into during an exception.
 //  We match same arms on purpose here.
 #[allow(clippy::match_same_arms)]
@@ -92,9 +195,9 @@ pub(crate) fn handler_from_action(action: UnwindAction) -> Option<u32> {
     match action {
         UnwindAction::Continue => None,
         UnwindAction::Cleanup(handler) => Some(handler.as_u32()),
-        // This is triggered during double panics and panic corssing FFI boundaries.
-        // TODO: This is incorrect, since it does nothing when it should terminate this program.
-        UnwindAction::Terminate(_reason) => None,
+        // This is triggered during double panics and panics crossing no-unwind FFI boundaries(eg. `extern "C"`, but not
+        // `extern "C-unwind"`), and must abort the program.
+        UnwindAction::Terminate(_reason) => Some(TERMINATE_HANDLER),
         // Reaching this is UB, so we can do whatever here
         // continuing unwinding seems like an OK option.
         UnwindAction::Unreachable => None,
@@ -2,7 +2,7 @@ use cilly::{
     call,
     call_site::CallSite,
     cil_node::CILNode,
-    eq, gt, gt_un, lt, lt_un,
+    eq, gt, gt_un, ldc_i32, lt, lt_un,
     v2::{Assembly, ClassRef, Float, FnSig, Int},
     Type,
 };
@@ -64,17 +64,7 @@ pub fn eq_unchecked(
         | TyKind::RawPtr(_, _) => {
             eq!(operand_a, operand_b)
         }
-        TyKind::Float(FloatTy::F128) => call!(
-            CallSite::builtin(
-                "__eqtf2".into(),
-                FnSig::new(
-                    [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
-                    Type::Bool
-                ),
-                true
-            ),
-            [operand_a, operand_b]
-        ),
+        TyKind::Float(FloatTy::F128) => eq!(f128_cmp("__eqtf2", operand_a, operand_b), ldc_i32!(0)),
         TyKind::Float(FloatTy::F16) => call!(
             CallSite::new_extern(
                 ClassRef::half(asm),
@@ -133,14 +123,16 @@ pub fn lt_unchecked(
             lt!(operand_a, operand_b)
         }
         TyKind::RawPtr(_, _) | TyKind::FnPtr(_, _) => lt_un!(operand_a, operand_b),
-        TyKind::Float(FloatTy::F128) => call!(
-            CallSite::builtin(
-                "__lttf2".into(),
+        TyKind::Float(FloatTy::F128) => lt!(f128_cmp("__lttf2", operand_a, operand_b), ldc_i32!(0)),
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(asm),
+                "op_LessThan".into(),
                 FnSig::new(
-                    [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
                     Type::Bool
                 ),
-                true
+                true,
             ),
             [operand_a, operand_b]
         ),
@@ -188,14 +180,16 @@ pub fn gt_unchecked(
         TyKind::Bool | TyKind::Char | TyKind::Float(FloatTy::F32 | FloatTy::F64) => {
             gt!(operand_a, operand_b)
         }
-        TyKind::Float(FloatTy::F128) => call!(
-            CallSite::builtin(
-                "__gttf2".into(),
+        TyKind::Float(FloatTy::F128) => gt!(f128_cmp("__gttf2", operand_a, operand_b), ldc_i32!(0)),
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(asm),
+                "op_GreaterThan".into(),
                 FnSig::new(
-                    [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
                     Type::Bool
                 ),
-                true
+                true,
             ),
             [operand_a, operand_b]
         ),
@@ -203,3 +197,18 @@ pub fn gt_unchecked(
         _ => panic!("Can't eq type  {ty_a:?}"),
     }
 }
+/// Calls a `libgcc` comparison routine for `f128`. Those return an int, which has to be compared with 0 to get the result:
+/// for example, `__lttf2(a, b) < 0` if `a < b`.
+pub fn f128_cmp(name: &str, operand_a: CILNode, operand_b: CILNode) -> CILNode {
+    call!(
+        CallSite::builtin(
+            name.into(),
+            FnSig::new(
+                [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
+                Type::Int(Int::I32)
+            ),
+            true
+        ),
+        [operand_a, operand_b]
+    )
+}
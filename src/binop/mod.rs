@@ -8,7 +8,7 @@ use cilly::{
     cil_root::CILRoot,
     conv_i8, conv_u16, conv_u32, conv_u64, conv_u8, div, eq,
     field_desc::FieldDescriptor,
-    gt_un, ld_false, lt_un, rem, rem_un, size_of, sub,
+    gt, gt_un, ld_false, ldc_i32, lt, lt_un, rem, rem_un, size_of, sub,
     v2::{ClassRef, Float, FnSig, Int},
     Type,
 };
@@ -36,6 +36,17 @@ pub(crate) fn binop<'tcx>(
     let ops_b = crate::operand::handle_operand(operand_b, ctx);
     let ty_a = operand_a.ty(&ctx.body().local_decls, ctx.tcx());
     let ty_b = operand_b.ty(&ctx.body().local_decls, ctx.tcx());
+    binop_values(binop, ty_a, ty_b, ops_a, ops_b, ctx)
+}
+/// Preforms an unchecked binary operation on already computed values `ops_a` and `ops_b`, of types `ty_a` and `ty_b`.
+pub(crate) fn binop_values<'tcx>(
+    binop: BinOp,
+    ty_a: Ty<'tcx>,
+    ty_b: Ty<'tcx>,
+    ops_a: CILNode,
+    ops_b: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
     match binop {
         BinOp::AddWithOverflow => {
             if ty_a.is_signed() {
@@ -73,11 +84,16 @@ pub(crate) fn binop<'tcx>(
         BinOp::Ge => match ty_a.kind() {
             // Unordered, to handle NaNs propely
             TyKind::Float(FloatTy::F32 | FloatTy::F64) => eq!(lt_un!(ops_a, ops_b), ld_false!()),
-            TyKind::Float(FloatTy::F128) => call!(
-                CallSite::builtin(
-                    "__getf2".into(),
+            TyKind::Float(FloatTy::F128) => eq!(
+                lt!(cmp::f128_cmp("__getf2", ops_a, ops_b), ldc_i32!(0)),
+                ld_false!()
+            ),
+            TyKind::Float(FloatTy::F16) => call!(
+                CallSite::new_extern(
+                    ClassRef::half(ctx.asm_mut()),
+                    "op_GreaterThanOrEqual".into(),
                     FnSig::new(
-                        [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
+                        [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
                         Type::Bool
                     ),
                     true
@@ -89,11 +105,16 @@ pub(crate) fn binop<'tcx>(
         BinOp::Le => match ty_a.kind() {
             // Unordered, to handle NaNs propely
             TyKind::Float(FloatTy::F32 | FloatTy::F64) => eq!(gt_un!(ops_a, ops_b), ld_false!()),
-            TyKind::Float(FloatTy::F128) => call!(
-                CallSite::builtin(
-                    "__letf2".into(),
+            TyKind::Float(FloatTy::F128) => eq!(
+                gt!(cmp::f128_cmp("__letf2", ops_a, ops_b), ldc_i32!(0)),
+                ld_false!()
+            ),
+            TyKind::Float(FloatTy::F16) => call!(
+                CallSite::new_extern(
+                    ClassRef::half(ctx.asm_mut()),
+                    "op_LessThanOrEqual".into(),
                     FnSig::new(
-                        [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
+                        [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
                         Type::Bool
                     ),
                     true
@@ -207,6 +228,18 @@ pub fn add_unchecked<'tcx>(
             }
         }
         TyKind::Float(FloatTy::F32 | FloatTy::F64) => ops_a + ops_b,
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(ctx.asm_mut()),
+                "op_Addition".into(),
+                FnSig::new(
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
+                    Type::Float(Float::F16)
+                ),
+                true
+            ),
+            [ops_a, ops_b]
+        ),
         TyKind::Float(FloatTy::F128) => call!(
             CallSite::builtin(
                 "__addtf3".into(),
@@ -265,6 +298,18 @@ pub fn sub_unchecked<'tcx>(
             }
         }
         TyKind::Float(FloatTy::F32 | FloatTy::F64) => sub!(ops_a, ops_b),
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(ctx.asm_mut()),
+                "op_Subtraction".into(),
+                FnSig::new(
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
+                    Type::Float(Float::F16)
+                ),
+                true
+            ),
+            [ops_a, ops_b]
+        ),
         TyKind::Float(FloatTy::F128) => call!(
             CallSite::builtin(
                 "__subtf3".into(),
@@ -317,9 +362,21 @@ fn rem_unchecked<'tcx>(
         TyKind::Int(_) | TyKind::Char | TyKind::Float(FloatTy::F32 | FloatTy::F64) => {
             rem!(ops_a, ops_b)
         }
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(ctx.asm_mut()),
+                "op_Modulus".into(),
+                FnSig::new(
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
+                    Type::Float(Float::F16)
+                ),
+                true
+            ),
+            [ops_a, ops_b]
+        ),
         TyKind::Float(FloatTy::F128) => call!(
             CallSite::builtin(
-                "fmodl".into(),
+                "fmodf128".into(),
                 FnSig::new(
                     [Type::Float(Float::F128), Type::Float(Float::F128)].into(),
                     Type::Float(Float::F128)
@@ -368,6 +425,18 @@ fn mul_unchecked<'tcx>(
                 [operand_a, operand_b]
             )
         }
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(ctx.asm_mut()),
+                "op_Multiply".into(),
+                FnSig::new(
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
+                    Type::Float(Float::F16)
+                ),
+                true
+            ),
+            [operand_a, operand_b]
+        ),
         TyKind::Float(FloatTy::F128) => call!(
             CallSite::builtin(
                 "__multf3".into(),
@@ -420,6 +489,18 @@ fn div_unchecked<'tcx>(
         TyKind::Int(_) | TyKind::Char | TyKind::Float(FloatTy::F32 | FloatTy::F64) => {
             div!(operand_a, operand_b)
         }
+        TyKind::Float(FloatTy::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(ctx.asm_mut()),
+                "op_Division".into(),
+                FnSig::new(
+                    [Type::Float(Float::F16), Type::Float(Float::F16)].into(),
+                    Type::Float(Float::F16)
+                ),
+                true
+            ),
+            [operand_a, operand_b]
+        ),
         TyKind::Float(FloatTy::F128) => call!(
             CallSite::builtin(
                 "__divtf3".into(),
@@ -2,6 +2,8 @@ use cilly::cil_node::CILNode;
 use cilly::v2::{Assembly, ClassRef, Float, FnSig, Int};
 use cilly::{call_site::CallSite, Type};
 
+use crate::unsupported;
+
 use cilly::{
     call, conv_f32, conv_f64, conv_f_un, conv_i16, conv_i32, conv_i64, conv_i8, conv_isize,
     conv_u16, conv_u32, conv_u64, conv_u8, conv_usize,
@@ -148,6 +150,27 @@ pub fn int_to_int(src: Type, target: Type, operand: CILNode, asm: &mut Assembly)
 }
 /// Returns CIL ops required to convert type src to target
 pub fn float_to_int(src: Type, target: Type, operand: CILNode, asm: &mut Assembly) -> CILNode {
+    match src {
+        // Each `f16` can be represented exactly by a `f32`.
+        Type::Float(Float::F16) => {
+            let operand = float_to_float(src, Type::Float(Float::F32), operand, asm);
+            return float_to_int(Type::Float(Float::F32), target, operand, asm);
+        }
+        Type::Float(Float::F128) => {
+            let Type::Int(int) = target else {
+                panic!("Can't cast a f128 to {target:?}")
+            };
+            return call!(
+                CallSite::builtin(
+                    format!("cast_f128_{}", int.name()).into(),
+                    FnSig::new(Box::new([src]), target),
+                    true
+                ),
+                [operand]
+            );
+        }
+        _ => (),
+    }
     match target {
         Type::Int(Int::I128) => call!(
             CallSite::new_extern(
@@ -380,12 +403,31 @@ fn to_int(target: Type, operand: CILNode) -> CILNode {
         Type::Int(Int::ISize) => conv_isize!(operand),
         Type::Int(Int::USize) => conv_usize!(operand),
         Type::Ptr(tpe) => conv_usize!(operand).cast_ptr(Type::Ptr(tpe)),
-        _ => todo!("Can't cast to {target:?} yet!"),
+        _ => unsupported!("Can't cast to {target:?} yet!"),
     }
 }
 /// Returns CIL ops required to casts from intiger type `src` to `target` MOVE TO CILLY
 pub fn int_to_float(src: Type, target: Type, parrent: CILNode, asm: &mut Assembly) -> CILNode {
-    if matches!(src, Type::Int(Int::I128)) {
+    if matches!(target, Type::Float(Float::F16)) {
+        // Ints either fit exactly in a `f64`, or are too big for a `f16` anyway, so this only rounds once.
+        let parrent = int_to_float(src, Type::Float(Float::F64), parrent, asm);
+        float_to_float(Type::Float(Float::F64), target, parrent, asm)
+    } else if matches!(target, Type::Float(Float::F128)) {
+        let (name, arg) = match src {
+            Type::Int(Int::I8 | Int::I16 | Int::I32) => ("__floatsitf", Type::Int(Int::I32)),
+            Type::Int(Int::U8 | Int::U16 | Int::U32) => ("__floatunsitf", Type::Int(Int::U32)),
+            Type::Int(Int::I64 | Int::ISize) => ("__floatditf", Type::Int(Int::I64)),
+            Type::Int(Int::U64 | Int::USize) => ("__floatunditf", Type::Int(Int::U64)),
+            Type::Int(Int::I128) => ("__floattitf", src),
+            Type::Int(Int::U128) => ("__floatuntitf", src),
+            // Pointers are converted like `u64`s, and `bool`s and `char`s like `u32`s.
+            Type::Ptr(_) | Type::FnPtr(_) => ("__floatunditf", Type::Int(Int::U64)),
+            Type::Bool | Type::PlatformChar => ("__floatunsitf", Type::Int(Int::U32)),
+            _ => unsupported!("Can't cast {src:?} to {target:?} yet!"),
+        };
+        let parrent = int_to_int(src, arg, parrent, asm);
+        soft_float(name, arg, target, parrent)
+    } else if matches!(src, Type::Int(Int::I128)) {
         call!(
             CallSite::boxed(
                 ClassRef::int_128(asm).into(),
@@ -407,7 +449,7 @@ pub fn int_to_float(src: Type, target: Type, parrent: CILNode, asm: &mut Assembl
             [parrent]
         )
     } else if matches!(target, Type::Int(Int::I128 | Int::U128)) {
-        todo!("Casting to 128 bit intiegers is not supported!")
+        unsupported!("Casting to 128 bit intiegers is not supported!")
     } else {
         match (&src, &target) {
             (Type::Int(Int::U32 | Int::U64), Type::Float(Float::F32)) => {
@@ -424,7 +466,44 @@ pub fn int_to_float(src: Type, target: Type, parrent: CILNode, asm: &mut Assembl
                 conv_f64!(conv_f_un!(conv_u64!(parrent)))
             }
             (_, Type::Float(Float::F64)) => conv_f64!(parrent),
-            _ => todo!("Can't  cast {src:?} to {target:?} yet!"),
+            _ => unsupported!("Can't cast {src:?} to {target:?} yet!"),
+        }
+    }
+}
+/// Returns CIL ops required to convert from float type `src` to `target`.
+pub fn float_to_float(src: Type, target: Type, operand: CILNode, asm: &mut Assembly) -> CILNode {
+    let (Type::Float(src_float), Type::Float(target_float)) = (src, target) else {
+        panic!("Can't preform a FloatToFloat cast from {src:?} to {target:?}")
+    };
+    match (src_float, target_float) {
+        (Float::F16, Float::F16) | (Float::F128, Float::F128) => operand,
+        (Float::F32 | Float::F64, Float::F32) => conv_f32!(operand),
+        (Float::F32 | Float::F64, Float::F64) => conv_f64!(operand),
+        (Float::F16, Float::F32 | Float::F64) | (Float::F32 | Float::F64, Float::F16) => call!(
+            CallSite::new_extern(
+                ClassRef::half(asm),
+                "op_Explicit".into(),
+                FnSig::new(Box::new([src]), target),
+                true,
+            ),
+            [operand]
+        ),
+        (Float::F32, Float::F128) => soft_float("__extendsftf2", src, target, operand),
+        (Float::F64, Float::F128) => soft_float("__extenddftf2", src, target, operand),
+        (Float::F128, Float::F32) => soft_float("__trunctfsf2", src, target, operand),
+        (Float::F128, Float::F64) => soft_float("__trunctfdf2", src, target, operand),
+        // Each `f16` can be represented exactly by a `f32`.
+        (Float::F16, Float::F128) => {
+            let operand = float_to_float(src, Type::Float(Float::F32), operand, asm);
+            float_to_float(Type::Float(Float::F32), target, operand, asm)
         }
+        (Float::F128, Float::F16) => soft_float("__trunctfhf2", src, target, operand),
     }
 }
+/// Calls a `libgcc` soft-float routine, used to emulate `f128`.
+fn soft_float(name: &str, src: Type, target: Type, operand: CILNode) -> CILNode {
+    call!(
+        CallSite::builtin(name.into(), FnSig::new(Box::new([src]), target), true),
+        [operand]
+    )
+}
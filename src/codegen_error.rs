@@ -1,4 +1,7 @@
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug};
+
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
 
 #[derive(Debug)]
 /// Repersentation of an error which occured while converting MIR to CIL assembly.
@@ -7,6 +10,8 @@ pub enum CodegenError {
     Error(crate::IString),
     Method(MethodCodegenError),
     FunctionABIUnsuported(&'static str),
+    /// A construct the codegen does not support yet. Raised using [`crate::unsupported`].
+    Unsupported(crate::IString),
 }
 
 impl From<MethodCodegenError> for CodegenError {
@@ -14,9 +19,100 @@ impl From<MethodCodegenError> for CodegenError {
         Self::Method(value)
     }
 }
+impl std::fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnersolvedGeneric => write!(f, "encountered an unresolved generic"),
+            Self::Error(msg) => write!(f, "internal codegen error: {msg}"),
+            Self::Method(err) => write!(f, "{err:?}"),
+            Self::FunctionABIUnsuported(abi) => write!(f, "the ABI {abi} is not supported"),
+            Self::Unsupported(msg) => write!(f, "unsupported construct: {msg}"),
+        }
+    }
+}
 impl CodegenError {
-    pub fn from_panic_message(msg: &str) -> Self {
-        Self::Error(msg.into())
+    /// Converts the payload of a panic raised while compiling a statement or terminator into an error.
+    #[must_use]
+    pub fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        match payload.downcast::<Self>() {
+            Ok(err) => *err,
+            Err(payload) => {
+                if let Some(msg) = payload.downcast_ref::<&str>() {
+                    Self::Error((*msg).into())
+                } else if let Some(msg) = payload.downcast_ref::<String>() {
+                    Self::Error(msg.as_str().into())
+                } else {
+                    Self::Error("the codegen panicked with a non-string message".into())
+                }
+            }
+        }
+    }
+    /// A stable code of this kind of error, used to group the failures of many crates.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Error(_) | Self::Method(_) => "CLR0000",
+            Self::Unsupported(_) => "CLR0001",
+            Self::FunctionABIUnsuported(_) => "CLR0002",
+            Self::UnersolvedGeneric => "CLR0003",
+        }
+    }
+    /// Reports this error, raised at `span` while compiling `item`, using rustc's diagnostics. If `ABORT_ON_ERROR` is
+    /// set, this is a hard error. Otherwise, this is a warning, since the offending code gets replaced with a stub.
+    pub fn report(&self, tcx: TyCtxt, span: Span, item: &str) {
+        let msg = format!("[{code}] {self}", code = self.code());
+        let item = format!("while compiling `{item}`");
+        if *crate::config::ABORT_ON_ERROR {
+            tcx.dcx().struct_span_err(span, msg).with_note(item).emit();
+        } else {
+            tcx.dcx()
+                .struct_span_warn(span, msg)
+                .with_note(item)
+                .with_note("the code was replaced with a stub, which throws an exception when reached")
+                .emit();
+        }
+    }
+}
+/// Stops compiling the current statement or terminator, because it uses a construct which is not supported yet. Unlike
+/// `todo!`, this is reported as a [`CodegenError`], and does not abort the whole compilation.
+#[macro_export]
+macro_rules! unsupported {
+    ($($arg:tt)*) => {
+        // `resume_unwind` does not run the panic hook, so rustc does not treat this as an ICE.
+        std::panic::resume_unwind(Box::new($crate::codegen_error::CodegenError::Unsupported(
+            format!($($arg)*).into(),
+        )))
+    };
+}
+/// The functions which could not be fully compiled, along with the codes of their errors. Reported at the end of
+/// the crate, to make it easy to see what is broken.
+#[derive(Default)]
+pub struct StubbedFunctions(Vec<(String, Vec<&'static str>)>);
+impl StubbedFunctions {
+    /// Records that `item` had an error with `code`.
+    pub fn record(&mut self, item: &str, code: &'static str) {
+        match self.0.last_mut() {
+            Some((last, codes)) if last == item => {
+                if !codes.contains(&code) {
+                    codes.push(code);
+                }
+            }
+            _ => self.0.push((item.to_owned(), vec![code])),
+        }
+    }
+    /// Emits a summary of all the functions with errors, if there were any.
+    pub fn report(&self, tcx: TyCtxt) {
+        if self.0.is_empty() {
+            return;
+        }
+        let mut diag = tcx.dcx().struct_warn(format!(
+            "{count} functions could not be fully compiled",
+            count = self.0.len()
+        ));
+        for (item, codes) in &self.0 {
+            diag.note(format!("`{item}`: {codes}", codes = codes.join(", ")));
+        }
+        diag.emit();
     }
 }
 
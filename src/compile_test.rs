@@ -30,6 +30,39 @@ pub fn test_dotnet_executable(file_path: &str, test_dir: &str) -> String {
         );
         return String::from_utf8_lossy(&out.stdout).to_string();
     }
+    if *crate::config::JS_MODE {
+        let out = std::process::Command::new("timeout")
+            .current_dir(test_dir)
+            .arg("-v")
+            .arg("5")
+            .arg("node")
+            .arg(format!("{file_path}.js"))
+            .output()
+            .expect("failed to run test program!");
+        let stderr = String::from_utf8(out.stderr).expect("stderr is not UTF8 String!");
+        assert!(
+            stderr.is_empty(),
+            "Test program failed with message {stderr:}"
+        );
+        return String::from_utf8_lossy(&out.stdout).to_string();
+    }
+    if *crate::config::JAVA_MODE {
+        let out = std::process::Command::new("timeout")
+            .current_dir(test_dir)
+            .arg("-v")
+            .arg("5")
+            .arg("java")
+            .arg("-jar")
+            .arg(format!("{file_path}.jar"))
+            .output()
+            .expect("failed to run test program!");
+        let stderr = String::from_utf8(out.stderr).expect("stderr is not UTF8 String!");
+        assert!(
+            stderr.is_empty(),
+            "Test program failed with message {stderr:}"
+        );
+        return String::from_utf8_lossy(&out.stdout).to_string();
+    }
 
     //println!("exec_path:{exec_path:?}");
     if *IS_DOTNET_PRESENT {
@@ -364,6 +397,13 @@ macro_rules! test_lib {
     };
 }
 #[cfg(test)]
+/// Returns the edition set by an `//@ edition: YEAR` line at the start of a test, like in the rustc UI tests. Tests use the 2021 edition by default.
+fn edition_directive(test_name: &str, test_dir: &str) -> Option<String> {
+    let source = std::fs::read_to_string(format!("{test_dir}{test_name}.rs")).ok()?;
+    let edition = source.lines().next()?.strip_prefix("//@ edition:")?;
+    Some(edition.trim().to_owned())
+}
+#[cfg(test)]
 fn compiler(test_name: &str, test_dir: &str, release: bool) -> std::process::Command {
     // Compiles the test project
     let mut cmd = std::process::Command::new("rustc");
@@ -371,8 +411,13 @@ fn compiler(test_name: &str, test_dir: &str, release: bool) -> std::process::Com
     if release {
         cmd.arg("-O");
     }
+    let mut args = rustc_args().to_vec();
+    if let Some(edition) = edition_directive(test_name, test_dir) {
+        let pos = args.iter().position(|arg| arg == "--edition").unwrap();
+        args[pos + 1] = edition;
+    }
     cmd.current_dir(test_dir)
-        .args(rustc_args().iter())
+        .args(args)
         .args([format!("./{test_name}.rs"), "-o".to_owned()]);
     if release {
         cmd.arg(format!("./{test_name}.exe"));
@@ -745,6 +790,8 @@ run_test! {cast,i32_to_u64,stable}
 run_test! {cast,i32_to_usize,stable}
 run_test! {control_flow,cf_for,stable}
 run_test! {control_flow,drop,stable}
+run_test! {control_flow,switch,stable}
+run_test! {control_flow,tail_call,stable}
 run_test! {fuzz,test0,stable}
 run_test! {fuzz,test1,stable}
 run_test! {intrinsics,addr_of,stable}
@@ -767,10 +814,12 @@ run_test! {intrinsics,pow_sqrt,stable}
 run_test! {intrinsics,printf,stable}
 run_test! {intrinsics,ptr_offset_from_unsigned,stable}
 run_test! {intrinsics,round,stable}
+run_test! {intrinsics,simd,stable}
 run_test! {intrinsics,size_of_val,stable}
 run_test! {intrinsics,transmute,stable}
 run_test! {intrinsics,trigonometry,stable}
 run_test! {intrinsics,type_id,stable}
+run_test! {intrinsics,unwind,stable}
 run_test! {intrinsics,wrapping_ops,stable}
 run_test! {iter,fold,stable}
 run_test! {statics,thread_local,stable}
@@ -787,16 +836,19 @@ run_test! {std,once_lock_test,unstable}
 run_test! {std,tlocal_key_test,stable}
 run_test! {types,adt_enum,stable}
 run_test! {types,f128,stable}
+run_test! {types,f16,stable}
 run_test! {types,aligned,stable}
 run_test! {types,any,stable}
 run_test! {types,arr,stable}
 run_test! {types,async_types,unstable}
+run_test! {types,coroutine,stable}
+run_test! {types,gen_blocks,stable}
 run_test! {types,dst,stable}
 run_test! {types,dyns,stable}
 run_test! {types,enums,stable}
 run_test! {types,int128,stable}
 run_test! {types,interop,stable}
-run_test! {types,interop_typedef,unstable}
+run_test! {types,interop_export,stable}
 run_test! {types,maybeuninit,stable}
 run_test! {types,nbody,stable}
 run_test! {types,ref_deref,stable}
@@ -812,6 +864,7 @@ run_test! {types,subslice,stable}
 run_test! {types,tuple_enum,stable}
 run_test! {types,tuple_structs,stable}
 run_test! {types,vec,stable}
+run_test! {types,vtables,stable}
 
 compare_tests! {fuzz,fuzz0,stable}
 compare_tests! {fuzz,fuzz1,stable}
@@ -956,6 +1009,34 @@ cargo_test! {benchmarks,bench}
 // TODO: This trips up some post-link sanity checks, investigate.
 cargo_test! {glam_test,unstable}
 cargo_test! {fastrand_test,stable}
+cargo_test! {interop_task,unstable}
+cargo_test! {interop_typedef,stable}
+/// A panic during cleanup must abort the program. If it unwinds instead, `catch_unwind` catches it, and the program succeeds.
+#[test]
+fn unwind_terminate() {
+    let test_dir = "./test/intrinsics/";
+    RUSTC_BUILD_STATUS.as_ref().expect("Could not build rustc!");
+    let out = compiler("unwind_terminate", test_dir, true)
+        .output()
+        .expect("failed to execute process");
+    if !out.stderr.is_empty() {
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        panic!("stdout:\n{stdout}\nstderr:\n{stderr}");
+    }
+    if *crate::config::DRY_RUN {
+        return;
+    }
+    let err = std::panic::catch_unwind(|| test_dotnet_executable("./unwind_terminate", test_dir))
+        .expect_err("A panic during cleanup did not abort the program.");
+    let msg = err
+        .downcast_ref::<String>()
+        .expect("test_dotnet_executable panicked with a non-string message.");
+    assert!(
+        msg.contains("panic in a destructor during cleanup"),
+        "The program failed for another reason: {msg}"
+    );
+}
 
 use lazy_static::lazy_static;
 #[cfg(target_os = "windows")]
@@ -1,15 +1,44 @@
-#![allow(dead_code)]
-use crate::fn_ctx::MethodCompileCtx;
-use cilly::v2::ClassDef;
+//! Interprets the bodies of `rustc_codegen_clr_comptime_entrypoint` functions, which describe .NET classes defined in Rust source.
+//!
+//! The body of such an entrypoint is a chain of calls to "magic" functions(`rustc_codegen_clr_new_typedef`, `rustc_codegen_clr_add_field_def`, etc.).
+//! Those functions are never called at runtime: instead, their generic arguments are read at compile time, and used to build a [`ClassDef`].
+//! Methods and constructors of such a class are thin wrappers, forwarding their arguments to Rust functions.
+use crate::{fn_ctx::MethodCompileCtx, r#type::get_type, utilis::garg_to_string};
+use cilly::v2::{
+    cilnode::MethodKind, Access, BasicBlock, CILNode, CILRoot, ClassDef, ClassRef, ClassRefIdx,
+    MethodDef, MethodImpl, MethodRefIdx, StringIdx, Type,
+};
+use rustc_middle::{
+    mir::{Operand, Rvalue, StatementKind, TerminatorKind},
+    ty::{GenericArg, Instance, ParamEnv, TyKind},
+};
+/// A method of a class defined in Rust, forwarding to a Rust function.
+#[derive(Clone)]
+struct ComptimeMethod {
+    access: Access,
+    kind: MethodKind,
+    name: StringIdx,
+    target: MethodRefIdx,
+}
+/// A class definition, which is still being built.
+#[derive(Clone)]
+struct ComptimeTypeDef {
+    name: StringIdx,
+    is_valuetype: bool,
+    extends: Option<ClassRefIdx>,
+    implements: Vec<ClassRefIdx>,
+    fields: Vec<(Type, StringIdx, Option<u32>)>,
+    methods: Vec<ComptimeMethod>,
+}
 #[derive(Clone)]
 enum ComptimeLocalVar {
     NotSet,
     Void,
-    ClassDef(ClassDef),
+    ClassDef(Box<ComptimeTypeDef>),
 }
 
 impl ComptimeLocalVar {
-    fn as_type_def(&self) -> Option<&ClassDef> {
+    fn as_type_def(&self) -> Option<&ComptimeTypeDef> {
         if let Self::ClassDef(v) = self {
             Some(v)
         } else {
@@ -17,15 +46,169 @@ impl ComptimeLocalVar {
         }
     }
 }
-#[allow(unused_variables)]
+/// Converts a Rust-style path(eg. `System::Runtime`) into a .NET one(eg. `System.Runtime`).
+fn garg_to_dotnet_path<'tcx>(garg: GenericArg<'tcx>, ctx: &MethodCompileCtx<'tcx, '_>) -> String {
+    garg_to_string(garg, ctx.tcx()).replace("::", ".")
+}
+/// Allocates a reference to the class `name` from the assembly `asm`. An empty assembly name refers to the assembly being built.
+fn class_ref_from_path(
+    asm: &str,
+    name: &str,
+    is_valuetype: bool,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> ClassRefIdx {
+    let asm = Some(asm)
+        .filter(|asm| !asm.is_empty())
+        .map(|asm| ctx.asm_mut().alloc_string(asm));
+    let name = ctx.asm_mut().alloc_string(name);
+    ctx.asm_mut()
+        .alloc_class_ref(ClassRef::new(name, asm, is_valuetype, [].into()))
+}
+/// Returns a reference to the Rust function `fn_type`, which a method of a class defined in Rust will forward to.
+fn forward_target<'tcx>(
+    fn_type: GenericArg<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> MethodRefIdx {
+    let fn_type = ctx.monomorphize(fn_type.as_type().expect("Expected a function type"));
+    let TyKind::FnDef(def_id, subst_ref) = fn_type.kind() else {
+        panic!("ERROR: Methods of interop types must be implemented by functions, not {fn_type:?}.")
+    };
+    let subst_ref = ctx.monomorphize(*subst_ref);
+    let Some(instance) =
+        Instance::try_resolve(ctx.tcx(), ParamEnv::reveal_all(), *def_id, subst_ref)
+            .expect("Invalid function def")
+    else {
+        panic!("ERROR: Could not get function instance. fn type:{fn_type:?}")
+    };
+    let sig = crate::function_sig::sig_from_instance_(instance, ctx)
+        .expect("Can't get the function signature");
+    let sig = ctx.asm_mut().alloc_sig(sig);
+    let name = crate::utilis::function_name(ctx.tcx().symbol_name(instance));
+    let main_module = *ctx.asm_mut().main_module();
+    ctx.asm_mut()
+        .new_methodref(main_module, name, sig, MethodKind::Static, [])
+}
+/// Gets the class definition the comptime function operates on(always its first argument).
+fn class_arg(
+    args: &[rustc_span::source_map::Spanned<Operand<'_>>],
+    locals: &[ComptimeLocalVar],
+) -> ComptimeTypeDef {
+    let local = args[0]
+        .node
+        .place()
+        .expect("ERROR: unuported operation in interop type definiton.")
+        .as_local()
+        .expect("ERROR: unuported operation in interop type definiton.");
+    locals[usize::from(local)]
+        .as_type_def()
+        .expect("ERROR: interop type definiton used before being created.")
+        .clone()
+}
+/// Adds a class defined in Rust, and all its methods, to the assembly.
+fn finish_type(type_def: ComptimeTypeDef, ctx: &mut MethodCompileCtx<'_, '_>) {
+    let mut class_def = ClassDef::new(
+        type_def.name,
+        type_def.is_valuetype,
+        0,
+        type_def.extends,
+        type_def.fields,
+        vec![],
+        // Classes defined in Rust are used by .NET code, so they must survive dead code elimination.
+        Access::Extern,
+        None,
+    );
+    for interface in type_def.implements {
+        class_def.add_interface(interface);
+    }
+    let class_idx = ctx.asm_mut().class_def(class_def);
+    let this_type = if type_def.is_valuetype {
+        ctx.asm_mut().nref(Type::ClassRef(*class_idx))
+    } else {
+        Type::ClassRef(*class_idx)
+    };
+    for method in type_def.methods {
+        let target = ctx.asm().get_mref(method.target).clone();
+        let target_sig = ctx.asm()[target.sig()].clone();
+        let mut inputs = target_sig.inputs().to_vec();
+        // The `this` of instance methods has the type of the class being defined.
+        if method.kind != MethodKind::Static {
+            assert!(
+                !inputs.is_empty(),
+                "ERROR: instance methods of interop types must take `this` as their first argument."
+            );
+            inputs[0] = this_type;
+        }
+        let output = if method.kind == MethodKind::Constructor {
+            Type::Void
+        } else {
+            *target_sig.output()
+        };
+        let sig = ctx.asm_mut().sig(inputs.clone(), output);
+        let mut roots = vec![];
+        // Constructors of classes must call the constructor of their base class first.
+        if method.kind == MethodKind::Constructor && !type_def.is_valuetype {
+            let base = type_def
+                .extends
+                .unwrap_or_else(|| ClassRef::object(ctx.asm_mut()));
+            let base_ctor_sig = ctx.asm_mut().sig([Type::ClassRef(base)], Type::Void);
+            let base_ctor =
+                ctx.asm_mut()
+                    .new_methodref(base, ".ctor", base_ctor_sig, MethodKind::Instance, []);
+            let this = ctx.asm_mut().alloc_node(CILNode::LdArg(0));
+            roots.push(
+                ctx.asm_mut()
+                    .alloc_root(CILRoot::Call(Box::new((base_ctor, [this].into())))),
+            );
+        }
+        let mut args: Box<[_]> = (0..u32::try_from(inputs.len()).unwrap())
+            .map(|arg| ctx.asm_mut().alloc_node(CILNode::LdArg(arg)))
+            .collect();
+        // The `this` of a valuetype is a managed reference, but Rust functions take the valuetype itself.
+        if type_def.is_valuetype && method.kind != MethodKind::Static {
+            assert!(
+                method.kind != MethodKind::Constructor,
+                "ERROR: valuetypes defined in Rust can't have constructors, since the Rust function would only get a copy of `this`."
+            );
+            let tpe = ctx.asm_mut().alloc_type(Type::ClassRef(*class_idx));
+            args[0] = ctx.asm_mut().alloc_node(CILNode::LdInd {
+                addr: args[0],
+                tpe,
+                volitale: false,
+            });
+        }
+        if *target_sig.output() == Type::Void || method.kind == MethodKind::Constructor {
+            roots.push(
+                ctx.asm_mut()
+                    .alloc_root(CILRoot::Call(Box::new((method.target, args)))),
+            );
+            roots.push(ctx.asm_mut().alloc_root(CILRoot::VoidRet));
+        } else {
+            let call = ctx
+                .asm_mut()
+                .alloc_node(CILNode::Call(Box::new((method.target, args))));
+            roots.push(ctx.asm_mut().alloc_root(CILRoot::Ret(call)));
+        }
+        let arg_names = vec![None; inputs.len()];
+        ctx.asm_mut().new_method(MethodDef::new(
+            method.access,
+            class_idx,
+            method.name,
+            sig,
+            method.kind,
+            MethodImpl::MethodBody {
+                blocks: vec![BasicBlock::new(roots, 0, None)],
+                locals: vec![],
+            },
+            arg_names,
+        ));
+    }
+}
 pub fn interpret<'tcx>(
     ctx: &mut MethodCompileCtx<'tcx, '_>,
     body: &'tcx rustc_middle::mir::Body<'tcx>,
 ) {
-    todo!("Can't yet start the comptime assembly editor.");
-    /*
-    let block_id = rustc_middle::mir::BasicBlock::from_usize(0);
-    let locals = vec![ComptimeLocalVar::NotSet; body.local_decls.len()];
+    let mut block_id = rustc_middle::mir::BasicBlock::from_usize(0);
+    let mut locals = vec![ComptimeLocalVar::NotSet; body.local_decls.len()];
 
     loop {
         let block_data = &body.basic_blocks[block_id];
@@ -47,14 +230,17 @@ pub fn interpret<'tcx>(
                             _,
                             _,
                         ) => continue,
-                        _ => panic!(),
+                        _ => panic!("can't interpret the rvalue {rvalue:?} yet in rustc_codegen_clr comptime"),
                     };
-
-                    let src = src.place().unwrap().as_local().unwrap();
+                    // Constants(eg. function items) carry no comptime state.
+                    let Some(src) = src.place() else { continue };
+                    let src = src.as_local().unwrap();
                     let target = target.as_local().unwrap();
                     locals[usize::from(target)] = locals[usize::from(src)].clone();
                 }
-                StatementKind::StorageLive(_) | StatementKind::StorageDead(_) => (),
+                StatementKind::StorageLive(_)
+                | StatementKind::StorageDead(_)
+                | StatementKind::Nop => (),
                 _ => todo!(
                     "can't interpret the statement {statement:?} yet in rustc_codegen_clr comptime"
                 ),
@@ -97,136 +283,102 @@ pub fn interpret<'tcx>(
                     locals[usize::from(local)] = if function_name
                         .contains("rustc_codegen_clr_new_typedef")
                     {
-                        let name = crate::utilis::garg_to_string(subst_ref[0], ctx.tcx())
-                            .replace("::", ".");
-                        let is_value_type = crate::utilis::garag_to_bool(subst_ref[1], ctx.tcx());
-                        let superclass_asm = crate::utilis::garg_to_string(subst_ref[2], ctx.tcx())
-                            .replace("::", ".");
-                        let superclass_name =
-                            crate::utilis::garg_to_string(subst_ref[3], ctx.tcx())
-                                .replace("::", ".");
-                        let inherits = if superclass_name.is_empty() {
+                        let name = garg_to_dotnet_path(subst_ref[0], ctx);
+                        let name = ctx.asm_mut().alloc_string(name);
+                        let is_valuetype = crate::utilis::garag_to_bool(subst_ref[1], ctx.tcx());
+                        let superclass_asm = garg_to_dotnet_path(subst_ref[2], ctx);
+                        let superclass_name = garg_to_dotnet_path(subst_ref[3], ctx);
+                        let extends = if superclass_name.is_empty() {
                             None
                         } else {
-                            Some(ClassRef::new(
-                                Some(superclass_asm).and_then(|superclass_asm| {
-                                    if superclass_asm.is_empty() {
-                                        None
-                                    } else {
-                                        Some(superclass_asm)
-                                    }
-                                }),
-                                superclass_name,
-                                is_value_type,
-                                [].into(),
+                            Some(class_ref_from_path(
+                                &superclass_asm,
+                                &superclass_name,
+                                false,
+                                ctx,
                             ))
                         };
-                        let tdef = ClassDef::new(
-                            cilly::access_modifier::AccessModifer::Public,
-                            name.into(),
-                            vec![],
-                            vec![],
-                            vec![],
-                            None,
-                            0,
-                            inherits,
-                            None,
-                        );
-
-                        ComptimeLocalVar::ClassDef(tdef)
+                        ComptimeLocalVar::ClassDef(Box::new(ComptimeTypeDef {
+                            name,
+                            is_valuetype,
+                            extends,
+                            implements: vec![],
+                            fields: vec![],
+                            methods: vec![],
+                        }))
                     } else if function_name.contains("rustc_codegen_clr_finish_type") {
-                        let local = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-                        ctx.asm_mut()
-                            .class_def(locals[usize::from(local)].as_type_def().unwrap().clone());
+                        let type_def = class_arg(args, &locals);
+                        finish_type(type_def, ctx);
                         ComptimeLocalVar::Void
                     } else if function_name.as_ref() == "black_box" {
                         ComptimeLocalVar::NotSet
+                    } else if function_name.contains("rustc_codegen_clr_add_interface_def") {
+                        let mut type_def = class_arg(args, &locals);
+                        let interface_asm = garg_to_dotnet_path(subst_ref[0], ctx);
+                        let interface_name = garg_to_dotnet_path(subst_ref[1], ctx);
+                        let interface =
+                            class_ref_from_path(&interface_asm, &interface_name, false, ctx);
+                        if !type_def.implements.contains(&interface) {
+                            type_def.implements.push(interface);
+                        }
+                        ComptimeLocalVar::ClassDef(Box::new(type_def))
                     } else if function_name.contains("rustc_codegen_clr_add_field_def") {
-                        let src = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-                        let mut type_def = locals[usize::from(src)].as_type_def().unwrap().clone();
-                        let tpe = crate::utilis::monomorphize(
-                            &instance,
-                            subst_ref[0].as_type().unwrap(),
-                            tcx,
-                        );
+                        let mut type_def = class_arg(args, &locals);
+                        let tpe = ctx.monomorphize(subst_ref[0].as_type().unwrap());
                         let tpe = get_type(tpe, ctx);
-                        let name = crate::utilis::garg_to_string(subst_ref[1], ctx.tcx());
-                        type_def.add_field(name.into(), tpe);
-                        ComptimeLocalVar::ClassDef(type_def)
+                        let name = garg_to_string(subst_ref[1], ctx.tcx());
+                        let name = ctx.asm_mut().alloc_string(name);
+                        type_def.fields.push((tpe, name, None));
+                        ComptimeLocalVar::ClassDef(Box::new(type_def))
                     } else if function_name.contains("rustc_codegen_clr_add_method_def") {
-                        let src = args[0]
-                            .node
-                            .place()
-                            .expect("ERROR: unuported operation in interop type definiton.")
-                            .as_local()
-                            .expect("ERROR: unuported operation in interop type definiton.");
-
-                        let mut type_def = locals[usize::from(src)].as_type_def().unwrap().clone();
-                        let fn_type = crate::utilis::monomorphize(
-                            &instance,
-                            subst_ref[3].as_type().unwrap(),
-                            ctx.tcx(),
-                        );
-                        let this_fname = crate::utilis::garg_to_string(subst_ref[2], ctx.tcx())
-                            .replace("::", ".");
-                        let def_instance = if let TyKind::FnDef(def_id, subst_ref) = fn_type.kind()
-                        {
-                            let subst_ref =
-                                crate::utilis::monomorphize(&instance, *subst_ref, ctx.tcx());
-                            let env = ParamEnv::reveal_all();
-                            let Some(instance) =
-                                Instance::try_resolve(ctx.tcx(), env, *def_id, subst_ref)
-                                    .expect("Invalid function def")
-                            else {
-                                panic!(
-                                    "ERROR: Could not get function instance. fn type:{fn_type:?}"
-                                )
-                            };
-
-                            instance
-                        } else {
-                            todo!("Trying to call a type which is not a function definition!");
+                        let mut type_def = class_arg(args, &locals);
+                        let access = method_access(&garg_to_string(subst_ref[0], ctx.tcx()));
+                        let modifiers = garg_to_string(subst_ref[1], ctx.tcx());
+                        let kind = match modifiers.as_ref() {
+                            "virtual" => MethodKind::Virtual,
+                            "static" => MethodKind::Static,
+                            "" | "instance" => MethodKind::Instance,
+                            _ => panic!("ERROR: unsuported method modifiers {modifiers:?} in interop type definiton."),
                         };
-                        let call_info = CallInfo::sig_from_instance_(def_instance, ctx);
-                        let target_function_name =
-                            crate::utilis::function_name(ctx.tcx().symbol_name(def_instance));
-                        let call_site = CallSite::new(
-                            None,
-                            target_function_name,
-                            call_info.sig().clone(),
-                            true,
-                        );
-
-                        let method = Method::alias_for(
-                            cilly::access_modifier::AccessModifer::Public,
-                            // Only virtuals for now
-                            cilly::method::MethodType::Virtual,
-                            this_fname.into(),
-                            call_site,
-                        );
-                        type_def.add_method(method);
-
-                        //type_def.add_method(name.into(), tpe);
-                        ComptimeLocalVar::ClassDef(type_def)
+                        let name = garg_to_dotnet_path(subst_ref[2], ctx);
+                        let name = ctx.asm_mut().alloc_string(name);
+                        let target = forward_target(subst_ref[3], ctx);
+                        type_def.methods.push(ComptimeMethod {
+                            access,
+                            kind,
+                            name,
+                            target,
+                        });
+                        ComptimeLocalVar::ClassDef(Box::new(type_def))
+                    } else if function_name.contains("rustc_codegen_clr_add_ctor_def") {
+                        let mut type_def = class_arg(args, &locals);
+                        let access = method_access(&garg_to_string(subst_ref[0], ctx.tcx()));
+                        let name = ctx.asm_mut().alloc_string(".ctor");
+                        let target = forward_target(subst_ref[1], ctx);
+                        type_def.methods.push(ComptimeMethod {
+                            access,
+                            kind: MethodKind::Constructor,
+                            name,
+                            target,
+                        });
+                        ComptimeLocalVar::ClassDef(Box::new(type_def))
                     } else {
                         todo!("Can't yet call the rustc_codegen_clr comptime interop fn named {function_name:?}")
                     };
                     block_id = target.unwrap();
                 }
+                TerminatorKind::Goto { target } => block_id = *target,
                 TerminatorKind::Return => return,
                 _ => todo!("can't interpret the term {term:?} yet in rustc_codegen_clr comptime"),
             },
             None => panic!("Terminatorless block"),
         }
-    } */
+    }
+}
+/// Converts the visibility of an interop method(eg. `pub`) into its .NET access modifier. Public methods may be called by .NET code, so they are never removed as dead code.
+fn method_access(vis: &str) -> Access {
+    match vis {
+        "pub" => Access::Extern,
+        _ => Access::Private,
+    }
 }
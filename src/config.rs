@@ -24,11 +24,10 @@ macro_rules! config_flag{
         }
     }
 }
-config_flag! {ABORT_ON_ERROR,false,"Should the codegen stop working when ecountering an error, or try to press on, replacing unusuported code with exceptions throws?"}
+config_flag! {ABORT_ON_ERROR,false,"Should codegen errors be reported as hard errors, or as warnings, replacing unusuported code with exceptions throws?"}
 
 config_flag! {NO_UNWIND,false,"Tells the codegen to never emmit try/catch statements."}
 
-config_flag! {INLINE_SIMPLE_FUNCTIONS,false,"Allows the optimizer to inline very simple functions. It is buggy."}
 config_flag! {REMOVE_UNSUED_LOCALS,false,"Turns on the local removal optimization."}
 config_flag! {CHECK_ALLOCATIONS,false,"Turns on allocation checks/debug info."}
 config_flag! {VERIFY_METHODS,false,"Typechecks all methods"}
@@ -57,9 +56,11 @@ lazy_static! {
 config_flag! {TEST_WITH_MONO,false,"Tells the codegen to use the mono runtime for tests."}
 
 config_flag! {JS_MODE,false,"Tells the codegen to emmit JS source files."}
+config_flag! {JAVA_MODE,false,"Tells the codegen to emmit Java source files."}
 
 config_flag! {C_MODE,false,"Tells the codegen to emmit C source files."}
 config_flag! {C_SANITIZE,false,"Tells the codegen sanitize C."}
+config_flag! {DIRECT_PE,false,"Tells the linker to write the .NET assembly directly, without invoking ilasm."}
 
 config_flag! {RANDOMIZE_LAYOUT,false,"Tells the codegen to randomize TEST type layout."}
 lazy_static! {
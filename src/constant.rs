@@ -1,6 +1,4 @@
-use core::f16;
-
-use crate::{assembly::MethodCompileCtx, r#type::get_type};
+use crate::{assembly::MethodCompileCtx, r#type::get_type, unsupported};
 
 use cilly::{
     call,
@@ -219,7 +217,21 @@ fn load_scalar_ptr(
                 CallSite::new(None, function_name, call_info.sig().clone(), true).into(),
             );
         }
-        GlobalAlloc::VTable(..) => todo!("Unhandled global alloc {global_alloc:?}"),
+        GlobalAlloc::VTable(ty, trait_ref) => {
+            // A vtable is just constant data, stored in its own memory allocation.
+            let vtable = ctx.tcx().vtable_allocation((ty, trait_ref));
+            let vtable_ptr = CILNode::LoadGlobalAllocPtr {
+                alloc_id: crate::utilis::alloc_id_to_u64(vtable),
+            };
+            if offset.bytes() != 0 {
+                CILNode::Add(
+                    vtable_ptr.into(),
+                    CILNode::ZeroExtendToUSize(CILNode::LdcU64(offset.bytes()).into()).into(),
+                )
+            } else {
+                vtable_ptr
+            }
+        }
     }
     //panic!("alloc_id:{alloc_id:?}")
 }
@@ -287,7 +299,7 @@ fn load_const_scalar<'tcx>(
                 }
             }
         }
-        TyKind::Adt(_, _) | TyKind::Closure(_, _) => CILNode::LdObj {
+        TyKind::Adt(_, _) | TyKind::Closure(_, _) | TyKind::Coroutine(_, _) => CILNode::LdObj {
             ptr: Box::new(
                 CILNode::PointerToConstValue(Box::new(scalar_u128))
                     .cast_ptr(ctx.asm_mut().nptr(scalar_type)),
@@ -295,32 +307,23 @@ fn load_const_scalar<'tcx>(
             obj: scalar_type.into(),
         },
         TyKind::Char => CILNode::LdcU32(u32::try_from(scalar_u128).unwrap()),
-        _ => todo!("Can't load scalar constants of type {scalar_ty:?}!"),
+        _ => unsupported!("Can't load scalar constants of type {scalar_ty:?}!"),
     }
 }
 fn load_const_float(value: u128, float_type: FloatTy, asm: &mut Assembly) -> CILNode {
     match float_type {
-        FloatTy::F16 => {
-            #[cfg(not(target_family = "windows"))]
-            {
-                call!(
-                    CallSite::new_extern(
-                        ClassRef::half(asm),
-                        "op_Explicit".into(),
-                        FnSig::new(Box::new([Type::Float(Float::F32)]), Type::Float(Float::F16)),
-                        true
-                    ),
-                    [CILNode::LdcF32(HashableF32(
-                        (f16::from_ne_bytes((u16::try_from(value).unwrap()).to_ne_bytes())) as f32
-                    ),)]
-                )
-            }
-            #[cfg(target_family = "windows")]
-            {
-                todo!("building a program using 16 bit floats is not supported on windwows yet")
-                // TODO: check if this still causes a linker error on windows
-            }
-        }
+        // The bits are reinterpreted as a `System.Half`, so the host does not need to support `f16`.
+        FloatTy::F16 => call!(
+            CallSite::new_extern(
+                ClassRef::bit_converter(asm),
+                "Int16BitsToHalf".into(),
+                FnSig::new(Box::new([Type::Int(Int::I16)]), Type::Float(Float::F16)),
+                true
+            ),
+            [CILNode::LdcI16(i16::from_ne_bytes(
+                u16::try_from(value).unwrap().to_ne_bytes()
+            ))]
+        ),
         FloatTy::F32 => {
             let value = f32::from_ne_bytes((u32::try_from(value).unwrap()).to_ne_bytes());
             CILNode::LdcF32(HashableF32(value))
@@ -564,7 +567,9 @@ fn get_fn_from_static_name(name: &str, ctx: &mut MethodCompileCtx<'_, '_>) -> Ca
             true,
         ),
         _ => {
-            todo!("Unsuported function refered to using a weak static. Function name is {name:?}.")
+            unsupported!(
+                "Unsuported function refered to using a weak static. Function name is {name:?}."
+            )
         }
     }
 }
@@ -0,0 +1,716 @@
+//! Exports Rust functions as a .NET-friendly public API.
+//!
+//! A function marked with `#[dotnet::export(class = "Foo.Bar", name = "Baz")]` gets a public static wrapper method `Baz` in the class `Foo.Bar`.
+//! The wrapper takes .NET types, converts them to the types the Rust function expects, and calls it. Supported types:
+//! 1. Integers, floats, `bool` and managed classes are passed as-is.
+//! 2. `&str` is exposed as `System.String`. The string is converted to UTF-8 before the call, and can be returned too.
+//! 3. `&[T]` and `&mut [T]`, where `T` is an integer, a float or `bool`, are exposed as `T[]`. The array is pinned for the duration of the call.
+//!    Functions taking slices also get an overload taking `ReadOnlySpan<T>` for `&[T]` and `Span<T>` for `&mut [T]`. A span may point into
+//!    the managed heap, and can't be pinned without a `fixed` local, so its elements are copied into unmanaged memory for the call(and copied
+//!    back for `&mut [T]`).
+//!
+//! Rust references can't be null, so passing `null` as a string or an array throws an `ArgumentNullException`. The UTF-8 copies
+//! of strings and spans are freed, and arrays unpinned, even if the Rust function throws. Functions using any other type in their signature
+//! are reported as errors.
+use crate::{assembly::MethodCompileCtx, r#type::get_type, utilis::adt_name, IString};
+use cilly::{
+    access_modifier::AccessModifer,
+    basic_block::{BasicBlock, Handler},
+    call,
+    call_site::CallSite,
+    call_virt,
+    cil_node::{CILNode, CallOpArgs},
+    cil_root::CILRoot,
+    cil_tree::CILTree,
+    conv_usize,
+    field_desc::FieldDescriptor,
+    method::{Method, MethodType},
+    size_of,
+    v2::{tpe::GenericKind, Access, ClassDef, ClassRef, ClassRefIdx, FnSig, Int, MethodDef},
+    Type,
+};
+use rustc_middle::{
+    mir::Body,
+    ty::{Ty, TyKind},
+};
+use rustc_span::Symbol;
+use std::num::NonZeroU8;
+/// Pinned handles are `GCHandleType.Pinned`.
+const GC_HANDLE_PINNED: i32 = 3;
+/// How a parameter or return value of an exported function is exposed to .NET code.
+#[derive(Clone, Copy)]
+enum ExportedType {
+    /// The .NET type is the same as the Rust type.
+    Direct(Type),
+    /// `&str`, exposed as `System.String`. Contains the fat pointer type of the Rust string.
+    Str(ClassRefIdx),
+    /// `&[T]` or `&mut [T]`, exposed as `T[]` or as a span. Contains the fat pointer type of the Rust slice, and the element type.
+    Slice {
+        fat_ptr: ClassRefIdx,
+        elem: Type,
+        mutable: bool,
+    },
+}
+impl ExportedType {
+    fn dotnet_type(&self, spans: bool, ctx: &mut MethodCompileCtx<'_, '_>) -> Type {
+        match self {
+            Self::Direct(tpe) => *tpe,
+            Self::Str(_) => Type::PlatformString,
+            Self::Slice { elem, mutable, .. } if spans => {
+                Type::ClassRef(span_type(*elem, *mutable, ctx))
+            }
+            Self::Slice { elem, .. } => Type::PlatformArray {
+                elem: ctx.asm_mut().alloc_type(*elem),
+                dims: NonZeroU8::new(1).unwrap(),
+            },
+        }
+    }
+}
+/// Returns `Span<elem>` if `mutable`, or `ReadOnlySpan<elem>` otherwise.
+fn span_type(elem: Type, mutable: bool, ctx: &mut MethodCompileCtx<'_, '_>) -> ClassRefIdx {
+    let name = if mutable {
+        "System.Span"
+    } else {
+        "System.ReadOnlySpan"
+    };
+    let name = ctx.asm_mut().alloc_string(name);
+    let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+    ctx.asm_mut()
+        .alloc_class_ref(ClassRef::new(name, Some(runtime), true, [elem].into()))
+}
+/// Returns how `ty` is exposed to .NET code, or an error message if it can't be used in the signature of an exported function.
+fn exported_type<'tcx>(
+    ty: Ty<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> Result<ExportedType, String> {
+    let unsupported =
+        || format!("The type `{ty}` can't be used in the signature of an exported function.");
+    Ok(match ty.kind() {
+        TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) | TyKind::Bool => {
+            ExportedType::Direct(get_type(ty, ctx))
+        }
+        TyKind::Adt(def, subst)
+            if adt_name(*def, ctx.tcx(), subst).contains(crate::r#type::INTEROP_CLASS_TPE_NAME) =>
+        {
+            ExportedType::Direct(get_type(ty, ctx))
+        }
+        TyKind::Ref(_, inner, mutability) => {
+            let fat_ptr = get_type(ty, ctx).as_class_ref();
+            match (inner.kind(), fat_ptr) {
+                (TyKind::Str, Some(fat_ptr)) => ExportedType::Str(fat_ptr),
+                (TyKind::Slice(elem), Some(fat_ptr))
+                    if matches!(
+                        elem.kind(),
+                        TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(_) | TyKind::Bool
+                    ) =>
+                {
+                    ExportedType::Slice {
+                        fat_ptr,
+                        elem: get_type(*elem, ctx),
+                        mutable: mutability.is_mut(),
+                    }
+                }
+                _ => return Err(unsupported()),
+            }
+        }
+        _ => return Err(unsupported()),
+    })
+}
+/// Reports an error about the exported function being compiled, pointing at its definition.
+fn export_error(msg: String, ctx: &MethodCompileCtx<'_, '_>) {
+    let span = ctx.tcx().def_span(ctx.instance().def_id());
+    ctx.tcx().dcx().struct_span_err(span, msg).emit();
+}
+/// Returns the class and the method name a function with the `#[dotnet::export]` attribute should be exported as.
+/// Returns `Ok(None)` if the function is not exported, and an error message if the attribute is malformed.
+fn export_target(ctx: &MethodCompileCtx<'_, '_>) -> Result<Option<(IString, IString)>, String> {
+    let def_id = ctx.instance().def_id();
+    let path = [Symbol::intern("dotnet"), Symbol::intern("export")];
+    let Some(attr) = ctx.tcx().get_attrs_by_path(def_id, &path).next() else {
+        return Ok(None);
+    };
+    let mut class = None;
+    let mut name = None;
+    for item in attr.meta_item_list().unwrap_or_default() {
+        let key = item.ident().map(|ident| ident.name);
+        match (key.as_ref().map(Symbol::as_str), item.value_str()) {
+            (Some("class"), Some(value)) => class = Some(value.as_str().into()),
+            (Some("name"), Some(value)) => name = Some(value.as_str().into()),
+            _ => return Err(format!("Invalid argument {item:?} of the `dotnet::export` attribute. Expected `class = \"Namespace.Class\"` or `name = \"Method\"`.")),
+        }
+    }
+    let Some(class) = class else {
+        return Err(
+            "The `dotnet::export` attribute requires the class to export the function in.".into(),
+        );
+    };
+    let name = name.unwrap_or_else(|| ctx.tcx().item_name(def_id).as_str().into());
+    Ok(Some((class, name)))
+}
+/// Emits the wrapper of the function `rust_name`, if it was marked with `#[dotnet::export]`.
+pub fn export_fn<'tcx>(
+    rust_name: &str,
+    sig: &FnSig,
+    mir: &Body<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) {
+    let (class_name, method_name) = match export_target(ctx) {
+        Ok(Some(target)) => target,
+        Ok(None) => return,
+        Err(msg) => return export_error(msg, ctx),
+    };
+    if !ctx.instance().args.is_empty() {
+        return export_error(
+            format!("Generic function {rust_name} can't be exported."),
+            ctx,
+        );
+    }
+    if mir.spread_arg.is_some() || sig.inputs().len() != mir.arg_count {
+        return export_error(
+            format!("Function {rust_name} can't be exported: it has hidden arguments."),
+            ctx,
+        );
+    }
+    let output = if *sig.output() == Type::Void {
+        None
+    } else {
+        match exported_type(ctx.monomorphize(mir.return_ty()), ctx) {
+            Ok(ExportedType::Slice { .. }) => {
+                return export_error(
+                    format!(
+                    "Function {rust_name} can't be exported: returning slices is not supported."
+                ),
+                    ctx,
+                )
+            }
+            Ok(output) => Some(output),
+            Err(msg) => return export_error(msg, ctx),
+        }
+    };
+    let mut has_slices = false;
+    for local in mir.args_iter() {
+        match exported_type(ctx.monomorphize(mir.local_decls[local].ty), ctx) {
+            Ok(exported) => has_slices |= matches!(exported, ExportedType::Slice { .. }),
+            Err(msg) => return export_error(msg, ctx),
+        }
+    }
+    let class = export_class(&class_name, ctx);
+    export_wrapper(rust_name, sig, mir, class, &method_name, output, false, ctx);
+    if has_slices {
+        export_wrapper(rust_name, sig, mir, class, &method_name, output, true, ctx);
+    }
+}
+/// Emits a wrapper calling the Rust function `rust_name`. If `spans` is set, slices are passed as spans instead of arrays.
+#[allow(clippy::too_many_arguments)]
+fn export_wrapper<'tcx>(
+    rust_name: &str,
+    sig: &FnSig,
+    mir: &Body<'tcx>,
+    class: cilly::v2::ClassDefIdx,
+    method_name: &str,
+    output: Option<ExportedType>,
+    spans: bool,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) {
+    let marshal = ClassRef::marshal(ctx.asm_mut());
+    let native_memory = ctx
+        .asm_mut()
+        .alloc_string("System.Runtime.InteropServices.NativeMemory");
+    let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+    let native_memory = ctx.asm_mut().alloc_class_ref(ClassRef::new(
+        native_memory,
+        Some(runtime),
+        false,
+        [].into(),
+    ));
+    let gc_handle = ClassRef::gc_handle(ctx.asm_mut());
+    let gc_handle_ref = ctx.asm_mut().nref(Type::ClassRef(gc_handle));
+    let void_ptr = ctx.asm_mut().nptr(Type::Void);
+    let data_ptr = |fat_ptr| FieldDescriptor::new(fat_ptr, void_ptr, crate::DATA_PTR.into());
+    let metadata =
+        |fat_ptr| FieldDescriptor::new(fat_ptr, Type::Int(Int::USize), crate::METADATA.into());
+    let mut inputs = Vec::with_capacity(mir.arg_count);
+    let mut arg_names = Vec::with_capacity(mir.arg_count);
+    let mut locals: Vec<(Option<IString>, Type)> = Vec::new();
+    let mut call_args = Vec::with_capacity(mir.arg_count);
+    // Rejects null arguments. This runs before any argument is converted, so nothing has to be freed if it throws.
+    let mut null_checks: Vec<CILTree> = Vec::new();
+    // Converts the .NET arguments to Rust ones.
+    let mut prologue: Vec<CILTree> = Vec::new();
+    // Frees the resources used by the converted arguments.
+    let mut epilogue: Vec<CILTree> = Vec::new();
+    for (arg, local) in mir.args_iter().enumerate() {
+        let ty = ctx.monomorphize(mir.local_decls[local].ty);
+        let exported = exported_type(ty, ctx).expect("Arguments are checked by `export_fn`.");
+        let arg_name: IString = mir
+            .var_debug_info
+            .iter()
+            .find(|info| {
+                matches!(info.value, rustc_middle::mir::VarDebugInfoContents::Place(place) if place.local == local && place.projection.is_empty())
+            })
+            .map_or_else(|| format!("arg{arg}").into(), |info| info.name.as_str().into());
+        inputs.push(exported.dotnet_type(spans, ctx));
+        let arg = u32::try_from(arg).unwrap();
+        match exported {
+            ExportedType::Str(_) => {
+                null_checks.push(throw_if_null(CILNode::LDArg(arg), &arg_name, ctx));
+            }
+            ExportedType::Slice { .. } if !spans => {
+                null_checks.push(throw_if_null(CILNode::LDArg(arg), &arg_name, ctx));
+            }
+            _ => (),
+        }
+        match exported {
+            ExportedType::Direct(_) => call_args.push(CILNode::LDArg(arg)),
+            ExportedType::Str(fat_ptr) => {
+                let utf8 = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_utf8").into()),
+                    Type::Int(Int::ISize),
+                ));
+                let fat = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_str").into()),
+                    Type::ClassRef(fat_ptr),
+                ));
+                let to_utf8 = CallSite::new(
+                    Some(marshal),
+                    "StringToCoTaskMemUTF8".into(),
+                    FnSig::new([Type::PlatformString].into(), Type::Int(Int::ISize)),
+                    true,
+                );
+                prologue.push(
+                    CILRoot::STLoc {
+                        local: utf8,
+                        tree: call!(to_utf8, [CILNode::LDArg(arg)]),
+                    }
+                    .into(),
+                );
+                prologue.push(
+                    CILRoot::set_field(
+                        CILNode::LDLocA(fat),
+                        CILNode::LDLoc(utf8).cast_ptr(void_ptr),
+                        data_ptr(fat_ptr),
+                    )
+                    .into(),
+                );
+                let len = utf8_byte_count(CILNode::LDArg(arg), ctx);
+                prologue.push(
+                    CILRoot::set_field(CILNode::LDLocA(fat), conv_usize!(len), metadata(fat_ptr))
+                        .into(),
+                );
+                let free = CallSite::new(
+                    Some(marshal),
+                    "FreeCoTaskMem".into(),
+                    FnSig::new([Type::Int(Int::ISize)].into(), Type::Void),
+                    true,
+                );
+                epilogue.push(
+                    CILRoot::Call {
+                        site: Box::new(free),
+                        args: [CILNode::LDLoc(utf8)].into(),
+                    }
+                    .into(),
+                );
+                call_args.push(CILNode::LDLoc(fat));
+            }
+            ExportedType::Slice {
+                fat_ptr,
+                elem,
+                mutable,
+            } if spans => {
+                let span = span_type(elem, mutable, ctx);
+                let native_span = span_type(elem, true, ctx);
+                let buf = u32::try_from(locals.len()).unwrap();
+                locals.push((Some(format!("{arg_name}_buf").into()), void_ptr));
+                let native = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_native").into()),
+                    Type::ClassRef(native_span),
+                ));
+                let fat = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_slice").into()),
+                    Type::ClassRef(fat_ptr),
+                ));
+                let span_ref = ctx.asm_mut().nref(Type::ClassRef(span));
+                let get_len = CallSite::new(
+                    Some(span),
+                    "get_Length".into(),
+                    FnSig::new([span_ref].into(), Type::Int(Int::I32)),
+                    false,
+                );
+                let len = || call!(get_len.clone(), [CILNode::LDArgA(arg)]);
+                let alloc = CallSite::new(
+                    Some(native_memory),
+                    "Alloc".into(),
+                    FnSig::new([Type::Int(Int::USize)].into(), void_ptr),
+                    true,
+                );
+                let byte_count = CILNode::Mul(
+                    Box::new(conv_usize!(len())),
+                    Box::new(conv_usize!(size_of!(elem))),
+                );
+                prologue.push(
+                    CILRoot::STLoc {
+                        local: buf,
+                        tree: call!(alloc, [byte_count]),
+                    }
+                    .into(),
+                );
+                let native_span_ref = ctx.asm_mut().nref(Type::ClassRef(native_span));
+                let native_ctor = CallSite::new(
+                    Some(native_span),
+                    ".ctor".into(),
+                    FnSig::new(
+                        [native_span_ref, void_ptr, Type::Int(Int::I32)].into(),
+                        Type::Void,
+                    ),
+                    false,
+                );
+                prologue.push(
+                    CILRoot::STLoc {
+                        local: native,
+                        tree: CILNode::NewObj(Box::new(CallOpArgs {
+                            site: Box::new(native_ctor),
+                            args: [CILNode::LDLoc(buf), len()].into(),
+                        })),
+                    }
+                    .into(),
+                );
+                prologue.push(
+                    CILRoot::Call {
+                        site: Box::new(span_copy_to(span, ctx)),
+                        args: [CILNode::LDArgA(arg), CILNode::LDLoc(native)].into(),
+                    }
+                    .into(),
+                );
+                prologue.push(
+                    CILRoot::set_field(
+                        CILNode::LDLocA(fat),
+                        CILNode::LDLoc(buf),
+                        data_ptr(fat_ptr),
+                    )
+                    .into(),
+                );
+                prologue.push(
+                    CILRoot::set_field(CILNode::LDLocA(fat), conv_usize!(len()), metadata(fat_ptr))
+                        .into(),
+                );
+                // Changes made by Rust code are copied back into the span passed by the caller.
+                if mutable {
+                    epilogue.push(
+                        CILRoot::Call {
+                            site: Box::new(span_copy_to(native_span, ctx)),
+                            args: [CILNode::LDLocA(native), CILNode::LDArg(arg)].into(),
+                        }
+                        .into(),
+                    );
+                }
+                let free = CallSite::new(
+                    Some(native_memory),
+                    "Free".into(),
+                    FnSig::new([void_ptr].into(), Type::Void),
+                    true,
+                );
+                epilogue.push(
+                    CILRoot::Call {
+                        site: Box::new(free),
+                        args: [CILNode::LDLoc(buf)].into(),
+                    }
+                    .into(),
+                );
+                call_args.push(CILNode::LDLoc(fat));
+            }
+            ExportedType::Slice { fat_ptr, .. } => {
+                let handle = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_handle").into()),
+                    Type::ClassRef(gc_handle),
+                ));
+                let fat = u32::try_from(locals.len()).unwrap();
+                locals.push((
+                    Some(format!("{arg_name}_slice").into()),
+                    Type::ClassRef(fat_ptr),
+                ));
+                let handle_type = ctx
+                    .asm_mut()
+                    .alloc_string("System.Runtime.InteropServices.GCHandleType");
+                let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+                let handle_type = ctx.asm_mut().alloc_class_ref(ClassRef::new(
+                    handle_type,
+                    Some(runtime),
+                    true,
+                    [].into(),
+                ));
+                let alloc = CallSite::new(
+                    Some(gc_handle),
+                    "Alloc".into(),
+                    FnSig::new(
+                        [Type::PlatformObject, Type::ClassRef(handle_type)].into(),
+                        Type::ClassRef(gc_handle),
+                    ),
+                    true,
+                );
+                prologue.push(
+                    CILRoot::STLoc {
+                        local: handle,
+                        tree: call!(
+                            alloc,
+                            [CILNode::LDArg(arg), CILNode::LdcI32(GC_HANDLE_PINNED)]
+                        ),
+                    }
+                    .into(),
+                );
+                let addr = CallSite::new(
+                    Some(gc_handle),
+                    "AddrOfPinnedObject".into(),
+                    FnSig::new([gc_handle_ref].into(), Type::Int(Int::ISize)),
+                    false,
+                );
+                prologue.push(
+                    CILRoot::set_field(
+                        CILNode::LDLocA(fat),
+                        call!(addr, [CILNode::LDLocA(handle)]).cast_ptr(void_ptr),
+                        data_ptr(fat_ptr),
+                    )
+                    .into(),
+                );
+                prologue.push(
+                    CILRoot::set_field(
+                        CILNode::LDLocA(fat),
+                        conv_usize!(CILNode::LDLen {
+                            arr: Box::new(CILNode::LDArg(arg)),
+                        }),
+                        metadata(fat_ptr),
+                    )
+                    .into(),
+                );
+                let free = CallSite::new(
+                    Some(gc_handle),
+                    "Free".into(),
+                    FnSig::new([gc_handle_ref].into(), Type::Void),
+                    false,
+                );
+                epilogue.push(
+                    CILRoot::Call {
+                        site: Box::new(free),
+                        args: [CILNode::LDLocA(handle)].into(),
+                    }
+                    .into(),
+                );
+                call_args.push(CILNode::LDLoc(fat));
+            }
+        }
+        arg_names.push(Some(arg_name));
+    }
+    let rust_fn = CallSite::new(None, rust_name.into(), sig.clone(), true);
+    let mut call = Vec::new();
+    let (ret, output) = match output {
+        None => {
+            call.push(
+                CILRoot::Call {
+                    site: Box::new(rust_fn),
+                    args: call_args.into(),
+                }
+                .into(),
+            );
+            (CILRoot::VoidRet, Type::Void)
+        }
+        Some(exported) => {
+            let ret = u32::try_from(locals.len()).unwrap();
+            locals.push((Some("ret".into()), *sig.output()));
+            call.push(
+                CILRoot::STLoc {
+                    local: ret,
+                    tree: call!(rust_fn, call_args),
+                }
+                .into(),
+            );
+            match exported {
+                ExportedType::Direct(tpe) => (
+                    CILRoot::Ret {
+                        tree: CILNode::LDLoc(ret),
+                    },
+                    tpe,
+                ),
+                ExportedType::Str(fat_ptr) => {
+                    let from_utf8 = CallSite::new(
+                        Some(marshal),
+                        "PtrToStringUTF8".into(),
+                        FnSig::new(
+                            [Type::Int(Int::ISize), Type::Int(Int::I32)].into(),
+                            Type::PlatformString,
+                        ),
+                        true,
+                    );
+                    let ptr = CILNode::LDField {
+                        addr: Box::new(CILNode::LDLocA(ret)),
+                        field: Box::new(data_ptr(fat_ptr)),
+                    };
+                    let len = CILNode::ConvI32(Box::new(CILNode::LDField {
+                        addr: Box::new(CILNode::LDLocA(ret)),
+                        field: Box::new(metadata(fat_ptr)),
+                    }));
+                    (
+                        CILRoot::Ret {
+                            tree: call!(from_utf8, [ptr, len]),
+                        },
+                        Type::PlatformString,
+                    )
+                }
+                ExportedType::Slice { .. } => unreachable!("Slice returns are rejected above."),
+            }
+        }
+    };
+    let mut trees = null_checks;
+    trees.extend(prologue);
+    let blocks = if epilogue.is_empty() {
+        trees.extend(call);
+        trees.push(ret.into());
+        vec![BasicBlock::new(trees, 0, None)]
+    } else {
+        // The call is protected, so that the epilogue runs before an exception leaves the wrapper(like a `finally` block).
+        trees.push(
+            CILRoot::GoTo {
+                target: 1,
+                sub_target: 0,
+            }
+            .into(),
+        );
+        call.push(
+            CILRoot::JumpingPad {
+                source: 1,
+                target: 2,
+            }
+            .into(),
+        );
+        let mut cleanup = epilogue.clone();
+        cleanup.push(CILRoot::ReThrow.into());
+        epilogue.push(ret.into());
+        vec![
+            BasicBlock::new(trees, 0, None),
+            BasicBlock::new(
+                call,
+                1,
+                Some(Handler::Blocks(vec![BasicBlock::new(cleanup, 3, None)])),
+            ),
+            BasicBlock::new(epilogue, 2, None),
+        ]
+    };
+    let method = Method::new(
+        AccessModifer::Extern,
+        MethodType::Static,
+        FnSig::new(inputs.into(), output),
+        method_name,
+        locals,
+        blocks,
+        arg_names,
+    );
+    let mut method = MethodDef::from_v1(&method, ctx.asm_mut(), class);
+    let doc = doc_comment(ctx).map(|doc| ctx.asm_mut().alloc_string(doc));
+    method.set_doc(doc);
+    ctx.asm_mut().new_method(method);
+}
+/// Returns the rustdoc comment of the function being compiled, which is then included in API descriptions(like C# facades).
+fn doc_comment(ctx: &MethodCompileCtx<'_, '_>) -> Option<String> {
+    let lines: Vec<String> = ctx
+        .tcx()
+        .get_attrs(ctx.instance().def_id(), rustc_span::sym::doc)
+        .filter_map(|attr| attr.doc_str())
+        .map(|line| {
+            let line = line.as_str();
+            line.strip_prefix(' ').unwrap_or(line).to_owned()
+        })
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+/// Returns `span.CopyTo(Span<T> destination)`, where `span` is a `Span<T>` or a `ReadOnlySpan<T>`.
+fn span_copy_to(span: ClassRefIdx, ctx: &mut MethodCompileCtx<'_, '_>) -> CallSite {
+    let span_ref = ctx.asm_mut().nref(Type::ClassRef(span));
+    let destination = ctx.asm_mut().alloc_string("System.Span");
+    let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+    let destination = ctx.asm_mut().alloc_class_ref(ClassRef::new(
+        destination,
+        Some(runtime),
+        true,
+        [Type::PlatformGeneric(0, GenericKind::TypeGeneric)].into(),
+    ));
+    CallSite::new(
+        Some(span),
+        "CopyTo".into(),
+        FnSig::new([span_ref, Type::ClassRef(destination)].into(), Type::Void),
+        false,
+    )
+}
+/// Calls `System.ArgumentNullException.ThrowIfNull(arg, name)`.
+fn throw_if_null(arg: CILNode, name: &str, ctx: &mut MethodCompileCtx<'_, '_>) -> CILTree {
+    let exception = ctx.asm_mut().alloc_string("System.ArgumentNullException");
+    let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+    let exception =
+        ctx.asm_mut()
+            .alloc_class_ref(ClassRef::new(exception, Some(runtime), false, [].into()));
+    let throw_if_null = CallSite::new(
+        Some(exception),
+        "ThrowIfNull".into(),
+        FnSig::new(
+            [Type::PlatformObject, Type::PlatformString].into(),
+            Type::Void,
+        ),
+        true,
+    );
+    CILRoot::Call {
+        site: Box::new(throw_if_null),
+        args: [arg, CILNode::LdStr(name.into())].into(),
+    }
+    .into()
+}
+/// Calls `System.Text.Encoding.UTF8.GetByteCount(string)`.
+fn utf8_byte_count(string: CILNode, ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let encoding = ctx.asm_mut().alloc_string("System.Text.Encoding");
+    let runtime = ctx.asm_mut().alloc_string("System.Runtime");
+    let encoding =
+        ctx.asm_mut()
+            .alloc_class_ref(ClassRef::new(encoding, Some(runtime), false, [].into()));
+    let get_utf8 = CallSite::new(
+        Some(encoding),
+        "get_UTF8".into(),
+        FnSig::new([].into(), Type::ClassRef(encoding)),
+        true,
+    );
+    let byte_count = CallSite::new(
+        Some(encoding),
+        "GetByteCount".into(),
+        FnSig::new(
+            [Type::ClassRef(encoding), Type::PlatformString].into(),
+            Type::Int(Int::I32),
+        ),
+        false,
+    );
+    call_virt!(byte_count, [call!(get_utf8, []), string])
+}
+/// Returns the public class exported functions are placed in, defining it if needed.
+fn export_class(name: &str, ctx: &mut MethodCompileCtx<'_, '_>) -> cilly::v2::ClassDefIdx {
+    let name = ctx.asm_mut().alloc_string(name);
+    let cref = ctx
+        .asm_mut()
+        .alloc_class_ref(ClassRef::new(name, None, false, [].into()));
+    if let Some(def) = ctx.asm().class_ref_to_def(cref) {
+        return def;
+    }
+    let object = ClassRef::object(ctx.asm_mut());
+    ctx.asm_mut().class_def(ClassDef::new(
+        name,
+        false,
+        0,
+        Some(object),
+        vec![],
+        vec![],
+        Access::Extern,
+        None,
+    ))
+}
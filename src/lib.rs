@@ -128,6 +128,8 @@ mod constant;
 
 /// Implementation of compiletime features neccessary for interop.
 mod comptime;
+/// Wrappers exposing Rust functions marked with `#[dotnet::export]` as a .NET API.
+mod export;
 /// Signature of a function (inputs)->output
 pub mod function_sig;
 /// Interop type handling.
@@ -206,12 +208,17 @@ impl CodegenBackend for MyBackend {
             let mut asm = Assembly::empty();
             let _ = cilly::utilis::get_environ(&mut asm);
 
+            let mut stubbed = codegen_error::StubbedFunctions::default();
             for cgu in cgus {
                 //println!("codegen {} has {} items.", cgu.name(), cgu.items().len());
                 for (item, _data) in cgu.items() {
-                    assembly::add_item(&mut asm, *item, tcx).expect("Could not add function");
+                    assembly::add_item(&mut asm, *item, tcx, &mut stubbed)
+                        .expect("Could not add function");
                 }
             }
+            stubbed.report(tcx);
+            // With `ABORT_ON_ERROR`, codegen errors are hard errors.
+            tcx.dcx().abort_if_errors();
 
             if let Some((entrypoint, _kind)) = tcx.entry_fn(()) {
                 let penv = rustc_middle::ty::ParamEnv::reveal_all();
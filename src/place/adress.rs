@@ -3,6 +3,7 @@ use crate::{
     assembly::MethodCompileCtx,
     assert_morphic,
     r#type::{fat_ptr_to, pointer_to_is_fat},
+    unsupported,
 };
 use cilly::{
     call,
@@ -109,6 +110,9 @@ fn field_address<'a>(
                 crate::r#type::pointer_to_is_fat(curr_type, ctx.tcx(), ctx.instance()),
                 crate::r#type::pointer_to_is_fat(field_ty, ctx.tcx(), ctx.instance()),
             ) {
+                (false, false) if crate::r#type::simd_vector(curr_type, ctx).is_some() => {
+                    super::simd_field_address(curr_type, field_index, field_ty, addr_calc, ctx)
+                }
                 (false, false) => {
                     let field_desc = crate::utilis::field_descrptor(curr_type, field_index, ctx);
                 CILNode::LDFieldAdress {
@@ -245,7 +249,7 @@ pub fn place_elem_adress<'tcx>(
                     )
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! {unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -358,7 +362,7 @@ pub fn place_elem_adress<'tcx>(
                     let array_type = ctx.type_from_cache(curr_ty);
                     let array_dotnet = array_type.as_class_ref().expect("Non array type");
                     if *from_end {
-                        todo!("Can't index array from end!");
+                        unsupported!("Can't index array from end!");
                     } else {
                         call!(
                             CallSite::new(
@@ -378,12 +382,12 @@ pub fn place_elem_adress<'tcx>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
         _ => {
-            rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't handle porojection {place_elem:?} in adress")}
+            rustc_middle::ty::print::with_no_trimmed_paths! {unsupported!("Can't handle porojection {place_elem:?} in adress")}
         }
     }
 }
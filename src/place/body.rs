@@ -5,6 +5,7 @@ use crate::{
     assert_morphic,
     place::{body_ty_is_by_adress, deref_op},
     r#type::fat_ptr_to,
+    unsupported,
 };
 use cilly::{
     call,
@@ -43,6 +44,14 @@ fn body_field<'a>(
                 crate::r#type::pointer_to_is_fat(curr_type, ctx.tcx(), ctx.instance()),
                 crate::r#type::pointer_to_is_fat(field_type, ctx.tcx(), ctx.instance()),
             ) {
+                (false, false) if crate::r#type::simd_vector(curr_type, ctx).is_some() => {
+                    let addr = super::simd_field_address(curr_type, field_index, field_type, parrent_node, ctx);
+                    if body_ty_is_by_adress(field_type, ctx) {
+                        (field_type.into(), addr)
+                    } else {
+                        (field_type.into(), deref_op(field_type.into(), ctx, addr))
+                    }
+                }
                 (false, false) => {
                     let field_desc = crate::utilis::field_descrptor(curr_type, field_index, ctx);
                     if body_ty_is_by_adress(field_type, ctx) {
@@ -64,7 +73,7 @@ fn body_field<'a>(
                     }
                 }
                 (false, true) => panic!("Sized type {curr_type:?} contains an unsized field of type {field_type}. This is a bug."),
-                (true,false)=>todo!("Can't yet handle access of a sized field of an unsized type. "),
+                (true,false)=>unsupported!("Can't yet handle access of a sized field of an unsized type. "),
                 (true,true)=>{
                     assert_eq!(
                         field_index,
@@ -214,7 +223,7 @@ pub fn place_elem_body<'tcx>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! {unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -287,10 +296,10 @@ pub fn place_elem_body<'tcx>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
-        _ => todo!("Can't handle porojection {place_elem:?} in body"),
+        _ => unsupported!("Can't handle porojection {place_elem:?} in body"),
     }
 }
@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, r#type::fat_ptr_to};
+use crate::{assembly::MethodCompileCtx, r#type::fat_ptr_to, unsupported};
 use cilly::{
     call,
     call_site::CallSite,
@@ -72,6 +72,10 @@ fn get_field<'a>(
                 crate::r#type::pointer_to_is_fat(curr_type, ctx.tcx(), ctx.instance()),
                 crate::r#type::pointer_to_is_fat(field_type, ctx.tcx(), ctx.instance()),
             ) {
+                (false, false) if crate::r#type::simd_vector(curr_type, ctx).is_some() => {
+                    let addr = super::simd_field_address(curr_type, field_index, field_type, addr_calc, ctx);
+                    super::deref_op(field_type.into(), ctx, addr)
+                }
                 (false, false) => {
                     let field_desc = crate::utilis::field_descrptor(curr_type, field_index, ctx);
                     CILNode::LDField {
@@ -173,7 +177,7 @@ fn place_elem_get<'a>(
                     )
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! {todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! {unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -222,7 +226,7 @@ fn place_elem_get<'a>(
                     let array_dotnet = array_type.as_class_ref().expect("Non array type");
                     //eprintln!("WARNING: ConstantIndex has required min_length of {min_length}, but bounds checking on const access not supported yet!");
                     if *from_end {
-                        todo!("Can't index array from end!");
+                        unsupported!("Can't index array from end!");
                     } else {
                         let index = CILNode::LdcU64(*offset);
                         call!(
@@ -240,7 +244,7 @@ fn place_elem_get<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -251,6 +255,6 @@ fn place_elem_get<'a>(
                 addr_calc
             }
         }
-        _ => todo!("Can't handle porojection {place_elem:?} in get"),
+        _ => unsupported!("Can't handle porojection {place_elem:?} in get"),
     }
 }
@@ -2,10 +2,11 @@
 
 use crate::assembly::MethodCompileCtx;
 use crate::r#type::pointer_to_is_fat;
+use crate::unsupported;
 use cilly::cil_node::CILNode;
 use cilly::cil_root::CILRoot;
 use cilly::v2::{ClassRef, Float};
-use cilly::{conv_usize, ldc_u64, Type};
+use cilly::{conv_usize, ldc_u32, ldc_u64, Type};
 
 use rustc_middle::mir::Place;
 
@@ -36,6 +37,22 @@ fn pointed_type(ty: PlaceTy) -> Ty {
         panic!("Can't dereference enum variant!");
     }
 }
+/// Returns the address of field `field_index` of a `#[repr(simd)]` type lowered to a .NET vector, given the address of the vector.
+/// .NET vectors don't expose their lanes as fields, so they are accessed at their offset instead.
+pub fn simd_field_address<'tcx>(
+    owner: Ty<'tcx>,
+    field_index: u32,
+    field_ty: Ty<'tcx>,
+    addr: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let offset =
+        crate::utilis::adt::FieldOffsetIterator::fields(ctx.layout_of(owner).layout.0 .0.clone())
+            .nth(field_index as usize)
+            .expect("Field index not in field offset iterator");
+    let field_type = ctx.type_from_cache(field_ty);
+    addr.cast_ptr(ctx.asm_mut().nptr(field_type)) + conv_usize!(ldc_u32!(offset))
+}
 fn body_ty_is_by_adress<'tcx>(last_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> bool {
     crate::assert_morphic!(last_ty);
     match *last_ty.kind() {
@@ -45,6 +62,7 @@ fn body_ty_is_by_adress<'tcx>(last_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx
         //TODO: check if slices are handled propely
         TyKind::Adt(_, _)
         | TyKind::Closure(_, _)
+        | TyKind::Coroutine(_, _)
         | TyKind::Array(_, _)
         | TyKind::Slice(_)
         | TyKind::Str => true,
@@ -53,7 +71,7 @@ fn body_ty_is_by_adress<'tcx>(last_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx
         TyKind::Ref(_, ty, _) | TyKind::RawPtr(ty, _) => {
             pointer_to_is_fat(ty, ctx.tcx(), ctx.instance())
         }
-        _ => todo!(
+        _ => unsupported!(
             "TODO: body_ty_is_by_adress does not support type {last_ty:?} kind:{kind:?}",
             kind = last_ty.kind()
         ),
@@ -112,7 +130,8 @@ pub fn deref_op<'tcx>(
             | TyKind::Tuple(_)
             | TyKind::Array(_, _)
             | TyKind::FnPtr(_, _)
-            | TyKind::Closure(_, _) => {
+            | TyKind::Closure(_, _)
+            | TyKind::Coroutine(_, _) => {
                 let derefed_type = ctx.type_from_cache(derefed_type);
 
                 CILNode::LdObj {
@@ -149,10 +168,10 @@ pub fn deref_op<'tcx>(
                 }
             }
 
-            _ => todo!("TODO: can't deref type {derefed_type:?} yet"),
+            _ => unsupported!("TODO: can't deref type {derefed_type:?} yet"),
         }
     } else {
-        todo!("Can't dereference enum variants yet!")
+        unsupported!("Can't dereference enum variants yet!")
     };
     res
 }
@@ -2,6 +2,7 @@ use crate::{
     assembly::MethodCompileCtx,
     place::{pointed_type, PlaceTy},
     r#type::{fat_ptr_to, pointer_to_is_fat},
+    unsupported,
 };
 use cilly::{
     call,
@@ -56,9 +57,20 @@ pub fn place_elem_set<'a>(
 
             ptr_set_op(pointed_type.into(), ctx, addr_calc, value_calc)
         }
-        PlaceElem::Field(field_index, _field_type) => match curr_type {
+        PlaceElem::Field(field_index, field_type) => match curr_type {
             PlaceTy::Ty(curr_type) => {
                 let curr_type = ctx.monomorphize(curr_type);
+                if crate::r#type::simd_vector(curr_type, ctx).is_some() {
+                    let field_type = ctx.monomorphize(*field_type);
+                    let addr = super::simd_field_address(
+                        curr_type,
+                        field_index.as_u32(),
+                        field_type,
+                        addr_calc,
+                        ctx,
+                    );
+                    return ptr_set_op(field_type.into(), ctx, addr, value_calc);
+                }
                 let field_desc =
                     crate::utilis::field_descrptor(curr_type, (*field_index).into(), ctx);
                 CILRoot::set_field(addr_calc, value_calc, field_desc)
@@ -126,7 +138,7 @@ pub fn place_elem_set<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -196,7 +208,7 @@ pub fn place_elem_set<'a>(
                     }
                 }
                 _ => {
-                    rustc_middle::ty::print::with_no_trimmed_paths! { todo!("Can't index into {curr_ty}!")}
+                    rustc_middle::ty::print::with_no_trimmed_paths! { unsupported!("Can't index into {curr_ty}!")}
                 }
             }
         }
@@ -221,7 +233,7 @@ pub fn place_elem_set<'a>(
             ));
             ops
         }*/
-        _ => todo!("Can't handle porojection {place_elem:?} in set"),
+        _ => unsupported!("Can't handle porojection {place_elem:?} in set"),
     }
 }
 /// Returns a set of instructons to set a pointer to a `pointed_type` to a value from the stack.
@@ -260,12 +272,16 @@ pub fn ptr_set_op<'tcx>(
             TyKind::Float(float_ty) => match float_ty {
                 FloatTy::F32 => CILRoot::STIndF32(addr_calc, value_calc),
                 FloatTy::F64 => CILRoot::STIndF64(addr_calc, value_calc),
-                _ => todo!("Can't yet set {float_ty:?} behnind a pointer."),
+                _ => unsupported!("Can't yet set {float_ty:?} behnind a pointer."),
             },
             TyKind::Bool => CILRoot::STIndI8(addr_calc, value_calc), // Both Rust bool and a managed bool are 1 byte wide. .NET bools are 4 byte wide only in the context of Marshaling/PInvoke,
             // due to historic reasons(BOOL was an alias for int in early Windows, and it stayed this way.) - FractalFir
             TyKind::Char => CILRoot::STIndI32(addr_calc, value_calc), // always 4 bytes wide: https://doc.rust-lang.org/std/primitive.char.html#representation
-            TyKind::Adt(_, _) | TyKind::Tuple(_) | TyKind::Array(_, _) | TyKind::Closure(_, _) => {
+            TyKind::Adt(_, _)
+            | TyKind::Tuple(_)
+            | TyKind::Array(_, _)
+            | TyKind::Closure(_, _)
+            | TyKind::Coroutine(_, _) => {
                 let pointed_type = ctx.type_from_cache(pointed_type);
                 CILRoot::STObj {
                     tpe: pointed_type.into(),
@@ -297,9 +313,9 @@ pub fn ptr_set_op<'tcx>(
                     CILRoot::STIndPtr(addr_calc, value_calc, Box::new(inner))
                 }
             }
-            _ => todo!(" can't deref type {pointed_type:?} yet"),
+            _ => unsupported!(" can't deref type {pointed_type:?} yet"),
         }
     } else {
-        todo!("Can't set the value behind a poitner to an enum variant!");
+        unsupported!("Can't set the value behind a poitner to an enum variant!");
     }
 }
@@ -3,6 +3,7 @@ use crate::{
     call_info::CallInfo,
     operand::handle_operand,
     r#type::{fat_ptr_to, get_type, pointer_to_is_fat},
+    unsupported,
 };
 use cilly::{
     call_site::CallSite,
@@ -111,7 +112,7 @@ pub fn handle_rvalue<'tcx>(
             }
             NullOp::OffsetOf(fields) => {
                 assert_eq!(fields.len(), 1);
-                todo!("Can't calc offset of yet!");
+                unsupported!("Can't calc offset of yet!");
             }
             rustc_middle::mir::NullOp::UbChecks => {
                 if ctx.tcx().sess.ub_checks() {
@@ -236,19 +237,17 @@ pub fn handle_rvalue<'tcx>(
                     val.cast_ptr(Type::Int(Int::USize)),
                     ctx.asm_mut(),
                 ),
-                _ => todo!("Can't cast using `PointerExposeProvenance` to {target:?}"),
+                _ => unsupported!("Can't cast using `PointerExposeProvenance` to {target:?}"),
             }
         }
         Rvalue::Cast(CastKind::FloatToFloat, operand, target) => {
-            let target = ctx.monomorphize(*target);
-            let target = ctx.type_from_cache(target);
-            let mut ops = handle_operand(operand, ctx);
-            match target {
-                Type::Float(Float::F32) => ops = CILNode::ConvF32(ops.into()),
-                Type::Float(Float::F64) => ops = CILNode::ConvF64(ops.into()),
-                _ => panic!("Can't preform a FloatToFloat cast to type {target:?}"),
-            }
-            ops
+            cast!(
+                ctx,
+                operand,
+                target,
+                crate::casts::float_to_float,
+                ctx.asm_mut()
+            )
         }
         Rvalue::Cast(
             CastKind::PointerCoercion(PointerCoercion::ReifyFnPointer),
@@ -272,7 +271,7 @@ pub fn handle_rvalue<'tcx>(
                 };
                 (instance, subst_ref)
             } else {
-                todo!("Trying to call a type which is not a function definition!");
+                unsupported!("Trying to call a type which is not a function definition!");
             };
             let function_name = crate::utilis::function_name(ctx.tcx().symbol_name(instance));
             let function_sig = crate::function_sig::sig_from_instance_(instance, ctx)
@@ -335,7 +334,7 @@ pub fn handle_rvalue<'tcx>(
                     )
                     .unwrap() as u64))
                 }
-                _ => todo!("Get length of type {ty:?}"),
+                _ => unsupported!("Get length of type {ty:?}"),
             }
         }
         Rvalue::Repeat(operand, times) => repeat(rvalue, ctx, operand, *times),
@@ -346,7 +345,7 @@ pub fn handle_rvalue<'tcx>(
                     args: GenericArgs::empty(),
                 };
                 // Call instance
-                todo!("Thread locals with shims unsupported!")
+                unsupported!("Thread locals with shims unsupported!")
             } else {
                 let alloc_id = ctx.tcx().reserve_and_set_static_alloc(*def_id);
                 let rvalue_ty = rvalue.ty(ctx.body(), ctx.tcx());
@@ -361,8 +360,8 @@ pub fn handle_rvalue<'tcx>(
             let target = ctx.type_from_cache(*target);
             handle_operand(operand, ctx).cast_ptr(target)
         }
-        Rvalue::Cast(rustc_middle::mir::CastKind::DynStar, _, _) => {
-            todo!("Unusported cast kind:DynStar")
+        Rvalue::Cast(rustc_middle::mir::CastKind::DynStar, operand, target) => {
+            crate::unsize::dyn_star(ctx, operand, *target)
         }
     }
 }
@@ -3,10 +3,11 @@ use crate::{
     call_info::CallInfo,
     interop::AssemblyRef,
     operand::operand_address,
+    unsupported,
     utilis::{
-        garg_to_string, CTOR_FN_NAME, MANAGED_CALL_FN_NAME, MANAGED_CALL_VIRT_FN_NAME,
-        MANAGED_CHECKED_CAST, MANAGED_IS_INST, MANAGED_LD_ELEM_REF, MANAGED_LD_LEN,
-        MANAGED_LD_NULL,
+        garg_to_string, CTOR_FN_NAME, GENERIC_CALL_FN_NAME, GENERIC_CTOR_FN_NAME,
+        MANAGED_CALL_FN_NAME, MANAGED_CALL_VIRT_FN_NAME, MANAGED_CHECKED_CAST, MANAGED_IS_INST,
+        MANAGED_LD_ELEM_REF, MANAGED_LD_LEN, MANAGED_LD_NULL,
     },
 };
 use cilly::{
@@ -225,6 +226,75 @@ fn call_ctor<'tcx>(
         )
     }
 }
+/// Calls a method of a generic managed class(used for interop). The signature of the method is passed as a function pointer type,
+/// which uses `RustcCLRInteropManagedGenericArg` wherever the method refers to a generic parameter of its class.
+fn call_generic_managed<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    function_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let argument_count = argc_from_fn_name(function_name, GENERIC_CALL_FN_NAME);
+    assert!(args.len() == argument_count as usize);
+    let class = ctx.monomorphize(subst_ref[0]).as_type().unwrap();
+    let class = ctx
+        .type_from_cache(class)
+        .as_class_ref()
+        .expect("Generic managed calls must target a class!");
+    let managed_fn_name = garg_to_string(subst_ref[1], ctx.tcx());
+    let is_static = crate::utilis::garag_to_bool(subst_ref[2], ctx.tcx());
+    let signature = ctx.monomorphize(subst_ref[3]).as_type().unwrap();
+    let Type::FnPtr(signature) = ctx.type_from_cache(signature) else {
+        panic!("The signature of a generic managed call must be a function pointer type!")
+    };
+    let signature = ctx.asm()[signature].clone();
+    let mut call_args = Vec::new();
+    for arg in args {
+        call_args.push(crate::operand::handle_operand(&arg.node, ctx));
+    }
+    let call = CallSite::new(Some(class), managed_fn_name, signature.clone(), is_static);
+    if *signature.output() == cilly::Type::Void {
+        CILRoot::Call {
+            site: Box::new(call),
+            args: call_args.into(),
+        }
+    } else {
+        crate::place::place_set(destination, call!(call, call_args), ctx)
+    }
+}
+/// Creates a new instance of a generic managed class, using its parameterless constructor.
+fn call_generic_ctor<'tcx>(
+    subst_ref: &[GenericArg<'tcx>],
+    function_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let argument_count = argc_from_fn_name(function_name, GENERIC_CTOR_FN_NAME);
+    assert!(
+        argument_count == 0 && args.is_empty(),
+        "Only parameterless constructors of generic classes are supported!"
+    );
+    let class = ctx.monomorphize(subst_ref[0]).as_type().unwrap();
+    let class = ctx
+        .type_from_cache(class)
+        .as_class_ref()
+        .expect("Only classes can be constructed!");
+    crate::place::place_set(
+        destination,
+        CILNode::NewObj(Box::new(CallOpArgs {
+            site: CallSite::boxed(
+                Some(class),
+                ".ctor".into(),
+                FnSig::new(Box::new([Type::ClassRef(class)]), Type::Void),
+                false,
+            ),
+            args: [].into(),
+        })),
+        ctx,
+    )
+}
 pub fn call_closure<'tcx>(
     args: &[Spanned<Operand<'tcx>>],
     destination: &Place<'tcx>,
@@ -313,21 +383,43 @@ pub fn call<'tcx>(
 
         (instance, subst)
     } else {
-        todo!("Trying to call a type which is not a function definition!");
+        unsupported!("Trying to call a type which is not a function definition!");
     };
     if let rustc_middle::ty::InstanceKind::Virtual(_def, fn_idx) = instance.def {
         assert!(!args.is_empty());
 
-        let fat_ptr_address = operand_address(&args[0].node, ctx);
         let fat_ptr_dyn = ctx.asm_mut().alloc_string("FatPtrn3Dyn");
-        let vtable_ptr = ld_field!(
-            fat_ptr_address.clone(),
-            FieldDescriptor::new(
-                ctx.asm_mut()
-                    .alloc_class_ref(ClassRef::new(fat_ptr_dyn, None, true, [].into())),
-                Type::Int(Int::USize),
-                crate::METADATA.into()
+        let fat_ptr_dyn =
+            ctx.asm_mut()
+                .alloc_class_ref(ClassRef::new(fat_ptr_dyn, None, true, [].into()));
+        let void_ptr = ctx.asm_mut().nptr(Type::Void);
+        // The receiver of a `dyn*` method is a thin `&dyn*`, so it needs to be dereferenced first, like in
+        // `rustc_codegen_ssa`. A `dyn*` is laid out like a fat pointer, with the value stored in place of the data
+        // pointer, and the method gets the address of that value.
+        let receiver = ctx.monomorphize(args[0].node.ty(ctx.body(), ctx.tcx()));
+        let (fat_ptr_address, obj_ptr) = if receiver
+            .builtin_deref(true)
+            .is_some_and(|pointee| pointee.is_dyn_star())
+        {
+            let dyn_star_ptr = ctx.asm_mut().nptr(Type::ClassRef(fat_ptr_dyn));
+            let dyn_star_address =
+                crate::operand::handle_operand(&args[0].node, ctx).cast_ptr(dyn_star_ptr);
+            (
+                dyn_star_address.clone(),
+                dyn_star_address.cast_ptr(void_ptr),
             )
+        } else {
+            let fat_ptr_address = operand_address(&args[0].node, ctx);
+            // Get the addres of the object
+            let obj_ptr = ld_field!(
+                fat_ptr_address.clone(),
+                FieldDescriptor::new(fat_ptr_dyn, void_ptr, crate::DATA_PTR.into())
+            );
+            (fat_ptr_address, obj_ptr)
+        };
+        let vtable_ptr = ld_field!(
+            fat_ptr_address,
+            FieldDescriptor::new(fat_ptr_dyn, Type::Int(Int::USize), crate::METADATA.into())
         );
 
         let vtable_index =
@@ -335,21 +427,11 @@ pub fn call<'tcx>(
         let vtable_offset = conv_usize!(vtable_index * size_of!(Type::Int(Int::USize)));
         // Get the address of the function ptr, and load it
 
-        // Get the addres of the object
-        let obj_ptr = ld_field!(
-            fat_ptr_address,
-            FieldDescriptor::new(
-                ctx.asm_mut()
-                    .alloc_class_ref(ClassRef::new(fat_ptr_dyn, None, true, [].into())),
-                ctx.asm_mut().nptr(Type::Void),
-                crate::DATA_PTR.into()
-            )
-        );
         // Get the call info
         let call_info = CallInfo::sig_from_instance_(instance, ctx);
 
         let mut signature = call_info.sig().clone();
-        signature.inputs_mut()[0] = ctx.asm_mut().nptr(Type::Void);
+        signature.inputs_mut()[0] = void_ptr;
         let mut call_args = [obj_ptr].to_vec();
         if call_info.split_last_tuple() {
             let last_arg = args
@@ -462,6 +544,18 @@ pub fn call<'tcx>(
         );
         // Not-Virtual (for interop)
         return call_managed(subst_ref, &function_name, args, destination, instance, ctx);
+    } else if function_name.contains(GENERIC_CTOR_FN_NAME) {
+        assert!(
+            !call_info.split_last_tuple(),
+            "Constructors may not use the `rust_call` calling convention!"
+        );
+        return call_generic_ctor(subst_ref, &function_name, args, destination, ctx);
+    } else if function_name.contains(GENERIC_CALL_FN_NAME) {
+        assert!(
+            !call_info.split_last_tuple(),
+            "Managed calls may not use the `rust_call` calling convention!"
+        );
+        return call_generic_managed(subst_ref, &function_name, args, destination, ctx);
     } else if function_name.contains(MANAGED_LD_LEN) {
         assert!(
             !call_info.split_last_tuple(),
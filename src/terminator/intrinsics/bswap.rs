@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set};
+use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set, unsupported};
 use cilly::{
     call,
     call_site::CallSite,
@@ -42,7 +42,7 @@ pub fn bswap<'tcx>(
                 )
             }
 
-            _ => todo!("Can't bswap {tpe:?}"),
+            _ => unsupported!("Can't bswap {tpe:?}"),
         },
         ctx,
     )
@@ -0,0 +1,120 @@
+//! Math intrinsics operating on `f16` and `f128`. The `f16` ones use the static methods of `System.Half`,
+//! while the `f128` ones call the `*f128` functions of libm.
+use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set, unsupported};
+use cilly::{
+    call,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    ldc_i32,
+    v2::{ClassRef, Float, FnSig, Int},
+    Type,
+};
+use rustc_middle::mir::{Operand, Place};
+use rustc_span::source_map::Spanned;
+pub fn f16_math<'tcx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let mut inputs = vec![Type::Float(Float::F16); args.len()];
+    let mut call_args: Vec<CILNode> = args
+        .iter()
+        .map(|arg| handle_operand(&arg.node, ctx))
+        .collect();
+    let method = match fn_name {
+        "sqrtf16" => "Sqrt",
+        "fabsf16" => "Abs",
+        "floorf16" => "Floor",
+        "ceilf16" => "Ceiling",
+        "truncf16" => "Truncate",
+        // `Round` rounds half-way cases to even by default.
+        "nearbyintf16" | "rintf16" | "roundevenf16" => "Round",
+        "roundf16" => {
+            let midpoint_rounding = Type::ClassRef(ClassRef::midpoint_rounding(ctx.asm_mut()));
+            inputs.push(midpoint_rounding);
+            // `MidpointRounding.AwayFromZero`
+            call_args.push(ldc_i32!(1).transmute_on_stack(
+                Type::Int(Int::I32),
+                midpoint_rounding,
+                ctx.asm_mut(),
+            ));
+            "Round"
+        }
+        "expf16" => "Exp",
+        "exp2f16" => "Exp2",
+        "logf16" => "Log",
+        "log2f16" => "Log2",
+        "log10f16" => "Log10",
+        "sinf16" => "Sin",
+        "cosf16" => "Cos",
+        "powf16" => "Pow",
+        "powif16" => {
+            let exp = call_args.pop().unwrap();
+            call_args.push(crate::casts::int_to_float(
+                Type::Int(Int::I32),
+                Type::Float(Float::F16),
+                exp,
+                ctx.asm_mut(),
+            ));
+            "Pow"
+        }
+        "copysignf16" => "CopySign",
+        "maxnumf16" => "MaxNumber",
+        "minnumf16" => "MinNumber",
+        "fmaf16" => "FusedMultiplyAdd",
+        _ => unsupported!("Unsupported f16 intrinsic {fn_name}"),
+    };
+    let half = ClassRef::half(ctx.asm_mut());
+    place_set(
+        destination,
+        call!(
+            CallSite::new_extern(
+                half,
+                method.into(),
+                FnSig::new(inputs.into(), Type::Float(Float::F16)),
+                true
+            ),
+            call_args
+        ),
+        ctx,
+    )
+}
+pub fn f128_math<'tcx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let mut inputs = vec![Type::Float(Float::F128); args.len()];
+    let call_args: Vec<CILNode> = args
+        .iter()
+        .map(|arg| handle_operand(&arg.node, ctx))
+        .collect();
+    let function = match fn_name {
+        "maxnumf128" => "fmaxf128",
+        "minnumf128" => "fminf128",
+        "powif128" => {
+            inputs[1] = Type::Int(Int::I32);
+            "__powitf2"
+        }
+        "sqrtf128" | "fabsf128" | "floorf128" | "ceilf128" | "truncf128" | "nearbyintf128"
+        | "rintf128" | "roundevenf128" | "roundf128" | "expf128" | "exp2f128" | "logf128"
+        | "log2f128" | "log10f128" | "sinf128" | "cosf128" | "powf128" | "copysignf128"
+        | "fmaf128" => fn_name,
+        _ => unsupported!("Unsupported f128 intrinsic {fn_name}"),
+    };
+    place_set(
+        destination,
+        call!(
+            CallSite::builtin(
+                function.into(),
+                FnSig::new(inputs.into(), Type::Float(Float::F128)),
+                true
+            ),
+            call_args
+        ),
+        ctx,
+    )
+}
@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set};
+use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set, unsupported};
 use cilly::{
     and, call,
     call_site::CallSite,
@@ -125,7 +125,7 @@ pub fn ctpop<'tcx>(
                 ),
                 ctx.asm_mut(),
             ),
-            _ => todo!("Unsported pop count type {tpe:?}"),
+            _ => unsupported!("Unsported pop count type {tpe:?}"),
         },
         ctx,
     )
@@ -189,7 +189,7 @@ pub fn ctlz<'tcx>(
                 ctx,
             )
         }
-        _ => todo!("Can't `ctlz`  type {tpe:?} yet!"),
+        _ => unsupported!("Can't `ctlz`  type {tpe:?} yet!"),
     };
     place_set(
         destination,
@@ -590,7 +590,7 @@ pub fn rotate_left<'tcx>(
             ),
             ctx,
         ),
-        _ => todo!("Can't ror {val_tpe:?}"),
+        _ => unsupported!("Can't ror {val_tpe:?}"),
     }
 }
 pub fn rotate_right<'tcx>(
@@ -805,7 +805,7 @@ pub fn rotate_right<'tcx>(
             ),
             ctx,
         ),
-        _ => todo!("Can't ror {val_tpe:?}"),
+        _ => unsupported!("Can't ror {val_tpe:?}"),
     }
 }
 pub fn bitreverse_u8(byte: CILNode) -> CILNode {
@@ -935,7 +935,7 @@ pub fn bitreverse<'tcx>(
                 ctx.asm_mut(),
             ),
 
-            _ => todo!("can't yet bitreverse {val_tpe:?}"),
+            _ => unsupported!("can't yet bitreverse {val_tpe:?}"),
         },
         ctx,
     )
@@ -2,6 +2,7 @@ use crate::{
     assembly::MethodCompileCtx,
     operand::handle_operand,
     place::{place_adress, place_set},
+    unsupported,
     utilis::field_descrptor,
 };
 use cilly::{
@@ -28,9 +29,11 @@ use utilis::{
     compare_bytes,
 };
 mod bswap;
+mod floats;
 mod interop;
 mod ints;
 mod saturating;
+mod simd;
 mod type_info;
 mod utilis;
 pub fn handle_intrinsic<'tcx>(
@@ -748,7 +751,7 @@ pub fn handle_intrinsic<'tcx>(
                     );
                 }
                 Type::Int(Int::I8 | Int::U16 | Int::I16) | Type::Bool | Type::PlatformChar => {
-                    todo!("can't {fn_name} {src_type:?}")
+                    unsupported!("can't {fn_name} {src_type:?}")
                 }
                 _ => (),
             }
@@ -994,6 +997,18 @@ pub fn handle_intrinsic<'tcx>(
             let tpe = ctx.monomorphize(tpe);
             let tpe = ctx.type_from_cache(tpe);
             let input = handle_operand(&args[0].node, ctx);
+            let src = ctx.monomorphize(
+                call_instance.args[0]
+                    .as_type()
+                    .expect("float_to_int_unchecked works only on types!"),
+            );
+            let src = ctx.type_from_cache(src);
+            // `f16` and `f128` can't be converted with `conv.*`, so use the saturating casts instead.
+            // This is fine, since out of range values are UB anyway.
+            if matches!(src, Type::Float(Float::F16 | Float::F128)) {
+                let cast = crate::casts::float_to_int(src, tpe, input, ctx.asm_mut());
+                return place_set(destination, cast, ctx);
+            }
             place_set(
                 destination,
                 match tpe {
@@ -1007,7 +1022,7 @@ pub fn handle_intrinsic<'tcx>(
                     Type::Int(Int::I32) => conv_i32!(input),
                     Type::Int(Int::I64) => conv_i64!(input),
                     Type::Int(Int::ISize) => conv_isize!(input),
-                    _ => todo!("can't float_to_int_unchecked on {tpe:?}"),
+                    _ => unsupported!("can't float_to_int_unchecked on {tpe:?}"),
                 },
                 ctx,
             )
@@ -1596,7 +1611,7 @@ pub fn handle_intrinsic<'tcx>(
                 ctx,
             )
         }
-        "abort" => CILRoot::throw("Called abort!", ctx.asm_mut()),
+        "abort" => CILRoot::fail_fast("Called abort!", ctx.asm_mut()),
         "const_allocate" => place_set(destination, conv_usize!(ldc_u32!(0)), ctx),
         "vtable_size" => {
             let vtableptr = handle_operand(&args[0].node, ctx);
@@ -1624,6 +1639,11 @@ pub fn handle_intrinsic<'tcx>(
                 ctx,
             )
         }
+        _ if fn_name.starts_with("simd_") => {
+            simd::handle_simd_intrinsic(fn_name, args, destination, call_instance, ctx)
+        }
+        _ if fn_name.ends_with("f16") => floats::f16_math(fn_name, args, destination, ctx),
+        _ if fn_name.ends_with("f128") => floats::f128_math(fn_name, args, destination, ctx),
         _ => intrinsic_slow(fn_name, args, destination, ctx, call_instance, span),
     }
 }
@@ -1801,7 +1821,7 @@ fn intrinsic_slow<'tcx>(
         let rhs = handle_operand(&args[0].node, ctx);
         place_set(destination, conv_u8!(eq!(lhs, rhs)), ctx)
     } else {
-        todo!("Unhandled intrinsic {fn_name}.")
+        unsupported!("Unhandled intrinsic {fn_name}.")
     }
 }
 fn volitale_load<'tcx>(
@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set};
+use crate::{assembly::MethodCompileCtx, operand::handle_operand, place::place_set, unsupported};
 use cilly::{
     call,
     call_site::CallSite,
@@ -230,7 +230,7 @@ pub fn saturating_add<'tcx>(
             );
             conv_i8!(diff_capped)
         }
-        _ => todo!("Can't use the intrinsic `saturating_add` on {a_type:?}"),
+        _ => unsupported!("Can't use the intrinsic `saturating_add` on {a_type:?}"),
     };
     place_set(destination, calc, ctx)
 }
@@ -441,7 +441,7 @@ pub fn saturating_sub<'tcx>(
             );
             conv_i8!(diff_capped)
         }
-        _ => todo!("Can't use the intrinsic `saturating_sub` on {a_type:?}"),
+        _ => unsupported!("Can't use the intrinsic `saturating_sub` on {a_type:?}"),
     };
     place_set(destination, calc, ctx)
 }
@@ -0,0 +1,669 @@
+//! Lowers the `simd_*` family of intrinsics.
+//!
+//! Operations on types lowered to `System.Runtime.Intrinsics.Vector{64,128,256}<T>` are preferably lowered to the static methods of
+//! `System.Runtime.Intrinsics.Vector{64,128,256}`. Everything else is lowered lane by lane: the lanes of a `#[repr(simd)]` type
+//! are laid out contiguously, so lane `n` is always at offset `n * size_of::<T>()`.
+use crate::{
+    assembly::MethodCompileCtx,
+    binop::{binop_values, cmp::ne_unchecked},
+    operand::handle_operand,
+    place::{deref_op, place_adress, place_set, ptr_set_op},
+    r#type::simd_vector,
+    unsupported,
+};
+use cilly::{
+    and, call,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    conv_f32, conv_f64, conv_i32, conv_usize, ldc_i64, ldc_u32, ldc_u64, lt_un, or, size_of, sub,
+    v2::{tpe::GenericKind, ClassRef, Float, FnSig, Int},
+    Type,
+};
+use rustc_middle::{
+    mir::{BinOp, Operand, Place},
+    ty::{FloatTy, Instance, Ty, TyKind},
+};
+use rustc_span::source_map::Spanned;
+pub fn handle_simd_intrinsic<'tcx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    call_instance: Instance<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    if let Some(vector) = vector_op(fn_name, args, destination, call_instance, ctx) {
+        return place_set(destination, vector, ctx);
+    }
+    lanewise_op(fn_name, args, destination, call_instance, ctx)
+}
+/// Returns the `n`th generic argument of a SIMD intrinsic.
+fn simd_generic<'tcx>(
+    call_instance: Instance<'tcx>,
+    n: usize,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> Ty<'tcx> {
+    ctx.monomorphize(
+        call_instance.args[n]
+            .as_type()
+            .expect("SIMD intrinsics work only on types!"),
+    )
+}
+/// Returns the size of the .NET vector `vec_ty` is lowered to, in bits, or `None` if `vec_ty` is not lowered to a .NET vector.
+fn vector_bits<'tcx>(vec_ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Option<u32> {
+    simd_vector(vec_ty, ctx)?;
+    Some(u32::try_from(ctx.layout_of(vec_ty).layout.size().bytes() * 8).unwrap())
+}
+/// Returns `Vector{bits}<!!generic>`.
+fn generic_vector(bits: u32, generic: u32, ctx: &mut MethodCompileCtx<'_, '_>) -> Type {
+    ClassRef::simd_vector(
+        bits,
+        Type::PlatformGeneric(generic, GenericKind::CallGeneric),
+        ctx.asm_mut(),
+    )
+    .into()
+}
+/// Calls the static method `name` of `System.Runtime.Intrinsics.Vector{bits}`, instantiated with `generics`.
+fn vector_call(
+    bits: u32,
+    name: &str,
+    sig: FnSig,
+    generics: Vec<Type>,
+    args: Vec<CILNode>,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> CILNode {
+    let mut site = CallSite::new(
+        Some(ClassRef::simd_vector_ops(bits, ctx.asm_mut())),
+        name.into(),
+        sig,
+        true,
+    );
+    site.set_generics(generics);
+    call!(site, args)
+}
+/// Reinterprets the bits of a `Vector{bits}<from>` as a `Vector{bits}<to>`.
+fn vector_as(
+    bits: u32,
+    from: Type,
+    to: Type,
+    vector: CILNode,
+    ctx: &mut MethodCompileCtx<'_, '_>,
+) -> CILNode {
+    if from == to {
+        return vector;
+    }
+    let input = generic_vector(bits, 0, ctx);
+    let output = generic_vector(bits, 1, ctx);
+    vector_call(
+        bits,
+        "As",
+        FnSig::new([input].into(), output),
+        vec![from, to],
+        vec![vector],
+        ctx,
+    )
+}
+/// Lowers `fn_name` to the methods of `System.Runtime.Intrinsics.Vector{64,128,256}`. Returns `None` if the operands are not .NET vectors,
+/// or if there is no equivalent method.
+fn vector_op<'tcx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    call_instance: Instance<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> Option<CILNode> {
+    let vec_ty = simd_generic(call_instance, 0, ctx);
+    let bits = vector_bits(vec_ty, ctx)?;
+    let (_, elem_ty) = vec_ty.simd_size_and_type(ctx.tcx());
+    let elem = ctx.type_from_cache(elem_ty);
+    let vector = generic_vector(bits, 0, ctx);
+    let generic = Type::PlatformGeneric(0, GenericKind::CallGeneric);
+    match fn_name {
+        "simd_add" | "simd_sub" | "simd_mul" | "simd_div" | "simd_and" | "simd_or" | "simd_xor" => {
+            let name = match fn_name {
+                "simd_add" => "Add",
+                "simd_sub" => "Subtract",
+                "simd_mul" => "Multiply",
+                "simd_div" => "Divide",
+                "simd_and" => "BitwiseAnd",
+                "simd_or" => "BitwiseOr",
+                "simd_xor" => "Xor",
+                _ => unreachable!(),
+            };
+            let a = handle_operand(&args[0].node, ctx);
+            let b = handle_operand(&args[1].node, ctx);
+            Some(vector_call(
+                bits,
+                name,
+                FnSig::new([vector, vector].into(), vector),
+                vec![elem],
+                vec![a, b],
+                ctx,
+            ))
+        }
+        "simd_neg" | "simd_fabs" | "simd_fsqrt" => {
+            let name = match fn_name {
+                "simd_neg" => "Negate",
+                "simd_fabs" => "Abs",
+                "simd_fsqrt" => "Sqrt",
+                _ => unreachable!(),
+            };
+            let a = handle_operand(&args[0].node, ctx);
+            Some(vector_call(
+                bits,
+                name,
+                FnSig::new([vector].into(), vector),
+                vec![elem],
+                vec![a],
+                ctx,
+            ))
+        }
+        "simd_eq" | "simd_ne" | "simd_lt" | "simd_le" | "simd_gt" | "simd_ge" => {
+            // The mask must have the same layout as the compared vectors, so it can be reinterpreted.
+            let mask_ty = simd_generic(call_instance, 1, ctx);
+            if vector_bits(mask_ty, ctx)? != bits {
+                return None;
+            }
+            let (_, mask_elem) = mask_ty.simd_size_and_type(ctx.tcx());
+            let mask_elem = ctx.type_from_cache(mask_elem);
+            let name = match fn_name {
+                "simd_eq" | "simd_ne" => "Equals",
+                "simd_lt" => "LessThan",
+                "simd_le" => "LessThanOrEqual",
+                "simd_gt" => "GreaterThan",
+                "simd_ge" => "GreaterThanOrEqual",
+                _ => unreachable!(),
+            };
+            let a = handle_operand(&args[0].node, ctx);
+            let b = handle_operand(&args[1].node, ctx);
+            let mut mask = vector_call(
+                bits,
+                name,
+                FnSig::new([vector, vector].into(), vector),
+                vec![elem],
+                vec![a, b],
+                ctx,
+            );
+            if fn_name == "simd_ne" {
+                mask = vector_call(
+                    bits,
+                    "OnesComplement",
+                    FnSig::new([vector].into(), vector),
+                    vec![elem],
+                    vec![mask],
+                    ctx,
+                );
+            }
+            Some(vector_as(bits, elem, mask_elem, mask, ctx))
+        }
+        "simd_select" => {
+            // Here, the first generic argument is the mask.
+            let val_ty = simd_generic(call_instance, 1, ctx);
+            if vector_bits(val_ty, ctx)? != bits {
+                return None;
+            }
+            let (_, val_elem) = val_ty.simd_size_and_type(ctx.tcx());
+            let val_elem = ctx.type_from_cache(val_elem);
+            let mask = handle_operand(&args[0].node, ctx);
+            let mask = vector_as(bits, elem, val_elem, mask, ctx);
+            let a = handle_operand(&args[1].node, ctx);
+            let b = handle_operand(&args[2].node, ctx);
+            // The lanes of a mask are either all ones, or all zeroes, so a bitwise select is enough.
+            Some(vector_call(
+                bits,
+                "ConditionalSelect",
+                FnSig::new([vector, vector, vector].into(), vector),
+                vec![val_elem],
+                vec![mask, a, b],
+                ctx,
+            ))
+        }
+        "simd_reduce_add_unordered" | "simd_reduce_add_ordered" => {
+            // Floating-point addition is not associative, so an ordered sum must be computed lane by lane.
+            if fn_name == "simd_reduce_add_ordered" && elem_ty.is_floating_point() {
+                return None;
+            }
+            let a = handle_operand(&args[0].node, ctx);
+            let sum = vector_call(
+                bits,
+                "Sum",
+                FnSig::new([vector].into(), generic),
+                vec![elem],
+                vec![a],
+                ctx,
+            );
+            if fn_name == "simd_reduce_add_ordered" {
+                let acc = handle_operand(&args[1].node, ctx);
+                Some(binop_values(BinOp::Add, elem_ty, elem_ty, acc, sum, ctx))
+            } else {
+                Some(sum)
+            }
+        }
+        "simd_extract" => {
+            let a = handle_operand(&args[0].node, ctx);
+            let idx = conv_i32!(handle_operand(&args[1].node, ctx));
+            Some(vector_call(
+                bits,
+                "GetElement",
+                FnSig::new([vector, Type::Int(Int::I32)].into(), generic),
+                vec![elem],
+                vec![a, idx],
+                ctx,
+            ))
+        }
+        "simd_insert" => {
+            let a = handle_operand(&args[0].node, ctx);
+            let idx = conv_i32!(handle_operand(&args[1].node, ctx));
+            let val = handle_operand(&args[2].node, ctx);
+            Some(vector_call(
+                bits,
+                "WithElement",
+                FnSig::new([vector, Type::Int(Int::I32), generic].into(), vector),
+                vec![elem],
+                vec![a, idx, val],
+                ctx,
+            ))
+        }
+        "simd_cast" => {
+            let dst_ty = ctx.monomorphize(destination.ty(ctx.body(), ctx.tcx()).ty);
+            if vector_bits(dst_ty, ctx)? != bits {
+                return None;
+            }
+            let (_, dst_elem_ty) = dst_ty.simd_size_and_type(ctx.tcx());
+            let dst_elem = ctx.type_from_cache(dst_elem_ty);
+            let a = handle_operand(&args[0].node, ctx);
+            match (elem_ty.is_floating_point(), dst_elem_ty.is_floating_point()) {
+                // Same sized integers only differ in their sign, which does not change their bits.
+                (false, false) => Some(vector_as(bits, elem, dst_elem, a, ctx)),
+                (true, true) if elem == dst_elem => Some(a),
+                (true, true) => None,
+                _ => {
+                    let name = match dst_elem {
+                        Type::Int(Int::I32) => "ConvertToInt32",
+                        Type::Int(Int::U32) => "ConvertToUInt32",
+                        Type::Int(Int::I64) => "ConvertToInt64",
+                        Type::Int(Int::U64) => "ConvertToUInt64",
+                        Type::Float(Float::F32) => "ConvertToSingle",
+                        Type::Float(Float::F64) => "ConvertToDouble",
+                        _ => return None,
+                    };
+                    let input = ClassRef::simd_vector(bits, elem, ctx.asm_mut()).into();
+                    let output = ClassRef::simd_vector(bits, dst_elem, ctx.asm_mut()).into();
+                    Some(vector_call(
+                        bits,
+                        name,
+                        FnSig::new([input].into(), output),
+                        vec![],
+                        vec![a],
+                        ctx,
+                    ))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+/// Returns the address of a vector operand. Constant vectors are first stored in a temporary local.
+fn vector_address<'tcx>(operand: &Operand<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> CILNode {
+    match operand {
+        Operand::Copy(place) | Operand::Move(place) => place_adress(place, ctx),
+        Operand::Constant(_) => {
+            let tpe = ctx.type_from_cache(ctx.monomorphize(operand.ty(ctx.body(), ctx.tcx())));
+            CILNode::TemporaryLocal(Box::new((
+                tpe,
+                [CILRoot::SetTMPLocal {
+                    value: handle_operand(operand, ctx),
+                }]
+                .into(),
+                CILNode::LoadAddresOfTMPLocal,
+            )))
+        }
+    }
+}
+/// Returns the address of lane `idx`(an usize) of the vector at `addr`.
+fn lane_address<'tcx>(
+    addr: CILNode,
+    elem_ty: Ty<'tcx>,
+    idx: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let elem = ctx.type_from_cache(elem_ty);
+    addr.cast_ptr(ctx.asm_mut().nptr(elem)) + idx * conv_usize!(size_of!(elem))
+}
+/// Returns the value of lane `idx` of the vector at `addr`.
+fn lane<'tcx>(
+    addr: CILNode,
+    elem_ty: Ty<'tcx>,
+    idx: u64,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let addr = lane_address(addr, elem_ty, conv_usize!(ldc_u64!(idx)), ctx);
+    deref_op(elem_ty.into(), ctx, addr)
+}
+/// Assigns a vector, made up from `lanes`, to `destination`.
+fn set_lanes<'tcx>(
+    destination: &Place<'tcx>,
+    lanes: Vec<CILNode>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let dst_ty = ctx.monomorphize(destination.ty(ctx.body(), ctx.tcx()).ty);
+    let (_, elem_ty) = dst_ty.simd_size_and_type(ctx.tcx());
+    let dst_type = ctx.type_from_cache(dst_ty);
+    // The lanes are first written to a temporary, since the destination may be one of the inputs.
+    let roots: Vec<_> = lanes
+        .into_iter()
+        .enumerate()
+        .map(|(idx, value)| {
+            let addr = lane_address(
+                CILNode::LoadAddresOfTMPLocal,
+                elem_ty,
+                conv_usize!(ldc_u64!(idx as u64)),
+                ctx,
+            );
+            ptr_set_op(elem_ty.into(), ctx, addr, value)
+        })
+        .collect();
+    place_set(
+        destination,
+        CILNode::TemporaryLocal(Box::new((dst_type, roots.into(), CILNode::LoadTMPLocal))),
+        ctx,
+    )
+}
+/// Returns the all-ones(`true`) or all-zeroes(`false`) mask lane of type `mask`.
+fn mask_lane(mask: Type, cond: CILNode, ctx: &mut MethodCompileCtx<'_, '_>) -> CILNode {
+    let ones = crate::casts::int_to_int(Type::Int(Int::I64), mask, ldc_i64!(-1), ctx.asm_mut());
+    let zeroes = crate::casts::int_to_int(Type::Int(Int::I64), mask, ldc_i64!(0), ctx.asm_mut());
+    CILNode::select(mask, ones, zeroes, cond)
+}
+/// Checks if a mask lane is set.
+fn is_lane_set<'tcx>(
+    mask_ty: Ty<'tcx>,
+    value: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let mask = ctx.type_from_cache(mask_ty);
+    let zero = crate::casts::int_to_int(Type::Int(Int::I64), mask, ldc_i64!(0), ctx.asm_mut());
+    ne_unchecked(mask_ty, value, zero, ctx.asm_mut())
+}
+/// Converts a single lane of a vector.
+fn cast_lane<'tcx>(
+    src_ty: Ty<'tcx>,
+    dst_ty: Ty<'tcx>,
+    value: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let src = ctx.type_from_cache(src_ty);
+    let dst = ctx.type_from_cache(dst_ty);
+    match (src_ty.is_floating_point(), dst_ty.is_floating_point()) {
+        (false, false) => crate::casts::int_to_int(src, dst, value, ctx.asm_mut()),
+        (true, false) => crate::casts::float_to_int(src, dst, value, ctx.asm_mut()),
+        (false, true) => crate::casts::int_to_float(src, dst, value, ctx.asm_mut()),
+        (true, true) => match dst {
+            Type::Float(Float::F32) => conv_f32!(value),
+            Type::Float(Float::F64) => conv_f64!(value),
+            _ => unsupported!("Can't cast a SIMD lane from {src:?} to {dst:?}"),
+        },
+    }
+}
+/// Returns the minimum or maximum of two lanes.
+fn min_max<'tcx>(
+    elem_ty: Ty<'tcx>,
+    is_min: bool,
+    a: CILNode,
+    b: CILNode,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILNode {
+    let (class, float) = match elem_ty.kind() {
+        TyKind::Float(FloatTy::F32) => (ClassRef::single(ctx.asm_mut()), Float::F32),
+        TyKind::Float(FloatTy::F64) => (ClassRef::double(ctx.asm_mut()), Float::F64),
+        _ => {
+            let elem = ctx.type_from_cache(elem_ty);
+            let op = if is_min { BinOp::Lt } else { BinOp::Gt };
+            let cond = binop_values(op, elem_ty, elem_ty, a.clone(), b.clone(), ctx);
+            return CILNode::select(elem, a, b, cond);
+        }
+    };
+    // Floats follow the IEEE-754 `minNum` and `maxNum`: a NaN is only returned if both lanes are NaN.
+    let name = if is_min { "MinNumber" } else { "MaxNumber" };
+    call!(
+        CallSite::new_extern(
+            class,
+            name.into(),
+            FnSig::new(
+                [Type::Float(float), Type::Float(float)].into(),
+                Type::Float(float)
+            ),
+            true
+        ),
+        [a, b]
+    )
+}
+/// Lowers `fn_name` lane by lane.
+fn lanewise_op<'tcx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tcx>>],
+    destination: &Place<'tcx>,
+    call_instance: Instance<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> CILRoot {
+    let vec_ty = simd_generic(call_instance, 0, ctx);
+    let (lanes, elem_ty) = vec_ty.simd_size_and_type(ctx.tcx());
+    match fn_name {
+        "simd_add" | "simd_sub" | "simd_mul" | "simd_div" | "simd_rem" | "simd_and" | "simd_or"
+        | "simd_xor" | "simd_shl" | "simd_shr" => {
+            let op = match fn_name {
+                "simd_add" => BinOp::Add,
+                "simd_sub" => BinOp::Sub,
+                "simd_mul" => BinOp::Mul,
+                "simd_div" => BinOp::Div,
+                "simd_rem" => BinOp::Rem,
+                "simd_and" => BinOp::BitAnd,
+                "simd_or" => BinOp::BitOr,
+                "simd_xor" => BinOp::BitXor,
+                "simd_shl" => BinOp::ShlUnchecked,
+                "simd_shr" => BinOp::ShrUnchecked,
+                _ => unreachable!(),
+            };
+            let a = vector_address(&args[0].node, ctx);
+            let b = vector_address(&args[1].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| {
+                    let a = lane(a.clone(), elem_ty, idx, ctx);
+                    let b = lane(b.clone(), elem_ty, idx, ctx);
+                    binop_values(op, elem_ty, elem_ty, a, b, ctx)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_eq" | "simd_ne" | "simd_lt" | "simd_le" | "simd_gt" | "simd_ge" => {
+            let op = match fn_name {
+                "simd_eq" => BinOp::Eq,
+                "simd_ne" => BinOp::Ne,
+                "simd_lt" => BinOp::Lt,
+                "simd_le" => BinOp::Le,
+                "simd_gt" => BinOp::Gt,
+                "simd_ge" => BinOp::Ge,
+                _ => unreachable!(),
+            };
+            let mask_ty = simd_generic(call_instance, 1, ctx);
+            let (_, mask_elem) = mask_ty.simd_size_and_type(ctx.tcx());
+            let mask_elem = ctx.type_from_cache(mask_elem);
+            let a = vector_address(&args[0].node, ctx);
+            let b = vector_address(&args[1].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| {
+                    let a = lane(a.clone(), elem_ty, idx, ctx);
+                    let b = lane(b.clone(), elem_ty, idx, ctx);
+                    let cond = binop_values(op, elem_ty, elem_ty, a, b, ctx);
+                    mask_lane(mask_elem, cond, ctx)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_neg" => {
+            let a = vector_address(&args[0].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| CILNode::Neg(lane(a.clone(), elem_ty, idx, ctx).into()))
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_fabs" | "simd_fsqrt" => {
+            let (class, float) = match elem_ty.kind() {
+                TyKind::Float(FloatTy::F32) => (ClassRef::mathf(ctx.asm_mut()), Float::F32),
+                TyKind::Float(FloatTy::F64) => (ClassRef::math(ctx.asm_mut()), Float::F64),
+                _ => panic!("{fn_name} works only on float vectors, not {vec_ty:?}"),
+            };
+            let name = if fn_name == "simd_fabs" {
+                "Abs"
+            } else {
+                "Sqrt"
+            };
+            let site = CallSite::new_extern(
+                class,
+                name.into(),
+                FnSig::new([Type::Float(float)].into(), Type::Float(float)),
+                true,
+            );
+            let a = vector_address(&args[0].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| call!(site.clone(), [lane(a.clone(), elem_ty, idx, ctx)]))
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_select" => {
+            // Here, the first generic argument is the mask.
+            let val_ty = simd_generic(call_instance, 1, ctx);
+            let (_, val_elem_ty) = val_ty.simd_size_and_type(ctx.tcx());
+            let val_ptr = ctx.type_from_cache(val_elem_ty);
+            let val_ptr = ctx.asm_mut().nptr(val_ptr);
+            let mask = vector_address(&args[0].node, ctx);
+            let a = vector_address(&args[1].node, ctx);
+            let b = vector_address(&args[2].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| {
+                    let cond = lane(mask.clone(), elem_ty, idx, ctx);
+                    let cond = is_lane_set(elem_ty, cond, ctx);
+                    let a = lane_address(a.clone(), val_elem_ty, conv_usize!(ldc_u64!(idx)), ctx);
+                    let b = lane_address(b.clone(), val_elem_ty, conv_usize!(ldc_u64!(idx)), ctx);
+                    // Selecting the address, and not the value, works with lanes of any type.
+                    let addr = CILNode::select(val_ptr, a, b, cond);
+                    deref_op(val_elem_ty.into(), ctx, addr)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_shuffle" => {
+            let dst_ty = ctx.monomorphize(destination.ty(ctx.body(), ctx.tcx()).ty);
+            let (dst_lanes, _) = dst_ty.simd_size_and_type(ctx.tcx());
+            let elem_ptr = ctx.type_from_cache(elem_ty);
+            let elem_ptr = ctx.asm_mut().nptr(elem_ptr);
+            let a = vector_address(&args[0].node, ctx);
+            let b = vector_address(&args[1].node, ctx);
+            // The indices are an array(or a vector) of `u32`s. Indices below `lanes` select from `a`, the rest from `b`.
+            let indices = vector_address(&args[2].node, ctx);
+            let u32_ty = ctx.tcx().types.u32;
+            let values = (0..dst_lanes)
+                .map(|idx| {
+                    let idx = lane(indices.clone(), u32_ty, idx, ctx);
+                    let lanes = ldc_u32!(u32::try_from(lanes).unwrap());
+                    let in_a = lt_un!(idx.clone(), lanes.clone());
+                    let a = lane_address(a.clone(), elem_ty, conv_usize!(idx.clone()), ctx);
+                    let b = lane_address(b.clone(), elem_ty, conv_usize!(sub!(idx, lanes)), ctx);
+                    let addr = CILNode::select(elem_ptr, a, b, in_a);
+                    deref_op(elem_ty.into(), ctx, addr)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_extract" => {
+            let a = vector_address(&args[0].node, ctx);
+            let idx = conv_usize!(handle_operand(&args[1].node, ctx));
+            let addr = lane_address(a, elem_ty, idx, ctx);
+            let value = deref_op(elem_ty.into(), ctx, addr);
+            place_set(destination, value, ctx)
+        }
+        "simd_insert" => {
+            let elem_ptr = ctx.type_from_cache(elem_ty);
+            let elem_ptr = ctx.asm_mut().nptr(elem_ptr);
+            let a = vector_address(&args[0].node, ctx);
+            let idx = handle_operand(&args[1].node, ctx);
+            let val = vector_address(&args[2].node, ctx).cast_ptr(elem_ptr);
+            let values = (0..lanes)
+                .map(|lane_idx| {
+                    let old =
+                        lane_address(a.clone(), elem_ty, conv_usize!(ldc_u64!(lane_idx)), ctx);
+                    let is_inserted = CILNode::Eq(
+                        idx.clone().into(),
+                        ldc_u32!(u32::try_from(lane_idx).unwrap()).into(),
+                    );
+                    let addr = CILNode::select(elem_ptr, val.clone(), old, is_inserted);
+                    deref_op(elem_ty.into(), ctx, addr)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_cast" | "simd_as" => {
+            let dst_ty = ctx.monomorphize(destination.ty(ctx.body(), ctx.tcx()).ty);
+            let (_, dst_elem_ty) = dst_ty.simd_size_and_type(ctx.tcx());
+            let a = vector_address(&args[0].node, ctx);
+            let values = (0..lanes)
+                .map(|idx| {
+                    let value = lane(a.clone(), elem_ty, idx, ctx);
+                    cast_lane(elem_ty, dst_elem_ty, value, ctx)
+                })
+                .collect();
+            set_lanes(destination, values, ctx)
+        }
+        "simd_reduce_add_ordered"
+        | "simd_reduce_add_unordered"
+        | "simd_reduce_mul_ordered"
+        | "simd_reduce_mul_unordered"
+        | "simd_reduce_and"
+        | "simd_reduce_or"
+        | "simd_reduce_xor"
+        | "simd_reduce_min"
+        | "simd_reduce_max" => {
+            let a = vector_address(&args[0].node, ctx);
+            // Ordered reductions start from an accumulator, the rest from the first lane.
+            let (mut acc, first) = if fn_name.ends_with("_ordered") {
+                (handle_operand(&args[1].node, ctx), 0)
+            } else {
+                (lane(a.clone(), elem_ty, 0, ctx), 1)
+            };
+            for idx in first..lanes {
+                let value = lane(a.clone(), elem_ty, idx, ctx);
+                acc = match fn_name {
+                    "simd_reduce_min" => min_max(elem_ty, true, acc, value, ctx),
+                    "simd_reduce_max" => min_max(elem_ty, false, acc, value, ctx),
+                    _ => {
+                        let op = match fn_name {
+                            "simd_reduce_add_ordered" | "simd_reduce_add_unordered" => BinOp::Add,
+                            "simd_reduce_mul_ordered" | "simd_reduce_mul_unordered" => BinOp::Mul,
+                            "simd_reduce_and" => BinOp::BitAnd,
+                            "simd_reduce_or" => BinOp::BitOr,
+                            "simd_reduce_xor" => BinOp::BitXor,
+                            _ => unreachable!(),
+                        };
+                        binop_values(op, elem_ty, elem_ty, acc, value, ctx)
+                    }
+                };
+            }
+            place_set(destination, acc, ctx)
+        }
+        "simd_reduce_all" | "simd_reduce_any" => {
+            let a = vector_address(&args[0].node, ctx);
+            let mut acc = None;
+            for idx in 0..lanes {
+                let value = lane(a.clone(), elem_ty, idx, ctx);
+                let is_set = is_lane_set(elem_ty, value, ctx);
+                acc = Some(match acc {
+                    None => is_set,
+                    Some(acc) if fn_name == "simd_reduce_all" => and!(acc, is_set),
+                    Some(acc) => or!(acc, is_set),
+                });
+            }
+            place_set(destination, acc.expect("SIMD vector with no lanes"), ctx)
+        }
+        _ => unsupported!("Unsupported SIMD intrinsic {fn_name}"),
+    }
+}
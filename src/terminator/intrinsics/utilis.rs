@@ -1,3 +1,4 @@
+use crate::unsupported;
 use cilly::{
     call,
     call_site::CallSite,
@@ -48,7 +49,7 @@ pub fn atomic_add(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             [addr, addend.clone()]
         ),
 
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_add` on {tpe:?}"),
     }
 }
 pub fn atomic_or(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
@@ -118,7 +119,7 @@ pub fn atomic_or(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_or` on {tpe:?}"),
     }
 }
 pub fn atomic_xor(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
@@ -212,7 +213,7 @@ pub fn atomic_xor(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_xor` on {tpe:?}"),
     }
 }
 pub fn atomic_and(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
@@ -271,7 +272,7 @@ pub fn atomic_and(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_and` on {tpe:?}"),
     }
 }
 pub fn compare_bytes(a: CILNode, b: CILNode, len: CILNode, asm: &mut Assembly) -> CILNode {
@@ -382,7 +383,7 @@ pub fn atomic_nand(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_nand` on {tpe:?}"),
     }
 }
 pub fn atomic_min(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
@@ -476,7 +477,7 @@ pub fn atomic_min(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_min` on {tpe:?}"),
     }
 }
 pub fn atomic_max(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly) -> CILNode {
@@ -570,6 +571,6 @@ pub fn atomic_max(addr: CILNode, addend: CILNode, tpe: Type, asm: &mut Assembly)
             ]
         )
         .cast_ptr(Type::Ptr(inner)),
-        _ => todo!(),
+        _ => unsupported!("Can't use `atomic_max` on {tpe:?}"),
     }
 }
@@ -1,4 +1,4 @@
-use crate::{assembly::MethodCompileCtx, place::place_set};
+use crate::{assembly::MethodCompileCtx, place::place_set, unsupported};
 use cilly::{
     call_site::CallSite,
     cil_node::CILNode,
@@ -7,7 +7,7 @@ use cilly::{
     conv_usize,
     field_desc::FieldDescriptor,
     ld_field, ldc_u32,
-    v2::{Assembly, FnSig, Int},
+    v2::{cilroot, Assembly, FnSig, Int},
     Type,
 };
 use rustc_middle::{
@@ -26,8 +26,81 @@ pub fn handle_call_terminator<'tycxt>(
     func: &Operand<'tycxt>,
     target: Option<BasicBlock>,
 ) -> Vec<CILTree> {
-    let mut trees = Vec::new();
-
+    let mut trees = vec![call_root(terminator, ctx, args, destination, func).into()];
+    // Final Jump
+    if let Some(target) = target {
+        trees.push(
+            CILRoot::GoTo {
+                target: target.as_u32(),
+                sub_target: 0,
+            }
+            .into(),
+        );
+    } else {
+        trees.push(CILRoot::throw("Function returning `Never` returned!", ctx.asm_mut()).into());
+    }
+    trees
+}
+/// Lowers a `become` call. The call writes its result to the return place, just like an ordinary call, and is then marked
+/// as a tail call.
+fn handle_tail_call<'tycxt>(
+    terminator: &Terminator<'tycxt>,
+    ctx: &mut MethodCompileCtx<'tycxt, '_>,
+    args: &[Spanned<Operand<'tycxt>>],
+    func: &Operand<'tycxt>,
+) -> Vec<CILTree> {
+    let call = call_root(terminator, ctx, args, &Place::return_place(), func);
+    let call = match call {
+        CILRoot::Call { .. } | CILRoot::CallVirt { .. } | CILRoot::CallI { .. } => call,
+        CILRoot::STLoc {
+            local: 0,
+            tree: CILNode::Call(call),
+        } => CILRoot::Call {
+            site: call.site,
+            args: call.args,
+        },
+        CILRoot::STLoc {
+            local: 0,
+            tree: CILNode::CallVirt(call),
+        } => CILRoot::CallVirt {
+            site: call.site,
+            args: call.args,
+        },
+        CILRoot::STLoc {
+            local: 0,
+            tree: CILNode::CallI(call),
+        } => {
+            let (sig, fn_ptr, args) = *call;
+            CILRoot::CallI {
+                sig: Box::new(sig),
+                fn_ptr: Box::new(fn_ptr),
+                args,
+            }
+        }
+        // Intrinsics and calls which don't lower to a single call instruction are performed normally.
+        call => return vec![call.into(), return_root(ctx).into()],
+    };
+    vec![CILRoot::TailCall(Box::new(call)).into()]
+}
+/// Returns the value of the return place.
+fn return_root(ctx: &mut MethodCompileCtx<'_, '_>) -> CILRoot {
+    let ret = ctx.monomorphize(ctx.body().return_ty());
+    if ctx.type_from_cache(ret) == cilly::Type::Void {
+        CILRoot::VoidRet
+    } else {
+        CILRoot::Ret {
+            tree: CILNode::LDLoc(0),
+        }
+    }
+}
+/// Lowers a call to `func`, which stores its result in `destination`.
+fn call_root<'tycxt>(
+    terminator: &Terminator<'tycxt>,
+    ctx: &mut MethodCompileCtx<'tycxt, '_>,
+    args: &[Spanned<Operand<'tycxt>>],
+    destination: &Place<'tycxt>,
+    func: &Operand<'tycxt>,
+) -> CILRoot {
     let func_ty = func.ty(ctx.body(), ctx.tcx());
     // Get the pointed type, if byref;
     let func_ty = match func_ty.builtin_deref(true) {
@@ -43,9 +116,8 @@ pub fn handle_call_terminator<'tycxt>(
                 "fn_ty{fn_ty:?} in call is not a function type!"
             );
             let fn_ty = ctx.monomorphize(fn_ty);
-            let call_ops = call::call(fn_ty, ctx, args, destination, terminator.source_info.span);
-            //eprintln!("\nCalling FnDef:{fn_ty:?}. call_ops:{call_ops:?}");
-            trees.push(call_ops.into());
+            //eprintln!("\nCalling FnDef:{fn_ty:?}");
+            call::call(fn_ty, ctx, args, destination, terminator.source_info.span)
         }
         TyKind::FnPtr(sig, _) => {
             //eprintln!("Calling FnPtr:{func_ty:?}");
@@ -60,44 +132,21 @@ pub fn handle_call_terminator<'tycxt>(
             }
             let called_operand = crate::operand::handle_operand(func, ctx);
             if *sig.output() == cilly::Type::Void {
-                trees.push(
-                    CILRoot::CallI {
-                        sig: Box::new(sig.clone()),
-                        fn_ptr: Box::new(called_operand),
-                        args: arg_operands.into(),
-                    }
-                    .into(),
-                );
+                CILRoot::CallI {
+                    sig: Box::new(sig.clone()),
+                    fn_ptr: Box::new(called_operand),
+                    args: arg_operands.into(),
+                }
             } else {
-                trees.push(
-                    place_set(
-                        destination,
-                        CILNode::CallI(Box::new((
-                            sig.clone(),
-                            called_operand,
-                            arg_operands.into(),
-                        ))),
-                        ctx,
-                    )
-                    .into(),
-                );
+                place_set(
+                    destination,
+                    CILNode::CallI(Box::new((sig.clone(), called_operand, arg_operands.into()))),
+                    ctx,
+                )
             }
         }
-        _ => todo!("Can't call type {func_ty:?}"),
+        _ => unsupported!("Can't call type {func_ty:?}"),
     }
-    // Final Jump
-    if let Some(target) = target {
-        trees.push(
-            CILRoot::GoTo {
-                target: target.as_u32(),
-                sub_target: 0,
-            }
-            .into(),
-        );
-    } else {
-        trees.push(CILRoot::throw("Function returning `Never` returned!", ctx.asm_mut()).into());
-    }
-    trees
 }
 pub fn handle_terminator<'tcx>(
     terminator: &Terminator<'tcx>,
@@ -113,18 +162,12 @@ pub fn handle_terminator<'tcx>(
             call_source: _,
             fn_span: _,
         } => handle_call_terminator(terminator, ctx, args, destination, func, *target),
-        TerminatorKind::TailCall { .. } => todo!(),
-        TerminatorKind::Return => {
-            let ret = ctx.monomorphize(ctx.body().return_ty());
-            if ctx.type_from_cache(ret) == cilly::Type::Void {
-                vec![CILRoot::VoidRet.into()]
-            } else {
-                vec![CILRoot::Ret {
-                    tree: CILNode::LDLoc(0),
-                }
-                .into()]
-            }
-        }
+        TerminatorKind::TailCall {
+            func,
+            args,
+            fn_span: _,
+        } => handle_tail_call(terminator, ctx, args, func),
+        TerminatorKind::Return => vec![return_root(ctx).into()],
         TerminatorKind::SwitchInt { discr, targets } => {
             let ty = ctx.monomorphize(discr.ty(ctx.body(), ctx.tcx()));
             let discr = crate::operand::handle_operand(discr, ctx);
@@ -170,13 +213,17 @@ pub fn handle_terminator<'tcx>(
                 .into()]
             } else {
                 match ty.kind() {
-                    TyKind::Dynamic(_, _, rustc_middle::ty::DynKind::Dyn) => {
+                    TyKind::Dynamic(_, _, dyn_kind) => {
                         let fat_ptr_address = crate::place::place_adress(place, ctx);
-                        let fat_ptr_type = ctx.type_from_cache(Ty::new_ptr(
-                            ctx.tcx(),
-                            ty,
-                            rustc_middle::ty::Mutability::Mut,
-                        ));
+                        // A `dyn*` is laid out like a fat pointer to `dyn Trait`.
+                        let fat_ptr_type = match dyn_kind {
+                            rustc_middle::ty::DynKind::Dyn => ctx.type_from_cache(Ty::new_ptr(
+                                ctx.tcx(),
+                                ty,
+                                rustc_middle::ty::Mutability::Mut,
+                            )),
+                            rustc_middle::ty::DynKind::DynStar => ctx.type_from_cache(ty),
+                        };
                         // Get the vtable
                         let vtable_ptr = ld_field!(
                             fat_ptr_address.clone(),
@@ -187,15 +234,21 @@ pub fn handle_terminator<'tcx>(
                             )
                         );
                         let void_ptr = ctx.asm_mut().nptr(Type::Void);
-                        // Get the addres of the object
-                        let obj_ptr = ld_field!(
-                            fat_ptr_address,
-                            FieldDescriptor::new(
-                                fat_ptr_type.as_class_ref().unwrap(),
-                                void_ptr,
-                                crate::DATA_PTR.into()
-                            )
-                        );
+                        // Get the addres of the object. The value of a `dyn*` is stored in place of the data pointer,
+                        // so its drop glue gets a pointer to that.
+                        let obj_ptr = match dyn_kind {
+                            rustc_middle::ty::DynKind::Dyn => ld_field!(
+                                fat_ptr_address,
+                                FieldDescriptor::new(
+                                    fat_ptr_type.as_class_ref().unwrap(),
+                                    void_ptr,
+                                    crate::DATA_PTR.into()
+                                )
+                            ),
+                            rustc_middle::ty::DynKind::DynStar => {
+                                fat_ptr_address.cast_ptr(void_ptr)
+                            }
+                        };
                         // We asusme the drop is the first method in the vtable
                         assert_eq!(
                             rustc_middle::ty::vtable::COMMON_VTABLE_ENTRIES_DROPINPLACE,
@@ -229,9 +282,6 @@ pub fn handle_terminator<'tcx>(
                             .into(),
                         ]
                     }
-                    TyKind::Dynamic(_, _, rustc_middle::ty::DynKind::DynStar) => {
-                        todo!("Can't drop dyn star yet!")
-                    }
                     _ => {
                         let sig =
                             crate::function_sig::sig_from_instance_(drop_instance, ctx).unwrap();
@@ -270,10 +320,13 @@ pub fn handle_terminator<'tcx>(
             eprintln!("Inline assembly is not yet supported!");
             vec![CILRoot::throw("Inline assembly is not yet supported!", ctx.asm_mut()).into()]
         }
-        TerminatorKind::UnwindTerminate(_) => {
+        TerminatorKind::UnwindTerminate(reason) => {
             let loc = terminator.source_info.span;
+            // The rethrow is never reached, and only marks the end of the block. UnwindTerminate is only allowed in
+            // cleanup blocks, so it becomes a throw of the exception being unwound, like the one of UnwindResume.
             vec![
-                rustc_middle::ty::print::with_no_trimmed_paths! {CILRoot::throw(&format!("UnwindTerminate reached at {loc:?}!"),ctx.asm_mut()).into()},
+                rustc_middle::ty::print::with_no_trimmed_paths! {CILRoot::fail_fast(&format!("{reason} at {loc:?}", reason = reason.as_str()),ctx.asm_mut()).into()},
+                CILRoot::ReThrow.into(),
             ]
         }
         TerminatorKind::FalseEdge {
@@ -299,13 +352,13 @@ pub fn handle_terminator<'tcx>(
             }
             .into()]
         }
-        TerminatorKind::CoroutineDrop {} => todo!("Can't drop corutines yet!"),
-        TerminatorKind::Yield {
-            value: _,
-            resume: _,
-            resume_arg: _,
-            drop: _,
-        } => todo!("Can't yeld yet!"), //_ => todo!("Unhandled terminator kind {kind:?}", kind = terminator.kind),
+        // The `StateTransform` pass turns each `Yield` into a return from the resume function, and
+        // replaces `CoroutineDrop` with a plain `Return` in the coroutine drop shim.
+        // Neither terminator can survive until codegen.
+        TerminatorKind::CoroutineDrop | TerminatorKind::Yield { .. } => panic!(
+            "Coroutine terminator {kind:?} encountered during codegen. This is a bug.",
+            kind = terminator.kind
+        ),
     };
     let last = res.last().unwrap().root();
     assert!(
@@ -316,6 +369,8 @@ pub fn handle_terminator<'tcx>(
                 | CILRoot::VoidRet
                 | CILRoot::ReThrow
                 | CILRoot::Throw(_)
+                | CILRoot::Switch { .. }
+                | CILRoot::TailCall(_)
         ),
         "Tree {last:?} did not terminate with an uncoditional jump!."
     );
@@ -328,6 +383,43 @@ fn handle_switch(
     switch: &SwitchTargets,
     asm: &mut Assembly,
 ) -> Vec<CILTree> {
+    let int = match ty.kind() {
+        TyKind::Int(int) => Some(crate::r#type::from_int(int)),
+        TyKind::Uint(uint) => Some(crate::r#type::from_uint(uint)),
+        TyKind::Char => Some(Type::Int(Int::U32)),
+        _ => None,
+    };
+    // A jump table is only worth it with more than one case, and can't hold 128 bit values.
+    if let Some(Type::Int(int)) = int {
+        if int.size().map_or(true, |size| size <= 8) && switch.iter().count() > 1 {
+            // `SwitchTargets` stores the bits of each value, zero extended, just like `cilroot::SwitchTargets`.
+            let cases = switch
+                .iter()
+                .map(|(value, target)| (value as u64, (target.as_u32(), 0)))
+                .collect();
+            let otherwise = (switch.otherwise().as_u32(), 0);
+            let targets = cilroot::SwitchTargets::new(cases, otherwise);
+            // A sparse switch compares its value once per case, so the discriminant is evaluated once, into a temporary.
+            let value = if matches!(targets, cilroot::SwitchTargets::Sparse { .. }) {
+                CILNode::TemporaryLocal(Box::new((
+                    Type::Int(int),
+                    [CILRoot::SetTMPLocal {
+                        value: discr.clone(),
+                    }]
+                    .into(),
+                    CILNode::LoadTMPLocal,
+                )))
+            } else {
+                discr.clone()
+            };
+            return vec![CILRoot::Switch {
+                value,
+                tpe: int,
+                targets: Box::new(targets),
+            }
+            .into()];
+        }
+    }
     let mut trees = Vec::new();
     for (value, target) in switch.iter() {
         //ops.extend(CILOp::debug_msg("Switchin"));
@@ -345,7 +437,7 @@ fn handle_switch(
             TyKind::Char => {
                 crate::constant::load_const_uint(value, rustc_middle::ty::UintTy::U32, asm)
             }
-            _ => todo!("Unsuported switch discriminant type {ty:?}"),
+            _ => unsupported!("Unsuported switch discriminant type {ty:?}"),
         };
         //ops.push(CILOp::LdcI64(value as i64));
         trees.push(
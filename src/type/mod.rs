@@ -8,11 +8,14 @@ use crate::{
     utilis::{adt::FieldOffsetIterator, garg_to_string},
 };
 use cilly::v2::{
-    cilnode::MethodKind, Access, BasicBlock, BinOp, CILNode, CILRoot, ClassDef, ClassDefIdx,
-    ClassRef, ClassRefIdx, Float, Int, MethodDef, MethodImpl, StringIdx, Type,
+    cilnode::MethodKind, tpe::GenericKind, Access, BasicBlock, BinOp, CILNode, CILRoot, ClassDef,
+    ClassDefIdx, ClassRef, ClassRefIdx, Float, Int, MethodDef, MethodImpl, StringIdx, Type,
 };
 pub use r#type::*;
-use rustc_middle::ty::{AdtDef, AdtKind, FloatTy, IntTy, List, ParamEnv, Ty, TyKind, UintTy};
+use rustc_middle::ty::{
+    AdtDef, AdtKind, CoroutineArgsExt, DynKind, FloatTy, GenericArgsRef, IntTy, List, ParamEnv, Ty,
+    TyKind, UintTy,
+};
 use rustc_span::def_id::DefId;
 use rustc_target::abi::Layout;
 //pub use tycache::*;
@@ -76,6 +79,38 @@ fn get_adt<'tcx>(
         cref
     }
 }
+/// Returns the `System.Runtime.Intrinsics.Vector{64,128,256}<T>` a `#[repr(simd)]` type is lowered to.
+/// Returns `None` if `ty` is not a SIMD type, or if .NET has no vector type with the same lanes.
+pub fn simd_vector<'tcx>(
+    ty: Ty<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+) -> Option<ClassRefIdx> {
+    let TyKind::Adt(def, _) = ty.kind() else {
+        return None;
+    };
+    if !def.repr().simd() {
+        return None;
+    }
+    let (lanes, elem) = ty.simd_size_and_type(ctx.tcx());
+    let elem_size = match elem.kind() {
+        TyKind::Int(IntTy::I128) | TyKind::Uint(UintTy::U128) => return None,
+        TyKind::Int(_) | TyKind::Uint(_) | TyKind::Float(FloatTy::F32 | FloatTy::F64) => {
+            ctx.layout_of(elem).layout.size().bytes()
+        }
+        _ => return None,
+    };
+    let size = ctx.layout_of(ty).layout.size().bytes();
+    // Padded vectors(eg. 3 lanes) can't be represented by a .NET vector.
+    if lanes * elem_size != size || !matches!(size, 8 | 16 | 32) {
+        return None;
+    }
+    let elem = get_type(elem, ctx);
+    Some(ClassRef::simd_vector(
+        u32::try_from(size * 8).unwrap(),
+        elem,
+        ctx.asm_mut(),
+    ))
+}
 /// Converts a Rust MIR type to an optimized .NET type representation.
 pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Type {
     let ty = ctx.monomorphize(ty);
@@ -122,7 +157,12 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
             }
             Type::ClassRef(cref)
         }
-        TyKind::Dynamic(_list, _, _) => {
+        // A `dyn*` is a pointer-sized value followed by a vtable pointer, so it has the same layout as `*mut dyn Trait`.
+        TyKind::Dynamic(list, region, DynKind::DynStar) => Type::ClassRef(fat_ptr_to(
+            Ty::new_dynamic(ctx.tcx(), *list, *region, DynKind::Dyn),
+            ctx,
+        )),
+        TyKind::Dynamic(_list, _, DynKind::Dyn) => {
             let name = ctx.asm_mut().alloc_string("Dyn");
             let cref = ctx
                 .asm_mut()
@@ -219,8 +259,8 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
                     )))
                 } else if name.contains(INTEROP_STRUCT_TPE_NAME) {
                     assert!(
-                        subst.len() == 2,
-                        "Managed struct reference must have exactly 2 generic arguments!"
+                        subst.len() == 3,
+                        "Managed struct reference must have exactly 3 generic arguments!"
                     );
                     let assembly = garg_to_string(subst[0], ctx.tcx());
                     let assembly = Some(assembly)
@@ -245,9 +285,43 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
                     }
                 } else if name.contains(INTEROP_CHR_TPE_NAME) {
                     Type::PlatformChar
+                } else if name.contains(INTEROP_GENERIC_CLASS_TPE_NAME)
+                    || name.contains(INTEROP_GENERIC_STRUCT_TPE_NAME)
+                {
+                    let is_valuetype = name.contains(INTEROP_GENERIC_STRUCT_TPE_NAME);
+                    // Generic structs also have their size.
+                    assert!(
+                        subst.len() == 3 + usize::from(is_valuetype),
+                        "Managed generic type reference must have an assembly, a class path and a type argument!"
+                    );
+                    let assembly = garg_to_string(subst[0], ctx.tcx());
+                    let assembly = Some(assembly)
+                        .filter(|assembly| !assembly.is_empty())
+                        .map(|asm| ctx.asm_mut().alloc_string(asm));
+                    let name = garg_to_string(subst[1], ctx.tcx());
+                    let name = ctx.asm_mut().alloc_string(name);
+                    let generic = subst[2]
+                        .as_type()
+                        .expect("The type argument of a generic class must be a type!");
+                    let generic = get_type(ctx.monomorphize(generic), ctx);
+                    Type::ClassRef(ctx.asm_mut().alloc_class_ref(ClassRef::new(
+                        name,
+                        assembly,
+                        is_valuetype,
+                        [generic].into(),
+                    )))
+                } else if name.contains(INTEROP_GENERIC_ARG_TPE_NAME) {
+                    assert!(
+                        subst.len() == 1,
+                        "Managed generic argument must have exactly 1 generic argument: its index!"
+                    );
+                    let idx = garag_to_usize(subst[0], ctx.tcx());
+                    Type::PlatformGeneric(idx.try_into().unwrap(), GenericKind::TypeGeneric)
                 } else {
                     todo!("Interop type {name:?} is not yet supported!")
                 }
+            } else if let Some(vector) = simd_vector(ty, ctx) {
+                Type::ClassRef(vector)
             } else {
                 let name = ctx.asm_mut().alloc_string(name);
                 Type::ClassRef(get_adt(ty, *def, subst, name, ctx))
@@ -387,13 +461,18 @@ pub fn get_type<'tcx>(ty: Ty<'tcx>, ctx: &mut MethodCompileCtx<'tcx, '_>) -> Typ
             Type::ClassRef(cref)
         }
         TyKind::Alias(_, _) => panic!("Attempted to get the .NET type of an unmorphized type"),
-        TyKind::Coroutine(_, _) => {
-            if *crate::config::ABORT_ON_ERROR {
-                panic!("Corutine {ty:?} is not yet supported")
-            } else {
-                eprintln!("WARNING: Corutine {ty:?} is not yet supported. Replacing it with Void to continue anyway, this can cause UB.");
-                Type::Void
+        TyKind::Coroutine(def, args) => {
+            let name = crate::utilis::coroutine_name(*def, args, ctx.tcx());
+            let name = ctx.asm_mut().alloc_string(name);
+            let cref = ctx
+                .asm_mut()
+                .alloc_class_ref(ClassRef::new(name, None, true, [].into()));
+            // If there is no defition of this coroutine present, create the coroutine.
+            if ctx.asm().class_ref_to_def(cref).is_none() {
+                let type_def = coroutine_typedef(ty, *def, args, ctx, name);
+                ctx.asm_mut().class_def(type_def);
             }
+            Type::ClassRef(cref)
         }
         _ => todo!("Can't yet get type {ty:?} from type cache."),
     }
@@ -489,6 +568,82 @@ pub fn closure_typedef(
         ),
     )
 }
+/// Creates a [`ClassDef`] representing the state machine of a coroutine.
+/// A coroutine is laid out like an enum: its upvars are stored at the start, followed by a tag
+/// holding the current state, and the locals saved across each suspension point.
+fn coroutine_typedef<'tcx>(
+    coroutine_ty: Ty<'tcx>,
+    def_id: DefId,
+    args: GenericArgsRef<'tcx>,
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+    coroutine_name: StringIdx,
+) -> ClassDef {
+    let layout = ctx.layout_of(coroutine_ty);
+    let coroutine = args.as_coroutine();
+    let mut fields: Vec<(Type, StringIdx, Option<u32>)> = vec![];
+    // Handle the upvars
+    let upvars: Vec<_> = coroutine.prefix_tys().iter().collect();
+    let offset_iter = FieldOffsetIterator::fields((*layout.layout.0).clone());
+    for ((idx, upvar), offset) in upvars.into_iter().enumerate().zip(offset_iter) {
+        let upvar = get_type(ctx.monomorphize(upvar), ctx);
+        if upvar == Type::Void {
+            continue;
+        }
+        fields.push((
+            upvar,
+            ctx.asm_mut().alloc_string(format!("f_{idx}")),
+            Some(offset),
+        ));
+    }
+    // Handle the state tag
+    let (tag_type, offset) = crate::utilis::adt::enum_tag_info(layout.layout, ctx.asm_mut());
+    if tag_type != Type::Void {
+        fields.push((
+            tag_type,
+            ctx.asm_mut().alloc_string(crate::ENUM_TAG),
+            Some(offset),
+        ));
+    }
+    // Handle the locals saved in each state.
+    let states: Vec<Vec<_>> = coroutine
+        .state_tys(def_id, ctx.tcx())
+        .map(Iterator::collect)
+        .collect();
+    for (vidx, state) in states.into_iter().enumerate() {
+        let vidx = rustc_target::abi::VariantIdx::from_usize(vidx);
+        let field_offset_iter = FieldOffsetIterator::fields(
+            crate::utilis::adt::get_variant_at_index(vidx, (*layout.layout.0).clone()),
+        );
+        for ((fidx, field), offset) in state.into_iter().enumerate().zip(field_offset_iter) {
+            let field_ty = get_type(ctx.monomorphize(field), ctx);
+            if field_ty == Type::Void {
+                continue;
+            }
+            let name = crate::utilis::coroutine_field_name(vidx, fidx);
+            fields.push((field_ty, ctx.asm_mut().alloc_string(name), Some(offset)));
+        }
+    }
+    ClassDef::new(
+        coroutine_name,
+        true,
+        0,
+        None,
+        fields,
+        vec![],
+        Access::Public,
+        Some(
+            NonZeroU32::new(
+                layout
+                    .layout
+                    .size()
+                    .bytes()
+                    .try_into()
+                    .expect("Coroutine size exceeds 2^32"),
+            )
+            .unwrap(),
+        ),
+    )
+}
 /// Turns an adt struct defintion into a [`ClassDef`]
 fn struct_<'tcx>(
     name: StringIdx,
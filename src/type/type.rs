@@ -8,7 +8,7 @@ use cilly::{
     call_site::CallSite,
     cil_node::CILNode,
     ldc_u32, ldc_u64,
-    v2::{Assembly, ClassRef, ClassRefIdx, FnSig, Int},
+    v2::{tpe::GenericKind, Assembly, ClassRef, ClassRefIdx, FnSig, Int},
     Type,
 };
 use rustc_middle::ty::{AdtDef, ConstKind, GenericArg, ParamEnv, Ty, TyCtxt, TyKind};
@@ -52,6 +52,9 @@ pub const INTEROP_CLASS_TPE_NAME: &str = "RustcCLRInteropManagedClass";
 pub const INTEROP_STRUCT_TPE_NAME: &str = "RustcCLRInteropManagedStruct";
 pub const INTEROP_CHR_TPE_NAME: &str = "RustcCLRInteropManagedChar";
 pub const INTEROP_ARR_TPE_NAME: &str = "RustcCLRInteropManagedArray";
+pub const INTEROP_GENERIC_CLASS_TPE_NAME: &str = "RustcCLRInteropManagedGenericClass";
+pub const INTEROP_GENERIC_STRUCT_TPE_NAME: &str = "RustcCLRInteropManagedGenericStruct";
+pub const INTEROP_GENERIC_ARG_TPE_NAME: &str = "RustcCLRInteropManagedGenericArg";
 #[must_use]
 /// Checks if a type is a magic interop type.
 pub fn is_name_magic(name: &str) -> bool {
@@ -110,6 +113,39 @@ pub fn magic_type<'tcx>(
         }
     } else if name.contains(INTEROP_CHR_TPE_NAME) {
         Type::PlatformChar
+    } else if name.contains(INTEROP_GENERIC_CLASS_TPE_NAME)
+        || name.contains(INTEROP_GENERIC_STRUCT_TPE_NAME)
+    {
+        let is_valuetype = name.contains(INTEROP_GENERIC_STRUCT_TPE_NAME);
+        // Generic structs also have their size.
+        assert!(
+            subst.len() == 3 + usize::from(is_valuetype),
+            "Managed generic type reference must have an assembly, a class path and a type argument!"
+        );
+        let assembly = garg_to_string(subst[0], ctx.tcx());
+        let assembly = Some(assembly)
+            .filter(|assembly| !assembly.is_empty())
+            .map(|a| ctx.asm_mut().alloc_string(a));
+        let name = garg_to_string(subst[1], ctx.tcx());
+        let name = ctx.asm_mut().alloc_string(name);
+        let generic = subst[2]
+            .as_type()
+            .expect("The type argument of a generic class must be a type!");
+        let generic = get_type(ctx.monomorphize(generic), ctx);
+        let dotnet_tpe = ctx.asm_mut().alloc_class_ref(ClassRef::new(
+            name,
+            assembly,
+            is_valuetype,
+            [generic].into(),
+        ));
+        Type::ClassRef(dotnet_tpe)
+    } else if name.contains(INTEROP_GENERIC_ARG_TPE_NAME) {
+        assert!(
+            subst.len() == 1,
+            "Managed generic argument must have exactly 1 generic argument: its index!"
+        );
+        let idx = garag_to_usize(subst[0], ctx.tcx());
+        Type::PlatformGeneric(idx.try_into().unwrap(), GenericKind::TypeGeneric)
     } else {
         todo!("Interop type {name:?} is not yet supported!")
     }
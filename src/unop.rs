@@ -4,11 +4,11 @@ use cilly::call_site::CallSite;
 use cilly::cil_node::CILNode;
 use cilly::field_desc::FieldDescriptor;
 
-use cilly::v2::{ClassRef, FnSig, Int};
+use cilly::v2::{ClassRef, Float, FnSig, Int};
 use cilly::{call, ld_field, Type};
 
 use rustc_middle::mir::{Operand, UnOp};
-use rustc_middle::ty::{IntTy, TyKind, UintTy};
+use rustc_middle::ty::{FloatTy, IntTy, TyKind, UintTy};
 
 /// Implements an unary operation, such as negation.
 pub fn unop<'tcx>(
@@ -40,6 +40,26 @@ pub fn unop<'tcx>(
                 ),
                 [parrent_node]
             ),
+            TyKind::Float(FloatTy::F16) => call!(
+                CallSite::boxed(
+                    ClassRef::half(ctx.asm_mut()).into(),
+                    "op_UnaryNegation".into(),
+                    FnSig::new(Box::new([Type::Float(Float::F16)]), Type::Float(Float::F16)),
+                    true,
+                ),
+                [parrent_node]
+            ),
+            TyKind::Float(FloatTy::F128) => call!(
+                CallSite::builtin(
+                    "__negtf2".into(),
+                    FnSig::new(
+                        Box::new([Type::Float(Float::F128)]),
+                        Type::Float(Float::F128)
+                    ),
+                    true,
+                ),
+                [parrent_node]
+            ),
             _ => CILNode::Neg(parrent_node.into()),
         },
         UnOp::Not => match ty.kind() {
@@ -1,6 +1,7 @@
 use crate::assembly::MethodCompileCtx;
 use crate::operand::{handle_operand, operand_address};
 use crate::r#type::fat_ptr_to;
+use crate::unsupported;
 use cilly::cil_node::CILNode;
 use cilly::cil_root::CILRoot;
 use cilly::field_desc::FieldDescriptor;
@@ -110,6 +111,59 @@ pub fn unsize2<'tcx>(
 
     res
 }
+/// Converts `operand`, which must be pointer-sized, to the `dyn*` type `target`.
+pub fn dyn_star<'tcx>(
+    ctx: &mut MethodCompileCtx<'tcx, '_>,
+    operand: &Operand<'tcx>,
+    target: Ty<'tcx>,
+) -> CILNode {
+    let target = ctx.monomorphize(target);
+    let source = ctx.monomorphize(operand.ty(ctx.body(), ctx.tcx()));
+    let TyKind::Dynamic(data, _, _) = target.kind() else {
+        panic!("DynStar cast to {target:?}, which is not a `dyn*` type");
+    };
+    let vtable = get_vtable(ctx, source, data.principal());
+    // A `dyn*` has the same layout as a fat pointer to `dyn Trait`.
+    let target_type = ctx.type_from_cache(target);
+    let fat_ptr_type = target_type
+        .as_class_ref()
+        .expect("A `dyn*` must be represented by a fat pointer");
+    let void_ptr = ctx.asm_mut().nptr(Type::Void);
+    let source_type = ctx.type_from_cache(source);
+    // The value itself is stored in place of the data pointer.
+    let value = match source_type {
+        Type::Ptr(_) | Type::FnPtr(_) | Type::Int(Int::USize | Int::ISize) => {
+            handle_operand(operand, ctx).cast_ptr(void_ptr)
+        }
+        _ => CILNode::TemporaryLocal(Box::new((
+            source_type,
+            Box::new([CILRoot::SetTMPLocal {
+                value: handle_operand(operand, ctx),
+            }]),
+            CILNode::LDIndPtr {
+                ptr: Box::new(CILNode::LoadAddresOfTMPLocal.cast_ptr(ctx.asm_mut().nptr(void_ptr))),
+                loaded_ptr: Box::new(void_ptr),
+            },
+        ))),
+    };
+    CILNode::TemporaryLocal(Box::new((
+        target_type,
+        [
+            CILRoot::set_field(
+                CILNode::LoadAddresOfTMPLocal,
+                value,
+                FieldDescriptor::new(fat_ptr_type, void_ptr, crate::DATA_PTR.into()),
+            ),
+            CILRoot::set_field(
+                CILNode::LoadAddresOfTMPLocal,
+                vtable.cast_ptr(Type::Int(Int::USize)),
+                FieldDescriptor::new(fat_ptr_type, Type::Int(Int::USize), crate::METADATA.into()),
+            ),
+        ]
+        .into(),
+        CILNode::LoadTMPLocal,
+    )))
+}
 /// Adopted from <https://github.com/rust-lang/rustc_codegen_cranelift/blob/45600348c009303847e8cddcfa8483f1f3d56625/src/unsize.rs#L64>
 pub(crate) fn unsized_info<'tcx>(
     ctx: &mut MethodCompileCtx<'tcx, '_>,
@@ -182,7 +236,7 @@ pub(crate) fn get_vtable<'tcx>(
     let ty = fx.monomorphize(ty);
     let alloc_id = fx.tcx().vtable_allocation((ty, trait_ref));
     CILNode::LoadGlobalAllocPtr {
-        alloc_id: alloc_id.0.get(),
+        alloc_id: crate::utilis::alloc_id_to_u64(alloc_id),
     }
 }
 /// Coerce `src`, which is a reference to a value of type `src_ty`,
@@ -208,6 +262,11 @@ pub(crate) fn unsize_metadata<'tcx>(
     match (&src_ty.ty.kind(), &dst_ty.ty.kind()) {
         (&TyKind::Ref(..), &TyKind::Ref(..) | &TyKind::RawPtr(..))
         | (&TyKind::RawPtr(..), &TyKind::RawPtr(..)) => coerce_ptr(),
+        // Upcasting a `dyn*`: its vtable is stored after the data, just like the metadata of a fat pointer.
+        (&TyKind::Dynamic(..), &TyKind::Dynamic(..)) => {
+            let (_, old_info) = load_scalar_pair(src_cil, fx);
+            unsized_info(fx, src_ty.ty, dst_ty.ty, Some(old_info))
+        }
         (&TyKind::Adt(def_a, subst_a), &TyKind::Adt(def_b, subst_b)) => {
             assert_eq!(def_a, def_b);
 
@@ -224,7 +283,11 @@ pub(crate) fn unsize_metadata<'tcx>(
                     return unsize_metadata(fx, src_cil, src_f_ty, dst_f_ty);
                 }
             }
-            todo!()
+            unsupported!(
+                "Can't unsize {:?} to {:?}, since none of its fields change type",
+                src_ty.ty,
+                dst_ty.ty
+            )
         }
         _ => panic!("unsize_metadata: invalid coercion {src_ty:?} -> {dst_ty:?}",),
     }
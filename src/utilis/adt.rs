@@ -140,7 +140,7 @@ fn primitive_to_type(primitive: rustc_target::abi::Primitive, asm: &mut Assembly
         Primitive::Float(rustc_abi::Float::F16) => Type::Float(Float::F16),
         Primitive::Float(rustc_abi::Float::F32) => Type::Float(Float::F32),
         Primitive::Float(rustc_abi::Float::F64) => Type::Float(Float::F64),
-        Primitive::Float(rustc_abi::Float::F128) => todo!("No support for 128 bit floats yet!"),
+        Primitive::Float(rustc_abi::Float::F128) => Type::Float(Float::F128),
         Primitive::Pointer(_) => asm.nptr(Type::Void),
     }
 }
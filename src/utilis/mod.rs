@@ -3,21 +3,28 @@ use cilly::{field_desc::FieldDescriptor, utilis::escape_class_name};
 use rustc_middle::{
     mir::interpret::AllocId,
     ty::{
-        AdtDef, Const, ConstKind, EarlyBinder, GenericArg, Instance, List, ParamEnv, SymbolName,
-        Ty, TyCtxt, TyKind, TypeFoldable,
+        AdtDef, Const, ConstKind, CoroutineArgs, CoroutineArgsExt, EarlyBinder, GenericArg,
+        GenericArgsRef, Instance, List, ParamEnv, SymbolName, Ty, TyCtxt, TyKind, TypeFoldable,
     },
 };
+use rustc_span::def_id::DefId;
+use rustc_target::abi::VariantIdx;
 pub mod adt;
 pub const CTOR_FN_NAME: &str = "rustc_clr_interop_managed_ctor";
 pub const MANAGED_CALL_FN_NAME: &str = "rustc_clr_interop_managed_call";
 pub const MANAGED_CALL_VIRT_FN_NAME: &str = "rustc_clr_interop_managed_call_virt";
+pub const GENERIC_CTOR_FN_NAME: &str = "rustc_clr_interop_generic_ctor";
+pub const GENERIC_CALL_FN_NAME: &str = "rustc_clr_interop_generic_call";
 pub const MANAGED_LD_LEN: &str = "rustc_clr_interop_managed_ld_len";
 pub const MANAGED_LD_NULL: &str = "rustc_clr_interop_managed_ld_null";
 pub const MANAGED_CHECKED_CAST: &str = "rustc_clr_interop_managed_checked_cast";
 pub const MANAGED_IS_INST: &str = "rustc_clr_interop_managed_is_inst";
 pub const MANAGED_LD_ELEM_REF: &str = "rustc_clr_interop_managed_ld_elem_ref";
 pub fn is_function_magic(name: &str) -> bool {
-    name.contains(CTOR_FN_NAME) || name.contains(MANAGED_CALL_FN_NAME)
+    name.contains(CTOR_FN_NAME)
+        || name.contains(MANAGED_CALL_FN_NAME)
+        || name.contains(GENERIC_CTOR_FN_NAME)
+        || name.contains(GENERIC_CALL_FN_NAME)
 }
 pub fn as_adt(ty: Ty) -> Option<(AdtDef, &List<GenericArg>)> {
     match ty.kind() {
@@ -54,6 +61,27 @@ pub fn adt_name<'tcx>(
     let dotnet_class_name = demangled.replace("::", ".");
     escape_class_name(&dotnet_class_name).into()
 }
+/// Gets the name of the state machine type of the coroutine `def_id`, instantiated with `args`.
+pub fn coroutine_name<'tcx>(
+    def_id: DefId,
+    args: GenericArgsRef<'tcx>,
+    tcx: TyCtxt<'tcx>,
+) -> crate::IString {
+    // The resume function of a coroutine has an unique symbol, so it can be used to name the coroutine itself.
+    let instance = Instance::new(def_id, args);
+    let auto_mangled =
+        rustc_symbol_mangling::symbol_name_for_instance_in_crate(tcx, instance, def_id.krate);
+    let demangled = format!("{}", rustc_demangle::demangle(&auto_mangled));
+    let dotnet_class_name = demangled.replace("::", ".");
+    escape_class_name(&dotnet_class_name).into()
+}
+/// Gets the name of the field `field_idx` of the coroutine variant(state) `variant_idx`.
+pub fn coroutine_field_name(variant_idx: VariantIdx, field_idx: usize) -> String {
+    format!(
+        "{variant_name}_{field_idx}",
+        variant_name = CoroutineArgs::variant_name(variant_idx)
+    )
+}
 
 /// Gets the name of a field with index `idx`
 pub fn field_name(ty: Ty, idx: u32) -> crate::IString {
@@ -119,6 +147,25 @@ pub fn enum_field_descriptor<'tcx>(
     variant_idx: u32,
     ctx: &mut MethodCompileCtx<'tcx, '_>,
 ) -> FieldDescriptor {
+    if let TyKind::Coroutine(def_id, args) = owner_ty.kind() {
+        // Fields of coroutine variants are the locals saved across a given suspension point.
+        let field_ty = args
+            .as_coroutine()
+            .state_tys(*def_id, ctx.tcx())
+            .nth(variant_idx as usize)
+            .expect("No coroutine variant with such index!")
+            .nth(field_idx as usize)
+            .expect("No coroutine field with provided index!");
+        let field_ty = ctx.monomorphize(field_ty);
+        let field_ty = ctx.type_from_cache(field_ty);
+        let owner_ty = ctx
+            .type_from_cache(owner_ty)
+            .as_class_ref()
+            .expect("Coroutine type invalid!");
+        let field_name =
+            coroutine_field_name(VariantIdx::from_u32(variant_idx), field_idx as usize);
+        return FieldDescriptor::new(owner_ty, field_ty, field_name.into());
+    }
     let (adt, subst) = as_adt(owner_ty).expect("Tried to get a field of a non ADT or tuple type!");
     let variant = adt
         .variants()
@@ -186,6 +233,24 @@ pub fn field_descrptor<'tcx>(
             field_type,
             field_name,
         );
+    } else if let TyKind::Coroutine(_, args) = owner_ty.kind() {
+        // Fields of the coroutine itself are its upvars.
+        let field_type = args
+            .as_coroutine()
+            .prefix_tys()
+            .iter()
+            .nth(field_idx as usize)
+            .expect("Could not find coroutine fields!");
+        let field_type = ctx.monomorphize(field_type);
+        let field_type = ctx.type_from_cache(field_type);
+        let owner_ty = ctx.monomorphize(owner_ty);
+        let owner_type = ctx.type_from_cache(owner_ty);
+        let field_name = format!("f_{field_idx}").into();
+        return FieldDescriptor::new(
+            owner_type.as_class_ref().expect("Coroutine type invalid!"),
+            field_type,
+            field_name,
+        );
     }
     let (adt, subst) = as_adt(owner_ty).expect("Tried to get a field of a non ADT or tuple type!");
     let field = adt
@@ -295,7 +360,7 @@ pub(crate) fn alloc_id_to_u64(alloc_id: AllocId) -> u64 {
 pub fn is_fn_intrinsic<'tcx>(fn_ty: Ty<'tcx>, tcx: TyCtxt<'tcx>) -> bool {
     match fn_ty.kind() {
         TyKind::FnDef(did, _) => tcx.is_intrinsic(*did, tcx.item_name(*did)),
-        TyKind::Closure(_, _) => false,
+        TyKind::Closure(_, _) | TyKind::Coroutine(_, _) => false,
         _ => todo!("Can't get signature of {fn_ty}"),
     }
 }
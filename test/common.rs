@@ -2,7 +2,7 @@
 use core::intrinsics::sqrtf32;
 use core::panic::PanicInfo;
 #[allow(dead_code)]
-extern "C" {
+unsafe extern "C" {
     fn puts(msg: *const core::ffi::c_char);
     fn malloc(size: usize) -> *mut core::ffi::c_void;
     fn free(ptr: *mut core::ffi::c_void);
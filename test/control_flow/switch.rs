@@ -0,0 +1,82 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+// Close values, lowered to a jump table.
+fn dense(val: u8) -> u32 {
+    match val {
+        0 => 10,
+        1 => 11,
+        2 => 12,
+        4 => 14,
+        5 => 15,
+        _ => 0,
+    }
+}
+// Signed values around 0, whose bits are not next to each other.
+fn dense_signed(val: i8) -> u32 {
+    match val {
+        -2 => 1,
+        -1 => 2,
+        0 => 3,
+        1 => 4,
+        _ => 0,
+    }
+}
+// Wide values, which can't just be truncated to 32 bits.
+fn dense_wide(val: u64) -> u32 {
+    match val {
+        0x1_0000_0000 => 1,
+        0x1_0000_0001 => 2,
+        0x1_0000_0002 => 3,
+        _ => 0,
+    }
+}
+// Values far apart.
+fn sparse(val: i64) -> u32 {
+    match val {
+        i64::MIN => 1,
+        -1000 => 2,
+        7 => 3,
+        i64::MAX => 4,
+        _ => 0,
+    }
+}
+fn chars(val: char) -> u32 {
+    match val {
+        'a' => 1,
+        'b' => 2,
+        'c' => 3,
+        'ą' => 4,
+        _ => 0,
+    }
+}
+fn main() {
+    test_eq!(dense(black_box(0)), 10);
+    test_eq!(dense(black_box(2)), 12);
+    test_eq!(dense(black_box(3)), 0);
+    test_eq!(dense(black_box(5)), 15);
+    test_eq!(dense(black_box(255)), 0);
+    test_eq!(dense_signed(black_box(-2)), 1);
+    test_eq!(dense_signed(black_box(1)), 4);
+    test_eq!(dense_signed(black_box(-3)), 0);
+    test_eq!(dense_signed(black_box(i8::MIN)), 0);
+    test_eq!(dense_wide(black_box(0x1_0000_0001)), 2);
+    test_eq!(dense_wide(black_box(1)), 0);
+    test_eq!(dense_wide(black_box(0xFFFF_FFFF)), 0);
+    test_eq!(dense_wide(black_box(u64::MAX)), 0);
+    test_eq!(sparse(black_box(i64::MIN)), 1);
+    test_eq!(sparse(black_box(-1000)), 2);
+    test_eq!(sparse(black_box(i64::MAX)), 4);
+    test_eq!(sparse(black_box(8)), 0);
+    test_eq!(chars(black_box('c')), 3);
+    test_eq!(chars(black_box('ą')), 4);
+    test_eq!(chars(black_box('d')), 0);
+}
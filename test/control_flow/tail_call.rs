@@ -0,0 +1,46 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params,
+    explicit_tail_calls
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+// Self-recursive, so the optimizer turns it into a loop.
+fn sum_to(n: u64, acc: u64) -> u64 {
+    if n == 0 {
+        return acc;
+    }
+    become sum_to(n - 1, acc + n)
+}
+// Mutually recursive, so it needs real tail calls not to overflow the stack.
+fn is_even(n: u32) -> bool {
+    if n == 0 {
+        return true;
+    }
+    become is_odd(n - 1)
+}
+fn is_odd(n: u32) -> bool {
+    if n == 0 {
+        return false;
+    }
+    become is_even(n - 1)
+}
+fn add_one(val: u32) -> u32 {
+    val + 1
+}
+// Calls through a fn pointer, with the same signature as the caller.
+fn indirect(val: u32) -> u32 {
+    let f: fn(u32) -> u32 = black_box(add_one);
+    become f(val)
+}
+fn main() {
+    test_eq!(sum_to(black_box(100_000), 0), 5_000_050_000);
+    test!(is_even(black_box(100_000)));
+    test!(!is_odd(black_box(100_000)));
+    test_eq!(indirect(black_box(41)), 42);
+}
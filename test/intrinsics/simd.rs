@@ -0,0 +1,74 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    repr_simd,
+    start,
+    unsized_const_params
+)]
+#![allow(
+    internal_features,
+    incomplete_features,
+    unused_variables,
+    dead_code,
+    unused_imports
+)]
+#![no_std]
+include!("../common.rs");
+use core::intrinsics::simd::*;
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq)]
+struct I32x4([i32; 4]);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq)]
+struct F32x4([f32; 4]);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq)]
+struct U8x8([u8; 8]);
+// Has no .NET equivalent, so it is always lowered lane by lane.
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq)]
+struct U16x3([u16; 3]);
+#[repr(simd)]
+struct Idx4([u32; 4]);
+fn main() {
+    let a = black_box(I32x4([1, 2, 3, 4]));
+    let b = black_box(I32x4([10, 20, 30, 40]));
+    unsafe {
+        test_eq!(simd_add(a, b).0, [11, 22, 33, 44]);
+        test_eq!(simd_sub(b, a).0, [9, 18, 27, 36]);
+        test_eq!(simd_mul(a, b).0, [10, 40, 90, 160]);
+        test_eq!(simd_xor(a, a).0, [0, 0, 0, 0]);
+        test_eq!(simd_shl(a, I32x4([1, 1, 2, 2])).0, [2, 4, 12, 16]);
+        test_eq!(simd_neg(a).0, [-1, -2, -3, -4]);
+        let mask: I32x4 = simd_lt(a, I32x4([2, 2, 2, 2]));
+        test_eq!(mask.0, [-1, 0, 0, 0]);
+        let mask: I32x4 = simd_ne(a, I32x4([1, 0, 3, 0]));
+        test_eq!(mask.0, [0, -1, 0, -1]);
+        test_eq!(simd_select(mask, a, b).0, [10, 2, 30, 4]);
+        test_eq!(simd_reduce_add_unordered(a), 10);
+        test_eq!(simd_reduce_add_ordered(a, 5), 15);
+        test_eq!(simd_reduce_mul_unordered(a), 24);
+        test_eq!(simd_reduce_max(a), 4);
+        test_eq!(simd_reduce_min(b), 10);
+        test!(simd_reduce_any(mask));
+        test!(!simd_reduce_all(mask));
+        test_eq!(simd_extract::<_, i32>(a, 2), 3);
+        test_eq!(simd_insert(a, 1, 7).0, [1, 7, 3, 4]);
+        let shuffled: I32x4 = simd_shuffle(a, b, const { Idx4([0, 4, 3, 7]) });
+        test_eq!(shuffled.0, [1, 10, 4, 40]);
+        let f: F32x4 = simd_cast(a);
+        test_eq!(f.0, [1.0, 2.0, 3.0, 4.0]);
+        let f = simd_div(f, F32x4([2.0, 2.0, 2.0, 2.0]));
+        test_eq!(f.0, [0.5, 1.0, 1.5, 2.0]);
+        test_eq!(simd_reduce_add_ordered(f, 0.0), 5.0);
+        let back: I32x4 = simd_cast(simd_fsqrt(F32x4([4.0, 9.0, 16.0, 25.0])));
+        test_eq!(back.0, [2, 3, 4, 5]);
+        let bytes = black_box(U8x8([250, 1, 2, 3, 4, 5, 6, 7]));
+        test_eq!(simd_add(bytes, bytes).0, [244, 2, 4, 6, 8, 10, 12, 14]);
+        let small = black_box(U16x3([1, 2, 3]));
+        test_eq!(simd_add(small, small).0, [2, 4, 6]);
+        test_eq!(simd_reduce_xor(small), 0);
+    }
+}
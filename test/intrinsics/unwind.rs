@@ -0,0 +1,81 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+extern "C-unwind" {
+    // Throws a `RustException` carrying `payload`.
+    fn _Unwind_RaiseException(payload: *mut u8) -> i32;
+}
+static mut DROPPED: u32 = 0;
+struct Guard;
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe { DROPPED += 1 };
+    }
+}
+fn unwinds(data: *mut u8) {
+    let guard = Guard;
+    unsafe { _Unwind_RaiseException(black_box(data)) };
+}
+fn returns(data: *mut u8) {
+    let guard = Guard;
+}
+fn catch(data: *mut u8, payload: *mut u8) {
+    // The payload is the pointer the exception was raised with.
+    test_eq!(data, payload);
+    unsafe { *data += 1 };
+}
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+type Object = RustcCLRInteropManagedClass<"System.Runtime", "System.Object">;
+static mut FOREIGN: u8 = 0;
+/// Used by `catch_unwind` to turn a foreign .NET exception into a panic payload.
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn exception_to_native(exception: Object) -> *mut u8 {
+    unsafe { core::ptr::addr_of_mut!(FOREIGN) }
+}
+fn throws_foreign(data: *mut u8) {
+    let guard = Guard;
+    // `Math.Abs(int.MinValue)` throws an `OverflowException`, which unwinds trough this frame.
+    rustc_clr_interop_managed_call1_::<"System.Runtime", "System.Math", false, "Abs", true, i32, i32>(
+        black_box(i32::MIN),
+    );
+}
+fn catch_foreign(data: *mut u8, payload: *mut u8) {
+    test_eq!(payload, unsafe { core::ptr::addr_of_mut!(FOREIGN) });
+    unsafe { *data += 1 };
+}
+fn main() {
+    let mut caught: u8 = 0;
+    let data = core::ptr::addr_of_mut!(caught);
+    // The destructor runs both when returning, and when unwinding.
+    test_eq!(
+        unsafe { core::intrinsics::catch_unwind(returns, data, catch) },
+        0
+    );
+    test_eq!(unsafe { DROPPED }, 1);
+    test_eq!(caught, 0);
+    test_eq!(
+        unsafe { core::intrinsics::catch_unwind(unwinds, data, catch) },
+        1
+    );
+    test_eq!(unsafe { DROPPED }, 2);
+    test_eq!(caught, 1);
+    // A .NET exception runs the destructors of the Rust frames it crosses, and is caught as a panic.
+    test_eq!(
+        unsafe { core::intrinsics::catch_unwind(throws_foreign, data, catch_foreign) },
+        1
+    );
+    test_eq!(unsafe { DROPPED }, 3);
+    test_eq!(caught, 2);
+}
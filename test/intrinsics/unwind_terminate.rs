@@ -0,0 +1,31 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+extern "C-unwind" {
+    // Throws a `RustException` carrying `payload`.
+    fn _Unwind_RaiseException(payload: *mut u8) -> i32;
+}
+struct PanicsOnDrop;
+impl Drop for PanicsOnDrop {
+    fn drop(&mut self) {
+        unsafe { _Unwind_RaiseException(core::ptr::null_mut()) };
+    }
+}
+fn unwinds_twice(data: *mut u8) {
+    let guard = PanicsOnDrop;
+    unsafe { _Unwind_RaiseException(black_box(data)) };
+}
+fn catch(data: *mut u8, payload: *mut u8) {}
+fn main() {
+    // The destructor of `guard` raises a second exception while the first one unwinds. This must abort the program, so
+    // `catch_unwind` never returns, and this test is expected to fail.
+    unsafe { core::intrinsics::catch_unwind(unwinds_twice, core::ptr::null_mut(), catch) };
+}
@@ -0,0 +1,121 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    coroutines,
+    coroutine_trait,
+    noop_waker,
+    start,
+    stmt_expr_attributes,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::cell::Cell;
+use core::future::Future;
+use core::ops::{Coroutine, CoroutineState};
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+/// Counts how many times it was dropped.
+struct DropCounter<'a>(&'a Cell<u32>);
+impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+/// A future, which is pending the first time it is polled.
+struct YieldOnce(bool);
+impl Future for YieldOnce {
+    type Output = ();
+    fn poll(mut self: core::pin::Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            Poll::Pending
+        }
+    }
+}
+fn main() {
+    let start = black_box(5_u32);
+    let mut counter = pin!(
+        #[coroutine]
+        move || {
+            let mut x = start;
+            yield x;
+            x += black_box(1);
+            yield x;
+            x * 2
+        }
+    );
+    test!(matches!(
+        counter.as_mut().resume(()),
+        CoroutineState::Yielded(5)
+    ));
+    test!(matches!(
+        counter.as_mut().resume(()),
+        CoroutineState::Yielded(6)
+    ));
+    test!(matches!(
+        counter.as_mut().resume(()),
+        CoroutineState::Complete(12)
+    ));
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut future = pin!(async_fn(8, black_box(9.5)));
+    match future.as_mut().poll(&mut cx) {
+        Poll::Ready(val) => test_eq!(val, 17.5),
+        Poll::Pending => test!(false),
+    }
+    // Dropping a suspended coroutine drops its upvars and the locals live across the current yield point.
+    let drops = Cell::new(0);
+    {
+        let upvar = DropCounter(&drops);
+        let mut suspended = pin!(
+            #[coroutine]
+            move || {
+                let local = DropCounter(upvar.0);
+                yield 1_u32;
+                drop(local);
+                drop(upvar);
+                2_u32
+            }
+        );
+        test!(matches!(
+            suspended.as_mut().resume(()),
+            CoroutineState::Yielded(1)
+        ));
+        test_eq!(drops.get(), 0);
+    }
+    test_eq!(drops.get(), 2);
+    // A coroutine which was never resumed only owns its upvars.
+    {
+        let upvar = DropCounter(&drops);
+        let unresumed = #[coroutine]
+        move || {
+            yield ();
+            drop(upvar);
+        };
+    }
+    test_eq!(drops.get(), 3);
+    // The same goes for an async fn waiting on another future.
+    {
+        let mut pending = pin!(async_drop(DropCounter(&drops)));
+        test!(matches!(pending.as_mut().poll(&mut cx), Poll::Pending));
+        test_eq!(drops.get(), 3);
+    }
+    test_eq!(drops.get(), 5);
+}
+async fn async_drop(arg: DropCounter<'_>) {
+    let local = DropCounter(arg.0);
+    YieldOnce(false).await;
+    drop(local);
+    drop(arg);
+}
+async fn async_fn(a: i32, b: f32) -> f32 {
+    add_async(a as f32, b).await
+}
+async fn add_async(a: f32, b: f32) -> f32 {
+    a + black_box(b)
+}
@@ -4,6 +4,7 @@
     core_intrinsics,
     start,
     unsized_const_params,
+    f16,
     f128
 )]
 #![allow(internal_features, incomplete_features, unused_variables, dead_code)]
@@ -25,4 +26,30 @@ fn main() {
     test_eq!(black_box(one * one), one);
     // Check that division works
     test_eq!(black_box(two / one), two);
+    // Check that comparisons work
+    let three = black_box(3_f128);
+    let four = black_box(4_f128);
+    test!(three < four);
+    test!(four > three);
+    test!(three <= three);
+    test!(four >= three);
+    test!(black_box(f128::NAN) != black_box(f128::NAN));
+    test!(!(black_box(f128::NAN) >= three));
+    // Check that negation works
+    test_eq!(-three, black_box(-3_f128));
+    // Check that casts work
+    test_eq!(three as f64, 3.0_f64);
+    test_eq!(black_box(1.5_f32) as f128, black_box(1.5_f128));
+    test_eq!(black_box(-7_i64) as f128, black_box(-7_f128));
+    test_eq!(four as u8, 4);
+    test_eq!(black_box(f128::NAN) as i32, 0);
+    test_eq!(black_box(f128::INFINITY) as i16, i16::MAX);
+    test_eq!(black_box(true as u8) as f128, black_box(1_f128));
+    test_eq!(three as f16, 3.0_f16);
+    // Just above the midpoint between 1.0 and the next f16. Rounding it to a f64 first would land on the midpoint, which
+    // then rounds down to 1.0.
+    let above_midpoint = f128::from_bits(black_box(0x3fff_0020_0000_0000_0010_0000_0000_0000));
+    test_eq!(above_midpoint as f16, 1.0009765625_f16);
+    // Check that intrinsics work
+    test_eq!(unsafe { core::intrinsics::sqrtf128(black_box(16_f128)) }, four);
 }
@@ -0,0 +1,40 @@
+#![feature(
+    lang_items,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params,
+    f16
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+fn main() {
+    let zero = black_box(0.0_f16);
+    let one = black_box(1.0_f16);
+    let two = black_box(2.0_f16);
+    // Arithmetic
+    test_eq!(black_box(one + one), two);
+    test_eq!(black_box(one - one), zero);
+    test_eq!(black_box(two * two), 4.0_f16);
+    test_eq!(black_box(one / two), 0.5_f16);
+    test_eq!(black_box(-one), -1.0_f16);
+    // Comparisons
+    test!(one < two);
+    test!(two > one);
+    test!(one <= one);
+    test!(two >= one);
+    test!(black_box(f16::NAN) != black_box(f16::NAN));
+    test!(!(black_box(f16::NAN) >= one));
+    // Casts
+    test_eq!(black_box(two) as f32, 2.0_f32);
+    test_eq!(black_box(2.5_f64) as f16, 2.5_f16);
+    test_eq!(black_box(7_i32) as f16, 7.0_f16);
+    test_eq!(black_box(7.5_f16) as u8, 7);
+    test_eq!(black_box(f16::NAN) as i32, 0);
+    test_eq!(black_box(f16::INFINITY) as u8, u8::MAX);
+    // Intrinsics
+    test_eq!(unsafe { core::intrinsics::sqrtf16(black_box(4.0)) }, two);
+    test_eq!(unsafe { core::intrinsics::floorf16(black_box(2.5)) }, two);
+    test_eq!(unsafe { core::intrinsics::ceilf16(black_box(1.5)) }, two);
+}
@@ -0,0 +1,45 @@
+//@ edition: 2024
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    gen_blocks,
+    start,
+    unsized_const_params
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::cell::Cell;
+/// Counts how many times it was dropped.
+struct DropCounter<'a>(&'a Cell<u32>);
+impl Drop for DropCounter<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+fn main() {
+    let limit = black_box(5_u32);
+    let squares = gen move {
+        for i in 0..limit {
+            yield i * i;
+        }
+    };
+    test_eq!(squares.sum::<u32>(), 30);
+    // A gen block, which is not run to completion, drops its state.
+    let drops = Cell::new(0);
+    let mut evens = gen {
+        let _guard = DropCounter(&drops);
+        let mut i = 0_u32;
+        loop {
+            yield i;
+            i += 2;
+        }
+    };
+    test_eq!(evens.next(), Some(0));
+    test_eq!(evens.next(), Some(2));
+    test_eq!(drops.get(), 0);
+    drop(evens);
+    test_eq!(drops.get(), 1);
+}
@@ -0,0 +1,191 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params,
+    register_tool
+)]
+#![allow(
+    internal_features,
+    incomplete_features,
+    unused_variables,
+    dead_code,
+    improper_ctypes_definitions
+)]
+#![no_std]
+#![register_tool(dotnet)]
+include!("../common.rs");
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedClass<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str> {
+    prevent_construction: usize,
+}
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedArray<T, const DIMENSIONS: usize> {
+    pd: core::marker::PhantomData<T>,
+    prevent_construction: usize,
+}
+#[derive(Clone, Copy)]
+struct RustcCLRInteropManagedGenericStruct<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+    T,
+    const SIZE: usize,
+> {
+    size_hint: [u8; SIZE],
+    pd: core::marker::PhantomData<T>,
+}
+struct RustcCLRInteropManagedGenericArg<const INDEX: u32> {
+    size_hint: usize,
+}
+#[inline(never)]
+fn rustc_clr_interop_generic_call1_<
+    Class,
+    const METHOD: &'static str,
+    const IS_STATIC: bool,
+    Sig,
+    Ret,
+    Arg1,
+>(
+    arg1: Arg1,
+) -> Ret {
+    core::intrinsics::abort();
+}
+type MString = RustcCLRInteropManagedClass<"System.Runtime", "System.String">;
+type Bytes = RustcCLRInteropManagedArray<u8, 1>;
+type Span<T> = RustcCLRInteropManagedGenericStruct<"System.Runtime", "System.Span", T, 16>;
+type ReadOnlySpan<T> =
+    RustcCLRInteropManagedGenericStruct<"System.Runtime", "System.ReadOnlySpan", T, 16>;
+#[dotnet::export(class = "RustExports.Math", name = "Add")]
+#[inline(never)]
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+#[dotnet::export(class = "RustExports.Math", name = "Sum")]
+#[inline(never)]
+pub fn sum(values: &[u8]) -> u64 {
+    values.iter().map(|value| u64::from(*value)).sum()
+}
+#[dotnet::export(class = "RustExports.Strings")]
+#[inline(never)]
+pub fn byte_len(text: &str) -> usize {
+    text.len()
+}
+#[dotnet::export(class = "RustExports.Strings", name = "Greeting")]
+#[inline(never)]
+pub fn greeting() -> &'static str {
+    "Hello from Rust!"
+}
+#[dotnet::export(class = "RustExports.Math", name = "Fill")]
+#[inline(never)]
+pub fn fill(values: &mut [u8], value: u8) {
+    for elem in values {
+        *elem = value;
+    }
+}
+/// Converts a Rust string into a .NET one.
+fn mstring(text: &str) -> MString {
+    rustc_clr_interop_managed_call2_::<
+        "System.Runtime",
+        "System.Runtime.InteropServices.Marshal",
+        false,
+        "PtrToStringUTF8",
+        true,
+        MString,
+        isize,
+        i32,
+    >(text.as_ptr() as isize, text.len() as i32)
+}
+fn main() {
+    // The exported wrappers are called like any other .NET method.
+    let sum_of = |bytes: Bytes| {
+        rustc_clr_interop_managed_call1_::<"", "RustExports.Math", false, "Sum", true, u64, Bytes>(
+            bytes,
+        )
+    };
+    test_eq!(
+        rustc_clr_interop_managed_call2_::<"", "RustExports.Math", false, "Add", true, i32, i32, i32>(
+            2, 3
+        ),
+        5
+    );
+    test_eq!(
+        rustc_clr_interop_managed_call1_::<
+            "",
+            "RustExports.Strings",
+            false,
+            "byte_len",
+            true,
+            usize,
+            MString,
+        >(mstring("Zażółć")),
+        10
+    );
+    let greeting =
+        rustc_clr_interop_managed_call0_::<"", "RustExports.Strings", false, "Greeting", MString>();
+    test_eq!(
+        rustc_clr_interop_managed_call1_::<
+            "System.Runtime",
+            "System.String",
+            false,
+            "get_Length",
+            false,
+            i32,
+            MString,
+        >(greeting),
+        16
+    );
+    // `[1, 2, 3]`
+    let bytes = rustc_clr_interop_managed_call1_::<
+        "System.Runtime",
+        "System.Convert",
+        false,
+        "FromBase64String",
+        true,
+        Bytes,
+        MString,
+    >(mstring("AQID"));
+    test_eq!(sum_of(bytes), 6);
+    rustc_clr_interop_managed_call2_::<"", "RustExports.Math", false, "Fill", true, (), Bytes, u8>(
+        bytes, 9,
+    );
+    test_eq!(sum_of(bytes), 27);
+    // Slices can also be passed as spans. Changes made to a `Span<T>` are visible to the caller.
+    let span = rustc_clr_interop_generic_call1_::<
+        Span<u8>,
+        "op_Implicit",
+        true,
+        fn(
+            RustcCLRInteropManagedArray<RustcCLRInteropManagedGenericArg<0>, 1>,
+        ) -> Span<RustcCLRInteropManagedGenericArg<0>>,
+        Span<u8>,
+        Bytes,
+    >(bytes);
+    rustc_clr_interop_managed_call2_::<"", "RustExports.Math", false, "Fill", true, (), Span<u8>, u8>(
+        span, 4,
+    );
+    test_eq!(sum_of(bytes), 12);
+    let span = rustc_clr_interop_generic_call1_::<
+        ReadOnlySpan<u8>,
+        "op_Implicit",
+        true,
+        fn(
+            RustcCLRInteropManagedArray<RustcCLRInteropManagedGenericArg<0>, 1>,
+        ) -> ReadOnlySpan<RustcCLRInteropManagedGenericArg<0>>,
+        ReadOnlySpan<u8>,
+        Bytes,
+    >(bytes);
+    test_eq!(
+        rustc_clr_interop_managed_call1_::<
+            "",
+            "RustExports.Math",
+            false,
+            "Sum",
+            true,
+            u64,
+            ReadOnlySpan<u8>,
+        >(span),
+        12
+    );
+}
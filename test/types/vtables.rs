@@ -0,0 +1,72 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    unsized_const_params,
+    dyn_star,
+    trait_upcasting
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code)]
+#![no_std]
+include!("../common.rs");
+trait Shape {
+    fn area(&self) -> u32;
+}
+trait Named: Shape {
+    fn name(&self) -> u32;
+}
+struct Square(u32);
+struct Rect(u32, u32);
+impl Shape for Square {
+    fn area(&self) -> u32 {
+        self.0 * self.0
+    }
+}
+impl Named for Square {
+    fn name(&self) -> u32 {
+        1
+    }
+}
+impl Shape for Rect {
+    fn area(&self) -> u32 {
+        self.0 * self.1
+    }
+}
+impl Named for Rect {
+    fn name(&self) -> u32 {
+        2
+    }
+}
+// The vtable pointers are a part of the constant data of this static.
+static SHAPES: [&dyn Named; 2] = [&Square(3), &Rect(2, 5)];
+static mut DROPPED: usize = 0;
+struct Counter(usize);
+impl Shape for Counter {
+    fn area(&self) -> u32 {
+        self.0 as u32
+    }
+}
+impl Drop for Counter {
+    fn drop(&mut self) {
+        unsafe { DROPPED += self.0 };
+    }
+}
+fn upcast(named: &dyn Named) -> &dyn Shape {
+    named
+}
+fn main() {
+    test_eq!(black_box(SHAPES[0]).name(), 1);
+    test_eq!(black_box(SHAPES[0]).area(), 9);
+    test_eq!(black_box(SHAPES[1]).name(), 2);
+    test_eq!(black_box(SHAPES[1]).area(), 10);
+    // Trait upcasting coercions
+    test_eq!(upcast(black_box(SHAPES[1])).area(), 10);
+    test_eq!(upcast(black_box(&Square(4))).area(), 16);
+    // `dyn*`
+    let star: dyn* Shape = black_box(Counter(7));
+    test_eq!(star.area(), 7);
+    drop(star);
+    test_eq!(unsafe { DROPPED }, 7);
+}